@@ -6,9 +6,11 @@ use compositor_layer::{CompositorData, CompositorLayer, DoesntWantScrollEvents};
 use compositor_layer::WantsScrollEvents;
 use compositor_task::{ChangeReadyState, ChangeRenderState, CompositorEventListener};
 use compositor_task::{CompositorProxy, CompositorReceiver, CompositorTask};
-use compositor_task::{CreateOrUpdateDescendantLayer, CreateOrUpdateRootLayer, Exit};
+use compositor_task::{CreateOrUpdateDescendantLayer, CreateOrUpdateRootLayer, CreateScreenshot};
+use compositor_task::Exit;
 use compositor_task::{FrameTreeUpdateMsg, GetGraphicsMetadata, LayerProperties};
-use compositor_task::{LoadComplete, Msg, Paint, RenderMsgDiscarded, ScrollFragmentPoint};
+use compositor_task::{LoadComplete, Msg, Paint, PaintSoftware, RenderMsgDiscarded};
+use compositor_task::ScrollFragmentPoint;
 use compositor_task::{ScrollTimeout, SetIds, SetLayerOrigin, ShutdownComplete};
 use constellation::{SendableFrameTree, FrameTreeDiff};
 use pipeline::CompositionPipeline;
@@ -29,7 +31,7 @@ use geom::point::{Point2D, TypedPoint2D};
 use geom::rect::{Rect, TypedRect};
 use geom::size::TypedSize2D;
 use geom::scale_factor::ScaleFactor;
-use gfx::render_task::{RenderChan, RenderMsg, RenderRequest, UnusedBufferMsg};
+use gfx::render_task::{RenderChan, RenderMsg, RenderRequest, SetViewportMsg, UnusedBufferMsg};
 use layers::geometry::{DevicePixel, LayerPixel};
 use layers::layers::{BufferRequest, Layer, LayerBufferSet};
 use layers::rendergl;
@@ -43,6 +45,7 @@ use servo_msg::compositor_msg::{Blank, Epoch, FinishedLoading, IdleRenderState,
 use servo_msg::compositor_msg::{ReadyState, RenderingRenderState, RenderState, Scrollable};
 use servo_msg::constellation_msg::{ConstellationChan, ExitMsg, LoadUrlMsg};
 use servo_msg::constellation_msg::{NavigateMsg, LoadData, PipelineId, ResizedWindowMsg};
+use servo_msg::constellation_msg::Screenshot;
 use servo_msg::constellation_msg::{WindowSizeData, KeyState, Key, KeyModifiers};
 use servo_msg::constellation_msg;
 use servo_util::geometry::{PagePx, ScreenPx, ViewportPx};
@@ -301,6 +304,11 @@ impl<Window: WindowMethods> IOCompositor<Window> {
                 self.remove_outstanding_render_msg();
             }
 
+            (PaintSoftware(..), NotShuttingDown) => {
+                // We always hand the renderer graphics metadata, so it never paints into memory.
+                panic!("compositor with a graphics context received tiles painted in memory");
+            }
+
             (ScrollFragmentPoint(pipeline_id, layer_id, point), NotShuttingDown) => {
                 self.scroll_fragment_to_point(pipeline_id, layer_id, point);
             }
@@ -314,6 +322,10 @@ impl<Window: WindowMethods> IOCompositor<Window> {
                 }
             }
 
+            (CreateScreenshot(chan), NotShuttingDown) => {
+                chan.send(Some(self.composite_to_screenshot()));
+            }
+
             (ScrollTimeout(timestamp), NotShuttingDown) => {
                 debug!("scroll timeout, drawing unrendered content!");
                 match self.composition_request {
@@ -893,6 +905,7 @@ impl<Window: WindowMethods> IOCompositor<Window> {
             let pipeline = &layer.extra_data.borrow().pipeline;
             let ScriptControlChan(ref chan) = pipeline.script_chan;
             chan.send(ViewportMsg(pipeline.id.clone(), layer_rect));
+            let _ = pipeline.render_chan.send_opt(SetViewportMsg(layer_rect));
         }
 
         for kid in layer.children().iter() {
@@ -959,32 +972,7 @@ impl<Window: WindowMethods> IOCompositor<Window> {
         return true;
     }
 
-    fn composite(&mut self) {
-        let output_image = opts::get().output_file.is_some() &&
-                            self.is_ready_to_render_image_output();
-
-        let mut framebuffer_ids = vec!();
-        let mut texture_ids = vec!();
-        let (width, height) = (self.window_size.width.get(), self.window_size.height.get());
-
-        if output_image {
-            framebuffer_ids = gl::gen_framebuffers(1);
-            gl::bind_framebuffer(gl::FRAMEBUFFER, framebuffer_ids[0]);
-
-            texture_ids = gl::gen_textures(1);
-            gl::bind_texture(gl::TEXTURE_2D, texture_ids[0]);
-
-            gl::tex_image_2d(gl::TEXTURE_2D, 0, gl::RGB as GLint, width as GLsizei,
-                             height as GLsizei, 0, gl::RGB, gl::UNSIGNED_BYTE, None);
-            gl::tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
-            gl::tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
-
-            gl::framebuffer_texture_2d(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D,
-                                       texture_ids[0], 0);
-
-            gl::bind_texture(gl::TEXTURE_2D, 0);
-        }
-
+    fn render_scene(&mut self) {
         profile(time::CompositingCategory, None, self.time_profiler_chan.clone(), || {
             debug!("compositor: compositing");
             // Adjust the layer dimensions as necessary to correspond to the size of the window.
@@ -1000,30 +988,68 @@ impl<Window: WindowMethods> IOCompositor<Window> {
                 None => {}
             }
         });
+    }
+
+    /// Renders the scene into an offscreen framebuffer and reads the pixels back.
+    fn composite_to_screenshot(&mut self) -> Screenshot {
+        let (width, height) = (self.window_size.width.get(), self.window_size.height.get());
+
+        let framebuffer_ids = gl::gen_framebuffers(1);
+        gl::bind_framebuffer(gl::FRAMEBUFFER, framebuffer_ids[0]);
 
-        if output_image {
+        let texture_ids = gl::gen_textures(1);
+        gl::bind_texture(gl::TEXTURE_2D, texture_ids[0]);
+
+        gl::tex_image_2d(gl::TEXTURE_2D, 0, gl::RGB as GLint, width as GLsizei,
+                         height as GLsizei, 0, gl::RGB, gl::UNSIGNED_BYTE, None);
+        gl::tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+        gl::tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+
+        gl::framebuffer_texture_2d(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D,
+                                   texture_ids[0], 0);
+
+        gl::bind_texture(gl::TEXTURE_2D, 0);
+
+        self.render_scene();
+
+        let mut pixels = gl::read_pixels(0, 0,
+                                         width as gl::GLsizei,
+                                         height as gl::GLsizei,
+                                         gl::RGB, gl::UNSIGNED_BYTE);
+
+        gl::bind_framebuffer(gl::FRAMEBUFFER, 0);
+
+        gl::delete_buffers(texture_ids.as_slice());
+        gl::delete_frame_buffers(framebuffer_ids.as_slice());
+
+        // flip image vertically (texture is upside down)
+        let orig_pixels = pixels.clone();
+        let stride = width * 3;
+        for y in range(0, height) {
+            let dst_start = y * stride;
+            let src_start = (height - y - 1) * stride;
+            let src_slice = orig_pixels.slice(src_start, src_start + stride);
+            copy_memory(pixels.slice_mut(dst_start, dst_start + stride),
+                        src_slice.slice_to(stride));
+        }
+
+        Screenshot {
+            width: width,
+            height: height,
+            pixels: pixels,
+        }
+    }
+
+    fn composite(&mut self) {
+        let output_image = opts::get().output_file.is_some() &&
+                            self.is_ready_to_render_image_output();
+
+        if !output_image {
+            self.render_scene();
+        } else {
+            let Screenshot { width, height, pixels } = self.composite_to_screenshot();
             let path =
                 from_str::<Path>(opts::get().output_file.as_ref().unwrap().as_slice()).unwrap();
-            let mut pixels = gl::read_pixels(0, 0,
-                                             width as gl::GLsizei,
-                                             height as gl::GLsizei,
-                                             gl::RGB, gl::UNSIGNED_BYTE);
-
-            gl::bind_framebuffer(gl::FRAMEBUFFER, 0);
-
-            gl::delete_buffers(texture_ids.as_slice());
-            gl::delete_frame_buffers(framebuffer_ids.as_slice());
-
-            // flip image vertically (texture is upside down)
-            let orig_pixels = pixels.clone();
-            let stride = width * 3;
-            for y in range(0, height) {
-                let dst_start = y * stride;
-                let src_start = (height - y - 1) * stride;
-                let src_slice = orig_pixels.slice(src_start, src_start + stride);
-                copy_memory(pixels.slice_mut(dst_start, dst_start + stride),
-                            src_slice.slice_to(stride));
-            }
             let mut img = png::Image {
                 width: width as u32,
                 height: height as u32,
@@ -1128,6 +1154,7 @@ fn create_root_layer_for_pipeline_and_rect(pipeline: &CompositionPipeline,
         rect: Rect::zero(),
         background_color: azure_hl::Color::new(0., 0., 0., 0.),
         scroll_policy: Scrollable,
+        opacity: 1.0,
     };
 
     let root_layer = CompositorData::new_layer(pipeline.clone(),
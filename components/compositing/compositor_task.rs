@@ -9,6 +9,7 @@ pub use constellation::{SendableFrameTree, FrameTreeDiff};
 
 use compositor;
 use headless;
+use software;
 use windowing::{WindowEvent, WindowMethods};
 
 use azure::azure_hl::{SourceSurfaceMethods, Color};
@@ -19,8 +20,10 @@ use layers::platform::surface::{NativeCompositingGraphicsContext, NativeGraphics
 use layers::layers::LayerBufferSet;
 use servo_msg::compositor_msg::{Epoch, LayerId, LayerMetadata, ReadyState};
 use servo_msg::compositor_msg::{RenderListener, RenderState, ScriptListener, ScrollPolicy};
-use servo_msg::constellation_msg::{ConstellationChan, PipelineId};
+use servo_msg::compositor_msg::SoftwareLayerBuffer;
+use servo_msg::constellation_msg::{ConstellationChan, PipelineId, Screenshot};
 use servo_util::memory::MemoryProfilerChan;
+use servo_util::opts;
 use servo_util::time::TimeProfilerChan;
 use std::comm::{channel, Sender, Receiver};
 use std::fmt::{FormatError, Formatter, Show};
@@ -90,6 +93,7 @@ pub struct LayerProperties {
     pub rect: Rect<f32>,
     pub background_color: Color,
     pub scroll_policy: ScrollPolicy,
+    pub opacity: f32,
 }
 
 impl LayerProperties {
@@ -104,6 +108,7 @@ impl LayerProperties {
                               metadata.position.size.height as f32)),
             background_color: metadata.background_color,
             scroll_policy: metadata.scroll_policy,
+            opacity: metadata.opacity,
         }
     }
 }
@@ -123,6 +128,13 @@ impl RenderListener for Box<CompositorProxy+'static+Send> {
         self.send(Paint(pipeline_id, epoch, replies));
     }
 
+    fn paint_software(&mut self,
+                      pipeline_id: PipelineId,
+                      epoch: Epoch,
+                      replies: Vec<(LayerId, Vec<SoftwareLayerBuffer>)>) {
+        self.send(PaintSoftware(pipeline_id, epoch, replies));
+    }
+
     fn initialize_layers_for_pipeline(&mut self,
                                       pipeline_id: PipelineId,
                                       metadata: Vec<LayerMetadata>,
@@ -165,7 +177,7 @@ pub enum Msg {
     /// to create surfaces that the compositor can see. On Linux this is the X display; on Mac this
     /// is the pixel format.
     ///
-    /// The headless compositor returns `None`.
+    /// The headless compositors return `None`, which makes the renderer paint into main memory.
     GetGraphicsMetadata(Sender<Option<NativeGraphicsMetadata>>),
 
     /// Tells the compositor to create the root layer for a pipeline if necessary (i.e. if no layer
@@ -180,6 +192,8 @@ pub enum Msg {
     ScrollFragmentPoint(PipelineId, LayerId, Point2D<f32>),
     /// Requests that the compositor paint the given layer buffer set for the given page size.
     Paint(PipelineId, Epoch, Vec<(LayerId, Box<LayerBufferSet>)>),
+    /// Requests that the compositor paint the given tiles, which were painted into main memory.
+    PaintSoftware(PipelineId, Epoch, Vec<(LayerId, Vec<SoftwareLayerBuffer>)>),
     /// Alerts the compositor to the current status of page loading.
     ChangeReadyState(PipelineId, ReadyState),
     /// Alerts the compositor to the current status of rendering.
@@ -195,6 +209,9 @@ pub enum Msg {
     /// Indicates that the scrolling timeout with the given starting timestamp has happened and a
     /// composite should happen. (See the `scrolling` module.)
     ScrollTimeout(u64),
+    /// Requests an image of the composited page, for WebDriver. Compositors that draw nothing
+    /// reply with `None`.
+    CreateScreenshot(Sender<Option<Screenshot>>),
 }
 
 impl Show for Msg {
@@ -208,6 +225,7 @@ impl Show for Msg {
             SetLayerOrigin(..) => write!(f, "SetLayerOrigin"),
             ScrollFragmentPoint(..) => write!(f, "ScrollFragmentPoint"),
             Paint(..) => write!(f, "Paint"),
+            PaintSoftware(..) => write!(f, "PaintSoftware"),
             ChangeReadyState(..) => write!(f, "ChangeReadyState"),
             ChangeRenderState(..) => write!(f, "ChangeRenderState"),
             RenderMsgDiscarded(..) => write!(f, "RenderMsgDiscarded"),
@@ -215,6 +233,7 @@ impl Show for Msg {
             FrameTreeUpdateMsg(..) => write!(f, "FrameTreeUpdateMsg"),
             LoadComplete => write!(f, "LoadComplete"),
             ScrollTimeout(..) => write!(f, "ScrollTimeout"),
+            CreateScreenshot(..) => write!(f, "CreateScreenshot"),
         }
     }
 }
@@ -254,6 +273,13 @@ impl CompositorTask {
                                                      memory_profiler_chan)
                     as Box<CompositorEventListener>
             }
+            None if opts::get().output_file.is_some() || opts::get().webdriver_port.is_some() => {
+                box software::SoftwareCompositor::create(receiver,
+                                                         constellation_chan.clone(),
+                                                         time_profiler_chan,
+                                                         memory_profiler_chan)
+                    as Box<CompositorEventListener>
+            }
             None => {
                 box headless::NullCompositor::create(receiver,
                                                      constellation_chan.clone(),
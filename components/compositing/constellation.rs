@@ -5,6 +5,7 @@
 use pipeline::{Pipeline, CompositionPipeline};
 
 use compositor_task::{CompositorProxy, FrameTreeUpdateMsg, LoadComplete, ShutdownComplete, SetLayerOrigin, SetIds};
+use compositor_task::CreateScreenshot;
 use devtools_traits;
 use devtools_traits::DevtoolsControlChan;
 use geom::rect::{Rect, TypedRect};
@@ -24,6 +25,8 @@ use servo_msg::constellation_msg::{LoadCompleteMsg, LoadUrlMsg, LoadData, Msg, N
 use servo_msg::constellation_msg::{NavigationType, PipelineId, RendererReadyMsg, ResizedWindowMsg};
 use servo_msg::constellation_msg::{ScriptLoadedURLInIFrameMsg, SubpageId, WindowSizeData};
 use servo_msg::constellation_msg::{KeyEvent, Key, KeyState, KeyModifiers};
+use servo_msg::constellation_msg::{GetRootPipelineMsg, ScreenshotMsg, WebDriverCommandMsg};
+use servo_msg::constellation_msg::PostMessageMsg;
 use servo_msg::constellation_msg;
 use servo_net::image_cache_task::{ImageCacheTask, ImageCacheTaskClient};
 use servo_net::resource_task::ResourceTask;
@@ -34,12 +37,14 @@ use servo_util::geometry::{PagePx, ViewportPx};
 use servo_util::opts;
 use servo_util::task::spawn_named;
 use servo_util::time::TimeProfilerChan;
+use std::any::Any;
 use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, HashSet};
 use std::io;
 use std::mem::replace;
 use std::rc::Rc;
 use url::Url;
+use webdriver_traits::WebDriverScriptCommand;
 
 /// Maintains the pipelines and navigation context and grants permission to composite.
 pub struct Constellation<LTF, STF> {
@@ -465,6 +470,22 @@ impl<LTF: LayoutTaskFactory, STF: ScriptTaskFactory> Constellation<LTF, STF> {
                 debug!("constellation got key event message");
                 self.handle_key_msg(key, state, modifiers);
             }
+            GetRootPipelineMsg(reply) => {
+                debug!("constellation got get root pipeline message");
+                reply.send(self.current_frame().as_ref().map(|frame| frame.pipeline.id));
+            }
+            WebDriverCommandMsg(pipeline_id, command) => {
+                debug!("constellation got webdriver command message");
+                self.handle_webdriver_command_msg(pipeline_id, command);
+            }
+            ScreenshotMsg(reply) => {
+                debug!("constellation got screenshot message");
+                self.compositor_proxy.send(CreateScreenshot(reply));
+            }
+            PostMessageMsg(pipeline_id, message) => {
+                debug!("constellation got post message message");
+                self.handle_post_message_msg(pipeline_id, message);
+            }
         }
         true
     }
@@ -787,6 +808,30 @@ impl<LTF: LayoutTaskFactory, STF: ScriptTaskFactory> Constellation<LTF, STF> {
         });
     }
 
+    fn handle_webdriver_command_msg(&self,
+                                    pipeline_id: PipelineId,
+                                    command: WebDriverScriptCommand) {
+        // If the pipeline has gone away, dropping the command closes its reply channel, which
+        // tells the WebDriver server that the window no longer exists.
+        match self.pipelines.get(&pipeline_id) {
+            Some(pipeline) => {
+                let ScriptControlChan(ref chan) = pipeline.script_chan;
+                chan.send(script_traits::WebDriverCommandMsg(pipeline_id, command));
+            }
+            None => debug!("webdriver command for nonexistent pipeline {}", pipeline_id),
+        }
+    }
+
+    fn handle_post_message_msg(&self, pipeline_id: PipelineId, message: Box<Any+Send>) {
+        match self.pipelines.get(&pipeline_id) {
+            Some(pipeline) => {
+                let ScriptControlChan(ref chan) = pipeline.script_chan;
+                chan.send(script_traits::PostMessageMsg(pipeline_id, message));
+            }
+            None => debug!("message posted to nonexistent pipeline {}", pipeline_id),
+        }
+    }
+
     fn handle_renderer_ready_msg(&mut self, pipeline_id: PipelineId) {
         debug!("Renderer {} ready to send paint msg", pipeline_id);
         // This message could originate from a pipeline in the navigation context or
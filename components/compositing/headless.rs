@@ -4,6 +4,7 @@
 
 use compositor_task::{GetGraphicsMetadata, CreateOrUpdateRootLayer, CreateOrUpdateDescendantLayer};
 use compositor_task::{Exit, ChangeReadyState, LoadComplete, Paint, ScrollFragmentPoint, SetIds};
+use compositor_task::{CreateScreenshot, PaintSoftware};
 use compositor_task::{SetLayerOrigin, ShutdownComplete, ChangeRenderState, RenderMsgDiscarded};
 use compositor_task::{CompositorEventListener, CompositorReceiver, ScrollTimeout, FrameTreeUpdateMsg};
 use windowing::WindowEvent;
@@ -88,6 +89,10 @@ impl CompositorEventListener for NullCompositor {
                 chan.send(None);
             }
 
+            CreateScreenshot(chan) => {
+                chan.send(None);
+            }
+
             SetIds(_, response_chan, _) => {
                 response_chan.send(());
             }
@@ -102,7 +107,7 @@ impl CompositorEventListener for NullCompositor {
 
             CreateOrUpdateRootLayer(..) |
             CreateOrUpdateDescendantLayer(..) |
-            SetLayerOrigin(..) | Paint(..) |
+            SetLayerOrigin(..) | Paint(..) | PaintSoftware(..) |
             ChangeReadyState(..) | ChangeRenderState(..) | ScrollFragmentPoint(..) |
             LoadComplete | RenderMsgDiscarded(..) | ScrollTimeout(..) => ()
         }
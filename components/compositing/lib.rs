@@ -31,6 +31,7 @@ extern crate libc;
 extern crate native;
 extern crate time;
 extern crate url;
+extern crate webdriver_traits;
 
 #[cfg(target_os="macos")]
 extern crate core_graphics;
@@ -47,6 +48,7 @@ mod scrolling;
 
 mod compositor;
 mod headless;
+mod software;
 
 pub mod pipeline;
 pub mod constellation;
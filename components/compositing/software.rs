@@ -0,0 +1,754 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A compositor that flattens the layer tree on the CPU and writes the result to a PNG file.
+//!
+//! This is used for `-o` and WebDriver in headless mode, so that screenshots (and reftests) can
+//! be taken on machines that have neither a display nor OpenGL. The compositor supplies no
+//! graphics metadata, so the render task paints tiles into main memory and sends them back with
+//! `PaintSoftware`.
+
+use compositor_task::{GetGraphicsMetadata, CreateOrUpdateRootLayer, CreateOrUpdateDescendantLayer};
+use compositor_task::{Exit, ChangeReadyState, LoadComplete, Paint, PaintSoftware};
+use compositor_task::{ScrollFragmentPoint, SetIds, SetLayerOrigin, ShutdownComplete};
+use compositor_task::{ChangeRenderState, RenderMsgDiscarded, ScrollTimeout, FrameTreeUpdateMsg};
+use compositor_task::{CompositorEventListener, CompositorReceiver, CreateScreenshot};
+use compositor_task::LayerProperties;
+use constellation::SendableFrameTree;
+use pipeline::CompositionPipeline;
+use windowing::WindowEvent;
+
+use azure::azure_hl::Color;
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::scale_factor::ScaleFactor;
+use geom::size::{Size2D, TypedSize2D};
+use gfx::render_task::{RenderChan, RenderMsg, RenderRequest};
+use layers::layers::BufferRequest;
+use png;
+use script_traits::{ScriptControlChan, ViewportMsg};
+use servo_msg::compositor_msg::{Blank, Epoch, FinishedLoading, FixedPosition, IdleRenderState};
+use servo_msg::compositor_msg::{LayerId, ReadyState, RenderingRenderState, RenderState};
+use servo_msg::compositor_msg::{ScrollPolicy, Scrollable, SoftwareLayerBuffer};
+use servo_msg::constellation_msg::{ConstellationChan, ExitMsg, PipelineId, ResizedWindowMsg};
+use servo_msg::constellation_msg::{Screenshot, WindowSizeData};
+use servo_util::memory::MemoryProfilerChan;
+use servo_util::memory;
+use servo_util::opts;
+use servo_util::time::{profile, TimeProfilerChan};
+use servo_util::time;
+use std::cmp;
+use std::collections::HashMap;
+use std::collections::hash_map::{Occupied, Vacant};
+use std::num::Float;
+use std::path::Path;
+
+/// A layer of the software compositor. Like the layers of the OpenGL compositor, each pipeline
+/// has a root layer that scrolls its content, whose children are the layers that the render task
+/// paints and the root layers of any iframes.
+struct SoftwareLayer {
+    /// The pipeline that paints this layer.
+    pipeline: CompositionPipeline,
+    /// The ID of this layer within the pipeline. Root layers of pipelines have the null ID and
+    /// no content of their own.
+    id: LayerId,
+    /// The epoch of the layer; tiles painted for any other epoch are dropped.
+    epoch: Epoch,
+    /// The position and size of the layer, relative to its parent.
+    bounds: Rect<f32>,
+    background_color: Color,
+    scroll_policy: ScrollPolicy,
+    /// The opacity of the enclosing stacking contexts.
+    opacity: f32,
+    /// Whether the layer clips its children, as the root layers of iframes do.
+    masks_to_bounds: bool,
+    /// The point that the content of this layer is scrolled to.
+    scroll_offset: Point2D<f32>,
+    /// The most recently painted tiles of this layer.
+    tiles: Vec<SoftwareLayerBuffer>,
+    children: Vec<SoftwareLayer>,
+}
+
+impl SoftwareLayer {
+    fn new(pipeline: CompositionPipeline, properties: &LayerProperties) -> SoftwareLayer {
+        SoftwareLayer {
+            pipeline: pipeline,
+            id: properties.id,
+            epoch: properties.epoch,
+            bounds: properties.rect,
+            background_color: properties.background_color,
+            scroll_policy: properties.scroll_policy,
+            opacity: properties.opacity,
+            masks_to_bounds: false,
+            scroll_offset: Point2D(0f32, 0f32),
+            tiles: vec!(),
+            children: vec!(),
+        }
+    }
+
+    fn new_root(pipeline: &CompositionPipeline, bounds: Rect<f32>, masks_to_bounds: bool)
+                -> SoftwareLayer {
+        SoftwareLayer {
+            pipeline: pipeline.clone(),
+            id: LayerId::null(),
+            epoch: Epoch(0),
+            bounds: bounds,
+            background_color: Color::new(0., 0., 0., 0.),
+            scroll_policy: Scrollable,
+            opacity: 1.0,
+            masks_to_bounds: masks_to_bounds,
+            scroll_offset: Point2D(0f32, 0f32),
+            tiles: vec!(),
+            children: vec!(),
+        }
+    }
+
+    fn find_mut<'a>(&'a mut self, pipeline_id: PipelineId, id: LayerId)
+                    -> Option<&'a mut SoftwareLayer> {
+        if self.pipeline.id == pipeline_id && self.id == id {
+            return Some(self)
+        }
+        for kid in self.children.iter_mut() {
+            match kid.find_mut(pipeline_id, id) {
+                Some(layer) => return Some(layer),
+                None => {}
+            }
+        }
+        None
+    }
+
+    fn update_except_size(&mut self, properties: &LayerProperties) {
+        self.epoch = properties.epoch;
+        self.background_color = properties.background_color;
+        self.scroll_policy = properties.scroll_policy;
+        self.opacity = properties.opacity;
+    }
+
+    fn update(&mut self, properties: &LayerProperties) {
+        self.bounds.size = properties.rect.size;
+        self.update_except_size(properties);
+        let scroll_offset = self.scroll_offset;
+        self.scroll_to(scroll_offset);
+    }
+
+    /// Scrolls the content of this layer to the given point, keeping the content in view.
+    fn scroll_to(&mut self, point: Point2D<f32>) {
+        let content_rect = self.children.iter().fold(Rect::zero(), |rect: Rect<f32>, kid| {
+            rect.union(&kid.bounds)
+        });
+        let max_x = (content_rect.size.width - self.bounds.size.width).max(0.0);
+        let max_y = (content_rect.size.height - self.bounds.size.height).max(0.0);
+        self.scroll_offset = Point2D(point.x.max(0.0).min(max_x), point.y.max(0.0).min(max_y));
+    }
+
+    /// Returns the position in the window that the bounds of a child layer are relative to,
+    /// given the position of this layer.
+    fn child_origin(&self, layer_origin: Point2D<f32>, child: &SoftwareLayer) -> Point2D<f32> {
+        if child.scroll_policy == FixedPosition {
+            layer_origin
+        } else {
+            layer_origin - self.scroll_offset
+        }
+    }
+
+    /// Collects requests for the tiles that cover the visible parts of this layer and its
+    /// descendants. `origin` is the position of the layer's parent in the window.
+    fn collect_buffer_requests(&self,
+                               requests: &mut HashMap<PipelineId, (RenderChan, Vec<RenderRequest>)>,
+                               origin: Point2D<f32>,
+                               clip: Rect<f32>) {
+        let layer_rect = Rect(origin + self.bounds.origin, self.bounds.size);
+        let visible_rect = match clip.intersection(&layer_rect) {
+            Some(visible_rect) => visible_rect,
+            None if self.masks_to_bounds => return,
+            None => Rect::zero(),
+        };
+
+        if self.id != LayerId::null() && visible_rect.size.width > 0.0 &&
+                visible_rect.size.height > 0.0 {
+            let visible_rect = Rect(visible_rect.origin - layer_rect.origin, visible_rect.size);
+            let buffer_requests = tile_rect(&visible_rect,
+                                            &self.bounds.size,
+                                            opts::get().tile_size);
+            let &(_, ref mut pipeline_requests) = match requests.entry(self.pipeline.id) {
+                Occupied(entry) => entry.into_mut(),
+                Vacant(entry) => entry.set((self.pipeline.render_chan.clone(), vec!())),
+            };
+            pipeline_requests.push(RenderRequest {
+                buffer_requests: buffer_requests,
+                scale: 1.0,
+                layer_id: self.id,
+                epoch: self.epoch,
+            });
+        }
+
+        let clip = if self.masks_to_bounds { visible_rect } else { clip };
+        for kid in self.children.iter() {
+            kid.collect_buffer_requests(requests, self.child_origin(layer_rect.origin, kid), clip)
+        }
+    }
+
+    fn send_viewport_rects(&self) {
+        if self.id == LayerId::null() {
+            let ScriptControlChan(ref chan) = self.pipeline.script_chan;
+            let _ = chan.send_opt(ViewportMsg(self.pipeline.id,
+                                              Rect(self.scroll_offset, self.bounds.size)));
+        }
+        for kid in self.children.iter() {
+            kid.send_viewport_rects();
+        }
+    }
+
+    /// Draws this layer and its descendants. `origin` is the position of the layer's parent in
+    /// the window.
+    fn composite(&self, framebuffer: &mut Framebuffer, origin: Point2D<f32>, clip: Rect<f32>) {
+        let layer_rect = Rect(origin + self.bounds.origin, self.bounds.size);
+        let clip = if self.masks_to_bounds {
+            match clip.intersection(&layer_rect) {
+                Some(clip) => clip,
+                None => return,
+            }
+        } else {
+            clip
+        };
+
+        if self.background_color.a > 0.0 {
+            let color = &self.background_color;
+            let pixel = [
+                (color.b * color.a * 255.0).round() as u8,
+                (color.g * color.a * 255.0).round() as u8,
+                (color.r * color.a * 255.0).round() as u8,
+                (color.a * 255.0).round() as u8,
+            ];
+            framebuffer.fill(&layer_rect, &clip, pixel.as_slice(), self.opacity);
+        }
+
+        for tile in self.tiles.iter() {
+            framebuffer.draw_tile(tile, &layer_rect.origin, &clip, self.opacity);
+        }
+
+        for kid in self.children.iter() {
+            kid.composite(framebuffer, self.child_origin(layer_rect.origin, kid), clip)
+        }
+    }
+}
+
+/// Splits the given rect of a layer into tiles of at most `tile_size` device pixels on each side,
+/// aligned to the tile grid and clipped to the layer.
+fn tile_rect(rect: &Rect<f32>, layer_size: &Size2D<f32>, tile_size: uint) -> Vec<BufferRequest> {
+    let tile_size = tile_size as f32;
+    let (max_x, max_y) = (rect.max_x().min(layer_size.width).ceil(),
+                          rect.max_y().min(layer_size.height).ceil());
+    let mut requests = vec!();
+    let mut y = (rect.origin.y / tile_size).floor() * tile_size;
+    while y < max_y {
+        let mut x = (rect.origin.x / tile_size).floor() * tile_size;
+        let height = (max_y - y).min(tile_size);
+        while x < max_x {
+            let width = (max_x - x).min(tile_size);
+            let screen_rect = Rect(Point2D(x as uint, y as uint),
+                                   Size2D(width as uint, height as uint));
+            let page_rect = Rect(Point2D(x, y), Size2D(width, height));
+            requests.push(BufferRequest::new(screen_rect, page_rect));
+            x += tile_size;
+        }
+        y += tile_size;
+    }
+    requests
+}
+
+/// An opaque RGB image that layers are drawn into.
+struct Framebuffer {
+    width: uint,
+    height: uint,
+    pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+    fn new(width: uint, height: uint) -> Framebuffer {
+        Framebuffer {
+            width: width,
+            height: height,
+            pixels: Vec::from_elem(width * height * 3, 255u8),
+        }
+    }
+
+    fn bounds(&self) -> Rect<f32> {
+        Rect(Point2D(0f32, 0f32), Size2D(self.width as f32, self.height as f32))
+    }
+
+    /// Returns the pixels of `rect` that lie within `clip` and the framebuffer, as half-open
+    /// ranges of columns and rows.
+    fn pixel_range(&self, rect: &Rect<f32>, clip: &Rect<f32>) -> Option<(uint, uint, uint, uint)> {
+        let bounds = self.bounds();
+        let rect = match rect.intersection(clip).and_then(|rect| rect.intersection(&bounds)) {
+            Some(rect) => rect,
+            None => return None,
+        };
+        Some((rect.origin.x.round() as uint,
+              rect.max_x().round() as uint,
+              rect.origin.y.round() as uint,
+              rect.max_y().round() as uint))
+    }
+
+    /// Fills `rect` with a premultiplied BGRA color.
+    fn fill(&mut self, rect: &Rect<f32>, clip: &Rect<f32>, pixel: &[u8], opacity: f32) {
+        let (min_x, max_x, min_y, max_y) = match self.pixel_range(rect, clip) {
+            Some(range) => range,
+            None => return,
+        };
+        for y in range(min_y, max_y) {
+            for x in range(min_x, max_x) {
+                let offset = (y * self.width + x) * 3;
+                blend_pixel(self.pixels.slice_mut(offset, offset + 3), pixel, opacity);
+            }
+        }
+    }
+
+    /// Draws a tile of a layer whose top left corner is at `origin` in the window.
+    fn draw_tile(&mut self,
+                 tile: &SoftwareLayerBuffer,
+                 origin: &Point2D<f32>,
+                 clip: &Rect<f32>,
+                 opacity: f32) {
+        let tile_origin = *origin + tile.rect.origin;
+        let tile_rect = Rect(tile_origin, Size2D(tile.screen_pos.size.width as f32,
+                                                 tile.screen_pos.size.height as f32));
+        let (min_x, max_x, min_y, max_y) = match self.pixel_range(&tile_rect, clip) {
+            Some(range) => range,
+            None => return,
+        };
+        let (tile_x, tile_y) = (tile_origin.x.round() as int, tile_origin.y.round() as int);
+        for y in range(min_y, max_y) {
+            let source_y = (y as int - tile_y) as uint;
+            if source_y >= tile.screen_pos.size.height {
+                continue
+            }
+            for x in range(min_x, max_x) {
+                let source_x = (x as int - tile_x) as uint;
+                if source_x >= tile.screen_pos.size.width {
+                    continue
+                }
+                let source = source_y * tile.stride + source_x * 4;
+                let offset = (y * self.width + x) * 3;
+                blend_pixel(self.pixels.slice_mut(offset, offset + 3),
+                            tile.data.slice(source, source + 4),
+                            opacity);
+            }
+        }
+    }
+}
+
+/// Draws a premultiplied BGRA pixel, faded by `opacity`, over an opaque RGB pixel.
+fn blend_pixel(destination: &mut [u8], source: &[u8], opacity: f32) {
+    let alpha = source[3] as f32 / 255.0 * opacity;
+    for i in range(0u, 3) {
+        let value = source[2 - i] as f32 * opacity + destination[i] as f32 * (1.0 - alpha);
+        destination[i] = value.round().max(0.0).min(255.0) as u8;
+    }
+}
+
+pub struct SoftwareCompositor {
+    /// The port on which we receive messages.
+    port: Box<CompositorReceiver>,
+    /// A channel to the constellation.
+    constellation_chan: ConstellationChan,
+    /// A channel to the time profiler.
+    time_profiler_chan: TimeProfilerChan,
+    /// A channel to the memory profiler.
+    memory_profiler_chan: MemoryProfilerChan,
+    /// The size of the output image.
+    window_size: Size2D<uint>,
+    /// The root layer of the root pipeline.
+    root: Option<SoftwareLayer>,
+    /// A point to scroll to that arrived before the layer it applies to.
+    fragment_point: Option<(PipelineId, Point2D<f32>)>,
+    /// The ready state of each pipeline.
+    ready_states: HashMap<PipelineId, ReadyState>,
+    /// The render state of each pipeline.
+    render_states: HashMap<PipelineId, RenderState>,
+    /// The number of RenderMsgs that have been sent but not yet answered.
+    outstanding_render_msgs: uint,
+    got_load_complete_message: bool,
+    got_set_ids_message: bool,
+    /// Whether the output has been written and the constellation asked to shut down.
+    shutting_down: bool,
+}
+
+impl SoftwareCompositor {
+    pub fn create(port: Box<CompositorReceiver>,
+                  constellation_chan: ConstellationChan,
+                  time_profiler_chan: TimeProfilerChan,
+                  memory_profiler_chan: MemoryProfilerChan)
+                  -> SoftwareCompositor {
+        let window_size = opts::get().initial_window_size;
+        let compositor = SoftwareCompositor {
+            port: port,
+            constellation_chan: constellation_chan,
+            time_profiler_chan: time_profiler_chan,
+            memory_profiler_chan: memory_profiler_chan,
+            window_size: Size2D(window_size.width.get(), window_size.height.get()),
+            root: None,
+            fragment_point: None,
+            ready_states: HashMap::new(),
+            render_states: HashMap::new(),
+            outstanding_render_msgs: 0,
+            got_load_complete_message: false,
+            got_set_ids_message: false,
+            shutting_down: false,
+        };
+        compositor.send_window_size();
+        compositor
+    }
+
+    fn send_window_size(&self) {
+        let size = TypedSize2D(self.window_size.width as f32, self.window_size.height as f32);
+        let ConstellationChan(ref chan) = self.constellation_chan;
+        chan.send(ResizedWindowMsg(WindowSizeData {
+            initial_viewport: size,
+            visible_viewport: size,
+            device_pixel_ratio: ScaleFactor(1.0),
+        }));
+    }
+
+    fn set_frame_tree(&mut self,
+                      frame_tree: &SendableFrameTree,
+                      new_constellation_chan: ConstellationChan) {
+        let bounds = Rect(Point2D(0f32, 0f32),
+                          Size2D(self.window_size.width as f32, self.window_size.height as f32));
+        self.root = Some(self.create_frame_tree_root_layers(frame_tree, bounds, false));
+        self.constellation_chan = new_constellation_chan;
+        self.send_window_size();
+        self.got_set_ids_message = true;
+        match self.root {
+            Some(ref root) => root.send_viewport_rects(),
+            None => {}
+        }
+    }
+
+    fn create_frame_tree_root_layers(&mut self,
+                                     frame_tree: &SendableFrameTree,
+                                     bounds: Rect<f32>,
+                                     masks_to_bounds: bool)
+                                     -> SoftwareLayer {
+        self.ready_states.insert(frame_tree.pipeline.id, Blank);
+        self.render_states.insert(frame_tree.pipeline.id, RenderingRenderState);
+
+        let mut root_layer = SoftwareLayer::new_root(&frame_tree.pipeline, bounds, masks_to_bounds);
+        for kid in frame_tree.children.iter() {
+            let (bounds, masks_to_bounds) = match kid.rect {
+                Some(ref rect) => (rect.to_untyped(), true),
+                None => (Rect::zero(), false),
+            };
+            root_layer.children.push(self.create_frame_tree_root_layers(&kid.frame_tree,
+                                                                        bounds,
+                                                                        masks_to_bounds));
+        }
+        root_layer
+    }
+
+    fn find_layer_mut<'a>(&'a mut self, pipeline_id: PipelineId, id: LayerId)
+                          -> Option<&'a mut SoftwareLayer> {
+        match self.root {
+            Some(ref mut root) => root.find_mut(pipeline_id, id),
+            None => None,
+        }
+    }
+
+    fn create_or_update_layer(&mut self, properties: LayerProperties, is_root: bool) {
+        let updated = match self.find_layer_mut(properties.pipeline_id, properties.id) {
+            Some(layer) => {
+                layer.update(&properties);
+                true
+            }
+            None => false,
+        };
+
+        if !updated {
+            match self.find_layer_mut(properties.pipeline_id, LayerId::null()) {
+                Some(root_layer) => {
+                    let layer = SoftwareLayer::new(root_layer.pipeline.clone(), &properties);
+                    if is_root {
+                        // The base layer goes beneath the layers of any iframes, which were
+                        // added when the frame tree arrived.
+                        root_layer.update_except_size(&properties);
+                        root_layer.children.insert(0, layer);
+                    } else {
+                        root_layer.children.push(layer);
+                    }
+                }
+                None => panic!("Tried to create or update layer for unknown pipeline"),
+            }
+        }
+
+        match self.fragment_point.take() {
+            Some((pipeline_id, point)) => self.scroll_fragment_point(pipeline_id, point),
+            None => {}
+        }
+        self.send_buffer_requests_for_all_layers();
+    }
+
+    fn scroll_fragment_point(&mut self, pipeline_id: PipelineId, point: Point2D<f32>) {
+        // Only the root layer of a pipeline scrolls, and it only has content to scroll to once
+        // the render task has described the layers of the pipeline.
+        let scrolled = match self.find_layer_mut(pipeline_id, LayerId::null()) {
+            Some(layer) => {
+                if layer.children.len() > 0 {
+                    layer.scroll_to(point);
+                }
+                layer.children.len() > 0
+            }
+            None => false,
+        };
+
+        if scrolled {
+            self.fragment_point = None;
+            match self.root {
+                Some(ref root) => root.send_viewport_rects(),
+                None => {}
+            }
+            self.send_buffer_requests_for_all_layers();
+        } else {
+            self.fragment_point = Some((pipeline_id, point));
+        }
+    }
+
+    fn paint(&mut self,
+             pipeline_id: PipelineId,
+             epoch: Epoch,
+             replies: Vec<(LayerId, Vec<SoftwareLayerBuffer>)>) {
+        for (layer_id, tiles) in replies.into_iter() {
+            match self.find_layer_mut(pipeline_id, layer_id) {
+                Some(layer) => {
+                    if layer.epoch == epoch {
+                        layer.tiles = tiles;
+                    } else {
+                        debug!("software compositor epoch mismatch: {} != {}",
+                               layer.epoch,
+                               epoch);
+                    }
+                }
+                None => debug!("software compositor given tiles for non-existent layer"),
+            }
+        }
+    }
+
+    fn send_buffer_requests_for_all_layers(&mut self) {
+        let mut requests = HashMap::new();
+        match self.root {
+            Some(ref root) => {
+                let clip = Rect(Point2D(0f32, 0f32), Size2D(self.window_size.width as f32,
+                                                             self.window_size.height as f32));
+                root.collect_buffer_requests(&mut requests, Point2D(0f32, 0f32), clip);
+            }
+            None => {}
+        }
+
+        for (_, (chan, pipeline_requests)) in requests.into_iter() {
+            if chan.send_opt(RenderMsg(pipeline_requests)).is_ok() {
+                self.outstanding_render_msgs += 1;
+            }
+        }
+    }
+
+    fn remove_outstanding_render_msg(&mut self) {
+        if self.outstanding_render_msgs > 0 {
+            self.outstanding_render_msgs -= 1;
+        } else {
+            debug!("too many rerender msgs completed");
+        }
+    }
+
+    fn is_ready_to_render_image_output(&self) -> bool {
+        if !self.got_load_complete_message || !self.got_set_ids_message {
+            return false;
+        }
+
+        let earliest_ready_state =
+            self.ready_states.values().fold(FinishedLoading, |a, &b| cmp::min(a, b));
+        if self.ready_states.len() == 0 || earliest_ready_state != FinishedLoading {
+            return false;
+        }
+
+        self.outstanding_render_msgs == 0 &&
+            self.render_states.values().all(|&state| state == IdleRenderState)
+    }
+
+    /// Flattens the layer tree into an image of the window.
+    fn composite(&self) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(self.window_size.width, self.window_size.height);
+        profile(time::CompositingCategory, None, self.time_profiler_chan.clone(), || {
+            debug!("software compositor: compositing");
+            match self.root {
+                Some(ref root) => {
+                    let clip = framebuffer.bounds();
+                    root.composite(&mut framebuffer, Point2D(0f32, 0f32), clip)
+                }
+                None => {}
+            }
+        });
+        framebuffer
+    }
+
+    /// Writes the composited page to the output file and shuts down.
+    fn composite_and_exit(&mut self) {
+        let framebuffer = self.composite();
+        let path = from_str::<Path>(opts::get().output_file.as_ref().unwrap().as_slice()).unwrap();
+        let mut img = png::Image {
+            width: framebuffer.width as u32,
+            height: framebuffer.height as u32,
+            pixels: png::RGB8(framebuffer.pixels),
+        };
+        let res = png::store_png(&mut img, &path);
+        assert!(res.is_ok());
+
+        debug!("shutting down the constellation after generating an output file");
+        let ConstellationChan(ref chan) = self.constellation_chan;
+        chan.send(ExitMsg);
+        self.shutting_down = true;
+    }
+}
+
+impl CompositorEventListener for SoftwareCompositor {
+    fn handle_event(&mut self, _: WindowEvent) -> bool {
+        match self.port.recv_compositor_msg() {
+            Exit(chan) => {
+                debug!("shutting down the constellation");
+                let ConstellationChan(ref con_chan) = self.constellation_chan;
+                con_chan.send(ExitMsg);
+                chan.send(());
+                self.shutting_down = true;
+            }
+
+            ShutdownComplete => {
+                debug!("constellation completed shutdown");
+                return false
+            }
+
+            GetGraphicsMetadata(chan) => {
+                chan.send(None);
+            }
+
+            SetIds(frame_tree, response_chan, new_constellation_chan) => {
+                response_chan.send(());
+                if !self.shutting_down {
+                    self.set_frame_tree(&frame_tree, new_constellation_chan);
+                }
+            }
+
+            FrameTreeUpdateMsg(frame_tree_diff, response_channel) => {
+                response_channel.send(());
+                if !self.shutting_down {
+                    let (bounds, masks_to_bounds) = match frame_tree_diff.rect {
+                        Some(ref rect) => (rect.to_untyped(), true),
+                        None => (Rect::zero(), false),
+                    };
+                    let layer = SoftwareLayer::new_root(&frame_tree_diff.pipeline,
+                                                        bounds,
+                                                        masks_to_bounds);
+                    match self.find_layer_mut(frame_tree_diff.parent_pipeline.id,
+                                              LayerId::null()) {
+                        Some(parent) => parent.children.push(layer),
+                        None => panic!("Tried to add a frame to an unknown pipeline"),
+                    }
+                }
+            }
+
+            CreateScreenshot(chan) => {
+                let framebuffer = self.composite();
+                chan.send(Some(Screenshot {
+                    width: framebuffer.width,
+                    height: framebuffer.height,
+                    pixels: framebuffer.pixels,
+                }));
+            }
+
+            // Once the output has been written, the rest of the messages only need a response.
+            _ if self.shutting_down => {}
+
+            CreateOrUpdateRootLayer(properties) => self.create_or_update_layer(properties, true),
+
+            CreateOrUpdateDescendantLayer(properties) => {
+                self.create_or_update_layer(properties, false)
+            }
+
+            SetLayerOrigin(pipeline_id, layer_id, origin) => {
+                match self.find_layer_mut(pipeline_id, layer_id) {
+                    Some(layer) => layer.bounds.origin = origin,
+                    None => panic!("Compositor received SetLayerOrigin for nonexistent layer"),
+                }
+                self.send_buffer_requests_for_all_layers();
+            }
+
+            ScrollFragmentPoint(pipeline_id, _, point) => {
+                self.scroll_fragment_point(pipeline_id, point);
+            }
+
+            PaintSoftware(pipeline_id, epoch, replies) => {
+                self.paint(pipeline_id, epoch, replies);
+                self.remove_outstanding_render_msg();
+            }
+
+            Paint(..) => {
+                // We never hand the renderer graphics metadata, so it never paints into native
+                // surfaces.
+                panic!("software compositor received tiles painted into native surfaces");
+            }
+
+            ChangeReadyState(pipeline_id, ready_state) => {
+                self.ready_states.insert(pipeline_id, ready_state);
+            }
+
+            ChangeRenderState(pipeline_id, render_state) => {
+                self.render_states.insert(pipeline_id, render_state);
+            }
+
+            RenderMsgDiscarded => self.remove_outstanding_render_msg(),
+
+            LoadComplete => self.got_load_complete_message = true,
+
+            ScrollTimeout(..) => {}
+        }
+
+        if opts::get().output_file.is_some() && !self.shutting_down &&
+                self.is_ready_to_render_image_output() {
+            self.composite_and_exit();
+        }
+        true
+    }
+
+    fn repaint_synchronously(&mut self) {}
+
+    fn shutdown(&mut self) {
+        // Drain compositor port, sometimes messages contain channels that are blocking
+        // another task from finishing (i.e. SetIds)
+        while self.port.try_recv_compositor_msg().is_some() {}
+
+        self.time_profiler_chan.send(time::ExitMsg);
+        self.memory_profiler_chan.send(memory::ExitMsg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::blend_pixel;
+
+    #[test]
+    fn test_blend_pixel() {
+        // Opaque pixels replace the destination; the source is BGRA and the destination RGB.
+        let mut pixel = [255u8, 255, 255];
+        blend_pixel(pixel.as_mut_slice(), [0u8, 0, 255, 255].as_slice(), 1.0);
+        assert_eq!(pixel.as_slice(), [255u8, 0, 0].as_slice());
+
+        // Half transparent black over white is grey.
+        let mut pixel = [255u8, 255, 255];
+        blend_pixel(pixel.as_mut_slice(), [0u8, 0, 0, 255].as_slice(), 0.5);
+        assert_eq!(pixel.as_slice(), [128u8, 128, 128].as_slice());
+
+        // Transparent pixels leave the destination alone.
+        let mut pixel = [10u8, 20, 30];
+        blend_pixel(pixel.as_mut_slice(), [0u8, 0, 0, 0].as_slice(), 1.0);
+        assert_eq!(pixel.as_slice(), [10u8, 20, 30].as_slice());
+    }
+}
@@ -123,6 +123,11 @@ impl ActorRegistry {
         self.actors.insert(actor.name().to_string(), actor);
     }
 
+    /// Remove an actor from the registry, so that it no longer receives messages.
+    pub fn unregister(&mut self, name: &str) {
+        self.actors.remove(&name.to_string());
+    }
+
     pub fn register_later(&self, actor: Box<Actor+Send+Sized>) {
         let mut actors = self.new_actors.borrow_mut();
         actors.push(actor);
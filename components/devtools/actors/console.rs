@@ -7,14 +7,19 @@
 /// inspection, JS evaluation, autocompletion) in Servo.
 
 use actor::{Actor, ActorRegistry};
+use actors::object::argument_grip;
 use protocol::JsonPacketStream;
 
 use devtools_traits::{EvaluateJS, NullValue, VoidValue, NumberValue, StringValue, BooleanValue};
-use devtools_traits::{ActorValue, DevtoolScriptControlMsg};
+use devtools_traits::{ActorValue, DevtoolScriptControlMsg, ConsoleMessage, CallSite};
+use devtools_traits::{TimerStarted, TimerEnded};
+use devtools_traits::{add_console_api_listener, remove_console_api_listener};
+use devtools_traits::{add_network_listener, remove_network_listener};
 use servo_msg::constellation_msg::PipelineId;
 
 use collections::TreeMap;
-use core::cell::RefCell;
+use std::collections::RingBuf;
+use core::cell::{Cell, RefCell};
 use serialize::json;
 use serialize::json::ToJson;
 use std::io::TcpStream;
@@ -91,6 +96,13 @@ struct AutocompleteReply {
     matchProp: String,
 }
 
+#[deriving(Encodable)]
+struct ConsoleAPICallMsg {
+    from: String,
+    __type__: String,
+    message: json::Json,
+}
+
 #[deriving(Encodable)]
 struct EvaluateJSReply {
     from: String,
@@ -102,11 +114,113 @@ struct EvaluateJSReply {
     helperResult: json::Json,
 }
 
+/// How many console API calls are kept for clients that attach later.
+static MAX_CACHED_MESSAGES: uint = 1000;
+
 pub struct ConsoleActor {
     pub name: String,
     pub pipeline: PipelineId,
     pub script_chan: Sender<DevtoolScriptControlMsg>,
     pub streams: RefCell<Vec<TcpStream>>,
+    /// The latest console API calls made by the current document, for `getCachedMessages`.
+    pub cached_messages: RefCell<RingBuf<json::JsonObject>>,
+    /// Whether a client has started the `NetworkActivity` listener.
+    pub listening_to_network: Cell<bool>,
+    /// Whether a client has started the `ConsoleAPI` listener.
+    pub listening_to_console_api: Cell<bool>,
+}
+
+impl ConsoleActor {
+    /// Stops counting this console among those listening for network activity.
+    pub fn stop_listening_to_network(&self) {
+        if self.listening_to_network.get() {
+            self.listening_to_network.set(false);
+            remove_network_listener();
+        }
+    }
+
+    /// Stops counting this console among those listening for console API calls.
+    pub fn stop_listening_to_console_api(&self) {
+        if self.listening_to_console_api.get() {
+            self.listening_to_console_api.set(false);
+            remove_console_api_listener();
+        }
+    }
+
+    /// Reports a console API call to the attached clients, and caches it for later ones.
+    pub fn console_api_call(&self, registry: &ActorRegistry, message: ConsoleMessage) {
+        let message = console_message_json(registry, message);
+
+        let mut cached = message.clone();
+        cached.insert("_type".to_string(), "ConsoleAPI".to_string().to_json());
+        let mut cached_messages = self.cached_messages.borrow_mut();
+        if cached_messages.len() == MAX_CACHED_MESSAGES {
+            cached_messages.pop_front();
+        }
+        cached_messages.push_back(cached);
+
+        let msg = ConsoleAPICallMsg {
+            from: self.name(),
+            __type__: "consoleAPICall".to_string(),
+            message: json::Object(message),
+        };
+        for stream in self.streams.borrow_mut().iter_mut() {
+            stream.write_json_packet(&msg);
+        }
+    }
+}
+
+fn call_site_json(call_site: &CallSite) -> json::JsonObject {
+    let mut m = TreeMap::new();
+    m.insert("filename".to_string(), call_site.location.url.to_json());
+    m.insert("lineNumber".to_string(), call_site.location.line.to_json());
+    m.insert("columnNumber".to_string(), call_site.location.column.to_json());
+    m.insert("functionName".to_string(),
+             call_site.function.clone().unwrap_or("".to_string()).to_json());
+    m
+}
+
+fn console_message_json(registry: &ActorRegistry, message: ConsoleMessage) -> json::JsonObject {
+    let mut m = call_site_json(&message.location);
+    m.insert("level".to_string(), message.level.to_json());
+    m.insert("timeStamp".to_string(), message.time_stamp.to_json());
+    m.insert("arguments".to_string(), json::List(message.arguments.iter().map(|argument| {
+        argument_grip(registry, argument)
+    }).collect()));
+    m.insert("styles".to_string(), message.styles.to_json());
+    m.insert("groupName".to_string(), message.group_name.unwrap_or("".to_string()).to_json());
+    m.insert("workerType".to_string(),
+             (if message.worker { "dedicated-worker" } else { "none" }).to_string().to_json());
+    if !message.stacktrace.is_empty() {
+        m.insert("stacktrace".to_string(), json::List(message.stacktrace.iter().map(|call_site| {
+            json::Object(call_site_json(call_site))
+        }).collect()));
+    }
+    match message.timer {
+        Some(TimerStarted(name)) => {
+            let mut timer = TreeMap::new();
+            timer.insert("name".to_string(), name.to_json());
+            timer.insert("started".to_string(), true.to_json());
+            m.insert("timer".to_string(), json::Object(timer));
+        }
+        Some(TimerEnded(name, duration)) => {
+            let mut timer = TreeMap::new();
+            timer.insert("name".to_string(), name.to_json());
+            timer.insert("duration".to_string(), duration.to_json());
+            m.insert("timer".to_string(), json::Object(timer));
+        }
+        None => (),
+    }
+    match message.counter {
+        Some(counter) => {
+            let mut count = TreeMap::new();
+            count.insert("label".to_string(), counter.label.to_json());
+            count.insert("count".to_string(), counter.count.to_json());
+            m.insert("counter".to_string(), json::Object(count));
+        }
+        None => (),
+    }
+    m
 }
 
 impl Actor for ConsoleActor {
@@ -122,12 +236,14 @@ impl Actor for ConsoleActor {
         Ok(match msg_type.as_slice() {
             "getCachedMessages" => {
                 let types = msg.get(&"messageTypes".to_string()).unwrap().as_list().unwrap();
-                let /*mut*/ messages = vec!();
+                let mut messages = vec!();
                 for msg_type in types.iter() {
                     let msg_type = msg_type.as_string().unwrap();
                     match msg_type.as_slice() {
                         "ConsoleAPI" => {
-                            //TODO: figure out all consoleapi properties from FFOX source
+                            messages.extend(self.cached_messages.borrow().iter().map(|message| {
+                                message.clone()
+                            }));
                         }
 
                         "PageError" => {
@@ -176,11 +292,30 @@ impl Actor for ConsoleActor {
 
             "startListeners" => {
                 //TODO: actually implement listener filters that support starting/stopping
+                let listeners = msg.get(&"listeners".to_string())
+                                   .and_then(|listeners| listeners.as_list());
+                let network = listeners.map_or(false, |listeners| {
+                    listeners.iter().any(|listener| {
+                        listener.as_string() == Some("NetworkActivity")
+                    })
+                });
+                if network && !self.listening_to_network.get() {
+                    self.listening_to_network.set(true);
+                    add_network_listener();
+                }
+                let console_api = listeners.map_or(false, |listeners| {
+                    listeners.iter().any(|listener| listener.as_string() == Some("ConsoleAPI"))
+                });
+                if console_api && !self.listening_to_console_api.get() {
+                    self.listening_to_console_api.set(true);
+                    add_console_api_listener();
+                }
                 let msg = StartedListenersReply {
                     from: self.name(),
                     nativeConsoleAPI: true,
                     startedListeners:
-                        vec!("PageError".to_string(), "ConsoleAPI".to_string()),
+                        vec!("PageError".to_string(), "ConsoleAPI".to_string(),
+                             "NetworkActivity".to_string()),
                     traits: StartedListenersTraits {
                         customNetworkRequest: true,
                     }
@@ -194,13 +329,19 @@ impl Actor for ConsoleActor {
                 let msg = StopListenersReply {
                     from: self.name(),
                     stoppedListeners: msg.get(&"listeners".to_string())
-                                         .unwrap()
-                                         .as_list()
+                                         .and_then(|listeners| listeners.as_list())
                                          .unwrap_or(&vec!())
                                          .iter()
-                                         .map(|listener| listener.as_string().unwrap().to_string())
+                                         .filter_map(|listener| listener.as_string())
+                                         .map(|listener| listener.to_string())
                                          .collect(),
                 };
+                if msg.stoppedListeners.iter().any(|l| l.as_slice() == "NetworkActivity") {
+                    self.stop_listening_to_network();
+                }
+                if msg.stoppedListeners.iter().any(|l| l.as_slice() == "ConsoleAPI") {
+                    self.stop_listening_to_console_api();
+                }
                 stream.write_json_packet(&msg);
                 true
             }
@@ -5,9 +5,12 @@
 /// Liberally derived from the [Firefox JS implementation](http://mxr.mozilla.org/mozilla-central/source/toolkit/devtools/server/actors/inspector.js).
 
 use devtools_traits::{GetRootNode, GetDocumentElement, GetChildren, DevtoolScriptControlMsg};
-use devtools_traits::{GetLayout, NodeInfo, ModifyAttribute};
+use devtools_traits::{GetLayout, NodeInfo, ModifyAttribute, GetAppliedRules};
 
 use actor::{Actor, ActorRegistry};
+use actors::stylesheets::{StyleRuleForm, StyleSheetForm};
+use actors::stylesheets::{element_style_form, get_stylesheets, style_rule_form};
+use actors::stylesheets::stylesheet_form;
 use protocol::JsonPacketStream;
 
 use collections::TreeMap;
@@ -353,14 +356,8 @@ struct PageStyleActor {
 #[deriving(Encodable)]
 struct GetAppliedReply {
     entries: Vec<AppliedEntry>,
-    rules: Vec<AppliedRule>,
-    sheets: Vec<AppliedSheet>,
-    from: String,
-}
-
-#[deriving(Encodable)]
-struct GetComputedReply {
-    computed: Vec<uint>, //XXX all css props
+    rules: Vec<StyleRuleForm>,
+    sheets: Vec<StyleSheetForm>,
     from: String,
 }
 
@@ -370,29 +367,7 @@ struct AppliedEntry {
     pseudoElement: json::Json,
     isSystem: bool,
     matchedSelectors: Vec<String>,
-}
-
-#[deriving(Encodable)]
-struct AppliedRule {
-    actor: String,
-    __type__: uint,
-    href: String,
-    cssText: String,
-    line: uint,
-    column: uint,
-    parentStyleSheet: String,
-}
-
-#[deriving(Encodable)]
-struct AppliedSheet {
-    actor: String,
-    href: String,
-    nodeHref: String,
-    disabled: bool,
-    title: String,
-    system: bool,
-    styleSheetIndex: int,
-    ruleCount: uint,
+    specificity: u32,
 }
 
 #[deriving(Encodable)]
@@ -424,27 +399,81 @@ impl Actor for PageStyleActor {
                       stream: &mut TcpStream) -> Result<bool, ()> {
         Ok(match msg_type.as_slice() {
             "getApplied" => {
-                //TODO: query script for relevant applied styles to node (msg.node)
-                let msg = GetAppliedReply {
-                    entries: vec!(),
-                    rules: vec!(),
-                    sheets: vec!(),
-                    from: self.name(),
+                let target = match msg.get(&"node".to_string()).and_then(|node| node.as_string()) {
+                    Some(target) => target,
+                    None => return Err(()),
                 };
-                stream.write_json_packet(&msg);
-                true
-            }
+                let sheets = try!(get_stylesheets(&self.script_chan, self.pipeline));
+                let node = registry.actor_to_script(target.to_string());
+                let (tx, rx) = channel();
+                self.script_chan.send(GetAppliedRules(self.pipeline, node.clone(), tx));
+                let mut applied = try!(rx.recv_opt());
+                // The style attribute comes first, then the most specific rules, and later rules
+                // before earlier ones of the same specificity, as they take precedence.
+                applied.reverse();
+                applied.sort_by(|a, b| {
+                    (a.stylesheet.is_some(), b.specificity)
+                        .cmp(&(b.stylesheet.is_some(), a.specificity))
+                });
+
+                let sheet_forms: Vec<StyleSheetForm> =
+                    sheets.iter().enumerate().map(|(position, sheet)| {
+                        stylesheet_form(registry, &self.script_chan, self.pipeline, position, sheet)
+                    }).collect();
+                let mut entries = vec!();
+                let mut rules = vec!();
+                let mut used_sheets: Vec<uint> = vec!();
+                for rule in applied.iter() {
+                    let stylesheet = match rule.stylesheet {
+                        Some(stylesheet) => stylesheet,
+                        None => {
+                            let form = element_style_form(registry, &self.script_chan,
+                                                          self.pipeline, node.clone(), rule);
+                            entries.push(AppliedEntry {
+                                rule: form.actor.clone(),
+                                pseudoElement: json::Null,
+                                isSystem: false,
+                                matchedSelectors: vec!(),
+                                specificity: rule.specificity,
+                            });
+                            rules.push(form);
+                            continue
+                        }
+                    };
+                    let position = match sheets.iter()
+                                               .position(|sheet| sheet.index == stylesheet) {
+                        Some(position) => position,
+                        None => continue,
+                    };
+                    let form = style_rule_form(registry, &self.script_chan, self.pipeline,
+                                               &sheet_forms[position], stylesheet, rule);
+                    entries.push(AppliedEntry {
+                        rule: form.actor.clone(),
+                        pseudoElement: json::Null,
+                        isSystem: false,
+                        matchedSelectors: vec!(rule.selector.clone()),
+                        specificity: rule.specificity,
+                    });
+                    rules.push(form);
+                    if !used_sheets.contains(&position) {
+                        used_sheets.push(position);
+                    }
+                }
 
-            "getComputed" => {
-                //TODO: query script for relevant computed styles on node (msg.node)
-                let msg = GetComputedReply {
-                    computed: vec!(),
+                let msg = GetAppliedReply {
+                    entries: entries,
+                    rules: rules,
+                    sheets: used_sheets.iter().map(|&position| {
+                        sheet_forms[position].clone()
+                    }).collect(),
                     from: self.name(),
                 };
                 stream.write_json_packet(&msg);
                 true
             }
 
+            //TODO: getComputed, once layout can report the computed values of a node.
+
             //TODO: query script for box layout properties of node (msg.node)
             "getLayout" => {
                 let target = msg.get(&"node".to_string()).unwrap().as_string().unwrap();
@@ -0,0 +1,372 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/// Liberally derived from the [Firefox JS implementation](http://mxr.mozilla.org/mozilla-central/source/toolkit/devtools/server/actors/webconsole.js).
+/// Handles interaction with the remote network monitor for a single HTTP request, which is
+/// announced to the console of every attached tab.
+
+use actor::{Actor, ActorRegistry};
+use protocol::JsonPacketStream;
+
+use devtools_traits::{HttpRequest, HttpResponse};
+
+use serialize::json;
+use std::ascii::AsciiExt;
+use std::io::TcpStream;
+use time;
+use time::Timespec;
+
+#[deriving(Encodable)]
+struct EventActor {
+    actor: String,
+    url: String,
+    method: String,
+    startedDateTime: String,
+    isXHR: bool,
+    private: bool,
+}
+
+#[deriving(Encodable)]
+struct NetworkEventMsg {
+    from: String,
+    __type__: String,
+    eventActor: EventActor,
+}
+
+#[deriving(Encodable)]
+struct HeadersUpdateMsg {
+    from: String,
+    __type__: String,
+    updateType: String,
+    headers: uint,
+    headersSize: uint,
+}
+
+#[deriving(Encodable)]
+struct ResponseStartMsg {
+    httpVersion: String,
+    status: String,
+    statusText: String,
+    headersSize: uint,
+    discardResponseBody: bool,
+}
+
+#[deriving(Encodable)]
+struct ResponseStartUpdateMsg {
+    from: String,
+    __type__: String,
+    updateType: String,
+    response: ResponseStartMsg,
+}
+
+#[deriving(Encodable)]
+struct ResponseContentUpdateMsg {
+    from: String,
+    __type__: String,
+    updateType: String,
+    mimeType: String,
+    contentSize: uint,
+    transferredSize: uint,
+    discardResponseBody: bool,
+}
+
+#[deriving(Encodable)]
+struct EventTimingsUpdateMsg {
+    from: String,
+    __type__: String,
+    updateType: String,
+    totalTime: u64,
+}
+
+#[deriving(Encodable)]
+struct HeaderMsg {
+    name: String,
+    value: String,
+}
+
+#[deriving(Encodable)]
+struct GetHeadersReply {
+    from: String,
+    headers: Vec<HeaderMsg>,
+    headersSize: uint,
+    rawHeaders: String,
+}
+
+#[deriving(Encodable)]
+struct GetCookiesReply {
+    from: String,
+    cookies: Vec<String>,
+}
+
+#[deriving(Encodable)]
+struct PostDataMsg {
+    text: String,
+}
+
+#[deriving(Encodable)]
+struct GetRequestPostDataReply {
+    from: String,
+    postData: PostDataMsg,
+    postDataDiscarded: bool,
+}
+
+#[deriving(Encodable)]
+struct ContentMsg {
+    mimeType: String,
+    size: uint,
+    text: String,
+}
+
+#[deriving(Encodable)]
+struct GetResponseContentReply {
+    from: String,
+    content: ContentMsg,
+    contentDiscarded: bool,
+}
+
+#[deriving(Encodable)]
+struct TimingsMsg {
+    blocked: u64,
+    dns: u64,
+    connect: u64,
+    send: u64,
+    wait: u64,
+    receive: u64,
+}
+
+#[deriving(Encodable)]
+struct GetEventTimingsReply {
+    from: String,
+    timings: TimingsMsg,
+    totalTime: u64,
+}
+
+pub struct NetworkEventActor {
+    pub name: String,
+    pub request: HttpRequest,
+    pub response: Option<HttpResponse>,
+}
+
+/// The size of the headers as they were sent, as `name: value\r\n` lines.
+fn headers_size(headers: &Vec<(String, String)>) -> uint {
+    headers.iter().fold(0, |size, &(ref name, ref value)| size + name.len() + value.len() + 4)
+}
+
+fn raw_headers(headers: &Vec<(String, String)>) -> String {
+    let mut raw = String::new();
+    for &(ref name, ref value) in headers.iter() {
+        raw.push_str(format!("{}: {}\r\n", name, value).as_slice());
+    }
+    raw
+}
+
+impl Actor for NetworkEventActor {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn handle_message(&self,
+                      _registry: &ActorRegistry,
+                      msg_type: &String,
+                      _msg: &json::JsonObject,
+                      stream: &mut TcpStream) -> Result<bool, ()> {
+        Ok(match msg_type.as_slice() {
+            "getRequestHeaders" => {
+                stream.write_json_packet(&self.headers_reply(&self.request.headers));
+                true
+            }
+
+            "getRequestCookies" | "getResponseCookies" => {
+                //TODO: report the cookies once the resource task keeps track of them
+                let msg = GetCookiesReply {
+                    from: self.name(),
+                    cookies: vec!(),
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            "getRequestPostData" => {
+                let msg = GetRequestPostDataReply {
+                    from: self.name(),
+                    postData: PostDataMsg {
+                        text: self.request.body.as_ref().map_or(String::new(), |body| {
+                            String::from_utf8_lossy(body.as_slice()).into_string()
+                        }),
+                    },
+                    postDataDiscarded: false,
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            "getResponseHeaders" => {
+                let headers = self.response.as_ref().map_or(vec!(), |response| {
+                    response.headers.clone()
+                });
+                stream.write_json_packet(&self.headers_reply(&headers));
+                true
+            }
+
+            "getResponseContent" => {
+                let (size, text) = match self.response {
+                    Some(ref response) => {
+                        (response.body.len(),
+                         String::from_utf8_lossy(response.body.as_slice()).into_string())
+                    }
+                    None => (0, String::new()),
+                };
+                let msg = GetResponseContentReply {
+                    from: self.name(),
+                    content: ContentMsg {
+                        mimeType: self.mime_type(),
+                        size: size,
+                        text: text,
+                    },
+                    contentDiscarded: false,
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            "getEventTimings" => {
+                let timings = match self.response {
+                    Some(ref response) => TimingsMsg {
+                        blocked: 0,
+                        dns: 0,
+                        connect: 0,
+                        send: response.timings.send,
+                        wait: response.timings.wait,
+                        receive: response.timings.receive,
+                    },
+                    None => TimingsMsg {
+                        blocked: 0,
+                        dns: 0,
+                        connect: 0,
+                        send: 0,
+                        wait: 0,
+                        receive: 0,
+                    },
+                };
+                let msg = GetEventTimingsReply {
+                    from: self.name(),
+                    totalTime: timings.send + timings.wait + timings.receive,
+                    timings: timings,
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            _ => false
+        })
+    }
+}
+
+impl NetworkEventActor {
+    fn headers_reply(&self, headers: &Vec<(String, String)>) -> GetHeadersReply {
+        GetHeadersReply {
+            from: self.name(),
+            headers: headers.iter().map(|&(ref name, ref value)| HeaderMsg {
+                name: name.clone(),
+                value: value.clone(),
+            }).collect(),
+            headersSize: headers_size(headers),
+            rawHeaders: raw_headers(headers),
+        }
+    }
+
+    fn mime_type(&self) -> String {
+        self.response.as_ref().and_then(|response| {
+            response.headers.iter().find(|&&(ref name, _)| {
+                name.as_slice().eq_ignore_ascii_case("content-type")
+            }).map(|&(_, ref value)| value.clone())
+        }).unwrap_or(String::new())
+    }
+
+    fn update_msg(&self, update_type: &str) -> HeadersUpdateMsg {
+        HeadersUpdateMsg {
+            from: self.name(),
+            __type__: "networkEventUpdate".to_string(),
+            updateType: update_type.to_string(),
+            headers: 0,
+            headersSize: 0,
+        }
+    }
+
+    /// Announces the request to a client through the given console actor.
+    pub fn notify_request(&self, console: &str, stream: &mut TcpStream) {
+        let start_time = Timespec::new((self.request.start_time / 1000) as i64,
+                                       ((self.request.start_time % 1000) * 1000000) as i32);
+        let msg = NetworkEventMsg {
+            from: console.to_string(),
+            __type__: "networkEvent".to_string(),
+            eventActor: EventActor {
+                actor: self.name(),
+                url: self.request.url.clone(),
+                method: self.request.method.clone(),
+                startedDateTime: time::at_utc(start_time).rfc3339(),
+                isXHR: false,
+                private: false,
+            },
+        };
+        stream.write_json_packet(&msg);
+
+        let msg = HeadersUpdateMsg {
+            headers: self.request.headers.len(),
+            headersSize: headers_size(&self.request.headers),
+            ..self.update_msg("requestHeaders")
+        };
+        stream.write_json_packet(&msg);
+    }
+
+    /// Tells a client that the response has arrived, so that it can ask for its details.
+    pub fn notify_response(&self, stream: &mut TcpStream) {
+        let response = match self.response {
+            Some(ref response) => response,
+            None => return,
+        };
+        let (status, status_text) = response.status.clone().unwrap_or((0, String::new()));
+
+        let msg = ResponseStartUpdateMsg {
+            from: self.name(),
+            __type__: "networkEventUpdate".to_string(),
+            updateType: "responseStart".to_string(),
+            response: ResponseStartMsg {
+                httpVersion: "HTTP/1.1".to_string(),
+                status: status.to_string(),
+                statusText: status_text,
+                headersSize: headers_size(&response.headers),
+                discardResponseBody: false,
+            },
+        };
+        stream.write_json_packet(&msg);
+
+        let msg = HeadersUpdateMsg {
+            headers: response.headers.len(),
+            headersSize: headers_size(&response.headers),
+            ..self.update_msg("responseHeaders")
+        };
+        stream.write_json_packet(&msg);
+
+        let msg = ResponseContentUpdateMsg {
+            from: self.name(),
+            __type__: "networkEventUpdate".to_string(),
+            updateType: "responseContent".to_string(),
+            mimeType: self.mime_type(),
+            contentSize: response.body.len(),
+            transferredSize: response.body.len(),
+            discardResponseBody: false,
+        };
+        stream.write_json_packet(&msg);
+
+        let msg = EventTimingsUpdateMsg {
+            from: self.name(),
+            __type__: "networkEventUpdate".to_string(),
+            updateType: "eventTimings".to_string(),
+            totalTime: response.timings.send + response.timings.wait + response.timings.receive,
+        };
+        stream.write_json_packet(&msg);
+    }
+}
+
@@ -0,0 +1,166 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/// Liberally derived from the [Firefox JS implementation](http://mxr.mozilla.org/mozilla-central/source/toolkit/devtools/server/actors/script.js).
+/// Objects that were logged to the console. Script only sends a preview of their own
+/// properties, so that is all that can be inspected.
+
+use actor::{Actor, ActorRegistry};
+use actors::thread::value_grip;
+use protocol::JsonPacketStream;
+
+use devtools_traits::ConsoleArgument;
+
+use collections::TreeMap;
+use serialize::json;
+use serialize::json::ToJson;
+use std::io::TcpStream;
+
+#[deriving(Encodable)]
+struct PrototypeAndPropertiesReply {
+    from: String,
+    prototype: json::Json,
+    ownProperties: json::Json,
+    safeGetterValues: json::Json,
+}
+
+#[deriving(Encodable)]
+struct OwnPropertyNamesReply {
+    from: String,
+    ownPropertyNames: Vec<String>,
+}
+
+#[deriving(Encodable)]
+struct PrototypeReply {
+    from: String,
+    prototype: json::Json,
+}
+
+#[deriving(Encodable)]
+struct ReleaseReply {
+    from: String,
+}
+
+pub struct ObjectActor {
+    name: String,
+    /// The grips of the previewed own properties.
+    properties: Vec<(String, json::Json)>,
+}
+
+impl ObjectActor {
+    fn own_properties(&self) -> json::Json {
+        let mut properties = TreeMap::new();
+        for &(ref name, ref grip) in self.properties.iter() {
+            let mut descriptor = TreeMap::new();
+            descriptor.insert("value".to_string(), grip.clone());
+            descriptor.insert("configurable".to_string(), true.to_json());
+            descriptor.insert("enumerable".to_string(), true.to_json());
+            descriptor.insert("writable".to_string(), true.to_json());
+            properties.insert(name.clone(), json::Object(descriptor));
+        }
+        json::Object(properties)
+    }
+}
+
+impl Actor for ObjectActor {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn handle_message(&self,
+                      _registry: &ActorRegistry,
+                      msg_type: &String,
+                      _msg: &json::JsonObject,
+                      stream: &mut TcpStream) -> Result<bool, ()> {
+        Ok(match msg_type.as_slice() {
+            "prototypeAndProperties" => {
+                let msg = PrototypeAndPropertiesReply {
+                    from: self.name(),
+                    prototype: null_grip(),
+                    ownProperties: self.own_properties(),
+                    safeGetterValues: json::Object(TreeMap::new()),
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            "ownPropertyNames" => {
+                let msg = OwnPropertyNamesReply {
+                    from: self.name(),
+                    ownPropertyNames: self.properties.iter().map(|&(ref name, _)| {
+                        name.clone()
+                    }).collect(),
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            "prototype" => {
+                let msg = PrototypeReply {
+                    from: self.name(),
+                    prototype: null_grip(),
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            "release" => {
+                let msg = ReleaseReply {
+                    from: self.name(),
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            _ => false,
+        })
+    }
+}
+
+fn null_grip() -> json::Json {
+    let mut m = TreeMap::new();
+    m.insert("type".to_string(), "null".to_string().to_json());
+    json::Object(m)
+}
+
+/// Describes a console argument the way the protocol expects, creating actors for objects so
+/// that their previewed properties can be expanded.
+pub fn argument_grip(registry: &ActorRegistry, argument: &ConsoleArgument) -> json::Json {
+    if argument.value.kind.as_slice() != "object" {
+        return value_grip(&argument.value);
+    }
+
+    let properties: Vec<(String, json::Json)> =
+        argument.properties.iter().map(|&(ref name, ref property)| {
+            (name.clone(), argument_grip(registry, property))
+        }).collect();
+
+    let mut own_properties = TreeMap::new();
+    for &(ref name, ref grip) in properties.iter() {
+        let mut descriptor = TreeMap::new();
+        descriptor.insert("value".to_string(), grip.clone());
+        own_properties.insert(name.clone(), json::Object(descriptor));
+    }
+    let mut preview = TreeMap::new();
+    preview.insert("kind".to_string(), "Object".to_string().to_json());
+    preview.insert("ownProperties".to_string(), json::Object(own_properties));
+    preview.insert("ownPropertiesLength".to_string(), argument.property_count.to_json());
+
+    let actor = ObjectActor {
+        name: registry.new_name("obj"),
+        properties: properties,
+    };
+
+    let mut m = TreeMap::new();
+    m.insert("type".to_string(), "object".to_string().to_json());
+    m.insert("class".to_string(), argument.value.text.to_json());
+    m.insert("actor".to_string(), actor.name().to_json());
+    m.insert("extensible".to_string(), true.to_json());
+    m.insert("frozen".to_string(), false.to_json());
+    m.insert("sealed".to_string(), false.to_json());
+    m.insert("ownPropertyLength".to_string(), argument.property_count.to_json());
+    m.insert("preview".to_string(), json::Object(preview));
+    registry.register_later(box actor);
+    json::Object(m)
+}
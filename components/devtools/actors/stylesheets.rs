@@ -0,0 +1,412 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/// Liberally derived from the [Firefox JS implementation](http://mxr.mozilla.org/mozilla-central/source/toolkit/devtools/server/actors/stylesheets.js).
+/// Lists the stylesheets of a tab's document and applies edits to them and to their rules live,
+/// for the style editor and the inspector's rule view.
+
+use actor::{Actor, ActorRegistry};
+use protocol::JsonPacketStream;
+
+use devtools_traits::{DevtoolScriptControlMsg, GetStylesheets, SetRuleText, SetStylesheetText};
+use devtools_traits::{ModifyRuleProperties, PropertyModification};
+use devtools_traits::{ModifyAttribute, ModifyStyleAttributeProperties, Modification};
+use devtools_traits::{AppliedRuleInfo, StylesheetInfo};
+
+use servo_msg::constellation_msg::PipelineId;
+use serialize::json;
+use std::cell::RefCell;
+use std::io::TcpStream;
+
+#[deriving(Clone, Encodable)]
+pub struct StyleSheetForm {
+    pub actor: String,
+    /// The URL of the stylesheet, or null for an inline `<style>` element.
+    pub href: Option<String>,
+    pub nodeHref: String,
+    pub disabled: bool,
+    pub title: String,
+    pub system: bool,
+    pub styleSheetIndex: int,
+    pub ruleCount: uint,
+}
+
+#[deriving(Clone, Encodable)]
+pub struct StyleRuleForm {
+    pub actor: String,
+    pub __type__: uint,
+    pub href: Option<String>,
+    pub cssText: String,
+    pub line: uint,
+    pub column: uint,
+    pub parentStyleSheet: String,
+}
+
+#[deriving(Encodable)]
+struct GetStyleSheetsReply {
+    from: String,
+    styleSheets: Vec<StyleSheetForm>,
+}
+
+#[deriving(Encodable)]
+struct GetTextReply {
+    from: String,
+    text: String,
+}
+
+#[deriving(Encodable)]
+struct UpdateReply {
+    from: String,
+}
+
+#[deriving(Encodable)]
+struct StyleAppliedMsg {
+    from: String,
+    __type__: String,
+}
+
+#[deriving(Encodable)]
+struct ModifyRuleReply {
+    from: String,
+    rule: StyleRuleForm,
+}
+
+/// The `CSSRule.STYLE_RULE` type.
+const STYLE_RULE: uint = 1;
+/// The type that the rule view gives to the style attribute of an element.
+const ELEMENT_STYLE: uint = 100;
+
+pub struct StyleSheetsActor {
+    pub name: String,
+    pub script_chan: Sender<DevtoolScriptControlMsg>,
+    pub pipeline: PipelineId,
+}
+
+impl Actor for StyleSheetsActor {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn handle_message(&self,
+                      registry: &ActorRegistry,
+                      msg_type: &String,
+                      _msg: &json::JsonObject,
+                      stream: &mut TcpStream) -> Result<bool, ()> {
+        Ok(match msg_type.as_slice() {
+            "getStyleSheets" => {
+                let sheets = try!(get_stylesheets(&self.script_chan, self.pipeline));
+                let msg = GetStyleSheetsReply {
+                    from: self.name(),
+                    styleSheets: sheets.iter().enumerate().map(|(position, sheet)| {
+                        stylesheet_form(registry, &self.script_chan, self.pipeline, position,
+                                        sheet)
+                    }).collect(),
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            _ => false,
+        })
+    }
+}
+
+/// Asks script for the author stylesheets of a pipeline's document.
+pub fn get_stylesheets(script_chan: &Sender<DevtoolScriptControlMsg>, pipeline: PipelineId)
+                       -> Result<Vec<StylesheetInfo>, ()> {
+    let (tx, rx) = channel();
+    script_chan.send(GetStylesheets(pipeline, tx));
+    rx.recv_opt()
+}
+
+/// Describes a stylesheet, creating its actor the first time that it is seen. `position` is
+/// the index of the stylesheet among the document's stylesheets.
+pub fn stylesheet_form(registry: &ActorRegistry,
+                       script_chan: &Sender<DevtoolScriptControlMsg>,
+                       pipeline: PipelineId,
+                       position: uint,
+                       sheet: &StylesheetInfo) -> StyleSheetForm {
+    let PipelineId(id) = pipeline;
+    let key = format!("stylesheet-{}-{}", id, sheet.index);
+    if !registry.script_actor_registered(key.clone()) {
+        let actor = StyleSheetActor {
+            name: registry.new_name("stylesheet"),
+            script_chan: script_chan.clone(),
+            pipeline: pipeline,
+            index: sheet.index,
+        };
+        registry.register_script_actor(key.clone(), actor.name());
+        registry.register_later(box actor);
+    }
+
+    StyleSheetForm {
+        actor: registry.script_to_actor(key),
+        href: sheet.href.clone(),
+        nodeHref: "".to_string(),
+        disabled: false,
+        title: "".to_string(),
+        system: false,
+        styleSheetIndex: position as int,
+        ruleCount: sheet.rule_count,
+    }
+}
+
+/// Describes a rule that applies to an element, creating its actor the first time that it is
+/// seen and updating the text it reports otherwise.
+pub fn style_rule_form(registry: &ActorRegistry,
+                       script_chan: &Sender<DevtoolScriptControlMsg>,
+                       pipeline: PipelineId,
+                       sheet: &StyleSheetForm,
+                       stylesheet: uint,
+                       rule: &AppliedRuleInfo) -> StyleRuleForm {
+    let form = StyleRuleForm {
+        actor: "".to_string(),
+        __type__: STYLE_RULE,
+        href: sheet.href.clone(),
+        cssText: rule.css_text.clone(),
+        line: rule.line,
+        column: rule.column,
+        parentStyleSheet: sheet.actor.clone(),
+    };
+    let PipelineId(id) = pipeline;
+    let key = format!("stylerule-{}-{}-{}", id, stylesheet, rule.rule);
+    rule_form(registry, script_chan, pipeline, key, StylesheetRule(stylesheet, rule.rule), form)
+}
+
+/// Describes the style attribute of the element with the given unique id, like
+/// `style_rule_form`.
+pub fn element_style_form(registry: &ActorRegistry,
+                          script_chan: &Sender<DevtoolScriptControlMsg>,
+                          pipeline: PipelineId,
+                          node: String,
+                          rule: &AppliedRuleInfo) -> StyleRuleForm {
+    let form = StyleRuleForm {
+        actor: "".to_string(),
+        __type__: ELEMENT_STYLE,
+        href: None,
+        cssText: rule.css_text.clone(),
+        line: 0,
+        column: 0,
+        parentStyleSheet: "".to_string(),
+    };
+    let PipelineId(id) = pipeline;
+    let key = format!("elementstyle-{}-{}", id, node);
+    rule_form(registry, script_chan, pipeline, key, StyleAttribute(node), form)
+}
+
+fn rule_form(registry: &ActorRegistry,
+             script_chan: &Sender<DevtoolScriptControlMsg>,
+             pipeline: PipelineId,
+             key: String,
+             target: StyleRuleTarget,
+             form: StyleRuleForm) -> StyleRuleForm {
+    if registry.script_actor_registered(key.clone()) {
+        let actor = registry.find::<StyleRuleActor>(registry.script_to_actor(key).as_slice());
+        *actor.form.borrow_mut() = StyleRuleForm {
+            actor: actor.name(),
+            .. form
+        };
+        return actor.form.borrow().clone();
+    }
+
+    let name = registry.new_name("domstylerule");
+    let form = StyleRuleForm {
+        actor: name.clone(),
+        .. form
+    };
+    let actor = StyleRuleActor {
+        name: name.clone(),
+        script_chan: script_chan.clone(),
+        pipeline: pipeline,
+        target: target,
+        form: RefCell::new(form.clone()),
+    };
+    registry.register_script_actor(key, name);
+    registry.register_later(box actor);
+    form
+}
+
+/// One stylesheet of a tab's document.
+struct StyleSheetActor {
+    name: String,
+    script_chan: Sender<DevtoolScriptControlMsg>,
+    pipeline: PipelineId,
+    /// The index that script knows the stylesheet by.
+    index: uint,
+}
+
+impl Actor for StyleSheetActor {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn handle_message(&self,
+                      _registry: &ActorRegistry,
+                      msg_type: &String,
+                      msg: &json::JsonObject,
+                      stream: &mut TcpStream) -> Result<bool, ()> {
+        Ok(match msg_type.as_slice() {
+            "getText" => {
+                let sheets = try!(get_stylesheets(&self.script_chan, self.pipeline));
+                let text = sheets.into_iter()
+                                 .find(|sheet| sheet.index == self.index)
+                                 .map(|sheet| sheet.text)
+                                 .unwrap_or("".to_string());
+                let msg = GetTextReply {
+                    from: self.name(),
+                    text: text,
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            "update" => {
+                let text = match msg.get(&"text".to_string()).and_then(|text| text.as_string()) {
+                    Some(text) => text.to_string(),
+                    None => return Err(()),
+                };
+                self.script_chan.send(SetStylesheetText(self.pipeline, self.index, text));
+
+                let msg = UpdateReply {
+                    from: self.name(),
+                };
+                stream.write_json_packet(&msg);
+                let msg = StyleAppliedMsg {
+                    from: self.name(),
+                    __type__: "styleApplied".to_string(),
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            _ => false,
+        })
+    }
+}
+
+/// What a `StyleRuleActor` edits.
+enum StyleRuleTarget {
+    /// A rule, given by the index of its stylesheet and its index in that stylesheet.
+    StylesheetRule(uint, uint),
+    /// The style attribute of the element with the given unique id.
+    StyleAttribute(String),
+}
+
+/// A style rule that applies to an element selected in the inspector.
+pub struct StyleRuleActor {
+    name: String,
+    script_chan: Sender<DevtoolScriptControlMsg>,
+    pipeline: PipelineId,
+    target: StyleRuleTarget,
+    form: RefCell<StyleRuleForm>,
+}
+
+impl StyleRuleActor {
+    /// Replaces the declarations of the rule, and replies with its new form.
+    fn set_text(&self, text: String, stream: &mut TcpStream) {
+        match self.target {
+            StylesheetRule(stylesheet, rule) => {
+                self.script_chan.send(SetRuleText(self.pipeline, stylesheet, rule, text.clone()))
+            }
+            StyleAttribute(ref node) => {
+                let modification = Modification {
+                    attributeName: "style".to_string(),
+                    newValue: Some(text.clone()),
+                };
+                self.script_chan.send(ModifyAttribute(self.pipeline, node.clone(),
+                                                      vec!(modification)))
+            }
+        }
+        self.reply_with_text(text, stream);
+    }
+
+    /// Replies with the form of the rule, once its declarations have changed to the given ones.
+    fn reply_with_text(&self, text: String, stream: &mut TcpStream) {
+        self.form.borrow_mut().cssText = text;
+
+        let msg = ModifyRuleReply {
+            from: self.name(),
+            rule: self.form.borrow().clone(),
+        };
+        stream.write_json_packet(&msg);
+    }
+}
+
+impl Actor for StyleRuleActor {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn handle_message(&self,
+                      _registry: &ActorRegistry,
+                      msg_type: &String,
+                      msg: &json::JsonObject,
+                      stream: &mut TcpStream) -> Result<bool, ()> {
+        Ok(match msg_type.as_slice() {
+            "setRuleText" => {
+                let text = msg.get(&"newText".to_string()).and_then(|text| text.as_string());
+                match text {
+                    Some(text) => self.set_text(text.to_string(), stream),
+                    None => return Err(()),
+                }
+                true
+            }
+
+            "modifyProperties" => {
+                let modifications = msg.get(&"modifications".to_string())
+                                       .and_then(|modifications| modifications.as_list());
+                let modifications = match modifications {
+                    Some(modifications) => modifications,
+                    None => return Err(()),
+                };
+                let modifications = modifications.iter().filter_map(|modification| {
+                    property_modification(modification)
+                }).collect();
+                let (tx, rx) = channel();
+                let msg = match self.target {
+                    StylesheetRule(stylesheet, rule) => {
+                        ModifyRuleProperties(self.pipeline, stylesheet, rule, modifications, tx)
+                    }
+                    StyleAttribute(ref node) => {
+                        ModifyStyleAttributeProperties(self.pipeline, node.clone(),
+                                                       modifications, tx)
+                    }
+                };
+                self.script_chan.send(msg);
+                match try!(rx.recv_opt()) {
+                    Some(text) => self.reply_with_text(text, stream),
+                    None => return Err(()),
+                }
+                true
+            }
+
+            _ => false,
+        })
+    }
+}
+
+/// Reads a `set` or `remove` modification of a `modifyProperties` request.
+fn property_modification(modification: &json::Json) -> Option<PropertyModification> {
+    let field = |name: &str| {
+        modification.as_object()
+                    .and_then(|modification| modification.get(&name.to_string()))
+                    .and_then(|value| value.as_string())
+    };
+    let name = match field("name") {
+        Some(name) => name.to_string(),
+        None => return None,
+    };
+    let value = match field("type") {
+        Some("set") => {
+            let important = field("priority") == Some("important");
+            Some((field("value").unwrap_or("").to_string(), important))
+        }
+        Some("remove") => None,
+        _ => return None,
+    };
+    Some(PropertyModification {
+        name: name,
+        value: value,
+    })
+}
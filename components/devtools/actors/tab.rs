@@ -59,6 +59,7 @@ pub struct TabActorMsg {
     outerWindowID: uint,
     consoleActor: String,
     inspectorActor: String,
+    styleSheetsActor: String,
 }
 
 pub struct TabActor {
@@ -67,6 +68,8 @@ pub struct TabActor {
     pub url: String,
     pub console: String,
     pub inspector: String,
+    pub thread: String,
+    pub stylesheets: String,
 }
 
 impl Actor for TabActor {
@@ -91,7 +94,7 @@ impl Actor for TabActor {
                 let msg = TabAttachedReply {
                     from: self.name(),
                     __type__: "tabAttached".to_string(),
-                    threadActor: self.name(),
+                    threadActor: self.thread.clone(),
                     cacheDisabled: false,
                     javascriptEnabled: true,
                     traits: TabTraits,
@@ -111,6 +114,8 @@ impl Actor for TabActor {
                 };
                 let console_actor = registry.find::<ConsoleActor>(self.console.as_slice());
                 console_actor.streams.borrow_mut().pop();
+                console_actor.stop_listening_to_network();
+                console_actor.stop_listening_to_console_api();
                 stream.write_json_packet(&msg);
                 true
             }
@@ -138,6 +143,7 @@ impl TabActor {
             outerWindowID: 0, //FIXME: this should probably be the pipeline id
             consoleActor: self.console.clone(),
             inspectorActor: self.inspector.clone(),
+            styleSheetsActor: self.stylesheets.clone(),
         }
     }
 }
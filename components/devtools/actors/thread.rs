@@ -0,0 +1,484 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/// Liberally derived from the [Firefox JS implementation](http://mxr.mozilla.org/mozilla-central/source/toolkit/devtools/server/actors/script.js).
+/// Controls the execution of a tab's scripts: pausing, stepping, breakpoints, and the frames
+/// and sources that can be inspected while paused.
+
+use actor::{Actor, ActorRegistry};
+use protocol::JsonPacketStream;
+
+use devtools_traits::{DevtoolScriptControlMsg, AttachThread, DetachThread, Interrupt, Resume};
+use devtools_traits::{SetBreakpoint, RemoveBreakpoint, GetFrames, GetSources, GetSourceText};
+use devtools_traits::{DebuggerValue, EnvironmentInfo, FrameInfo, SourceLocation};
+use devtools_traits::{PauseInfo, StepIn, StepOut, StepOver};
+
+use collections::TreeMap;
+use servo_msg::constellation_msg::PipelineId;
+use serialize::json;
+use serialize::json::ToJson;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io::TcpStream;
+use std::num::Float;
+use std::u32;
+
+#[deriving(Encodable)]
+struct WhyMsg {
+    __type__: String,
+}
+
+#[deriving(Encodable)]
+struct ThreadAttachedReply {
+    from: String,
+    __type__: String,
+    actor: String,
+    why: WhyMsg,
+}
+
+#[deriving(Encodable)]
+struct ThreadPausedMsg {
+    from: String,
+    __type__: String,
+    actor: String,
+    why: WhyMsg,
+    frame: json::Json,
+}
+
+#[deriving(Encodable)]
+struct ThreadResumedReply {
+    from: String,
+    __type__: String,
+}
+
+#[deriving(Encodable)]
+struct ThreadDetachedReply {
+    from: String,
+    __type__: String,
+}
+
+#[deriving(Encodable)]
+struct FramesReply {
+    from: String,
+    frames: Vec<json::Json>,
+}
+
+#[deriving(Encodable)]
+struct SourceMsg {
+    actor: String,
+    url: String,
+    isBlackBoxed: bool,
+    isPrettyPrinted: bool,
+}
+
+#[deriving(Encodable)]
+struct SourcesReply {
+    from: String,
+    sources: Vec<SourceMsg>,
+}
+
+#[deriving(Encodable)]
+struct SetBreakpointReply {
+    from: String,
+    actor: String,
+    isPending: bool,
+    actualLocation: Option<SourceLocation>,
+}
+
+#[deriving(Encodable)]
+struct SourceReply {
+    from: String,
+    source: String,
+    contentType: String,
+}
+
+#[deriving(Encodable)]
+struct DeleteBreakpointReply {
+    from: String,
+}
+
+fn type_grip(type_: &str) -> json::Json {
+    let mut m = TreeMap::new();
+    m.insert("type".to_string(), type_.to_string().to_json());
+    json::Object(m)
+}
+
+/// Describes a value the way the protocol expects. Objects are described by their class only,
+/// since there is no object actor to inspect them through yet.
+pub fn value_grip(value: &DebuggerValue) -> json::Json {
+    match value.kind.as_slice() {
+        "boolean" => (value.text.as_slice() == "true").to_json(),
+        "number" => {
+            let number: f64 = from_str(value.text.as_slice()).unwrap_or(Float::nan());
+            if number.is_nan() {
+                type_grip("NaN")
+            } else if number.is_infinite() {
+                type_grip(if number < 0. { "-Infinity" } else { "Infinity" })
+            } else {
+                number.to_json()
+            }
+        }
+        "string" => value.text.to_json(),
+        "object" => {
+            let mut m = TreeMap::new();
+            m.insert("type".to_string(), "object".to_string().to_json());
+            m.insert("class".to_string(), value.text.to_json());
+            json::Object(m)
+        }
+        "null" => type_grip("null"),
+        _ => type_grip("undefined"),
+    }
+}
+
+/// Describes a chain of environments, innermost first, as nested environment grips.
+fn environment_grip(actor: &str, environments: &[EnvironmentInfo]) -> json::Json {
+    let (environment, parents) = match environments {
+        [ref environment, parents..] => (environment, parents),
+        [] => return json::Null,
+    };
+
+    let mut variables = TreeMap::new();
+    for binding in environment.bindings.iter() {
+        let mut m = TreeMap::new();
+        m.insert("value".to_string(), value_grip(&binding.value));
+        variables.insert(binding.name.clone(), json::Object(m));
+    }
+    let mut bindings = TreeMap::new();
+    bindings.insert("arguments".to_string(), json::List(vec!()));
+    bindings.insert("variables".to_string(), json::Object(variables));
+
+    let type_ = match environment.kind.as_slice() {
+        "declarative" => "block",
+        "with" => "with",
+        _ => "object",
+    };
+    let mut m = TreeMap::new();
+    m.insert("actor".to_string(), format!("{}-env{}", actor, parents.len()).to_json());
+    m.insert("type".to_string(), type_.to_string().to_json());
+    m.insert("bindings".to_string(), json::Object(bindings));
+    if !parents.is_empty() {
+        m.insert("parent".to_string(), environment_grip(actor, parents));
+    }
+    json::Object(m)
+}
+
+fn frame_grip(thread: &str, frame: &FrameInfo) -> json::Json {
+    let actor = format!("{}-frame{}", thread, frame.depth);
+    let mut location = TreeMap::new();
+    location.insert("url".to_string(), frame.location.url.to_json());
+    location.insert("line".to_string(), frame.location.line.to_json());
+    location.insert("column".to_string(), frame.location.column.to_json());
+
+    let mut m = TreeMap::new();
+    m.insert("type".to_string(), "call".to_string().to_json());
+    m.insert("depth".to_string(), frame.depth.to_json());
+    match frame.callee {
+        Some(ref callee) => {
+            let mut callee_grip = TreeMap::new();
+            callee_grip.insert("type".to_string(), "object".to_string().to_json());
+            callee_grip.insert("class".to_string(), "Function".to_string().to_json());
+            callee_grip.insert("name".to_string(), callee.to_json());
+            m.insert("callee".to_string(), json::Object(callee_grip));
+        }
+        None => (),
+    }
+    m.insert("this".to_string(), value_grip(&frame.this));
+    m.insert("arguments".to_string(),
+             json::List(frame.arguments.iter().map(value_grip).collect()));
+    m.insert("where".to_string(), json::Object(location));
+    m.insert("environment".to_string(), environment_grip(actor.as_slice(),
+                                                         frame.environment.as_slice()));
+    m.insert("actor".to_string(), actor.to_json());
+    json::Object(m)
+}
+
+pub struct ThreadActor {
+    pub name: String,
+    pub script_chan: Sender<DevtoolScriptControlMsg>,
+    pub pipeline: PipelineId,
+    /// The source actor for each script URL that has been listed.
+    pub sources: RefCell<HashMap<String, String>>,
+    pub attached: Cell<bool>,
+    /// Where script reports pauses while a client is attached.
+    pub pause_port: RefCell<Option<Receiver<PauseInfo>>>,
+    /// The stream of the attached client.
+    pub stream: RefCell<Option<TcpStream>>,
+    /// How many times script has paused since the client attached.
+    pub pauses: Cell<uint>,
+}
+
+impl ThreadActor {
+    /// Tells the attached client about the pauses that script has reported. Pauses happen while
+    /// script runs, so they are sent as they arrive rather than in reply to a request. They are
+    /// sent from the server's main loop, so that they don't interleave with other packets.
+    pub fn send_pauses(&self) {
+        let pause_port = self.pause_port.borrow();
+        let pause_port = match *pause_port {
+            Some(ref port) => port,
+            None => return,
+        };
+        let mut stream = self.stream.borrow_mut();
+        let stream = stream.as_mut().unwrap();
+        loop {
+            let info = match pause_port.try_recv() {
+                Ok(info) => info,
+                Err(_) => return,
+            };
+            self.pauses.set(self.pauses.get() + 1);
+            let msg = ThreadPausedMsg {
+                from: self.name(),
+                __type__: "paused".to_string(),
+                actor: format!("{}-pause{}", self.name, self.pauses.get()),
+                why: WhyMsg {
+                    __type__: info.reason,
+                },
+                frame: frame_grip(self.name.as_slice(), &info.frame),
+            };
+            stream.write_json_packet(&msg);
+        }
+    }
+}
+
+impl Actor for ThreadActor {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn handle_message(&self,
+                      registry: &ActorRegistry,
+                      msg_type: &String,
+                      msg: &json::JsonObject,
+                      stream: &mut TcpStream) -> Result<bool, ()> {
+        Ok(match msg_type.as_slice() {
+            // https://wiki.mozilla.org/Remote_Debugging_Protocol#Attaching_To_a_Thread
+            "attach" => {
+                if !self.attached.get() {
+                    self.attached.set(true);
+                    let (pause_chan, pause_port) = channel();
+                    self.script_chan.send(AttachThread(self.pipeline, pause_chan));
+                    *self.pause_port.borrow_mut() = Some(pause_port);
+                    *self.stream.borrow_mut() = Some(stream.clone());
+                    self.pauses.set(0);
+                }
+
+                let msg = ThreadAttachedReply {
+                    from: self.name(),
+                    __type__: "paused".to_string(),
+                    actor: format!("{}-pause0", self.name()),
+                    why: WhyMsg {
+                        __type__: "attached".to_string(),
+                    },
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            "resume" => {
+                let limit = msg.get(&"resumeLimit".to_string())
+                               .and_then(|limit| limit.as_object())
+                               .and_then(|limit| limit.get(&"type".to_string()))
+                               .and_then(|limit| limit.as_string());
+                let limit = match limit {
+                    Some("next") => Some(StepOver),
+                    Some("step") => Some(StepIn),
+                    Some("finish") => Some(StepOut),
+                    _ => None,
+                };
+                self.script_chan.send(Resume(self.pipeline, limit));
+
+                let msg = ThreadResumedReply {
+                    from: self.name(),
+                    __type__: "resumed".to_string(),
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            // The client is told about the pause once script reaches it.
+            "interrupt" => {
+                self.script_chan.send(Interrupt(self.pipeline));
+                true
+            }
+
+            "detach" => {
+                if self.attached.get() {
+                    self.attached.set(false);
+                    self.script_chan.send(DetachThread(self.pipeline));
+                    *self.pause_port.borrow_mut() = None;
+                    *self.stream.borrow_mut() = None;
+                }
+
+                let msg = ThreadDetachedReply {
+                    from: self.name(),
+                    __type__: "detached".to_string(),
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            "frames" => {
+                let start = msg.get(&"start".to_string()).and_then(|start| start.as_u64());
+                let count = msg.get(&"count".to_string()).and_then(|count| count.as_u64());
+                let (tx, rx) = channel();
+                self.script_chan.send(GetFrames(self.pipeline,
+                                                start.unwrap_or(0) as uint,
+                                                count.unwrap_or(u32::MAX as u64) as uint,
+                                                tx));
+                let frames = try!(rx.recv_opt());
+
+                let msg = FramesReply {
+                    from: self.name(),
+                    frames: frames.iter().map(|frame| {
+                        frame_grip(self.name.as_slice(), frame)
+                    }).collect(),
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            "sources" => {
+                let (tx, rx) = channel();
+                self.script_chan.send(GetSources(self.pipeline, tx));
+                let sources = try!(rx.recv_opt());
+
+                let mut actors = self.sources.borrow_mut();
+                let sources = sources.into_iter().map(|source| {
+                    let existing = actors.get(&source.url).map(|actor| actor.clone());
+                    let actor = match existing {
+                        Some(actor) => actor,
+                        None => {
+                            let actor = SourceActor {
+                                name: registry.new_name("source"),
+                                url: source.url.clone(),
+                                script_chan: self.script_chan.clone(),
+                                pipeline: self.pipeline,
+                            };
+                            let name = actor.name();
+                            actors.insert(source.url.clone(), name.clone());
+                            registry.register_later(box actor);
+                            name
+                        }
+                    };
+                    SourceMsg {
+                        actor: actor,
+                        url: source.url,
+                        isBlackBoxed: false,
+                        isPrettyPrinted: false,
+                    }
+                }).collect();
+
+                let msg = SourcesReply {
+                    from: self.name(),
+                    sources: sources,
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            "setBreakpoint" => {
+                let location = msg.get(&"location".to_string()).and_then(|loc| loc.as_object());
+                let url = location.and_then(|loc| loc.get(&"url".to_string()))
+                                  .and_then(|url| url.as_string());
+                let line = location.and_then(|loc| loc.get(&"line".to_string()))
+                                   .and_then(|line| line.as_u64());
+                let (url, line) = match (url, line) {
+                    (Some(url), Some(line)) => (url.to_string(), line as uint),
+                    _ => return Err(()),
+                };
+
+                let (tx, rx) = channel();
+                self.script_chan.send(SetBreakpoint(self.pipeline, url.clone(), line, tx));
+                let location = try!(rx.recv_opt());
+
+                let actor = BreakpointActor {
+                    name: registry.new_name("breakpoint"),
+                    url: url,
+                    line: line,
+                    script_chan: self.script_chan.clone(),
+                    pipeline: self.pipeline,
+                };
+                let msg = SetBreakpointReply {
+                    from: self.name(),
+                    actor: actor.name(),
+                    isPending: location.is_none(),
+                    actualLocation: location,
+                };
+                registry.register_later(box actor);
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            _ => false
+        })
+    }
+}
+
+/// The text of one script, as loaded by a tab.
+struct SourceActor {
+    name: String,
+    url: String,
+    script_chan: Sender<DevtoolScriptControlMsg>,
+    pipeline: PipelineId,
+}
+
+impl Actor for SourceActor {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn handle_message(&self,
+                      _registry: &ActorRegistry,
+                      msg_type: &String,
+                      _msg: &json::JsonObject,
+                      stream: &mut TcpStream) -> Result<bool, ()> {
+        Ok(match msg_type.as_slice() {
+            "source" => {
+                let (tx, rx) = channel();
+                self.script_chan.send(GetSourceText(self.pipeline, self.url.clone(), tx));
+                let source = try!(rx.recv_opt());
+
+                let msg = SourceReply {
+                    from: self.name(),
+                    source: source.unwrap_or(String::new()),
+                    contentType: "text/javascript".to_string(),
+                };
+                stream.write_json_packet(&msg);
+                true
+            }
+
+            _ => false
+        })
+    }
+}
+
+struct BreakpointActor {
+    name: String,
+    url: String,
+    line: uint,
+    script_chan: Sender<DevtoolScriptControlMsg>,
+    pipeline: PipelineId,
+}
+
+impl Actor for BreakpointActor {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn handle_message(&self,
+                      _registry: &ActorRegistry,
+                      msg_type: &String,
+                      _msg: &json::JsonObject,
+                      stream: &mut TcpStream) -> Result<bool, ()> {
+        Ok(match msg_type.as_slice() {
+            "delete" => {
+                self.script_chan.send(RemoveBreakpoint(self.pipeline, self.url.clone(),
+                                                       self.line));
+                stream.write_json_packet(&DeleteBreakpointReply { from: self.name() });
+                true
+            }
+
+            _ => false
+        })
+    }
+}
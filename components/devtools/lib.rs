@@ -25,22 +25,29 @@ extern crate core;
 extern crate devtools_traits;
 extern crate serialize;
 extern crate sync;
+extern crate time;
 extern crate "msg" as servo_msg;
 extern crate "util" as servo_util;
 
 use actor::{Actor, ActorRegistry};
 use actors::console::ConsoleActor;
 use actors::inspector::InspectorActor;
+use actors::network_event::NetworkEventActor;
 use actors::root::RootActor;
+use actors::stylesheets::StyleSheetsActor;
 use actors::tab::TabActor;
+use actors::thread::ThreadActor;
 use protocol::JsonPacketStream;
 
 use devtools_traits::{ServerExitMsg, DevtoolsControlMsg, NewGlobal, DevtoolScriptControlMsg};
+use devtools_traits::{NetworkEvent, NetworkEventMsg, RequestSent, ResponseReceived};
+use devtools_traits::{NavigatedMsg, RequestFailed, is_network_monitored};
+use devtools_traits::{ConsoleAPIMsg, ConsoleMessage, PipelineExitedMsg};
 use servo_msg::constellation_msg::PipelineId;
 use servo_util::task::spawn_named;
 
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, RingBuf};
 use std::comm;
 use std::comm::{Disconnected, Empty};
 use std::io::{TcpListener, TcpStream};
@@ -52,8 +59,12 @@ mod actor;
 mod actors {
     pub mod console;
     pub mod inspector;
+    pub mod network_event;
+    pub mod object;
     pub mod root;
+    pub mod stylesheets;
     pub mod tab;
+    pub mod thread;
 }
 mod protocol;
 
@@ -90,6 +101,10 @@ fn run_server(receiver: Receiver<DevtoolsControlMsg>, port: u16) {
 
     let mut actor_pipelines: HashMap<PipelineId, String> = HashMap::new();
 
+    let mut actor_requests: HashMap<String, String> = HashMap::new();
+
+    let mut network_actors: Vec<String> = vec!();
+
     /// Process the input from a single devtools client until EOF.
     fn handle_client(actors: Arc<Mutex<ActorRegistry>>, mut stream: TcpStream) {
         println!("connection established to {}", stream.peer_name().unwrap());
@@ -131,20 +146,38 @@ fn run_server(receiver: Receiver<DevtoolsControlMsg>, port: u16) {
         let mut actors = actors.lock();
 
         //TODO: move all this actor creation into a constructor method on TabActor
-        let (tab, console, inspector) = {
+        let (tab, console, inspector, thread, stylesheets) = {
             let console = ConsoleActor {
                 name: actors.new_name("console"),
                 script_chan: sender.clone(),
                 pipeline: pipeline,
                 streams: RefCell::new(Vec::new()),
+                cached_messages: RefCell::new(RingBuf::new()),
+                listening_to_network: Cell::new(false),
+                listening_to_console_api: Cell::new(false),
             };
             let inspector = InspectorActor {
                 name: actors.new_name("inspector"),
                 walker: RefCell::new(None),
                 pageStyle: RefCell::new(None),
                 highlighter: RefCell::new(None),
+                script_chan: sender.clone(),
+                pipeline: pipeline,
+            };
+            let stylesheets = StyleSheetsActor {
+                name: actors.new_name("stylesheets"),
+                script_chan: sender.clone(),
+                pipeline: pipeline,
+            };
+            let thread = ThreadActor {
+                name: actors.new_name("context"),
                 script_chan: sender,
                 pipeline: pipeline,
+                sources: RefCell::new(HashMap::new()),
+                attached: Cell::new(false),
+                pause_port: RefCell::new(None),
+                stream: RefCell::new(None),
+                pauses: Cell::new(0),
             };
             //TODO: send along the current page title and URL
             let tab = TabActor {
@@ -153,17 +186,145 @@ fn run_server(receiver: Receiver<DevtoolsControlMsg>, port: u16) {
                 url: "about:blank".to_string(),
                 console: console.name(),
                 inspector: inspector.name(),
+                thread: thread.name(),
+                stylesheets: stylesheets.name(),
             };
 
             let root = actors.find_mut::<RootActor>("root");
             root.tabs.push(tab.name.clone());
-            (tab, console, inspector)
+            (tab, console, inspector, thread, stylesheets)
         };
 
         actor_pipelines.insert(pipeline, tab.name.clone());
         actors.register(box tab);
         actors.register(box console);
         actors.register(box inspector);
+        actors.register(box thread);
+        actors.register(box stylesheets);
+    }
+
+    /// Returns the streams of the clients attached to each tab, with the name of the tab's
+    /// console actor, which is where network activity is announced.
+    fn console_streams(actors: &ActorRegistry) -> Vec<(String, TcpStream)> {
+        let mut streams = vec!();
+        for tab in actors.find::<RootActor>("root").tabs.iter() {
+            let console = actors.find::<TabActor>(tab.as_slice()).console.clone();
+            let console_actor = actors.find::<ConsoleActor>(console.as_slice());
+            for stream in console_actor.streams.borrow().iter() {
+                streams.push((console.clone(), stream.clone()));
+            }
+        }
+        streams
+    }
+
+    fn handle_pauses(actors: Arc<Mutex<ActorRegistry>>) {
+        let actors = actors.lock();
+        for tab in actors.find::<RootActor>("root").tabs.iter() {
+            let thread = actors.find::<TabActor>(tab.as_slice()).thread.clone();
+            actors.find::<ThreadActor>(thread.as_slice()).send_pauses();
+        }
+    }
+
+    fn handle_console_message(actors: Arc<Mutex<ActorRegistry>>,
+                              pipeline: PipelineId,
+                              message: ConsoleMessage,
+                              actor_pipelines: &HashMap<PipelineId, String>) {
+        let actors = actors.lock();
+        let tab = match actor_pipelines.get(&pipeline) {
+            Some(tab) => tab,
+            None => return,
+        };
+        let console = actors.find::<TabActor>(tab.as_slice()).console.clone();
+        actors.find::<ConsoleActor>(console.as_slice()).console_api_call(&*actors, message);
+    }
+
+    fn handle_network_event(actors: Arc<Mutex<ActorRegistry>>,
+                            request_id: String,
+                            event: NetworkEvent,
+                            actor_requests: &mut HashMap<String, String>,
+                            network_actors: &mut Vec<String>) {
+        let mut actors = actors.lock();
+        let mut streams = console_streams(&*actors);
+
+        match event {
+            // Loads that start while nobody is watching are never shown.
+            RequestSent(_) if !is_network_monitored() => {}
+            RequestSent(request) => {
+                let actor = NetworkEventActor {
+                    name: actors.new_name("netEvent"),
+                    request: request,
+                    response: None,
+                };
+                for &(ref console, ref mut stream) in streams.iter_mut() {
+                    actor.notify_request(console.as_slice(), stream);
+                }
+                actor_requests.insert(request_id, actor.name());
+                network_actors.push(actor.name());
+                actors.register(box actor);
+            }
+            ResponseReceived(response) => {
+                let name = match actor_requests.remove(&request_id) {
+                    Some(name) => name,
+                    None => return,
+                };
+                let actor = actors.find_mut::<NetworkEventActor>(name.as_slice());
+                actor.response = Some(response);
+                for &(_, ref mut stream) in streams.iter_mut() {
+                    actor.notify_response(stream);
+                }
+            }
+            RequestFailed(_) => {
+                let name = match actor_requests.remove(&request_id) {
+                    Some(name) => name,
+                    None => return,
+                };
+                network_actors.retain(|actor| *actor != name);
+                actors.unregister(name.as_slice());
+            }
+        }
+    }
+
+    /// Stops counting the listeners of an exited pipeline's console, as its client will not
+    /// detach from it.
+    fn handle_pipeline_exit(actors: Arc<Mutex<ActorRegistry>>,
+                            pipeline: PipelineId,
+                            actor_pipelines: &HashMap<PipelineId, String>) {
+        let actors = actors.lock();
+        let tab = match actor_pipelines.get(&pipeline) {
+            Some(tab) => tab,
+            None => return,
+        };
+        let console = actors.find::<TabActor>(tab.as_slice()).console.clone();
+        let console = actors.find::<ConsoleActor>(console.as_slice());
+        console.stop_listening_to_network();
+        console.stop_listening_to_console_api();
+    }
+
+    /// Forgets the console messages of a pipeline's previous document when it loads a new one,
+    /// along with the network events of finished loads. Loads aren't tied to the pipeline that
+    /// made them, so the network events of every tab are dropped.
+    fn handle_navigation(actors: Arc<Mutex<ActorRegistry>>,
+                         pipeline: PipelineId,
+                         actor_pipelines: &HashMap<PipelineId, String>,
+                         actor_requests: &HashMap<String, String>,
+                         network_actors: &mut Vec<String>) {
+        let mut actors = actors.lock();
+        match actor_pipelines.get(&pipeline) {
+            Some(tab) => {
+                let console = actors.find::<TabActor>(tab.as_slice()).console.clone();
+                let console = actors.find::<ConsoleActor>(console.as_slice());
+                console.cached_messages.borrow_mut().clear();
+            }
+            None => {}
+        }
+        let pending: Vec<&String> = actor_requests.values().collect();
+        network_actors.retain(|name| {
+            if pending.contains(&name) {
+                return true;
+            }
+            actors.unregister(name.as_slice());
+            false
+        });
     }
 
     //TODO: figure out some system that allows us to watch for new connections,
@@ -175,9 +336,24 @@ fn run_server(receiver: Receiver<DevtoolsControlMsg>, port: u16) {
     loop {
         match acceptor.accept() {
             Err(ref e) if e.kind == TimedOut => {
+                handle_pauses(actors.clone());
                 match receiver.try_recv() {
                     Ok(ServerExitMsg) | Err(Disconnected) => break,
                     Ok(NewGlobal(id, sender)) => handle_new_global(actors.clone(), id, sender, &mut actor_pipelines),
+                    Ok(NavigatedMsg(pipeline)) => {
+                        handle_navigation(actors.clone(), pipeline, &actor_pipelines,
+                                          &actor_requests, &mut network_actors)
+                    }
+                    Ok(NetworkEventMsg(request_id, event)) => {
+                        handle_network_event(actors.clone(), request_id, event,
+                                             &mut actor_requests, &mut network_actors)
+                    }
+                    Ok(ConsoleAPIMsg(pipeline, message)) => {
+                        handle_console_message(actors.clone(), pipeline, message, &actor_pipelines)
+                    }
+                    Ok(PipelineExitedMsg(pipeline)) => {
+                        handle_pipeline_exit(actors.clone(), pipeline, &actor_pipelines)
+                    }
                     Err(Empty) => acceptor.set_timeout(Some(POLL_TIMEOUT)),
                 }
             }
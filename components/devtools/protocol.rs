@@ -17,9 +17,12 @@ impl JsonPacketStream for TcpStream {
     fn write_json_packet<'a, T: Encodable<json::Encoder<'a>,IoError>>(&mut self, obj: &T) {
         let s = json::encode(obj).replace("__type__", "type");
         println!("<- {:s}", s);
-        self.write_str(s.len().to_string().as_slice()).unwrap();
-        self.write_u8(':' as u8).unwrap();
-        self.write_str(s.as_slice()).unwrap();
+        // The packet is written at once, so that packets from different tasks can't interleave.
+        let packet = format!("{}:{}", s.len(), s);
+        match self.write_str(packet.as_slice()) {
+            Ok(()) => {}
+            Err(e) => println!("error: couldn't write devtools packet: {}", e.desc),
+        }
     }
 
     fn read_json_packet<'a>(&mut self) -> IoResult<json::Json> {
@@ -19,6 +19,7 @@ extern crate serialize;
 
 use serialize::{Decodable, Decoder};
 use servo_msg::constellation_msg::PipelineId;
+use std::sync::atomic::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
 
 pub type DevtoolsControlChan = Sender<DevtoolsControlMsg>;
 pub type DevtoolsControlPort = Receiver<DevtoolScriptControlMsg>;
@@ -27,9 +28,145 @@ pub type DevtoolsControlPort = Receiver<DevtoolScriptControlMsg>;
 /// according to changes in the browser.
 pub enum DevtoolsControlMsg {
     NewGlobal(PipelineId, Sender<DevtoolScriptControlMsg>),
+    /// Report that a pipeline has started loading a new document, so that what was kept for
+    /// the previous one can be dropped.
+    NavigatedMsg(PipelineId),
+    /// Report a stage of an HTTP load made by the resource task. The string identifies the
+    /// request, so that its response can be matched up with it.
+    NetworkEventMsg(String, NetworkEvent),
+    /// Report a call to the console API of a pipeline's window, or of a worker that the
+    /// pipeline started.
+    ConsoleAPIMsg(PipelineId, ConsoleMessage),
+    /// Report that a pipeline has exited, so that its listeners stop counting.
+    PipelineExitedMsg(PipelineId),
     ServerExitMsg
 }
 
+/// A call to the console API, after its format string has been applied to its arguments.
+pub struct ConsoleMessage {
+    /// The console method that was called, such as `log`, `warn` or `groupEnd`.
+    pub level: String,
+    pub arguments: Vec<ConsoleArgument>,
+    /// The CSS that `%c` directives applied to each argument, or nothing if there were none.
+    pub styles: Vec<String>,
+    /// Where the console was called from.
+    pub location: CallSite,
+    /// The stack of a `trace` call, newest frame first.
+    pub stacktrace: Vec<CallSite>,
+    /// The label of a `group`, `groupCollapsed` or `groupEnd` call.
+    pub group_name: Option<String>,
+    pub timer: Option<ConsoleTimer>,
+    pub counter: Option<ConsoleCounter>,
+    /// Whether the call was made in a worker.
+    pub worker: bool,
+    /// When the call was made, in milliseconds since the Unix epoch.
+    pub time_stamp: u64,
+}
+
+/// A value passed to the console API. Objects come with a preview of their own properties,
+/// since they cannot be inspected once the call has returned.
+pub struct ConsoleArgument {
+    pub value: DebuggerValue,
+    /// The first few own properties of an object. Their values are previewed in turn for
+    /// `table` calls, whose rows are objects.
+    pub properties: Vec<(String, ConsoleArgument)>,
+    /// How many own properties an object has.
+    pub property_count: uint,
+}
+
+#[deriving(Clone)]
+pub struct CallSite {
+    /// The name of the function that was running, if it has one.
+    pub function: Option<String>,
+    pub location: SourceLocation,
+}
+
+pub enum ConsoleTimer {
+    /// A `time` call started the named timer.
+    TimerStarted(String),
+    /// A `timeEnd` call stopped the named timer after this many milliseconds.
+    TimerEnded(String, f64),
+}
+
+pub struct ConsoleCounter {
+    pub label: String,
+    pub count: uint,
+}
+
+/// The stages of an HTTP load that the network monitor displays. A load that is redirected
+/// reports one request and response for each URL it visits.
+pub enum NetworkEvent {
+    RequestSent(HttpRequest),
+    ResponseReceived(HttpResponse),
+    /// The load failed before a response was received, for the given reason.
+    RequestFailed(String),
+}
+
+/// The number of devtools consoles that are listening for network activity.
+static mut NETWORK_LISTENERS: AtomicUint = INIT_ATOMIC_UINT;
+
+/// Records that a console has started listening for network activity.
+pub fn add_network_listener() {
+    unsafe { NETWORK_LISTENERS.fetch_add(1, SeqCst); }
+}
+
+/// Records that a console which was listening for network activity has stopped.
+pub fn remove_network_listener() {
+    unsafe { NETWORK_LISTENERS.fetch_sub(1, SeqCst); }
+}
+
+/// Whether any console is listening for network activity. Loads only keep copies of their
+/// bodies for the network monitor while one is.
+pub fn is_network_monitored() -> bool {
+    unsafe { NETWORK_LISTENERS.load(SeqCst) > 0 }
+}
+
+/// The number of devtools consoles that are listening for console API calls.
+static mut CONSOLE_API_LISTENERS: AtomicUint = INIT_ATOMIC_UINT;
+
+/// Records that a console has started listening for console API calls.
+pub fn add_console_api_listener() {
+    unsafe { CONSOLE_API_LISTENERS.fetch_add(1, SeqCst); }
+}
+
+/// Records that a console which was listening for console API calls has stopped.
+pub fn remove_console_api_listener() {
+    unsafe { CONSOLE_API_LISTENERS.fetch_sub(1, SeqCst); }
+}
+
+/// Whether any console is listening for console API calls. Scripts only capture the stacks of
+/// their console calls while one is.
+pub fn is_console_api_monitored() -> bool {
+    unsafe { CONSOLE_API_LISTENERS.load(SeqCst) > 0 }
+}
+
+pub struct HttpRequest {
+    pub url: String,
+    pub method: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+    /// When the request was started, in milliseconds since the Unix epoch.
+    pub start_time: u64,
+}
+
+pub struct HttpResponse {
+    /// The status code and reason phrase.
+    pub status: Option<(u16, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub timings: NetworkTimings,
+}
+
+/// How long each phase of an HTTP load took, in milliseconds.
+pub struct NetworkTimings {
+    /// Connecting and sending the request.
+    pub send: u64,
+    /// Waiting for the response headers.
+    pub wait: u64,
+    /// Reading the response body.
+    pub receive: u64,
+}
+
 /// Serialized JS return values
 /// TODO: generalize this beyond the EvaluateJS message?
 pub enum EvaluateJSReply {
@@ -76,6 +213,137 @@ pub enum DevtoolScriptControlMsg {
     GetChildren(PipelineId, String, Sender<Vec<NodeInfo>>),
     GetLayout(PipelineId, String, Sender<(f32, f32)>),
     ModifyAttribute(PipelineId, String, Vec<Modification>),
+    /// Start debugging the scripts of a pipeline. Pauses are reported through the sender
+    /// until the thread is detached.
+    AttachThread(PipelineId, Sender<PauseInfo>),
+    DetachThread(PipelineId),
+    /// Pause before the next function call.
+    Interrupt(PipelineId),
+    /// Continue a paused pipeline, optionally pausing again at the given limit.
+    Resume(PipelineId, Option<ResumeLimit>),
+    /// Set a breakpoint at a URL and line. The reply is the location that the breakpoint was
+    /// set at, or `None` if no script has been loaded there yet.
+    SetBreakpoint(PipelineId, String, uint, Sender<Option<SourceLocation>>),
+    RemoveBreakpoint(PipelineId, String, uint),
+    /// List the frames of a paused pipeline's stack, starting at the given depth.
+    GetFrames(PipelineId, uint, uint, Sender<Vec<FrameInfo>>),
+    GetSources(PipelineId, Sender<Vec<SourceInfo>>),
+    GetSourceText(PipelineId, String, Sender<Option<String>>),
+    GetStylesheets(PipelineId, Sender<Vec<StylesheetInfo>>),
+    /// List the stylesheet rules that apply to the element with the given unique id.
+    GetAppliedRules(PipelineId, String, Sender<Vec<AppliedRuleInfo>>),
+    /// Replace the text of the stylesheet at the given index and restyle the page.
+    SetStylesheetText(PipelineId, uint, String),
+    /// Replace the declarations of a rule, given by its stylesheet and rule indices, and restyle
+    /// the page.
+    SetRuleText(PipelineId, uint, uint, String),
+    /// Change properties of a rule, given by its stylesheet and rule indices, and restyle the
+    /// page. Replies with the new declarations of the rule, or nothing if there is no such rule.
+    ModifyRuleProperties(PipelineId, uint, uint, Vec<PropertyModification>,
+                         Sender<Option<String>>),
+    /// Change properties in the style attribute of the element with the given unique id, and
+    /// reply with its new declarations, or nothing if there is no such element.
+    ModifyStyleAttributeProperties(PipelineId, String, Vec<PropertyModification>,
+                                   Sender<Option<String>>),
+}
+
+/// A change to a property of a style rule, made in the inspector.
+pub struct PropertyModification {
+    pub name: String,
+    /// The new value of the property and whether it is `!important`, or `None` to remove it.
+    pub value: Option<(String, bool)>,
+}
+
+/// How far a paused pipeline should run before pausing again.
+#[deriving(PartialEq, Show)]
+pub enum ResumeLimit {
+    /// Pause at the next line of the current function, or in its caller.
+    StepOver,
+    /// Pause at the next line, including in functions that the current line calls.
+    StepIn,
+    /// Pause once the current function returns.
+    StepOut,
+}
+
+#[deriving(Clone, Decodable, Encodable)]
+pub struct SourceLocation {
+    pub url: String,
+    pub line: uint,
+    pub column: uint,
+}
+
+#[deriving(Decodable)]
+pub struct SourceInfo {
+    pub url: String,
+    /// Whether this is the main script of a worker, rather than a script of the page.
+    pub worker: bool,
+}
+
+/// A JS value seen by the debugger. `kind` is the JS type of the value, or `null`, and `text`
+/// is how it is displayed: the value itself for primitives, and the class for objects.
+#[deriving(Decodable)]
+pub struct DebuggerValue {
+    pub kind: String,
+    pub text: String,
+}
+
+#[deriving(Decodable)]
+pub struct BindingInfo {
+    pub name: String,
+    pub value: DebuggerValue,
+}
+
+/// A scope that a frame can see, such as a function's local variables or a `with` object.
+#[deriving(Decodable)]
+pub struct EnvironmentInfo {
+    /// One of `declarative`, `object` and `with`.
+    pub kind: String,
+    pub bindings: Vec<BindingInfo>,
+}
+
+#[deriving(Decodable)]
+pub struct FrameInfo {
+    /// How many frames are newer than this one.
+    pub depth: uint,
+    /// The name of the function being run, if it has one.
+    pub callee: Option<String>,
+    pub location: SourceLocation,
+    pub this: DebuggerValue,
+    pub arguments: Vec<DebuggerValue>,
+    /// The scopes that the frame can see, innermost first. The global scope is left out.
+    pub environment: Vec<EnvironmentInfo>,
+}
+
+/// Why a pipeline paused, and where.
+#[deriving(Decodable)]
+pub struct PauseInfo {
+    /// One of `debuggerStatement`, `breakpoint`, `resumeLimit` and `interrupted`.
+    pub reason: String,
+    pub frame: FrameInfo,
+}
+
+pub struct StylesheetInfo {
+    /// The index of the stylesheet among all the stylesheets of the page, including the user
+    /// agent's, which is used to refer to it in later messages.
+    pub index: uint,
+    /// The URL of the stylesheet, or `None` for an inline `<style>` element.
+    pub href: Option<String>,
+    pub text: String,
+    pub rule_count: uint,
+}
+
+pub struct AppliedRuleInfo {
+    /// The index of the rule's stylesheet, or `None` for the element's style attribute.
+    pub stylesheet: Option<uint>,
+    /// The index of the rule among the style rules of its stylesheet, including those nested in
+    /// `@media` rules.
+    pub rule: uint,
+    pub selector: String,
+    /// The declarations of the rule, without the braces.
+    pub css_text: String,
+    pub line: uint,
+    pub column: uint,
+    pub specificity: u32,
 }
 
 /// Messages to instruct devtools server to update its state relating to a particular
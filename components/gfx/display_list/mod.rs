@@ -16,7 +16,7 @@
 
 use color::Color;
 use display_list::optimizer::DisplayListOptimizer;
-use render_context::{RenderContext, ToAzureRect};
+use render_context::{RenderContext, ToAzurePoint, ToAzureRect};
 use text::glyph::CharIndex;
 use text::TextRun;
 
@@ -32,11 +32,13 @@ use servo_util::dlist as servo_dlist;
 use servo_util::geometry::{mod, Au};
 use servo_util::range::Range;
 use servo_util::smallvec::{SmallVec, SmallVec8};
+use std::collections::HashSet;
 use std::fmt;
 use std::mem;
 use std::slice::Items;
 use style::computed_values::border_style;
 use sync::Arc;
+use url::Url;
 
 // It seems cleaner to have layout code not mention Azure directly, so let's just reexport this for
 // layout to use.
@@ -304,6 +306,64 @@ impl StackingContext {
         Rect(offset, tile_subrect.size)
     }
 
+    /// Places the URLs of the animated images in this stacking context that intersect `rect` into
+    /// `result`, along with the images and their bounds, offset by `origin`. Child stacking
+    /// contexts that are painted into layers of their own are skipped.
+    pub fn collect_animated_images(&self,
+                                   rect: &Rect<AzFloat>,
+                                   origin: &Point2D<AzFloat>,
+                                   result: &mut Vec<(Url, Arc<Box<Image>>, Rect<AzFloat>)>) {
+        for display_list in [
+            &self.display_list.background_and_borders,
+            &self.display_list.block_backgrounds_and_borders,
+            &self.display_list.floats,
+            &self.display_list.content,
+        ].iter() {
+            for item in display_list.iter() {
+                match *item {
+                    ImageDisplayItemClass(ref image_item) if image_item.image.is_animated() => {
+                        let bounds = image_item.base.bounds.to_azure_rect().translate(origin);
+                        if bounds.intersects(rect) {
+                            result.push((image_item.url.clone(), image_item.image.clone(), bounds))
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for kid in self.display_list.children.iter() {
+            if kid.layer.is_none() {
+                let kid_origin = *origin + kid.bounds.origin.to_azure_point();
+                kid.collect_animated_images(rect, &kid_origin, result)
+            }
+        }
+    }
+
+    /// Places the URLs of all the images in this stacking context and its descendants into
+    /// `result`.
+    pub fn collect_image_urls(&self, result: &mut HashSet<Url>) {
+        for display_list in [
+            &self.display_list.background_and_borders,
+            &self.display_list.block_backgrounds_and_borders,
+            &self.display_list.floats,
+            &self.display_list.content,
+        ].iter() {
+            for item in display_list.iter() {
+                match *item {
+                    ImageDisplayItemClass(ref image_item) => {
+                        result.insert(image_item.url.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for kid in self.display_list.children.iter() {
+            kid.collect_image_urls(result)
+        }
+    }
+
     /// Places all nodes containing the point of interest into `result`, topmost first. If
     /// `topmost_only` is true, stops after placing one node into the list. `result` must be empty
     /// upon entry to this function.
@@ -476,6 +536,8 @@ pub enum TextOrientation {
 #[deriving(Clone)]
 pub struct ImageDisplayItem {
     pub base: BaseDisplayItem,
+    /// The URL of the image, which picks the frame of an animated image that is painted.
+    pub url: Url,
     pub image: Arc<Box<Image>>,
 
     /// The dimensions to which the image display item should be stretched. If this is smaller than
@@ -590,6 +652,9 @@ impl DisplayItem {
 
             ImageDisplayItemClass(ref image_item) => {
                 debug!("Drawing image at {}.", image_item.base.bounds);
+                let frame = render_context.image_frames.get(&image_item.url).map_or(0, |&frame| {
+                    frame
+                });
 
                 let mut y_offset = Au(0);
                 while y_offset < image_item.base.bounds.size.height {
@@ -600,7 +665,7 @@ impl DisplayItem {
                         bounds.origin.y = bounds.origin.y + y_offset;
                         bounds.size = image_item.stretch_size;
 
-                        render_context.draw_image(bounds, image_item.image.clone());
+                        render_context.draw_image(bounds, image_item.image.clone(), frame);
 
                         x_offset = x_offset + image_item.stretch_size.width;
                     }
@@ -4,23 +4,28 @@
 
 use geom::{Point2D, Rect, Size2D};
 use std::mem;
+use std::slice;
 use std::string;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use servo_util::cache::{Cache, HashCache};
 use servo_util::smallvec::{SmallVec, SmallVec8};
-use style::computed_values::{font_variant, font_weight};
+use style::computed_values::{font_stretch, font_variant, font_variant_caps, font_weight};
+use style::computed_values::word_break;
 use style::style_structs::Font as FontStyle;
+use style::CodepointRange;
 use sync::Arc;
 
 use servo_util::geometry::Au;
 use platform::font_context::FontContextHandle;
 use platform::font::{FontHandle, FontTable};
 use text::glyph::{GlyphStore, GlyphId};
-use text::shaping::ShaperMethods;
+use text::shaping::{ShaperMethods, ShapingOptions};
 use text::{Shaper, TextRun};
 use font_template::FontTemplateDescriptor;
 use platform::font_template::FontTemplateData;
+use sfnt::{mod, tag_from_bytes};
 
 // FontHandle encapsulates access to the platform's font API,
 // e.g. quartz, FreeType. It provides access to metrics and tables
@@ -35,6 +40,7 @@ pub trait FontHandleMethods {
     fn face_name(&self) -> String;
     fn is_italic(&self) -> bool;
     fn boldness(&self) -> font_weight::T;
+    fn stretchiness(&self) -> font_stretch::T;
 
     fn glyph_index(&self, codepoint: char) -> Option<GlyphId>;
     fn glyph_h_advance(&self, GlyphId) -> Option<FractionalPixel>;
@@ -84,34 +90,62 @@ pub struct FontMetrics {
     pub line_gap:         Au,
 }
 
+static SMALL_CAPS_FEATURES: &'static [&'static [u8]] = &[b"smcp"];
+static ALL_SMALL_CAPS_FEATURES: &'static [&'static [u8]] = &[b"smcp", b"c2sc"];
+static PETITE_CAPS_FEATURES: &'static [&'static [u8]] = &[b"pcap"];
+static ALL_PETITE_CAPS_FEATURES: &'static [&'static [u8]] = &[b"pcap", b"c2pc"];
+
 pub type SpecifiedFontStyle = FontStyle;
 pub type UsedFontStyle = FontStyle;
 
 pub struct Font {
     pub handle: FontHandle,
     pub metrics: FontMetrics,
+    /// `small_caps` if this is a scaled-down copy of a font that is used to synthesize small
+    /// capitals. Such a font renders lowercase letters with uppercase glyphs.
     pub variant: font_variant::T,
     pub descriptor: FontTemplateDescriptor,
+    /// The characters that this web font may be used for. Empty if it may be used for all.
+    pub unicode_range: Vec<CodepointRange>,
     pub requested_pt_size: Au,
     pub actual_pt_size: Au,
     pub shaper: Option<Shaper>,
-    pub shape_cache: HashCache<String, Arc<GlyphStore>>,
+    /// The glyphs that text was shaped into, by the text and then by the options it was shaped
+    /// with, so that text can be looked up without copying it.
+    pub shape_cache: HashMap<String, Vec<(ShapingOptions, Arc<GlyphStore>)>>,
     pub glyph_advance_cache: HashCache<u32, FractionalPixel>,
+    /// The tags of the OpenType features that the font's `GSUB` table provides.
+    pub gsub_features: Vec<FontTableTag>,
 }
 
 impl Font {
-    pub fn shape_text(&mut self, text: &str, is_whitespace: bool) -> Arc<GlyphStore> {
+    pub fn shape_text(&mut self, text: &str, options: &ShapingOptions, is_whitespace: bool)
+                      -> Arc<GlyphStore> {
         self.make_shaper();
         let shaper = &self.shaper;
-        match self.shape_cache.find_equiv(text) {
+        match self.shape_cache.get(text) {
+            Some(entries) => {
+                for &(ref entry_options, ref glyphs) in entries.iter() {
+                    if entry_options == options {
+                        return glyphs.clone()
+                    }
+                }
+            }
             None => {}
-            Some(glyphs) => return (*glyphs).clone(),
         }
 
         let mut glyphs = GlyphStore::new(text.char_len() as int, is_whitespace);
-        shaper.as_ref().unwrap().shape_text(text, &mut glyphs);
+        shaper.as_ref().unwrap().shape_text(text, options, &mut glyphs);
         let glyphs = Arc::new(glyphs);
-        self.shape_cache.insert(text.to_string(), glyphs.clone());
+        let entry = (options.clone(), glyphs.clone());
+        match self.shape_cache.get_mut(text) {
+            Some(entries) => {
+                entries.push(entry);
+                return glyphs
+            }
+            None => {}
+        }
+        self.shape_cache.insert(text.to_string(), vec!(entry));
         glyphs
     }
 
@@ -141,6 +175,45 @@ impl Font {
         return result;
     }
 
+    /// Returns true if the font has substitutions for all of the given OpenType features.
+    pub fn supports_features(&self, tags: &[&[u8]]) -> bool {
+        tags.iter().all(|tag| self.gsub_features.contains(&tag_from_bytes(*tag)))
+    }
+
+    /// Returns the OpenType features that render text with the given capitalization, or `None`
+    /// if the font lacks them and small capitals have to be synthesized instead. Petite capitals
+    /// fall back to small capitals, as CSS Fonts Level 3 § 6.8 requires.
+    pub fn caps_features(&self, caps: font_variant_caps::T) -> Option<Vec<FontTableTag>> {
+        let (preferred, fallback) = match caps {
+            font_variant_caps::normal => return Some(vec!()),
+            font_variant_caps::small_caps => (SMALL_CAPS_FEATURES, SMALL_CAPS_FEATURES),
+            font_variant_caps::all_small_caps => {
+                (ALL_SMALL_CAPS_FEATURES, ALL_SMALL_CAPS_FEATURES)
+            }
+            font_variant_caps::petite_caps => (PETITE_CAPS_FEATURES, SMALL_CAPS_FEATURES),
+            font_variant_caps::all_petite_caps => {
+                (ALL_PETITE_CAPS_FEATURES, ALL_SMALL_CAPS_FEATURES)
+            }
+            font_variant_caps::unicase => return Some(vec!(tag_from_bytes(b"unic"))),
+            font_variant_caps::titling_caps => return Some(vec!(tag_from_bytes(b"titl"))),
+        };
+        [preferred, fallback].iter().find(|tags| self.supports_features(**tags)).map(|tags| {
+            tags.iter().map(|tag| tag_from_bytes(*tag)).collect()
+        })
+    }
+
+    /// Returns true if the given character is rendered with a synthesized small capital when the
+    /// font lacks the features for the given capitalization.
+    pub fn synthesizes_small_caps_for(&self, caps: font_variant_caps::T, codepoint: char) -> bool {
+        let all_letters = match caps {
+            font_variant_caps::small_caps | font_variant_caps::petite_caps => false,
+            font_variant_caps::all_small_caps | font_variant_caps::all_petite_caps => true,
+            _ => return false,
+        };
+        (codepoint.is_lowercase() || (all_letters && codepoint.is_uppercase())) &&
+            self.caps_features(caps).is_none()
+    }
+
     pub fn glyph_index(&self, codepoint: char) -> Option<GlyphId> {
         let codepoint = match self.variant {
             font_variant::small_caps => codepoint.to_uppercase(),
@@ -149,6 +222,14 @@ impl Font {
         self.handle.glyph_index(codepoint)
     }
 
+    /// Returns true if the font may be used for the given character and its character map has a
+    /// glyph for it.
+    pub fn has_glyph_for(&self, codepoint: char) -> bool {
+        let in_unicode_range = self.unicode_range.is_empty() ||
+            self.unicode_range.iter().any(|range| range.contains(codepoint));
+        in_unicode_range && self.glyph_index(codepoint).is_some()
+    }
+
     pub fn glyph_h_kerning(&mut self, first_glyph: GlyphId, second_glyph: GlyphId) -> FractionalPixel {
         self.handle.glyph_h_kerning(first_glyph, second_glyph)
     }
@@ -166,12 +247,16 @@ impl Font {
 
 pub struct FontGroup {
     pub fonts: SmallVec8<Rc<RefCell<Font>>>,
+    /// True if none of the style's font families were available, so that the only font is one of
+    /// the platform's last resort fonts.
+    pub is_last_resort: bool,
 }
 
 impl FontGroup {
-    pub fn new(fonts: SmallVec8<Rc<RefCell<Font>>>) -> FontGroup {
+    pub fn new(fonts: SmallVec8<Rc<RefCell<Font>>>, is_last_resort: bool) -> FontGroup {
         FontGroup {
             fonts: fonts,
+            is_last_resort: is_last_resort,
         }
     }
 
@@ -179,10 +264,30 @@ impl FontGroup {
         assert!(self.fonts.len() > 0);
 
         // TODO(Issue #177): Actually fall back through the FontGroup when a font is unsuitable.
-        TextRun::new(&mut *self.fonts.get(0).borrow_mut(), text.clone())
+        TextRun::new(&mut *self.fonts.get(0).borrow_mut(),
+                     text.clone(),
+                     &ShapingOptions::new(),
+                     word_break::normal)
     }
 }
 
+/// Returns the tags of the OpenType features that the font's `GSUB` table provides.
+pub fn read_gsub_features(handle: &FontHandle) -> Vec<FontTableTag> {
+    let table = match handle.get_table_for_tag(sfnt::GSUB) {
+        Some(table) => table,
+        None => return vec!(),
+    };
+    let mut features = vec!();
+    table.with_buffer(|buffer, length| {
+        unsafe {
+            slice::raw::buf_as_slice(buffer, length, |gsub| {
+                features = sfnt::gsub_feature_tags(gsub).unwrap_or(vec!());
+            })
+        }
+    });
+    features
+}
+
 pub struct RunMetrics {
     // may be negative due to negative width (i.e., kerning of '.' in 'P.T.')
     pub advance_width: Au,
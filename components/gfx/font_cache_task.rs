@@ -3,6 +3,8 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use platform::font_list::get_available_families;
+use platform::font_list::get_fallback_font_families;
+use platform::font_list::get_local_font;
 use platform::font_list::get_system_default_family;
 use platform::font_list::get_variations_for_family;
 use platform::font_list::get_last_resort_font_families;
@@ -10,13 +12,16 @@ use platform::font_context::FontContextHandle;
 
 use collections::str::Str;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUint, SeqCst};
 use sync::Arc;
 use font_template::{FontTemplate, FontTemplateDescriptor};
 use platform::font_template::FontTemplateData;
+use servo_net::fetch::request::Font as FontContext;
 use servo_net::resource_task::{ResourceTask, load_whole_resource};
 use servo_util::task::spawn_named;
 use servo_util::str::LowercaseString;
-use style::{Source, LocalSource, UrlSource_};
+use style::{CodepointRange, FontFaceRule, LocalSource, UrlSource, UrlSource_};
+use woff::{decode_web_font, is_supported_format};
 
 /// A list of font templates that make up a given font family.
 struct FontFamily {
@@ -30,33 +35,33 @@ impl FontFamily {
         }
     }
 
-    /// Find a font in this family that matches a given desriptor.
-    fn find_font_for_style<'a>(&'a mut self, desc: &FontTemplateDescriptor, fctx: &FontContextHandle)
-                               -> Option<Arc<FontTemplateData>> {
-        // TODO(Issue #189): optimize lookup for
-        // regular/bold/italic/bolditalic with fixed offsets and a
-        // static decision table for fallback between these values.
-
-        // TODO(Issue #190): if not in the fast path above, do
-        // expensive matching of weights, etc.
-        for template in self.templates.iter_mut() {
-            let maybe_template = template.get_if_matches(fctx, desc);
-            if maybe_template.is_some() {
-                return maybe_template;
+    /// Find the font in this family that best matches a given descriptor, and which may be used
+    /// for the given character if there is one. Without a character, fonts with a `unicode-range`
+    /// are skipped, since they are loaded only for text that needs them. Returns the index of the
+    /// font template.
+    fn find_template_for_style(&mut self,
+                               desc: &FontTemplateDescriptor,
+                               fctx: &FontContextHandle,
+                               codepoint: Option<char>)
+                               -> Option<uint> {
+        let mut best_template = None;
+        for (index, template) in self.templates.iter_mut().enumerate() {
+            match codepoint {
+                Some(codepoint) if !template.covers(codepoint) => continue,
+                None if !template.unicode_range().is_empty() => continue,
+                _ => {}
             }
-        }
 
-        // If a request is made for a font family that exists,
-        // pick the first valid font in the family if we failed
-        // to find an exact match for the descriptor.
-        for template in self.templates.iter_mut() {
-            let maybe_template = template.get();
-            if maybe_template.is_some() {
-                return maybe_template;
+            let distance = match template.descriptor(fctx) {
+                Some(actual_desc) => actual_desc.distance_from(desc),
+                None => continue,
+            };
+            match best_template {
+                Some((best_distance, _)) if best_distance <= distance => {}
+                _ => best_template = Some((distance, index)),
             }
         }
-
-        None
+        best_template.map(|(_, index)| index)
     }
 
     fn add_template(&mut self, identifier: &str, maybe_data: Option<Vec<u8>>) {
@@ -69,19 +74,66 @@ impl FontFamily {
         let template = FontTemplate::new(identifier, maybe_data);
         self.templates.push(template);
     }
+
+    /// Adds a template for an `@font-face` rule. The font is not loaded until it is used.
+    fn add_web_font_template(&mut self, rule: FontFaceRule) {
+        let identifier = web_font_identifier(&rule);
+        for template in self.templates.iter() {
+            if template.identifier() == identifier.as_slice() {
+                return;
+            }
+        }
+
+        let descriptor = FontTemplateDescriptor::new(rule.weight, rule.stretch, rule.style);
+        let template = FontTemplate::new_web_font(identifier.as_slice(),
+                                                  descriptor,
+                                                  rule.unicode_range,
+                                                  rule.sources);
+        self.templates.push(template);
+    }
+}
+
+/// Identifies the font face described by an `@font-face` rule, so that adding the same rule twice
+/// only creates one template.
+fn web_font_identifier(rule: &FontFaceRule) -> String {
+    let sources: Vec<String> = rule.sources.iter().map(|source| {
+        match *source {
+            UrlSource_(ref url_source) => url_source.url.serialize(),
+            LocalSource(ref name) => format!("local({})", name),
+        }
+    }).collect();
+    let ranges: Vec<String> = rule.unicode_range.iter().map(|range| {
+        format!("U+{:X}-{:X}", range.start, range.end)
+    }).collect();
+    format!("{} {} {} {} {}",
+            sources.connect(", "),
+            rule.weight.value(),
+            rule.style,
+            rule.stretch,
+            ranges.connect(", "))
+}
+
+/// A font template chosen by the font cache task, along with the characters it may be used for.
+#[deriving(Clone)]
+pub struct FontTemplateInfo {
+    pub font_template: Arc<FontTemplateData>,
+    /// Empty if the font may be used for all characters.
+    pub unicode_range: Vec<CodepointRange>,
 }
 
 /// Commands that the FontContext sends to the font cache task.
 pub enum Command {
     GetFontTemplate(String, FontTemplateDescriptor, Sender<Reply>),
+    GetWebFontTemplateForChar(String, FontTemplateDescriptor, char, Sender<Reply>),
     GetLastResortFontTemplate(FontTemplateDescriptor, Sender<Reply>),
-    AddWebFont(String, Source, Sender<()>),
+    GetFallbackFontTemplates(char, FontTemplateDescriptor, Sender<Vec<Arc<FontTemplateData>>>),
+    AddWebFont(FontFaceRule, Sender<()>),
     Exit(Sender<()>),
 }
 
 /// Reply messages sent from the font cache task to the FontContext caller.
 pub enum Reply {
-    GetFontTemplateReply(Option<Arc<FontTemplateData>>),
+    GetFontTemplateReply(Option<FontTemplateInfo>),
 }
 
 /// The font cache task itself. It maintains a list of reference counted
@@ -93,6 +145,8 @@ struct FontCache {
     web_families: HashMap<LowercaseString, FontFamily>,
     font_context: FontContextHandle,
     resource_task: ResourceTask,
+    /// Counts the changes to the available fonts.
+    generation: Arc<AtomicUint>,
 }
 
 fn add_generic_font(generic_fonts: &mut HashMap<LowercaseString, LowercaseString>,
@@ -116,38 +170,32 @@ impl FontCache {
                     let maybe_font_template = self.get_font_template(&family, &descriptor);
                     result.send(GetFontTemplateReply(maybe_font_template));
                 }
+                GetWebFontTemplateForChar(family, descriptor, codepoint, result) => {
+                    let family = LowercaseString::new(family.as_slice());
+                    let family = self.transform_family(&family);
+                    let maybe_font_template = self.find_font_in_web_family(&family,
+                                                                           &descriptor,
+                                                                           Some(codepoint));
+                    result.send(GetFontTemplateReply(maybe_font_template));
+                }
                 GetLastResortFontTemplate(descriptor, result) => {
                     let font_template = self.get_last_resort_font_template(&descriptor);
-                    result.send(GetFontTemplateReply(Some(font_template)));
+                    result.send(GetFontTemplateReply(Some(FontTemplateInfo {
+                        font_template: font_template,
+                        unicode_range: vec!(),
+                    })));
                 }
-                AddWebFont(family_name, src, result) => {
-                    let family_name = LowercaseString::new(family_name.as_slice());
+                GetFallbackFontTemplates(codepoint, descriptor, result) => {
+                    result.send(self.get_fallback_font_templates(codepoint, &descriptor));
+                }
+                AddWebFont(rule, result) => {
+                    let family_name = LowercaseString::new(rule.family.as_slice());
                     if !self.web_families.contains_key(&family_name) {
                         let family = FontFamily::new();
                         self.web_families.insert(family_name.clone(), family);
                     }
-
-                    match src {
-                        UrlSource_(ref url_source) => {
-                            let url = &url_source.url;
-                            let maybe_resource = load_whole_resource(&self.resource_task, url.clone());
-                            match maybe_resource {
-                                Ok((_, bytes)) => {
-                                    let family = &mut self.web_families[family_name];
-                                    family.add_template(url.to_string().as_slice(), Some(bytes));
-                                },
-                                Err(_) => {
-                                    debug!("Failed to load web font: family={} url={}", family_name, url);
-                                }
-                            }
-                        }
-                        LocalSource(ref local_family_name) => {
-                            let family = &mut self.web_families[family_name];
-                            get_variations_for_family(local_family_name.as_slice(), |path| {
-                                family.add_template(path.as_slice(), None);
-                            });
-                        }
-                    }
+                    self.web_families[family_name].add_web_font_template(rule);
+                    self.generation.fetch_add(1, SeqCst);
                     result.send(());
                 }
                 Exit(result) => {
@@ -192,37 +240,128 @@ impl FontCache {
 
             // TODO(Issue #192: handle generic font families, like 'serif' and 'sans-serif'.
             // if such family exists, try to match style to a font
-            let result = s.find_font_for_style(desc, &self.font_context);
-            if result.is_some() {
-                return result;
+            match s.find_template_for_style(desc, &self.font_context, None) {
+                Some(index) => s.templates[index].get(),
+                None => None,
             }
-
-            None
         } else {
             debug!("FontList: Couldn't find font family with name={:s}", family_name.to_string());
             None
         }
     }
 
-    fn find_font_in_web_family<'a>(&'a mut self, family_name: &LowercaseString, desc: &FontTemplateDescriptor)
-                                -> Option<Arc<FontTemplateData>> {
-        if self.web_families.contains_key(family_name) {
-            let family = &mut self.web_families[*family_name];
-            let maybe_font = family.find_font_for_style(desc, &self.font_context);
-            maybe_font
-        } else {
-            None
+    /// Finds the best font in a web font family, loading it if necessary. If a character is
+    /// given, only fonts whose `unicode-range` includes it are considered; otherwise the best of
+    /// the fonts without a `unicode-range` is used.
+    fn find_font_in_web_family<'a>(&'a mut self,
+                                   family_name: &LowercaseString,
+                                   desc: &FontTemplateDescriptor,
+                                   codepoint: Option<char>)
+                                   -> Option<FontTemplateInfo> {
+        if !self.web_families.contains_key(family_name) {
+            return None
+        }
+
+        // Fonts that fail to load are marked invalid, so this terminates.
+        loop {
+            let index = {
+                let family = &mut self.web_families[*family_name];
+                match family.find_template_for_style(desc, &self.font_context, codepoint) {
+                    Some(index) => index,
+                    None => return None,
+                }
+            };
+
+            if self.web_families[*family_name].templates[index].needs_loading() {
+                self.load_web_font(family_name, index);
+            }
+
+            let template = &mut self.web_families[*family_name].templates[index];
+            match template.get() {
+                Some(font_template) => {
+                    return Some(FontTemplateInfo {
+                        font_template: font_template,
+                        unicode_range: template.unicode_range().to_vec(),
+                    })
+                }
+                None => template.set_invalid(),
+            }
+        }
+    }
+
+    /// Loads a web font from the first of its sources that works.
+    fn load_web_font(&mut self, family_name: &LowercaseString, index: uint) {
+        let sources = self.web_families[*family_name].templates[index].take_pending_sources();
+
+        for source in sources.iter() {
+            let maybe_data = match *source {
+                UrlSource_(ref url_source) => self.load_url_source(family_name, url_source),
+                // `local()` names a single face, by its full name or its PostScript name.
+                LocalSource(ref name) => {
+                    get_local_font(name.as_slice()).map(|identifier| {
+                        Arc::new(FontTemplateData::new(identifier.as_slice(), None))
+                    })
+                }
+            };
+            match maybe_data {
+                Some(data) => {
+                    self.web_families[*family_name].templates[index].set_data(data);
+                    self.generation.fetch_add(1, SeqCst);
+                    return
+                }
+                None => {}
+            }
+        }
+
+        debug!("Failed to load any source of web font: family={}", family_name);
+        self.web_families[*family_name].templates[index].set_invalid();
+    }
+
+    fn load_url_source(&self, family_name: &LowercaseString, url_source: &UrlSource)
+                       -> Option<Arc<FontTemplateData>> {
+        let url = &url_source.url;
+        if !url_source.format_hints.is_empty() &&
+                !url_source.format_hints.iter().any(|hint| is_supported_format(hint.as_slice())) {
+            debug!("Skipping web font in unsupported format: family={} url={}", family_name, url);
+            return None
+        }
+
+        match load_whole_resource(&self.resource_task, url.clone(), FontContext) {
+            Ok((_, bytes)) => {
+                // Untrusted font data never reaches the platform font engine without being
+                // decoded and sanitized first.
+                match decode_web_font(bytes.as_slice()) {
+                    Ok(font_data) => {
+                        Some(Arc::new(FontTemplateData::new(url.to_string().as_slice(),
+                                                            Some(font_data))))
+                    }
+                    Err(error) => {
+                        debug!("Failed to decode web font: family={} url={} ({})",
+                               family_name, url, error);
+                        None
+                    }
+                }
+            }
+            Err(_) => {
+                debug!("Failed to load web font: family={} url={}", family_name, url);
+                None
+            }
         }
     }
 
     fn get_font_template(&mut self, family: &LowercaseString, desc: &FontTemplateDescriptor)
-                            -> Option<Arc<FontTemplateData>> {
+                            -> Option<FontTemplateInfo> {
         let transformed_family_name = self.transform_family(family);
-        let mut maybe_template = self.find_font_in_web_family(&transformed_family_name, desc);
-        if maybe_template.is_none() {
-            maybe_template = self.find_font_in_local_family(&transformed_family_name, desc);
+        let maybe_template = self.find_font_in_web_family(&transformed_family_name, desc, None);
+        if maybe_template.is_some() {
+            return maybe_template
         }
-        maybe_template
+        self.find_font_in_local_family(&transformed_family_name, desc).map(|font_template| {
+            FontTemplateInfo {
+                font_template: font_template,
+                unicode_range: vec!(),
+            }
+        })
     }
 
     fn get_last_resort_font_template(&mut self, desc: &FontTemplateDescriptor)
@@ -239,6 +378,25 @@ impl FontCache {
 
         panic!("Unable to find any fonts that match (do you have fallback fonts installed?)");
     }
+
+    /// Finds the fonts that the system recommends for rendering the given character, in order.
+    /// The caller is responsible for checking which of them actually cover the character.
+    fn get_fallback_font_templates(&mut self, codepoint: char, desc: &FontTemplateDescriptor)
+                                   -> Vec<Arc<FontTemplateData>> {
+        let mut templates = vec!();
+        for family in get_fallback_font_families(codepoint).iter() {
+            let family = LowercaseString::new(family.as_slice());
+            match self.find_font_in_local_family(&family, desc) {
+                Some(template) => templates.push(template),
+                None => {}
+            }
+        }
+
+        if templates.is_empty() {
+            debug!("FontList: Couldn't find a fallback font for {}", codepoint);
+        }
+        templates
+    }
 }
 
 /// The public interface to the font cache task, used exclusively by
@@ -246,11 +404,14 @@ impl FontCache {
 #[deriving(Clone)]
 pub struct FontCacheTask {
     chan: Sender<Command>,
+    generation: Arc<AtomicUint>,
 }
 
 impl FontCacheTask {
     pub fn new(resource_task: ResourceTask) -> FontCacheTask {
         let (chan, port) = channel();
+        let generation = Arc::new(AtomicUint::new(0));
+        let cache_generation = generation.clone();
 
         spawn_named("FontCacheTask", proc() {
             // TODO: Allow users to specify these.
@@ -268,6 +429,7 @@ impl FontCacheTask {
                 web_families: HashMap::new(),
                 font_context: FontContextHandle::new(),
                 resource_task: resource_task,
+                generation: cache_generation,
             };
 
             cache.refresh_local_families();
@@ -276,11 +438,18 @@ impl FontCacheTask {
 
         FontCacheTask {
             chan: chan,
+            generation: generation,
         }
     }
 
+    /// Counts the changes to the available fonts, so that callers know when to forget that no
+    /// font covered a character.
+    pub fn generation(&self) -> uint {
+        self.generation.load(SeqCst)
+    }
+
     pub fn get_font_template(&self, family: String, desc: FontTemplateDescriptor)
-                                                -> Option<Arc<FontTemplateData>> {
+                                                -> Option<FontTemplateInfo> {
 
         let (response_chan, response_port) = channel();
         self.chan.send(GetFontTemplate(family, desc, response_chan));
@@ -294,6 +463,25 @@ impl FontCacheTask {
         }
     }
 
+    /// Returns the best web font of a family that may be used for the given character, taking
+    /// `unicode-range` into account.
+    pub fn get_web_font_template_for_char(&self,
+                                          family: String,
+                                          desc: FontTemplateDescriptor,
+                                          codepoint: char)
+                                          -> Option<FontTemplateInfo> {
+        let (response_chan, response_port) = channel();
+        self.chan.send(GetWebFontTemplateForChar(family, desc, codepoint, response_chan));
+
+        let reply = response_port.recv();
+
+        match reply {
+            GetFontTemplateReply(data) => {
+                data
+            }
+        }
+    }
+
     pub fn get_last_resort_font_template(&self, desc: FontTemplateDescriptor)
                                                 -> Arc<FontTemplateData> {
 
@@ -304,14 +492,23 @@ impl FontCacheTask {
 
         match reply {
             GetFontTemplateReply(data) => {
-                data.unwrap()
+                data.unwrap().font_template
             }
         }
     }
 
-    pub fn add_web_font(&self, family: String, src: Source) {
+    /// Returns the fonts that the system recommends for rendering the given character, in the
+    /// order in which they should be tried.
+    pub fn get_fallback_font_templates(&self, codepoint: char, desc: FontTemplateDescriptor)
+                                       -> Vec<Arc<FontTemplateData>> {
+        let (response_chan, response_port) = channel();
+        self.chan.send(GetFallbackFontTemplates(codepoint, desc, response_chan));
+        response_port.recv()
+    }
+
+    pub fn add_web_font(&self, rule: FontFaceRule) {
         let (response_chan, response_port) = channel();
-        self.chan.send(AddWebFont(family, src, response_chan));
+        self.chan.send(AddWebFont(rule, response_chan));
         response_port.recv();
     }
 
@@ -3,9 +3,10 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use font::{Font, FontGroup};
-use font::SpecifiedFontStyle;
+use font::{SpecifiedFontStyle, read_gsub_features};
 use platform::font_context::FontContextHandle;
-use style::computed_values::{font_style, font_variant};
+use style::computed_values::font_variant;
+use style::CodepointRange;
 
 use font_cache_task::FontCacheTask;
 use font_template::FontTemplateDescriptor;
@@ -17,6 +18,8 @@ use servo_util::smallvec::{SmallVec, SmallVec8};
 use servo_util::geometry::Au;
 use servo_util::arc_ptr_eq;
 
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::{Occupied, Vacant};
 use std::rc::Rc;
 use std::cell::RefCell;
 use sync::Arc;
@@ -41,6 +44,10 @@ fn create_scaled_font(template: &Arc<FontTemplateData>, pt_size: Au) -> ScaledFo
 
 static SMALL_CAPS_SCALE_FACTOR: f64 = 0.8;      // Matches FireFox (see gfxFont.h)
 
+/// The number of characters that web fonts were found not to cover that are remembered, across
+/// families and styles, before that cache is cleared.
+static MAX_UNCOVERED_WEB_FONT_CHARS: uint = 1024;
+
 struct LayoutFontCacheEntry {
     family: String,
     font: Option<Rc<RefCell<Font>>>,
@@ -50,6 +57,16 @@ struct FallbackFontCacheEntry {
     font: Rc<RefCell<Font>>,
 }
 
+struct WebFontCacheEntry {
+    family: String,
+    font: Rc<RefCell<Font>>,
+}
+
+struct SmallCapsFontCacheEntry {
+    font: Rc<RefCell<Font>>,
+    small_caps_font: Rc<RefCell<Font>>,
+}
+
 /// A cached azure font (per render task) that
 /// can be shared by multiple text runs.
 struct RenderFontCacheEntry {
@@ -70,6 +87,26 @@ pub struct FontContext {
     layout_font_cache: Vec<LayoutFontCacheEntry>,
     fallback_font_cache: Vec<FallbackFontCacheEntry>,
 
+    /// Fonts that the system supplied for characters that no font in a font group could render.
+    char_fallback_font_cache: Vec<FallbackFontCacheEntry>,
+
+    /// Web fonts that were loaded for characters in their `unicode-range`.
+    web_font_for_char_cache: Vec<WebFontCacheEntry>,
+
+    /// Characters that no web font of a family covers, and the styles they were looked up in.
+    uncovered_web_font_chars: HashMap<(String, char), Vec<FontTemplateDescriptor>>,
+
+    /// Scaled-down copies of layout fonts for synthesizing small capitals.
+    small_caps_font_cache: Vec<SmallCapsFontCacheEntry>,
+
+    /// Characters for which the system has no font at all, so that we don't ask again until
+    /// fonts are added.
+    uncovered_chars: HashSet<char>,
+
+    /// The generation of the font cache task's fonts that the uncovered characters, including the
+    /// ones that web fonts don't cover, were looked up in.
+    font_generation: uint,
+
     /// Strong reference as the render FontContext is (for now) recycled
     /// per frame. TODO: Make this weak when incremental redraw is done.
     render_font_cache: Vec<RenderFontCacheEntry>,
@@ -86,19 +123,24 @@ impl FontContext {
             font_cache_task: font_cache_task,
             layout_font_cache: vec!(),
             fallback_font_cache: vec!(),
+            char_fallback_font_cache: vec!(),
+            web_font_for_char_cache: vec!(),
+            uncovered_web_font_chars: HashMap::new(),
+            small_caps_font_cache: vec!(),
+            uncovered_chars: HashSet::new(),
+            font_generation: 0,
             render_font_cache: vec!(),
             last_style: None,
             last_fontgroup: None,
         }
     }
 
-    /// Create a font for use in layout calculations.
+    /// Create a font for use in layout calculations. Fonts with the `small_caps` variant are used
+    /// to synthesize small capitals for fonts that lack the OpenType small capital features.
     fn create_layout_font(&self, template: Arc<FontTemplateData>,
                             descriptor: FontTemplateDescriptor, pt_size: Au,
-                            variant: font_variant::T) -> Font {
-        // TODO: (Bug #3463): Currently we only support fake small-caps
-        // rendering. We should also support true small-caps (where the
-        // font supports it) in the future.
+                            variant: font_variant::T,
+                            unicode_range: Vec<CodepointRange>) -> Font {
         let actual_pt_size = match variant {
             font_variant::small_caps => pt_size.scale_by(SMALL_CAPS_SCALE_FACTOR),
             font_variant::normal => pt_size,
@@ -107,17 +149,20 @@ impl FontContext {
         let handle: FontHandle = FontHandleMethods::new_from_template(&self.platform_handle,
                                     template, Some(actual_pt_size)).unwrap();
         let metrics = handle.get_metrics();
+        let gsub_features = read_gsub_features(&handle);
 
         Font {
             handle: handle,
             shaper: None,
             variant: variant,
             descriptor: descriptor,
+            unicode_range: unicode_range,
             requested_pt_size: pt_size,
             actual_pt_size: actual_pt_size,
             metrics: metrics,
-            shape_cache: HashCache::new(),
+            shape_cache: HashMap::new(),
             glyph_advance_cache: HashCache::new(),
+            gsub_features: gsub_features,
         }
     }
 
@@ -138,7 +183,8 @@ impl FontContext {
         // so they will never be released. Find out a good time to drop them.
 
         let desc = FontTemplateDescriptor::new(style.font_weight,
-                                               style.font_style == font_style::italic);
+                                               style.font_stretch,
+                                               style.font_style);
         let mut fonts = SmallVec8::new();
 
         for family in style.font_family.iter() {
@@ -154,8 +200,7 @@ impl FontContext {
                         Some(ref cached_font_ref) => {
                             let cached_font = cached_font_ref.borrow();
                             if cached_font.descriptor == desc &&
-                               cached_font.requested_pt_size == style.font_size &&
-                               cached_font.variant == style.font_variant {
+                               cached_font.requested_pt_size == style.font_size {
                                 fonts.push((*cached_font_ref).clone());
                                 cache_hit = true;
                                 break;
@@ -171,10 +216,11 @@ impl FontContext {
                                                                            desc.clone());
                 match font_template {
                     Some(font_template) => {
-                        let layout_font = self.create_layout_font(font_template,
+                        let layout_font = self.create_layout_font(font_template.font_template,
                                                                   desc.clone(),
                                                                   style.font_size,
-                                                                  style.font_variant);
+                                                                  font_variant::normal,
+                                                                  font_template.unicode_range);
                         let layout_font = Rc::new(RefCell::new(layout_font));
                         self.layout_font_cache.push(LayoutFontCacheEntry {
                             family: family.name().to_string(),
@@ -194,13 +240,13 @@ impl FontContext {
 
         // If unable to create any of the specified fonts, create one from the
         // list of last resort fonts for this platform.
-        if fonts.len() == 0 {
+        let is_last_resort = fonts.len() == 0;
+        if is_last_resort {
             let mut cache_hit = false;
             for cached_font_entry in self.fallback_font_cache.iter() {
                 let cached_font = cached_font_entry.font.borrow();
                 if cached_font.descriptor == desc &&
-                            cached_font.requested_pt_size == style.font_size &&
-                            cached_font.variant == style.font_variant {
+                            cached_font.requested_pt_size == style.font_size {
                     fonts.push(cached_font_entry.font.clone());
                     cache_hit = true;
                     break;
@@ -212,7 +258,8 @@ impl FontContext {
                 let layout_font = self.create_layout_font(font_template,
                                                           desc.clone(),
                                                           style.font_size,
-                                                          style.font_variant);
+                                                          font_variant::normal,
+                                                          vec!());
                 let layout_font = Rc::new(RefCell::new(layout_font));
                 self.fallback_font_cache.push(FallbackFontCacheEntry {
                     font: layout_font.clone(),
@@ -221,12 +268,191 @@ impl FontContext {
             }
         }
 
-        let font_group = Rc::new(FontGroup::new(fonts));
+        let font_group = Rc::new(FontGroup::new(fonts, is_last_resort));
         self.last_style = Some(style);
         self.last_fontgroup = Some(font_group.clone());
         font_group
     }
 
+    /// Returns a font that can render the given character in the given style, for use when none
+    /// of the fonts in the style's font group can. Returns `None` if the system has no such font.
+    pub fn get_fallback_font_for_char(&mut self, style: &SpecifiedFontStyle, codepoint: char)
+                                      -> Option<Rc<RefCell<Font>>> {
+        self.forget_uncovered_chars_if_fonts_changed();
+        if self.uncovered_chars.contains(&codepoint) {
+            return None
+        }
+
+        let desc = FontTemplateDescriptor::new(style.font_weight,
+                                               style.font_stretch,
+                                               style.font_style);
+
+        // Fallback fonts usually cover whole scripts, so try the ones we already have first.
+        for cached_font_entry in self.char_fallback_font_cache.iter() {
+            let cached_font = cached_font_entry.font.borrow();
+            if cached_font.descriptor == desc &&
+                    cached_font.requested_pt_size == style.font_size &&
+                    cached_font.has_glyph_for(codepoint) {
+                return Some(cached_font_entry.font.clone())
+            }
+        }
+
+        let font_templates = self.font_cache_task.get_fallback_font_templates(codepoint,
+                                                                              desc.clone());
+        for font_template in font_templates.into_iter() {
+            let layout_font = self.create_layout_font(font_template,
+                                                      desc.clone(),
+                                                      style.font_size,
+                                                      font_variant::normal,
+                                                      vec!());
+            if !layout_font.has_glyph_for(codepoint) {
+                continue
+            }
+
+            let layout_font = Rc::new(RefCell::new(layout_font));
+            self.char_fallback_font_cache.push(FallbackFontCacheEntry {
+                font: layout_font.clone(),
+            });
+            return Some(layout_font)
+        }
+
+        self.uncovered_chars.insert(codepoint);
+        None
+    }
+
+    /// Forgets which characters no font covered once the font cache task has new fonts, or has
+    /// loaded web fonts.
+    fn forget_uncovered_chars_if_fonts_changed(&mut self) {
+        let generation = self.font_cache_task.generation();
+        if generation != self.font_generation {
+            self.font_generation = generation;
+            self.uncovered_chars.clear();
+            self.uncovered_web_font_chars.clear();
+        }
+    }
+
+    /// Returns a web font of one of the style's font families whose `unicode-range` includes the
+    /// given character. Such fonts are only loaded once a character in their range is used.
+    pub fn get_web_font_for_char(&mut self, style: &SpecifiedFontStyle, codepoint: char)
+                                 -> Option<Rc<RefCell<Font>>> {
+        self.forget_uncovered_chars_if_fonts_changed();
+        let desc = FontTemplateDescriptor::new(style.font_weight,
+                                               style.font_stretch,
+                                               style.font_style);
+
+        for family in style.font_family.iter() {
+            let family_name = family.name();
+            for cached_font_entry in self.web_font_for_char_cache.iter() {
+                let cached_font = cached_font_entry.font.borrow();
+                if cached_font_entry.family.as_slice() == family_name &&
+                        cached_font.descriptor == desc &&
+                        cached_font.requested_pt_size == style.font_size &&
+                        cached_font.has_glyph_for(codepoint) {
+                    return Some(cached_font_entry.font.clone())
+                }
+            }
+
+            if self.is_uncovered_by_web_fonts(family_name, &desc, codepoint) {
+                continue
+            }
+
+            let font_template =
+                match self.font_cache_task.get_web_font_template_for_char(family_name.to_string(),
+                                                                          desc.clone(),
+                                                                          codepoint) {
+                    Some(font_template) => font_template,
+                    None => {
+                        self.add_uncovered_web_font_char(family_name, &desc, codepoint);
+                        continue
+                    }
+                };
+
+            // If the face has been loaded already, it was checked for the glyph above.
+            let is_cached = self.web_font_for_char_cache.iter().any(|cached_font_entry| {
+                let cached_font = cached_font_entry.font.borrow();
+                cached_font_entry.family.as_slice() == family_name &&
+                    cached_font.descriptor == desc &&
+                    cached_font.requested_pt_size == style.font_size &&
+                    arc_ptr_eq(&cached_font.handle.get_template(), &font_template.font_template)
+            });
+            if is_cached {
+                self.add_uncovered_web_font_char(family_name, &desc, codepoint);
+                continue
+            }
+
+            let layout_font = self.create_layout_font(font_template.font_template,
+                                                      desc.clone(),
+                                                      style.font_size,
+                                                      font_variant::normal,
+                                                      font_template.unicode_range);
+            if !layout_font.has_glyph_for(codepoint) {
+                self.add_uncovered_web_font_char(family_name, &desc, codepoint);
+                continue
+            }
+
+            let layout_font = Rc::new(RefCell::new(layout_font));
+            self.web_font_for_char_cache.push(WebFontCacheEntry {
+                family: family_name.to_string(),
+                font: layout_font.clone(),
+            });
+            return Some(layout_font)
+        }
+        None
+    }
+
+    fn is_uncovered_by_web_fonts(&self,
+                                 family_name: &str,
+                                 desc: &FontTemplateDescriptor,
+                                 codepoint: char)
+                                 -> bool {
+        match self.uncovered_web_font_chars.get(&(family_name.to_string(), codepoint)) {
+            Some(descs) => descs.contains(desc),
+            None => false,
+        }
+    }
+
+    fn add_uncovered_web_font_char(&mut self,
+                                   family_name: &str,
+                                   desc: &FontTemplateDescriptor,
+                                   codepoint: char) {
+        if self.uncovered_web_font_chars.len() >= MAX_UNCOVERED_WEB_FONT_CHARS {
+            self.uncovered_web_font_chars.clear();
+        }
+        let key = (family_name.to_string(), codepoint);
+        match self.uncovered_web_font_chars.entry(key) {
+            Occupied(entry) => entry.into_mut().push(desc.clone()),
+            Vacant(entry) => {
+                entry.set(vec!(desc.clone()));
+            }
+        }
+    }
+
+    /// Returns a copy of the given layout font for synthesizing small capitals: it is scaled down
+    /// and renders lowercase letters with uppercase glyphs.
+    pub fn get_small_caps_font(&mut self, font: &Rc<RefCell<Font>>) -> Rc<RefCell<Font>> {
+        for cached_font_entry in self.small_caps_font_cache.iter() {
+            if &*cached_font_entry.font as *const RefCell<Font> ==
+                    &**font as *const RefCell<Font> {
+                return cached_font_entry.small_caps_font.clone()
+            }
+        }
+
+        let small_caps_font = {
+            let font = font.borrow();
+            self.create_layout_font(font.handle.get_template(),
+                                    font.descriptor.clone(),
+                                    font.requested_pt_size,
+                                    font_variant::small_caps,
+                                    font.unicode_range.clone())
+        };
+        let small_caps_font = Rc::new(RefCell::new(small_caps_font));
+        self.small_caps_font_cache.push(SmallCapsFontCacheEntry {
+            font: font.clone(),
+            small_caps_font: small_caps_font.clone(),
+        });
+        small_caps_font
+    }
+
     /// Create a render font for use with azure. May return a cached
     /// reference if already used by this font context.
     pub fn get_render_font_from_template(&mut self,
@@ -2,37 +2,102 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use style::computed_values::font_weight;
+use style::computed_values::{font_stretch, font_style, font_weight};
+use style::{CodepointRange, Source};
 use platform::font_context::FontContextHandle;
 use platform::font::FontHandle;
 use platform::font_template::FontTemplateData;
 
+use std::mem;
 use sync::{Arc, Weak};
 use font::FontHandleMethods;
 
 /// Describes how to select a font from a given family.
-/// This is very basic at the moment and needs to be
-/// expanded or refactored when we support more of the
-/// font styling parameters.
-#[deriving(Clone)]
+#[deriving(Clone, PartialEq)]
 pub struct FontTemplateDescriptor {
     pub weight: font_weight::T,
-    pub italic: bool,
+    pub stretch: font_stretch::T,
+    pub style: font_style::T,
 }
 
 impl FontTemplateDescriptor {
-    pub fn new(weight: font_weight::T, italic: bool) -> FontTemplateDescriptor {
+    pub fn new(weight: font_weight::T, stretch: font_stretch::T, style: font_style::T)
+               -> FontTemplateDescriptor {
         FontTemplateDescriptor {
             weight: weight,
-            italic: italic,
+            stretch: stretch,
+            style: style,
         }
     }
+
+    /// Returns how far this descriptor is from the requested one, as a tuple that compares lower
+    /// for better matches. This implements the font matching algorithm of CSS Fonts Level 3 § 5.2:
+    /// the candidate faces are narrowed down by font-stretch, then by font-style, and finally by
+    /// font-weight.
+    pub fn distance_from(&self, requested: &FontTemplateDescriptor) -> (u32, u32, u32) {
+        (stretch_distance(stretch_value(requested.stretch), stretch_value(self.stretch)),
+         style_distance(requested.style, self.style),
+         weight_distance(requested.weight.value() as u32, self.weight.value() as u32))
+    }
 }
 
-impl PartialEq for FontTemplateDescriptor {
-    fn eq(&self, other: &FontTemplateDescriptor) -> bool {
-        self.weight.is_bold() == other.weight.is_bold() &&
-        self.italic == other.italic
+/// Returns the numeric value of a font-stretch keyword, from 1 (ultra-condensed) to 9
+/// (ultra-expanded).
+fn stretch_value(stretch: font_stretch::T) -> u32 {
+    match stretch {
+        font_stretch::ultra_condensed => 1,
+        font_stretch::extra_condensed => 2,
+        font_stretch::condensed => 3,
+        font_stretch::semi_condensed => 4,
+        font_stretch::normal => 5,
+        font_stretch::semi_expanded => 6,
+        font_stretch::expanded => 7,
+        font_stretch::extra_expanded => 8,
+        font_stretch::ultra_expanded => 9,
+    }
+}
+
+/// If the requested width is normal or condensed, narrower widths are checked first, then wider
+/// ones; otherwise wider widths are checked first.
+fn stretch_distance(requested: u32, actual: u32) -> u32 {
+    if actual == requested {
+        0
+    } else if (requested <= 5) == (actual < requested) {
+        if actual < requested { requested - actual } else { actual - requested }
+    } else {
+        10 + if actual < requested { requested - actual } else { actual - requested }
+    }
+}
+
+/// Italic faces are preferred for italic text, falling back to oblique faces and then normal
+/// ones; oblique text prefers oblique faces, then italic ones; normal text prefers normal faces,
+/// then oblique ones.
+fn style_distance(requested: font_style::T, actual: font_style::T) -> u32 {
+    match (requested, actual) {
+        (font_style::italic, font_style::italic) => 0,
+        (font_style::italic, font_style::oblique) => 1,
+        (font_style::italic, font_style::normal) => 2,
+        (font_style::oblique, font_style::oblique) => 0,
+        (font_style::oblique, font_style::italic) => 1,
+        (font_style::oblique, font_style::normal) => 2,
+        (font_style::normal, font_style::normal) => 0,
+        (font_style::normal, font_style::oblique) => 1,
+        (font_style::normal, font_style::italic) => 2,
+    }
+}
+
+/// For a requested weight of 400, 500 is checked first, and vice versa. Below that, lighter
+/// weights are checked in descending order and then heavier weights in ascending order; above
+/// it, heavier weights are checked first and then lighter ones.
+fn weight_distance(requested: u32, actual: u32) -> u32 {
+    if actual == requested {
+        0
+    } else if (requested == 400 && actual == 500) || (requested == 500 && actual == 400) {
+        1
+    } else if requested <= 500 {
+        if actual < requested { 10 + requested - actual } else { 2000 + actual - requested }
+    } else {
+        if actual > requested { 10 + actual - requested } else { 2000 + requested - actual }
     }
 }
 
@@ -45,6 +110,12 @@ pub struct FontTemplate {
     weak_ref: Option<Weak<FontTemplateData>>,
     strong_ref: Option<Arc<FontTemplateData>>,      // GWTODO: Add code path to unset the strong_ref for web fonts!
     is_valid: bool,
+    /// The characters that this font may be used for, from the `unicode-range` descriptor of a
+    /// web font. Empty if the font may be used for all characters.
+    unicode_range: Vec<CodepointRange>,
+    /// The sources that a web font has yet to be loaded from, in order of preference. Web fonts
+    /// are only loaded once they are selected for rendering some text.
+    pending_sources: Vec<Source>,
 }
 
 /// Holds all of the template information for a font that
@@ -73,6 +144,26 @@ impl FontTemplate {
             weak_ref: maybe_weak_ref,
             strong_ref: maybe_strong_ref,
             is_valid: true,
+            unicode_range: vec!(),
+            pending_sources: vec!(),
+        }
+    }
+
+    /// Creates a template for an `@font-face` rule. Its descriptor comes from the rule rather than
+    /// from the font itself, and its data is not loaded until `set_data` is called.
+    pub fn new_web_font(identifier: &str,
+                        descriptor: FontTemplateDescriptor,
+                        unicode_range: Vec<CodepointRange>,
+                        sources: Vec<Source>)
+                        -> FontTemplate {
+        FontTemplate {
+            identifier: identifier.to_string(),
+            descriptor: Some(descriptor),
+            weak_ref: None,
+            strong_ref: None,
+            is_valid: true,
+            unicode_range: unicode_range,
+            pending_sources: sources,
         }
     }
 
@@ -80,56 +171,84 @@ impl FontTemplate {
         self.identifier.as_slice()
     }
 
-    /// Get the data for creating a font if it matches a given descriptor.
-    pub fn get_if_matches(&mut self, fctx: &FontContextHandle,
-                            requested_desc: &FontTemplateDescriptor) -> Option<Arc<FontTemplateData>> {
+    pub fn is_valid(&self) -> bool {
+        self.is_valid
+    }
+
+    pub fn unicode_range<'a>(&'a self) -> &'a [CodepointRange] {
+        self.unicode_range.as_slice()
+    }
+
+    /// Returns true if this font may be used to render the given character.
+    pub fn covers(&self, codepoint: char) -> bool {
+        self.unicode_range.is_empty() ||
+            self.unicode_range.iter().any(|range| range.contains(codepoint))
+    }
+
+    /// Returns true if this is a web font that has not been loaded yet.
+    pub fn needs_loading(&self) -> bool {
+        self.is_valid && !self.pending_sources.is_empty()
+    }
+
+    /// Takes the sources that this web font can be loaded from, so that the font cache task can
+    /// load it.
+    pub fn take_pending_sources(&mut self) -> Vec<Source> {
+        mem::replace(&mut self.pending_sources, vec!())
+    }
+
+    /// Supplies the data of a web font once it has been loaded.
+    pub fn set_data(&mut self, data: Arc<FontTemplateData>) {
+        self.weak_ref = Some(data.downgrade());
+        self.strong_ref = Some(data);
+    }
+
+    /// Marks this font as unusable, e.g. because none of its sources could be loaded.
+    pub fn set_invalid(&mut self) {
+        self.is_valid = false;
+    }
+
+    /// Get the descriptor of this font, loading the font to find out its style if necessary.
+    /// Returns `None` if the font cannot be loaded.
+    pub fn descriptor(&mut self, fctx: &FontContextHandle) -> Option<FontTemplateDescriptor> {
         // The font template data can be unloaded when nothing is referencing
         // it (via the Weak reference to the Arc above). However, if we have
         // already loaded a font, store the style information about it separately,
         // so that we can do font matching against it again in the future
-        // without having to reload the font (unless it is an actual match).
+        // without having to reload the font.
+        if !self.is_valid {
+            return None
+        }
         match self.descriptor {
-            Some(actual_desc) => {
-                if *requested_desc == actual_desc {
-                    Some(self.get_data())
-                } else {
-                    None
-                }
-            },
-            None => {
-                if self.is_valid {
-                    let data = self.get_data();
-                    let handle: Result<FontHandle, ()> = FontHandleMethods::new_from_template(fctx, data.clone(), None);
-                    match handle {
-                        Ok(handle) => {
-                            let actual_desc = FontTemplateDescriptor::new(handle.boldness(),
-                                                handle.is_italic());
-                            let desc_match = actual_desc == *requested_desc;
-
-                            self.descriptor = Some(actual_desc);
-                            self.is_valid = true;
-                            if desc_match {
-                                Some(data)
-                            } else {
-                                None
-                            }
-                        }
-                        Err(()) => {
-                            self.is_valid = false;
-                            debug!("Unable to create a font from template {}", self.identifier);
-                            None
-                        }
-                    }
+            Some(descriptor) => return Some(descriptor),
+            None => {}
+        }
+
+        let data = self.get_data();
+        let handle: Result<FontHandle, ()> = FontHandleMethods::new_from_template(fctx, data, None);
+        match handle {
+            Ok(handle) => {
+                let style = if handle.is_italic() {
+                    font_style::italic
                 } else {
-                    None
-                }
+                    font_style::normal
+                };
+                let descriptor = FontTemplateDescriptor::new(handle.boldness(),
+                                                             handle.stretchiness(),
+                                                             style);
+                self.descriptor = Some(descriptor);
+                Some(descriptor)
+            }
+            Err(()) => {
+                self.is_valid = false;
+                debug!("Unable to create a font from template {}", self.identifier);
+                None
             }
         }
     }
 
     /// Get the data for creating a font.
     pub fn get(&mut self) -> Option<Arc<FontTemplateData>> {
-        match self.is_valid {
+        match self.is_valid && self.pending_sources.is_empty() {
             true => Some(self.get_data()),
             false => None
         }
@@ -155,3 +274,70 @@ impl FontTemplate {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::FontTemplateDescriptor;
+    use style::computed_values::{font_stretch, font_style, font_weight};
+
+    fn best_match(requested: FontTemplateDescriptor, candidates: &[FontTemplateDescriptor])
+                  -> FontTemplateDescriptor {
+        let mut best = candidates[0];
+        for candidate in candidates.iter() {
+            if candidate.distance_from(&requested) < best.distance_from(&requested) {
+                best = *candidate;
+            }
+        }
+        best
+    }
+
+    fn descriptor(weight: font_weight::T, style: font_style::T) -> FontTemplateDescriptor {
+        FontTemplateDescriptor::new(weight, font_stretch::normal, style)
+    }
+
+    #[test]
+    fn test_weight_matching() {
+        let faces = [
+            descriptor(font_weight::Weight300, font_style::normal),
+            descriptor(font_weight::Weight500, font_style::normal),
+            descriptor(font_weight::Weight700, font_style::normal),
+        ];
+        let best = |weight| best_match(descriptor(weight, font_style::normal), faces.as_slice());
+        assert!(best(font_weight::Weight400).weight == font_weight::Weight500);
+        assert!(best(font_weight::Weight200).weight == font_weight::Weight300);
+        assert!(best(font_weight::Weight600).weight == font_weight::Weight700);
+        assert!(best(font_weight::Weight900).weight == font_weight::Weight700);
+        assert!(best(font_weight::Weight100).weight == font_weight::Weight300);
+    }
+
+    #[test]
+    fn test_style_matching() {
+        let faces = [
+            descriptor(font_weight::Weight700, font_style::normal),
+            descriptor(font_weight::Weight400, font_style::oblique),
+        ];
+        let best = best_match(descriptor(font_weight::Weight700, font_style::italic),
+                              faces.as_slice());
+        assert!(best.style == font_style::oblique);
+    }
+
+    #[test]
+    fn test_stretch_matching() {
+        let faces = [
+            FontTemplateDescriptor::new(font_weight::Weight400,
+                                        font_stretch::semi_condensed,
+                                        font_style::normal),
+            FontTemplateDescriptor::new(font_weight::Weight400,
+                                        font_stretch::expanded,
+                                        font_style::normal),
+        ];
+        let best = |stretch| {
+            best_match(FontTemplateDescriptor::new(font_weight::Weight400, stretch,
+                                                   font_style::normal),
+                       faces.as_slice())
+        };
+        assert!(best(font_stretch::normal).stretch == font_stretch::semi_condensed);
+        assert!(best(font_stretch::semi_expanded).stretch == font_stretch::expanded);
+        assert!(best(font_stretch::ultra_condensed).stretch == font_stretch::semi_condensed);
+    }
+}
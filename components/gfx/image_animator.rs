@@ -0,0 +1,276 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Plays animated images. The render task tells the animator which animated images are visible;
+//! the animator advances them to their next frames as their delays elapse and reports which areas
+//! of which layers have to be repainted as a result. Images that aren't visible stay on their
+//! current frame until they are visible again.
+//!
+//! Images are shared between pipelines, so the frame that is shown is kept here, for each image
+//! URL, rather than in the images.
+
+use azure::AzFloat;
+use geom::rect::Rect;
+use servo_msg::compositor_msg::LayerId;
+use servo_net::image::base::Image;
+use std::collections::HashMap;
+use sync::Arc;
+use url::Url;
+
+/// The index of the frame that is shown for each animated image, by URL. Images that aren't in
+/// the map show their first frame.
+pub type ImageFrames = HashMap<Url, uint>;
+
+/// An animated image that is painted into a layer.
+pub struct AnimatedImage {
+    pub layer_id: LayerId,
+    /// The area of the layer that the image is painted into.
+    pub bounds: Rect<AzFloat>,
+    pub url: Url,
+    pub image: Arc<Box<Image>>,
+}
+
+/// The playback state of a visible animated image.
+struct Animation {
+    url: Url,
+    image: Arc<Box<Image>>,
+    /// When the image moves to its next frame, in nanoseconds.
+    next_frame_time: u64,
+    /// How many more times the animation is played, including the current time.
+    plays_left: Option<u32>,
+}
+
+impl Animation {
+    fn new(url: Url, image: Arc<Box<Image>>, frame: uint, now: u64) -> Animation {
+        Animation {
+            url: url,
+            next_frame_time: now + frame_delay(&**image, frame),
+            plays_left: image.play_count,
+            image: image,
+        }
+    }
+
+    /// Returns the frame after `frame`, or `None` if the animation has finished instead.
+    fn advance(&mut self, frame: uint, now: u64) -> Option<uint> {
+        let mut next_frame = frame + 1;
+        if next_frame >= self.image.frames.len() {
+            match self.plays_left {
+                Some(plays_left) if plays_left <= 1 => return None,
+                Some(plays_left) => self.plays_left = Some(plays_left - 1),
+                None => {}
+            }
+            next_frame = 0;
+        }
+        self.next_frame_time = now + frame_delay(&**self.image, next_frame);
+        Some(next_frame)
+    }
+}
+
+/// How long `frame` of `image` is shown, in nanoseconds.
+fn frame_delay(image: &Image, frame: uint) -> u64 {
+    image.frame(frame).delay as u64 * 1_000_000
+}
+
+pub struct ImageAnimator {
+    /// The animated images in the visible parts of the layers.
+    visible_images: Vec<AnimatedImage>,
+    /// The animations of the visible images. Several display items can show the same image, so
+    /// there is at most one animation per URL.
+    animations: Vec<Animation>,
+    /// The frames that are shown, including those of images that are paused. The map is replaced
+    /// rather than modified when a frame changes, because the painting threads hold on to it.
+    frames: Arc<ImageFrames>,
+}
+
+impl ImageAnimator {
+    pub fn new() -> ImageAnimator {
+        ImageAnimator {
+            visible_images: vec!(),
+            animations: vec!(),
+            frames: Arc::new(HashMap::new()),
+        }
+    }
+
+    /// The index of the frame of the image at `url` that is shown.
+    pub fn frame(&self, url: &Url) -> uint {
+        self.frames.get(url).map_or(0, |&frame| frame)
+    }
+
+    /// The frames to paint the animated images with.
+    pub fn frames(&self) -> Arc<ImageFrames> {
+        self.frames.clone()
+    }
+
+    /// Replaces the set of visible animated images. Images that are no longer visible pause on
+    /// their current frame, and images that have become visible start playing from theirs.
+    pub fn set_visible_images(&mut self, images: Vec<AnimatedImage>, now: u64) {
+        let mut animations = vec!();
+        for visible_image in images.iter() {
+            if animations.iter().any(|animation: &Animation| animation.url == visible_image.url) {
+                continue
+            }
+            let index = self.animations.iter().position(|animation| {
+                animation.url == visible_image.url
+            });
+            animations.push(match index {
+                Some(index) => self.animations.swap_remove(index).unwrap(),
+                None => Animation::new(visible_image.url.clone(),
+                                       visible_image.image.clone(),
+                                       self.frame(&visible_image.url),
+                                       now),
+            });
+        }
+        self.visible_images = images;
+        self.animations = animations;
+    }
+
+    /// When the next frame of any visible image is due, in nanoseconds.
+    pub fn next_frame_time(&self) -> Option<u64> {
+        self.animations.iter().map(|animation| animation.next_frame_time).min()
+    }
+
+    /// Advances every animation whose next frame is due, and returns the areas of the layers that
+    /// show the images that changed.
+    pub fn advance(&mut self, now: u64) -> Vec<(LayerId, Rect<AzFloat>)> {
+        let mut frames = (*self.frames).clone();
+        let mut changed_urls = vec!();
+        let mut index = 0;
+        while index < self.animations.len() {
+            if self.animations[index].next_frame_time > now {
+                index += 1;
+                continue
+            }
+            let frame = self.frame(&self.animations[index].url);
+            match self.animations[index].advance(frame, now) {
+                Some(next_frame) => {
+                    let url = self.animations[index].url.clone();
+                    frames.insert(url.clone(), next_frame);
+                    changed_urls.push(url);
+                    index += 1;
+                }
+                None => {
+                    self.animations.remove(index);
+                }
+            }
+        }
+        if changed_urls.is_empty() {
+            return vec!()
+        }
+        self.frames = Arc::new(frames);
+
+        self.visible_images.iter().filter(|visible_image| {
+            changed_urls.contains(&visible_image.url)
+        }).map(|visible_image| {
+            (visible_image.layer_id.clone(), visible_image.bounds)
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnimatedImage, ImageAnimator};
+
+    use geom::point::Point2D;
+    use geom::rect::Rect;
+    use geom::size::Size2D;
+    use servo_msg::compositor_msg::LayerId;
+    use servo_net::image::base::{Image, RawFrame, DisposeNone, BlendSource};
+    use sync::Arc;
+    use url::Url;
+
+    static MS: u64 = 1_000_000;
+
+    /// A 1x1 animation with frames shown for 100ms and 200ms.
+    fn image(play_count: Option<u32>) -> Arc<Box<Image>> {
+        let frames = vec!(100, 200).into_iter().map(|delay| {
+            RawFrame {
+                rect: Rect(Point2D(0, 0), Size2D(1, 1)),
+                pixels: vec!(0, 0, 0, 255),
+                delay: delay,
+                disposal: DisposeNone,
+                blend: BlendSource,
+            }
+        }).collect();
+        Arc::new(box Image::from_frames(1, 1, frames, play_count).unwrap())
+    }
+
+    fn url() -> Url {
+        Url::parse("http://example.com/animation.gif").unwrap()
+    }
+
+    fn visible(image: &Arc<Box<Image>>, x: f32) -> AnimatedImage {
+        AnimatedImage {
+            layer_id: LayerId::null(),
+            bounds: Rect(Point2D(x, 0.0), Size2D(1.0, 1.0)),
+            url: url(),
+            image: image.clone(),
+        }
+    }
+
+    #[test]
+    fn test_advance() {
+        let image = image(None);
+        let mut animator = ImageAnimator::new();
+        animator.set_visible_images(vec!(visible(&image, 0.0), visible(&image, 5.0)), 0);
+        assert_eq!(animator.next_frame_time(), Some(100 * MS));
+
+        // Nothing is due yet.
+        assert!(animator.advance(50 * MS).is_empty());
+        assert_eq!(animator.frame(&url()), 0);
+
+        // Both display items of the image are repainted.
+        let areas = animator.advance(100 * MS);
+        assert_eq!(areas.len(), 2);
+        let (_, ref bounds) = areas[1];
+        assert_eq!(bounds.origin.x, 5.0);
+        assert_eq!(animator.frame(&url()), 1);
+        assert_eq!(animator.next_frame_time(), Some(300 * MS));
+
+        // Looping animations go back to the first frame.
+        assert_eq!(animator.advance(300 * MS).len(), 2);
+        assert_eq!(animator.frame(&url()), 0);
+    }
+
+    #[test]
+    fn test_play_count() {
+        let image = image(Some(1));
+        let mut animator = ImageAnimator::new();
+        animator.set_visible_images(vec!(visible(&image, 0.0)), 0);
+        animator.advance(100 * MS);
+        assert_eq!(animator.frame(&url()), 1);
+
+        // The animation stops on its last frame.
+        assert!(animator.advance(300 * MS).is_empty());
+        assert_eq!(animator.frame(&url()), 1);
+        assert_eq!(animator.next_frame_time(), None);
+    }
+
+    #[test]
+    fn test_offscreen_images_pause() {
+        let image = image(None);
+        let mut animator = ImageAnimator::new();
+        animator.set_visible_images(vec!(visible(&image, 0.0)), 0);
+        animator.set_visible_images(vec!(), 50 * MS);
+        assert_eq!(animator.next_frame_time(), None);
+        assert!(animator.advance(1000 * MS).is_empty());
+        assert_eq!(animator.frame(&url()), 0);
+
+        // The frame's delay starts over when the image is visible again.
+        animator.set_visible_images(vec!(visible(&image, 0.0)), 2000 * MS);
+        assert_eq!(animator.next_frame_time(), Some(2100 * MS));
+    }
+
+    #[test]
+    fn test_frames_are_snapshots() {
+        let image = image(None);
+        let mut animator = ImageAnimator::new();
+        animator.set_visible_images(vec!(visible(&image, 0.0)), 0);
+        let frames = animator.frames();
+        animator.advance(100 * MS);
+
+        // Tiles that are being painted keep the frames that they started with.
+        assert_eq!(frames.get(&url()), None);
+        assert_eq!(animator.frames().get(&url()), Some(&1));
+    }
+}
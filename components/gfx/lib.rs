@@ -12,7 +12,9 @@
 extern crate log;
 
 extern crate azure;
+extern crate brotli;
 extern crate collections;
+extern crate flate2;
 extern crate geom;
 extern crate layers;
 extern crate libc;
@@ -59,6 +61,7 @@ mod render_context;
 pub mod color;
 #[path="display_list/mod.rs"]
 pub mod display_list;
+pub mod image_animator;
 pub mod render_task;
 
 // Fonts
@@ -66,6 +69,8 @@ pub mod font;
 pub mod font_context;
 pub mod font_cache_task;
 pub mod font_template;
+pub mod sfnt;
+pub mod woff;
 
 // Misc.
 mod buffer_map;
@@ -11,7 +11,7 @@ use servo_util::geometry;
 use platform::font_context::FontContextHandle;
 use text::glyph::GlyphId;
 use text::util::{float_to_fixed, fixed_to_float};
-use style::computed_values::font_weight;
+use style::computed_values::{font_stretch, font_weight};
 use platform::font_template::FontTemplateData;
 
 use freetype::freetype::{FT_Get_Char_Index, FT_Get_Postscript_Name};
@@ -157,6 +157,27 @@ impl FontHandleMethods for FontHandle {
         }
     }
 
+    fn stretchiness(&self) -> font_stretch::T {
+        unsafe {
+            let os2 = FT_Get_Sfnt_Table(self.face, ft_sfnt_os2) as *mut TT_OS2;
+            let valid = os2.is_not_null() && (*os2).version != 0xffff;
+            if !valid {
+                return font_stretch::normal
+            }
+            match (*os2).usWidthClass {
+                1 => font_stretch::ultra_condensed,
+                2 => font_stretch::extra_condensed,
+                3 => font_stretch::condensed,
+                4 => font_stretch::semi_condensed,
+                6 => font_stretch::semi_expanded,
+                7 => font_stretch::expanded,
+                8 => font_stretch::extra_expanded,
+                9 => font_stretch::ultra_expanded,
+                _ => font_stretch::normal,
+            }
+        }
+    }
+
     fn glyph_index(&self,
                        codepoint: char) -> Option<GlyphId> {
         assert!(self.face.is_not_null());
@@ -7,27 +7,33 @@
 extern crate freetype;
 extern crate fontconfig;
 
-use fontconfig::fontconfig::{FcChar8, FcResultMatch, FcSetSystem};
+use fontconfig::fontconfig::{FcChar8, FcChar32, FcCharSet, FcPattern, FcResultMatch, FcSetSystem};
 use fontconfig::fontconfig::{
     FcConfigGetCurrent, FcConfigGetFonts,
     FcConfigSubstitute, FcDefaultSubstitute,
-    FcFontMatch,
+    FcFontMatch, FcFontSort,
     FcNameParse, FcPatternGetString,
     FcPatternDestroy, FcFontSetDestroy,
     FcMatchPattern,
     FcPatternCreate, FcPatternAddString,
     FcFontSetList, FcObjectSetCreate, FcObjectSetDestroy,
-    FcObjectSetAdd, FcPatternGetInteger
+    FcObjectSetAdd, FcPatternGetInteger,
+    FcCharSetCreate, FcCharSetDestroy, FcCharSetAddChar, FcCharSetHasChar,
+    FcPatternAddCharSet, FcPatternGetCharSet
 };
 
 use libc;
 use libc::c_int;
+use std::ascii::AsciiExt;
 use std::ptr;
 use std::string;
 
 static FC_FAMILY: &'static [u8] = b"family\0";
 static FC_FILE: &'static [u8] = b"file\0";
 static FC_INDEX: &'static [u8] = b"index\0";
+static FC_CHARSET: &'static [u8] = b"charset\0";
+static FC_FULLNAME: &'static [u8] = b"fullname\0";
+static FC_POSTSCRIPT_NAME: &'static [u8] = b"postscriptname\0";
 
 pub fn get_available_families(callback: |String|) {
     unsafe {
@@ -96,6 +102,42 @@ pub fn get_variations_for_family(family_name: &str, callback: |String|) {
     }
 }
 
+/// Whether one of the values of a string property of a font is the given name, ignoring ASCII
+/// case.
+unsafe fn has_name(font: *mut FcPattern, object: &[u8], name: &str) -> bool {
+    let mut value: *mut FcChar8 = ptr::null_mut();
+    let mut v: c_int = 0;
+    while FcPatternGetString(font, object.as_ptr() as *mut i8, v, &mut value) == FcResultMatch {
+        let value = string::raw::from_buf(value as *const i8 as *const u8);
+        if value.as_slice().eq_ignore_ascii_case(name) {
+            return true
+        }
+        v += 1;
+    }
+    false
+}
+
+/// Returns the file of the installed font with the given full name or PostScript name, which is
+/// how `local()` in `@font-face` rules names fonts.
+pub fn get_local_font(name: &str) -> Option<String> {
+    unsafe {
+        let config = FcConfigGetCurrent();
+        let font_set = FcConfigGetFonts(config, FcSetSystem);
+        for i in range(0, (*font_set).nfont as int) {
+            let font = *(*font_set).fonts.offset(i);
+            if !has_name(font, FC_FULLNAME, name) && !has_name(font, FC_POSTSCRIPT_NAME, name) {
+                continue
+            }
+            let mut file: *mut FcChar8 = ptr::null_mut();
+            if FcPatternGetString(font, FC_FILE.as_ptr() as *mut i8, 0,
+                                  &mut file) == FcResultMatch {
+                return Some(string::raw::from_buf(file as *const i8 as *const u8))
+            }
+        }
+    }
+    None
+}
+
 pub fn get_system_default_family(generic_name: &str) -> Option<String> {
     let mut generic_name_c = generic_name.to_c_str();
     let generic_name_ptr = generic_name_c.as_mut_ptr();
@@ -124,6 +166,56 @@ pub fn get_system_default_family(generic_name: &str) -> Option<String> {
     }
 }
 
+/// Returns the families of the fonts that fontconfig considers suitable for rendering the given
+/// character, best match first. Only families that actually cover the character are returned.
+pub fn get_fallback_font_families(codepoint: char) -> Vec<String> {
+    let mut families = vec!();
+    unsafe {
+        let config = FcConfigGetCurrent();
+        let pattern = FcPatternCreate();
+        assert!(pattern.is_not_null());
+        let char_set = FcCharSetCreate();
+        assert!(char_set.is_not_null());
+
+        FcCharSetAddChar(char_set, codepoint as FcChar32);
+        let ok = FcPatternAddCharSet(pattern, FC_CHARSET.as_ptr() as *mut i8, char_set);
+        assert!(ok != 0);
+        FcConfigSubstitute(config, pattern, FcMatchPattern);
+        FcDefaultSubstitute(pattern);
+
+        let mut result = 0;
+        let font_set = FcFontSort(config, pattern, 1, ptr::null_mut(), &mut result);
+        if result == FcResultMatch && font_set.is_not_null() {
+            for i in range(0, (*font_set).nfont as int) {
+                let font = *(*font_set).fonts.offset(i);
+
+                let mut font_char_set: *mut FcCharSet = ptr::null_mut();
+                if FcPatternGetCharSet(font, FC_CHARSET.as_ptr() as *mut i8, 0,
+                                       &mut font_char_set) != FcResultMatch ||
+                        FcCharSetHasChar(font_char_set, codepoint as FcChar32) == 0 {
+                    continue
+                }
+
+                let mut family: *mut FcChar8 = ptr::null_mut();
+                if FcPatternGetString(font, FC_FAMILY.as_ptr() as *mut i8, 0,
+                                      &mut family) == FcResultMatch {
+                    let family_name = string::raw::from_buf(family as *const i8 as *const u8);
+                    if !families.as_slice().contains(&family_name) {
+                        families.push(family_name);
+                    }
+                }
+            }
+            FcFontSetDestroy(font_set);
+        }
+
+        FcCharSetDestroy(char_set);
+        FcPatternDestroy(pattern);
+    }
+
+    debug!("fallback families for {}: {}", codepoint, families);
+    families
+}
+
 #[cfg(target_os="linux")]
 pub fn get_last_resort_font_families() -> Vec<String> {
     vec!(
@@ -15,7 +15,7 @@ use servo_util::geometry::{Au, px_to_pt};
 use servo_util::geometry;
 use platform::macos::font_context::FontContextHandle;
 use text::glyph::GlyphId;
-use style::computed_values::font_weight;
+use style::computed_values::{font_stretch, font_weight};
 use platform::font_template::FontTemplateData;
 
 use core_foundation::base::CFIndex;
@@ -110,6 +110,22 @@ impl FontHandleMethods for FontHandle {
         return font_weight::Weight900;
     }
 
+    fn stretchiness(&self) -> font_stretch::T {
+        // -1.0 to 1.0
+        let normalized = self.ctfont.all_traits().normalized_width();
+        // 0.0 to 9.0
+        let normalized = (normalized + 1.0) / 2.0 * 9.0;
+        if normalized < 1.0 { return font_stretch::ultra_condensed; }
+        if normalized < 2.0 { return font_stretch::extra_condensed; }
+        if normalized < 3.0 { return font_stretch::condensed; }
+        if normalized < 4.0 { return font_stretch::semi_condensed; }
+        if normalized < 5.0 { return font_stretch::normal; }
+        if normalized < 6.0 { return font_stretch::semi_expanded; }
+        if normalized < 7.0 { return font_stretch::expanded; }
+        if normalized < 8.0 { return font_stretch::extra_expanded; }
+        return font_stretch::ultra_expanded;
+    }
+
     fn glyph_index(&self, codepoint: char) -> Option<GlyphId> {
         let characters: [UniChar,  ..1] = [codepoint as UniChar];
         let mut glyphs: [CGGlyph, ..1] = [0 as CGGlyph];
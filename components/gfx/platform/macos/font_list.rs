@@ -6,6 +6,7 @@ use core_foundation::base::TCFType;
 use core_foundation::string::{CFString, CFStringRef};
 use core_text::font_descriptor::{CTFontDescriptor, CTFontDescriptorRef};
 use core_text;
+use std::ascii::AsciiExt;
 use std::mem;
 
 pub fn get_available_families(callback: |String|) {
@@ -37,10 +38,56 @@ pub fn get_variations_for_family(family_name: &str, callback: |String|) {
     }
 }
 
+/// Returns the PostScript name of the installed font with the given full name or PostScript name,
+/// which is how `local()` in `@font-face` rules names fonts.
+pub fn get_local_font(name: &str) -> Option<String> {
+    let family_names = core_text::font_collection::get_family_names();
+    for strref in family_names.iter() {
+        let family_name_ref: CFStringRef = unsafe { mem::transmute(strref) };
+        let family_name_cf: CFString = unsafe { TCFType::wrap_under_get_rule(family_name_ref) };
+        let family_name = family_name_cf.to_string();
+        let family_collection =
+            match core_text::font_collection::create_for_family(family_name.as_slice()) {
+                Some(family_collection) => family_collection,
+                None => continue,
+            };
+        for descref in family_collection.get_descriptors().iter() {
+            let descref: CTFontDescriptorRef = unsafe { mem::transmute(descref) };
+            let desc: CTFontDescriptor = unsafe { TCFType::wrap_under_get_rule(descref) };
+            let postscript_name = desc.font_name();
+            if postscript_name.as_slice().eq_ignore_ascii_case(name) ||
+                    desc.display_name().as_slice().eq_ignore_ascii_case(name) {
+                return Some(postscript_name)
+            }
+        }
+    }
+    None
+}
+
 pub fn get_system_default_family(_generic_name: &str) -> Option<String> {
     None
 }
 
+/// Returns the families to try, in order, when none of the fonts in a font group can render the
+/// given character.
+pub fn get_fallback_font_families(codepoint: char) -> Vec<String> {
+    let mut families = vec!();
+    match codepoint as u32 {
+        0x2600...0x27BF | 0x1F000...0x1F6FF => families.push("Apple Color Emoji".to_string()),
+        0x1100...0x11FF | 0x3130...0x318F | 0xAC00...0xD7AF => {
+            families.push("Apple SD Gothic Neo".to_string())
+        }
+        0x2E80...0x9FFF | 0xF900...0xFAFF | 0xFF00...0xFFEF | 0x20000...0x2FFFF => {
+            families.push("Hiragino Kaku Gothic ProN".to_string());
+            families.push("STHeiti".to_string());
+        }
+        _ => {}
+    }
+    families.push("Lucida Grande".to_string());
+    families.push("Arial Unicode MS".to_string());
+    families
+}
+
 pub fn get_last_resort_font_families() -> Vec<String> {
     vec!("Arial Unicode MS".to_string(), "Arial".to_string())
 }
@@ -13,6 +13,7 @@ use azure::{AZ_CAP_BUTT, AzFloat, struct__AzDrawOptions, struct__AzGlyph};
 use azure::{struct__AzGlyphBuffer, struct__AzPoint, AzDrawTargetFillGlyphs};
 use display_list::{SidewaysLeft, SidewaysRight, TextDisplayItem, Upright, BorderRadii};
 use font_context::FontContext;
+use image_animator::ImageFrames;
 use geom::matrix2d::Matrix2D;
 use geom::point::Point2D;
 use geom::rect::Rect;
@@ -40,6 +41,8 @@ pub struct RenderContext<'a> {
     pub page_rect: Rect<f32>,
     /// The rectangle that this context encompasses in screen coordinates (pixels).
     pub screen_rect: Rect<uint>,
+    /// The frames of the animated images that are shown.
+    pub image_frames: &'a ImageFrames,
 }
 
 enum Direction {
@@ -114,9 +117,9 @@ impl<'a> RenderContext<'a>  {
         self.draw_target.pop_clip();
     }
 
-    pub fn draw_image(&self, bounds: Rect<Au>, image: Arc<Box<Image>>) {
+    pub fn draw_image(&self, bounds: Rect<Au>, image: Arc<Box<Image>>, frame: uint) {
         let size = Size2D(image.width as i32, image.height as i32);
-        let (pixel_width, pixels, source_format) = match image.pixels {
+        let (pixel_width, pixels, source_format) = match image.frame(frame).pixels {
             RGBA8(ref pixels) => (4, pixels.as_slice(), B8G8R8A8),
             K8(ref pixels) => (1, pixels.as_slice(), A8),
             RGB8(_) => panic!("RGB8 color type not supported"),
@@ -8,6 +8,7 @@ use buffer_map::BufferMap;
 use display_list::{mod, StackingContext};
 use font_cache_task::FontCacheTask;
 use font_context::FontContext;
+use image_animator::{AnimatedImage, ImageAnimator, ImageFrames};
 use render_context::RenderContext;
 
 use azure::azure_hl::{B8G8R8A8, Color, DrawTarget, SkiaBackend, StolenGLResources};
@@ -23,6 +24,7 @@ use layers;
 use native::task::NativeTaskBuilder;
 use servo_msg::compositor_msg::{Epoch, IdleRenderState, LayerId};
 use servo_msg::compositor_msg::{LayerMetadata, RenderListener, RenderingRenderState, ScrollPolicy};
+use servo_msg::compositor_msg::SoftwareLayerBuffer;
 use servo_msg::constellation_msg::{ConstellationChan, Failure, FailureMsg, PipelineId};
 use servo_msg::constellation_msg::{RendererReadyMsg};
 use servo_msg::platform::surface::NativeSurfaceAzureMethods;
@@ -33,10 +35,15 @@ use servo_util::task::spawn_named_with_send_on_failure;
 use servo_util::task_state;
 use servo_util::time::{TimeProfilerChan, profile};
 use servo_util::time;
+use std::collections::HashMap;
+use std::collections::hash_map::{Occupied, Vacant};
 use std::comm::{Receiver, Sender, channel};
+use std::io::timer::Timer;
 use std::mem;
 use std::task::TaskBuilder;
+use std::time::duration::Duration;
 use sync::Arc;
+use time::precise_time_ns;
 
 /// Information about a hardware graphics layer that layout sends to the painting task.
 #[deriving(Clone)]
@@ -71,6 +78,8 @@ pub enum Msg {
     RenderInitMsg(Arc<StackingContext>),
     RenderMsg(Vec<RenderRequest>),
     UnusedBufferMsg(Vec<Box<LayerBuffer>>),
+    /// The part of the page that is visible, in page coordinates.
+    SetViewportMsg(Rect<f32>),
     PaintPermissionGranted,
     PaintPermissionRevoked,
     ExitMsg(Option<Sender<()>>),
@@ -122,6 +131,26 @@ pub struct RenderTask<C> {
 
     /// Communication handles to each of the worker threads.
     worker_threads: Vec<WorkerThreadProxy>,
+
+    /// The part of the page that the compositor shows, in page coordinates.
+    viewport: Option<Rect<f32>>,
+
+    /// The tiles painted for each layer in this epoch. Tiles that show animated images are
+    /// repainted from these when the images change frames.
+    painted_tiles: HashMap<LayerId, PaintedTiles>,
+
+    /// Plays the animated images that are visible.
+    image_animator: ImageAnimator,
+
+    /// The timer that wakes the render task when the next frame of an animated image is due.
+    animation_timer: Timer,
+    animation_tick: Option<Receiver<()>>,
+}
+
+/// The tiles of a layer that have been painted at a particular scale.
+struct PaintedTiles {
+    scale: f32,
+    tiles: Vec<BufferRequest>,
 }
 
 // If we implement this as a function, we get borrowck errors from borrowing
@@ -138,12 +167,13 @@ fn initialize_layers<C>(compositor: &mut C,
                         root_stacking_context: &StackingContext)
                         where C: RenderListener {
     let mut metadata = Vec::new();
-    build(&mut metadata, root_stacking_context, &ZERO_POINT);
+    build(&mut metadata, root_stacking_context, &ZERO_POINT, 1.0);
     compositor.initialize_layers_for_pipeline(pipeline_id, metadata, epoch);
 
     fn build(metadata: &mut Vec<LayerMetadata>,
              stacking_context: &StackingContext,
-             page_position: &Point2D<Au>) {
+             page_position: &Point2D<Au>,
+             opacity: AzFloat) {
         let page_position = stacking_context.bounds.origin + *page_position;
         match stacking_context.layer {
             None => {}
@@ -157,16 +187,69 @@ fn initialize_layers<C>(compositor: &mut C,
                                     stacking_context.bounds.size.height.to_nearest_px() as uint)),
                     background_color: render_layer.background_color,
                     scroll_policy: render_layer.scroll_policy,
+                    opacity: opacity as f32,
                 })
             }
         }
 
+        // Layers are painted separately from their ancestors, so they have to be composited with
+        // the opacity of the ancestors.
+        let opacity = opacity * stacking_context.opacity;
         for kid in stacking_context.display_list.children.iter() {
-            build(metadata, &**kid, &page_position)
+            build(metadata, &**kid, &page_position, opacity)
         }
     }
 }
 
+/// Finds the animated images in the parts of the layers of `root_stacking_context` that are in
+/// `viewport`, which is in page coordinates.
+fn find_visible_animated_images(root_stacking_context: &StackingContext, viewport: &Rect<AzFloat>)
+                                -> Vec<AnimatedImage> {
+    let mut result = Vec::new();
+    find(&mut result, root_stacking_context, &ZERO_POINT, viewport);
+    return result;
+
+    fn find(result: &mut Vec<AnimatedImage>,
+            stacking_context: &StackingContext,
+            page_position: &Point2D<Au>,
+            viewport: &Rect<AzFloat>) {
+        let page_position = stacking_context.bounds.origin + *page_position;
+        match stacking_context.layer {
+            None => {}
+            Some(ref render_layer) => {
+                // Tiles are in the coordinate system of the layer.
+                let layer_offset = Point2D(-page_position.x.to_nearest_px() as AzFloat,
+                                           -page_position.y.to_nearest_px() as AzFloat);
+                let visible_rect = viewport.translate(&layer_offset);
+                let mut images = Vec::new();
+                stacking_context.collect_animated_images(&visible_rect,
+                                                         &Point2D(0.0, 0.0),
+                                                         &mut images);
+                for (url, image, bounds) in images.into_iter() {
+                    result.push(AnimatedImage {
+                        layer_id: render_layer.id,
+                        bounds: bounds,
+                        url: url,
+                        image: image,
+                    })
+                }
+            }
+        }
+
+        for kid in stacking_context.display_list.children.iter() {
+            find(result, &**kid, &page_position, viewport)
+        }
+    }
+}
+
+fn copy_buffer_request(tile: &BufferRequest) -> BufferRequest {
+    BufferRequest {
+        screen_rect: tile.screen_rect,
+        page_rect: tile.page_rect,
+        content_age: tile.content_age,
+    }
+}
+
 impl<C> RenderTask<C> where C: RenderListener + Send {
     pub fn create(id: PipelineId,
                   port: Receiver<Msg>,
@@ -201,6 +284,11 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
                     epoch: Epoch(0),
                     buffer_map: BufferMap::new(10000000),
                     worker_threads: worker_threads,
+                    viewport: None,
+                    painted_tiles: HashMap::new(),
+                    image_animator: ImageAnimator::new(),
+                    animation_timer: Timer::new().unwrap(),
+                    animation_tick: None,
                 };
 
                 render_task.start();
@@ -226,10 +314,12 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
         debug!("render_task: beginning rendering loop");
 
         loop {
-            match self.port.recv() {
+            match self.receive() {
                 RenderInitMsg(stacking_context) => {
                     self.epoch.next();
                     self.root_stacking_context = Some(stacking_context.clone());
+                    self.painted_tiles.clear();
+                    self.update_visible_animated_images();
 
                     if !self.paint_permission {
                         debug!("render_task: render ready msg");
@@ -253,33 +343,52 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
                     }
 
                     let mut replies = Vec::new();
+                    let mut software_replies = Vec::new();
                     self.compositor.set_render_state(self.id, RenderingRenderState);
                     for RenderRequest { buffer_requests, scale, layer_id, epoch }
                           in requests.into_iter() {
-                        if self.epoch == epoch {
+                        if self.epoch != epoch {
+                            debug!("renderer epoch mismatch: {} != {}", self.epoch, epoch);
+                        } else if self.native_graphics_context.is_some() {
+                            self.remember_painted_tiles(layer_id.clone(),
+                                                        scale,
+                                                        buffer_requests.as_slice());
                             self.render(&mut replies, buffer_requests, scale, layer_id);
                         } else {
-                            debug!("renderer epoch mismatch: {} != {}", self.epoch, epoch);
+                            self.render_software(&mut software_replies,
+                                                 buffer_requests,
+                                                 scale,
+                                                 layer_id);
                         }
                     }
 
                     self.compositor.set_render_state(self.id, IdleRenderState);
 
                     debug!("render_task: returning surfaces");
-                    self.compositor.paint(self.id, self.epoch, replies);
+                    if self.native_graphics_context.is_some() {
+                        self.compositor.paint(self.id, self.epoch, replies);
+                    } else {
+                        self.compositor.paint_software(self.id, self.epoch, software_replies);
+                    }
                 }
                 UnusedBufferMsg(unused_buffers) => {
                     for buffer in unused_buffers.into_iter().rev() {
                         self.buffer_map.insert(native_graphics_context!(self), buffer);
                     }
                 }
+                SetViewportMsg(viewport) => {
+                    self.viewport = Some(viewport);
+                    self.update_visible_animated_images();
+                }
                 PaintPermissionGranted => {
                     self.paint_permission = true;
+                    self.update_visible_animated_images();
 
                     match self.root_stacking_context {
                         None => {}
                         Some(ref stacking_context) => {
                             self.epoch.next();
+                            self.painted_tiles.clear();
                             initialize_layers(&mut self.compositor,
                                               self.id,
                                               self.epoch,
@@ -289,6 +398,7 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
                 }
                 PaintPermissionRevoked => {
                     self.paint_permission = false;
+                    self.update_visible_animated_images();
                 }
                 ExitMsg(response_ch) => {
                     debug!("render_task: exitmsg response send");
@@ -299,6 +409,99 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
         }
     }
 
+    /// Waits for the next message, advancing animated images whenever their next frames are due
+    /// in the meantime.
+    fn receive(&mut self) -> Msg {
+        loop {
+            match self.animation_tick {
+                None => return self.port.recv(),
+                Some(ref tick) => {
+                    let port = &self.port;
+                    select! (
+                        msg = port.recv() => return msg,
+                        _ = tick.recv() => ()
+                    )
+                }
+            }
+            self.animate_images();
+        }
+    }
+
+    /// Works out which animated images are visible, and plays them. Animations only run when
+    /// painting into native surfaces; tiles painted into memory are for screenshots, which always
+    /// show the first frame.
+    fn update_visible_animated_images(&mut self) {
+        let images = match (&self.root_stacking_context, &self.viewport) {
+            (&Some(ref stacking_context), &Some(ref viewport))
+                    if self.paint_permission && self.native_graphics_context.is_some() => {
+                find_visible_animated_images(&**stacking_context, viewport)
+            }
+            _ => vec!(),
+        };
+        let now = precise_time_ns();
+        self.image_animator.set_visible_images(images, now);
+        self.schedule_animation_tick(now);
+    }
+
+    fn schedule_animation_tick(&mut self, now: u64) {
+        self.animation_tick = self.image_animator.next_frame_time().map(|frame_time| {
+            let delay = if frame_time > now { (frame_time - now) / 1_000_000 } else { 0 };
+            self.animation_timer.oneshot(Duration::milliseconds(delay as i64))
+        });
+    }
+
+    /// Moves animated images on to their next frames, and repaints the tiles that show them.
+    fn animate_images(&mut self) {
+        let now = precise_time_ns();
+        let areas = self.image_animator.advance(now);
+        self.schedule_animation_tick(now);
+        if areas.is_empty() {
+            return
+        }
+
+        let mut requests = vec!();
+        for (layer_id, painted_tiles) in self.painted_tiles.iter() {
+            let tiles: Vec<BufferRequest> = painted_tiles.tiles.iter().filter(|tile| {
+                areas.iter().any(|&(ref area_layer_id, ref area)| {
+                    area_layer_id == layer_id && tile.page_rect.intersects(area)
+                })
+            }).map(copy_buffer_request).collect();
+            if !tiles.is_empty() {
+                requests.push((layer_id.clone(), painted_tiles.scale, tiles));
+            }
+        }
+
+        let mut replies = Vec::new();
+        for (layer_id, scale, tiles) in requests.into_iter() {
+            self.render(&mut replies, tiles, scale, layer_id);
+        }
+        if !replies.is_empty() {
+            self.compositor.paint(self.id, self.epoch, replies);
+        }
+    }
+
+    /// Records the tiles that the compositor asked for, replacing earlier requests for the same
+    /// tiles.
+    fn remember_painted_tiles(&mut self, layer_id: LayerId, scale: f32, tiles: &[BufferRequest]) {
+        let painted_tiles = match self.painted_tiles.entry(layer_id) {
+            Occupied(entry) => entry.into_mut(),
+            Vacant(entry) => {
+                entry.set(PaintedTiles {
+                    scale: scale,
+                    tiles: vec!(),
+                })
+            }
+        };
+        if painted_tiles.scale != scale {
+            painted_tiles.scale = scale;
+            painted_tiles.tiles.clear();
+        }
+        for tile in tiles.iter() {
+            painted_tiles.tiles.retain(|painted_tile| painted_tile.screen_rect != tile.screen_rect);
+            painted_tiles.tiles.push(copy_buffer_request(tile));
+        }
+    }
+
     /// Retrieves an appropriately-sized layer buffer from the cache to match the requirements of
     /// the given tile, or creates one if a suitable one cannot be found.
     fn find_or_create_layer_buffer_for_tile(&mut self, tile: &BufferRequest, scale: f32)
@@ -363,6 +566,7 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
 
             // Divide up the layer into tiles and distribute them to workers via a simple round-
             // robin strategy.
+            let image_frames = self.image_animator.frames();
             let tiles = mem::replace(&mut tiles, Vec::new());
             let tile_count = tiles.len();
             for (i, tile) in tiles.into_iter().enumerate() {
@@ -371,6 +575,7 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
                 self.worker_threads[thread_id].paint_tile(tile,
                                                           layer_buffer,
                                                           stacking_context.clone(),
+                                                          image_frames.clone(),
                                                           scale);
             }
             let new_buffers = Vec::from_fn(tile_count, |i| {
@@ -384,6 +589,43 @@ impl<C> RenderTask<C> where C: RenderListener + Send {
             replies.push((layer_id, layer_buffer_set));
         })
     }
+
+    /// Renders one layer into main memory, for compositors without a graphics context.
+    fn render_software(&mut self,
+                       replies: &mut Vec<(LayerId, Vec<SoftwareLayerBuffer>)>,
+                       tiles: Vec<BufferRequest>,
+                       scale: f32,
+                       layer_id: LayerId) {
+        time::profile(time::PaintingCategory, None, self.time_profiler_chan.clone(), || {
+            // Bail out if there is no appropriate stacking context.
+            let stacking_context = match self.root_stacking_context {
+                Some(ref stacking_context) => {
+                    match display_list::find_stacking_context_with_layer_id(stacking_context,
+                                                                            layer_id) {
+                        Some(stacking_context) => stacking_context,
+                        None => return,
+                    }
+                }
+                None => return,
+            };
+
+            let image_frames = self.image_animator.frames();
+            let tile_count = tiles.len();
+            for (i, tile) in tiles.into_iter().enumerate() {
+                let thread_id = i % self.worker_threads.len();
+                self.worker_threads[thread_id].paint_tile(tile,
+                                                          None,
+                                                          stacking_context.clone(),
+                                                          image_frames.clone(),
+                                                          scale);
+            }
+            let new_buffers = Vec::from_fn(tile_count, |i| {
+                let thread_id = i % self.worker_threads.len();
+                self.worker_threads[thread_id].get_painted_software_tile_buffer()
+            });
+            replies.push((layer_id, new_buffers));
+        })
+    }
 }
 
 struct WorkerThreadProxy {
@@ -426,13 +668,28 @@ impl WorkerThreadProxy {
                   tile: BufferRequest,
                   layer_buffer: Option<Box<LayerBuffer>>,
                   stacking_context: Arc<StackingContext>,
+                  image_frames: Arc<ImageFrames>,
                   scale: f32) {
-        self.sender.send(PaintTileMsgToWorkerThread(tile, layer_buffer, stacking_context, scale))
+        self.sender.send(PaintTileMsgToWorkerThread(tile,
+                                                    layer_buffer,
+                                                    stacking_context,
+                                                    image_frames,
+                                                    scale))
     }
 
     fn get_painted_tile_buffer(&mut self) -> Box<LayerBuffer> {
         match self.receiver.recv() {
             PaintedTileMsgFromWorkerThread(layer_buffer) => layer_buffer,
+            PaintedSoftwareTileMsgFromWorkerThread(..) => {
+                panic!("expected a tile painted into a native surface")
+            }
+        }
+    }
+
+    fn get_painted_software_tile_buffer(&mut self) -> SoftwareLayerBuffer {
+        match self.receiver.recv() {
+            PaintedSoftwareTileMsgFromWorkerThread(buffer) => buffer,
+            PaintedTileMsgFromWorkerThread(..) => panic!("expected a tile painted into memory"),
         }
     }
 
@@ -471,8 +728,19 @@ impl WorkerThread {
         loop {
             match self.receiver.recv() {
                 ExitMsgToWorkerThread => break,
-                PaintTileMsgToWorkerThread(tile, layer_buffer, stacking_context, scale) => {
-                    let draw_target = self.optimize_and_paint_tile(&tile, stacking_context, scale);
+                PaintTileMsgToWorkerThread(tile, layer_buffer, stacking_context, image_frames,
+                                           scale) => {
+                    let draw_target = self.optimize_and_paint_tile(&tile,
+                                                                   stacking_context,
+                                                                   &*image_frames,
+                                                                   scale);
+                    if self.native_graphics_context.is_none() {
+                        let buffer = create_software_buffer_for_painted_tile(&tile,
+                                                                             draw_target,
+                                                                             scale);
+                        self.sender.send(PaintedSoftwareTileMsgFromWorkerThread(buffer));
+                        continue
+                    }
                     let buffer = self.create_layer_buffer_for_painted_tile(&tile,
                                                                            layer_buffer,
                                                                            draw_target,
@@ -486,10 +754,11 @@ impl WorkerThread {
     fn optimize_and_paint_tile(&mut self,
                                tile: &BufferRequest,
                                stacking_context: Arc<StackingContext>,
+                               image_frames: &ImageFrames,
                                scale: f32)
                                -> DrawTarget {
         let size = Size2D(tile.screen_rect.size.width as i32, tile.screen_rect.size.height as i32);
-        let draw_target = if !opts::get().gpu_painting {
+        let draw_target = if !opts::get().gpu_painting || self.native_graphics_context.is_none() {
             DrawTarget::new(SkiaBackend, size, B8G8R8A8)
         } else {
             // FIXME(pcwalton): Cache the components of draw targets (texture color buffer,
@@ -509,6 +778,7 @@ impl WorkerThread {
                 font_ctx: &mut self.font_context,
                 page_rect: tile.page_rect,
                 screen_rect: tile.screen_rect,
+                image_frames: image_frames,
             };
 
             // Apply the translation to render the tile we want.
@@ -582,11 +852,41 @@ impl WorkerThread {
     }
 }
 
+/// Copies the pixels of a painted tile out of its draw target. Rows of the draw target's surface
+/// may be padded, so they are copied one at a time to leave the buffer's rows tightly packed.
+fn create_software_buffer_for_painted_tile(tile: &BufferRequest,
+                                           draw_target: DrawTarget,
+                                           scale: f32)
+                                           -> SoftwareLayerBuffer {
+    let row_length = (tile.screen_rect.size.width * 4) as uint;
+    let height = tile.screen_rect.size.height as uint;
+    let surface = draw_target.snapshot().get_data_surface();
+    let surface_stride = surface.stride() as uint;
+    let mut data = Vec::with_capacity(row_length * height);
+    surface.with_data(|pixels| {
+        for row in pixels.chunks(surface_stride).take(height) {
+            data.push_all(row.slice_to(row_length));
+        }
+    });
+    SoftwareLayerBuffer {
+        rect: tile.page_rect,
+        screen_pos: tile.screen_rect,
+        resolution: scale,
+        stride: row_length,
+        data: data,
+    }
+}
+
 enum MsgToWorkerThread {
     ExitMsgToWorkerThread,
-    PaintTileMsgToWorkerThread(BufferRequest, Option<Box<LayerBuffer>>, Arc<StackingContext>, f32),
+    PaintTileMsgToWorkerThread(BufferRequest,
+                               Option<Box<LayerBuffer>>,
+                               Arc<StackingContext>,
+                               Arc<ImageFrames>,
+                               f32),
 }
 
 enum MsgFromWorkerThread {
     PaintedTileMsgFromWorkerThread(Box<LayerBuffer>),
+    PaintedSoftwareTileMsgFromWorkerThread(SoftwareLayerBuffer),
 }
@@ -0,0 +1,495 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Reading, writing, and sanitization of sfnt (TrueType and OpenType) font files.
+//!
+//! Fonts downloaded from the web are untrusted input that ends up in FreeType or Core Text, so
+//! before they are handed to the platform they are checked in the style of the OpenType Sanitizer:
+//! the table directory and the tables that every font needs in order to load are validated, and
+//! the font is rebuilt from the known tables only.
+
+use std::cmp;
+
+pub type Tag = u32;
+
+pub const TRUETYPE_FLAVOR: u32 = 0x00010000;
+pub const APPLE_TRUETYPE_FLAVOR: u32 = 0x74727565;    // 'true'
+pub const CFF_FLAVOR: u32 = 0x4F54544F;               // 'OTTO'
+pub const COLLECTION_FLAVOR: u32 = 0x74746366;        // 'ttcf'
+
+pub const CMAP: Tag = 0x636D6170;
+pub const GLYF: Tag = 0x676C7966;
+pub const HEAD: Tag = 0x68656164;
+pub const HHEA: Tag = 0x68686561;
+pub const HMTX: Tag = 0x686D7478;
+pub const LOCA: Tag = 0x6C6F6361;
+pub const MAXP: Tag = 0x6D617870;
+pub const CFF: Tag = 0x43464620;
+pub const GSUB: Tag = 0x47535542;
+
+/// The largest font that we are willing to decode.
+pub const MAX_FONT_SIZE: uint = 30 * 1024 * 1024;
+
+/// The magic number stored in the `head` table.
+const HEAD_MAGIC_NUMBER: u32 = 0x5F0F3CF5;
+
+/// The value that the checksum of a whole font must add up to.
+const FONT_CHECKSUM: u32 = 0xB1B0AFBA;
+
+const SFNT_HEADER_SIZE: uint = 12;
+const TABLE_RECORD_SIZE: uint = 16;
+
+/// The tables that survive sanitization. Anything else is dropped from the font. Only the tables
+/// that `check_tables` looks at are validated in depth; the layout, color and bitmap tables are
+/// kept so that fonts keep rendering, but are only checked to lie within the font.
+static SANITIZED_TABLES: &'static [&'static [u8]] = &[
+    b"BASE", b"CBDT", b"CBLC", b"CFF ", b"COLR", b"CPAL", b"EBDT", b"EBLC", b"EBSC", b"GDEF",
+    b"GPOS", b"GSUB", b"JSTF", b"LTSH", b"MATH", b"OS/2", b"VDMX", b"VORG", b"cmap", b"cvt ",
+    b"fpgm", b"gasp", b"glyf", b"hdmx", b"head", b"hhea", b"hmtx", b"kern", b"loca", b"maxp",
+    b"name", b"post", b"prep", b"sbix", b"vhea", b"vmtx",
+];
+
+/// Converts a four-byte tag name such as `b"glyf"` to a `Tag`.
+pub fn tag_from_bytes(bytes: &[u8]) -> Tag {
+    bytes.iter().fold(0, |tag, &byte| (tag << 8) | byte as u32)
+}
+
+/// A bounds-checked big-endian reader over a byte buffer.
+#[deriving(Clone)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+    position: uint,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader {
+            data: data,
+            position: 0,
+        }
+    }
+
+    #[inline]
+    pub fn position(&self) -> uint {
+        self.position
+    }
+
+    #[inline]
+    pub fn remaining(&self) -> uint {
+        self.data.len() - self.position
+    }
+
+    pub fn skip(&mut self, length: uint) -> Result<(), &'static str> {
+        if length > self.remaining() {
+            return Err("unexpected end of data")
+        }
+        self.position += length;
+        Ok(())
+    }
+
+    pub fn read_bytes(&mut self, length: uint) -> Result<&'a [u8], &'static str> {
+        if length > self.remaining() {
+            return Err("unexpected end of data")
+        }
+        let bytes = self.data.slice(self.position, self.position + length);
+        self.position += length;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, &'static str> {
+        let bytes = try!(self.read_bytes(1));
+        Ok(bytes[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, &'static str> {
+        let bytes = try!(self.read_bytes(2));
+        Ok((bytes[0] as u16 << 8) | bytes[1] as u16)
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, &'static str> {
+        Ok(try!(self.read_u16()) as i16)
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, &'static str> {
+        let bytes = try!(self.read_bytes(4));
+        Ok((bytes[0] as u32 << 24) | (bytes[1] as u32 << 16) | (bytes[2] as u32 << 8) |
+           bytes[3] as u32)
+    }
+}
+
+pub fn write_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.push((value >> 8) as u8);
+    buffer.push(value as u8);
+}
+
+pub fn write_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.push((value >> 24) as u8);
+    buffer.push((value >> 16) as u8);
+    buffer.push((value >> 8) as u8);
+    buffer.push(value as u8);
+}
+
+fn read_u16_at(data: &[u8], offset: uint) -> Result<u16, &'static str> {
+    let mut reader = Reader::new(data);
+    try!(reader.skip(offset));
+    reader.read_u16()
+}
+
+fn read_u32_at(data: &[u8], offset: uint) -> Result<u32, &'static str> {
+    let mut reader = Reader::new(data);
+    try!(reader.skip(offset));
+    reader.read_u32()
+}
+
+/// A single table of an sfnt file.
+pub struct Table {
+    pub tag: Tag,
+    pub data: Vec<u8>,
+}
+
+fn find_table<'a>(tables: &'a [Table], tag: Tag) -> Option<&'a Table> {
+    tables.iter().find(|table| table.tag == tag)
+}
+
+/// Computes the checksum of a table, as stored in the table directory.
+pub fn checksum(data: &[u8]) -> u32 {
+    let mut sum = 0u32;
+    for chunk in data.chunks(4) {
+        let mut value = 0u32;
+        for i in range(0, 4) {
+            value = (value << 8) | if i < chunk.len() { chunk[i] as u32 } else { 0 };
+        }
+        sum += value;
+    }
+    sum
+}
+
+#[inline]
+fn padded_length(length: uint) -> uint {
+    (length + 3) & !3
+}
+
+/// Assembles an sfnt file with the given flavor (`sfntVersion`) from a list of tables. The table
+/// checksums and the `checkSumAdjustment` field of the `head` table are recomputed.
+pub fn build(flavor: u32, mut tables: Vec<Table>) -> Vec<u8> {
+    tables.sort_by(|a, b| a.tag.cmp(&b.tag));
+
+    let num_tables = tables.len();
+    let mut entry_selector = 0u;
+    while (1u << (entry_selector + 1)) <= num_tables {
+        entry_selector += 1;
+    }
+    let search_range = (1u << entry_selector) * TABLE_RECORD_SIZE;
+
+    let directory_size = SFNT_HEADER_SIZE + num_tables * TABLE_RECORD_SIZE;
+    let total_size = tables.iter().fold(directory_size, |size, table| {
+        size + padded_length(table.data.len())
+    });
+
+    let mut font = Vec::with_capacity(total_size);
+    write_u32(&mut font, flavor);
+    write_u16(&mut font, num_tables as u16);
+    write_u16(&mut font, search_range as u16);
+    write_u16(&mut font, entry_selector as u16);
+    write_u16(&mut font, (num_tables * TABLE_RECORD_SIZE - search_range) as u16);
+
+    // The font checksum must be computed with the adjustment zeroed out.
+    for table in tables.iter_mut() {
+        if table.tag == HEAD && table.data.len() >= 12 {
+            for byte in table.data.slice_mut(8, 12).iter_mut() {
+                *byte = 0;
+            }
+        }
+    }
+
+    let mut offset = directory_size;
+    let mut head_offset = None;
+    for table in tables.iter() {
+        if table.tag == HEAD && table.data.len() >= 12 {
+            head_offset = Some(offset);
+        }
+        write_u32(&mut font, table.tag);
+        write_u32(&mut font, checksum(table.data.as_slice()));
+        write_u32(&mut font, offset as u32);
+        write_u32(&mut font, table.data.len() as u32);
+        offset += padded_length(table.data.len());
+    }
+
+    for table in tables.iter() {
+        font.push_all(table.data.as_slice());
+        for _ in range(table.data.len(), padded_length(table.data.len())) {
+            font.push(0);
+        }
+    }
+
+    match head_offset {
+        Some(head_offset) => {
+            let adjustment = FONT_CHECKSUM - checksum(font.as_slice());
+            let mut bytes = Vec::with_capacity(4);
+            write_u32(&mut bytes, adjustment);
+            font.as_mut_slice().slice_mut(head_offset + 8, head_offset + 12)
+                .clone_from_slice(bytes.as_slice());
+        }
+        None => {}
+    }
+
+    font
+}
+
+/// Validates an sfnt file and rebuilds it from the tables that the font engines are allowed to
+/// see. Returns an error describing the first problem found if the font is malformed.
+pub fn sanitize(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    if data.len() > MAX_FONT_SIZE {
+        return Err("font is too large")
+    }
+
+    let mut reader = Reader::new(data);
+    let flavor = try!(reader.read_u32());
+    if flavor == COLLECTION_FLAVOR {
+        return Err("font collections are not supported")
+    }
+    if flavor != TRUETYPE_FLAVOR && flavor != APPLE_TRUETYPE_FLAVOR && flavor != CFF_FLAVOR {
+        return Err("unknown sfnt version")
+    }
+
+    let num_tables = try!(reader.read_u16()) as uint;
+    if num_tables == 0 {
+        return Err("font has no tables")
+    }
+    try!(reader.skip(6));
+
+    let directory_size = SFNT_HEADER_SIZE + num_tables * TABLE_RECORD_SIZE;
+    let mut records: Vec<(Tag, uint, uint)> = Vec::with_capacity(num_tables);
+    for _ in range(0, num_tables) {
+        let tag = try!(reader.read_u32());
+        try!(reader.skip(4));
+        let offset = try!(reader.read_u32()) as uint;
+        let length = try!(reader.read_u32()) as uint;
+
+        if offset & 3 != 0 {
+            return Err("table is not aligned")
+        }
+        if offset < directory_size || offset > data.len() || length > data.len() - offset {
+            return Err("table is out of bounds")
+        }
+        if records.iter().any(|&(other_tag, _, _)| other_tag == tag) {
+            return Err("duplicate table")
+        }
+        records.push((tag, offset, length));
+    }
+
+    // Tables must not overlap one another.
+    let mut sorted_records = records.clone();
+    sorted_records.sort_by(|&(_, a, _), &(_, b, _)| a.cmp(&b));
+    for pair in sorted_records.windows(2) {
+        let (_, offset, length) = pair[0];
+        let (_, next_offset, _) = pair[1];
+        if offset + length > next_offset {
+            return Err("tables overlap")
+        }
+    }
+
+    let tables: Vec<Table> = records.into_iter().filter(|&(tag, _, _)| {
+        SANITIZED_TABLES.iter().any(|name| tag_from_bytes(*name) == tag)
+    }).map(|(tag, offset, length)| {
+        Table {
+            tag: tag,
+            data: data.slice(offset, offset + length).to_vec(),
+        }
+    }).collect();
+
+    try!(check_tables(flavor, tables.as_slice()));
+    Ok(build(flavor, tables))
+}
+
+/// Checks the tables that the font engines need in order to load the font at all.
+fn check_tables(flavor: u32, tables: &[Table]) -> Result<(), &'static str> {
+    let head = match find_table(tables, HEAD) {
+        Some(head) => head.data.as_slice(),
+        None => return Err("missing head table"),
+    };
+    if head.len() < 54 {
+        return Err("head table is too short")
+    }
+    if try!(read_u32_at(head, 12)) != HEAD_MAGIC_NUMBER {
+        return Err("bad head magic number")
+    }
+    let units_per_em = try!(read_u16_at(head, 18));
+    if units_per_em < 16 || units_per_em > 16384 {
+        return Err("bad unitsPerEm")
+    }
+    let index_to_loc_format = try!(read_u16_at(head, 50));
+    if index_to_loc_format > 1 {
+        return Err("bad indexToLocFormat")
+    }
+
+    let maxp = match find_table(tables, MAXP) {
+        Some(maxp) => maxp.data.as_slice(),
+        None => return Err("missing maxp table"),
+    };
+    let num_glyphs = try!(read_u16_at(maxp, 4)) as uint;
+    if num_glyphs == 0 {
+        return Err("font has no glyphs")
+    }
+
+    let hhea = match find_table(tables, HHEA) {
+        Some(hhea) => hhea.data.as_slice(),
+        None => return Err("missing hhea table"),
+    };
+    if hhea.len() < 36 {
+        return Err("hhea table is too short")
+    }
+    let num_h_metrics = try!(read_u16_at(hhea, 34)) as uint;
+    if num_h_metrics == 0 || num_h_metrics > num_glyphs {
+        return Err("bad numberOfHMetrics")
+    }
+
+    match find_table(tables, HMTX) {
+        Some(hmtx) if hmtx.data.len() >= num_h_metrics * 4 + (num_glyphs - num_h_metrics) * 2 => {}
+        Some(_) => return Err("hmtx table is too short"),
+        None => return Err("missing hmtx table"),
+    }
+
+    try!(check_cmap(match find_table(tables, CMAP) {
+        Some(cmap) => cmap.data.as_slice(),
+        None => return Err("missing cmap table"),
+    }));
+
+    if flavor == CFF_FLAVOR {
+        if find_table(tables, CFF).is_none() {
+            return Err("missing CFF table")
+        }
+        return Ok(())
+    }
+
+    match (find_table(tables, GLYF), find_table(tables, LOCA)) {
+        (Some(glyf), Some(loca)) => {
+            check_glyf_and_loca(glyf.data.as_slice(),
+                                loca.data.as_slice(),
+                                num_glyphs,
+                                index_to_loc_format == 1)
+        }
+        _ => Err("missing glyf or loca table"),
+    }
+}
+
+fn check_cmap(cmap: &[u8]) -> Result<(), &'static str> {
+    let mut reader = Reader::new(cmap);
+    try!(reader.skip(2));
+    let num_subtables = try!(reader.read_u16()) as uint;
+    if num_subtables == 0 {
+        return Err("cmap table has no subtables")
+    }
+    for _ in range(0, num_subtables) {
+        try!(reader.skip(4));
+        let offset = try!(reader.read_u32()) as uint;
+        // Every subtable starts with a 16-bit format and a length field.
+        if offset > cmap.len() || cmap.len() - offset < 4 {
+            return Err("cmap subtable is out of bounds")
+        }
+    }
+    Ok(())
+}
+
+fn check_glyf_and_loca(glyf: &[u8], loca: &[u8], num_glyphs: uint, long_offsets: bool)
+                       -> Result<(), &'static str> {
+    let mut reader = Reader::new(loca);
+    let mut last_offset = 0;
+    for i in range(0, num_glyphs + 1) {
+        let offset = if long_offsets {
+            try!(reader.read_u32()) as uint
+        } else {
+            try!(reader.read_u16()) as uint * 2
+        };
+        if offset < last_offset || offset > glyf.len() {
+            return Err("bad loca table")
+        }
+
+        if i > 0 && offset > last_offset {
+            let glyph = glyf.slice(last_offset, offset);
+            if glyph.len() < 10 {
+                return Err("glyph is too short")
+            }
+            if try!(read_u16_at(glyph, 0)) as i16 < -1 {
+                return Err("bad number of contours")
+            }
+        }
+        last_offset = cmp::max(last_offset, offset);
+    }
+    Ok(())
+}
+
+/// Returns the tags of the features in the feature list of a `GSUB` table, without duplicates.
+pub fn gsub_feature_tags(gsub: &[u8]) -> Result<Vec<Tag>, &'static str> {
+    let mut header = Reader::new(gsub);
+    try!(header.skip(6));   // version and ScriptList offset
+    let feature_list_offset = try!(header.read_u16()) as uint;
+
+    let mut reader = Reader::new(gsub);
+    try!(reader.skip(feature_list_offset));
+    let feature_count = try!(reader.read_u16());
+    let mut tags = vec!();
+    for _ in range(0, feature_count) {
+        let tag = try!(reader.read_u32());
+        try!(reader.skip(2));   // Feature offset
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HEAD, Table, TRUETYPE_FLAVOR, build, checksum, gsub_feature_tags, sanitize};
+    use super::tag_from_bytes;
+
+    #[test]
+    fn test_tag_from_bytes() {
+        assert_eq!(tag_from_bytes(b"head"), HEAD);
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(checksum(&[0, 0, 0, 1, 0, 0, 0, 2]), 3);
+        assert_eq!(checksum(&[1]), 0x01000000);
+    }
+
+    #[test]
+    fn test_build_pads_and_sorts_tables() {
+        let font = build(TRUETYPE_FLAVOR, vec!(
+            Table { tag: tag_from_bytes(b"name"), data: vec!(1, 2, 3) },
+            Table { tag: tag_from_bytes(b"cmap"), data: vec!(4) },
+        ));
+        assert_eq!(font.len(), 12 + 2 * 16 + 4 + 4);
+        assert_eq!(font.slice(12, 16), b"cmap");
+        assert_eq!(font.slice(28, 32), b"name");
+        assert_eq!(font.slice(44, 48), [4u8, 0, 0, 0].as_slice());
+    }
+
+    #[test]
+    fn test_sanitize_rejects_garbage() {
+        assert!(sanitize(&[]).is_err());
+        assert!(sanitize(b"not a font at all").is_err());
+        let font = build(TRUETYPE_FLAVOR, vec!(
+            Table { tag: tag_from_bytes(b"name"), data: vec!(1, 2, 3) },
+        ));
+        assert!(sanitize(font.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_gsub_feature_tags() {
+        let mut gsub = vec!(0, 1, 0, 0, 0, 10, 0, 10, 0, 0);
+        gsub.push_all(&[0, 3]);
+        gsub.push_all(b"liga");
+        gsub.push_all(&[0, 0]);
+        gsub.push_all(b"smcp");
+        gsub.push_all(&[0, 0]);
+        gsub.push_all(b"liga");
+        gsub.push_all(&[0, 0]);
+        assert_eq!(gsub_feature_tags(gsub.as_slice()),
+                   Ok(vec!(tag_from_bytes(b"liga"), tag_from_bytes(b"smcp"))));
+        assert!(gsub_feature_tags(gsub.slice_to(14)).is_err());
+    }
+}
@@ -87,7 +87,7 @@ impl GlyphEntry {
 pub type GlyphId = u32;
 
 // TODO: unify with bit flags?
-#[deriving(PartialEq)]
+#[deriving(Clone, PartialEq, Show)]
 pub enum BreakType {
     BreakTypeNone,
     BreakTypeNormal,
@@ -0,0 +1,415 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Line break opportunities, as computed by the Unicode Line Breaking Algorithm (UAX #14).
+//!
+//! See http://www.unicode.org/reports/tr14/. This is the pair table based implementation that the
+//! specification describes in section 7.3. The class table below covers the scripts and
+//! punctuation that show up in practice; characters it doesn't know about are treated as `AL`,
+//! which is what the specification recommends for unassigned code points anyway.
+
+use text::glyph::{BreakType, BreakTypeHyphen, BreakTypeNone, BreakTypeNormal};
+
+use style::computed_values::word_break;
+
+/// U+00AD SOFT HYPHEN, which marks a place where a word may be hyphenated.
+pub static SOFT_HYPHEN: char = '\u00ad';
+
+/// The line breaking classes of UAX #14, section 5.1.
+///
+/// The classes that appear in the pair table come first, in table order, so that they can be
+/// used directly as table indices.
+#[allow(non_camel_case_types)]
+#[deriving(Clone, PartialEq, Show)]
+pub enum LineBreakClass {
+    OP, CL, CP, QU, GL, NS, EX, SY, IS, PR, PO, NU, AL, HL, ID, IN, HY, BA, BB, B2, ZW, CM, WJ,
+    H2, H3, JL, JV, JT, RI,
+
+    // Classes that are resolved to one of the above, or that are handled outside the pair table.
+    AI, BK, CB, CJ, CR, LF, NL, SA, SG, SP, XX,
+}
+
+/// The pair table from UAX #14, section 7.3, indexed by the class before and after the potential
+/// break. The entries are:
+///
+///   * `_`: direct break opportunity.
+///   * `%`: indirect break opportunity; break only if spaces separate the two classes.
+///   * `#`: indirect break opportunity for a combining mark following a space.
+///   * `@`: prohibited break for a combining mark following a space.
+///   * `^`: prohibited break.
+static PAIR_TABLE: [&'static [u8], ..29] = [
+    //  OP CL CP QU GL NS EX SY IS PR PO NU AL HL ID IN HY BA BB B2 ZW CM WJ H2 H3 JL JV JT RI
+    b"^^^^^^^^^^^^^^^^^^^^^@^^^^^^^", // OP
+    b"_^^%%^^^^%%_____%%__^#^______", // CL
+    b"_^^%%^^^^%%%%%__%%__^#^______", // CP
+    b"^^^%%%^^^%%%%%%%%%%%^#^%%%%%%", // QU
+    b"%^^%%%^^^%%%%%%%%%%%^#^%%%%%%", // GL
+    b"_^^%%%^^^_______%%__^#^______", // NS
+    b"_^^%%%^^^______%%%__^#^______", // EX
+    b"_^^%%%^^^__%_%__%%__^#^______", // SY
+    b"_^^%%%^^^__%%%__%%__^#^______", // IS
+    b"%^^%%%^^^__%%%%_%%__^#^%%%%%_", // PR
+    b"%^^%%%^^^__%%%__%%__^#^______", // PO
+    b"%^^%%%^^^%%%%%_%%%__^#^______", // NU
+    b"%^^%%%^^^%%%%%_%%%__^#^______", // AL
+    b"%^^%%%^^^%%%%%_%%%__^#^______", // HL
+    b"_^^%%%^^^_%____%%%__^#^______", // ID
+    b"_^^%%%^^^______%%%__^#^______", // IN
+    b"_^^%_%^^^__%____%%__^#^______", // HY
+    b"_^^%_%^^^_______%%__^#^______", // BA
+    b"%^^%%%^^^%%%%%%%%%%%^#^%%%%%%", // BB
+    b"_^^%%%^^^_______%%_^^#^______", // B2
+    b"____________________^________", // ZW
+    b"%^^%%%^^^%%%%%_%%%__^#^______", // CM
+    b"%^^%%%^^^%%%%%%%%%%%^#^%%%%%%", // WJ
+    b"_^^%%%^^^_%____%%%__^#^___%%_", // H2
+    b"_^^%%%^^^_%____%%%__^#^____%_", // H3
+    b"_^^%%%^^^_%____%%%__^#^%%%%__", // JL
+    b"_^^%%%^^^_%____%%%__^#^___%%_", // JV
+    b"_^^%%%^^^_%____%%%__^#^____%_", // JT
+    b"_^^%%%^^^_______%%__^#^_____%", // RI
+];
+
+/// Returns the line breaking class of the given character, as listed in `LineBreak.txt`.
+pub fn line_break_class(ch: char) -> LineBreakClass {
+    match ch as u32 {
+        // Basic Latin and Latin-1 Supplement.
+        0x09 => BA,
+        0x0A => LF,
+        0x0B...0x0C => BK,
+        0x0D => CR,
+        0x00...0x1F => CM,
+        0x20 => SP,
+        0x21 => EX,
+        0x22 | 0x27 => QU,
+        0x24 | 0x2B | 0x5C => PR,
+        0x25 => PO,
+        0x28 | 0x5B | 0x7B => OP,
+        0x29 | 0x5D => CP,
+        0x2C | 0x2E | 0x3A | 0x3B => IS,
+        0x2D => HY,
+        0x2F => SY,
+        0x30...0x39 => NU,
+        0x3F => EX,
+        0x7C => BA,
+        0x7D => CL,
+        0x85 => NL,
+        0x7F...0x9F => CM,
+        0xA0 => GL,
+        0xA1 | 0xBF => OP,
+        0xA2 | 0xB0 => PO,
+        0xA3...0xA5 | 0xB1 => PR,
+        0xAB | 0xBB => QU,
+        0xAD => BA,
+        0xB4 => BB,
+        0xA7 | 0xA8 | 0xAA | 0xB2 | 0xB3 | 0xB6...0xBA | 0xBC...0xBE | 0xD7 | 0xF7 => AI,
+
+        // Spacing modifier letters and combining diacritical marks.
+        0x2C8 | 0x2CC | 0x2DF => BB,
+        0x34F => GL,
+        0x300...0x36F => CM,
+        0x483...0x489 => CM,
+
+        // Hebrew.
+        0x591...0x5BD | 0x5BF | 0x5C1...0x5C2 | 0x5C4...0x5C5 | 0x5C7 => CM,
+        0x5BE => BA,
+        0x5D0...0x5EA => HL,
+
+        // Arabic.
+        0x610...0x61A | 0x64B...0x65F | 0x670 | 0x6D6...0x6DC | 0x6DF...0x6E4 => CM,
+        0x660...0x669 | 0x66B...0x66C | 0x6F0...0x6F9 => NU,
+        0x66A => PO,
+
+        // Devanagari and the other Indic scripts use the same layout of marks and digits.
+        0x900...0x903 | 0x93A...0x93C | 0x93E...0x94F | 0x951...0x957 | 0x962...0x963 => CM,
+        0x964...0x965 => BA,
+        0x966...0x96F => NU,
+
+        // Thai, Lao, Myanmar and Khmer, which need dictionary-based breaking.
+        0xE31 | 0xE34...0xE3A | 0xE47...0xE4E => CM,
+        0xE50...0xE59 => NU,
+        0xE5A...0xE5B => BA,
+        0xEB1 | 0xEB4...0xEBC | 0xEC8...0xECD => CM,
+        0xED0...0xED9 => NU,
+        0xE00...0xEFF => SA,
+        0x1000...0x109F => SA,
+        0x17D4...0x17D5 => BA,
+        0x1780...0x17FF => SA,
+
+        // Hangul Jamo.
+        0x1100...0x115F | 0xA960...0xA97C => JL,
+        0x1160...0x11A7 | 0xD7B0...0xD7C6 => JV,
+        0x11A8...0x11FF | 0xD7CB...0xD7FB => JT,
+
+        // Other spaces and combining marks.
+        0x1680 => BA,
+        0x1AB0...0x1AFF | 0x1DC0...0x1DFF => CM,
+
+        // General Punctuation.
+        0x2007 | 0x2011 | 0x202F => GL,
+        0x2000...0x200A => BA,
+        0x200B => ZW,
+        0x200C...0x200F | 0x202A...0x202E | 0x2066...0x206F => CM,
+        0x2010 | 0x2012 | 0x2013 | 0x2027 => BA,
+        0x2014 => B2,
+        0x2015 | 0x2016 | 0x2020 | 0x2021 => AI,
+        0x2018 | 0x2019 | 0x201B...0x201D | 0x201F | 0x2039 | 0x203A => QU,
+        0x201A | 0x201E | 0x2045 | 0x207D | 0x208D => OP,
+        0x2024...0x2026 => IN,
+        0x2028 | 0x2029 => BK,
+        0x2030...0x2037 => PO,
+        0x203C | 0x203D | 0x2047...0x2049 => NS,
+        0x2044 => IS,
+        0x2046 | 0x207E | 0x208E => CL,
+        0x2060 => WJ,
+
+        // Currency symbols, letterlike symbols and combining marks for symbols.
+        0x20A7 | 0x20B6 | 0x20BB => PO,
+        0x20A0...0x20CF => PR,
+        0x20D0...0x20F0 => CM,
+        0x2103 | 0x2109 => PO,
+        0x2116 | 0x2212 | 0x2213 => PR,
+
+        // CJK Symbols and Punctuation.
+        0x3000 => BA,
+        0x3001 | 0x3002 | 0x3009 | 0x300B | 0x300D | 0x300F | 0x3011 | 0x3015 | 0x3017 |
+        0x3019 | 0x301B | 0x301E | 0x301F => CL,
+        0x3008 | 0x300A | 0x300C | 0x300E | 0x3010 | 0x3014 | 0x3016 | 0x3018 | 0x301A |
+        0x301D => OP,
+        0x3005 | 0x301C | 0x303B | 0x303C => NS,
+        0x302A...0x302F => CM,
+
+        // Hiragana and Katakana. Small kana are `CJ`.
+        0x3041 | 0x3043 | 0x3045 | 0x3047 | 0x3049 | 0x3063 | 0x3083 | 0x3085 | 0x3087 |
+        0x308E | 0x3095 | 0x3096 | 0x30A1 | 0x30A3 | 0x30A5 | 0x30A7 | 0x30A9 | 0x30C3 |
+        0x30E3 | 0x30E5 | 0x30E7 | 0x30EE | 0x30F5 | 0x30F6 | 0x30FC | 0x31F0...0x31FF => CJ,
+        0x3099 | 0x309A => CM,
+        0x309B...0x309E | 0x30A0 | 0x30FB | 0x30FD | 0x30FE => NS,
+
+        // Hangul syllables are `H2` for LV syllables and `H3` for LVT syllables.
+        0xAC00...0xD7A3 => {
+            if (ch as u32 - 0xAC00) % 28 == 0 {
+                H2
+            } else {
+                H3
+            }
+        }
+
+        // Ideographs, kana, Yi and the other East Asian wide characters.
+        0x2E80...0x2FFF | 0x3003...0x4DBF | 0x4E00...0x9FFF | 0xA000...0xA4CF |
+        0xF900...0xFAFF => ID,
+
+        // Variation selectors and vertical forms.
+        0xFE00...0xFE0F | 0xFE20...0xFE2F => CM,
+        0xFE10 | 0xFE13 | 0xFE14 => IS,
+        0xFE11 | 0xFE12 | 0xFE18 | 0xFE50 | 0xFE52 | 0xFE5A | 0xFE5C | 0xFE5E => CL,
+        0xFE15 | 0xFE16 | 0xFE56 | 0xFE57 => EX,
+        0xFE17 | 0xFE59 | 0xFE5B | 0xFE5D => OP,
+        0xFE19 => IN,
+        0xFE54 | 0xFE55 => NS,
+        0xFE30...0xFE6B => ID,
+        0xFEFF => WJ,
+
+        // Halfwidth and Fullwidth Forms.
+        0xFF01 | 0xFF1F => EX,
+        0xFF04 | 0xFFE1 | 0xFFE5 | 0xFFE6 => PR,
+        0xFF05 | 0xFFE0 => PO,
+        0xFF08 | 0xFF3B | 0xFF5B | 0xFF5F | 0xFF62 => OP,
+        0xFF09 | 0xFF0C | 0xFF0E | 0xFF3D | 0xFF5D | 0xFF60 | 0xFF61 | 0xFF63 | 0xFF64 => CL,
+        0xFF1A | 0xFF1B | 0xFF65 => NS,
+        0xFF00...0xFF60 | 0xFFE2...0xFFE4 => ID,
+        0xFFFC => CB,
+        0xFFFD => AI,
+
+        // Regional indicators, emoji and the supplementary ideographic planes.
+        0x1F1E6...0x1F1FF => RI,
+        0x1F3FB...0x1F3FF => CM,
+        0x1F000...0x1F0FF | 0x1F200...0x1F2FF | 0x1F300...0x1F64F | 0x1F680...0x1F6FF => ID,
+        0x20000...0x2FFFD | 0x30000...0x3FFFD => ID,
+        0xE0001...0xE01EF => CM,
+
+        _ => XX,
+    }
+}
+
+/// Resolves the classes that UAX #14 section 6.1 (rule LB1) maps onto other classes, and applies
+/// the tailoring that the `word-break` property asks for.
+fn resolve_class(ch: char, word_break: word_break::T) -> LineBreakClass {
+    let class = match line_break_class(ch) {
+        AI | SG | XX | SA => AL,
+        CJ => NS,
+        CB => BA,
+        class => class,
+    };
+
+    // See http://dev.w3.org/csswg/css-text/#word-break-property.
+    match (word_break, class) {
+        (word_break::break_all, AL) | (word_break::break_all, HL) |
+        (word_break::break_all, NU) => ID,
+        (word_break::keep_all, ID) | (word_break::keep_all, H2) | (word_break::keep_all, H3) |
+        (word_break::keep_all, JL) | (word_break::keep_all, JV) |
+        (word_break::keep_all, JT) => AL,
+        (_, class) => class,
+    }
+}
+
+/// Returns the class to use for the first character of a line. See rules LB2 and LB10.
+fn start_of_line_class(class: LineBreakClass) -> LineBreakClass {
+    match class {
+        LF | NL => BK,
+        SP => WJ,
+        CM => AL,
+        class => class,
+    }
+}
+
+/// Computes the line break opportunities in `text`.
+///
+/// The returned vector has one entry per character of `text`, giving the kind of break that may
+/// occur *before* that character. Breaks after a soft hyphen are reported as `BreakTypeHyphen`,
+/// so that a hyphen can be rendered at the end of the line. Mandatory breaks are reported as
+/// ordinary break opportunities, since newlines are handled by layout itself.
+pub fn break_opportunities(text: &str, word_break: word_break::T) -> Vec<BreakType> {
+    let mut result = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+    let mut prev_char = match chars.next() {
+        None => return result,
+        Some(ch) => ch,
+    };
+    result.push(BreakTypeNone);
+
+    let mut last_class = resolve_class(prev_char, word_break);
+    let mut cur_class = start_of_line_class(last_class);
+    for ch in chars {
+        let next_class = resolve_class(ch, word_break);
+
+        let can_break = if cur_class == BK || (cur_class == CR && next_class != LF) {
+            // Rules LB4 and LB5: always break after hard line breaks.
+            cur_class = start_of_line_class(next_class);
+            true
+        } else {
+            match next_class {
+                // Rule LB7: don't break before spaces; remember that we saw them instead.
+                SP => false,
+                // Rule LB6: don't break before hard line breaks.
+                BK | LF | NL => {
+                    cur_class = BK;
+                    false
+                }
+                CR => {
+                    cur_class = CR;
+                    false
+                }
+                _ => {
+                    match PAIR_TABLE[cur_class as uint][next_class as uint] {
+                        b'_' => {
+                            cur_class = next_class;
+                            true
+                        }
+                        b'%' => {
+                            cur_class = next_class;
+                            last_class == SP
+                        }
+                        b'#' => {
+                            // A combining mark takes on the class of its base character (rule
+                            // LB9), unless it follows a space (rule LB10).
+                            if last_class == SP {
+                                cur_class = next_class;
+                                true
+                            } else {
+                                false
+                            }
+                        }
+                        b'@' => {
+                            if last_class == SP {
+                                cur_class = next_class;
+                            }
+                            false
+                        }
+                        _ => {
+                            cur_class = next_class;
+                            false
+                        }
+                    }
+                }
+            }
+        };
+
+        result.push(match (can_break, prev_char == SOFT_HYPHEN) {
+            (false, _) => BreakTypeNone,
+            (true, false) => BreakTypeNormal,
+            (true, true) => BreakTypeHyphen,
+        });
+
+        last_class = next_class;
+        prev_char = ch;
+    }
+
+    result
+}
+
+#[cfg(test)]
+fn break_positions(text: &str, word_break: word_break::T) -> Vec<uint> {
+    break_opportunities(text, word_break).iter().enumerate().filter_map(|(i, break_type)| {
+        if *break_type == BreakTypeNone {
+            None
+        } else {
+            Some(i)
+        }
+    }).collect()
+}
+
+#[test]
+fn test_break_after_spaces() {
+    assert_eq!(break_positions("foo bar  baz", word_break::normal), vec!(4, 9));
+    assert_eq!(break_positions(" foo", word_break::normal), vec!(1));
+    assert_eq!(break_positions("foo ", word_break::normal), vec!());
+}
+
+#[test]
+fn test_no_break_around_punctuation() {
+    assert_eq!(break_positions("(foo) bar.", word_break::normal), vec!(6));
+    assert_eq!(break_positions("\"foo\" bar", word_break::normal), vec!(6));
+    assert_eq!(break_positions("( foo", word_break::normal), vec!());
+    assert_eq!(break_positions("100%", word_break::normal), vec!());
+    assert_eq!(break_positions("a\u00a0b", word_break::normal), vec!());
+}
+
+#[test]
+fn test_break_after_hyphens_and_slashes() {
+    assert_eq!(break_positions("well-known", word_break::normal), vec!(5));
+    assert_eq!(break_positions("-1", word_break::normal), vec!());
+    assert_eq!(break_positions("http://example.com/a", word_break::normal), vec!(7, 19));
+    assert_eq!(break_positions("a\u200bb", word_break::normal), vec!(2));
+}
+
+#[test]
+fn test_cjk() {
+    assert_eq!(break_positions("日本語", word_break::normal), vec!(1, 2));
+    assert_eq!(break_positions("日本。語", word_break::normal), vec!(1, 3));
+    assert_eq!(break_positions("「日本」", word_break::normal), vec!(2));
+    assert_eq!(break_positions("ちょっと", word_break::normal), vec!(3));
+    assert_eq!(break_positions("한국어", word_break::normal), vec!(1, 2));
+}
+
+#[test]
+fn test_word_break() {
+    assert_eq!(break_positions("abc", word_break::break_all), vec!(1, 2));
+    assert_eq!(break_positions("ab.", word_break::break_all), vec!(1));
+    assert_eq!(break_positions("日本語", word_break::keep_all), vec!());
+    assert_eq!(break_positions("日本 語", word_break::keep_all), vec!(3));
+}
+
+#[test]
+fn test_soft_hyphen() {
+    let breaks = break_opportunities("hy\u00adphen", word_break::normal);
+    assert!(breaks[3] == BreakTypeHyphen);
+    assert_eq!(break_positions("hy\u00adphen", word_break::normal), vec!(3));
+}
+
+#[test]
+fn test_mandatory_breaks() {
+    assert_eq!(break_positions("a\nb", word_break::normal), vec!(2));
+    assert_eq!(break_positions("a\r\nb", word_break::normal), vec!(3));
+}
@@ -12,6 +12,7 @@ pub use text::shaping::Shaper;
 pub use text::text_run::TextRun;
 
 pub mod glyph;
+pub mod line_break;
 #[path="shaping/mod.rs"] pub mod shaping;
 pub mod text_run;
 pub mod util;
@@ -7,7 +7,7 @@ extern crate harfbuzz;
 use font::{Font, FontHandleMethods, FontTableMethods, FontTableTag};
 use platform::font::FontTable;
 use text::glyph::{CharIndex, GlyphStore, GlyphId, GlyphData};
-use text::shaping::ShaperMethods;
+use text::shaping::{ShaperMethods, ShapingOptions};
 use text::util::{float_to_fixed, fixed_to_float};
 
 use geom::Point2D;
@@ -18,9 +18,9 @@ use harfbuzz::{hb_bool_t};
 use harfbuzz::{hb_buffer_add_utf8};
 use harfbuzz::{hb_buffer_destroy};
 use harfbuzz::{hb_buffer_get_glyph_positions};
-use harfbuzz::{hb_buffer_set_direction};
+use harfbuzz::{hb_buffer_set_direction, hb_buffer_set_language};
 use harfbuzz::{hb_face_destroy};
-use harfbuzz::{hb_face_t, hb_font_t};
+use harfbuzz::{hb_face_t, hb_feature_t, hb_font_t};
 use harfbuzz::{hb_font_create};
 use harfbuzz::{hb_font_destroy, hb_buffer_create};
 use harfbuzz::{hb_font_funcs_create};
@@ -34,6 +34,7 @@ use harfbuzz::{hb_font_set_ppem};
 use harfbuzz::{hb_font_set_scale};
 use harfbuzz::{hb_glyph_info_t};
 use harfbuzz::{hb_glyph_position_t};
+use harfbuzz::{hb_language_from_string};
 use harfbuzz::{hb_position_t, hb_tag_t};
 use harfbuzz::{hb_shape, hb_buffer_get_glyph_infos};
 use libc::{c_uint, c_int, c_void, c_char};
@@ -200,18 +201,42 @@ impl Shaper {
 impl ShaperMethods for Shaper {
     /// Calculate the layout metrics associated with the given text when rendered in a specific
     /// font.
-    fn shape_text(&self, text: &str, glyphs: &mut GlyphStore) {
+    fn shape_text(&self, text: &str, options: &ShapingOptions, glyphs: &mut GlyphStore) {
         unsafe {
             let hb_buffer: *mut hb_buffer_t = hb_buffer_create();
             hb_buffer_set_direction(hb_buffer, HB_DIRECTION_LTR);
 
+            match options.language {
+                Some(ref language) => {
+                    // HarfBuzz takes BCP 47 language tags; an OpenType language system tag can be
+                    // given as a private use subtag.
+                    let language = format!("x-hbot{:<4s}", language.as_slice());
+                    hb_buffer_set_language(hb_buffer,
+                                           hb_language_from_string(
+                                               language.as_ptr() as *const c_char,
+                                               language.len() as c_int));
+                }
+                None => {}
+            }
+
             hb_buffer_add_utf8(hb_buffer,
                                text.as_ptr() as *const c_char,
                                text.len() as c_int,
                                0,
                                text.len() as c_int);
 
-            hb_shape(self.hb_font, hb_buffer, ptr::null_mut(), 0);
+            let features: Vec<hb_feature_t> = options.features.iter().map(|feature| {
+                hb_feature_t {
+                    tag: feature.tag as hb_tag_t,
+                    value: feature.value,
+                    start: 0,
+                    end: !0,
+                }
+            }).collect();
+            hb_shape(self.hb_font,
+                     hb_buffer,
+                     features.as_ptr() as *mut hb_feature_t,
+                     features.len() as c_uint);
             self.save_glyph_results(text, glyphs, hb_buffer);
             hb_buffer_destroy(hb_buffer);
         }
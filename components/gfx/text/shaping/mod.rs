@@ -7,6 +7,10 @@
 //!
 //! Currently, only harfbuzz bindings are implemented.
 
+use font::{Font, FontTableTag};
+use sfnt::tag_from_bytes;
+use style::computed_values::{font_kerning, font_variant, font_variant_caps};
+use style::style_structs::Font as FontStyle;
 use text::glyph::GlyphStore;
 
 pub use text::shaping::harfbuzz::Shaper;
@@ -14,6 +18,115 @@ pub use text::shaping::harfbuzz::Shaper;
 pub mod harfbuzz;
 
 pub trait ShaperMethods {
-    fn shape_text(&self, text: &str, glyphs: &mut GlyphStore);
+    fn shape_text(&self, text: &str, options: &ShapingOptions, glyphs: &mut GlyphStore);
 }
 
+/// An OpenType feature to turn on or off, or to set to a particular alternate, while shaping.
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
+pub struct ShapingFeature {
+    pub tag: FontTableTag,
+    pub value: u32,
+}
+
+/// The settings that text is shaped with, other than the font itself. Text shaped with different
+/// options yields different glyphs, so fonts cache shaped text by its options as well.
+#[deriving(Clone, PartialEq, Eq, Hash, Show)]
+pub struct ShapingOptions {
+    /// The features to apply, in order; later settings of a feature override earlier ones.
+    pub features: Vec<ShapingFeature>,
+    /// The OpenType language system to shape with, from `font-language-override`.
+    pub language: Option<String>,
+}
+
+impl ShapingOptions {
+    /// Shapes with the font's default features.
+    pub fn new() -> ShapingOptions {
+        ShapingOptions {
+            features: vec!(),
+            language: None,
+        }
+    }
+
+    /// Returns the options that text in the given style is shaped with when rendered in `font`.
+    /// The small capital features are only requested if the font has them; otherwise layout
+    /// synthesizes small capitals with a scaled-down copy of the font.
+    pub fn for_style(style: &FontStyle, font: &Font) -> ShapingOptions {
+        let mut options = ShapingOptions::new();
+
+        match style.font_kerning {
+            font_kerning::auto => {}
+            font_kerning::normal => options.push(b"kern", 1),
+            font_kerning::none => options.push(b"kern", 0),
+        }
+
+        let ligatures = &style.font_variant_ligatures;
+        for enabled in ligatures.common_ligatures.iter() {
+            options.push(b"liga", *enabled as u32);
+            options.push(b"clig", *enabled as u32);
+        }
+        for enabled in ligatures.discretionary_ligatures.iter() {
+            options.push(b"dlig", *enabled as u32);
+        }
+        for enabled in ligatures.historical_ligatures.iter() {
+            options.push(b"hlig", *enabled as u32);
+        }
+        for enabled in ligatures.contextual.iter() {
+            options.push(b"calt", *enabled as u32);
+        }
+
+        let numeric = &style.font_variant_numeric;
+        let numeric_features = [
+            (numeric.lining_nums, b"lnum"),
+            (numeric.oldstyle_nums, b"onum"),
+            (numeric.proportional_nums, b"pnum"),
+            (numeric.tabular_nums, b"tnum"),
+            (numeric.diagonal_fractions, b"frac"),
+            (numeric.stacked_fractions, b"afrc"),
+            (numeric.ordinal, b"ordn"),
+            (numeric.slashed_zero, b"zero"),
+        ];
+        for &(enabled, tag) in numeric_features.iter() {
+            if enabled {
+                options.push(tag, 1);
+            }
+        }
+
+        if font.variant == font_variant::normal {
+            for tag in font.caps_features(caps_for_style(style)).unwrap_or(vec!()).into_iter() {
+                options.features.push(ShapingFeature {
+                    tag: tag,
+                    value: 1,
+                });
+            }
+        }
+
+        for setting in style.font_feature_settings.iter() {
+            options.push(setting.tag.as_bytes(), setting.value);
+        }
+
+        options.language = match style.font_language_override {
+            Some(ref language) if language.len() > 0 && language.len() <= 4 &&
+                    language.as_slice().chars().all(|c| c > ' ' && c <= '~') => {
+                Some(language.clone())
+            }
+            _ => None,
+        };
+        options
+    }
+
+    fn push(&mut self, tag: &[u8], value: u32) {
+        self.features.push(ShapingFeature {
+            tag: tag_from_bytes(tag),
+            value: value,
+        });
+    }
+}
+
+/// Returns the capitalization that text in the given style is rendered with. The CSS 2.1
+/// `font-variant: small-caps` is equivalent to `font-variant-caps: small-caps`.
+pub fn caps_for_style(style: &FontStyle) -> font_variant_caps::T {
+    match (style.font_variant_caps, style.font_variant) {
+        (font_variant_caps::normal, font_variant::small_caps) => font_variant_caps::small_caps,
+        (caps, _) => caps,
+    }
+}
@@ -7,8 +7,11 @@ use servo_util::geometry::Au;
 use servo_util::range::Range;
 use servo_util::vec::{Comparator, FullBinarySearchMethods};
 use std::slice::Items;
+use style::computed_values::word_break;
 use sync::Arc;
-use text::glyph::{CharIndex, GlyphStore};
+use text::glyph::{BreakType, BreakTypeNone, CharIndex, GlyphStore};
+use text::line_break::{mod, SOFT_HYPHEN};
+use text::shaping::ShapingOptions;
 use font::FontHandleMethods;
 use platform::font_template::FontTemplateData;
 
@@ -30,6 +33,31 @@ pub struct GlyphRun {
     glyph_store: Arc<GlyphStore>,
     /// The range of characters in the containing run.
     range: Range<CharIndex>,
+    /// The line break opportunities in this run, as offsets from the start of the run. These are
+    /// computed per UAX #14 over the whole text run, so the first entry may be at offset zero.
+    break_opportunities: Vec<(CharIndex, BreakType)>,
+    /// True if this run consists of a single soft hyphen. Such runs are shaped as a hyphen, which
+    /// is only rendered if the line is broken right after it.
+    is_soft_hyphen: bool,
+}
+
+impl GlyphRun {
+    /// Returns the kind of line break opportunity before the character at the given offset from
+    /// the start of this run.
+    fn break_type_at(&self, offset: CharIndex) -> BreakType {
+        for &(index, break_type) in self.break_opportunities.iter() {
+            if index == offset {
+                return break_type
+            }
+        }
+        BreakTypeNone
+    }
+
+    /// Returns true if this run is a soft hyphen that should not be drawn or measured when it is
+    /// part of the given range, which is the case unless it is the last character of the range.
+    fn is_hidden_in_range(&self, range: &Range<CharIndex>) -> bool {
+        self.is_soft_hyphen && self.range.end() != range.end()
+    }
 }
 
 pub struct SliceIterator<'a> {
@@ -37,6 +65,37 @@ pub struct SliceIterator<'a> {
     range:      Range<CharIndex>,
 }
 
+/// A piece of a text run that contains no line break opportunities, except possibly one at its
+/// start.
+pub struct BreakableSlice<'a> {
+    /// The glyphs that make up this slice.
+    pub glyphs: &'a GlyphStore,
+    /// The character index of the start of the glyph store, relative to the start of the run.
+    pub offset: CharIndex,
+    /// The range of characters in this slice, relative to the start of the glyph store.
+    pub range: Range<CharIndex>,
+    /// The kind of line break opportunity before the first character of this slice.
+    pub break_before: BreakType,
+}
+
+impl<'a> BreakableSlice<'a> {
+    /// Returns the range of characters in this slice, relative to the start of the text run.
+    pub fn text_run_range(&self) -> Range<CharIndex> {
+        let mut range = self.range;
+        range.shift_by(self.offset);
+        range
+    }
+}
+
+/// An iterator over the `BreakableSlice`s of a range of a text run.
+pub struct BreakableSliceIterator<'a> {
+    glyph_iter: Items<'a, GlyphRun>,
+    range: Range<CharIndex>,
+    /// The glyph run currently being split up, and the range of it that has yet to be returned,
+    /// relative to the start of the run.
+    current: Option<(&'a GlyphRun, Range<CharIndex>)>,
+}
+
 struct CharIndexComparator;
 
 impl Comparator<CharIndex,GlyphRun> for CharIndexComparator {
@@ -55,20 +114,68 @@ impl<'a> Iterator<(&'a GlyphStore, CharIndex, Range<CharIndex>)> for SliceIterat
     // inline(always) due to the inefficient rt failures messing up inline heuristics, I think.
     #[inline(always)]
     fn next(&mut self) -> Option<(&'a GlyphStore, CharIndex, Range<CharIndex>)> {
-        let slice_glyphs = self.glyph_iter.next();
-        if slice_glyphs.is_none() {
-            return None;
-        }
-        let slice_glyphs = slice_glyphs.unwrap();
+        loop {
+            let slice_glyphs = self.glyph_iter.next();
+            if slice_glyphs.is_none() {
+                return None;
+            }
+            let slice_glyphs = slice_glyphs.unwrap();
 
-        let mut char_range = self.range.intersect(&slice_glyphs.range);
-        let slice_range_begin = slice_glyphs.range.begin();
-        char_range.shift_by(-slice_range_begin);
-        if !char_range.is_empty() {
+            let mut char_range = self.range.intersect(&slice_glyphs.range);
+            if char_range.is_empty() {
+                return None;
+            }
+            if slice_glyphs.is_hidden_in_range(&self.range) {
+                continue
+            }
+
+            let slice_range_begin = slice_glyphs.range.begin();
+            char_range.shift_by(-slice_range_begin);
             return Some((&*slice_glyphs.glyph_store, slice_range_begin, char_range))
         }
+    }
+}
+
+impl<'a> Iterator<BreakableSlice<'a>> for BreakableSliceIterator<'a> {
+    fn next(&mut self) -> Option<BreakableSlice<'a>> {
+        loop {
+            match self.current {
+                Some((glyph_run, remaining)) if !remaining.is_empty() => {
+                    // Find the next break opportunity, if there is one in the remaining range.
+                    let begin = remaining.begin();
+                    let mut end = remaining.end();
+                    for &(index, _) in glyph_run.break_opportunities.iter() {
+                        if index > begin && index < end {
+                            end = index;
+                            break
+                        }
+                    }
 
-        return None;
+                    self.current = Some((glyph_run, Range::new(end, remaining.end() - end)));
+                    return Some(BreakableSlice {
+                        glyphs: &*glyph_run.glyph_store,
+                        offset: glyph_run.range.begin(),
+                        range: Range::new(begin, end - begin),
+                        break_before: glyph_run.break_type_at(begin),
+                    })
+                }
+                _ => {}
+            }
+
+            let glyph_run = match self.glyph_iter.next() {
+                None => return None,
+                Some(glyph_run) => glyph_run,
+            };
+            let mut char_range = self.range.intersect(&glyph_run.range);
+            if char_range.is_empty() {
+                return None
+            }
+            if glyph_run.is_hidden_in_range(&self.range) {
+                continue
+            }
+            char_range.shift_by(-glyph_run.range.begin());
+            self.current = Some((glyph_run, char_range));
+        }
     }
 }
 
@@ -117,8 +224,21 @@ impl<'a> Iterator<Range<CharIndex>> for LineIterator<'a> {
 }
 
 impl<'a> TextRun {
-    pub fn new(font: &mut Font, text: String) -> TextRun {
-        let glyphs = TextRun::break_and_shape(font, text.as_slice());
+    pub fn new(font: &mut Font, text: String, options: &ShapingOptions, word_break: word_break::T)
+               -> TextRun {
+        let break_opportunities = line_break::break_opportunities(text.as_slice(), word_break);
+        TextRun::new_with_break_opportunities(font, text, options, break_opportunities.as_slice())
+    }
+
+    /// Creates a text run whose line break opportunities have already been computed, with one
+    /// entry per character of `text`. This is used for text that is split into several runs, one
+    /// per font, so that the opportunities at the boundaries between the runs are not lost.
+    pub fn new_with_break_opportunities(font: &mut Font,
+                                        text: String,
+                                        options: &ShapingOptions,
+                                        break_opportunities: &[BreakType])
+                                        -> TextRun {
+        let glyphs = TextRun::break_and_shape(font, text.as_slice(), options, break_opportunities);
         let run = TextRun {
             text: Arc::new(text),
             font_metrics: font.metrics.clone(),
@@ -129,68 +249,106 @@ impl<'a> TextRun {
         return run;
     }
 
-    pub fn break_and_shape(font: &mut Font, text: &str) -> Vec<GlyphRun> {
-        // TODO(Issue #230): do a better job. See Gecko's LineBreaker.
+    pub fn break_and_shape(font: &mut Font,
+                           text: &str,
+                           options: &ShapingOptions,
+                           break_opportunities: &[BreakType])
+                           -> Vec<GlyphRun> {
         let mut glyphs = vec!();
         let (mut byte_i, mut char_i) = (0u, CharIndex(0));
         let mut cur_slice_is_whitespace = false;
+        let mut prev_ch_is_soft_hyphen = false;
         let (mut byte_last_boundary, mut char_last_boundary) = (0, CharIndex(0));
         while byte_i < text.len() {
             let range = text.char_range_at(byte_i);
             let ch = range.ch;
             let next = range.next;
 
-            // Slices alternate between whitespace and non-whitespace,
-            // representing line break opportunities.
-            let can_break_before = if cur_slice_is_whitespace {
-                match ch {
-                    ' ' | '\t' | '\n' => false,
-                    _ => {
-                        cur_slice_is_whitespace = false;
-                        true
-                    }
-                }
-            } else {
-                match ch {
-                    ' ' | '\t' | '\n' => {
-                        cur_slice_is_whitespace = true;
-                        true
-                    },
-                    _ => false
-                }
+            // Slices alternate between whitespace and non-whitespace. Soft hyphens get a slice
+            // of their own, since whether they are rendered depends on where the line breaks.
+            let ch_is_whitespace = match ch {
+                ' ' | '\t' | '\n' => true,
+                _ => false,
             };
+            let ch_is_soft_hyphen = ch == SOFT_HYPHEN;
+            let starts_slice = ch_is_whitespace != cur_slice_is_whitespace ||
+                ch_is_soft_hyphen || prev_ch_is_soft_hyphen;
 
-            // Create a glyph store for this slice if it's nonempty.
-            if can_break_before && byte_i > byte_last_boundary {
-                let slice = text.slice(byte_last_boundary, byte_i);
-                debug!("creating glyph store for slice {} (ws? {}), {} - {} in run {}",
-                        slice, !cur_slice_is_whitespace, byte_last_boundary, byte_i, text);
-                glyphs.push(GlyphRun {
-                    glyph_store: font.shape_text(slice, !cur_slice_is_whitespace),
-                    range: Range::new(char_last_boundary, char_i - char_last_boundary),
-                });
+            // Create a glyph store for the previous slice if it's nonempty.
+            if starts_slice && byte_i > byte_last_boundary {
+                glyphs.push(TextRun::shape_slice(font,
+                                                 text,
+                                                 options,
+                                                 byte_last_boundary,
+                                                 byte_i,
+                                                 Range::new(char_last_boundary,
+                                                            char_i - char_last_boundary),
+                                                 cur_slice_is_whitespace,
+                                                 break_opportunities));
                 byte_last_boundary = byte_i;
                 char_last_boundary = char_i;
             }
 
+            cur_slice_is_whitespace = ch_is_whitespace;
+            prev_ch_is_soft_hyphen = ch_is_soft_hyphen;
             byte_i = next;
             char_i = char_i + CharIndex(1);
         }
 
         // Create a glyph store for the final slice if it's nonempty.
         if byte_i > byte_last_boundary {
-            let slice = text.slice_from(byte_last_boundary);
-            debug!("creating glyph store for final slice {} (ws? {}), {} - {} in run {}",
-                slice, cur_slice_is_whitespace, byte_last_boundary, text.len(), text);
-            glyphs.push(GlyphRun {
-                glyph_store: font.shape_text(slice, cur_slice_is_whitespace),
-                range: Range::new(char_last_boundary, char_i - char_last_boundary),
-            });
+            glyphs.push(TextRun::shape_slice(font,
+                                             text,
+                                             options,
+                                             byte_last_boundary,
+                                             text.len(),
+                                             Range::new(char_last_boundary,
+                                                        char_i - char_last_boundary),
+                                             cur_slice_is_whitespace,
+                                             break_opportunities));
         }
 
         glyphs
     }
 
+    /// Shapes the slice of `text` between the given byte offsets, which covers `range`, and
+    /// records the line break opportunities that fall inside it.
+    fn shape_slice(font: &mut Font,
+                   text: &str,
+                   options: &ShapingOptions,
+                   byte_begin: uint,
+                   byte_end: uint,
+                   range: Range<CharIndex>,
+                   is_whitespace: bool,
+                   break_opportunities: &[BreakType])
+                   -> GlyphRun {
+        let slice = text.slice(byte_begin, byte_end);
+        debug!("creating glyph store for slice {} (ws? {}), {} - {} in run {}",
+               slice, is_whitespace, byte_begin, byte_end, text);
+
+        let is_soft_hyphen = slice.char_at(0) == SOFT_HYPHEN;
+        let glyph_store = if is_soft_hyphen {
+            font.shape_text("-", options, false)
+        } else {
+            font.shape_text(slice, options, is_whitespace)
+        };
+
+        let mut run_break_opportunities = vec!();
+        for index in range.each_index() {
+            match break_opportunities[index.to_uint()] {
+                BreakTypeNone => {}
+                break_type => run_break_opportunities.push((index - range.begin(), break_type)),
+            }
+        }
+
+        GlyphRun {
+            glyph_store: glyph_store,
+            range: range,
+            break_opportunities: run_break_opportunities,
+            is_soft_hyphen: is_soft_hyphen,
+        }
+    }
+
     pub fn char_len(&self) -> CharIndex {
         match self.glyphs.last() {
             None => CharIndex(0),
@@ -237,12 +395,53 @@ impl<'a> TextRun {
                         self.font_metrics.descent)
     }
 
+    /// Returns the width of the widest piece of the given range that cannot be broken across
+    /// lines. Trailing whitespace before a break opportunity hangs, so it is not counted.
     pub fn min_width_for_range(&self, range: &Range<CharIndex>) -> Au {
         debug!("iterating outer range {}", range);
-        self.iter_slices_for_range(range).fold(Au(0), |max_piece_width, (_, offset, slice_range)| {
-            debug!("iterated on {}[{}]", offset, slice_range);
-            Au::max(max_piece_width, self.advance_for_range(&slice_range))
-        })
+        let (mut max_piece_width, mut piece_width, mut pending_whitespace_width) =
+            (Au(0), Au(0), Au(0));
+        for slice in self.iter_breakable_slices_for_range(range) {
+            debug!("iterated on {}[{}]", slice.offset, slice.range);
+            if slice.break_before != BreakTypeNone {
+                piece_width = Au(0);
+                pending_whitespace_width = Au(0);
+            }
+
+            let advance = slice.glyphs.advance_for_char_range(&slice.range);
+            if slice.glyphs.is_whitespace() {
+                pending_whitespace_width = pending_whitespace_width + advance;
+            } else {
+                piece_width = piece_width + pending_whitespace_width + advance;
+                pending_whitespace_width = Au(0);
+                max_piece_width = Au::max(max_piece_width, piece_width);
+            }
+        }
+        max_piece_width
+    }
+
+    /// Returns true if the line may be broken before the character at the given index.
+    pub fn can_break_before(&self, index: CharIndex) -> bool {
+        match self.index_of_first_glyph_run_containing(index) {
+            None => false,
+            Some(glyph_run_index) => {
+                let glyph_run = &self.glyphs.as_slice()[glyph_run_index];
+                glyph_run.break_type_at(index - glyph_run.range.begin()) != BreakTypeNone
+            }
+        }
+    }
+
+    /// Returns the index of the last line break opportunity inside the given range, not counting
+    /// one at its start.
+    pub fn last_break_opportunity_in_range(&self, range: &Range<CharIndex>) -> Option<CharIndex> {
+        let mut last_break_opportunity = None;
+        for slice in self.iter_breakable_slices_for_range(range) {
+            let slice_begin = slice.offset + slice.range.begin();
+            if slice.break_before != BreakTypeNone && slice_begin > range.begin() {
+                last_break_opportunity = Some(slice_begin)
+            }
+        }
+        last_break_opportunity
     }
 
     /// Returns the index of the first glyph run containing the given character index.
@@ -261,6 +460,21 @@ impl<'a> TextRun {
         }
     }
 
+    /// Returns an iterator over the pieces of the given range that lie between line break
+    /// opportunities. Whitespace and non-whitespace are never mixed in a single piece.
+    pub fn iter_breakable_slices_for_range(&'a self, range: &Range<CharIndex>)
+                                           -> BreakableSliceIterator<'a> {
+        let index = match self.index_of_first_glyph_run_containing(range.begin()) {
+            None => self.glyphs.len(),
+            Some(index) => index,
+        };
+        BreakableSliceIterator {
+            glyph_iter: self.glyphs.slice_from(index).iter(),
+            range: *range,
+            current: None,
+        }
+    }
+
     pub fn iter_natural_lines_for_range(&'a self, range: &Range<CharIndex>) -> LineIterator<'a> {
         LineIterator {
             range:  *range,
@@ -0,0 +1,745 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Decoding of WOFF and WOFF2 web fonts to sfnt files that the platform font engines can load.
+//!
+//! See http://www.w3.org/TR/WOFF/ and http://www.w3.org/TR/WOFF2/.
+
+use sfnt::{mod, COLLECTION_FLAVOR, GLYF, HEAD, HHEA, HMTX, LOCA, MAX_FONT_SIZE, Reader, Table};
+use sfnt::{write_u16, write_u32};
+
+use brotli::Decompressor;
+use flate2::reader::ZlibDecoder;
+use std::ascii::AsciiExt;
+use std::cmp;
+use std::i16;
+use std::i32;
+use std::io::{BufReader, Reader as IoReader};
+
+const WOFF_SIGNATURE: u32 = 0x774F4646;     // 'wOFF'
+const WOFF2_SIGNATURE: u32 = 0x774F4632;    // 'wOF2'
+
+/// The tags that a WOFF2 table directory entry can refer to by index instead of spelling out.
+static WOFF2_KNOWN_TAGS: [&'static [u8], ..63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+    b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+    b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+    b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+    b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+    b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+
+/// The index in a WOFF2 table directory entry which means that an explicit tag follows.
+const WOFF2_ARBITRARY_TAG_INDEX: u8 = 63;
+
+// Flags of simple glyph points in the glyf table.
+const ON_CURVE_POINT: u8 = 0x01;
+const X_SHORT_VECTOR: u8 = 0x02;
+const Y_SHORT_VECTOR: u8 = 0x04;
+const REPEAT_FLAG: u8 = 0x08;
+const X_IS_SAME_OR_POSITIVE: u8 = 0x10;
+const Y_IS_SAME_OR_POSITIVE: u8 = 0x20;
+const OVERLAP_SIMPLE: u8 = 0x40;
+
+// Flags of composite glyph components in the glyf table.
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+/// Returns true if fonts with the given `format()` hint from an `@font-face` `src` descriptor can
+/// be loaded.
+pub fn is_supported_format(format_hint: &str) -> bool {
+    match format_hint.to_ascii_lower().as_slice() {
+        "truetype" | "opentype" | "woff" | "woff2" => true,
+        _ => false,
+    }
+}
+
+/// Converts the contents of a downloaded font file, which may be a WOFF, WOFF2, TrueType, or
+/// OpenType font, to a sanitized sfnt file. Returns an error describing the problem if the font
+/// cannot be decoded or is malformed.
+pub fn decode_web_font(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let signature = try!(Reader::new(data).read_u32());
+    let font = match signature {
+        WOFF_SIGNATURE => try!(decode_woff(data)),
+        WOFF2_SIGNATURE => try!(decode_woff2(data)),
+        _ => return sfnt::sanitize(data),
+    };
+    sfnt::sanitize(font.as_slice())
+}
+
+/// Decodes a WOFF file, whose tables are individually compressed with zlib.
+fn decode_woff(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let mut reader = Reader::new(data);
+    try!(reader.skip(4));
+    let flavor = try!(reader.read_u32());
+    if try!(reader.read_u32()) as uint != data.len() {
+        return Err("WOFF length does not match the file size")
+    }
+    let num_tables = try!(reader.read_u16()) as uint;
+    if try!(reader.read_u16()) != 0 {
+        return Err("WOFF reserved field is not zero")
+    }
+    if try!(reader.read_u32()) as uint > MAX_FONT_SIZE {
+        return Err("font is too large")
+    }
+    // The version and the extended metadata and private data blocks are of no use to us.
+    try!(reader.skip(24));
+
+    let mut tables = Vec::with_capacity(num_tables);
+    let mut total_length = 0;
+    for _ in range(0, num_tables) {
+        let tag = try!(reader.read_u32());
+        let offset = try!(reader.read_u32()) as uint;
+        let compressed_length = try!(reader.read_u32()) as uint;
+        let original_length = try!(reader.read_u32()) as uint;
+        // The checksum is recomputed when the sfnt file is built.
+        try!(reader.skip(4));
+
+        if offset > data.len() || compressed_length > data.len() - offset {
+            return Err("WOFF table is out of bounds")
+        }
+        if compressed_length > original_length {
+            return Err("WOFF table is larger when compressed")
+        }
+        total_length += original_length;
+        if total_length > MAX_FONT_SIZE {
+            return Err("font is too large")
+        }
+
+        let compressed_data = data.slice(offset, offset + compressed_length);
+        let table_data = if compressed_length == original_length {
+            compressed_data.to_vec()
+        } else {
+            match ZlibDecoder::new(BufReader::new(compressed_data)).read_to_end() {
+                Ok(bytes) => bytes,
+                Err(_) => return Err("bad zlib data in WOFF table"),
+            }
+        };
+        if table_data.len() != original_length {
+            return Err("WOFF table has the wrong length")
+        }
+
+        tables.push(Table {
+            tag: tag,
+            data: table_data,
+        });
+    }
+
+    Ok(sfnt::build(flavor, tables))
+}
+
+/// An entry of the WOFF2 table directory.
+struct Woff2TableEntry {
+    tag: u32,
+    /// Whether the table data has been transformed, as opposed to merely compressed.
+    transformed: bool,
+    original_length: uint,
+    /// The offset of the table data in the decompressed data stream.
+    offset: uint,
+    /// The length of the table data in the decompressed data stream.
+    length: uint,
+}
+
+impl Woff2TableEntry {
+    fn table_data<'a>(&self, decompressed_data: &'a [u8]) -> &'a [u8] {
+        decompressed_data.slice(self.offset, self.offset + self.length)
+    }
+}
+
+fn find_entry<'a>(entries: &'a [Woff2TableEntry], tag: u32) -> Option<&'a Woff2TableEntry> {
+    entries.iter().find(|entry| entry.tag == tag)
+}
+
+/// Decodes a WOFF2 file, whose tables are compressed together with Brotli after optionally
+/// transforming the glyf, loca, and hmtx tables.
+fn decode_woff2(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let mut reader = Reader::new(data);
+    try!(reader.skip(4));
+    let flavor = try!(reader.read_u32());
+    if flavor == COLLECTION_FLAVOR {
+        return Err("WOFF2 font collections are not supported")
+    }
+    if try!(reader.read_u32()) as uint != data.len() {
+        return Err("WOFF2 length does not match the file size")
+    }
+    let num_tables = try!(reader.read_u16()) as uint;
+    if try!(reader.read_u16()) != 0 {
+        return Err("WOFF2 reserved field is not zero")
+    }
+    if try!(reader.read_u32()) as uint > MAX_FONT_SIZE {
+        return Err("font is too large")
+    }
+    let compressed_length = try!(reader.read_u32()) as uint;
+    // The version and the extended metadata and private data blocks are of no use to us.
+    try!(reader.skip(24));
+
+    let mut entries = Vec::with_capacity(num_tables);
+    let mut offset = 0;
+    for _ in range(0, num_tables) {
+        let flags = try!(reader.read_u8());
+        let tag_index = flags & 0x3f;
+        let tag = if tag_index == WOFF2_ARBITRARY_TAG_INDEX {
+            try!(reader.read_u32())
+        } else {
+            sfnt::tag_from_bytes(WOFF2_KNOWN_TAGS[tag_index as uint])
+        };
+
+        // The glyf and loca tables are transformed unless the transform version is 3; other
+        // tables are transformed unless it is 0.
+        let transform_version = flags >> 6;
+        let transformed = match (tag, transform_version) {
+            (GLYF, 0) | (LOCA, 0) | (HMTX, 1) => true,
+            (GLYF, 3) | (LOCA, 3) | (_, 0) => false,
+            _ => return Err("unknown WOFF2 table transform"),
+        };
+
+        let original_length = try!(read_uint_base_128(&mut reader)) as uint;
+        let length = if transformed {
+            try!(read_uint_base_128(&mut reader)) as uint
+        } else {
+            original_length
+        };
+        if tag == LOCA && transformed && length != 0 {
+            return Err("transformed loca table is not empty")
+        }
+
+        entries.push(Woff2TableEntry {
+            tag: tag,
+            transformed: transformed,
+            original_length: original_length,
+            offset: offset,
+            length: length,
+        });
+        offset += length;
+        if offset > MAX_FONT_SIZE {
+            return Err("font is too large")
+        }
+    }
+
+    let compressed_data = try!(reader.read_bytes(compressed_length));
+    let decompressed_data = match Decompressor::new(BufReader::new(compressed_data)).read_to_end() {
+        Ok(bytes) => bytes,
+        Err(_) => return Err("bad Brotli data in WOFF2 file"),
+    };
+    if decompressed_data.len() != offset {
+        return Err("WOFF2 data has the wrong length")
+    }
+    let decompressed_data = decompressed_data.as_slice();
+
+    let glyf_and_loca = match (find_entry(entries.as_slice(), GLYF),
+                               find_entry(entries.as_slice(), LOCA)) {
+        (Some(glyf), Some(loca)) if glyf.transformed && loca.transformed => {
+            let glyf_and_loca =
+                try!(reconstruct_glyf_and_loca(glyf.table_data(decompressed_data)));
+            if glyf_and_loca.loca.len() != loca.original_length {
+                return Err("reconstructed loca table has the wrong length")
+            }
+            Some(glyf_and_loca)
+        }
+        (Some(glyf), Some(loca)) if glyf.transformed || loca.transformed => {
+            return Err("glyf and loca tables must be transformed together")
+        }
+        _ => None,
+    };
+
+    let mut tables = Vec::with_capacity(num_tables);
+    for entry in entries.iter() {
+        let table_bytes = match (entry.tag, &glyf_and_loca) {
+            (GLYF, &Some(ref glyf_and_loca)) => glyf_and_loca.glyf.clone(),
+            (LOCA, &Some(ref glyf_and_loca)) => glyf_and_loca.loca.clone(),
+            (HMTX, _) if entry.transformed => {
+                let x_mins = match glyf_and_loca {
+                    Some(ref glyf_and_loca) => glyf_and_loca.x_mins.as_slice(),
+                    None => return Err("transformed hmtx table requires a transformed glyf table"),
+                };
+                let num_h_metrics = match find_entry(entries.as_slice(), HHEA) {
+                    Some(hhea) => {
+                        let mut hhea_reader = Reader::new(hhea.table_data(decompressed_data));
+                        try!(hhea_reader.skip(34));
+                        try!(hhea_reader.read_u16()) as uint
+                    }
+                    None => return Err("transformed hmtx table requires an hhea table"),
+                };
+                try!(reconstruct_hmtx(entry.table_data(decompressed_data), num_h_metrics, x_mins))
+            }
+            _ => entry.table_data(decompressed_data).to_vec(),
+        };
+        if table_bytes.len() != entry.original_length {
+            return Err("WOFF2 table has the wrong length")
+        }
+        tables.push(Table {
+            tag: entry.tag,
+            data: table_bytes,
+        });
+    }
+
+    // The loca table format is recorded in the transformed glyf table; the head table has to
+    // agree with it.
+    match (&glyf_and_loca, tables.iter().find(|table| table.tag == HEAD)) {
+        (&Some(ref glyf_and_loca), Some(head)) => {
+            let mut head_reader = Reader::new(head.data.as_slice());
+            try!(head_reader.skip(50));
+            if try!(head_reader.read_u16()) != glyf_and_loca.index_format {
+                return Err("loca format does not match the head table")
+            }
+        }
+        _ => {}
+    }
+
+    Ok(sfnt::build(flavor, tables))
+}
+
+/// Reads a variable-length `UIntBase128` number from a WOFF2 table directory.
+fn read_uint_base_128(reader: &mut Reader) -> Result<u32, &'static str> {
+    let mut value = 0u32;
+    for i in range(0u, 5) {
+        let byte = try!(reader.read_u8());
+        if i == 0 && byte == 0x80 {
+            return Err("UIntBase128 has leading zeros")
+        }
+        if value & 0xFE000000 != 0 {
+            return Err("UIntBase128 overflows")
+        }
+        value = (value << 7) | (byte & 0x7f) as u32;
+        if byte & 0x80 == 0 {
+            return Ok(value)
+        }
+    }
+    Err("UIntBase128 is too long")
+}
+
+/// Reads a variable-length `255UInt16` number from a transformed glyf table.
+fn read_255_u16(reader: &mut Reader) -> Result<u16, &'static str> {
+    const LOWEST_U_CODE: u16 = 253;
+    let code = try!(reader.read_u8());
+    match code {
+        253 => reader.read_u16(),
+        254 => Ok(try!(reader.read_u8()) as u16 + LOWEST_U_CODE * 2),
+        255 => Ok(try!(reader.read_u8()) as u16 + LOWEST_U_CODE),
+        _ => Ok(code as u16),
+    }
+}
+
+/// The glyf and loca tables rebuilt from a transformed glyf table.
+struct GlyfAndLoca {
+    glyf: Vec<u8>,
+    loca: Vec<u8>,
+    /// The `indexToLocFormat` of the loca table.
+    index_format: u16,
+    /// The minimum x coordinate of each glyph, which is needed to reconstruct the hmtx table.
+    x_mins: Vec<i16>,
+}
+
+/// A point of a simple glyph, relative to the previous point.
+struct GlyphPoint {
+    dx: i32,
+    dy: i32,
+    on_curve: bool,
+}
+
+#[inline]
+fn bitmap_has_glyph(bitmap: &[u8], glyph_index: uint) -> bool {
+    bitmap[glyph_index >> 3] & (0x80 >> (glyph_index & 7)) != 0
+}
+
+/// Rebuilds the glyf and loca tables from the transformed glyf table of a WOFF2 file.
+fn reconstruct_glyf_and_loca(data: &[u8]) -> Result<GlyfAndLoca, &'static str> {
+    let mut reader = Reader::new(data);
+    try!(reader.skip(2));
+    let option_flags = try!(reader.read_u16());
+    let num_glyphs = try!(reader.read_u16()) as uint;
+    let index_format = try!(reader.read_u16());
+    if index_format > 1 {
+        return Err("bad loca format in transformed glyf table")
+    }
+
+    let mut stream_lengths = [0u, ..7];
+    for length in stream_lengths.iter_mut() {
+        *length = try!(reader.read_u32()) as uint;
+    }
+    let mut contour_stream = Reader::new(try!(reader.read_bytes(stream_lengths[0])));
+    let mut points_stream = Reader::new(try!(reader.read_bytes(stream_lengths[1])));
+    let mut flag_stream = Reader::new(try!(reader.read_bytes(stream_lengths[2])));
+    let mut glyph_stream = Reader::new(try!(reader.read_bytes(stream_lengths[3])));
+    let mut composite_stream = Reader::new(try!(reader.read_bytes(stream_lengths[4])));
+    let mut bbox_stream = Reader::new(try!(reader.read_bytes(stream_lengths[5])));
+    let mut instruction_stream = Reader::new(try!(reader.read_bytes(stream_lengths[6])));
+    let overlap_bitmap = if option_flags & 1 != 0 {
+        Some(try!(reader.read_bytes((num_glyphs + 7) / 8)))
+    } else {
+        None
+    };
+    let bbox_bitmap = try!(bbox_stream.read_bytes(((num_glyphs + 31) / 32) * 4));
+
+    let mut glyf = Vec::new();
+    let mut loca_offsets = Vec::with_capacity(num_glyphs + 1);
+    let mut x_mins = Vec::with_capacity(num_glyphs);
+    for glyph_index in range(0, num_glyphs) {
+        loca_offsets.push(glyf.len());
+
+        let num_contours = try!(contour_stream.read_i16());
+        let has_bbox = bitmap_has_glyph(bbox_bitmap, glyph_index);
+        if num_contours == 0 {
+            if has_bbox {
+                return Err("empty glyph has a bounding box")
+            }
+            x_mins.push(0);
+            continue
+        }
+
+        if num_contours == -1 {
+            // A composite glyph, which must have an explicit bounding box.
+            if !has_bbox {
+                return Err("composite glyph has no bounding box")
+            }
+            let bbox = try!(bbox_stream.read_bytes(8));
+            let (components, have_instructions) =
+                try!(read_composite_glyph_components(&mut composite_stream));
+
+            write_u16(&mut glyf, num_contours as u16);
+            glyf.push_all(bbox);
+            glyf.push_all(components);
+            if have_instructions {
+                let instruction_length = try!(read_255_u16(&mut glyph_stream));
+                write_u16(&mut glyf, instruction_length);
+                glyf.push_all(try!(instruction_stream.read_bytes(instruction_length as uint)));
+            }
+            x_mins.push(try!(Reader::new(bbox).read_i16()));
+        } else if num_contours > 0 {
+            let mut end_points = Vec::with_capacity(num_contours as uint);
+            let mut num_points = 0u;
+            for _ in range(0, num_contours) {
+                num_points += try!(read_255_u16(&mut points_stream)) as uint;
+                if num_points == 0 || num_points > 0xffff {
+                    return Err("bad number of points in glyph")
+                }
+                end_points.push((num_points - 1) as u16);
+            }
+
+            let mut points = Vec::with_capacity(num_points);
+            for _ in range(0, num_points) {
+                let flag = try!(flag_stream.read_u8());
+                points.push(try!(read_triplet(flag, &mut glyph_stream)));
+            }
+            let instruction_length = try!(read_255_u16(&mut glyph_stream));
+            let instructions = try!(instruction_stream.read_bytes(instruction_length as uint));
+
+            let (x_min, y_min, x_max, y_max) = if has_bbox {
+                (try!(bbox_stream.read_i16()), try!(bbox_stream.read_i16()),
+                 try!(bbox_stream.read_i16()), try!(bbox_stream.read_i16()))
+            } else {
+                try!(compute_bounding_box(points.as_slice()))
+            };
+
+            write_u16(&mut glyf, num_contours as u16);
+            for &value in [x_min, y_min, x_max, y_max].iter() {
+                write_u16(&mut glyf, value as u16);
+            }
+            for &end_point in end_points.iter() {
+                write_u16(&mut glyf, end_point);
+            }
+            write_u16(&mut glyf, instruction_length);
+            glyf.push_all(instructions);
+            let overlap = match overlap_bitmap {
+                Some(bitmap) => bitmap_has_glyph(bitmap, glyph_index),
+                None => false,
+            };
+            try!(write_simple_glyph_points(&mut glyf, points.as_slice(), overlap));
+            x_mins.push(x_min);
+        } else {
+            return Err("bad number of contours in glyph")
+        }
+
+        while glyf.len() % 4 != 0 {
+            glyf.push(0);
+        }
+    }
+    loca_offsets.push(glyf.len());
+
+    let mut loca = Vec::with_capacity(loca_offsets.len() * if index_format == 0 { 2 } else { 4 });
+    for &offset in loca_offsets.iter() {
+        if index_format == 0 {
+            if offset / 2 > 0xffff {
+                return Err("glyf table is too large for short loca offsets")
+            }
+            write_u16(&mut loca, (offset / 2) as u16);
+        } else {
+            write_u32(&mut loca, offset as u32);
+        }
+    }
+
+    Ok(GlyfAndLoca {
+        glyf: glyf,
+        loca: loca,
+        index_format: index_format,
+        x_mins: x_mins,
+    })
+}
+
+/// Reads the components of a composite glyph, returning their data verbatim along with whether
+/// the glyph has instructions.
+fn read_composite_glyph_components<'a>(reader: &mut Reader<'a>)
+                                       -> Result<(&'a [u8], bool), &'static str> {
+    let mut measuring_reader = reader.clone();
+    let start = measuring_reader.position();
+    let mut have_instructions = false;
+    loop {
+        let flags = try!(measuring_reader.read_u16());
+        have_instructions = have_instructions || (flags & WE_HAVE_INSTRUCTIONS) != 0;
+
+        // The glyph index, the arguments, and the transformation, if any.
+        let mut length = 2 + if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            length += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            length += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            length += 8;
+        }
+        try!(measuring_reader.skip(length));
+
+        if flags & MORE_COMPONENTS == 0 {
+            break
+        }
+    }
+
+    let components = try!(reader.read_bytes(measuring_reader.position() - start));
+    Ok((components, have_instructions))
+}
+
+#[inline]
+fn with_sign(flag: i32, value: i32) -> i32 {
+    if flag & 1 != 0 { value } else { -value }
+}
+
+/// Decodes a point of a simple glyph from its flag byte and the triplet-encoded coordinates in
+/// the glyph stream.
+fn read_triplet(flag: u8, glyph_stream: &mut Reader) -> Result<GlyphPoint, &'static str> {
+    let on_curve = flag & 0x80 == 0;
+    let flag = (flag & 0x7f) as i32;
+    let data_length = if flag < 84 { 1 } else if flag < 120 { 2 } else if flag < 124 { 3 } else { 4 };
+    let data: Vec<i32> =
+        try!(glyph_stream.read_bytes(data_length)).iter().map(|&byte| byte as i32).collect();
+
+    let (dx, dy) = if flag < 10 {
+        (0, with_sign(flag, ((flag & 14) << 7) + data[0]))
+    } else if flag < 20 {
+        (with_sign(flag, (((flag - 10) & 14) << 7) + data[0]), 0)
+    } else if flag < 84 {
+        let b0 = flag - 20;
+        let b1 = data[0];
+        (with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)),
+         with_sign(flag >> 1, 1 + ((b0 & 0x0c) << 2) + (b1 & 0x0f)))
+    } else if flag < 120 {
+        let b0 = flag - 84;
+        (with_sign(flag, 1 + ((b0 / 12) << 8) + data[0]),
+         with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + data[1]))
+    } else if flag < 124 {
+        (with_sign(flag, (data[0] << 4) + (data[1] >> 4)),
+         with_sign(flag >> 1, ((data[1] & 0x0f) << 8) + data[2]))
+    } else {
+        (with_sign(flag, (data[0] << 8) + data[1]),
+         with_sign(flag >> 1, (data[2] << 8) + data[3]))
+    };
+
+    Ok(GlyphPoint {
+        dx: dx,
+        dy: dy,
+        on_curve: on_curve,
+    })
+}
+
+/// Computes the bounding box of a simple glyph whose bounding box was omitted from the WOFF2 file.
+fn compute_bounding_box(points: &[GlyphPoint]) -> Result<(i16, i16, i16, i16), &'static str> {
+    let (mut x, mut y) = (0i32, 0i32);
+    let (mut x_min, mut y_min) = (i32::MAX, i32::MAX);
+    let (mut x_max, mut y_max) = (i32::MIN, i32::MIN);
+    for point in points.iter() {
+        x += point.dx;
+        y += point.dy;
+        x_min = cmp::min(x_min, x);
+        y_min = cmp::min(y_min, y);
+        x_max = cmp::max(x_max, x);
+        y_max = cmp::max(y_max, y);
+    }
+
+    for &value in [x_min, y_min, x_max, y_max].iter() {
+        if value < i16::MIN as i32 || value > i16::MAX as i32 {
+            return Err("glyph coordinates are out of range")
+        }
+    }
+    Ok((x_min as i16, y_min as i16, x_max as i16, y_max as i16))
+}
+
+/// Writes the flags and coordinates of the points of a simple glyph in the glyf table format.
+fn write_simple_glyph_points(glyf: &mut Vec<u8>, points: &[GlyphPoint], overlap: bool)
+                             -> Result<(), &'static str> {
+    let mut flags = Vec::with_capacity(points.len());
+    let mut x_coordinates = Vec::with_capacity(points.len() * 2);
+    let mut y_coordinates = Vec::with_capacity(points.len() * 2);
+    let mut last_flag = None;
+    let mut repeat_count = 0u8;
+
+    for (i, point) in points.iter().enumerate() {
+        let mut flag = if point.on_curve { ON_CURVE_POINT } else { 0 };
+        if i == 0 && overlap {
+            flag |= OVERLAP_SIMPLE;
+        }
+        flag |= try!(write_coordinate(&mut x_coordinates,
+                                      point.dx,
+                                      X_SHORT_VECTOR,
+                                      X_IS_SAME_OR_POSITIVE));
+        flag |= try!(write_coordinate(&mut y_coordinates,
+                                      point.dy,
+                                      Y_SHORT_VECTOR,
+                                      Y_IS_SAME_OR_POSITIVE));
+
+        if last_flag == Some(flag) && repeat_count < 255 {
+            if repeat_count == 0 {
+                *flags.last_mut().unwrap() |= REPEAT_FLAG;
+                flags.push(1);
+            } else {
+                *flags.last_mut().unwrap() += 1;
+            }
+            repeat_count += 1;
+        } else {
+            flags.push(flag);
+            last_flag = Some(flag);
+            repeat_count = 0;
+        }
+    }
+
+    glyf.push_all(flags.as_slice());
+    glyf.push_all(x_coordinates.as_slice());
+    glyf.push_all(y_coordinates.as_slice());
+    Ok(())
+}
+
+/// Writes a single coordinate delta in the shortest form available, returning the point flags
+/// that describe that form.
+fn write_coordinate(coordinates: &mut Vec<u8>, delta: i32, short_flag: u8, same_or_positive_flag: u8)
+                    -> Result<u8, &'static str> {
+    if delta == 0 {
+        Ok(same_or_positive_flag)
+    } else if delta > -256 && delta < 256 {
+        coordinates.push(delta.abs() as u8);
+        Ok(short_flag | if delta > 0 { same_or_positive_flag } else { 0 })
+    } else if delta >= i16::MIN as i32 && delta <= i16::MAX as i32 {
+        write_u16(coordinates, delta as i16 as u16);
+        Ok(0)
+    } else {
+        Err("glyph coordinate delta is out of range")
+    }
+}
+
+/// Rebuilds the hmtx table from the transformed hmtx table of a WOFF2 file, in which left side
+/// bearings equal to the minimum x coordinate of the glyph may be omitted.
+fn reconstruct_hmtx(data: &[u8], num_h_metrics: uint, x_mins: &[i16])
+                    -> Result<Vec<u8>, &'static str> {
+    let num_glyphs = x_mins.len();
+    if num_h_metrics == 0 || num_h_metrics > num_glyphs {
+        return Err("bad numberOfHMetrics")
+    }
+
+    let mut reader = Reader::new(data);
+    let flags = try!(reader.read_u8());
+    let has_proportional_lsbs = flags & 1 == 0;
+    let has_monospace_lsbs = flags & 2 == 0;
+    if flags & 0xfc != 0 || (has_proportional_lsbs && has_monospace_lsbs) {
+        return Err("bad flags in transformed hmtx table")
+    }
+
+    let mut advance_widths = Vec::with_capacity(num_h_metrics);
+    for _ in range(0, num_h_metrics) {
+        advance_widths.push(try!(reader.read_u16()));
+    }
+
+    let mut hmtx = Vec::with_capacity(num_h_metrics * 4 + (num_glyphs - num_h_metrics) * 2);
+    for (glyph_index, &advance_width) in advance_widths.iter().enumerate() {
+        let lsb = if has_proportional_lsbs {
+            try!(reader.read_i16())
+        } else {
+            x_mins[glyph_index]
+        };
+        write_u16(&mut hmtx, advance_width);
+        write_u16(&mut hmtx, lsb as u16);
+    }
+    for glyph_index in range(num_h_metrics, num_glyphs) {
+        let lsb = if has_monospace_lsbs {
+            try!(reader.read_i16())
+        } else {
+            x_mins[glyph_index]
+        };
+        write_u16(&mut hmtx, lsb as u16);
+    }
+    Ok(hmtx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GlyphPoint, compute_bounding_box, decode_web_font, is_supported_format};
+    use super::{read_255_u16, read_triplet, read_uint_base_128, write_simple_glyph_points};
+    use sfnt::Reader;
+
+    #[test]
+    fn test_is_supported_format() {
+        assert!(is_supported_format("woff"));
+        assert!(is_supported_format("WOFF2"));
+        assert!(is_supported_format("truetype"));
+        assert!(!is_supported_format("embedded-opentype"));
+        assert!(!is_supported_format("svg"));
+    }
+
+    #[test]
+    fn test_read_uint_base_128() {
+        assert_eq!(read_uint_base_128(&mut Reader::new(&[0x3f])), Ok(63));
+        assert_eq!(read_uint_base_128(&mut Reader::new(&[0x81, 0x00])), Ok(128));
+        assert!(read_uint_base_128(&mut Reader::new(&[0x80, 0x01])).is_err());
+        assert!(read_uint_base_128(&mut Reader::new(&[0xff, 0xff, 0xff, 0xff, 0x7f])).is_err());
+    }
+
+    #[test]
+    fn test_read_255_u16() {
+        assert_eq!(read_255_u16(&mut Reader::new(&[252])), Ok(252));
+        assert_eq!(read_255_u16(&mut Reader::new(&[255, 0])), Ok(253));
+        assert_eq!(read_255_u16(&mut Reader::new(&[254, 0])), Ok(506));
+        assert_eq!(read_255_u16(&mut Reader::new(&[253, 0x12, 0x34])), Ok(0x1234));
+    }
+
+    #[test]
+    fn test_read_triplet() {
+        let point = read_triplet(0x01, &mut Reader::new(&[5])).unwrap();
+        assert_eq!((point.dx, point.dy, point.on_curve), (0, 5, true));
+        let point = read_triplet(0x8a, &mut Reader::new(&[5])).unwrap();
+        assert_eq!((point.dx, point.dy, point.on_curve), (-5, 0, false));
+        let point = read_triplet(0x7f, &mut Reader::new(&[1, 0, 2, 0])).unwrap();
+        assert_eq!((point.dx, point.dy, point.on_curve), (256, 512, true));
+    }
+
+    #[test]
+    fn test_write_simple_glyph_points() {
+        let points = [
+            GlyphPoint { dx: 10, dy: 0, on_curve: true },
+            GlyphPoint { dx: 0, dy: -300, on_curve: true },
+            GlyphPoint { dx: 0, dy: -300, on_curve: true },
+        ];
+        assert_eq!(compute_bounding_box(points.as_slice()), Ok((10, -600, 10, 0)));
+
+        let mut glyf = Vec::new();
+        write_simple_glyph_points(&mut glyf, points.as_slice(), false).unwrap();
+        assert_eq!(glyf, vec!(0x33, 0x19, 1, 10, 0xfe, 0xd4, 0xfe, 0xd4));
+    }
+
+    #[test]
+    fn test_decode_rejects_garbage() {
+        assert!(decode_web_font(&[]).is_err());
+        assert!(decode_web_font(b"wOFF").is_err());
+        assert!(decode_web_font(b"wOF2\x00\x01\x00\x00").is_err());
+    }
+}
@@ -232,8 +232,11 @@ impl<'a> FlowConstructor<'a> {
     }
 
     /// Builds the `ImageFragmentInfo` for the given image. This is out of line to guide inlining.
-    fn build_fragment_info_for_image(&mut self, node: &ThreadSafeLayoutNode, url: Option<Url>)
-                                -> SpecificFragmentInfo {
+    fn build_fragment_info_for_image(&mut self,
+                                     node: &ThreadSafeLayoutNode,
+                                     url: Option<Url>,
+                                     density: f64)
+                                     -> SpecificFragmentInfo {
         match url {
             None => GenericFragment,
             Some(url) => {
@@ -241,6 +244,7 @@ impl<'a> FlowConstructor<'a> {
                 // little sense to me.
                 ImageFragment(box ImageFragmentInfo::new(node,
                                                          url,
+                                                         density,
                                                          self.layout_context
                                                              .shared
                                                              .image_cache
@@ -262,11 +266,11 @@ impl<'a> FlowConstructor<'a> {
                 IframeFragment(box IframeFragmentInfo::new(node))
             }
             Some(ElementNodeTypeId(HTMLImageElementTypeId)) => {
-                self.build_fragment_info_for_image(node, node.image_url())
+                self.build_fragment_info_for_image(node, node.image_url(), node.image_density())
             }
             Some(ElementNodeTypeId(HTMLObjectElementTypeId)) => {
                 let data = node.get_object_data();
-                self.build_fragment_info_for_image(node, data)
+                self.build_fragment_info_for_image(node, data, 1.0)
             }
             Some(ElementNodeTypeId(HTMLTableElementTypeId)) => TableWrapperFragment,
             Some(ElementNodeTypeId(HTMLTableColElementTypeId)) => {
@@ -291,6 +291,7 @@ impl FragmentDisplayListBuilding for Fragment {
         // Create the image display item.
         display_list.push(ImageDisplayItemClass(box ImageDisplayItem {
             base: BaseDisplayItem::new(bounds, self.node, clip_rect),
+            url: image_url.clone(),
             image: image.clone(),
             stretch_size: Size2D(Au::from_px(image.width as int),
                                  Au::from_px(image.height as int)),
@@ -715,6 +716,7 @@ impl FragmentDisplayListBuilding for Fragment {
                             base: BaseDisplayItem::new(absolute_content_box,
                                                        self.node,
                                                        *clip_rect),
+                            url: image_ref.url().clone(),
                             image: image.clone(),
                             stretch_size: absolute_content_box.size,
                         }));
@@ -24,7 +24,7 @@ use wrapper::{TLayoutNode, ThreadSafeLayoutNode};
 
 use geom::{Point2D, Rect, Size2D};
 use gfx::display_list::OpaqueNode;
-use gfx::text::glyph::CharIndex;
+use gfx::text::glyph::{BreakTypeNone, CharIndex};
 use gfx::text::text_run::TextRun;
 use script_traits::UntrustedNodeAddress;
 use serialize::{Encodable, Encoder};
@@ -44,8 +44,8 @@ use string_cache::Atom;
 use style::{ComputedValues, TElement, TNode, cascade_anonymous};
 use style::computed_values::{LengthOrPercentage, LengthOrPercentageOrAuto};
 use style::computed_values::{LengthOrPercentageOrNone};
-use style::computed_values::{LPA_Auto, clear, position, text_align, text_decoration};
-use style::computed_values::{vertical_align, white_space};
+use style::computed_values::{LPA_Auto, clear, overflow_wrap, position, text_align};
+use style::computed_values::{text_decoration, vertical_align, white_space};
 use sync::{Arc, Mutex};
 use url::Url;
 
@@ -221,6 +221,8 @@ impl InlineBlockFragmentInfo {
 pub struct ImageFragmentInfo {
     /// The image held within this fragment.
     pub image: ImageHolder<UntrustedNodeAddress>,
+    /// The pixel density of the image source, by which its intrinsic size is divided.
+    pub density: f64,
     pub for_node: UntrustedNodeAddress,
     pub computed_inline_size: Option<Au>,
     pub computed_block_size: Option<Au>,
@@ -236,6 +238,7 @@ impl ImageFragmentInfo {
     /// sense to me.
     pub fn new(node: &ThreadSafeLayoutNode,
                image_url: Url,
+               density: f64,
                local_image_cache: Arc<Mutex<LocalImageCache<UntrustedNodeAddress>>>)
                -> ImageFragmentInfo {
         fn convert_length(node: &ThreadSafeLayoutNode, name: &Atom) -> Option<Au> {
@@ -255,6 +258,7 @@ impl ImageFragmentInfo {
 
         ImageFragmentInfo {
             image: ImageHolder::new(image_url, local_image_cache),
+            density: density,
             for_node: untrusted_node,
             computed_inline_size: None,
             computed_block_size: None,
@@ -274,16 +278,18 @@ impl ImageFragmentInfo {
         self.computed_block_size.expect("image block_size is not computed yet!")
     }
 
-    /// Returns the original inline-size of the image.
+    /// Returns the original inline-size of the image, corrected for its pixel density.
     pub fn image_inline_size(&mut self) -> Au {
         let size = self.image.get_size(self.for_node).unwrap_or(Size2D::zero());
-        Au::from_px(if self.writing_mode_is_vertical { size.height } else { size.width })
+        let inline_size = if self.writing_mode_is_vertical { size.height } else { size.width };
+        Au::from_frac_px(inline_size as f64 / self.density)
     }
 
-    /// Returns the original block-size of the image.
+    /// Returns the original block-size of the image, corrected for its pixel density.
     pub fn image_block_size(&mut self) -> Au {
         let size = self.image.get_size(self.for_node).unwrap_or(Size2D::zero());
-        Au::from_px(if self.writing_mode_is_vertical { size.width } else { size.height })
+        let block_size = if self.writing_mode_is_vertical { size.width } else { size.height };
+        Au::from_frac_px(block_size as f64 / self.density)
     }
 
     // Return used value for inline-size or block-size.
@@ -925,6 +931,39 @@ impl Fragment {
         self.is_scanned_text_fragment()
     }
 
+    /// Returns true if the line may be broken right before this fragment. This is false for a
+    /// text fragment that continues a word from the fragment before it, as happens when the word
+    /// is rendered with several fonts.
+    pub fn can_break_before(&self) -> bool {
+        match self.specific {
+            ScannedTextFragment(ref info) => info.run.can_break_before(info.range.begin()),
+            _ => true,
+        }
+    }
+
+    /// Splits a text fragment at the last line break opportunity inside it, if there is one.
+    pub fn split_at_last_break_opportunity(&self)
+                                           -> Option<(SplitInfo,
+                                                      SplitInfo,
+                                                      Arc<Box<TextRun>>)> {
+        match self.specific {
+            ScannedTextFragment(ref text_fragment_info) => {
+                let range = text_fragment_info.range;
+                let position = match text_fragment_info.run
+                                                       .last_break_opportunity_in_range(&range) {
+                    None => return None,
+                    Some(position) => position,
+                };
+                let inline_start_range = Range::new(range.begin(), position - range.begin());
+                let inline_end_range = Range::new(position, range.end() - position);
+                Some((SplitInfo::new(inline_start_range, &**text_fragment_info),
+                      SplitInfo::new(inline_end_range, &**text_fragment_info),
+                      text_fragment_info.run.clone()))
+            }
+            _ => None,
+        }
+    }
+
     /// Returns the newline positions of this fragment, if it's a scanned text fragment.
     pub fn newline_positions(&self) -> Option<&Vec<CharIndex>> {
         match self.specific {
@@ -1120,79 +1159,135 @@ impl Fragment {
                 panic!("Unscanned text fragments should have been scanned by now!")
             }
             ScannedTextFragment(ref text_fragment_info) => {
-                let mut pieces_processed_count: uint = 0;
+                let run = &text_fragment_info.run;
                 let mut remaining_inline_size: Au = max_inline_size;
                 let mut inline_start_range = Range::new(text_fragment_info.range.begin() + start,
                                                         CharIndex(0));
-                let mut inline_end_range: Option<Range<CharIndex>> = None;
+
+                // The position, relative to the start of the text run, at which we are going to
+                // break the line, if any.
+                let mut split_position: Option<CharIndex> = None;
+
+                // The most recent line break opportunity we've passed.
+                let mut last_break_opportunity: Option<CharIndex> = None;
+
+                // True if nothing fit on an empty line and we're waiting for the next break
+                // opportunity so that the overflowing text can be broken off.
+                let mut overflowing = false;
 
                 debug!("split_to_inline_size: splitting text fragment \
                         (strlen={}, range={}, avail_inline_size={})",
-                       text_fragment_info.run.text.len(),
+                       run.text.len(),
                        text_fragment_info.range,
                        max_inline_size);
 
-                for (glyphs, offset, slice_range) in text_fragment_info.run.iter_slices_for_range(
-                        &text_fragment_info.range) {
+                let mut range = text_fragment_info.range;
+                range.adjust_by(start, -start);
+                for slice in run.iter_breakable_slices_for_range(&range) {
                     debug!("split_to_inline_size: considering slice (offset={}, range={}, \
                                                                remain_inline_size={})",
-                           offset,
-                           slice_range,
+                           slice.offset,
+                           slice.range,
                            remaining_inline_size);
 
-                    let metrics = text_fragment_info.run.metrics_for_slice(glyphs, &slice_range);
-                    let advance = metrics.advance_width;
-
-                    let should_continue;
-                    if advance <= remaining_inline_size || glyphs.is_whitespace() {
-                        should_continue = true;
+                    let slice_begin = slice.offset + slice.range.begin();
+                    let is_whitespace = slice.glyphs.is_whitespace();
+                    if slice.break_before != BreakTypeNone &&
+                            inline_start_range.length() > CharIndex(0) {
+                        if overflowing {
+                            debug!("split_to_inline_size: case=ending overflowing piece");
+                            split_position = Some(slice_begin);
+                            break
+                        }
+                        last_break_opportunity = Some(slice_begin);
+                    }
 
-                        if starts_line && pieces_processed_count == 0 && glyphs.is_whitespace() {
-                            debug!("split_to_inline_size: case=skipping leading trimmable whitespace");
-                            inline_start_range.shift_by(slice_range.length());
+                    let advance = run.metrics_for_slice(slice.glyphs, &slice.range).advance_width;
+                    if overflowing || advance <= remaining_inline_size || is_whitespace {
+                        if starts_line && inline_start_range.length() == CharIndex(0) &&
+                                is_whitespace {
+                            debug!("split_to_inline_size: case=skipping leading trimmable \
+                                    whitespace");
+                            inline_start_range.shift_by(slice.range.length());
                         } else {
                             debug!("split_to_inline_size: case=enlarging span");
                             remaining_inline_size = remaining_inline_size - advance;
-                            inline_start_range.extend_by(slice_range.length());
-                        }
-                    } else {
-                        // The advance is more than the remaining inline-size.
-                        should_continue = false;
-                        let slice_begin = offset + slice_range.begin();
-
-                        if slice_begin < text_fragment_info.range.end() {
-                            // There are still some things inline-start over at the end of the line. Create
-                            // the inline-end chunk.
-                            let inline_end_range_end = text_fragment_info.range.end() - slice_begin;
-                            inline_end_range = Some(Range::new(slice_begin, inline_end_range_end));
-                            debug!("split_to_inline_size: case=splitting remainder with inline_end range={}",
-                                   inline_end_range);
+                            inline_start_range.extend_by(slice.range.length());
                         }
+                        continue
                     }
 
-                    pieces_processed_count += 1;
-
-                    if !should_continue {
-                        break
+                    // The advance is more than the remaining inline-size, so break at the last
+                    // opportunity we saw.
+                    match last_break_opportunity {
+                        Some(position) => {
+                            debug!("split_to_inline_size: case=breaking at last opportunity");
+                            split_position = Some(position);
+                            break
+                        }
+                        None if !starts_line => {
+                            // Nothing fits, so this fragment has to go on the next line.
+                            return None
+                        }
+                        None if self.style().get_inheritedtext().overflow_wrap ==
+                                overflow_wrap::break_word => {
+                            // There's no break opportunity to fall back on, so break between
+                            // characters instead. At least one character is put on the line.
+                            debug!("split_to_inline_size: case=breaking word");
+                            let mut position = slice_begin;
+                            for index in slice.range.each_index() {
+                                if index > slice.range.begin() &&
+                                        !slice.glyphs.is_cluster_start(index) {
+                                    position = position + CharIndex(1);
+                                    continue
+                                }
+                                let char_advance = slice.glyphs.advance_for_char_range(
+                                    &Range::new(index, CharIndex(1)));
+                                if char_advance > remaining_inline_size &&
+                                        position > inline_start_range.begin() {
+                                    break
+                                }
+                                remaining_inline_size = remaining_inline_size - char_advance;
+                                position = position + CharIndex(1);
+                            }
+                            split_position = Some(position);
+                            break
+                        }
+                        None => {
+                            debug!("split_to_inline_size: case=overflowing");
+                            overflowing = true;
+                            inline_start_range.extend_by(slice.range.length());
+                        }
                     }
                 }
 
-                let inline_start_is_some = inline_start_range.length() > CharIndex(0);
+                let inline_end_range = match split_position {
+                    None => None,
+                    Some(position) => {
+                        inline_start_range = Range::new(inline_start_range.begin(),
+                                                        position - inline_start_range.begin());
+                        if position < text_fragment_info.range.end() {
+                            let length = text_fragment_info.range.end() - position;
+                            debug!("split_to_inline_size: case=splitting remainder with \
+                                    inline_end range={}",
+                                   Range::new(position, length));
+                            Some(Range::new(position, length))
+                        } else {
+                            None
+                        }
+                    }
+                };
 
-                if (pieces_processed_count == 1 || !inline_start_is_some) && !starts_line {
-                    None
+                let inline_start = if inline_start_range.length() > CharIndex(0) {
+                    Some(SplitInfo::new(inline_start_range, &**text_fragment_info))
                 } else {
-                    let inline_start = if inline_start_is_some {
-                        Some(SplitInfo::new(inline_start_range, &**text_fragment_info))
-                    } else {
-                         None
-                    };
-                    let inline_end = inline_end_range.map(|inline_end_range| {
-                        SplitInfo::new(inline_end_range, &**text_fragment_info)
-                    });
-
-                    Some((inline_start, inline_end, text_fragment_info.run.clone()))
-                }
+                    None
+                };
+                let inline_end = inline_end_range.map(|inline_end_range| {
+                    SplitInfo::new(inline_end_range, &**text_fragment_info)
+                });
+
+                Some((inline_start, inline_end, text_fragment_info.run.clone()))
             }
         }
     }
@@ -1432,7 +1527,9 @@ impl Fragment {
                 // FIXME: Should probably use a whitelist of styles that can safely differ (#3165)
                 self.style().get_font() == other.style().get_font() &&
                     self.text_decoration() == other.text_decoration() &&
-                    self.white_space() == other.white_space()
+                    self.white_space() == other.white_space() &&
+                    self.style().get_inheritedtext().word_break ==
+                        other.style().get_inheritedtext().word_break
             }
             _ => false,
         }
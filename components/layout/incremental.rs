@@ -164,6 +164,10 @@ pub fn compute_damage(old: &Option<Arc<ComputedValues>>, new: &ComputedValues) -
         get_padding.padding_bottom, get_padding.padding_left,
         get_box.width, get_box.height,
         get_font.font_family, get_font.font_size, get_font.font_style, get_font.font_weight,
+        get_font.font_variant, get_font.font_stretch, get_font.font_kerning,
+        get_font.font_variant_caps, get_font.font_variant_ligatures,
+        get_font.font_variant_numeric, get_font.font_feature_settings,
+        get_font.font_language_override,
         get_inheritedtext.text_align, get_text.text_decoration, get_inheritedbox.line_height
     ]);
 
@@ -527,7 +527,14 @@ impl LineBreaker {
                 debug!("LineBreaker: Tried to split unsplittable render fragment! Deferring to \
                         next line. {}",
                        in_fragment);
+                let can_break_before = in_fragment.can_break_before();
                 self.work_list.push_front(in_fragment);
+                if !can_break_before && !self.defer_unbreakable_tail() {
+                    debug!("LineBreaker: case=no break opportunity on the line, so overflowing");
+                    let fragment = self.work_list.pop_front().unwrap();
+                    self.push_fragment_to_line(fragment);
+                    return true
+                }
                 false
             },
             Some((Some(inline_start_fragment), Some(inline_end_fragment))) => {
@@ -554,6 +561,62 @@ impl LineBreaker {
         }
     }
 
+    /// Moves the fragments at the end of the pending line that can't be separated from the
+    /// fragment at the front of the work list, such as the parts of a word that are rendered with
+    /// different fonts, to the work list as well. The first of those fragments is split at its
+    /// last line break opportunity, if it has one. Returns false, leaving the line as it is, if
+    /// there is no break opportunity on the line.
+    fn defer_unbreakable_tail(&mut self) -> bool {
+        let line_begin = self.pending_line.range.begin().to_uint();
+        let mut tail_begin = self.new_fragments.len();
+        let mut split = None;
+        loop {
+            if tail_begin == line_begin {
+                return false
+            }
+            tail_begin -= 1;
+            let fragment = &self.new_fragments[tail_begin];
+            match fragment.split_at_last_break_opportunity() {
+                Some(split_info) => {
+                    split = Some(split_info);
+                    break
+                }
+                None if fragment.can_break_before() && tail_begin > line_begin => break,
+                None => {}
+            }
+        }
+
+        while self.new_fragments.len() > tail_begin {
+            let fragment = self.new_fragments.pop().unwrap();
+            self.pending_line.range.extend_by(FragmentIndex(-1));
+            self.pending_line.bounds.size.inline = self.pending_line.bounds.size.inline -
+                fragment.border_box.size.inline;
+            self.work_list.push_front(fragment);
+        }
+
+        match split {
+            None => {}
+            Some((inline_start, inline_end, run)) => {
+                let fragment = self.work_list.pop_front().unwrap();
+                let writing_mode = self.floats.writing_mode;
+                let split_fragment = |split: SplitInfo| {
+                    let info = box ScannedTextFragmentInfo::new(run.clone(),
+                                                                split.range,
+                                                                Vec::new(),
+                                                                fragment.border_box.size);
+                    let size = LogicalSize::new(writing_mode,
+                                                split.inline_size,
+                                                fragment.border_box.size.block);
+                    fragment.transform(size, info)
+                };
+                debug!("LineBreaker: Splitting {} at its last break opportunity", fragment);
+                self.push_fragment_to_line(split_fragment(inline_start));
+                self.work_list.push_front(split_fragment(inline_end));
+            }
+        }
+        true
+    }
+
     // An unconditional push
     fn push_fragment_to_line(&mut self, fragment: Fragment) {
         debug!("LineBreaker: Pushing fragment {} to line {:u}",
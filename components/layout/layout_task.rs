@@ -38,13 +38,15 @@ use script::dom::element::{HTMLBodyElementTypeId, HTMLHtmlElementTypeId};
 use script::layout_interface::{AddStylesheetMsg, ContentBoxResponse, ContentBoxesResponse};
 use script::layout_interface::{ContentBoxesQuery, ContentBoxQuery, ExitNowMsg, GetRPCMsg};
 use script::layout_interface::{HitTestResponse, LayoutChan, LayoutRPC, LoadStylesheetMsg};
-use script::layout_interface::{MouseOverResponse, Msg, NoQuery, PrepareToExitMsg};
-use script::layout_interface::{ReapLayoutDataMsg, Reflow, ReflowForDisplay, ReflowMsg};
-use script::layout_interface::{ScriptLayoutChan, TrustedNodeAddress};
+use script::layout_interface::{MatchedRulesResponse, MouseOverResponse, Msg, NoQuery};
+use script::layout_interface::{PrepareToExitMsg, ReapLayoutDataMsg, Reflow, ReflowForDisplay};
+use script::layout_interface::{ReflowMsg, ReplaceStylesheetMsg, ScriptLayoutChan};
+use script::layout_interface::{StylesheetSource, StylesheetsResponse, TrustedNodeAddress};
 use script_traits::{SendEventMsg, ReflowEvent, ReflowCompleteMsg, OpaqueScriptLayoutChannel};
 use script_traits::{ScriptControlChan, UntrustedNodeAddress};
 use servo_msg::compositor_msg::Scrollable;
 use servo_msg::constellation_msg::{ConstellationChan, PipelineId, Failure, FailureMsg};
+use servo_net::fetch::request::Style as StyleContext;
 use servo_net::image_cache_task::{ImageCacheTask, ImageResponseMsg};
 use servo_net::local_image_cache::{ImageResponder, LocalImageCache};
 use servo_net::resource_task::{ResourceTask, load_bytes_iter};
@@ -59,9 +61,10 @@ use servo_util::time;
 use servo_util::workqueue::WorkQueue;
 use std::cell::Cell;
 use std::comm::{channel, Sender, Receiver, Select};
+use std::collections::HashSet;
 use std::mem;
 use std::ptr;
-use style::{AuthorOrigin, Stylesheet, Stylist, TNode, iter_font_face_rules};
+use style::{AuthorOrigin, Stylesheet, Stylist, TNode, iter_font_face_rules, style_rules};
 use style::{Device, Screen};
 use sync::{Arc, Mutex, MutexGuard};
 use url::Url;
@@ -390,6 +393,9 @@ impl LayoutTask {
         match request {
             AddStylesheetMsg(sheet) => self.handle_add_stylesheet(sheet, possibly_locked_rw_data),
             LoadStylesheetMsg(url) => self.handle_load_stylesheet(url, possibly_locked_rw_data),
+            ReplaceStylesheetMsg(index, sheet) => {
+                self.handle_replace_stylesheet(index, sheet, possibly_locked_rw_data)
+            }
             GetRPCMsg(response_chan) => {
                 response_chan.send(box LayoutRPCImpl(self.rw_data.clone()) as
                                    Box<LayoutRPC + Send>);
@@ -458,6 +464,7 @@ impl LayoutTask {
                 None => {}
                 Some(ref mut traversal) => traversal.shutdown(),
             }
+            rw_data.local_image_cache.lock().discard();
             LayoutTask::return_rw_data(possibly_locked_rw_data, rw_data);
         }
 
@@ -472,7 +479,7 @@ impl LayoutTask {
         // TODO: Get the actual value. http://dev.w3.org/csswg/css-syntax/#environment-encoding
         let environment_encoding = UTF_8 as EncodingRef;
 
-        let (metadata, iter) = load_bytes_iter(&self.resource_task, url);
+        let (metadata, iter) = load_bytes_iter(&self.resource_task, url, StyleContext);
         let protocol_encoding_label = metadata.charset.as_ref().map(|s| s.as_slice());
         let final_url = metadata.final_url;
 
@@ -480,7 +487,8 @@ impl LayoutTask {
                                                 final_url,
                                                 protocol_encoding_label,
                                                 Some(environment_encoding),
-                                                AuthorOrigin);
+                                                AuthorOrigin,
+                                                opts::get().devtools_port.is_some());
         self.handle_add_stylesheet(sheet, possibly_locked_rw_data);
     }
 
@@ -491,13 +499,28 @@ impl LayoutTask {
         // Find all font-face rules and notify the font cache of them.
         // GWTODO: Need to handle unloading web fonts (when we handle unloading stylesheets!)
         let mut rw_data = self.lock_rw_data(possibly_locked_rw_data);
-        iter_font_face_rules(&sheet, &rw_data.stylist.device, |family, src| {
-            self.font_cache_task.add_web_font(family.to_string(), (*src).clone());
+        iter_font_face_rules(&sheet, &rw_data.stylist.device, |rule| {
+            self.font_cache_task.add_web_font((*rule).clone());
         });
         rw_data.stylist.add_stylesheet(sheet);
         LayoutTask::return_rw_data(possibly_locked_rw_data, rw_data);
     }
 
+    fn handle_replace_stylesheet<'a>(&'a self,
+                                     index: uint,
+                                     sheet: Stylesheet,
+                                     possibly_locked_rw_data:
+                                        &mut Option<MutexGuard<'a, LayoutTaskData>>) {
+        let mut rw_data = self.lock_rw_data(possibly_locked_rw_data);
+        if index < rw_data.stylist.stylesheets().len() {
+            iter_font_face_rules(&sheet, &rw_data.stylist.device, |rule| {
+                self.font_cache_task.add_web_font((*rule).clone());
+            });
+            rw_data.stylist.replace_stylesheet(index, sheet);
+        }
+        LayoutTask::return_rw_data(possibly_locked_rw_data, rw_data);
+    }
+
     /// Retrieves the flow tree root from the root node.
     fn try_get_layout_root(&self, node: LayoutNode) -> Option<FlowRef> {
         let mut layout_data_ref = node.mutate_layout_data();
@@ -684,6 +707,11 @@ impl LayoutTask {
 
             rw_data.stacking_context = Some(stacking_context.clone());
 
+            // Images that are no longer displayed can be evicted from the image cache.
+            let mut image_urls = HashSet::new();
+            stacking_context.collect_image_urls(&mut image_urls);
+            rw_data.local_image_cache.lock().retain_images(&image_urls);
+
             debug!("Layout done!");
 
             self.render_chan.send(RenderInitMsg(stacking_context));
@@ -964,6 +992,38 @@ impl LayoutRPC for LayoutRPCImpl {
             Ok(MouseOverResponse(mouse_over_list))
         }
     }
+
+    fn stylesheets(&self) -> StylesheetsResponse {
+        let &LayoutRPCImpl(ref rw_data) = self;
+        let rw_data = rw_data.lock();
+        let mut stylesheets = vec!();
+        for (index, sheet) in rw_data.stylist.stylesheets().iter().enumerate() {
+            match sheet.origin {
+                AuthorOrigin => {
+                    stylesheets.push(StylesheetSource {
+                        index: index,
+                        url: sheet.base_url.clone(),
+                        source: sheet.source.clone(),
+                        rule_count: style_rules(sheet).len(),
+                    })
+                }
+                _ => (),
+            }
+        }
+        StylesheetsResponse(stylesheets)
+    }
+
+    fn matched_rules(&self, node: TrustedNodeAddress) -> MatchedRulesResponse {
+        let mut node: JS<Node> = unsafe {
+            JS::from_trusted_node_address(node)
+        };
+        let node: &mut LayoutNode = unsafe {
+            mem::transmute(&mut node)
+        };
+        let &LayoutRPCImpl(ref rw_data) = self;
+        let rw_data = rw_data.lock();
+        MatchedRulesResponse(rw_data.stylist.matched_rules(node))
+    }
 }
 
 struct UnioningFragmentBoundsIterator {
@@ -9,9 +9,11 @@
 use fragment::{Fragment, ScannedTextFragmentInfo, UnscannedTextFragment};
 use inline::InlineFragments;
 
-use gfx::font::{FontMetrics,RunMetrics};
+use gfx::font::{Font, FontMetrics, RunMetrics};
 use gfx::font_context::FontContext;
-use gfx::text::glyph::CharIndex;
+use gfx::text::glyph::{BreakTypeNormal, CharIndex};
+use gfx::text::line_break::{mod, CM, SOFT_HYPHEN, line_break_class};
+use gfx::text::shaping::{ShapingOptions, caps_for_style};
 use gfx::text::text_run::TextRun;
 use gfx::text::util::{mod, CompressWhitespaceNewline, CompressNone};
 use servo_util::dlist;
@@ -19,8 +21,10 @@ use servo_util::geometry::Au;
 use servo_util::logical_geometry::{LogicalSize, WritingMode};
 use servo_util::range::Range;
 use servo_util::smallvec::{SmallVec, SmallVec1};
+use std::cell::RefCell;
 use std::collections::DList;
 use std::mem;
+use std::rc::Rc;
 use style::ComputedValues;
 use style::computed_values::{line_height, text_orientation, white_space};
 use style::style_structs::Font as FontStyle;
@@ -93,25 +97,24 @@ impl TextRunScanner {
             }
         }
 
-        // TODO(#177): Text run creation must account for the renderability of text by font group
-        // fonts. This is probably achieved by creating the font group above and then letting
-        // `FontGroup` decide which `Font` to stick into the text run.
-        //
         // Concatenate all of the transformed strings together, saving the new character indices.
         let mut new_ranges: SmallVec1<Range<CharIndex>> = SmallVec1::new();
         let mut new_line_positions: SmallVec1<NewLinePositions> = SmallVec1::new();
         let mut char_total = CharIndex(0);
-        let run = {
+        let runs = {
+            let font_style;
             let fontgroup;
             let compression;
+            let word_break;
             {
                 let in_fragment = self.clump.front().unwrap();
-                let font_style = in_fragment.style().get_font_arc();
-                fontgroup = font_context.get_layout_font_group_for_style(font_style);
+                font_style = in_fragment.style().get_font_arc();
+                fontgroup = font_context.get_layout_font_group_for_style(font_style.clone());
                 compression = match in_fragment.white_space() {
                     white_space::normal | white_space::nowrap => CompressWhitespaceNewline,
                     white_space::pre => CompressNone,
-                }
+                };
+                word_break = in_fragment.style().get_inheritedtext().word_break;
             }
 
             // First, transform/compress text of all the nodes.
@@ -129,14 +132,28 @@ impl TextRunScanner {
                                                        last_whitespace,
                                                        &mut run_text,
                                                        &mut new_line_pos);
-                new_line_positions.push(NewLinePositions(new_line_pos));
+
+                // The positions are relative to the previous newline; make them relative to the
+                // start of the clump instead, so that they can be divided up among the runs.
+                let mut new_line_pos_in_clump = Vec::with_capacity(new_line_pos.len());
+                let mut position = char_total;
+                for (i, new_line_offset) in new_line_pos.into_iter().enumerate() {
+                    position = if i == 0 {
+                        position + new_line_offset
+                    } else {
+                        position + CharIndex(1) + new_line_offset
+                    };
+                    new_line_pos_in_clump.push(position);
+                }
+                new_line_positions.push(NewLinePositions(new_line_pos_in_clump));
 
                 let added_chars = CharIndex(run_text.as_slice().char_len() as int) - old_length;
                 new_ranges.push(Range::new(char_total, added_chars));
                 char_total = char_total + added_chars;
             }
 
-            // Now create the run.
+            // Now create the runs, one for each stretch of text that can be rendered with a
+            // single font.
             //
             // TextRuns contain a cycle which is usually resolved by the teardown sequence.
             // If no clump takes ownership, however, it will leak.
@@ -144,10 +161,36 @@ impl TextRunScanner {
                 self.clump = DList::new();
                 return last_whitespace
             }
-            Arc::new(box TextRun::new(&mut *fontgroup.fonts.get(0).borrow_mut(), run_text))
+
+            let mut fonts: Vec<Rc<RefCell<Font>>> =
+                fontgroup.fonts.iter().map(|font| font.clone()).collect();
+            let run_infos = split_text_by_font(font_context,
+                                               &*font_style,
+                                               &mut fonts,
+                                               fontgroup.is_last_resort,
+                                               run_text.as_slice());
+
+            // The line break opportunities are found in the text of the whole clump, so that the
+            // ones between runs aren't lost, and words that span several fonts aren't broken up.
+            // The clump itself may be broken before, as the text preceding it belongs to another
+            // element.
+            let mut break_opportunities =
+                line_break::break_opportunities(run_text.as_slice(), word_break);
+            break_opportunities.as_mut_slice()[0] = BreakTypeNormal;
+
+            run_infos.into_iter().map(|run_info| {
+                let mut font = fonts[run_info.font_index].borrow_mut();
+                let options = ShapingOptions::for_style(&*font_style, &*font);
+                let begin = run_info.range.begin().to_uint();
+                let end = run_info.range.end().to_uint();
+                let run = TextRun::new_with_break_opportunities(
+                    &mut *font, run_info.text, &options, break_opportunities.slice(begin, end));
+                (Arc::new(box run), run_info.range)
+            }).collect::<Vec<(Arc<Box<TextRun>>, Range<CharIndex>)>>()
         };
 
-        // Make new fragments with the run and adjusted text indices.
+        // Make new fragments with the runs and adjusted text indices. A fragment whose text is
+        // rendered with several fonts is split into one fragment per run.
         debug!("TextRunScanner: pushing {} fragment(s)", self.clump.len());
         for (logical_offset, old_fragment) in
                 mem::replace(&mut self.clump, DList::new()).into_iter().enumerate() {
@@ -160,24 +203,159 @@ impl TextRunScanner {
             }
 
             let text_size = old_fragment.border_box.size;
-            let &NewLinePositions(ref mut new_line_positions) =
-                new_line_positions.get_mut(logical_offset);
-            let new_text_fragment_info =
-                box ScannedTextFragmentInfo::new(run.clone(),
-                                                 range,
-                                                 mem::replace(new_line_positions, Vec::new()),
-                                                 text_size);
-            let new_metrics = new_text_fragment_info.run.metrics_for_range(&range);
-            let bounding_box_size = bounding_box_for_run_metrics(&new_metrics,
-                                                                 old_fragment.style.writing_mode);
-            let new_fragment = old_fragment.transform(bounding_box_size, new_text_fragment_info);
-            out_fragments.push(new_fragment)
+            let &NewLinePositions(ref new_line_positions) = new_line_positions.get(logical_offset);
+            for &(ref run, ref run_range) in runs.iter() {
+                let fragment_range = range.intersect(run_range);
+                if fragment_range.is_empty() {
+                    continue
+                }
+
+                let mut fragment_new_line_positions = Vec::new();
+                let mut last_position = fragment_range.begin();
+                for &position in new_line_positions.iter() {
+                    if fragment_range.contains(position) {
+                        fragment_new_line_positions.push(position - last_position);
+                        last_position = position + CharIndex(1);
+                    }
+                }
+
+                let mut range_in_run = fragment_range;
+                range_in_run.shift_by(-run_range.begin());
+                let new_text_fragment_info =
+                    box ScannedTextFragmentInfo::new(run.clone(),
+                                                     range_in_run,
+                                                     fragment_new_line_positions,
+                                                     text_size);
+                let new_metrics = new_text_fragment_info.run.metrics_for_range(&range_in_run);
+                let bounding_box_size =
+                    bounding_box_for_run_metrics(&new_metrics, old_fragment.style.writing_mode);
+                let new_fragment = old_fragment.transform(bounding_box_size,
+                                                          new_text_fragment_info);
+                out_fragments.push(new_fragment)
+            }
         }
 
         last_whitespace
     }
 }
 
+/// A stretch of a clump's text that is rendered with a single font.
+struct RunInfo {
+    /// The text of the run.
+    text: String,
+    /// The index of the font to render the run with.
+    font_index: uint,
+    /// The range of the clump's text that this run covers.
+    range: Range<CharIndex>,
+}
+
+/// Splits `text` into runs by font coverage. Each character is rendered with the first font in
+/// `fonts` that has a glyph for it; if there is none, a web font restricted to a `unicode-range` or
+/// a system fallback font is appended to `fonts` and used instead. Whitespace and combining
+/// characters stay in the font of the character that precedes them.
+///
+/// If the style asks for small capitals and the chosen font does not provide them, the letters
+/// that should become small capitals are rendered with a scaled-down copy of the font instead,
+/// which is appended to `fonts` as well.
+fn split_text_by_font(font_context: &mut FontContext,
+                      font_style: &FontStyle,
+                      fonts: &mut Vec<Rc<RefCell<Font>>>,
+                      is_last_resort: bool,
+                      text: &str)
+                      -> Vec<RunInfo> {
+    let caps = caps_for_style(font_style);
+
+    // Maps the indices of fonts to the indices of their small capital copies.
+    let mut small_caps_font_indices: Vec<(uint, uint)> = vec!();
+
+    let mut run_infos: Vec<RunInfo> = vec!();
+    for (i, ch) in text.chars().enumerate() {
+        let font_index = match run_infos.last() {
+            Some(run_info) if keeps_previous_font(ch) => run_info.font_index,
+            _ => font_index_for_char(font_context, font_style, fonts, is_last_resort, ch),
+        };
+
+        let synthesizes_small_caps = fonts[font_index].borrow().synthesizes_small_caps_for(caps,
+                                                                                           ch);
+        let font_index = if !synthesizes_small_caps {
+            font_index
+        } else {
+            match small_caps_font_indices.iter().find(|&&(index, _)| index == font_index) {
+                Some(&(_, small_caps_index)) => small_caps_index,
+                None => {
+                    let small_caps_font = font_context.get_small_caps_font(&fonts[font_index]);
+                    fonts.push(small_caps_font);
+                    small_caps_font_indices.push((font_index, fonts.len() - 1));
+                    fonts.len() - 1
+                }
+            }
+        };
+
+        let starts_run = match run_infos.last() {
+            None => true,
+            Some(run_info) => run_info.font_index != font_index,
+        };
+        if starts_run {
+            run_infos.push(RunInfo {
+                text: String::new(),
+                font_index: font_index,
+                range: Range::new(CharIndex(i as int), CharIndex(0)),
+            });
+        }
+
+        let run_info = run_infos.last_mut().unwrap();
+        run_info.text.push(ch);
+        run_info.range.extend_by(CharIndex(1));
+    }
+    run_infos
+}
+
+#[inline]
+fn keeps_previous_font(ch: char) -> bool {
+    ch.is_whitespace() || ch == SOFT_HYPHEN || line_break_class(ch) == CM
+}
+
+/// Returns the index of the font in `fonts` to render the given character with, appending a web
+/// font or system fallback font if necessary. If no font on the system covers the character, the
+/// primary font is used, which will render it as a missing glyph.
+///
+/// If the first font is a last resort font, which stands in for font families that are not
+/// available in their entirety, it is only used once the web fonts of those families turn out not
+/// to cover the character.
+fn font_index_for_char(font_context: &mut FontContext,
+                       font_style: &FontStyle,
+                       fonts: &mut Vec<Rc<RefCell<Font>>>,
+                       is_last_resort: bool,
+                       ch: char)
+                       -> uint {
+    let first_family_font = if is_last_resort { 1 } else { 0 };
+    for i in range(first_family_font, fonts.len()) {
+        if fonts[i].borrow().has_glyph_for(ch) {
+            return i
+        }
+    }
+
+    match font_context.get_web_font_for_char(font_style, ch) {
+        None => {}
+        Some(font) => {
+            fonts.push(font);
+            return fonts.len() - 1
+        }
+    }
+
+    if is_last_resort && fonts[0].borrow().has_glyph_for(ch) {
+        return 0
+    }
+
+    match font_context.get_fallback_font_for_char(font_style, ch) {
+        None => 0,
+        Some(font) => {
+            fonts.push(font);
+            fonts.len() - 1
+        }
+    }
+}
+
 struct NewLinePositions(Vec<CharIndex>);
 
 #[inline]
@@ -108,6 +108,17 @@ pub trait TLayoutNode {
         }
     }
 
+    /// If this is an image element, returns the pixel density of its image source. If this is not
+    /// an image element, fails.
+    fn image_density(&self) -> f64 {
+        unsafe {
+            match HTMLImageElementCast::to_js(self.get_jsmanaged()) {
+                Some(elem) => elem.image_density(),
+                None => panic!("not an image!")
+            }
+        }
+    }
+
     /// If this node is an iframe element, returns its pipeline and subpage IDs. If this node is
     /// not an iframe element, fails.
     fn iframe_pipeline_and_subpage_ids(&self) -> (PipelineId, SubpageId) {
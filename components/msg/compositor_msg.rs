@@ -42,7 +42,7 @@ impl Epoch {
     }
 }
 
-#[deriving(Clone, PartialEq)]
+#[deriving(Clone, PartialEq, Eq, Hash)]
 pub struct LayerId(pub uint, pub uint);
 
 impl Show for LayerId {
@@ -79,6 +79,25 @@ pub struct LayerMetadata {
     pub background_color: Color,
     /// The scrolling policy of this layer.
     pub scroll_policy: ScrollPolicy,
+    /// The opacity that the compositor applies to the layer. This is the combined opacity of the
+    /// stacking contexts that enclose the layer; the layer's own opacity is painted into it.
+    pub opacity: f32,
+}
+
+/// A tile painted into main memory rather than into a native surface. The renderer sends these
+/// to compositors that have no graphics context to share surfaces with, such as the software
+/// compositor used in headless mode.
+pub struct SoftwareLayerBuffer {
+    /// The rect in the page coordinates of the layer that this tile covers.
+    pub rect: Rect<f32>,
+    /// The rect in the device pixels of the layer that this tile covers.
+    pub screen_pos: Rect<uint>,
+    /// The scale at which the tile was painted.
+    pub resolution: f32,
+    /// The number of bytes in each row of `data`.
+    pub stride: uint,
+    /// The pixels of the tile, as premultiplied BGRA.
+    pub data: Vec<u8>,
 }
 
 /// The interface used by the renderer to acquire draw targets for each render frame and
@@ -99,6 +118,13 @@ pub trait RenderListener for Sized? {
              epoch: Epoch,
              replies: Vec<(LayerId, Box<LayerBufferSet>)>);
 
+    /// Sends new tiles painted into main memory for the given layer to the compositor. This is
+    /// used instead of `paint` when the compositor supplied no graphics metadata.
+    fn paint_software(&mut self,
+                      pipeline_id: PipelineId,
+                      epoch: Epoch,
+                      replies: Vec<(LayerId, Vec<SoftwareLayerBuffer>)>);
+
     fn render_msg_discarded(&mut self);
     fn set_render_state(&mut self, PipelineId, RenderState);
 }
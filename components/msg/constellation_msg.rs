@@ -11,9 +11,11 @@ use geom::scale_factor::ScaleFactor;
 use hyper::header::Headers;
 use hyper::method::{Method, Get};
 use layers::geometry::DevicePixel;
+use std::any::Any;
 use servo_util::geometry::{PagePx, ViewportPx};
 use std::comm::{channel, Sender, Receiver};
 use url::Url;
+use webdriver_traits::WebDriverScriptCommand;
 
 #[deriving(Clone)]
 pub struct ConstellationChan(pub Sender<Msg>);
@@ -58,7 +60,7 @@ pub enum KeyState {
 }
 
 //N.B. Straight up copied from glfw-rs
-#[deriving(Show)]
+#[deriving(PartialEq, Show)]
 pub enum Key {
     KeySpace,
     KeyApostrophe,
@@ -205,6 +207,22 @@ pub enum Msg {
     RendererReadyMsg(PipelineId),
     ResizedWindowMsg(WindowSizeData),
     KeyEvent(Key, KeyState, KeyModifiers),
+    /// Requests the ID of the pipeline of the root frame, if a page has been loaded.
+    GetRootPipelineMsg(Sender<Option<PipelineId>>),
+    /// Runs a WebDriver command in the script task of the given pipeline.
+    WebDriverCommandMsg(PipelineId, WebDriverScriptCommand),
+    /// Requests an image of the composited page.
+    ScreenshotMsg(Sender<Option<Screenshot>>),
+    /// Posts a message to the window of the given pipeline. The message is opaque to the
+    /// constellation.
+    PostMessageMsg(PipelineId, Box<Any+Send>),
+}
+
+/// An image of the composited page, as rows of RGB pixels from the top down.
+pub struct Screenshot {
+    pub width: uint,
+    pub height: uint,
+    pub pixels: Vec<u8>,
 }
 
 /// Similar to net::resource_task::LoadData
@@ -12,6 +12,7 @@ extern crate layers;
 extern crate serialize;
 extern crate "util" as servo_util;
 extern crate url;
+extern crate webdriver_traits;
 
 #[cfg(target_os="macos")]
 extern crate core_foundation;
@@ -0,0 +1,195 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Undoes the [content codings](http://tools.ietf.org/html/rfc7231#section-3.1.2.1) of HTTP
+//! response bodies as they are read, so that consumers only ever see the decoded bytes.
+
+use brotli::Decompressor;
+use flate2::reader::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use hyper::header::Headers;
+use std::ascii::AsciiExt;
+use std::io::{EndOfFile, MemReader};
+use std::io::util::{ChainedReader, NullReader};
+
+/// The value of the `Accept-Encoding` header sent with requests.
+pub static ACCEPT_ENCODING: &'static str = "gzip, deflate, br";
+
+/// The content codings of a response, in the order that they were applied.
+pub fn content_codings(headers: &Headers) -> Vec<String> {
+    let raw = match headers.get_raw("Content-Encoding") {
+        Some(raw) => raw,
+        None => return vec!(),
+    };
+    let mut codings = vec!();
+    for value in raw.iter() {
+        let value = String::from_utf8_lossy(value.as_slice()).into_string();
+        for coding in value.as_slice().split(',') {
+            let coding = coding.trim().to_ascii_lower();
+            // Identity is the absence of a coding.
+            if !coding.is_empty() && coding.as_slice() != "identity" {
+                codings.push(coding);
+            }
+        }
+    }
+    codings
+}
+
+/// Wraps a response body in decoders for the given content codings. A body in a coding that
+/// isn't supported is passed on as it is, still in that coding and any applied before it. Fails
+/// if the start of the body isn't valid for its coding.
+pub fn decoder<'a, R: Reader + 'a>(body: R, codings: &[String])
+                                   -> Result<Box<Reader + 'a>, String> {
+    let mut body = box body as Box<Reader + 'a>;
+    // The last coding that was applied is the first one to undo.
+    for coding in codings.iter().rev() {
+        body = match coding.as_slice() {
+            "gzip" | "x-gzip" => try!(gzip_decoder(body)),
+            "deflate" => try!(deflate_decoder(body)),
+            "br" => box Decompressor::new(body) as Box<Reader + 'a>,
+            _ => break,
+        };
+    }
+    Ok(body)
+}
+
+fn gzip_decoder<'a>(body: Box<Reader + 'a>) -> Result<Box<Reader + 'a>, String> {
+    // The gzip header is read straight away. Responses that have no body, such as responses to
+    // `HEAD` requests, don't have one.
+    match GzDecoder::new(body) {
+        Ok(decoder) => Ok(box decoder as Box<Reader + 'a>),
+        Err(ref e) if e.kind == EndOfFile => Ok(box NullReader as Box<Reader + 'a>),
+        Err(e) => Err(format!("bad gzip data: {}", e.desc)),
+    }
+}
+
+/// Servers disagree about whether `deflate` means a zlib stream, as the specification says, or
+/// a raw deflate stream, so look at the first two bytes to tell which one it is.
+fn deflate_decoder<'a>(mut body: Box<Reader + 'a>) -> Result<Box<Reader + 'a>, String> {
+    let mut header = vec!();
+    while header.len() < 2 {
+        match body.read_byte() {
+            Ok(byte) => header.push(byte),
+            Err(ref e) if e.kind == EndOfFile => break,
+            Err(e) => return Err(format!("bad deflate data: {}", e.desc)),
+        }
+    }
+    if header.is_empty() {
+        return Ok(box NullReader as Box<Reader + 'a>);
+    }
+
+    let zlib = is_zlib_header(header.as_slice());
+    let readers = vec!(box MemReader::new(header) as Box<Reader + 'a>, body);
+    let body = ChainedReader::new(readers.into_iter());
+    Ok(if zlib {
+        box ZlibDecoder::new(body) as Box<Reader + 'a>
+    } else {
+        box DeflateDecoder::new(body) as Box<Reader + 'a>
+    })
+}
+
+/// Whether the bytes start a zlib stream (http://tools.ietf.org/html/rfc1950#section-2.2).
+fn is_zlib_header(header: &[u8]) -> bool {
+    header.len() == 2 &&
+        header[0] & 0x0f == 8 &&
+        ((header[0] as u16) << 8 | header[1] as u16) % 31 == 0
+}
+
+/// Compresses bytes into a zlib stream, for tests that need compressed data such as PNG images.
+#[cfg(test)]
+pub fn zlib_bytes(data: &[u8]) -> Vec<u8> {
+    use flate2;
+    use flate2::writer::ZlibEncoder;
+
+    let mut encoder = ZlibEncoder::new(vec!(), flate2::Default);
+    encoder.write(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{content_codings, decoder, is_zlib_header, zlib_bytes};
+
+    use flate2;
+    use flate2::writer::{DeflateEncoder, GzEncoder};
+    use hyper::header::Headers;
+    use std::io::MemReader;
+
+    static TEXT: &'static [u8] = b"Hello, hello, hello, hello, hello!";
+
+    /// Reads the whole of a decoded body.
+    fn decode_all(body: Vec<u8>, codings: &[&str]) -> Result<Vec<u8>, String> {
+        let codings: Vec<String> = codings.iter().map(|coding| coding.to_string()).collect();
+        let mut decoder = try!(decoder(MemReader::new(body), codings.as_slice()));
+        decoder.read_to_end().map_err(|e| e.desc.to_string())
+    }
+
+    #[test]
+    fn test_content_codings() {
+        let mut headers = Headers::new();
+        assert!(content_codings(&headers).is_empty());
+        headers.set_raw("Content-Encoding", vec![b"identity, GZIP".to_vec(), b" br ".to_vec()]);
+        assert_eq!(content_codings(&headers), vec!("gzip".to_string(), "br".to_string()));
+    }
+
+    #[test]
+    fn test_identity() {
+        assert_eq!(decode_all(TEXT.to_vec(), &[]).unwrap(), TEXT.to_vec());
+    }
+
+    #[test]
+    fn test_gzip() {
+        let mut encoder = GzEncoder::new(vec!(), flate2::Default);
+        encoder.write(TEXT).unwrap();
+        let body = encoder.finish().unwrap();
+        assert_eq!(decode_all(body, &["gzip"]).unwrap(), TEXT.to_vec());
+    }
+
+    #[test]
+    fn test_deflate() {
+        let zlib = zlib_bytes(TEXT);
+        assert!(is_zlib_header(zlib.slice_to(2)));
+        assert_eq!(decode_all(zlib, &["deflate"]).unwrap(), TEXT.to_vec());
+
+        let mut encoder = DeflateEncoder::new(vec!(), flate2::Default);
+        encoder.write(TEXT).unwrap();
+        let raw = encoder.finish().unwrap();
+        assert!(!is_zlib_header(raw.slice_to(2)));
+        assert_eq!(decode_all(raw, &["deflate"]).unwrap(), TEXT.to_vec());
+    }
+
+    #[test]
+    fn test_brotli() {
+        // A window size of 64KB, then a metablock of the text stored uncompressed, whose length
+        // less one is in the four nibbles after the first four bits, and an empty last metablock.
+        let mut body = vec!(0x10, 0x02, 0x10);
+        assert_eq!(TEXT.len(), 0x21 + 1);
+        body.push_all(TEXT);
+        body.push(0x03);
+        assert_eq!(decode_all(body, &["br"]).unwrap(), TEXT.to_vec());
+
+        // The shortest stream, which is empty.
+        assert!(decode_all(vec!(0x06), &["br"]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_empty_body() {
+        assert!(decode_all(vec!(), &["gzip"]).unwrap().is_empty());
+        assert!(decode_all(vec!(), &["deflate"]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_body() {
+        // A zlib header followed by a block of the reserved type.
+        assert!(decode_all(vec!(0x78, 0x9c, 0xff, 0xff, 0xff), &["deflate"]).is_err());
+        assert!(decode_all(b"not gzip at all".to_vec(), &["gzip"]).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_coding() {
+        // The body is passed through, without undoing the codings applied before the unknown one.
+        let zlib = zlib_bytes(TEXT);
+        assert_eq!(decode_all(TEXT.to_vec(), &["compress"]).unwrap(), TEXT.to_vec());
+        assert_eq!(decode_all(zlib.clone(), &["deflate", "compress"]).unwrap(), zlib);
+    }
+}
@@ -2,13 +2,23 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use url::Url;
-use hyper::method::{Get, Method};
+use url::{Url, UrlParser};
+use hyper::http::RawStatus;
+use hyper::method::{Get, Head, Method, Options, Post};
 use hyper::mime::{Mime, Text, Html, Charset, Utf8};
 use hyper::header::Headers;
 use hyper::header::common::ContentType;
-use fetch::cors_cache::CORSCache;
-use fetch::response::Response;
+use fetch::cors_cache::{CacheRequestDetails, CORSCache};
+use fetch::response::{Response, Default as Unfiltered, OpaqueRedirect};
+use fetch::response::Basic as BasicFilter;
+use fetch::response::CORS as CORSFilter;
+use fetch::response::Opaque as OpaqueFilter;
+use fetch::response::{Done as BodyDone, Empty, Receiving};
+use fetch::response::{ResponseMsg, Chunk, Finished, Errored};
+use http_cache::header_value;
+use resource_task::{Done, Load, LoadData, Payload, ProgressMsg, ResourceTask};
+use std::ascii::AsciiExt;
+use std::num::FromPrimitive;
 
 /// A [request context](http://fetch.spec.whatwg.org/#concept-request-context)
 pub enum Context {
@@ -35,6 +45,7 @@ pub enum Referer {
 }
 
 /// A [request mode](http://fetch.spec.whatwg.org/#concept-request-mode)
+#[deriving(Clone, PartialEq)]
 pub enum RequestMode {
     SameOrigin,
     NoCORS,
@@ -43,13 +54,34 @@ pub enum RequestMode {
 }
 
 /// Request [credentials mode](http://fetch.spec.whatwg.org/#concept-request-credentials-mode)
+#[deriving(Clone, PartialEq)]
 pub enum CredentialsMode {
     Omit,
     CredentialsSameOrigin,
     Include
 }
 
+/// A [cache mode](http://fetch.spec.whatwg.org/#concept-request-cache-mode)
+#[deriving(Clone, PartialEq, Show)]
+pub enum CacheMode {
+    DefaultCache,
+    NoStore,
+    Reload,
+    NoCache,
+    ForceCache,
+    OnlyIfCached
+}
+
+/// A [redirect mode](http://fetch.spec.whatwg.org/#concept-request-redirect-mode)
+#[deriving(Clone, PartialEq)]
+pub enum RedirectMode {
+    FollowRedirect,
+    ErrorRedirect,
+    ManualRedirect
+}
+
 /// [Response tainting](http://fetch.spec.whatwg.org/#concept-request-response-tainting)
+#[deriving(Clone, PartialEq)]
 pub enum ResponseTainting {
     Basic,
     CORSTainting,
@@ -77,11 +109,18 @@ pub struct Request {
     pub sync: bool,
     pub mode: RequestMode,
     pub credentials_mode: CredentialsMode,
+    pub cache_mode: CacheMode,
     pub use_url_credentials: bool,
-    pub manual_redirect: bool,
+    pub redirect_mode: RedirectMode,
     pub redirect_count: uint,
     pub response_tainting: ResponseTainting,
-    pub cache: Option<Box<CORSCache+'static>>
+    /// The [CORS preflight cache](http://fetch.spec.whatwg.org/#cors-preflight-cache)
+    pub cache: Option<Box<CORSCache+Send>>,
+    /// Where the body of the response is sent as it arrives, instead of being buffered into
+    /// the response returned by `fetch`.
+    pub body_chan: Option<Sender<ResponseMsg>>,
+    /// The body of the last load, which is still arriving.
+    pending_body: Option<Receiver<ProgressMsg>>
 }
 
 impl Request {
@@ -104,48 +143,624 @@ impl Request {
             sync: false,
             mode: NoCORS,
             credentials_mode: Omit,
+            cache_mode: DefaultCache,
             use_url_credentials: false,
-            manual_redirect: false,
+            redirect_mode: FollowRedirect,
             redirect_count: 0,
             response_tainting: Basic,
-            cache: None
+            cache: None,
+            body_chan: None,
+            pending_body: None
+        }
+    }
+
+    /// [Fetch](http://fetch.spec.whatwg.org#concept-fetch)
+    pub fn fetch(&mut self, resource_task: &ResourceTask) -> Response {
+        // Step 2
+        if self.headers.get_raw("Accept").is_none() {
+            self.headers.set_raw("Accept", vec![b"*/*".to_vec()]);
+        }
+        // Step 3 (Accept-Language) is left to the network layer.
+        self.main_fetch(resource_task, false, false)
+    }
+
+    /// Sends the body of a response returned by `fetch` to the request's body channel as it
+    /// arrives, followed by `Finished`, or by `Errored` if the load fails.
+    pub fn send_body(&mut self, response: &Response) {
+        let body_chan = match self.body_chan.take() {
+            Some(body_chan) => body_chan,
+            None => return
+        };
+        let pending_body = self.pending_body.take();
+        if response.is_network_error() {
+            pending_body.map(discard_body);
+            let _ = body_chan.send_opt(Errored);
+            return;
+        }
+        let progress_port = match pending_body {
+            Some(progress_port) => progress_port,
+            None => {
+                // The body was complete when the response was returned.
+                match response.body {
+                    BodyDone(ref body) | Receiving(ref body) if !body.is_empty() => {
+                        let _ = body_chan.send_opt(Chunk(body.clone()));
+                    },
+                    _ => {}
+                }
+                let _ = body_chan.send_opt(Finished);
+                return;
+            }
+        };
+        match response.body {
+            Empty => {
+                // The body is filtered out.
+                discard_body(progress_port);
+                let _ = body_chan.send_opt(Finished);
+                return;
+            },
+            _ => {}
+        }
+        loop {
+            match progress_port.recv_opt() {
+                Ok(Payload(data)) => {
+                    if body_chan.send_opt(Chunk(data)).is_err() {
+                        // Nobody is listening anymore, but the loader still sends the rest.
+                        return discard_body(progress_port);
+                    }
+                },
+                Ok(Done(Ok(()))) => {
+                    let _ = body_chan.send_opt(Finished);
+                    return;
+                },
+                Ok(Done(Err(_))) | Err(()) => {
+                    let _ = body_chan.send_opt(Errored);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// [Main fetch](http://fetch.spec.whatwg.org/#concept-main-fetch)
+    fn main_fetch(&mut self, resource_task: &ResourceTask, cors_flag: bool,
+                  recursive: bool) -> Response {
+        // Steps 1-3: Mixed content and CSP checks (unimplemented)
+        // Step 4
+        let is_same_origin = match self.origin {
+            Some(ref origin) => same_origin(origin, &self.url),
+            None => true
+        };
+        let scheme = self.url.scheme.clone();
+        let response = if (is_same_origin && !cors_flag) ||
+                          (scheme.as_slice() == "data" && self.same_origin_data) ||
+                          scheme.as_slice() == "about" {
+            self.basic_fetch(resource_task)
+        } else if self.mode == SameOrigin {
+            Response::network_error()
+        } else if self.mode == NoCORS {
+            self.response_tainting = Opaque;
+            self.basic_fetch(resource_task)
+        } else if scheme.as_slice() != "http" && scheme.as_slice() != "https" {
+            Response::network_error()
+        } else if self.mode == ForcedPreflightMode ||
+                  (self.unsafe_request && (!is_simple_method(&self.method) ||
+                                           self.non_simple_header_names().len() > 0)) {
+            self.response_tainting = CORSTainting;
+            self.http_fetch(resource_task, true, true, false)
+        } else {
+            self.response_tainting = CORSTainting;
+            self.http_fetch(resource_task, true, false, false)
+        };
+
+        // Step 5
+        if recursive {
+            return response;
+        }
+
+        // Step 6: Filter the response, unless it's an error or was filtered by a redirect.
+        if response.is_network_error() || response.response_type != Unfiltered {
+            return response;
+        }
+        match self.response_tainting {
+            Basic => response.to_filtered(BasicFilter),
+            CORSTainting => response.to_filtered(CORSFilter),
+            Opaque => response.to_filtered(OpaqueFilter)
         }
     }
 
     /// [Basic fetch](http://fetch.spec.whatwg.org#basic-fetch)
-    pub fn basic_fetch(&mut self) -> Response {
+    fn basic_fetch(&mut self, resource_task: &ResourceTask) -> Response {
         match self.url.scheme.as_slice() {
             "about" => match self.url.non_relative_scheme_data() {
                 Some(s) if s.as_slice() == "blank" => {
                     let mut response = Response::new();
                     response.headers.set(ContentType(Mime(Text, Html, vec![(Charset, Utf8)])));
+                    response.body = BodyDone(vec!());
                     response
                 },
                 _ => Response::network_error()
             },
             "http" | "https" => {
-                self.http_fetch(false, false, false)
+                self.http_fetch(resource_task, false, false, false)
             },
-            "blob" | "data" | "file" | "ftp" => {
-                // XXXManishearth handle these
-                panic!("Unimplemented scheme for Fetch")
+            "data" | "file" => {
+                let (method, headers) = (self.method.clone(), self.headers.clone());
+                let stream = self.body_chan.is_some();
+                self.load(resource_task, method, headers, None, stream)
             },
-
+            // XXXManishearth handle these
+            "blob" | "ftp" => Response::network_error(),
             _ => Response::network_error()
         }
     }
 
-    // [HTTP fetch](http://fetch.spec.whatwg.org#http-fetch)
-    pub fn http_fetch(&mut self, _cors_flag: bool, cors_preflight_flag: bool, _authentication_fetch_flag: bool) -> Response {
-        let response = Response::new();
-        // TODO: Service worker fetch
-        // Step 3
+    /// [HTTP fetch](http://fetch.spec.whatwg.org#http-fetch)
+    fn http_fetch(&mut self, resource_task: &ResourceTask, cors_flag: bool,
+                  cors_preflight_flag: bool, authentication_fetch_flag: bool) -> Response {
+        // Step 3: Service worker fetch (unimplemented)
         // Substep 1
         self.skip_service_worker = true;
         // Substep 2
-        if cors_preflight_flag {
-            // XXXManishearth stuff goes here
+        if cors_preflight_flag && !self.preflight_cache_matches() {
+            let preflight = self.preflight_fetch(resource_task);
+            if preflight.is_network_error() {
+                return preflight;
+            }
+        }
+        // Substep 4
+        let credentials_flag = match self.credentials_mode {
+            Include => true,
+            CredentialsSameOrigin => self.response_tainting == Basic,
+            Omit => false
+        };
+        // Substep 5
+        let response = self.http_network_or_cache_fetch(resource_task, credentials_flag,
+                                                        authentication_fetch_flag);
+        // Substep 6
+        if cors_flag && !response.is_network_error() && !self.cors_check(&response) {
+            return Response::network_error();
+        }
+
+        // Step 4
+        match status_code(&response) {
+            301 | 302 | 303 | 307 | 308 if response.headers.get_raw("Location").is_some() => {
+                match self.redirect_mode {
+                    ErrorRedirect => Response::network_error(),
+                    ManualRedirect => response.to_filtered(OpaqueRedirect),
+                    FollowRedirect => self.http_redirect_fetch(resource_task, cors_flag, response)
+                }
+            },
+            // There is no authentication dialog to ask for credentials with, so the
+            // response is returned as it is.
+            _ => response
+        }
+    }
+
+    /// [HTTP redirect fetch](http://fetch.spec.whatwg.org/#http-redirect-fetch)
+    fn http_redirect_fetch(&mut self, resource_task: &ResourceTask, cors_flag: bool,
+                           response: Response) -> Response {
+        // Steps 1-3
+        let location = match header_value(&response.headers, "Location") {
+            Some(location) => location,
+            None => return response
+        };
+        let base = response.url.clone().unwrap_or(self.url.clone());
+        let location = match UrlParser::new().base_url(&base).parse(location.as_slice().trim()) {
+            Ok(location) => location,
+            Err(_) => return Response::network_error()
+        };
+        // Step 4
+        match location.scheme.as_slice() {
+            "http" | "https" => {},
+            _ => return Response::network_error()
+        }
+        // Steps 5-6
+        if self.redirect_count == 20 {
+            return Response::network_error();
+        }
+        self.redirect_count += 1;
+        // Step 7
+        let has_credentials = location.username().map_or(false, |name| !name.is_empty()) ||
+                              location.password().is_some();
+        if cors_flag && has_credentials {
+            return Response::network_error();
+        }
+        // Step 8
+        let code = status_code(&response);
+        if ((code == 301 || code == 302) && self.method == Post) ||
+           (code == 303 && self.method != Head) {
+            self.method = Get;
+            self.body = None;
+        }
+        // Steps 9-10
+        let cors_flag = cors_flag || match self.origin {
+            Some(ref origin) => self.mode != NoCORS && !same_origin(origin, &location),
+            None => false
+        };
+        self.url = location;
+        // Step 11
+        self.main_fetch(resource_task, cors_flag, true)
+    }
+
+    /// [HTTP network or cache fetch](http://fetch.spec.whatwg.org/#http-network-or-cache-fetch)
+    fn http_network_or_cache_fetch(&mut self, resource_task: &ResourceTask,
+                                   _credentials_flag: bool,
+                                   _authentication_fetch_flag: bool) -> Response {
+        // Steps 1-2
+        let mut headers = self.headers.clone();
+        // Step 3
+        match self.referer {
+            RefererUrl(ref referer) => {
+                headers.set_raw("Referer", vec![referer.serialize().into_bytes()]);
+            },
+            _ => {}
+        }
+        // Step 4
+        let unsafe_method = self.method != Get && self.method != Head;
+        if self.force_origin_header || self.response_tainting == CORSTainting || unsafe_method {
+            headers.set_raw("Origin", vec![self.serialized_origin().into_bytes()]);
+        }
+        // Steps 5-7: Credentials and cookies are left to the network layer, and the HTTP cache
+        // is used according to the request's cache mode.
+        let (method, body) = (self.method.clone(), self.body.clone());
+        let stream = self.body_chan.is_some();
+        self.load(resource_task, method, headers, body, stream)
+    }
+
+    /// [CORS preflight fetch](http://fetch.spec.whatwg.org/#cors-preflight-fetch)
+    fn preflight_fetch(&mut self, resource_task: &ResourceTask) -> Response {
+        // Steps 1-3
+        let mut headers = Headers::new();
+        let method_name = self.method.to_string();
+        headers.set_raw("Access-Control-Request-Method", vec![method_name.clone().into_bytes()]);
+        let header_names = self.non_simple_header_names();
+        if !header_names.is_empty() {
+            headers.set_raw("Access-Control-Request-Headers",
+                            vec![header_names.connect(",").into_bytes()]);
+        }
+        headers.set_raw("Origin", vec![self.serialized_origin().into_bytes()]);
+        match self.referer {
+            RefererUrl(ref referer) => {
+                headers.set_raw("Referer", vec![referer.serialize().into_bytes()]);
+            },
+            _ => {}
+        }
+
+        // Step 4
+        let response = self.load(resource_task, Options, headers, None, false);
+
+        // Step 5
+        let code = status_code(&response);
+        if !self.cors_check(&response) || code < 200 || code > 299 {
+            return Response::network_error();
+        }
+        // Substeps 1-3 (parsing rules: http://fetch.spec.whatwg.org/#http-new-header-syntax)
+        let mut methods = header_list(&response.headers, "Access-Control-Allow-Methods");
+        let allowed_headers: Vec<String> =
+            header_list(&response.headers, "Access-Control-Allow-Headers")
+                .iter().map(|name| name.to_ascii_lower()).collect();
+        // Substep 4
+        if methods.is_empty() || self.mode == ForcedPreflightMode {
+            methods = vec!(method_name.clone());
+        }
+        // Substep 5
+        if !is_simple_method(&self.method) && !methods.contains(&method_name) {
+            return Response::network_error();
+        }
+        // Substep 6
+        if header_names.iter().any(|name| !allowed_headers.contains(name)) {
+            return Response::network_error();
+        }
+        // Substeps 7-9
+        let max_age = header_value(&response.headers, "Access-Control-Max-Age").and_then(|age| {
+            from_str::<uint>(age.as_slice().trim())
+        }).unwrap_or(0);
+        // Substeps 10-12
+        let (origin, url) = match self.origin {
+            Some(ref origin) => (origin.clone(), self.url.clone()),
+            None => return response
+        };
+        let credentials = self.credentials_mode == Include;
+        match self.cache {
+            Some(ref mut cache) => {
+                let methods = methods.iter().filter_map(|method| {
+                    from_str::<Method>(method.as_slice())
+                });
+                for method in methods {
+                    let details = cache_details(&origin, &url, credentials);
+                    cache.match_method_and_update(details, method, max_age);
+                }
+                for name in allowed_headers.iter() {
+                    let details = cache_details(&origin, &url, credentials);
+                    cache.match_header_and_update(details, name.as_slice(), max_age);
+                }
+            },
+            None => {}
+        }
+        response
+    }
+
+    /// Whether the CORS preflight cache says that a preflight isn't needed.
+    fn preflight_cache_matches(&mut self) -> bool {
+        let origin = match self.origin {
+            Some(ref origin) => origin.clone(),
+            None => return false
+        };
+        let url = self.url.clone();
+        let method = self.method.clone();
+        let credentials = self.credentials_mode == Include;
+        let header_names = self.non_simple_header_names();
+        let forced = self.mode == ForcedPreflightMode;
+        let cache = match self.cache {
+            Some(ref mut cache) => cache,
+            None => return false
+        };
+        let method_match = cache.match_method(cache_details(&origin, &url, credentials),
+                                              method.clone());
+        if !method_match && (!is_simple_method(&method) || forced) {
+            return false;
+        }
+        header_names.iter().all(|name| {
+            cache.match_header(cache_details(&origin, &url, credentials), name.as_slice())
+        })
+    }
+
+    /// [CORS check](http://fetch.spec.whatwg.org/#concept-cors-check)
+    fn cors_check(&self, response: &Response) -> bool {
+        // Steps 1-2
+        let origin = match header_value(&response.headers, "Access-Control-Allow-Origin") {
+            Some(origin) => origin.as_slice().trim().to_string(),
+            None => return false
+        };
+        // Step 3
+        if self.credentials_mode != Include && origin.as_slice() == "*" {
+            return true;
+        }
+        // Step 4
+        if origin != self.serialized_origin() {
+            return false;
+        }
+        // Step 5
+        if self.credentials_mode != Include {
+            return true;
+        }
+        // Steps 6-7
+        match header_value(&response.headers, "Access-Control-Allow-Credentials") {
+            Some(ref credentials) => credentials.as_slice().trim() == "true",
+            None => false
+        }
+    }
+
+    /// The ASCII serialization of the request's origin, which is "null" if it has none.
+    fn serialized_origin(&self) -> String {
+        match self.origin {
+            Some(ref origin) => serialize_origin(origin),
+            None => "null".to_string()
+        }
+    }
+
+    /// The sorted, lowercased names of the request's headers that aren't simple headers.
+    fn non_simple_header_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.headers.iter().filter(|header| {
+            !is_simple_header(header.name(), header.value_string().as_slice())
+        }).map(|header| header.name().to_ascii_lower()).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Loads the request's URL through the resource task, which answers HTTP requests from the
+    /// HTTP cache when it can, but doesn't follow redirects. If `stream` is set, the response is
+    /// returned as soon as its metadata arrives and its body is left in `pending_body`.
+    fn load(&mut self, resource_task: &ResourceTask, method: Method, headers: Headers,
+            body: Option<Vec<u8>>, stream: bool) -> Response {
+        let (start_chan, start_port) = channel();
+        let mut load_data = LoadData::new(self.url.clone(), start_chan);
+        load_data.method = method;
+        load_data.headers = headers;
+        load_data.data = body;
+        load_data.cache_mode = self.cache_mode.clone();
+        load_data.follow_redirects = false;
+        resource_task.send(Load(load_data));
+
+        let load_response = match start_port.recv_opt() {
+            Ok(load_response) => load_response,
+            Err(()) => return Response::network_error()
+        };
+        // The body of an earlier load, such as a redirect, is no longer needed.
+        self.pending_body.take().map(discard_body);
+        let body = if stream {
+            self.pending_body = Some(load_response.progress_port);
+            Receiving(vec!())
+        } else {
+            let mut body = vec!();
+            loop {
+                match load_response.progress_port.recv_opt() {
+                    Ok(Payload(data)) => body.push_all(data.as_slice()),
+                    Ok(Done(Ok(()))) => break,
+                    Ok(Done(Err(_))) | Err(()) => return Response::network_error()
+                }
+            }
+            BodyDone(body)
+        };
+
+        let metadata = load_response.metadata;
+        let mut response = Response::new();
+        response.url = Some(metadata.final_url);
+        match metadata.status {
+            Some(RawStatus(code, reason)) => {
+                response.status = FromPrimitive::from_u16(code);
+                response.status_message = reason;
+            }
+            None => {
+                response.status = None;
+                response.status_message = "".to_string();
+            }
         }
+        response.headers = metadata.headers.unwrap_or(Headers::new());
+        // Loaders other than the HTTP one only report the content type in the metadata.
+        if !response.headers.has::<ContentType>() {
+            match metadata.content_type {
+                Some((ref type_, ref subtype)) => {
+                    let content_type = match metadata.charset {
+                        Some(ref charset) => format!("{}/{};charset={}", type_, subtype, charset),
+                        None => format!("{}/{}", type_, subtype)
+                    };
+                    response.headers.set_raw("Content-Type", vec![content_type.into_bytes()]);
+                },
+                None => {}
+            }
+        }
+        response.body = body;
         response
     }
 }
+
+/// Waits for the rest of a body nobody wants, since loaders fail if their consumer goes away.
+fn discard_body(progress_port: Receiver<ProgressMsg>) {
+    loop {
+        match progress_port.recv_opt() {
+            Ok(Payload(_)) => {},
+            Ok(Done(_)) | Err(()) => return
+        }
+    }
+}
+
+/// Whether two URLs have the [same origin](http://tools.ietf.org/html/rfc6454#section-5).
+fn same_origin(a: &Url, b: &Url) -> bool {
+    a.scheme == b.scheme && a.host() == b.host() && a.port_or_default() == b.port_or_default()
+}
+
+/// The [ASCII serialization](http://tools.ietf.org/html/rfc6454#section-6.2) of a URL's origin.
+fn serialize_origin(url: &Url) -> String {
+    let host = url.serialize_host().unwrap_or("".to_string());
+    match url.port() {
+        Some(port) => format!("{}://{}:{}", url.scheme, host, port),
+        None => format!("{}://{}", url.scheme, host)
+    }
+}
+
+fn cache_details(origin: &Url, url: &Url, credentials: bool) -> CacheRequestDetails {
+    CacheRequestDetails {
+        origin: origin.clone(),
+        destination: url.clone(),
+        credentials: credentials
+    }
+}
+
+/// The status code of a response, which is 0 if it has none.
+fn status_code(response: &Response) -> u16 {
+    response.status.clone().map_or(0, |status| status as u16)
+}
+
+/// The comma-separated values of a header.
+fn header_list(headers: &Headers, name: &str) -> Vec<String> {
+    match header_value(headers, name) {
+        Some(value) => {
+            value.as_slice().split(',')
+                            .map(|item| item.trim().to_string())
+                            .filter(|item| !item.is_empty())
+                            .collect()
+        },
+        None => vec!()
+    }
+}
+
+/// A [simple method](http://fetch.spec.whatwg.org/#simple-method)
+fn is_simple_method(method: &Method) -> bool {
+    match *method {
+        Get | Head | Post => true,
+        _ => false
+    }
+}
+
+/// A [simple header](http://fetch.spec.whatwg.org/#simple-header)
+fn is_simple_header(name: &str, value: &str) -> bool {
+    match name.to_ascii_lower().as_slice() {
+        "accept" | "accept-language" | "content-language" => true,
+        "content-type" => {
+            let essence = value.split(';').next().unwrap_or("").trim().to_ascii_lower();
+            match essence.as_slice() {
+                "text/plain" | "application/x-www-form-urlencoded" | "multipart/form-data" => true,
+                _ => false
+            }
+        },
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CORSMode, Include, Request, XMLHttpRequest};
+    use super::{is_simple_header, same_origin, serialize_origin};
+
+    use fetch::response::Response;
+    use url::Url;
+
+    fn cors_request(origin: &str) -> Request {
+        let mut request = Request::new(Url::parse("http://example.org/data").unwrap(),
+                                       XMLHttpRequest);
+        request.origin = Some(Url::parse(origin).unwrap());
+        request.mode = CORSMode;
+        request
+    }
+
+    fn response(headers: &[(&str, &str)]) -> Response {
+        let mut response = Response::new();
+        for &(name, value) in headers.iter() {
+            response.headers.set_raw(name.to_string(), vec![value.as_bytes().to_vec()]);
+        }
+        response
+    }
+
+    #[test]
+    fn test_serialize_origin() {
+        assert_eq!(serialize_origin(&Url::parse("http://servo.org/a?b#c").unwrap()).as_slice(),
+                   "http://servo.org");
+        assert_eq!(serialize_origin(&Url::parse("https://servo.org:8443/").unwrap()).as_slice(),
+                   "https://servo.org:8443");
+        assert!(same_origin(&Url::parse("http://servo.org/a").unwrap(),
+                            &Url::parse("http://servo.org:80/b").unwrap()));
+        assert!(!same_origin(&Url::parse("http://servo.org/").unwrap(),
+                             &Url::parse("https://servo.org/").unwrap()));
+    }
+
+    #[test]
+    fn test_simple_headers() {
+        assert!(is_simple_header("Accept", "text/html"));
+        assert!(is_simple_header("Content-Type", "text/plain; charset=utf-8"));
+        assert!(!is_simple_header("Content-Type", "application/json"));
+        assert!(!is_simple_header("X-Requested-With", "XMLHttpRequest"));
+    }
+
+    #[test]
+    fn test_cors_check() {
+        let request = cors_request("http://servo.org/page.html");
+        assert!(!request.cors_check(&response(&[])));
+        assert!(request.cors_check(&response(&[("Access-Control-Allow-Origin", "*")])));
+        assert!(request.cors_check(&response(&[("Access-Control-Allow-Origin",
+                                                "http://servo.org")])));
+        assert!(!request.cors_check(&response(&[("Access-Control-Allow-Origin",
+                                                 "http://example.com")])));
+    }
+
+    #[test]
+    fn test_cors_check_with_credentials() {
+        let mut request = cors_request("http://servo.org/page.html");
+        request.credentials_mode = Include;
+        assert!(!request.cors_check(&response(&[("Access-Control-Allow-Origin", "*")])));
+        assert!(!request.cors_check(&response(&[("Access-Control-Allow-Origin",
+                                                 "http://servo.org")])));
+        assert!(request.cors_check(&response(&[("Access-Control-Allow-Origin",
+                                                "http://servo.org"),
+                                               ("Access-Control-Allow-Credentials", "true")])));
+    }
+
+    #[test]
+    fn test_non_simple_header_names() {
+        let mut request = cors_request("http://servo.org/");
+        request.headers.set_raw("Accept", vec![b"*/*".to_vec()]);
+        request.headers.set_raw("X-B", vec![b"1".to_vec()]);
+        request.headers.set_raw("X-A", vec![b"2".to_vec()]);
+        assert_eq!(request.non_simple_header_names(), vec!("x-a".to_string(), "x-b".to_string()));
+    }
+}
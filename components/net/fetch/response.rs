@@ -16,7 +16,8 @@ pub enum ResponseType {
     CORS,
     Default,
     Error,
-    Opaque
+    Opaque,
+    OpaqueRedirect
 }
 
 /// [Response termination reason](http://fetch.spec.whatwg.org/#concept-response-termination-reason)
@@ -58,6 +59,8 @@ pub struct Response {
     pub url: Option<Url>,
     /// `None` can be considered a StatusCode of `0`.
     pub status: Option<StatusCode>,
+    /// The [status message](http://fetch.spec.whatwg.org/#concept-response-status-message)
+    pub status_message: String,
     pub headers: Headers,
     pub body: ResponseBody,
     /// [Internal response](http://fetch.spec.whatwg.org/#concept-internal-response), only used if the Response is a filtered response
@@ -71,6 +74,7 @@ impl Response {
             termination_reason: None,
             url: None,
             status: Some(StatusOk),
+            status_message: "OK".to_string(),
             headers: Headers::new(),
             body: Empty,
             internal_response: None
@@ -83,6 +87,7 @@ impl Response {
             termination_reason: None,
             url: None,
             status: None,
+            status_message: "".to_string(),
             headers: Headers::new(),
             body: Empty,
             internal_response: None
@@ -120,23 +125,89 @@ impl Response {
                 response.response_type = filter_type;
             },
             CORS => {
+                let exposed = exposed_header_names(&old_headers);
                 let headers = old_headers.iter().filter(|header| {
-                    match header.name().to_ascii_lower().as_slice() {
+                    let name = header.name().to_ascii_lower();
+                    match name.as_slice() {
                         "cache-control" | "content-language" |
-                        "content-type" | "expires" | "last-modified" | "Pragma" => false,
-                        // XXXManishearth handle Access-Control-Expose-Headers
-                        _ => true
+                        "content-type" | "expires" | "last-modified" | "pragma" => true,
+                        _ => exposed.contains(&name)
                     }
                 }).collect();
                 response.headers = headers;
                 response.response_type = filter_type;
             },
-            Opaque => {
+            Opaque | OpaqueRedirect => {
                 response.headers = Headers::new();
                 response.status = None;
+                response.status_message = "".to_string();
                 response.body = Empty;
+                response.response_type = filter_type;
             }
         }
         response
     }
 }
+
+/// The lowercased names listed by the `Access-Control-Expose-Headers` headers of a response.
+fn exposed_header_names(headers: &Headers) -> Vec<String> {
+    let mut names = vec!();
+    for value in headers.get_raw("Access-Control-Expose-Headers").unwrap_or(&[]).iter() {
+        let value = String::from_utf8_lossy(value.as_slice()).into_string();
+        for name in value.as_slice().split(',') {
+            let name = name.trim().to_ascii_lower();
+            if !name.is_empty() {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Basic, CORS, Opaque, Response, Receiving};
+
+    use hyper::header::Headers;
+
+    fn response(headers: &[(&str, &str)]) -> Response {
+        let mut response = Response::new();
+        let mut raw = Headers::new();
+        for &(name, value) in headers.iter() {
+            raw.set_raw(name.to_string(), vec![value.as_bytes().to_vec()]);
+        }
+        response.headers = raw;
+        response.body = Receiving(b"body".to_vec());
+        response
+    }
+
+    #[test]
+    fn test_basic_filter() {
+        let filtered = response(&[("Set-Cookie", "a=b"), ("X-Custom", "1")]).to_filtered(Basic);
+        assert!(filtered.response_type == Basic);
+        assert!(filtered.headers.get_raw("Set-Cookie").is_none());
+        assert!(filtered.headers.get_raw("X-Custom").is_some());
+        assert!(filtered.internal_response.unwrap().headers.get_raw("Set-Cookie").is_some());
+    }
+
+    #[test]
+    fn test_cors_filter() {
+        let filtered = response(&[("Content-Type", "text/plain"),
+                                  ("X-Secret", "1"),
+                                  ("X-Exposed", "2"),
+                                  ("Access-Control-Expose-Headers", "x-exposed")])
+            .to_filtered(CORS);
+        assert!(filtered.headers.get_raw("Content-Type").is_some());
+        assert!(filtered.headers.get_raw("X-Secret").is_none());
+        assert!(filtered.headers.get_raw("X-Exposed").is_some());
+    }
+
+    #[test]
+    fn test_opaque_filter() {
+        let filtered = response(&[("Content-Type", "text/plain")]).to_filtered(Opaque);
+        assert!(filtered.response_type == Opaque);
+        assert!(filtered.status.is_none());
+        assert!(filtered.headers.iter().next().is_none());
+        assert!(filtered.internal_response.is_some());
+    }
+}
@@ -0,0 +1,893 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! An implementation of an [HTTP cache](http://tools.ietf.org/html/rfc7234) for the resource
+//! task. Responses are kept in memory and, if a directory is given, written to disk so that they
+//! are kept across runs.
+
+use hyper::header::Headers;
+use hyper::http::RawStatus;
+use hyper::method::{Get, Method};
+use serialize::json;
+use std::ascii::AsciiExt;
+use std::cmp::max;
+use std::collections::HashMap;
+use std::hash::hash;
+use std::io;
+use std::io::File;
+use std::io::fs;
+use std::mem;
+use sync::{Arc, Mutex};
+use time::{strptime, Timespec};
+use url::Url;
+
+/// The HTTP cache shared by the loads of a resource task.
+pub type SharedHttpCache = Arc<Mutex<HttpCache>>;
+
+/// Statuses that may be stored without explicit freshness information
+/// (http://tools.ietf.org/html/rfc7231#section-6.1).
+static CACHEABLE_BY_DEFAULT: &'static [u16] = &[200, 203, 204, 300, 301, 404, 405, 410, 414, 501];
+
+/// A `Cache-Control` directive, with its argument if it has one.
+type Directive = (String, Option<String>);
+
+/// The values of a header, joined as if they had been sent in a single header.
+pub fn header_value(headers: &Headers, name: &str) -> Option<String> {
+    headers.get_raw(name).map(|raw| {
+        let values: Vec<String> = raw.iter().map(|value| {
+            String::from_utf8_lossy(value.as_slice()).into_string()
+        }).collect();
+        values.connect(", ")
+    })
+}
+
+/// Parses an RFC 2616 compliant date/time string.
+fn parse_http_date(value: &str) -> Option<Timespec> {
+    strptime(value, "%a, %d %b %Y %T %Z").or_else(|_| {
+        strptime(value, "%A, %d-%b-%y %T %Z")
+    }).or_else(|_| {
+        strptime(value, "%c")
+    }).ok().map(|tm| tm.to_timespec())
+}
+
+fn date_header(headers: &Headers, name: &str) -> Option<Timespec> {
+    header_value(headers, name).and_then(|value| parse_http_date(value.as_slice().trim()))
+}
+
+/// The directives of the `Cache-Control` headers, with lowercased names.
+fn cache_directives(headers: &Headers) -> Vec<Directive> {
+    let value = match header_value(headers, "Cache-Control") {
+        Some(value) => value,
+        None => return vec!(),
+    };
+    value.as_slice().split(',').filter_map(|directive| {
+        let mut parts = directive.splitn(1, '=');
+        let name = parts.next().unwrap().trim().to_ascii_lower();
+        if name.is_empty() {
+            return None;
+        }
+        let argument = parts.next().map(|argument| argument.trim().trim_chars('"').to_string());
+        Some((name, argument))
+    }).collect()
+}
+
+fn has_directive(directives: &[Directive], name: &str) -> bool {
+    directives.iter().any(|&(ref directive, _)| directive.as_slice() == name)
+}
+
+/// The `max-age` directive, in seconds.
+fn max_age(directives: &[Directive]) -> Option<i64> {
+    directives.iter().filter_map(|&(ref directive, ref argument)| {
+        match (directive.as_slice(), argument) {
+            ("max-age", &Some(ref seconds)) => from_str(seconds.as_slice()),
+            _ => None,
+        }
+    }).next()
+}
+
+/// Whether the headers ask for revalidation with the HTTP/1.0 `Pragma: no-cache`, which is
+/// only honoured when there is no `Cache-Control` header.
+fn has_pragma_no_cache(headers: &Headers) -> bool {
+    headers.get_raw("Cache-Control").is_none() && match header_value(headers, "Pragma") {
+        Some(pragma) => pragma.as_slice().to_ascii_lower().as_slice().contains("no-cache"),
+        None => false,
+    }
+}
+
+/// The names of the request headers that a response varies on, lowercased.
+fn vary_names(headers: &Headers) -> Vec<String> {
+    match header_value(headers, "Vary") {
+        Some(vary) => {
+            vary.as_slice().split(',')
+                           .map(|name| name.trim().to_ascii_lower())
+                           .filter(|name| !name.is_empty())
+                           .collect()
+        }
+        None => vec!(),
+    }
+}
+
+/// Whether a request asks that the cache is not used to answer it, or to store its response.
+pub fn request_forbids_store(request_headers: &Headers) -> bool {
+    has_directive(cache_directives(request_headers).as_slice(), "no-store")
+}
+
+/// Whether a request asks that a stored response is revalidated before it is used.
+pub fn request_forbids_reuse(request_headers: &Headers) -> bool {
+    let directives = cache_directives(request_headers);
+    has_directive(directives.as_slice(), "no-cache") ||
+        max_age(directives.as_slice()) == Some(0) ||
+        has_pragma_no_cache(request_headers)
+}
+
+/// Whether the response to a request may be stored
+/// (http://tools.ietf.org/html/rfc7234#section-3).
+pub fn is_storable(method: &Method,
+                   request_headers: &Headers,
+                   status: &RawStatus,
+                   response_headers: &Headers) -> bool {
+    if *method != Get {
+        return false;
+    }
+
+    let directives = cache_directives(response_headers);
+    if request_forbids_store(request_headers) ||
+            has_directive(directives.as_slice(), "no-store") {
+        return false;
+    }
+    if request_headers.get_raw("Authorization").is_some() &&
+            !has_directive(directives.as_slice(), "public") {
+        return false;
+    }
+    if vary_names(response_headers).iter().any(|name| name.as_slice() == "*") {
+        return false;
+    }
+
+    let &RawStatus(code, _) = status;
+    CACHEABLE_BY_DEFAULT.contains(&code) ||
+        max_age(directives.as_slice()).is_some() ||
+        response_headers.get_raw("Expires").is_some()
+}
+
+/// A stored response.
+#[deriving(Clone)]
+pub struct CachedResponse {
+    pub status: RawStatus,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+    /// The values of the request headers named by the `Vary` header of the response, which a
+    /// request must have for the response to be used.
+    vary: Vec<(String, Option<String>)>,
+    /// When the request that the response answers was sent.
+    request_time: Timespec,
+    /// When the response was received.
+    response_time: Timespec,
+}
+
+impl CachedResponse {
+    pub fn new(request_headers: &Headers,
+               status: RawStatus,
+               headers: Headers,
+               body: Vec<u8>,
+               request_time: Timespec,
+               response_time: Timespec) -> CachedResponse {
+        let vary = vary_names(&headers).into_iter().map(|name| {
+            let value = header_value(request_headers, name.as_slice());
+            (name, value)
+        }).collect();
+        CachedResponse {
+            status: status,
+            headers: headers,
+            body: body,
+            vary: vary,
+            request_time: request_time,
+            response_time: response_time,
+        }
+    }
+
+    /// Whether the response may answer a request with the given headers.
+    fn matches(&self, request_headers: &Headers) -> bool {
+        self.vary.iter().all(|&(ref name, ref value)| {
+            header_value(request_headers, name.as_slice()) == *value
+        })
+    }
+
+    fn date(&self) -> Timespec {
+        date_header(&self.headers, "Date").unwrap_or(self.response_time)
+    }
+
+    /// How long the response stays fresh for, in seconds
+    /// (http://tools.ietf.org/html/rfc7234#section-4.2.1).
+    fn freshness_lifetime(&self) -> i64 {
+        match max_age(cache_directives(&self.headers).as_slice()) {
+            Some(seconds) => return seconds,
+            None => {}
+        }
+
+        match header_value(&self.headers, "Expires") {
+            // Invalid dates, such as "0", are in the past.
+            Some(expires) => {
+                return match parse_http_date(expires.as_slice().trim()) {
+                    Some(expires) => max(0, expires.sec - self.date().sec),
+                    None => 0,
+                }
+            }
+            None => {}
+        }
+
+        // Heuristic freshness, as a tenth of the time since the resource was last modified.
+        let RawStatus(code, _) = self.status;
+        match date_header(&self.headers, "Last-Modified") {
+            Some(modified) if CACHEABLE_BY_DEFAULT.contains(&code) => {
+                max(0, (self.date().sec - modified.sec) / 10)
+            }
+            _ => 0,
+        }
+    }
+
+    /// The age of the response at `now`, in seconds
+    /// (http://tools.ietf.org/html/rfc7234#section-4.2.3).
+    fn current_age(&self, now: Timespec) -> i64 {
+        let apparent_age = max(0, self.response_time.sec - self.date().sec);
+        let age = header_value(&self.headers, "Age").and_then(|age| {
+            from_str::<i64>(age.as_slice().trim())
+        }).unwrap_or(0);
+        let corrected_age = age + self.response_time.sec - self.request_time.sec;
+        max(apparent_age, corrected_age) + now.sec - self.response_time.sec
+    }
+
+    /// Whether the response may be used at `now` without revalidating it.
+    pub fn is_fresh(&self, now: Timespec) -> bool {
+        let directives = cache_directives(&self.headers);
+        if has_directive(directives.as_slice(), "no-cache") || has_pragma_no_cache(&self.headers) {
+            return false;
+        }
+        self.current_age(now) < self.freshness_lifetime()
+    }
+
+    /// Whether the response has a validator that a conditional request can be made with.
+    pub fn can_revalidate(&self) -> bool {
+        self.headers.get_raw("ETag").is_some() || self.headers.get_raw("Last-Modified").is_some()
+    }
+
+    /// Makes a request conditional on the resource having changed since this response.
+    pub fn add_validators(&self, request_headers: &mut Headers) {
+        match header_value(&self.headers, "ETag") {
+            Some(etag) => request_headers.set_raw("If-None-Match", vec![etag.into_bytes()]),
+            None => {}
+        }
+        match header_value(&self.headers, "Last-Modified") {
+            Some(modified) => {
+                request_headers.set_raw("If-Modified-Since", vec![modified.into_bytes()])
+            }
+            None => {}
+        }
+    }
+}
+
+/// The most that the bodies of the responses kept in memory may add up to.
+static MAX_MEMORY_SIZE: uint = 32 * 1024 * 1024;
+
+/// The most disk space that the stored responses may take up.
+static MAX_DISK_SIZE: uint = 256 * 1024 * 1024;
+
+/// The metadata of a stored response as it is written to disk. The body is written next to it,
+/// as is.
+#[deriving(Decodable, Encodable)]
+struct DiskEntry {
+    url: String,
+    status: u16,
+    reason: String,
+    headers: Vec<(String, String)>,
+    vary: Vec<(String, Option<String>)>,
+    request_time: i64,
+    response_time: i64,
+}
+
+impl DiskEntry {
+    fn from_response(key: &String, response: &CachedResponse) -> DiskEntry {
+        let RawStatus(code, ref reason) = response.status;
+        DiskEntry {
+            url: key.clone(),
+            status: code,
+            reason: reason.to_string(),
+            headers: response.headers.iter().map(|header| {
+                (header.name().to_string(), header.value_string())
+            }).collect(),
+            vary: response.vary.clone(),
+            request_time: response.request_time.sec,
+            response_time: response.response_time.sec,
+        }
+    }
+
+    fn into_response(self, body: Vec<u8>) -> CachedResponse {
+        let mut headers = Headers::new();
+        for (name, value) in self.headers.into_iter() {
+            headers.set_raw(name, vec![value.into_bytes()]);
+        }
+        CachedResponse {
+            status: RawStatus(self.status, self.reason.into_string()),
+            headers: headers,
+            body: body,
+            vary: self.vary,
+            request_time: Timespec::new(self.request_time, 0),
+            response_time: Timespec::new(self.response_time, 0),
+        }
+    }
+}
+
+/// Reads the metadata and the body of a response from disk.
+fn read_response(stem: &Path) -> Option<(DiskEntry, Vec<u8>)> {
+    let metadata = File::open(&stem.with_extension("json")).read_to_string();
+    let body = File::open(&stem.with_extension("body")).read_to_end();
+    match (metadata, body) {
+        (Ok(metadata), Ok(body)) => match json::decode::<DiskEntry>(metadata.as_slice()) {
+            Ok(entry) => Some((entry, body)),
+            Err(e) => {
+                error!("http_cache: can't decode {}: {}", stem.display(), e);
+                None
+            }
+        },
+        (Err(e), _) | (_, Err(e)) => {
+            error!("http_cache: can't read {}: {}", stem.display(), e);
+            None
+        }
+    }
+}
+
+/// Replaces a file in one step, so that readers never see it half written.
+fn write_file(stem: &Path, extension: &str, data: &[u8], id: uint) {
+    let path = stem.with_extension(extension);
+    let temporary = stem.with_extension(format!("{}.{}.tmp", extension, id));
+    match File::create(&temporary).write(data).and_then(|()| fs::rename(&temporary, &path)) {
+        Ok(()) => {}
+        Err(e) => {
+            error!("http_cache: can't write {}: {}", path.display(), e);
+            let _ = fs::unlink(&temporary);
+        }
+    }
+}
+
+/// Changes to the files of the cache. They are made once the cache is unlocked, so that other
+/// loads don't wait for the disk.
+pub struct DiskWrite {
+    /// The files of a response, with its metadata and, unless only the metadata changed, its
+    /// body.
+    write: Option<(Path, String, Option<Vec<u8>>)>,
+    /// The files of the responses to remove.
+    remove: Vec<Path>,
+    /// Tells the temporary files of concurrent writes apart.
+    id: uint,
+}
+
+impl DiskWrite {
+    fn none() -> DiskWrite {
+        DiskWrite {
+            write: None,
+            remove: vec!(),
+            id: 0,
+        }
+    }
+
+    pub fn run(self) {
+        for stem in self.remove.iter() {
+            for extension in ["json", "body"].iter() {
+                let path = stem.with_extension(*extension);
+                if path.exists() {
+                    let _ = fs::unlink(&path);
+                }
+            }
+        }
+        match self.write {
+            Some((ref stem, ref metadata, ref body)) => {
+                match *body {
+                    Some(ref body) => write_file(stem, "body", body.as_slice(), self.id),
+                    None => {}
+                }
+                write_file(stem, "json", metadata.as_bytes(), self.id);
+            }
+            None => {}
+        }
+    }
+}
+
+/// A response whose files are on disk.
+struct DiskRecord {
+    /// The path of the files, without their extension.
+    stem: Path,
+    /// The size of the files.
+    size: uint,
+}
+
+/// Lists the responses in a cache directory, least recently written first.
+fn read_directory(directory: &Path) -> Vec<DiskRecord> {
+    let paths = match fs::readdir(directory) {
+        Ok(paths) => paths,
+        Err(e) => {
+            error!("http_cache: can't list {}: {}", directory.display(), e);
+            return vec!();
+        }
+    };
+    let mut records: Vec<(u64, DiskRecord)> = paths.into_iter().filter(|path| {
+        path.extension_str() == Some("json")
+    }).filter_map(|path| {
+        let stem = path.dir_path().join(path.filestem().unwrap());
+        match (fs::stat(&path), fs::stat(&stem.with_extension("body"))) {
+            (Ok(metadata), Ok(body)) => {
+                Some((metadata.modified, DiskRecord {
+                    stem: stem,
+                    size: (metadata.size + body.size) as uint,
+                }))
+            }
+            _ => None,
+        }
+    }).collect();
+    records.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+    records.into_iter().map(|(_, record)| record).collect()
+}
+
+/// The key of the responses for a URL. The fragment isn't part of it, since it is never sent to
+/// the server.
+fn cache_key(url: &Url) -> String {
+    let mut url = url.clone();
+    url.fragment = None;
+    url.serialize()
+}
+
+fn body_size(responses: &[CachedResponse]) -> uint {
+    responses.iter().fold(0, |size, response| size + response.body.len())
+}
+
+pub struct HttpCache {
+    /// The responses kept in memory for each URL, one for each combination of the request
+    /// headers that the responses vary on.
+    entries: HashMap<String, Vec<CachedResponse>>,
+    /// The URLs of the responses in memory, least recently used first.
+    memory_lru: Vec<String>,
+    /// The size of the bodies of the responses in memory.
+    memory_size: uint,
+    memory_limit: uint,
+    /// The directory that responses are also written to.
+    directory: Option<Path>,
+    /// The responses on disk, least recently read or written first.
+    disk_lru: Vec<DiskRecord>,
+    /// The size of the files of the responses on disk.
+    disk_size: uint,
+    disk_limit: uint,
+    /// The number of writes to disk so far.
+    writes: uint,
+}
+
+impl HttpCache {
+    pub fn new(directory: Option<Path>) -> HttpCache {
+        match directory {
+            Some(ref directory) if !directory.is_dir() => {
+                match fs::mkdir_recursive(directory, io::USER_RWX) {
+                    Ok(()) => {}
+                    Err(e) => error!("http_cache: can't create {}: {}", directory.display(), e),
+                }
+            }
+            _ => {}
+        }
+        let disk_lru = match directory {
+            Some(ref directory) => read_directory(directory),
+            None => vec!(),
+        };
+        HttpCache {
+            entries: HashMap::new(),
+            memory_lru: vec!(),
+            memory_size: 0,
+            memory_limit: MAX_MEMORY_SIZE,
+            directory: directory,
+            disk_size: disk_lru.iter().fold(0, |size, record| size + record.size),
+            disk_lru: disk_lru,
+            disk_limit: MAX_DISK_SIZE,
+            writes: 0,
+        }
+    }
+
+    pub fn new_shared(directory: Option<Path>) -> SharedHttpCache {
+        Arc::new(Mutex::new(HttpCache::new(directory)))
+    }
+
+    /// Finds a stored response that may answer a request for `url` with the given headers.
+    /// The response may be stale.
+    pub fn lookup(&mut self, url: &Url, request_headers: &Headers) -> Option<CachedResponse> {
+        let key = cache_key(url);
+        self.read_from_disk(&key);
+        self.touch(&key);
+        self.entries.get(&key).and_then(|responses| {
+            responses.iter().find(|response| response.matches(request_headers))
+        }).map(|response| response.clone())
+    }
+
+    /// Stores a response for `url`, replacing the one stored for the same request headers.
+    /// The returned write must be run once the cache is unlocked.
+    pub fn store(&mut self, url: &Url, response: CachedResponse) -> DiskWrite {
+        let key = cache_key(url);
+        self.read_from_disk(&key);
+        let mut responses = self.forget_in_memory(&key);
+        responses.retain(|stored| stored.vary != response.vary);
+        let write = self.disk_write(&key, &response, true);
+        responses.push(response);
+        self.keep_in_memory(key, responses);
+        write
+    }
+
+    /// Updates the stored response that a `304 Not Modified` response to a conditional request
+    /// validated with the new headers, and returns it with the write that must be run once the
+    /// cache is unlocked.
+    pub fn validate(&mut self,
+                    url: &Url,
+                    request_headers: &Headers,
+                    not_modified_headers: &Headers,
+                    request_time: Timespec,
+                    response_time: Timespec) -> Option<(CachedResponse, DiskWrite)> {
+        let key = cache_key(url);
+        self.touch(&key);
+        let updated = {
+            let response = self.entries.get_mut(&key).and_then(|responses| {
+                responses.iter_mut().find(|response| response.matches(request_headers))
+            });
+            let response = match response {
+                Some(response) => response,
+                None => return None,
+            };
+            for header in not_modified_headers.iter() {
+                // The length of the stored body is still the stored length.
+                if header.name().eq_ignore_ascii_case("Content-Length") {
+                    continue;
+                }
+                response.headers.set_raw(header.name().to_string(),
+                                         vec![header.value_string().into_bytes()]);
+            }
+            response.request_time = request_time;
+            response.response_time = response_time;
+            response.clone()
+        };
+        let write = self.disk_write(&key, &updated, false);
+        Some((updated, write))
+    }
+
+    /// Forgets the responses stored for `url`, after a request that may have changed the
+    /// resource. The returned write must be run once the cache is unlocked.
+    pub fn invalidate(&mut self, url: &Url) -> DiskWrite {
+        let key = cache_key(url);
+        self.forget_in_memory(&key);
+        let mut write = DiskWrite::none();
+        for record in self.forget_on_disk(&key).into_iter() {
+            write.remove.push(record.stem);
+        }
+        write
+    }
+
+    fn stem(&self, key: &String, vary: &Vec<(String, Option<String>)>) -> Option<Path> {
+        self.directory.as_ref().map(|directory| {
+            directory.join(format!("{:016x}-{:016x}", hash(key), hash(vary)))
+        })
+    }
+
+    /// Marks the responses for a URL as the most recently used ones in memory.
+    fn touch(&mut self, key: &String) {
+        match self.memory_lru.iter().position(|used| used == key) {
+            Some(index) => {
+                let key = self.memory_lru.remove(index).unwrap();
+                self.memory_lru.push(key);
+            }
+            None => {}
+        }
+    }
+
+    /// Keeps the responses for a URL in memory, and evicts the least recently used responses of
+    /// other URLs until their bodies fit into the memory limit again.
+    fn keep_in_memory(&mut self, key: String, responses: Vec<CachedResponse>) {
+        self.memory_size += body_size(responses.as_slice());
+        self.entries.insert(key.clone(), responses);
+        self.memory_lru.push(key);
+        while self.memory_size > self.memory_limit && self.memory_lru.len() > 1 {
+            let evicted = self.memory_lru.remove(0).unwrap();
+            match self.entries.remove(&evicted) {
+                Some(responses) => self.memory_size -= body_size(responses.as_slice()),
+                None => {}
+            }
+        }
+    }
+
+    fn forget_in_memory(&mut self, key: &String) -> Vec<CachedResponse> {
+        self.memory_lru.retain(|used| used != key);
+        match self.entries.remove(key) {
+            Some(responses) => {
+                self.memory_size -= body_size(responses.as_slice());
+                responses
+            }
+            None => vec!(),
+        }
+    }
+
+    /// Takes the records of the files of the responses for a URL out of the disk index. Other
+    /// URLs with the same hash are included.
+    fn forget_on_disk(&mut self, key: &String) -> Vec<DiskRecord> {
+        let prefix = format!("{:016x}-", hash(key));
+        let records = mem::replace(&mut self.disk_lru, vec!());
+        let (forgotten, kept) = records.partition(|record| {
+            match record.stem.filename_str() {
+                Some(name) => name.starts_with(prefix.as_slice()),
+                None => false,
+            }
+        });
+        self.disk_lru = kept;
+        self.disk_size -= forgotten.iter().fold(0, |size, record| size + record.size);
+        forgotten
+    }
+
+    /// Records that a response is written to disk, and evicts the least recently used responses
+    /// from disk until they fit into the disk limit again.
+    fn disk_write(&mut self, key: &String, response: &CachedResponse, with_body: bool)
+                  -> DiskWrite {
+        let stem = match self.stem(key, &response.vary) {
+            Some(stem) => stem,
+            None => return DiskWrite::none(),
+        };
+        let metadata = json::encode(&DiskEntry::from_response(key, response));
+        let size = metadata.len() + response.body.len();
+        match self.disk_lru.iter().position(|record| record.stem == stem) {
+            Some(index) => self.disk_size -= self.disk_lru.remove(index).unwrap().size,
+            None => {}
+        }
+        self.disk_lru.push(DiskRecord {
+            stem: stem.clone(),
+            size: size,
+        });
+        self.disk_size += size;
+
+        let mut remove = vec!();
+        while self.disk_size > self.disk_limit && self.disk_lru.len() > 1 {
+            let evicted = self.disk_lru.remove(0).unwrap();
+            self.disk_size -= evicted.size;
+            remove.push(evicted.stem);
+        }
+
+        self.writes += 1;
+        let body = if with_body {
+            Some(response.body.clone())
+        } else {
+            None
+        };
+        DiskWrite {
+            write: Some((stem, metadata, body)),
+            remove: remove,
+            id: self.writes,
+        }
+    }
+
+    /// Loads the responses stored on disk for a URL if they aren't in memory yet.
+    fn read_from_disk(&mut self, key: &String) {
+        if self.entries.contains_key(key) || self.directory.is_none() {
+            return;
+        }
+        let mut responses = vec!();
+        for record in self.forget_on_disk(key).into_iter() {
+            match read_response(&record.stem) {
+                Some((entry, body)) => {
+                    // Another URL can have the same hash.
+                    if entry.url == *key {
+                        responses.push(entry.into_response(body));
+                    }
+                    self.disk_size += record.size;
+                    self.disk_lru.push(record);
+                }
+                None => {}
+            }
+        }
+        if !responses.is_empty() {
+            self.keep_in_memory(key.clone(), responses);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CachedResponse, HttpCache, is_storable, request_forbids_reuse};
+
+    use hyper::header::Headers;
+    use hyper::http::RawStatus;
+    use hyper::method::{Get, Post};
+    use std::io::TempDir;
+    use time::Timespec;
+    use url::Url;
+
+    fn headers(list: &[(&str, &str)]) -> Headers {
+        let mut headers = Headers::new();
+        for &(name, value) in list.iter() {
+            headers.set_raw(name.to_string(), vec![value.as_bytes().to_vec()]);
+        }
+        headers
+    }
+
+    fn ok() -> RawStatus {
+        RawStatus(200, "OK".into_string())
+    }
+
+    /// A response received at the given time, in seconds.
+    fn response(list: &[(&str, &str)], received: i64) -> CachedResponse {
+        let time = Timespec::new(received, 0);
+        CachedResponse::new(&Headers::new(), ok(), headers(list), b"body".to_vec(), time, time)
+    }
+
+    fn at(seconds: i64) -> Timespec {
+        Timespec::new(seconds, 0)
+    }
+
+    #[test]
+    fn test_max_age() {
+        let response = response(&[("Cache-Control", "public, max-age=60")], 1000);
+        assert!(response.is_fresh(at(1059)));
+        assert!(!response.is_fresh(at(1060)));
+    }
+
+    #[test]
+    fn test_age_header() {
+        let response = response(&[("Cache-Control", "max-age=60"), ("Age", "50")], 1000);
+        assert!(response.is_fresh(at(1009)));
+        assert!(!response.is_fresh(at(1010)));
+    }
+
+    #[test]
+    fn test_expires() {
+        // 784111777 is Sun, 06 Nov 1994 08:49:37 GMT.
+        let response = response(&[("Date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+                                  ("Expires", "Sun, 06 Nov 1994 08:50:37 GMT")], 784111777);
+        assert!(response.is_fresh(at(784111777 + 59)));
+        assert!(!response.is_fresh(at(784111777 + 60)));
+    }
+
+    #[test]
+    fn test_invalid_expires() {
+        let response = response(&[("Expires", "0")], 1000);
+        assert!(!response.is_fresh(at(1000)));
+    }
+
+    #[test]
+    fn test_max_age_overrides_expires() {
+        let response = response(&[("Cache-Control", "max-age=60"), ("Expires", "0")], 1000);
+        assert!(response.is_fresh(at(1000)));
+    }
+
+    #[test]
+    fn test_no_cache() {
+        let response = response(&[("Cache-Control", "no-cache, max-age=60")], 1000);
+        assert!(!response.is_fresh(at(1000)));
+        let response = response(&[("Pragma", "no-cache"),
+                                  ("Expires", "Sun, 06 Nov 2044 08:49:37 GMT")], 1000);
+        assert!(!response.is_fresh(at(1000)));
+    }
+
+    #[test]
+    fn test_heuristic_freshness() {
+        let response = response(&[("Date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+                                  ("Last-Modified", "Sun, 06 Nov 1994 07:49:37 GMT")], 784111777);
+        assert!(response.is_fresh(at(784111777 + 359)));
+        assert!(!response.is_fresh(at(784111777 + 360)));
+    }
+
+    #[test]
+    fn test_storable() {
+        let request = Headers::new();
+        assert!(is_storable(&Get, &request, &ok(), &Headers::new()));
+        assert!(!is_storable(&Post, &request, &ok(), &Headers::new()));
+        assert!(!is_storable(&Get, &request, &ok(), &headers(&[("Cache-Control", "no-store")])));
+        assert!(!is_storable(&Get, &headers(&[("Cache-Control", "no-store")]), &ok(),
+                             &Headers::new()));
+        assert!(!is_storable(&Get, &request, &ok(), &headers(&[("Vary", "*")])));
+        assert!(!is_storable(&Get, &request, &RawStatus(302, "Found".into_string()),
+                             &Headers::new()));
+        assert!(is_storable(&Get, &request, &RawStatus(302, "Found".into_string()),
+                            &headers(&[("Cache-Control", "max-age=60")])));
+    }
+
+    #[test]
+    fn test_request_forbids_reuse() {
+        assert!(!request_forbids_reuse(&Headers::new()));
+        assert!(request_forbids_reuse(&headers(&[("Cache-Control", "no-cache")])));
+        assert!(request_forbids_reuse(&headers(&[("Cache-Control", "max-age=0")])));
+        assert!(request_forbids_reuse(&headers(&[("Pragma", "no-cache")])));
+    }
+
+    #[test]
+    fn test_vary() {
+        let url = Url::parse("http://example.com/").unwrap();
+        let english = headers(&[("Accept-Language", "en")]);
+        let french = headers(&[("Accept-Language", "fr")]);
+        let vary = headers(&[("Vary", "Accept-Language")]);
+        let mut cache = HttpCache::new(None);
+        cache.store(&url, CachedResponse::new(&english, ok(), vary.clone(), b"hello".to_vec(),
+                                              at(0), at(0)));
+        assert_eq!(cache.lookup(&url, &english).unwrap().body, b"hello".to_vec());
+        assert!(cache.lookup(&url, &french).is_none());
+
+        cache.store(&url, CachedResponse::new(&french, ok(), vary, b"bonjour".to_vec(),
+                                              at(0), at(0)));
+        assert_eq!(cache.lookup(&url, &english).unwrap().body, b"hello".to_vec());
+        assert_eq!(cache.lookup(&url, &french).unwrap().body, b"bonjour".to_vec());
+    }
+
+    #[test]
+    fn test_validate() {
+        let url = Url::parse("http://example.com/").unwrap();
+        let mut cache = HttpCache::new(None);
+        cache.store(&url, response(&[("Cache-Control", "max-age=60"), ("ETag", "\"v1\"")], 0));
+        let stale = cache.lookup(&url, &Headers::new()).unwrap();
+        assert!(!stale.is_fresh(at(100)));
+        assert!(stale.can_revalidate());
+
+        let mut request = Headers::new();
+        stale.add_validators(&mut request);
+        assert_eq!(request.get_raw("If-None-Match").unwrap()[0], b"\"v1\"".to_vec());
+
+        let (validated, _) = cache.validate(&url, &Headers::new(),
+                                            &headers(&[("Cache-Control", "max-age=120")]),
+                                            at(100), at(100)).unwrap();
+        assert_eq!(validated.body, b"body".to_vec());
+        assert!(validated.is_fresh(at(219)));
+        assert!(cache.lookup(&url, &Headers::new()).unwrap().is_fresh(at(219)));
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let url = Url::parse("http://example.com/").unwrap();
+        let mut cache = HttpCache::new(None);
+        cache.store(&url, response(&[], 0));
+        cache.invalidate(&url);
+        assert!(cache.lookup(&url, &Headers::new()).is_none());
+    }
+
+    #[test]
+    fn test_fragment_is_not_part_of_key() {
+        let mut cache = HttpCache::new(None);
+        cache.store(&Url::parse("http://example.com/#top").unwrap(), response(&[], 0));
+        let url = Url::parse("http://example.com/#bottom").unwrap();
+        assert!(cache.lookup(&url, &Headers::new()).is_some());
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let first = Url::parse("http://example.com/first").unwrap();
+        let second = Url::parse("http://example.com/second").unwrap();
+        let third = Url::parse("http://example.com/third").unwrap();
+        let mut cache = HttpCache::new(None);
+        cache.memory_limit = 8;
+        cache.store(&first, response(&[], 0));
+        cache.store(&second, response(&[], 0));
+        // Using the first response makes the second one the least recently used.
+        assert!(cache.lookup(&first, &Headers::new()).is_some());
+        cache.store(&third, response(&[], 0));
+        assert!(cache.lookup(&first, &Headers::new()).is_some());
+        assert!(cache.lookup(&second, &Headers::new()).is_none());
+        assert!(cache.lookup(&third, &Headers::new()).is_some());
+    }
+
+    #[test]
+    fn test_disk() {
+        let directory = TempDir::new("http_cache").unwrap();
+        let first = Url::parse("http://example.com/first").unwrap();
+        let second = Url::parse("http://example.com/second").unwrap();
+        {
+            let mut cache = HttpCache::new(Some(directory.path().clone()));
+            cache.store(&first, response(&[], 0)).run();
+            cache.store(&second, response(&[], 0)).run();
+        }
+
+        let mut cache = HttpCache::new(Some(directory.path().clone()));
+        assert_eq!(cache.lookup(&first, &Headers::new()).unwrap().body, b"body".to_vec());
+
+        // Writing a third response evicts the least recently used one from disk.
+        let third = Url::parse("http://example.com/third").unwrap();
+        cache.disk_limit = cache.disk_size;
+        cache.store(&third, response(&[], 0)).run();
+        let mut cache = HttpCache::new(Some(directory.path().clone()));
+        assert!(cache.lookup(&first, &Headers::new()).is_some());
+        assert!(cache.lookup(&second, &Headers::new()).is_none());
+        assert!(cache.lookup(&third, &Headers::new()).is_some());
+    }
+}
@@ -2,20 +2,76 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use content_decoder::{ACCEPT_ENCODING, content_codings, decoder};
+use fetch::request::{DefaultCache, ForceCache, NoCache, NoStore, OnlyIfCached, Reload};
+use http_cache::{CachedResponse, SharedHttpCache, is_storable};
+use http_cache::{request_forbids_reuse, request_forbids_store};
+use proxy::{Connector, proxy_authorization};
 use resource_task::{Metadata, Payload, Done, TargetedLoadResponse, LoadData, start_sending_opt, ResponseSenders};
+use resource_task::NetworkConfig;
 
+use devtools_traits::{DevtoolsControlChan, HttpRequest, HttpResponse, NetworkEventMsg};
+use devtools_traits::{NetworkTimings, RequestFailed, RequestSent, ResponseReceived};
+use devtools_traits::is_network_monitored;
 use log;
 use std::collections::HashSet;
 use hyper::client::Request;
+use hyper::header::Headers;
 use hyper::header::common::{ContentLength, ContentType, Host, Location};
+use hyper::http::RawStatus;
 use hyper::method::{Get, Head};
 use hyper::status::Redirection;
-use std::io::Reader;
+use std::io::{EndOfFile, Reader};
+use std::sync::atomic::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
 use servo_util::task::spawn_named;
+use time;
 use url::{Url, UrlParser};
 
-pub fn factory(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
-    spawn_named("http_loader", proc() load(load_data, start_chan))
+/// Identifies the loads reported to the devtools network monitor.
+static mut NEXT_REQUEST_ID: AtomicUint = INIT_ATOMIC_UINT;
+
+/// The most of a response body that is kept for the network monitor.
+static MAX_MONITORED_BODY_SIZE: uint = 1024 * 1024;
+
+pub fn factory(load_data: LoadData,
+               start_chan: Sender<TargetedLoadResponse>,
+               devtools_chan: Option<DevtoolsControlChan>,
+               http_cache: SharedHttpCache,
+               config: NetworkConfig) {
+    spawn_named("http_loader", proc() {
+        load(load_data, start_chan, devtools_chan, http_cache, config)
+    })
+}
+
+fn header_list(headers: &Headers) -> Vec<(String, String)> {
+    headers.iter().map(|header| (header.name().to_string(), header.value_string())).collect()
+}
+
+fn elapsed_ms(start_ns: u64, end_ns: u64) -> u64 {
+    (end_ns - start_ns) / 1000000
+}
+
+fn report_response(devtools_chan: &Option<DevtoolsControlChan>,
+                   request_id: &str,
+                   status: &RawStatus,
+                   headers: &Headers,
+                   body: Vec<u8>,
+                   timings: NetworkTimings) {
+    devtools_chan.as_ref().map(|chan| {
+        let RawStatus(code, ref reason) = *status;
+        chan.send(NetworkEventMsg(request_id.to_string(), ResponseReceived(HttpResponse {
+            status: Some((code, reason.to_string())),
+            headers: header_list(headers),
+            body: body,
+            timings: timings,
+        })));
+    });
+}
+
+fn report_failure(devtools_chan: &Option<DevtoolsControlChan>, request_id: &str, err: &str) {
+    devtools_chan.as_ref().map(|chan| {
+        chan.send(NetworkEventMsg(request_id.to_string(), RequestFailed(err.to_string())));
+    });
 }
 
 fn send_error(url: Url, err: String, senders: ResponseSenders) {
@@ -28,11 +84,30 @@ fn send_error(url: Url, err: String, senders: ResponseSenders) {
     };
 }
 
-fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
-    // FIXME: At the time of writing this FIXME, servo didn't have any central
-    //        location for configuration. If you're reading this and such a
-    //        repository DOES exist, please update this constant to use it.
-    let max_redirects = 50u;
+/// Answers a load with a response from the HTTP cache.
+fn send_cached(url: Url, cached: CachedResponse, senders: ResponseSenders) {
+    let mut metadata = Metadata::default(url);
+    metadata.set_content_type(match cached.headers.get() {
+        Some(&ContentType(ref mime)) => Some(mime),
+        None => None
+    });
+    metadata.headers = Some(cached.headers);
+    metadata.status = Some(cached.status);
+
+    match start_sending_opt(senders, metadata) {
+        Ok(progress_chan) => {
+            let _ = progress_chan.send_opt(Payload(cached.body));
+            let _ = progress_chan.send_opt(Done(Ok(())));
+        }
+        Err(()) => {}
+    }
+}
+
+fn load(load_data: LoadData,
+        start_chan: Sender<TargetedLoadResponse>,
+        devtools_chan: Option<DevtoolsControlChan>,
+        http_cache: SharedHttpCache,
+        config: NetworkConfig) {
     let mut iters = 0u;
     let mut url = load_data.url.clone();
     let mut redirected_to = HashSet::new();
@@ -42,11 +117,19 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
         eventual_consumer: load_data.consumer
     };
 
+    // Cache-Control and Pragma request headers ask for the same things as the cache modes.
+    let cache_mode = match load_data.cache_mode {
+        DefaultCache if request_forbids_store(&load_data.headers) => NoStore,
+        DefaultCache if request_forbids_reuse(&load_data.headers) => NoCache,
+        mode => mode,
+    };
+    let use_cache = load_data.method == Get && cache_mode != NoStore;
+
     // Loop to handle redirects.
     loop {
         iters = iters + 1;
 
-        if iters > max_redirects {
+        if iters > config.max_redirects {
             send_error(url, "too many redirects".to_string(), senders);
             return;
         }
@@ -67,9 +150,38 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
             }
         }
 
+        let cached = if use_cache && cache_mode != Reload {
+            http_cache.lock().lookup(&url, &load_data.headers)
+        } else {
+            None
+        };
+        // A stale stored response, used if the server says that it hasn't been modified.
+        let revalidating = match cached {
+            Some(cached) => match cache_mode {
+                ForceCache | OnlyIfCached => {
+                    send_cached(url, cached, senders);
+                    return;
+                }
+                DefaultCache if cached.is_fresh(time::get_time()) => {
+                    send_cached(url, cached, senders);
+                    return;
+                }
+                _ if cached.can_revalidate() => Some(cached),
+                _ => None,
+            },
+            None if cache_mode == OnlyIfCached => {
+                send_error(url, "not in the HTTP cache".to_string(), senders);
+                return;
+            }
+            None => None,
+        };
+
         info!("requesting {:s}", url.serialize());
 
-        let mut req = match Request::new(load_data.method.clone(), url.clone()) {
+        let proxy = config.proxy.proxy_for(&url).map(|proxy| proxy.clone());
+        let mut connector = Connector::new(proxy.clone(), config.timeout_ms);
+        let mut req = match Request::with_connector(load_data.method.clone(), url.clone(),
+                                                    &mut connector) {
             Ok(req) => req,
             Err(e) => {
                 send_error(url, e.to_string(), senders);
@@ -81,23 +193,66 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
         let host = req.headers().get::<Host>().unwrap().clone();
         *req.headers_mut() = load_data.headers.clone();
         req.headers_mut().set(host);
+        // Requests that a proxy forwards carry its credentials. Tunnels send them when they are
+        // opened instead.
+        match proxy {
+            Some(ref proxy) if url.scheme.as_slice() == "http" => {
+                match proxy_authorization(proxy) {
+                    Some(credentials) => {
+                        req.headers_mut().set_raw("Proxy-Authorization",
+                                                  vec![credentials.into_bytes()])
+                    }
+                    None => {}
+                }
+            }
+            _ => {}
+        }
         // FIXME(seanmonstar): use AcceptEncoding from Hyper once available
-        //if !req.headers.has::<AcceptEncoding>() {
-            // We currently don't support HTTP Compression (FIXME #2587)
-            req.headers_mut().set_raw("Accept-Encoding", vec![b"identity".to_vec()]);
-        //}
+        if req.headers().get_raw("Accept-Encoding").is_none() {
+            req.headers_mut().set_raw("Accept-Encoding", vec![ACCEPT_ENCODING.as_bytes().to_vec()]);
+        }
+        match revalidating {
+            Some(ref cached) => cached.add_validators(req.headers_mut()),
+            None => {}
+        }
+        match load_data.data {
+            Some(ref data) => req.headers_mut().set(ContentLength(data.len())),
+            None => {
+                match load_data.method {
+                    Get | Head => (),
+                    _ => req.headers_mut().set(ContentLength(0))
+                }
+            }
+        }
+
+        let request_id = unsafe { NEXT_REQUEST_ID.fetch_add(1, SeqCst) }.to_string();
+        let start_ns = time::precise_time_ns();
+        let request_time = time::get_time();
+        devtools_chan.as_ref().map(|chan| {
+            let now = time::get_time();
+            chan.send(NetworkEventMsg(request_id.clone(), RequestSent(HttpRequest {
+                url: url.serialize(),
+                method: load_data.method.to_string(),
+                headers: header_list(req.headers()),
+                body: if is_network_monitored() { load_data.data.clone() } else { None },
+                start_time: now.sec as u64 * 1000 + now.nsec as u64 / 1000000,
+            })));
+        });
+
         let writer = match load_data.data {
             Some(ref data) => {
-                req.headers_mut().set(ContentLength(data.len()));
                 let mut writer = match req.start() {
                     Ok(w) => w,
                     Err(e) => {
+                        report_failure(&devtools_chan, request_id.as_slice(),
+                                       e.to_string().as_slice());
                         send_error(url, e.to_string(), senders);
                         return;
                     }
                 };
                 match writer.write(data.as_slice()) {
                     Err(e) => {
+                        report_failure(&devtools_chan, request_id.as_slice(), e.desc);
                         send_error(url, e.desc.to_string(), senders);
                         return;
                     }
@@ -106,26 +261,29 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
                 writer
             },
             None => {
-                match load_data.method {
-                    Get | Head => (),
-                    _ => req.headers_mut().set(ContentLength(0))
-                }
                 match req.start() {
                     Ok(w) => w,
                     Err(e) => {
+                        report_failure(&devtools_chan, request_id.as_slice(),
+                                       e.to_string().as_slice());
                         send_error(url, e.to_string(), senders);
                         return;
                     }
                 }
             }
         };
+        let sent_ns = time::precise_time_ns();
         let mut response = match writer.send() {
             Ok(r) => r,
             Err(e) => {
+                report_failure(&devtools_chan, request_id.as_slice(),
+                               e.to_string().as_slice());
                 send_error(url, e.to_string(), senders);
                 return;
             }
         };
+        let response_start_ns = time::precise_time_ns();
+        let response_time = time::get_time();
 
         // Dump headers, but only do the iteration if info!() is enabled.
         info!("got HTTP response {}, headers:", response.status);
@@ -135,7 +293,8 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
             }
         }
 
-        if response.status.class() == Redirection {
+        // Fetches follow redirects themselves, so they get the redirect response.
+        if response.status.class() == Redirection && load_data.follow_redirects {
             match response.headers.get::<Location>() {
                 Some(&Location(ref new_url)) => {
                     // CORS (http://fetch.spec.whatwg.org/#http-fetch, status section, point 9, 10)
@@ -143,6 +302,8 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
                         Some(ref c) => {
                             if c.preflight {
                                 // The preflight lied
+                                report_failure(&devtools_chan, request_id.as_slice(),
+                                               "Preflight fetch inconsistent with main fetch");
                                 send_error(url, "Preflight fetch inconsistent with main fetch".to_string(), senders);
                                 return;
                             } else {
@@ -155,11 +316,19 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
                     let new_url = match UrlParser::new().base_url(&url).parse(new_url.as_slice()) {
                         Ok(u) => u,
                         Err(e) => {
+                            report_failure(&devtools_chan, request_id.as_slice(),
+                                           e.to_string().as_slice());
                             send_error(url, e.to_string(), senders);
                             return;
                         }
                     };
                     info!("redirecting to {}", new_url);
+                    report_response(&devtools_chan, request_id.as_slice(), response.status_raw(),
+                                    &response.headers, vec!(), NetworkTimings {
+                        send: elapsed_ms(start_ns, sent_ns),
+                        wait: elapsed_ms(sent_ns, response_start_ns),
+                        receive: 0,
+                    });
                     url = new_url;
                     continue;
                 }
@@ -167,34 +336,130 @@ fn load(load_data: LoadData, start_chan: Sender<TargetedLoadResponse>) {
             }
         }
 
-        let mut metadata = Metadata::default(url);
-        metadata.set_content_type(match response.headers.get() {
+        let RawStatus(code, _) = *response.status_raw();
+        if code == 304 && revalidating.is_some() {
+            report_response(&devtools_chan, request_id.as_slice(), response.status_raw(),
+                            &response.headers, vec!(), NetworkTimings {
+                send: elapsed_ms(start_ns, sent_ns),
+                wait: elapsed_ms(sent_ns, response_start_ns),
+                receive: 0,
+            });
+            let validated = http_cache.lock().validate(&url, &load_data.headers,
+                                                       &response.headers, request_time,
+                                                       response_time);
+            let validated = match validated {
+                Some((validated, write)) => {
+                    write.run();
+                    validated
+                }
+                None => revalidating.unwrap(),
+            };
+            send_cached(url, validated, senders);
+            return;
+        }
+
+        // Requests that may change the resource make the responses stored for it outdated.
+        match load_data.method {
+            Get | Head => {}
+            _ if code < 400 => {
+                let write = http_cache.lock().invalidate(&url);
+                write.run();
+            }
+            _ => {}
+        }
+        // Loads that don't follow redirects see the redirects themselves, which must not be
+        // stored, since ordinary loads of the same URL would be answered with them.
+        let storable = use_cache && response.status.class() != Redirection &&
+                       is_storable(&load_data.method, &load_data.headers,
+                                   response.status_raw(), &response.headers);
+
+        // Responses without a body have nothing to decode.
+        let codings = match (&load_data.method, code) {
+            (&Head, _) | (_, 204) | (_, 304) => vec!(),
+            _ => content_codings(&response.headers),
+        };
+        let status = response.status_raw().clone();
+        let mut headers = response.headers.clone();
+        if !codings.is_empty() {
+            // The length of the decoded body isn't known until it has all been read.
+            headers.remove::<ContentLength>();
+        }
+
+        let mut metadata = Metadata::default(url.clone());
+        metadata.set_content_type(match headers.get() {
             Some(&ContentType(ref mime)) => Some(mime),
             None => None
         });
-        metadata.headers = Some(response.headers.clone());
-        metadata.status = Some(response.status_raw().clone());
+        metadata.headers = Some(headers.clone());
+        metadata.status = Some(status.clone());
+
+        let mut body_reader = match decoder(&mut response, codings.as_slice()) {
+            Ok(reader) => reader,
+            Err(e) => {
+                report_failure(&devtools_chan, request_id.as_slice(), e.as_slice());
+                send_error(url, e, senders);
+                return;
+            }
+        };
 
         let progress_chan = match start_sending_opt(senders, metadata) {
             Ok(p) => p,
-            _ => return
+            _ => {
+                report_failure(&devtools_chan, request_id.as_slice(), "load cancelled");
+                return;
+            }
         };
+        // Keep a copy of the body if the response is cached, or the start of it if the network
+        // monitor is listening.
+        let monitored = devtools_chan.is_some() && is_network_monitored();
+        let mut body = vec!();
         loop {
             let mut buf = Vec::with_capacity(1024);
 
             unsafe { buf.set_len(1024); }
-            match response.read(buf.as_mut_slice()) {
+            match body_reader.read(buf.as_mut_slice()) {
                 Ok(len) => {
                     unsafe { buf.set_len(len); }
+                    if storable || (monitored && body.len() < MAX_MONITORED_BODY_SIZE) {
+                        body.push_all(buf.as_slice());
+                    }
                     if progress_chan.send_opt(Payload(buf)).is_err() {
                         // The send errors when the receiver is out of scope,
                         // which will happen if the fetch has timed out (or has been aborted)
                         // so we don't need to continue with the loading of the file here.
+                        report_failure(&devtools_chan, request_id.as_slice(), "load cancelled");
                         return;
                     }
                 }
-                Err(_) => {
-                    let _ = progress_chan.send_opt(Done(Ok(())));
+                Err(e) => {
+                    // A corrupt compressed body fails the load rather than being cut short.
+                    let result = match e.kind {
+                        EndOfFile => Ok(()),
+                        _ => Err(format!("error reading the response body: {}", e.desc)),
+                    };
+                    if storable && result.is_ok() {
+                        let stored = CachedResponse::new(&load_data.headers,
+                                                         status.clone(),
+                                                         headers.clone(),
+                                                         body.clone(),
+                                                         request_time,
+                                                         response_time);
+                        // The files are written once the cache is unlocked.
+                        let write = http_cache.lock().store(&url, stored);
+                        write.run();
+                    }
+                    let _ = progress_chan.send_opt(Done(result));
+                    if monitored {
+                        body.truncate(MAX_MONITORED_BODY_SIZE);
+                    } else {
+                        body = vec!();
+                    }
+                    report_response(&devtools_chan, request_id.as_slice(), &status,
+                                    &headers, body, NetworkTimings {
+                        send: elapsed_ms(start_ns, sent_ns),
+                        wait: elapsed_ms(sent_ns, response_start_ns),
+                        receive: elapsed_ms(response_start_ns, time::precise_time_ns()),
+                    });
                     break;
                 }
             }
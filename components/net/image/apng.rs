@@ -0,0 +1,373 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A decoder for animated PNGs.
+//!
+//! libpng doesn't know about animation, so each frame is rewritten as a standalone PNG that
+//! libpng decodes. See <https://wiki.mozilla.org/APNG_Specification>.
+
+use image::base::{Image, RawFrame, DisposeNone, DisposeBackground, DisposePrevious};
+use image::base::{BlendSource, BlendOver};
+use image::base::{MAX_DIMENSION, MAX_DECODED_SIZE, fits_in, frame_size};
+
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::size::Size2D;
+use png;
+
+pub static SIGNATURE: &'static [u8] = b"\x89PNG\r\n\x1a\n";
+
+pub struct Chunk<'a> {
+    pub kind: &'a [u8],
+    pub data: &'a [u8],
+}
+
+/// Splits a PNG into its chunks, stopping at the first malformed or incomplete one.
+pub fn chunks<'a>(buffer: &'a [u8]) -> Vec<Chunk<'a>> {
+    let mut chunks = vec!();
+    if !buffer.starts_with(SIGNATURE) {
+        return chunks
+    }
+
+    let mut position = SIGNATURE.len();
+    while position + 12 <= buffer.len() {
+        let length = read_u32(buffer.slice_from(position)) as uint;
+        if position + 12 + length > buffer.len() {
+            break
+        }
+        chunks.push(Chunk {
+            kind: buffer.slice(position + 4, position + 8),
+            data: buffer.slice(position + 8, position + 8 + length),
+        });
+        position += 12 + length;
+    }
+    chunks
+}
+
+pub fn read_u32(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32 << 24) | (bytes[1] as u32 << 16) | (bytes[2] as u32 << 8) | bytes[3] as u32
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    (bytes[0] as u16 << 8) | bytes[1] as u16
+}
+
+fn push_u32(output: &mut Vec<u8>, value: u32) {
+    output.push_all(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]);
+}
+
+/// The CRC-32 that PNG uses to check chunks.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in bytes.iter() {
+        crc ^= byte as u32;
+        for _ in range(0u, 8) {
+            crc = if crc & 1 != 0 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+pub fn push_chunk(output: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    push_u32(output, data.len() as u32);
+    let start = output.len();
+    output.push_all(kind);
+    output.push_all(data);
+    let crc = crc32(output.slice_from(start));
+    push_u32(output, crc);
+}
+
+/// Whether `buffer` is a PNG with an animation control chunk, which must come before the image
+/// data.
+pub fn is_animated_png(buffer: &[u8]) -> bool {
+    chunks(buffer).iter()
+                  .take_while(|chunk| chunk.kind != b"IDAT")
+                  .any(|chunk| chunk.kind == b"acTL")
+}
+
+/// The contents of a frame control chunk.
+struct FrameControl {
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    /// The frame delay in milliseconds.
+    delay: u32,
+    dispose_op: u8,
+    blend_op: u8,
+}
+
+impl FrameControl {
+    fn parse(data: &[u8]) -> Option<FrameControl> {
+        if data.len() < 26 {
+            return None
+        }
+        let delay_numerator = read_u16(data.slice_from(20)) as u32;
+        let delay_denominator = match read_u16(data.slice_from(22)) {
+            0 => 100,
+            denominator => denominator as u32,
+        };
+        Some(FrameControl {
+            width: read_u32(data.slice_from(4)),
+            height: read_u32(data.slice_from(8)),
+            x: read_u32(data.slice_from(12)),
+            y: read_u32(data.slice_from(16)),
+            delay: delay_numerator * 1000 / delay_denominator,
+            dispose_op: data[24],
+            blend_op: data[25],
+        })
+    }
+}
+
+/// A frame of an animation and its compressed image data.
+struct Frame {
+    control: FrameControl,
+    data: Vec<u8>,
+}
+
+/// Decodes every frame of an animated PNG. Returns `None` if the animation is malformed, in which
+/// case the caller shows the default image instead.
+pub fn decode(buffer: &[u8]) -> Option<Image> {
+    let chunks = chunks(buffer);
+    let header = try_opt!(chunks.iter().next().and_then(|chunk| {
+        if chunk.kind == b"IHDR" && chunk.data.len() == 13 { Some(chunk.data) } else { None }
+    }));
+    let width = read_u32(header);
+    let height = read_u32(header.slice_from(4));
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return None
+    }
+    let canvas_size = try_opt!(frame_size(width, height));
+
+    // Chunks like the palette and transparency that every frame needs in order to be decoded.
+    let mut shared_chunks = vec!();
+    let mut play_count = None;
+    let mut frames: Vec<Frame> = vec!();
+    let mut seen_image_data = false;
+    for chunk in chunks.iter().skip(1) {
+        if chunk.kind == b"acTL" {
+            if chunk.data.len() < 8 {
+                return None
+            }
+            play_count = match read_u32(chunk.data.slice_from(4)) {
+                0 => None,
+                plays => Some(plays),
+            };
+        } else if chunk.kind == b"fcTL" {
+            let control = try_opt!(FrameControl::parse(chunk.data));
+            let rect = Rect(Point2D(control.x, control.y), Size2D(control.width, control.height));
+            if !fits_in(&rect, width, height) {
+                return None
+            }
+            // Each frame is composited onto a canvas of its own.
+            let decoded_size = try_opt!(canvas_size.checked_mul(&(frames.len() + 1)));
+            if decoded_size > MAX_DECODED_SIZE {
+                return None
+            }
+            frames.push(Frame {
+                control: control,
+                data: vec!(),
+            });
+        } else if chunk.kind == b"IDAT" {
+            seen_image_data = true;
+            // The default image is only part of the animation if a frame control chunk comes
+            // before it.
+            match frames.last_mut() {
+                Some(frame) => frame.data.push_all(chunk.data),
+                None => {}
+            }
+        } else if chunk.kind == b"fdAT" {
+            // Frame data chunks are image data chunks with a sequence number.
+            match frames.last_mut() {
+                Some(ref mut frame) if chunk.data.len() >= 4 => {
+                    frame.data.push_all(chunk.data.slice_from(4))
+                }
+                _ => return None,
+            }
+        } else if chunk.kind == b"IEND" {
+            break
+        } else if !seen_image_data {
+            shared_chunks.push(chunk);
+        }
+    }
+
+    let mut raw_frames = vec!();
+    for (index, Frame { control, data }) in frames.into_iter().enumerate() {
+        let mut frame_header = header.to_vec();
+        frame_header.slice_mut(0, 8).copy_from(&[
+            (control.width >> 24) as u8, (control.width >> 16) as u8,
+            (control.width >> 8) as u8, control.width as u8,
+            (control.height >> 24) as u8, (control.height >> 16) as u8,
+            (control.height >> 8) as u8, control.height as u8,
+        ]);
+
+        let mut png = SIGNATURE.to_vec();
+        push_chunk(&mut png, b"IHDR", frame_header.as_slice());
+        for chunk in shared_chunks.iter() {
+            push_chunk(&mut png, chunk.kind, chunk.data);
+        }
+        push_chunk(&mut png, b"IDAT", data.as_slice());
+        push_chunk(&mut png, b"IEND", &[]);
+
+        let image = match png::load_png_from_memory(png.as_slice()) {
+            Ok(image) => image,
+            Err(_) => return None,
+        };
+        raw_frames.push(RawFrame {
+            rect: Rect(Point2D(control.x, control.y), Size2D(control.width, control.height)),
+            pixels: to_rgba(image),
+            delay: control.delay,
+            disposal: match control.dispose_op {
+                1 => DisposeBackground,
+                // There is nothing to restore before the first frame.
+                2 if index == 0 => DisposeBackground,
+                2 => DisposePrevious,
+                _ => DisposeNone,
+            },
+            blend: if control.blend_op == 1 { BlendOver } else { BlendSource },
+        });
+    }
+
+    Image::from_frames(width, height, raw_frames, play_count)
+}
+
+/// Converts a decoded PNG into RGBA pixels with straight alpha.
+fn to_rgba(image: png::Image) -> Vec<u8> {
+    match image.pixels {
+        png::RGBA8(pixels) => pixels,
+        png::RGB8(pixels) => {
+            pixels.as_slice().chunks(3).flat_map(|pixel| {
+                vec!(pixel[0], pixel[1], pixel[2], 255).into_iter()
+            }).collect()
+        }
+        png::K8(pixels) => {
+            pixels.iter().flat_map(|&gray| vec!(gray, gray, gray, 255).into_iter()).collect()
+        }
+        png::KA8(pixels) => {
+            pixels.as_slice().chunks(2).flat_map(|pixel| {
+                vec!(pixel[0], pixel[0], pixel[0], pixel[1]).into_iter()
+            }).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SIGNATURE, crc32, decode, is_animated_png, push_chunk, push_u32};
+    use image::base::{BlendOver, BlendSource, DisposeNone};
+
+    use content_decoder::zlib_bytes;
+    use png;
+
+    /// Compresses RGBA scanlines into image data.
+    fn image_data(rows: &[&[u8]]) -> Vec<u8> {
+        let mut raw = vec!();
+        for row in rows.iter() {
+            // No filter.
+            raw.push(0);
+            raw.push_all(*row);
+        }
+        zlib_bytes(raw.as_slice())
+    }
+
+    fn frame_control(sequence: u32, width: u32, x: u32, delay: (u16, u16), blend_op: u8)
+                     -> Vec<u8> {
+        let mut data = vec!();
+        for &value in [sequence, width, 1, x, 0].iter() {
+            push_u32(&mut data, value);
+        }
+        let (numerator, denominator) = delay;
+        data.push_all(&[(numerator >> 8) as u8, numerator as u8,
+                        (denominator >> 8) as u8, denominator as u8,
+                        0, blend_op]);
+        data
+    }
+
+    /// A 2x1 animation that plays twice: two red pixels, then a blue pixel drawn over the right
+    /// one.
+    fn animated_png() -> Vec<u8> {
+        let mut png = SIGNATURE.to_vec();
+        // 2x1, 8-bit RGBA.
+        push_chunk(&mut png, b"IHDR", &[0, 0, 0, 2, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        push_chunk(&mut png, b"acTL", &[0, 0, 0, 2, 0, 0, 0, 2]);
+        push_chunk(&mut png, b"fcTL", frame_control(0, 2, 0, (0, 0), 0).as_slice());
+        push_chunk(&mut png, b"IDAT", image_data(&[&[255, 0, 0, 255, 255, 0, 0, 255]]).as_slice());
+        push_chunk(&mut png, b"fcTL", frame_control(1, 1, 1, (1, 2), 1).as_slice());
+        let mut frame_data = vec!(0, 0, 0, 2);
+        frame_data.push_all(image_data(&[&[0, 0, 255, 255]]).as_slice());
+        push_chunk(&mut png, b"fdAT", frame_data.as_slice());
+        push_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    fn pixels(image: &png::PixelsByColorType) -> Vec<u8> {
+        match *image {
+            png::RGBA8(ref pixels) => pixels.clone(),
+            _ => panic!("expected RGBA pixels"),
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+    }
+
+    #[test]
+    fn test_is_animated_png() {
+        let png = animated_png();
+        assert!(is_animated_png(png.as_slice()));
+        assert!(png::is_png(png.as_slice()));
+        assert!(!is_animated_png(SIGNATURE));
+    }
+
+    #[test]
+    fn test_decode_animated_png() {
+        let png = animated_png();
+        let image = decode(png.as_slice()).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.play_count, Some(2));
+        assert_eq!(image.frames.len(), 2);
+
+        // A zero delay is clamped.
+        assert_eq!(image.frames[0].delay, 100);
+        assert_eq!(image.frames[0].blend, BlendSource);
+        assert_eq!(pixels(&image.frames[0].pixels), vec!(0, 0, 255, 255, 0, 0, 255, 255));
+
+        assert_eq!(image.frames[1].delay, 500);
+        assert_eq!(image.frames[1].disposal, DisposeNone);
+        assert_eq!(image.frames[1].blend, BlendOver);
+        assert_eq!(pixels(&image.frames[1].pixels), vec!(0, 0, 255, 255, 255, 0, 0, 255));
+    }
+
+    #[test]
+    fn test_decode_frame_data_without_frame_control() {
+        let mut png = SIGNATURE.to_vec();
+        push_chunk(&mut png, b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        push_chunk(&mut png, b"acTL", &[0, 0, 0, 1, 0, 0, 0, 0]);
+        push_chunk(&mut png, b"fdAT", &[0, 0, 0, 0, 0]);
+        push_chunk(&mut png, b"IEND", &[]);
+        assert!(decode(png.as_slice()).is_none());
+    }
+    #[test]
+    fn test_decode_too_large_png() {
+        let mut png = SIGNATURE.to_vec();
+        // 16385x1.
+        push_chunk(&mut png, b"IHDR", &[0, 0, 0x40, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        push_chunk(&mut png, b"acTL", &[0, 0, 0, 1, 0, 0, 0, 0]);
+        push_chunk(&mut png, b"IEND", &[]);
+        assert!(decode(png.as_slice()).is_none());
+    }
+
+    #[test]
+    fn test_decode_frame_outside_canvas() {
+        let mut png = SIGNATURE.to_vec();
+        push_chunk(&mut png, b"IHDR", &[0, 0, 0, 2, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        push_chunk(&mut png, b"acTL", &[0, 0, 0, 1, 0, 0, 0, 0]);
+        // The frame's right edge overflows.
+        push_chunk(&mut png, b"fcTL", frame_control(0, 2, 0xffffffff, (0, 0), 0).as_slice());
+        push_chunk(&mut png, b"IDAT", image_data(&[&[255, 0, 0, 255, 255, 0, 0, 255]]).as_slice());
+        push_chunk(&mut png, b"IEND", &[]);
+        assert!(decode(png.as_slice()).is_none());
+    }
+}
@@ -2,13 +2,231 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use image::{apng, gif, progressive, webp};
+
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::size::Size2D;
 use std::iter::range_step;
 use stb_image::image as stb_image;
 use png;
 
-// FIXME: Images must not be copied every frame. Instead we should atomically
-// reference count them.
-pub type Image = png::Image;
+/// Frame delays at or below this many milliseconds are treated as `DEFAULT_FRAME_DELAY`, as other
+/// browsers do, so that images with a zero delay don't spin.
+static MIN_FRAME_DELAY: u32 = 10;
+static DEFAULT_FRAME_DELAY: u32 = 100;
+
+/// Images wider or taller than this many pixels aren't decoded.
+pub static MAX_DIMENSION: u32 = 16384;
+
+/// Every frame of an animation is stored as a whole canvas of RGBA pixels, so images whose frames
+/// would take up more than this many bytes altogether aren't decoded.
+pub static MAX_DECODED_SIZE: uint = 256 * 1024 * 1024;
+
+/// What happens to the area of the canvas that a frame covers once the frame has been shown.
+#[deriving(Clone, PartialEq, Show)]
+pub enum FrameDisposal {
+    /// The frame is left in place.
+    DisposeNone,
+    /// The area is cleared to transparent black.
+    DisposeBackground,
+    /// The area is restored to what it was before the frame was drawn.
+    DisposePrevious,
+}
+
+/// How a frame is drawn onto the canvas.
+#[deriving(Clone, PartialEq, Show)]
+pub enum FrameBlend {
+    /// The frame replaces the area it covers, alpha included.
+    BlendSource,
+    /// The frame is composited over the area it covers.
+    BlendOver,
+}
+
+/// One frame of an image, as the decoders produce it: just the area that the frame covers, as
+/// RGBA pixels with straight alpha.
+pub struct RawFrame {
+    pub rect: Rect<u32>,
+    pub pixels: Vec<u8>,
+    /// How long the frame is shown, in milliseconds.
+    pub delay: u32,
+    pub disposal: FrameDisposal,
+    pub blend: FrameBlend,
+}
+
+/// One frame of an image, ready to be drawn.
+pub struct ImageFrame {
+    /// The whole image while this frame is shown; that is, the frame composited over the frames
+    /// before it.
+    pub pixels: png::PixelsByColorType,
+    /// The area of the image that this frame updated.
+    pub rect: Rect<u32>,
+    /// How long the frame is shown, in milliseconds.
+    pub delay: u32,
+    pub disposal: FrameDisposal,
+    pub blend: FrameBlend,
+}
+
+/// A decoded image. Still images have a single frame; animated images have several. Which frame
+/// is shown is up to whoever paints the image, so images can be shared between tasks.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<ImageFrame>,
+    /// How many times an animation is played, or `None` if it loops forever.
+    pub play_count: Option<u32>,
+}
+
+impl Image {
+    /// Wraps a decoded still image.
+    pub fn from_png(image: png::Image) -> Image {
+        Image {
+            width: image.width,
+            height: image.height,
+            frames: vec!(ImageFrame {
+                pixels: image.pixels,
+                rect: Rect(Point2D(0, 0), Size2D(image.width, image.height)),
+                delay: 0,
+                disposal: DisposeNone,
+                blend: BlendSource,
+            }),
+            play_count: Some(1),
+        }
+    }
+
+    /// Wraps a decoded still image given as RGBA pixels with straight alpha.
+    pub fn from_rgba(width: u32, height: u32, pixels: Vec<u8>) -> Image {
+        let mut pixels = pixels;
+        byte_swap_and_premultiply(pixels.as_mut_slice());
+        Image::from_png(png::Image {
+            width: width,
+            height: height,
+            pixels: png::RGBA8(pixels),
+        })
+    }
+
+    /// Composites the frames of an animation onto a `width` by `height` canvas. Returns `None` if
+    /// there are no frames, or a frame doesn't fit on the canvas.
+    pub fn from_frames(width: u32, height: u32, frames: Vec<RawFrame>, play_count: Option<u32>)
+                       -> Option<Image> {
+        if frames.is_empty() {
+            return None
+        }
+
+        let stride = try_opt!((width as uint).checked_mul(&4));
+        let mut canvas = Vec::from_elem(try_opt!(stride.checked_mul(&(height as uint))), 0u8);
+        let mut composited = Vec::with_capacity(frames.len());
+        for frame in frames.into_iter() {
+            let rect = frame.rect;
+            if !fits_in(&rect, width, height) ||
+                    Some(frame.pixels.len()) != frame_size(rect.size.width, rect.size.height) {
+                return None
+            }
+
+            let previous = match frame.disposal {
+                DisposePrevious => Some(canvas.clone()),
+                DisposeNone | DisposeBackground => None,
+            };
+
+            let frame_stride = rect.size.width as uint * 4;
+            for y in range(0, rect.size.height as uint) {
+                let row = (rect.origin.y as uint + y) * stride + rect.origin.x as uint * 4;
+                for x in range(0, rect.size.width as uint) {
+                    let source = frame.pixels.slice(y * frame_stride + x * 4,
+                                                    y * frame_stride + x * 4 + 4);
+                    let destination = canvas.slice_mut(row + x * 4, row + x * 4 + 4);
+                    match frame.blend {
+                        BlendSource => destination.copy_from(source),
+                        BlendOver => blend_over(destination, source),
+                    };
+                }
+            }
+
+            let clear = frame.disposal == DisposeBackground;
+            let mut pixels = canvas.clone();
+            byte_swap_and_premultiply(pixels.as_mut_slice());
+            composited.push(ImageFrame {
+                pixels: png::RGBA8(pixels),
+                rect: rect,
+                delay: clamp_frame_delay(frame.delay),
+                disposal: frame.disposal,
+                blend: frame.blend,
+            });
+
+            match previous {
+                Some(previous) => canvas = previous,
+                None if clear => {
+                    for y in range(0, rect.size.height as uint) {
+                        let row = (rect.origin.y as uint + y) * stride + rect.origin.x as uint * 4;
+                        for byte in canvas.slice_mut(row, row + frame_stride).iter_mut() {
+                            *byte = 0
+                        }
+                    }
+                }
+                None => {}
+            }
+        }
+
+        Some(Image {
+            width: width,
+            height: height,
+            frames: composited,
+            play_count: play_count,
+        })
+    }
+
+    /// Whether this image has more than one frame.
+    pub fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+
+    /// The frame at `index`, or the first frame if there is no such frame.
+    pub fn frame(&self, index: uint) -> &ImageFrame {
+        self.frames.as_slice().get(index).unwrap_or(&self.frames[0])
+    }
+}
+
+/// Whether a frame covering `rect` lies within a `width` by `height` canvas.
+pub fn fits_in(rect: &Rect<u32>, width: u32, height: u32) -> bool {
+    match (rect.origin.x.checked_add(&rect.size.width),
+           rect.origin.y.checked_add(&rect.size.height)) {
+        (Some(right), Some(bottom)) => right <= width && bottom <= height,
+        _ => false,
+    }
+}
+
+/// How many bytes `width` by `height` RGBA pixels take up, or `None` if that overflows.
+pub fn frame_size(width: u32, height: u32) -> Option<uint> {
+    (width as uint).checked_mul(&(height as uint)).and_then(|area| area.checked_mul(&4))
+}
+
+fn clamp_frame_delay(delay: u32) -> u32 {
+    if delay <= MIN_FRAME_DELAY {
+        DEFAULT_FRAME_DELAY
+    } else {
+        delay
+    }
+}
+
+/// Composites an RGBA pixel with straight alpha over another.
+fn blend_over(destination: &mut [u8], source: &[u8]) {
+    let source_alpha = source[3] as u32;
+    if source_alpha == 255 {
+        destination.copy_from(source);
+        return
+    }
+    if source_alpha == 0 {
+        return
+    }
+
+    let destination_alpha = destination[3] as u32 * (255 - source_alpha) / 255;
+    let alpha = source_alpha + destination_alpha;
+    for i in range(0, 3) {
+        destination[i] = ((source[i] as u32 * source_alpha +
+                           destination[i] as u32 * destination_alpha) / alpha) as u8;
+    }
+    destination[3] = alpha as u8;
+}
 
 
 static TEST_IMAGE: &'static [u8] = include_bin!("test.jpeg");
@@ -46,6 +264,22 @@ pub fn load_from_memory(buffer: &[u8]) -> Option<Image> {
         return None;
     }
 
+    if webp::is_webp(buffer) {
+        return webp::decode(buffer)
+    }
+
+    if gif::is_gif(buffer) {
+        match gif::decode(buffer) {
+            Some(image) => return Some(image),
+            None => debug!("falling back to stb_image for a GIF that failed to decode"),
+        }
+    } else if apng::is_animated_png(buffer) {
+        match apng::decode(buffer) {
+            Some(image) => return Some(image),
+            None => debug!("showing the default image of an APNG that failed to decode"),
+        }
+    }
+
     if png::is_png(buffer) {
         match png::load_png_from_memory(buffer) {
             Ok(mut png_image) => {
@@ -56,7 +290,7 @@ pub fn load_from_memory(buffer: &[u8]) -> Option<Image> {
                     }
                     _ => {}
                 }
-                Some(png_image)
+                Some(Image::from_png(png_image))
             }
             Err(_err) => None,
         }
@@ -69,11 +303,11 @@ pub fn load_from_memory(buffer: &[u8]) -> Option<Image> {
             stb_image::ImageU8(mut image) => {
                 assert!(image.depth == 4);
                 byte_swap(image.data.as_mut_slice());
-                Some(png::Image {
+                Some(Image::from_png(png::Image {
                     width: image.width as u32,
                     height: image.height as u32,
                     pixels: png::RGBA8(image.data)
-                })
+                }))
             }
             stb_image::ImageF32(_image) => {
                 error!("HDR images not implemented");
@@ -86,3 +320,18 @@ pub fn load_from_memory(buffer: &[u8]) -> Option<Image> {
         }
     }
 }
+
+/// Decodes the part of a progressive JPEG or interlaced PNG that has arrived so far. Returns `None`
+/// for other images, or if too little of the image has arrived to show anything.
+pub fn load_partial_from_memory(buffer: &[u8]) -> Option<Image> {
+    if !progressive::is_progressive(buffer) {
+        return None
+    }
+    if png::is_png(buffer) {
+        progressive::decode_partial_png(buffer)
+    } else {
+        progressive::truncate_jpeg_to_complete_scans(buffer).and_then(|jpeg| {
+            load_from_memory(jpeg.as_slice())
+        })
+    }
+}
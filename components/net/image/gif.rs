@@ -0,0 +1,461 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A decoder for GIF images that keeps every frame of an animation.
+//!
+//! stb_image only decodes the first frame of a GIF, so it is used only as a fallback.
+//! See <http://www.w3.org/Graphics/GIF/spec-gif89a.txt>.
+
+use image::base::{Image, RawFrame, FrameDisposal, DisposeNone, DisposeBackground};
+use image::base::{DisposePrevious, BlendOver, MAX_DIMENSION, MAX_DECODED_SIZE};
+
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::size::Size2D;
+
+static MAX_CODE_SIZE: uint = 12;
+
+pub fn is_gif(buffer: &[u8]) -> bool {
+    buffer.starts_with(b"GIF87a") || buffer.starts_with(b"GIF89a")
+}
+
+/// The contents of the graphic control extension, which applies to the next frame.
+struct GraphicControl {
+    delay: u32,
+    disposal: FrameDisposal,
+    transparent_index: Option<u8>,
+}
+
+impl GraphicControl {
+    fn new() -> GraphicControl {
+        GraphicControl {
+            delay: 0,
+            disposal: DisposeNone,
+            transparent_index: None,
+        }
+    }
+}
+
+struct Reader<'a> {
+    buffer: &'a [u8],
+    position: uint,
+}
+
+impl<'a> Reader<'a> {
+    fn read_u8(&mut self) -> Option<u8> {
+        if self.position >= self.buffer.len() {
+            return None
+        }
+        self.position += 1;
+        Some(self.buffer[self.position - 1])
+    }
+
+    fn read_u16(&mut self) -> Option<u16> {
+        let low = try_opt!(self.read_u8());
+        let high = try_opt!(self.read_u8());
+        Some((high as u16 << 8) | low as u16)
+    }
+
+    fn read_bytes(&mut self, length: uint) -> Option<&'a [u8]> {
+        if self.position + length > self.buffer.len() {
+            return None
+        }
+        self.position += length;
+        Some(self.buffer.slice(self.position - length, self.position))
+    }
+
+    /// Reads a color table of 2^(size + 1) RGB entries.
+    fn read_color_table(&mut self, size: u8) -> Option<&'a [u8]> {
+        self.read_bytes(3 * (1u << (size as uint + 1)))
+    }
+
+    /// Reads a series of data sub-blocks, up to the block terminator.
+    fn read_sub_blocks(&mut self) -> Option<Vec<u8>> {
+        let mut data = vec!();
+        loop {
+            let length = try_opt!(self.read_u8()) as uint;
+            if length == 0 {
+                return Some(data)
+            }
+            data.push_all(try_opt!(self.read_bytes(length)));
+        }
+    }
+}
+
+/// Decodes every frame of a GIF. Returns `None` if the image is malformed.
+pub fn decode(buffer: &[u8]) -> Option<Image> {
+    let mut reader = Reader {
+        buffer: buffer,
+        position: 6,
+    };
+
+    // Logical screen descriptor.
+    let width = try_opt!(reader.read_u16()) as u32;
+    let height = try_opt!(reader.read_u16()) as u32;
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return None
+    }
+    let canvas_size = try_opt!((width as uint).checked_mul(&(height as uint))
+                                              .and_then(|area| area.checked_mul(&4)));
+    let flags = try_opt!(reader.read_u8());
+    let _background_color_index = try_opt!(reader.read_u8());
+    let _pixel_aspect_ratio = try_opt!(reader.read_u8());
+    let global_color_table = if flags & 0x80 != 0 {
+        Some(try_opt!(reader.read_color_table(flags & 0x07)))
+    } else {
+        None
+    };
+
+    let mut frames = vec!();
+    let mut control = GraphicControl::new();
+    // Without a NETSCAPE2.0 extension, animations play once.
+    let mut play_count = Some(1);
+    loop {
+        match try_opt!(reader.read_u8()) {
+            // Extension.
+            0x21 => {
+                let label = try_opt!(reader.read_u8());
+                let data = try_opt!(reader.read_sub_blocks());
+                match label {
+                    0xf9 if data.len() >= 4 => {
+                        control.disposal = match (data[0] >> 2) & 0x07 {
+                            2 => DisposeBackground,
+                            3 => DisposePrevious,
+                            _ => DisposeNone,
+                        };
+                        // The delay is in hundredths of a second.
+                        control.delay = ((data[2] as u32 << 8) | data[1] as u32) * 10;
+                        control.transparent_index = if data[0] & 0x01 != 0 {
+                            Some(data[3])
+                        } else {
+                            None
+                        };
+                    }
+                    0xff if data.starts_with(b"NETSCAPE2.0\x01") && data.len() >= 14 => {
+                        // The loop count is the number of times the animation repeats after it
+                        // has played once, with zero meaning forever.
+                        play_count = match (data[13] as u32 << 8) | data[12] as u32 {
+                            0 => None,
+                            loops => Some(loops + 1),
+                        };
+                    }
+                    _ => {}
+                }
+            }
+            // Image descriptor.
+            0x2c => {
+                // Each frame is composited onto a canvas of its own.
+                let decoded_size = try_opt!(canvas_size.checked_mul(&(frames.len() + 1)));
+                if decoded_size > MAX_DECODED_SIZE {
+                    return None
+                }
+                let frame = try_opt!(read_frame(&mut reader,
+                                                global_color_table,
+                                                &control,
+                                                width,
+                                                height));
+                frames.push(frame);
+                control = GraphicControl::new();
+            }
+            // Trailer.
+            0x3b => break,
+            _ => return None,
+        }
+    }
+
+    Image::from_frames(width, height, frames, play_count)
+}
+
+/// Reads a frame, which must fit on the `canvas_width` by `canvas_height` canvas.
+fn read_frame(reader: &mut Reader,
+              global_color_table: Option<&[u8]>,
+              control: &GraphicControl,
+              canvas_width: u32,
+              canvas_height: u32)
+              -> Option<RawFrame> {
+    let left = try_opt!(reader.read_u16()) as u32;
+    let top = try_opt!(reader.read_u16()) as u32;
+    let width = try_opt!(reader.read_u16()) as u32;
+    let height = try_opt!(reader.read_u16()) as u32;
+    // The fields are 16 bits wide, so the sums can't overflow.
+    if left + width > canvas_width || top + height > canvas_height {
+        return None
+    }
+    let flags = try_opt!(reader.read_u8());
+    let color_table = if flags & 0x80 != 0 {
+        try_opt!(reader.read_color_table(flags & 0x07))
+    } else {
+        try_opt!(global_color_table)
+    };
+    let interlaced = flags & 0x40 != 0;
+
+    let min_code_size = try_opt!(reader.read_u8()) as uint;
+    let data = try_opt!(reader.read_sub_blocks());
+    // The frame fits on the canvas, whose size has been checked.
+    let pixel_count = width as uint * height as uint;
+    let indices = try_opt!(decode_lzw(min_code_size, data.as_slice(), pixel_count));
+
+    let mut pixels = Vec::from_elem(pixel_count * 4, 0u8);
+    for (i, &index) in indices.iter().enumerate() {
+        let row = if interlaced {
+            deinterlaced_row(i / width as uint, height as uint)
+        } else {
+            i / width as uint
+        };
+        let offset = (row * width as uint + i % width as uint) * 4;
+        if Some(index) == control.transparent_index || index as uint * 3 + 2 >= color_table.len() {
+            continue
+        }
+        pixels.slice_mut(offset, offset + 3)
+              .copy_from(color_table.slice(index as uint * 3, index as uint * 3 + 3));
+        pixels[offset + 3] = 255;
+    }
+
+    Some(RawFrame {
+        rect: Rect(Point2D(left, top), Size2D(width, height)),
+        pixels: pixels,
+        delay: control.delay,
+        disposal: control.disposal.clone(),
+        blend: BlendOver,
+    })
+}
+
+/// Maps the `pass_row`th row of an interlaced image, in the order it is stored, to its position in
+/// the image.
+fn deinterlaced_row(pass_row: uint, height: uint) -> uint {
+    // Pass 1 holds every 8th row from row 0, pass 2 every 8th row from row 4, pass 3 every 4th row
+    // from row 2 and pass 4 every other row from row 1.
+    let passes = [(0u, 8u), (4, 8), (2, 4), (1, 2)];
+    let mut pass_row = pass_row;
+    for &(start, step) in passes.iter() {
+        let rows = if height > start { (height - start + step - 1) / step } else { 0 };
+        if pass_row < rows {
+            return start + pass_row * step
+        }
+        pass_row -= rows;
+    }
+    pass_row
+}
+
+/// Decompresses GIF image data into `pixel_count` color indices. Images whose data runs out early
+/// are padded with index 0, as other browsers do.
+fn decode_lzw(min_code_size: uint, data: &[u8], pixel_count: uint) -> Option<Vec<u8>> {
+    if min_code_size < 2 || min_code_size >= MAX_CODE_SIZE {
+        return None
+    }
+
+    let clear_code = 1u << min_code_size;
+    let end_code = clear_code + 1;
+    // For each code, the code whose string it extends, and the last and first bytes of its string.
+    let mut prefixes = Vec::from_elem(1 << MAX_CODE_SIZE, 0u16);
+    let mut suffixes = Vec::from_elem(1 << MAX_CODE_SIZE, 0u8);
+    let mut firsts = Vec::from_elem(1 << MAX_CODE_SIZE, 0u8);
+    for code in range(0, clear_code) {
+        suffixes[code] = code as u8;
+        firsts[code] = code as u8;
+    }
+
+    let mut output = Vec::with_capacity(pixel_count);
+    let mut string = Vec::new();
+    let mut code_size = min_code_size + 1;
+    let mut next_code = end_code + 1;
+    let mut previous: Option<uint> = None;
+    let (mut bits, mut bit_count) = (0u32, 0u);
+    let mut bytes = data.iter();
+
+    while output.len() < pixel_count {
+        while bit_count < code_size {
+            match bytes.next() {
+                Some(&byte) => {
+                    bits |= byte as u32 << bit_count;
+                    bit_count += 8;
+                }
+                None => {
+                    output.grow(pixel_count - output.len(), 0);
+                    return Some(output)
+                }
+            }
+        }
+        let code = (bits & ((1 << code_size) - 1)) as uint;
+        bits >>= code_size;
+        bit_count -= code_size;
+
+        if code == clear_code {
+            code_size = min_code_size + 1;
+            next_code = end_code + 1;
+            previous = None;
+            continue
+        }
+        if code == end_code {
+            break
+        }
+
+        let first = match previous {
+            None => {
+                if code >= clear_code {
+                    return None
+                }
+                output.push(code as u8);
+                previous = Some(code);
+                continue
+            }
+            Some(previous) if code < next_code => {
+                if next_code < 1 << MAX_CODE_SIZE {
+                    prefixes[next_code] = previous as u16;
+                    suffixes[next_code] = firsts[code];
+                    firsts[next_code] = firsts[previous];
+                }
+                firsts[code]
+            }
+            Some(previous) if code == next_code => {
+                // The code being defined right now: the previous string plus its own first byte.
+                let first = firsts[previous];
+                if next_code < 1 << MAX_CODE_SIZE {
+                    prefixes[next_code] = previous as u16;
+                    suffixes[next_code] = first;
+                    firsts[next_code] = first;
+                }
+                first
+            }
+            Some(_) => return None,
+        };
+        debug_assert!(first == firsts[code]);
+
+        // Unwind the string for this code, which comes out backwards.
+        string.clear();
+        let mut current = code;
+        while current >= clear_code {
+            string.push(suffixes[current]);
+            current = prefixes[current] as uint;
+        }
+        string.push(current as u8);
+        output.extend(string.iter().rev().map(|&byte| byte));
+
+        if next_code < 1 << MAX_CODE_SIZE {
+            next_code += 1;
+            if next_code == 1 << code_size && code_size < MAX_CODE_SIZE {
+                code_size += 1;
+            }
+        }
+        previous = Some(code);
+    }
+
+    output.truncate(pixel_count);
+    output.grow(pixel_count - output.len(), 0);
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, decode_lzw, deinterlaced_row, is_gif};
+    use image::base::{DisposeBackground, DisposeNone};
+    use png;
+
+    /// A 2x1 animation that loops forever: a red pixel and a transparent one, then a green pixel
+    /// drawn over the transparent one.
+    static ANIMATED_GIF: &'static [u8] = &[
+        // Header and logical screen descriptor, with a 2-entry global color table.
+        0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x02, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00,
+        0xff, 0x00, 0x00, 0x00, 0xff, 0x00,
+        // NETSCAPE2.0 application extension.
+        0x21, 0xff, 0x0b, 0x4e, 0x45, 0x54, 0x53, 0x43, 0x41, 0x50, 0x45, 0x32, 0x2e, 0x30,
+        0x03, 0x01, 0x00, 0x00, 0x00,
+        // Graphic control extension: 50ms, dispose to background, color 1 is transparent.
+        0x21, 0xf9, 0x04, 0x09, 0x05, 0x00, 0x01, 0x00,
+        // Frame 1: 2x1 at (0, 0), pixels [0, 1].
+        0x2c, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00,
+        0x02, 0x02, 0x44, 0x0a, 0x00,
+        // Graphic control extension: 100ms, no transparency.
+        0x21, 0xf9, 0x04, 0x00, 0x0a, 0x00, 0x00, 0x00,
+        // Frame 2: 1x1 at (1, 0), pixel [1].
+        0x2c, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00,
+        0x02, 0x02, 0x4c, 0x01, 0x00,
+        // Trailer.
+        0x3b,
+    ];
+
+    fn pixels(image: &png::PixelsByColorType) -> Vec<u8> {
+        match *image {
+            png::RGBA8(ref pixels) => pixels.clone(),
+            _ => panic!("expected RGBA pixels"),
+        }
+    }
+
+    #[test]
+    fn test_is_gif() {
+        assert!(is_gif(ANIMATED_GIF));
+        assert!(!is_gif(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    #[test]
+    fn test_decode_lzw() {
+        // Clear, 0, 1, end with 3-bit codes.
+        assert_eq!(decode_lzw(2, &[0x44, 0x0a], 2), Some(vec!(0, 1)));
+        // Data that runs out early is padded.
+        assert_eq!(decode_lzw(2, &[0x44], 3), Some(vec!(0, 0, 0)));
+        // A code that hasn't been defined yet.
+        assert_eq!(decode_lzw(2, &[0x3c], 2), None);
+    }
+
+    #[test]
+    fn test_deinterlaced_row() {
+        let rows: Vec<uint> = range(0u, 8).map(|row| deinterlaced_row(row, 8)).collect();
+        assert_eq!(rows, vec!(0, 4, 2, 6, 1, 3, 5, 7));
+    }
+
+    #[test]
+    fn test_decode_animated_gif() {
+        let image = decode(ANIMATED_GIF).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert!(image.is_animated());
+        assert_eq!(image.play_count, None);
+
+        assert_eq!(image.frames[0].delay, 50);
+        assert_eq!(image.frames[0].disposal, DisposeBackground);
+        // Premultiplied BGRA: a red pixel and a transparent one.
+        assert_eq!(pixels(&image.frames[0].pixels), vec!(0, 0, 255, 255, 0, 0, 0, 0));
+
+        // The first frame was cleared before the second was drawn.
+        assert_eq!(image.frames[1].delay, 100);
+        assert_eq!(image.frames[1].disposal, DisposeNone);
+        assert_eq!(pixels(&image.frames[1].pixels), vec!(0, 0, 0, 0, 0, 255, 0, 255));
+    }
+
+    #[test]
+    fn test_decode_truncated_gif() {
+        assert!(decode(ANIMATED_GIF.slice_to(30)).is_none());
+    }
+
+    #[test]
+    fn test_decode_oversized_gif() {
+        // A 65535x65535 canvas.
+        let mut gif = ANIMATED_GIF.to_vec();
+        for byte in gif.slice_mut(6, 10).iter_mut() {
+            *byte = 0xff;
+        }
+        assert!(decode(gif.as_slice()).is_none());
+
+        // A second frame that is 3 pixels wide, at x = 1 on a 2-pixel-wide canvas.
+        let mut gif = ANIMATED_GIF.to_vec();
+        let descriptor = gif.len() - 16;
+        gif[descriptor + 5] = 0x03;
+        assert!(decode(gif.as_slice()).is_none());
+    }
+
+    #[test]
+    fn test_decode_gif_with_too_many_frames() {
+        // A 4096x4096 canvas takes up 64MB, so the fifth frame of the animation is one too many,
+        // however small the frames themselves are.
+        let mut gif = b"GIF89a".to_vec();
+        gif.push_all(&[0x00, 0x10, 0x00, 0x10, 0x80, 0x00, 0x00,
+                       0xff, 0x00, 0x00, 0x00, 0xff, 0x00]);
+        for _ in range(0u, 5) {
+            // A 1x1 frame at (0, 0), pixel [0].
+            gif.push_all(&[0x2c, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00,
+                           0x02, 0x02, 0x44, 0x01, 0x00]);
+        }
+        gif.push(0x3b);
+        assert!(decode(gif.as_slice()).is_none());
+    }
+}
@@ -3,7 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use image::base::Image;
-use image_cache_task::{ImageReady, ImageNotReady, ImageFailed};
+use image_cache_task::{ImageReady, ImagePartiallyReady, ImageNotReady, ImageFailed};
 use local_image_cache::LocalImageCache;
 
 use geom::size::Size2D;
@@ -90,6 +90,11 @@ impl<NodeAddress: Send> ImageHolder<NodeAddress> {
                 ImageReady(image) => {
                     self.image = Some(image);
                 }
+                ImagePartiallyReady(image) => {
+                    // Not stored, so that the next call picks up more of the image.
+                    debug!("image partially ready for {:s}", self.url.serialize());
+                    return Some(image);
+                }
                 ImageNotReady => {
                     debug!("image not ready for {:s}", self.url.serialize());
                 }
@@ -0,0 +1,418 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Decoding of images that have only partly arrived. Progressive JPEGs and interlaced PNGs store a
+//! coarse version of the whole image before the details, so a useful image can be shown long
+//! before the last byte arrives.
+
+use image::apng::{SIGNATURE, chunks, read_u32};
+use image::base::{Image, MAX_DIMENSION, MAX_DECODED_SIZE, frame_size};
+
+use flate2::reader::ZlibDecoder;
+use png;
+use std::io::MemReader;
+
+static JPEG_SOI: &'static [u8] = b"\xff\xd8";
+static JPEG_EOI: &'static [u8] = b"\xff\xd9";
+static JPEG_SOF2: u8 = 0xc2;
+static JPEG_SOS: u8 = 0xda;
+
+/// The Adam7 passes of an interlaced PNG: where the first pixel of each pass is, how far apart
+/// its pixels are, and how big a block each pixel stands for until later passes arrive.
+static ADAM7_PASSES: [(uint, uint, uint, uint, uint, uint), ..7] = [
+    // (x, y, x step, y step, block width, block height)
+    (0, 0, 8, 8, 8, 8),
+    (4, 0, 8, 8, 4, 8),
+    (0, 4, 4, 8, 4, 4),
+    (2, 0, 4, 4, 2, 4),
+    (0, 2, 2, 4, 2, 2),
+    (1, 0, 2, 2, 1, 2),
+    (0, 1, 1, 2, 1, 1),
+];
+
+/// Whether `buffer` is the start of an image that can usefully be shown before all of it has
+/// arrived.
+pub fn is_progressive(buffer: &[u8]) -> bool {
+    progressiveness(buffer) == Some(true)
+}
+
+/// Like `is_progressive`, but `None` if too little of the image has arrived to tell.
+pub fn progressiveness(buffer: &[u8]) -> Option<bool> {
+    if buffer.starts_with(JPEG_SOI) {
+        // The frame header says how the image is coded.
+        return jpeg_segments(buffer).iter().find(|&&(marker, _)| {
+            marker >= 0xc0 && marker <= 0xcf && marker != 0xc4 && marker != 0xc8 && marker != 0xcc
+        }).map(|&(marker, _)| marker == JPEG_SOF2)
+    }
+    if buffer.len() < SIGNATURE.len() {
+        return None
+    }
+    if !png::is_png(buffer) {
+        return Some(false)
+    }
+    chunks(buffer).iter().next().map(|chunk| {
+        chunk.kind == b"IHDR" && chunk.data.len() == 13 && chunk.data[12] == 1
+    })
+}
+
+/// Splits a JPEG into its markers and where they start, up to the first scan whose data is
+/// incomplete. Each scan's data is skipped along with its header.
+fn jpeg_segments(buffer: &[u8]) -> Vec<(u8, uint)> {
+    let mut segments = vec!();
+    let mut position = JPEG_SOI.len();
+    loop {
+        // Skip fill bytes.
+        while position + 1 < buffer.len() && buffer[position] == 0xff &&
+                buffer[position + 1] == 0xff {
+            position += 1;
+        }
+        if position + 4 > buffer.len() || buffer[position] != 0xff {
+            return segments
+        }
+        let marker = buffer[position + 1];
+        if marker == JPEG_EOI[1] {
+            segments.push((marker, position));
+            return segments
+        }
+        let length = (buffer[position + 2] as uint << 8) | buffer[position + 3] as uint;
+        let mut end = position + 2 + length;
+        if end > buffer.len() {
+            return segments
+        }
+
+        if marker == JPEG_SOS {
+            // The entropy-coded data runs up to the next marker that isn't a stuffed zero byte or
+            // a restart marker.
+            loop {
+                if end + 1 >= buffer.len() {
+                    // The scan hasn't finished arriving.
+                    return segments
+                }
+                if buffer[end] == 0xff && buffer[end + 1] != 0 &&
+                        !(buffer[end + 1] >= 0xd0 && buffer[end + 1] <= 0xd7) {
+                    break
+                }
+                end += 1;
+            }
+        }
+        segments.push((marker, position));
+        position = end;
+    }
+}
+
+/// Cuts a partly received progressive JPEG after its last complete scan, and ends it there, so
+/// that it can be decoded as if it were whole. Returns `None` if no scan has arrived yet.
+pub fn truncate_jpeg_to_complete_scans(buffer: &[u8]) -> Option<Vec<u8>> {
+    let segments = jpeg_segments(buffer);
+    let (last_marker, last_position) = try_opt!(segments.last().map(|&segment| segment));
+    if last_marker == JPEG_EOI[1] {
+        return Some(buffer.to_vec())
+    }
+    if !segments.iter().any(|&(marker, _)| marker == JPEG_SOS) {
+        return None
+    }
+
+    // Everything up to the start of the segment after the last complete one.
+    let end = if last_marker == JPEG_SOS {
+        next_marker_position(buffer, last_position)
+    } else {
+        last_position + 2 + ((buffer[last_position + 2] as uint << 8) |
+                             buffer[last_position + 3] as uint)
+    };
+    let mut truncated = buffer.slice_to(end).to_vec();
+    truncated.push_all(JPEG_EOI);
+    Some(truncated)
+}
+
+/// The position of the marker after the complete scan that starts at `position`.
+fn next_marker_position(buffer: &[u8], position: uint) -> uint {
+    let length = (buffer[position + 2] as uint << 8) | buffer[position + 3] as uint;
+    let mut end = position + 2 + length;
+    while !(buffer[end] == 0xff && buffer[end + 1] != 0 &&
+            !(buffer[end + 1] >= 0xd0 && buffer[end + 1] <= 0xd7)) {
+        end += 1;
+    }
+    end
+}
+
+/// Decodes the passes of an interlaced PNG that have arrived so far. Each pixel is drawn as a
+/// block that covers the pixels of the passes that haven't arrived yet. Only 8-bit images and
+/// palette images are supported.
+pub fn decode_partial_png(buffer: &[u8]) -> Option<Image> {
+    let chunks = chunks(buffer);
+    let header = try_opt!(chunks.iter().next().and_then(|chunk| {
+        if chunk.kind == b"IHDR" && chunk.data.len() == 13 { Some(chunk.data) } else { None }
+    }));
+    let (width, height) = (read_u32(header), read_u32(header.slice_from(4)));
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return None
+    }
+    let size = try_opt!(frame_size(width, height));
+    if size > MAX_DECODED_SIZE {
+        return None
+    }
+    let (width, height) = (width as uint, height as uint);
+    let (bit_depth, color_type) = (header[8] as uint, header[9]);
+    let channels = match (color_type, bit_depth) {
+        (0, 8) => 1,
+        (2, 8) => 3,
+        (3, 1) | (3, 2) | (3, 4) | (3, 8) => 1,
+        (4, 8) => 2,
+        (6, 8) => 4,
+        _ => return None,
+    };
+    if width == 0 || height == 0 {
+        return None
+    }
+
+    let mut palette: &[u8] = &[];
+    let mut palette_alpha: &[u8] = &[];
+    let mut compressed = vec!();
+    for chunk in chunks.iter() {
+        if chunk.kind == b"PLTE" {
+            palette = chunk.data;
+        } else if chunk.kind == b"tRNS" {
+            palette_alpha = chunk.data;
+        } else if chunk.kind == b"IDAT" {
+            compressed.push_all(chunk.data);
+        }
+    }
+
+    let bits_per_pixel = channels * bit_depth;
+    let bytes_per_pixel = if bits_per_pixel >= 8 { bits_per_pixel / 8 } else { 1 };
+    let data = inflate_partial(compressed, adam7_data_size(width, height, bits_per_pixel));
+
+    let mut pixels = Vec::from_elem(size, 0u8);
+    let mut position = 0;
+    let mut drew_anything = false;
+    'passes: for &(x0, y0, x_step, y_step, block_width, block_height) in ADAM7_PASSES.iter() {
+        if x0 >= width || y0 >= height {
+            // Passes without pixels take up no space.
+            continue
+        }
+        let pass_width = (width - x0 + x_step - 1) / x_step;
+        let pass_height = (height - y0 + y_step - 1) / y_step;
+        let stride = (pass_width * bits_per_pixel + 7) / 8;
+        let mut previous_row = Vec::from_elem(stride, 0u8);
+        for row in range(0, pass_height) {
+            if position + 1 + stride > data.len() {
+                // The rest of the image hasn't arrived yet.
+                break 'passes
+            }
+            let filter = data[position];
+            let mut current_row = data.slice(position + 1, position + 1 + stride).to_vec();
+            position += 1 + stride;
+            if !unfilter(filter, current_row.as_mut_slice(), previous_row.as_slice(),
+                         bytes_per_pixel) {
+                return None
+            }
+
+            for column in range(0, pass_width) {
+                let rgba = match color_type {
+                    3 => {
+                        let index = packed_sample(current_row.as_slice(), column, bit_depth);
+                        if index * 3 + 2 >= palette.len() {
+                            return None
+                        }
+                        [palette[index * 3], palette[index * 3 + 1], palette[index * 3 + 2],
+                         if index < palette_alpha.len() { palette_alpha[index] } else { 255 }]
+                    }
+                    _ => {
+                        let sample = current_row.slice(column * channels,
+                                                       column * channels + channels);
+                        match channels {
+                            1 => [sample[0], sample[0], sample[0], 255],
+                            2 => [sample[0], sample[0], sample[0], sample[1]],
+                            3 => [sample[0], sample[1], sample[2], 255],
+                            _ => [sample[0], sample[1], sample[2], sample[3]],
+                        }
+                    }
+                };
+
+                let x = x0 + column * x_step;
+                let y = y0 + row * y_step;
+                for block_y in range(y, ::std::cmp::min(y + block_height, height)) {
+                    for block_x in range(x, ::std::cmp::min(x + block_width, width)) {
+                        let offset = (block_y * width + block_x) * 4;
+                        pixels.slice_mut(offset, offset + 4).copy_from(&rgba);
+                    }
+                }
+            }
+            drew_anything = true;
+            previous_row = current_row;
+        }
+    }
+
+    if !drew_anything {
+        return None
+    }
+    Some(Image::from_rgba(width as u32, height as u32, pixels))
+}
+
+/// How many bytes the filtered rows of all the passes of an interlaced image take up.
+fn adam7_data_size(width: uint, height: uint, bits_per_pixel: uint) -> uint {
+    ADAM7_PASSES.iter().map(|&(x0, y0, x_step, y_step, _, _)| {
+        if x0 >= width || y0 >= height {
+            return 0
+        }
+        let pass_width = (width - x0 + x_step - 1) / x_step;
+        let pass_height = (height - y0 + y_step - 1) / y_step;
+        pass_height * (1 + (pass_width * bits_per_pixel + 7) / 8)
+    }).fold(0, |total, size| total + size)
+}
+
+/// Inflates as much of a zlib stream as possible, up to about `limit` bytes.
+fn inflate_partial(compressed: Vec<u8>, limit: uint) -> Vec<u8> {
+    let mut decoder = ZlibDecoder::new(MemReader::new(compressed));
+    let mut data = vec!();
+    let mut buffer = [0u8, ..4096];
+    while data.len() < limit {
+        match decoder.read(&mut buffer) {
+            Ok(length) => data.push_all(buffer.slice_to(length)),
+            Err(_) => break,
+        }
+    }
+    data
+}
+
+/// Reads the `index`th sample of `bit_depth` bits from a row, most significant bits first.
+fn packed_sample(row: &[u8], index: uint, bit_depth: uint) -> uint {
+    let bit = index * bit_depth;
+    let byte = row[bit / 8] as uint;
+    (byte >> (8 - bit_depth - bit % 8)) & ((1 << bit_depth) - 1)
+}
+
+/// Undoes the filter of a row of an image. Returns false if the filter type is unknown.
+fn unfilter(filter: u8, row: &mut [u8], previous_row: &[u8], bytes_per_pixel: uint) -> bool {
+    for i in range(0, row.len()) {
+        let left = if i >= bytes_per_pixel { row[i - bytes_per_pixel] as int } else { 0 };
+        let above = previous_row[i] as int;
+        let upper_left =
+            if i >= bytes_per_pixel { previous_row[i - bytes_per_pixel] as int } else { 0 };
+        let predictor = match filter {
+            0 => 0,
+            1 => left,
+            2 => above,
+            3 => (left + above) / 2,
+            4 => {
+                let estimate = left + above - upper_left;
+                let distance_left = (estimate - left).abs();
+                let distance_above = (estimate - above).abs();
+                let distance_upper_left = (estimate - upper_left).abs();
+                if distance_left <= distance_above && distance_left <= distance_upper_left {
+                    left
+                } else if distance_above <= distance_upper_left {
+                    above
+                } else {
+                    upper_left
+                }
+            }
+            _ => return false,
+        };
+        row[i] = (row[i] as int + predictor) as u8;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_partial_png, is_progressive, progressiveness};
+    use super::{truncate_jpeg_to_complete_scans, unfilter};
+    use image::apng::push_chunk;
+
+    use content_decoder::zlib_bytes;
+    use png;
+
+    /// An interlaced 8x8 RGB image whose data only holds its first pass: a single red pixel.
+    fn first_pass_png() -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        push_chunk(&mut png, b"IHDR", &[0, 0, 0, 8, 0, 0, 0, 8, 8, 2, 0, 0, 1]);
+        let data = zlib_bytes(&[0, 255, 0, 0]);
+        push_chunk(&mut png, b"IDAT", data.as_slice());
+        png
+    }
+
+    /// A progressive JPEG skeleton: a frame header, then a complete scan, then one that has only
+    /// partly arrived.
+    static PARTIAL_JPEG: &'static [u8] = &[
+        0xff, 0xd8,
+        0xff, 0xc2, 0x00, 0x04, 0x08, 0x00,
+        0xff, 0xda, 0x00, 0x03, 0x01, 0x12, 0xff, 0x00, 0x34, 0xff, 0xd0, 0x56,
+        0xff, 0xda, 0x00, 0x03, 0x01, 0x78, 0x9a,
+    ];
+
+    #[test]
+    fn test_is_progressive() {
+        assert!(is_progressive(first_pass_png().as_slice()));
+        assert!(is_progressive(PARTIAL_JPEG));
+        // A baseline JPEG.
+        assert!(!is_progressive(&[0xff, 0xd8, 0xff, 0xc0, 0x00, 0x04, 0x08, 0x00]));
+        assert!(!is_progressive(b"GIF89a"));
+    }
+
+    #[test]
+    fn test_progressiveness() {
+        let png = first_pass_png();
+        assert_eq!(progressiveness(png.slice_to(20)), None);
+        assert_eq!(progressiveness(png.as_slice()), Some(true));
+        assert_eq!(progressiveness(&[0xff, 0xd8, 0xff, 0xc0]), None);
+        assert_eq!(progressiveness(&[0xff, 0xd8, 0xff, 0xc0, 0x00, 0x04, 0x08, 0x00]), Some(false));
+        assert_eq!(progressiveness(b"GIF89a"), None);
+        assert_eq!(progressiveness(b"GIF89a\x01\x00"), Some(false));
+    }
+
+    #[test]
+    fn test_truncate_jpeg_to_complete_scans() {
+        let truncated = truncate_jpeg_to_complete_scans(PARTIAL_JPEG).unwrap();
+        let mut expected = PARTIAL_JPEG.slice_to(20).to_vec();
+        expected.push_all(&[0xff, 0xd9]);
+        assert_eq!(truncated, expected);
+
+        // No scan has arrived yet.
+        assert!(truncate_jpeg_to_complete_scans(PARTIAL_JPEG.slice_to(12)).is_none());
+    }
+
+    #[test]
+    fn test_decode_first_pass_png() {
+        let image = decode_partial_png(first_pass_png().as_slice()).unwrap();
+        assert_eq!((image.width, image.height), (8, 8));
+        // The first pixel stands for the whole image until the other passes arrive.
+        match image.frames[0].pixels {
+            png::RGBA8(ref pixels) => {
+                assert!(pixels.as_slice().chunks(4).all(|pixel| pixel == [0, 0, 255, 255]));
+            }
+            _ => panic!("expected RGBA pixels"),
+        }
+    }
+
+    #[test]
+    fn test_decode_png_without_data() {
+        let mut png = first_pass_png();
+        let length = png.len();
+        png.truncate(length - 20);
+        assert!(decode_partial_png(png.as_slice()).is_none());
+    }
+
+    #[test]
+    fn test_decode_too_large_png() {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        // 16385x8.
+        push_chunk(&mut png, b"IHDR", &[0, 0, 0x40, 1, 0, 0, 0, 8, 8, 2, 0, 0, 1]);
+        let data = zlib_bytes(&[0, 255, 0, 0]);
+        push_chunk(&mut png, b"IDAT", data.as_slice());
+        assert!(decode_partial_png(png.as_slice()).is_none());
+    }
+
+    #[test]
+    fn test_unfilter() {
+        // Sub, then up.
+        let mut row = [1u8, 2, 3];
+        assert!(unfilter(1, &mut row, &[0, 0, 0], 1));
+        assert_eq!(row.as_slice(), [1u8, 3, 6].as_slice());
+        let mut next_row = [1u8, 1, 1];
+        assert!(unfilter(2, &mut next_row, &row, 1));
+        assert_eq!(next_row.as_slice(), [2u8, 4, 7].as_slice());
+        assert!(!unfilter(5, &mut next_row, &row, 1));
+    }
+}
@@ -0,0 +1,96 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Decoding of WebP images with libwebp. Both the lossy and the lossless formats are supported;
+//! only the first frame of an animated WebP is shown.
+//!
+//! libwebp is a system library, so WebP images are only decoded when the `webp` feature is
+//! enabled.
+
+use image::base::Image;
+
+#[cfg(feature = "webp")]
+use libc::{c_int, c_void, size_t};
+#[cfg(feature = "webp")]
+use libc;
+#[cfg(feature = "webp")]
+use std::slice;
+
+#[cfg(feature = "webp")]
+#[link(name = "webp")]
+extern {
+    fn WebPDecodeRGBA(data: *const u8, data_size: size_t, width: *mut c_int, height: *mut c_int)
+                      -> *mut u8;
+}
+
+/// Whether `buffer` starts with a WebP header: a RIFF container holding WebP data.
+pub fn is_webp(buffer: &[u8]) -> bool {
+    buffer.len() >= 12 && buffer.starts_with(b"RIFF") && buffer.slice(8, 12) == b"WEBP"
+}
+
+#[cfg(feature = "webp")]
+pub fn decode(buffer: &[u8]) -> Option<Image> {
+    let (mut width, mut height) = (0 as c_int, 0 as c_int);
+    let pixels = unsafe {
+        let pixels = WebPDecodeRGBA(buffer.as_ptr(),
+                                    buffer.len() as size_t,
+                                    &mut width,
+                                    &mut height);
+        if pixels.is_null() {
+            debug!("libwebp failed to decode an image");
+            return None
+        }
+        let length = width as uint * height as uint * 4;
+        let copy = slice::raw::buf_as_slice(pixels as *const u8, length, |data| data.to_vec());
+        libc::free(pixels as *mut c_void);
+        copy
+    };
+    Some(Image::from_rgba(width as u32, height as u32, pixels))
+}
+
+#[cfg(not(feature = "webp"))]
+pub fn decode(_buffer: &[u8]) -> Option<Image> {
+    debug!("not decoding a WebP image, because WebP support isn't enabled");
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, is_webp};
+    #[cfg(feature = "webp")]
+    use png;
+
+    /// A 1x1 lossless WebP.
+    static LOSSLESS_WEBP: &'static [u8] = &[
+        0x52, 0x49, 0x46, 0x46, 0x1a, 0x00, 0x00, 0x00, 0x57, 0x45, 0x42, 0x50,
+        0x56, 0x50, 0x38, 0x4c, 0x0d, 0x00, 0x00, 0x00, 0x2f, 0x00, 0x00, 0x00,
+        0x10, 0x07, 0x10, 0x11, 0x11, 0x88, 0x88, 0xfe, 0x07, 0x00,
+    ];
+
+    #[test]
+    fn test_is_webp() {
+        assert!(is_webp(b"RIFF\x24\x00\x00\x00WEBPVP8 "));
+        assert!(!is_webp(b"RIFF\x24\x00\x00\x00WAVEfmt "));
+        assert!(!is_webp(b"RIFF"));
+    }
+
+    #[test]
+    #[cfg(feature = "webp")]
+    fn test_decode() {
+        let image = decode(LOSSLESS_WEBP).unwrap();
+        assert_eq!((image.width, image.height), (1, 1));
+        match image.frames[0].pixels {
+            png::RGBA8(ref pixels) => assert_eq!(pixels.len(), 4),
+            _ => panic!("expected RGBA pixels"),
+        }
+        assert!(decode(LOSSLESS_WEBP.slice_to(24)).is_none());
+    }
+
+    #[test]
+    #[cfg(not(feature = "webp"))]
+    fn test_decode_without_libwebp() {
+        assert!(is_webp(LOSSLESS_WEBP));
+        assert!(decode(LOSSLESS_WEBP).is_none());
+    }
+}
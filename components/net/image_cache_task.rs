@@ -2,17 +2,20 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use image::base::{Image, load_from_memory};
-use resource_task;
-use resource_task::{LoadData, ResourceTask};
-
+use image::base::{Image, load_from_memory, load_partial_from_memory};
+use image::progressive::progressiveness;
+use png;
+use fetch::request::{Image as ImageContext, Request};
+use fetch::response::{Chunk, Finished, Errored};
+use resource_task::{ResourceTask, fetch_streaming};
+
+use servo_util::memory::{MemoryReport, MemoryReporter};
 use servo_util::task::spawn_named;
 use servo_util::taskpool::TaskPool;
 use std::comm::{channel, Receiver, Sender};
-use std::collections::HashMap;
+use std::collections::{HashMap, TreeMap};
 use std::collections::hash_map::{Occupied, Vacant};
 use std::mem::replace;
-use std::result;
 use sync::{Arc, Mutex};
 use serialize::{Encoder, Encodable};
 use url::Url;
@@ -41,6 +44,24 @@ pub enum Msg {
     /// Used by the decoder tasks to post decoded images back to the cache
     StoreImage(Url, Option<Arc<Box<Image>>>),
 
+    /// Used by the prefetch tasks to post back the data received since the last time of images
+    /// that can be shown before they have fully arrived
+    StorePartialImageData(Url, Vec<u8>),
+
+    /// Used by the decoder tasks to post images decoded from partial data back to the cache
+    StorePartialImage(Url, Option<Arc<Box<Image>>>),
+
+    /// Tell the cache that a client is displaying a decoded image, which must not be evicted
+    /// until the client discards it
+    AddImageUser(Url),
+
+    /// Tell the cache that a client, such as the layout task of a pipeline that has exited, no
+    /// longer displays these images
+    DiscardImages(Vec<Url>),
+
+    /// Request the sizes of the images kept by the cache
+    ReportMemory(Sender<Vec<MemoryReport>>),
+
     /// For testing
     WaitForStore(Sender<()>),
 
@@ -51,6 +72,8 @@ pub enum Msg {
 #[deriving(Clone)]
 pub enum ImageResponseMsg {
     ImageReady(Arc<Box<Image>>),
+    /// A version of the image decoded from the data that has arrived so far.
+    ImagePartiallyReady(Arc<Box<Image>>),
     ImageNotReady,
     ImageFailed
 }
@@ -59,10 +82,14 @@ impl PartialEq for ImageResponseMsg {
     fn eq(&self, other: &ImageResponseMsg) -> bool {
         match (self, other) {
             (&ImageReady(..), &ImageReady(..)) => panic!("unimplemented comparison"),
+            (&ImagePartiallyReady(..), &ImagePartiallyReady(..)) => {
+                panic!("unimplemented comparison")
+            }
             (&ImageNotReady, &ImageNotReady) => true,
             (&ImageFailed, &ImageFailed) => true,
 
-            (&ImageReady(..), _) | (&ImageNotReady, _) | (&ImageFailed, _) => false
+            (&ImageReady(..), _) | (&ImagePartiallyReady(..), _) | (&ImageNotReady, _) |
+            (&ImageFailed, _) => false
         }
     }
 }
@@ -81,7 +108,12 @@ impl<E, S: Encoder<E>> Encodable<S, E> for ImageCacheTask {
 type DecoderFactory = fn() -> (proc(&[u8]) : 'static -> Option<Image>);
 
 impl ImageCacheTask {
-    pub fn new(resource_task: ResourceTask, task_pool: TaskPool) -> ImageCacheTask {
+    /// Starts an image cache. Decoded images that no client is displaying are evicted, least
+    /// recently used first, once they take up more than `memory_budget` bytes; they are decoded
+    /// again from their data if they are requested later. The data kept for that has a budget of
+    /// its own of the same size, beyond which images are fetched again instead.
+    pub fn new(resource_task: ResourceTask, task_pool: TaskPool, memory_budget: Option<uint>)
+               -> ImageCacheTask {
         let (chan, port) = channel();
         let chan_clone = chan.clone();
 
@@ -92,6 +124,14 @@ impl ImageCacheTask {
                 chan: chan_clone,
                 state_map: HashMap::new(),
                 wait_map: HashMap::new(),
+                partial_map: HashMap::new(),
+                encoded_map: HashMap::new(),
+                encoded_lru: LruList::new(),
+                encoded_size: 0,
+                user_map: HashMap::new(),
+                lru: LruList::new(),
+                decoded_size: 0,
+                memory_budget: memory_budget,
                 need_exit: None,
                 task_pool: task_pool,
             };
@@ -103,11 +143,12 @@ impl ImageCacheTask {
         }
     }
 
-    pub fn new_sync(resource_task: ResourceTask, task_pool: TaskPool) -> ImageCacheTask {
+    pub fn new_sync(resource_task: ResourceTask, task_pool: TaskPool, memory_budget: Option<uint>)
+                    -> ImageCacheTask {
         let (chan, port) = channel();
 
         spawn_named("ImageCacheTask (sync)", proc() {
-            let inner_cache = ImageCacheTask::new(resource_task, task_pool);
+            let inner_cache = ImageCacheTask::new(resource_task, task_pool, memory_budget);
 
             loop {
                 let msg: Msg = port.recv();
@@ -142,6 +183,22 @@ struct ImageCache {
     state_map: HashMap<Url, ImageState>,
     /// List of clients waiting on a WaitForImage response
     wait_map: HashMap<Url, Arc<Mutex<Vec<Sender<ImageResponseMsg>>>>>,
+    /// The images decoded so far from images that haven't fully arrived
+    partial_map: HashMap<Url, PartialImage>,
+    /// The data of decoded images, kept so that they can be decoded again after being evicted
+    encoded_map: HashMap<Url, Arc<Vec<u8>>>,
+    /// The URLs of the kept data, least recently used first
+    encoded_lru: LruList,
+    /// The number of bytes taken up by the kept data
+    encoded_size: uint,
+    /// How many clients are displaying each decoded image
+    user_map: HashMap<Url, uint>,
+    /// The decoded images, least recently used first
+    lru: LruList,
+    /// The number of bytes taken up by the decoded images
+    decoded_size: uint,
+    /// How many bytes of decoded images to keep, or `None` to keep all of them
+    memory_budget: Option<uint>,
     need_exit: Option<Sender<()>>,
     task_pool: TaskPool,
 }
@@ -153,6 +210,8 @@ enum ImageState {
     Prefetched(Vec<u8>),
     Decoding,
     Decoded(Arc<Box<Image>>),
+    /// The image was decoded, but the decoded image was evicted to save memory.
+    Evicted,
     Failed
 }
 
@@ -162,6 +221,21 @@ enum AfterPrefetch {
     DoNotDecode
 }
 
+/// The state of decoding an image from the part of it that has arrived.
+struct PartialImage {
+    /// The data that has arrived so far
+    data: Vec<u8>,
+    /// How much of the data the last decode started with
+    decoded_length: uint,
+    /// Whether a decoder task is working on this image
+    decoding: bool,
+    /// The most recently decoded image
+    image: Option<Arc<Box<Image>>>,
+}
+
+/// How much more of a progressive image has to arrive before it is decoded again.
+static PARTIAL_IMAGE_STEP: uint = 32 * 1024;
+
 impl ImageCache {
     pub fn run(&mut self) {
         let mut store_chan: Option<Sender<()>> = None;
@@ -189,6 +263,11 @@ impl ImageCache {
 
                     self.store_image(url, image)
                 }
+                StorePartialImageData(url, data) => self.store_partial_image_data(url, data),
+                StorePartialImage(url, image) => self.store_partial_image(url, image),
+                AddImageUser(url) => self.add_image_user(url),
+                DiscardImages(urls) => self.discard_images(urls),
+                ReportMemory(reports_chan) => self.report_memory(reports_chan),
                 GetImage(url, response) => self.get_image(url, response),
                 WaitForImage(url, response) => {
                     self.wait_for_image(url, response)
@@ -213,9 +292,12 @@ impl ImageCache {
                         Prefetching(..) => can_exit = false,
                         Decoding => can_exit = false,
 
-                        Init | Prefetched(..) | Decoded(..) | Failed => ()
+                        Init | Prefetched(..) | Decoded(..) | Evicted | Failed => ()
                     }
                 }
+                if self.partial_map.values().any(|partial| partial.decoding) {
+                    can_exit = false;
+                }
 
                 if can_exit {
                     response.send(());
@@ -251,7 +333,9 @@ impl ImageCache {
                     let url = url_clone;
                     debug!("image_cache_task: started fetch for {:s}", url.serialize());
 
-                    let image = load_image_data(url.clone(), resource_task.clone());
+                    let image = load_image_data(url.clone(), resource_task.clone(), |data| {
+                        to_cache.send(StorePartialImageData(url.clone(), data.to_vec()));
+                    });
                     to_cache.send(StorePrefetchedImageData(url.clone(), image));
                     debug!("image_cache_task: ended fetch for {:s}", url.serialize());
                 });
@@ -259,7 +343,7 @@ impl ImageCache {
                 self.set_state(url, Prefetching(DoNotDecode));
             }
 
-            Prefetching(..) | Prefetched(..) | Decoding | Decoded(..) | Failed => {
+            Prefetching(..) | Prefetched(..) | Decoding | Decoded(..) | Evicted | Failed => {
                 // We've already begun working on this image
             }
         }
@@ -287,6 +371,7 @@ impl ImageCache {
           | Prefetched(..)
           | Decoding
           | Decoded(..)
+          | Evicted
           | Failed => {
             panic!("wrong state for storing prefetched image")
           }
@@ -299,7 +384,8 @@ impl ImageCache {
 
             Prefetching(DoNotDecode) => {
                 // We don't have the data yet, queue up the decode
-                self.set_state(url, Prefetching(DoDecode))
+                self.set_state(url.clone(), Prefetching(DoDecode));
+                self.decode_partial_image(url)
             }
 
             Prefetching(DoDecode) => {
@@ -307,19 +393,27 @@ impl ImageCache {
             }
 
             Prefetched(data) => {
-                let to_cache = self.chan.clone();
-                let url_clone = url.clone();
-
-                self.task_pool.execute(proc() {
-                    let url = url_clone;
-                    debug!("image_cache_task: started image decode for {:s}", url.serialize());
-                    let image = load_from_memory(data.as_slice());
-                    let image = image.map(|image| Arc::new(box image));
-                    to_cache.send(StoreImage(url.clone(), image));
-                    debug!("image_cache_task: ended image decode for {:s}", url.serialize());
-                });
+                let data = Arc::new(data);
+                if self.memory_budget.is_some() {
+                    // Keep the data around in case the decoded image is evicted.
+                    self.keep_encoded_data(url.clone(), data.clone());
+                }
+                self.start_decoding(url, data)
+            }
 
-                self.set_state(url, Decoding);
+            Evicted => {
+                match self.encoded_map.get(&url).map(|data| data.clone()) {
+                    Some(data) => {
+                        self.encoded_lru.touch(&url);
+                        self.start_decoding(url, data)
+                    }
+                    None => {
+                        // The data was evicted too, so the image is fetched again.
+                        self.set_state(url.clone(), Init);
+                        self.prefetch(url.clone());
+                        self.decode(url)
+                    }
+                }
             }
 
             Decoding | Decoded(..) | Failed => {
@@ -328,16 +422,38 @@ impl ImageCache {
         }
     }
 
+    fn start_decoding(&mut self, url: Url, data: Arc<Vec<u8>>) {
+        let to_cache = self.chan.clone();
+        let url_clone = url.clone();
+
+        self.task_pool.execute(proc() {
+            let url = url_clone;
+            debug!("image_cache_task: started image decode for {:s}", url.serialize());
+            let image = load_from_memory(data.as_slice());
+            let image = image.map(|image| Arc::new(box image));
+            to_cache.send(StoreImage(url.clone(), image));
+            debug!("image_cache_task: ended image decode for {:s}", url.serialize());
+        });
+
+        self.set_state(url, Decoding);
+    }
+
     fn store_image(&mut self, url: Url, image: Option<Arc<Box<Image>>>) {
 
         match self.get_state(&url) {
           Decoding => {
             match image {
               Some(image) => {
+                self.forget_partial_image(&url);
                 self.set_state(url.clone(), Decoded(image.clone()));
+                self.decoded_size += decoded_size(&**image);
+                self.lru.insert(url.clone());
                 self.purge_waiters(url, || ImageReady(image.clone()) );
+                self.evict_unused_images();
               }
               None => {
+                self.forget_partial_image(&url);
+                self.forget_encoded_data(&url);
                 self.set_state(url.clone(), Failed);
                 self.purge_waiters(url, || ImageFailed );
               }
@@ -348,6 +464,7 @@ impl ImageCache {
           | Prefetching(..)
           | Prefetched(..)
           | Decoded(..)
+          | Evicted
           | Failed => {
             panic!("incorrect state in store_image")
           }
@@ -355,6 +472,104 @@ impl ImageCache {
 
     }
 
+    fn store_partial_image_data(&mut self, url: Url, data: Vec<u8>) {
+        match self.get_state(&url) {
+            Prefetching(next_step) => {
+                match self.partial_map.entry(url.clone()) {
+                    Occupied(mut entry) => entry.get_mut().data.push_all(data.as_slice()),
+                    Vacant(entry) => {
+                        entry.set(PartialImage {
+                            data: data,
+                            decoded_length: 0,
+                            decoding: false,
+                            image: None,
+                        });
+                    }
+                }
+                match next_step {
+                    DoDecode => self.decode_partial_image(url),
+                    DoNotDecode => ()
+                }
+            }
+
+            Init | Prefetched(..) | Decoding | Decoded(..) | Evicted | Failed => {
+                panic!("wrong state for storing partial image data")
+            }
+        }
+    }
+
+    /// Starts decoding the data of a partly received image, unless a decode is already under way,
+    /// in which case the data is decoded once that one finishes. Each decode starts over from the
+    /// beginning of the image, so the image is only decoded again once at least twice as much of
+    /// it has arrived, which keeps the total work linear in the size of the image.
+    fn decode_partial_image(&mut self, url: Url) {
+        let data = match self.partial_map.get_mut(&url) {
+            Some(partial) => {
+                if partial.decoding || partial.data.len() < partial.decoded_length * 2 ||
+                        partial.data.len() == partial.decoded_length {
+                    return
+                }
+                partial.decoding = true;
+                partial.decoded_length = partial.data.len();
+                partial.data.clone()
+            }
+            None => return,
+        };
+
+        let to_cache = self.chan.clone();
+        self.task_pool.execute(proc() {
+            debug!("image_cache_task: started partial image decode for {:s}", url.serialize());
+            let image = load_partial_from_memory(data.as_slice());
+            let image = image.map(|image| Arc::new(box image));
+            to_cache.send(StorePartialImage(url.clone(), image));
+            debug!("image_cache_task: ended partial image decode for {:s}", url.serialize());
+        });
+    }
+
+    fn store_partial_image(&mut self, url: Url, image: Option<Arc<Box<Image>>>) {
+        match self.partial_map.get_mut(&url) {
+            Some(partial) => partial.decoding = false,
+            None => panic!("partial image stored without a decode"),
+        }
+
+        match self.get_state(&url) {
+            Prefetching(DoDecode) => {
+                match image {
+                    Some(image) => {
+                        self.partial_map.get_mut(&url).unwrap().image = Some(image.clone());
+                        self.purge_waiters(url.clone(), || ImagePartiallyReady(image.clone()));
+                    }
+                    None => ()
+                }
+                self.decode_partial_image(url)
+            }
+
+            Prefetching(DoNotDecode) | Init => panic!("partial image stored before decode"),
+
+            Prefetched(..) | Decoding | Decoded(..) | Evicted | Failed => {
+                // The whole image arrived while this one was being decoded.
+                self.forget_partial_image(&url)
+            }
+        }
+    }
+
+    /// Drops the partial image for a URL once the whole image is available, unless it is still
+    /// being decoded, in which case it is dropped when the decode finishes.
+    fn forget_partial_image(&mut self, url: &Url) {
+        let decoding = match self.partial_map.get(url) {
+            Some(partial) => partial.decoding,
+            None => return,
+        };
+        if decoding {
+            let partial = self.partial_map.get_mut(url).unwrap();
+            partial.data = vec!();
+            partial.decoded_length = 0;
+            partial.image = None;
+        } else {
+            self.partial_map.remove(url);
+        }
+    }
+
     fn purge_waiters(&mut self, url: Url, f: || -> ImageResponseMsg) {
         match self.wait_map.remove(&url) {
             Some(waiters) => {
@@ -367,13 +582,24 @@ impl ImageCache {
         }
     }
 
-    fn get_image(&self, url: Url, response: Sender<ImageResponseMsg>) {
+    fn get_image(&mut self, url: Url, response: Sender<ImageResponseMsg>) {
         match self.get_state(&url) {
             Init => panic!("request for image before prefetch"),
-            Prefetching(DoDecode) => response.send(ImageNotReady),
+            Prefetching(DoDecode) | Decoding => {
+                match self.partial_map.get(&url).and_then(|partial| partial.image.clone()) {
+                    Some(image) => response.send(ImagePartiallyReady(image)),
+                    None => response.send(ImageNotReady),
+                }
+            }
             Prefetching(DoNotDecode) | Prefetched(..) => panic!("request for image before decode"),
-            Decoding => response.send(ImageNotReady),
-            Decoded(image) => response.send(ImageReady(image)),
+            Decoded(image) => {
+                self.touch(&url);
+                response.send(ImageReady(image))
+            }
+            Evicted => {
+                self.decode(url);
+                response.send(ImageNotReady)
+            }
             Failed => response.send(ImageFailed),
         }
     }
@@ -384,6 +610,11 @@ impl ImageCache {
 
             Prefetching(DoNotDecode) | Prefetched(..) => panic!("request for image before decode"),
 
+            Evicted => {
+                self.decode(url.clone());
+                self.wait_for_image(url, response)
+            }
+
             Prefetching(DoDecode) | Decoding => {
                 // We don't have this image yet
                 match self.wait_map.entry(url) {
@@ -397,6 +628,7 @@ impl ImageCache {
             }
 
             Decoded(image) => {
+                self.touch(&url);
                 response.send(ImageReady(image));
             }
 
@@ -406,6 +638,180 @@ impl ImageCache {
         }
     }
 
+    /// Marks a decoded image as the most recently used one.
+    fn touch(&mut self, url: &Url) {
+        self.lru.touch(url)
+    }
+
+    fn add_image_user(&mut self, url: Url) {
+        self.touch(&url);
+        match self.user_map.entry(url) {
+            Occupied(mut entry) => *entry.get_mut() += 1,
+            Vacant(entry) => {
+                entry.set(1);
+            }
+        }
+    }
+
+    fn discard_images(&mut self, urls: Vec<Url>) {
+        for url in urls.into_iter() {
+            let unused = match self.user_map.get_mut(&url) {
+                Some(users) => {
+                    *users -= 1;
+                    *users == 0
+                }
+                None => false,
+            };
+            if unused {
+                self.user_map.remove(&url);
+            }
+        }
+        self.evict_unused_images();
+        self.evict_unused_encoded_data();
+    }
+
+    /// Evicts the least recently used decoded images that no client is displaying until the
+    /// decoded images fit in the memory budget.
+    fn evict_unused_images(&mut self) {
+        let memory_budget = match self.memory_budget {
+            Some(memory_budget) => memory_budget,
+            None => return,
+        };
+
+        let mut evicted = vec!();
+        let mut remaining_size = self.decoded_size;
+        for url in self.lru.order.values() {
+            if remaining_size <= memory_budget {
+                break
+            }
+            if self.user_map.contains_key(url) {
+                continue
+            }
+            match self.state_map.get(url) {
+                Some(&Decoded(ref image)) => remaining_size -= decoded_size(&**image),
+                _ => panic!("evicting an image that isn't decoded"),
+            }
+            evicted.push(url.clone());
+        }
+
+        for url in evicted.into_iter() {
+            debug!("image_cache_task: evicting {:s}", url.serialize());
+            self.lru.remove(&url);
+            self.set_state(url, Evicted);
+        }
+        self.decoded_size = remaining_size;
+    }
+
+    /// Keeps the data of an image, so that it can be decoded again after being evicted.
+    fn keep_encoded_data(&mut self, url: Url, data: Arc<Vec<u8>>) {
+        self.forget_encoded_data(&url);
+        self.encoded_size += data.len();
+        self.encoded_map.insert(url.clone(), data);
+        self.encoded_lru.insert(url);
+        self.evict_unused_encoded_data();
+    }
+
+    fn forget_encoded_data(&mut self, url: &Url) {
+        match self.encoded_map.remove(url) {
+            Some(data) => self.encoded_size -= data.len(),
+            None => ()
+        }
+        self.encoded_lru.remove(url);
+    }
+
+    /// Drops the least recently used data of images that no client is displaying until the data
+    /// fits in the memory budget.
+    fn evict_unused_encoded_data(&mut self) {
+        let memory_budget = match self.memory_budget {
+            Some(memory_budget) => memory_budget,
+            None => return,
+        };
+
+        let mut evicted = vec!();
+        let mut remaining_size = self.encoded_size;
+        for url in self.encoded_lru.order.values() {
+            if remaining_size <= memory_budget {
+                break
+            }
+            if self.user_map.contains_key(url) {
+                continue
+            }
+            remaining_size -= self.encoded_map.get(url).unwrap().len();
+            evicted.push(url.clone());
+        }
+
+        for url in evicted.into_iter() {
+            debug!("image_cache_task: dropping the data of {:s}", url.serialize());
+            self.forget_encoded_data(&url);
+        }
+    }
+
+    fn report_memory(&self, reports_chan: Sender<Vec<MemoryReport>>) {
+        reports_chan.send(vec!(
+            MemoryReport {
+                path: "image-cache-decoded".to_string(),
+                size: self.decoded_size as u64,
+            },
+            MemoryReport {
+                path: "image-cache-encoded".to_string(),
+                size: self.encoded_size as u64,
+            },
+        ));
+    }
+}
+
+/// The number of bytes taken up by the frames of a decoded image.
+fn decoded_size(image: &Image) -> uint {
+    image.frames.iter().fold(0, |size, frame| {
+        size + match frame.pixels {
+            png::RGB8(ref pixels) | png::RGBA8(ref pixels) |
+            png::K8(ref pixels) | png::KA8(ref pixels) => pixels.len(),
+        }
+    })
+}
+
+/// The URLs of decoded images in the order they were last used, least recently used first.
+struct LruList {
+    /// The URLs by when they were last used
+    order: TreeMap<u64, Url>,
+    /// When each URL was last used
+    stamps: HashMap<Url, u64>,
+    next_stamp: u64,
+}
+
+impl LruList {
+    fn new() -> LruList {
+        LruList {
+            order: TreeMap::new(),
+            stamps: HashMap::new(),
+            next_stamp: 0,
+        }
+    }
+
+    /// Adds a URL as the most recently used one, or moves it there.
+    fn insert(&mut self, url: Url) {
+        self.remove(&url);
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        self.order.insert(stamp, url.clone());
+        self.stamps.insert(url, stamp);
+    }
+
+    /// Moves a URL that is in the list to the most recently used end.
+    fn touch(&mut self, url: &Url) {
+        if self.stamps.contains_key(url) {
+            self.insert(url.clone())
+        }
+    }
+
+    fn remove(&mut self, url: &Url) {
+        match self.stamps.remove(url) {
+            Some(stamp) => {
+                self.order.remove(&stamp);
+            }
+            None => ()
+        }
+    }
 }
 
 
@@ -421,6 +827,12 @@ impl ImageCacheTaskClient for ImageCacheTask {
     }
 }
 
+impl MemoryReporter for ImageCacheTask {
+    fn collect_reports(&self, reports_chan: Sender<Vec<MemoryReport>>) -> bool {
+        self.chan.send_opt(ReportMemory(reports_chan)).is_ok()
+    }
+}
+
 impl ImageCacheTask {
     pub fn send(&self, msg: Msg) {
         self.chan.send(msg);
@@ -441,29 +853,45 @@ impl ImageCacheTask {
     }
 }
 
-fn load_image_data(url: Url, resource_task: ResourceTask) -> Result<Vec<u8>, ()> {
-    let (response_chan, response_port) = channel();
-    resource_task.send(resource_task::Load(LoadData::new(url, response_chan)));
+/// Loads the data of an image. Whenever enough more of an image that can be shown before it has
+/// fully arrived has been received, the data received since the last time is passed to
+/// `progress`.
+fn load_image_data(url: Url, resource_task: ResourceTask, progress: |&[u8]|)
+                   -> Result<Vec<u8>, ()> {
+    let (response, body_port) = fetch_streaming(&resource_task, Request::new(url, ImageContext));
+    if response.is_network_error() {
+        return Err(());
+    }
 
     let mut image_data = vec!();
+    let mut reported_length = 0;
+    // Whether the image can be shown before it has fully arrived, once its headers say so.
+    let mut progressive = None;
 
-    let progress_port = response_port.recv().progress_port;
     loop {
-        match progress_port.recv() {
-            resource_task::Payload(data) => {
+        match body_port.recv_opt() {
+            Ok(Chunk(data)) => {
                 image_data.push_all(data.as_slice());
+                if image_data.len() >= reported_length + PARTIAL_IMAGE_STEP {
+                    if progressive.is_none() {
+                        progressive = progressiveness(image_data.as_slice());
+                    }
+                    if progressive == Some(true) {
+                        progress(image_data.slice_from(reported_length));
+                    }
+                    reported_length = image_data.len();
+                }
             }
-            resource_task::Done(result::Ok(..)) => {
+            Ok(Finished) => {
                 return Ok(image_data);
             }
-            resource_task::Done(result::Err(..)) => {
+            Ok(Errored) | Err(()) => {
                 return Err(());
             }
         }
     }
 }
 
-
 pub fn spawn_listener<A: Send>(f: proc(Receiver<A>):Send) -> Sender<A> {
     let (setup_chan, setup_port) = channel();
 
@@ -480,10 +908,14 @@ pub fn spawn_listener<A: Send>(f: proc(Receiver<A>):Send) -> Sender<A> {
 mod tests {
     use super::*;
 
+    use fetch::request::Request;
+    use fetch::response::{Response, Receiving, Chunk, Finished, Errored};
     use resource_task;
-    use resource_task::{ResourceTask, Metadata, start_sending, ResponseSenders};
-    use sniffer_task;
+    use resource_task::ResourceTask;
+    use image::apng::push_chunk;
     use image::base::test_image_bin;
+    use content_decoder::zlib_bytes;
+    use servo_util::memory::MemoryReporter;
     use servo_util::taskpool::TaskPool;
     use std::comm;
     use url::Url;
@@ -512,6 +944,17 @@ mod tests {
         }
     }
 
+    struct SendTestImageAndNotify {
+        fetched_chan: Sender<()>,
+    }
+    impl Closure for SendTestImageAndNotify {
+        fn invoke(&self, response: Sender<resource_task::ProgressMsg>) {
+            self.fetched_chan.send(());
+            response.send(resource_task::Payload(test_image_bin()));
+            response.send(resource_task::Done(Ok(())));
+        }
+    }
+
     struct SendBogusImage;
     impl Closure for SendBogusImage {
         fn invoke(&self, response: Sender<resource_task::ProgressMsg>) {
@@ -554,20 +997,73 @@ mod tests {
         }
     }
 
+    /// Sends an interlaced PNG in two parts that are each big enough to be worth decoding.
+    struct SendPartialImageInTwoParts;
+    impl Closure for SendPartialImageInTwoParts {
+        fn invoke(&self, response: Sender<resource_task::ProgressMsg>) {
+            let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+            push_chunk(&mut png, b"IHDR", &[0, 0, 0, 8, 0, 0, 0, 8, 8, 2, 0, 0, 1]);
+            push_chunk(&mut png, b"tEXt", Vec::from_elem(super::PARTIAL_IMAGE_STEP, 0).as_slice());
+            response.send(resource_task::Payload(png));
+
+            let mut png = vec!();
+            push_chunk(&mut png, b"tEXt", Vec::from_elem(super::PARTIAL_IMAGE_STEP, 1).as_slice());
+            let data = zlib_bytes(&[0, 255, 0, 0]);
+            push_chunk(&mut png, b"IDAT", data.as_slice());
+            response.send(resource_task::Payload(png));
+            response.send(resource_task::Done(Ok(())));
+        }
+    }
+
+    /// Sends the first pass of an interlaced PNG, padded so that it is worth decoding, once the
+    /// client requests the image, then fails once the client asks for the rest.
+    struct WaitSendPartialImageErr {
+        wait_port: Receiver<()>,
+    }
+    impl Closure for WaitSendPartialImageErr {
+        fn invoke(&self, response: Sender<resource_task::ProgressMsg>) {
+            let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+            push_chunk(&mut png, b"IHDR", &[0, 0, 0, 8, 0, 0, 0, 8, 8, 2, 0, 0, 1]);
+            push_chunk(&mut png, b"tEXt", Vec::from_elem(super::PARTIAL_IMAGE_STEP, 0).as_slice());
+            let data = zlib_bytes(&[0, 255, 0, 0]);
+            push_chunk(&mut png, b"IDAT", data.as_slice());
+            self.wait_port.recv();
+            response.send(resource_task::Payload(png));
+            self.wait_port.recv();
+            response.send(resource_task::Done(Err("".to_string())));
+        }
+    }
+
+    /// Answers a fetch, returning where to send the body of the response.
+    fn respond(request: Request, response_chan: Sender<Response>)
+               -> Sender<resource_task::ProgressMsg> {
+        let body_chan = request.body_chan.unwrap();
+        let (chan, port) = comm::channel();
+        spawn(proc() {
+            for msg in port.iter() {
+                let msg = match msg {
+                    resource_task::Payload(data) => Chunk(data),
+                    resource_task::Done(Ok(())) => Finished,
+                    resource_task::Done(Err(_)) => Errored
+                };
+                let _ = body_chan.send_opt(msg);
+            }
+        });
+        let mut response = Response::new();
+        response.body = Receiving(vec!());
+        response_chan.send(response);
+        chan
+    }
+
     fn mock_resource_task<T: Closure+Send>(on_load: Box<T>) -> ResourceTask {
         spawn_listener(proc(port: Receiver<resource_task::ControlMsg>) {
             loop {
                 match port.recv() {
-                    resource_task::Load(response) => {
-                        let sniffer_task = sniffer_task::new_sniffer_task();
-                        let senders = ResponseSenders {
-                            immediate_consumer: sniffer_task,
-                            eventual_consumer: response.consumer.clone(),
-                        };
-                        let chan = start_sending(senders, Metadata::default(
-                            Url::parse("file:///fake").unwrap()));
+                    resource_task::Fetch(request, response_chan) => {
+                        let chan = respond(request, response_chan);
                         on_load.invoke(chan);
                     }
+                    resource_task::Load(_) => panic!("images should be fetched"),
                     resource_task::Exit => break
                 }
             }
@@ -578,7 +1074,8 @@ mod tests {
     fn should_exit_on_request() {
         let mock_resource_task = mock_resource_task(box DoesNothing);
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
 
         image_cache_task.exit();
         mock_resource_task.send(resource_task::Exit);
@@ -589,7 +1086,8 @@ mod tests {
     fn should_fail_if_unprefetched_image_is_requested() {
         let mock_resource_task = mock_resource_task(box DoesNothing);
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
         let url = Url::parse("file:///").unwrap();
 
         let (chan, port) = channel();
@@ -603,7 +1101,8 @@ mod tests {
 
         let mock_resource_task = mock_resource_task(box JustSendOK { url_requested_chan: url_requested_chan});
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
         let url = Url::parse("file:///").unwrap();
 
         image_cache_task.send(Prefetch(url));
@@ -618,7 +1117,8 @@ mod tests {
 
         let mock_resource_task = mock_resource_task(box JustSendOK { url_requested_chan: url_requested_chan});
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
         let url = Url::parse("file:///").unwrap();
 
         image_cache_task.send(Prefetch(url.clone()));
@@ -638,7 +1138,8 @@ mod tests {
 
         let mock_resource_task = mock_resource_task(box WaitSendTestImage{wait_port: wait_port});
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
         let url = Url::parse("file:///").unwrap();
 
         image_cache_task.send(Prefetch(url.clone()));
@@ -655,7 +1156,8 @@ mod tests {
     fn should_return_decoded_image_data_if_data_has_arrived() {
         let mock_resource_task = mock_resource_task(box SendTestImage);
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
         let url = Url::parse("file:///").unwrap();
 
         let join_port = image_cache_task.wait_for_store();
@@ -681,7 +1183,8 @@ mod tests {
     fn should_return_decoded_image_data_for_multiple_requests() {
         let mock_resource_task = mock_resource_task(box SendTestImage);
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
         let url = Url::parse("file:///").unwrap();
 
         let join_port = image_cache_task.wait_for_store();
@@ -714,18 +1217,13 @@ mod tests {
         let mock_resource_task = spawn_listener(proc(port: Receiver<resource_task::ControlMsg>) {
             loop {
                 match port.recv() {
-                    resource_task::Load(response) => {
-                        let sniffer_task = sniffer_task::new_sniffer_task();
-                        let senders = ResponseSenders {
-                            immediate_consumer: sniffer_task,
-                            eventual_consumer: response.consumer.clone(),
-                        };
-                        let chan = start_sending(senders, Metadata::default(
-                            Url::parse("file:///fake").unwrap()));
+                    resource_task::Fetch(request, response_chan) => {
+                        let chan = respond(request, response_chan);
                         chan.send(resource_task::Payload(test_image_bin()));
                         chan.send(resource_task::Done(Ok(())));
                         image_bin_sent_chan.send(());
                     }
+                    resource_task::Load(_) => panic!("images should be fetched"),
                     resource_task::Exit => {
                         resource_task_exited_chan.send(());
                         break
@@ -734,7 +1232,8 @@ mod tests {
             }
         });
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
         let url = Url::parse("file:///").unwrap();
 
         image_cache_task.send(Prefetch(url.clone()));
@@ -766,18 +1265,13 @@ mod tests {
         let mock_resource_task = spawn_listener(proc(port: Receiver<resource_task::ControlMsg>) {
             loop {
                 match port.recv() {
-                    resource_task::Load(response) => {
-                        let sniffer_task = sniffer_task::new_sniffer_task();
-                        let senders = ResponseSenders {
-                            immediate_consumer: sniffer_task,
-                            eventual_consumer: response.consumer.clone(),
-                        };
-                        let chan = start_sending(senders, Metadata::default(
-                            Url::parse("file:///fake").unwrap()));
+                    resource_task::Fetch(request, response_chan) => {
+                        let chan = respond(request, response_chan);
                         chan.send(resource_task::Payload(test_image_bin()));
                         chan.send(resource_task::Done(Err("".to_string())));
                         image_bin_sent_chan.send(());
                     }
+                    resource_task::Load(_) => panic!("images should be fetched"),
                     resource_task::Exit => {
                         resource_task_exited_chan.send(());
                         break
@@ -786,7 +1280,8 @@ mod tests {
             }
         });
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
         let url = Url::parse("file:///").unwrap();
 
         image_cache_task.send(Prefetch(url.clone()));
@@ -815,7 +1310,8 @@ mod tests {
     fn should_return_failed_if_image_bin_cannot_be_fetched() {
         let mock_resource_task = mock_resource_task(box SendTestImageErr);
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
         let url = Url::parse("file:///").unwrap();
 
         let join_port = image_cache_task.wait_for_store_prefetched();
@@ -841,7 +1337,8 @@ mod tests {
     fn should_return_failed_for_multiple_get_image_requests_if_image_bin_cannot_be_fetched() {
         let mock_resource_task = mock_resource_task(box SendTestImageErr);
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
         let url = Url::parse("file:///").unwrap();
 
         let join_port = image_cache_task.wait_for_store_prefetched();
@@ -875,7 +1372,8 @@ mod tests {
     fn should_return_failed_if_image_decode_fails() {
         let mock_resource_task = mock_resource_task(box SendBogusImage);
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
         let url = Url::parse("file:///").unwrap();
 
         let join_port = image_cache_task.wait_for_store();
@@ -903,7 +1401,8 @@ mod tests {
     fn should_return_image_on_wait_if_image_is_already_loaded() {
         let mock_resource_task = mock_resource_task(box SendTestImage);
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
         let url = Url::parse("file:///").unwrap();
 
         let join_port = image_cache_task.wait_for_store();
@@ -931,7 +1430,8 @@ mod tests {
 
         let mock_resource_task = mock_resource_task(box WaitSendTestImage {wait_port: wait_port});
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
         let url = Url::parse("file:///").unwrap();
 
         image_cache_task.send(Prefetch(url.clone()));
@@ -957,7 +1457,8 @@ mod tests {
 
         let mock_resource_task = mock_resource_task(box WaitSendTestImageErr{wait_port: wait_port});
 
-        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
         let url = Url::parse("file:///").unwrap();
 
         image_cache_task.send(Prefetch(url.clone()));
@@ -977,11 +1478,218 @@ mod tests {
         mock_resource_task.send(resource_task::Exit);
     }
 
+    #[test]
+    fn should_return_partial_image_while_image_is_loading() {
+        let (wait_chan, wait_port) = comm::channel();
+
+        let mock_resource_task =
+            mock_resource_task(box WaitSendPartialImageErr { wait_port: wait_port });
+
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   None);
+        let url = Url::parse("file:///").unwrap();
+
+        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Decode(url.clone()));
+
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(WaitForImage(url.clone(), response_chan));
+        wait_chan.send(());
+        match response_port.recv() {
+          ImagePartiallyReady(image) => assert_eq!((image.width, image.height), (8, 8)),
+          _ => panic!("bleh")
+        }
+
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(GetImage(url.clone(), response_chan));
+        match response_port.recv() {
+          ImagePartiallyReady(..) => (),
+          _ => panic!("bleh")
+        }
+
+        // Waiters after a partial image wait for the rest of the image.
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(WaitForImage(url, response_chan));
+        wait_chan.send(());
+        match response_port.recv() {
+          ImageFailed => (),
+          _ => panic!("bleh")
+        }
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
+
+    #[test]
+    fn should_report_only_new_partial_image_data() {
+        let mock_resource_task = mock_resource_task(box SendPartialImageInTwoParts);
+        let url = Url::parse("file:///").unwrap();
+
+        let mut reported = vec!();
+        let data = super::load_image_data(url, mock_resource_task.clone(), |data| {
+            reported.push_all(data);
+        }).unwrap();
+        // The parts that are reported add up to the whole image, rather than repeating the
+        // beginning of it.
+        assert_eq!(reported, data);
+
+        mock_resource_task.send(resource_task::Exit);
+    }
+
+    #[test]
+    fn should_decode_evicted_images_again_on_request() {
+        let mock_resource_task = mock_resource_task(box SendTestImage);
+
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   Some(0));
+        let url = Url::parse("file:///").unwrap();
+
+        let join_port = image_cache_task.wait_for_store();
+
+        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Decode(url.clone()));
+
+        // Nothing displays the image, so it is evicted as soon as it is decoded.
+        join_port.recv();
+
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(GetImage(url.clone(), response_chan));
+        assert!(response_port.recv() == ImageNotReady);
+
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(WaitForImage(url, response_chan));
+        match response_port.recv() {
+          ImageReady(_) => (),
+          _ => panic!("bleh")
+        }
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
+
+    #[test]
+    fn should_not_evict_images_until_discarded() {
+        let mock_resource_task = mock_resource_task(box SendTestImage);
+
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   Some(0));
+        let url = Url::parse("file:///").unwrap();
+
+        let join_port = image_cache_task.wait_for_store();
+
+        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Decode(url.clone()));
+        image_cache_task.send(AddImageUser(url.clone()));
+
+        join_port.recv();
+
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(GetImage(url.clone(), response_chan));
+        match response_port.recv() {
+          ImageReady(_) => (),
+          _ => panic!("bleh")
+        }
+
+        image_cache_task.send(DiscardImages(vec!(url.clone())));
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(GetImage(url, response_chan));
+        assert!(response_port.recv() == ImageNotReady);
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
+
+    #[test]
+    fn should_fetch_images_again_once_their_data_is_evicted() {
+        let (fetched_chan, fetched) = comm::channel();
+        let mock_resource_task = mock_resource_task(box SendTestImageAndNotify {
+            fetched_chan: fetched_chan,
+        });
+
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   Some(0));
+        let url = Url::parse("file:///").unwrap();
+
+        let join_port = image_cache_task.wait_for_store();
+
+        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Decode(url.clone()));
+        image_cache_task.send(AddImageUser(url.clone()));
+
+        join_port.recv();
+        fetched.recv();
+
+        // Neither the decoded image nor its data fit in the budget once nothing displays it.
+        image_cache_task.send(DiscardImages(vec!(url.clone())));
+        let (reports_chan, reports_port) = comm::channel();
+        assert!(image_cache_task.collect_reports(reports_chan));
+        let reports = reports_port.recv();
+        assert_eq!(reports[1].size, 0);
+
+        let (response_chan, response_port) = comm::channel();
+        image_cache_task.send(WaitForImage(url, response_chan));
+        fetched.recv();
+        match response_port.recv() {
+          ImageReady(_) => (),
+          _ => panic!("bleh")
+        }
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
+
+    #[test]
+    fn should_report_decoded_and_encoded_image_sizes() {
+        let mock_resource_task = mock_resource_task(box SendTestImage);
+
+        let image_cache_task = ImageCacheTask::new(mock_resource_task.clone(), TaskPool::new(4),
+                                                   Some(1024 * 1024));
+        let url = Url::parse("file:///").unwrap();
+
+        let join_port = image_cache_task.wait_for_store();
+
+        image_cache_task.send(Prefetch(url.clone()));
+        image_cache_task.send(Decode(url));
+
+        join_port.recv();
+
+        let (reports_chan, reports_port) = comm::channel();
+        assert!(image_cache_task.collect_reports(reports_chan));
+        let reports = reports_port.recv();
+        assert_eq!(reports[0].path.as_slice(), "image-cache-decoded");
+        assert!(reports[0].size > 0);
+        assert_eq!(reports[1].path.as_slice(), "image-cache-encoded");
+        assert_eq!(reports[1].size, test_image_bin().len() as u64);
+
+        image_cache_task.exit();
+        mock_resource_task.send(resource_task::Exit);
+    }
+
+    #[test]
+    fn lru_list_should_order_urls_by_last_use() {
+        let a = Url::parse("file:///a").unwrap();
+        let b = Url::parse("file:///b").unwrap();
+        let c = Url::parse("file:///c").unwrap();
+
+        let mut lru = super::LruList::new();
+        lru.insert(a.clone());
+        lru.insert(b.clone());
+        lru.insert(c.clone());
+        lru.touch(&a);
+        lru.remove(&b);
+        // URLs that aren't in the list aren't added by touching them.
+        lru.touch(&b);
+
+        let order: Vec<Url> = lru.order.values().map(|url| url.clone()).collect();
+        assert_eq!(order, vec!(c, a));
+    }
+
     #[test]
     fn sync_cache_should_wait_for_images() {
         let mock_resource_task = mock_resource_task(box SendTestImage);
 
-        let image_cache_task = ImageCacheTask::new_sync(mock_resource_task.clone(), TaskPool::new(4));
+        let image_cache_task = ImageCacheTask::new_sync(mock_resource_task.clone(),
+                                                        TaskPool::new(4), None);
         let url = Url::parse("file:///").unwrap();
 
         image_cache_task.send(Prefetch(url.clone()));
@@ -7,9 +7,14 @@
 #![deny(unused_imports)]
 #![deny(unused_variables)]
 
+extern crate brotli;
 extern crate collections;
+extern crate devtools_traits;
+extern crate flate2;
 extern crate geom;
 extern crate hyper;
+extern crate libc;
+extern crate openssl;
 extern crate png;
 #[phase(plugin, link)]
 extern crate log;
@@ -20,22 +25,34 @@ extern crate sync;
 extern crate time;
 extern crate url;
 
+/// Unwraps an `Option`, returning `None` from the enclosing function if there is no value.
+macro_rules! try_opt(
+    ($e:expr) => (match $e { Some(value) => value, None => return None })
+)
+
 /// Image handling.
 ///
 /// It may be surprising that this goes in the network crate as opposed to the graphics crate.
 /// However, image handling is generally very integrated with the network stack (especially where
 /// caching is involved) and as a result it must live in here.
 pub mod image {
+    pub mod apng;
     pub mod base;
+    pub mod gif;
     pub mod holder;
+    pub mod progressive;
+    pub mod webp;
 }
 
 pub mod about_loader;
+mod content_decoder;
 pub mod file_loader;
+pub mod http_cache;
 pub mod http_loader;
 pub mod data_loader;
 pub mod image_cache_task;
 pub mod local_image_cache;
+pub mod proxy;
 pub mod resource_task;
 pub mod storage_task;
 mod sniffer_task;
@@ -8,11 +8,12 @@ extra message traffic, it also avoids waiting on the same image
 multiple times and thus triggering reflows multiple times.
 */
 
-use image_cache_task::{Decode, GetImage, ImageCacheTask, ImageFailed, ImageNotReady, ImageReady};
+use image_cache_task::{AddImageUser, Decode, DiscardImages, GetImage, ImageCacheTask};
+use image_cache_task::{ImageFailed, ImageNotReady, ImagePartiallyReady, ImageReady};
 use image_cache_task::{ImageResponseMsg, Prefetch, WaitForImage};
 
 use std::comm::{Receiver, channel};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::collections::hash_map::{Occupied, Vacant};
 use servo_util::task::spawn_named;
 use url::Url;
@@ -43,6 +44,8 @@ impl<NodeAddress: Send> LocalImageCache<NodeAddress> {
 struct ImageState {
     prefetched: bool,
     decoded: bool,
+    /// Whether the image cache has been told that the decoded image is displayed
+    in_use: bool,
     last_request_round: uint,
     last_response: ImageResponseMsg
 }
@@ -80,6 +83,52 @@ impl<NodeAddress: Send> LocalImageCache<NodeAddress> {
         self.image_cache_task.send(Decode((*url).clone()));
     }
 
+    /// Tells the image cache that the images this cache has received won't be displayed any
+    /// more, so that they can be evicted. Layout should call this when its pipeline exits.
+    pub fn discard(&mut self) {
+        let urls = self.state_map.iter().filter(|&(_, state)| state.in_use).map(|(url, _)| {
+            url.clone()
+        }).collect();
+        self.state_map.clear();
+        self.send_discard_images(urls);
+    }
+
+    /// Tells the image cache which of the images that this cache has received are displayed,
+    /// so that the others can be evicted. Layout should call this whenever it has built a new
+    /// display list, with the URLs of the images in it.
+    pub fn retain_images(&mut self, displayed_urls: &HashSet<Url>) {
+        let mut discarded_urls = vec!();
+        for (url, state) in self.state_map.iter_mut() {
+            let ready = match state.last_response {
+                ImageReady(..) => true,
+                _ => false,
+            };
+            if !ready {
+                continue
+            }
+            let displayed = displayed_urls.contains(url);
+            if !displayed {
+                if state.in_use {
+                    discarded_urls.push(url.clone());
+                }
+                // Let go of the image, so that the image cache can evict it, and ask for it
+                // again if it is displayed again.
+                state.last_response = ImageNotReady;
+                state.last_request_round = 0;
+            } else if !state.in_use {
+                self.image_cache_task.send(AddImageUser(url.clone()));
+            }
+            state.in_use = displayed;
+        }
+        self.send_discard_images(discarded_urls);
+    }
+
+    fn send_discard_images(&self, urls: Vec<Url>) {
+        if !urls.is_empty() {
+            self.image_cache_task.send(DiscardImages(urls));
+        }
+    }
+
     // FIXME: Should return a Future
     pub fn get_image(&mut self, node_address: NodeAddress, url: &Url) -> Receiver<ImageResponseMsg> {
         {
@@ -97,6 +146,16 @@ impl<NodeAddress: Send> LocalImageCache<NodeAddress> {
                     chan.send(ImageReady(image.clone()));
                     return port;
                 }
+                ImagePartiallyReady(ref image) => {
+                    if last_round == round_number {
+                        let (chan, port) = channel();
+                        chan.send(ImagePartiallyReady(image.clone()));
+                        return port;
+                    } else {
+                        // More of the image may have been decoded since
+                        // the last round
+                    }
+                }
                 ImageNotReady => {
                     if last_round == round_number {
                         let (chan, port) = channel();
@@ -120,8 +179,8 @@ impl<NodeAddress: Send> LocalImageCache<NodeAddress> {
 
         let response = response_port.recv();
         match response {
-            ImageNotReady => {
-                // Need to reflow when the image is available
+            ImagePartiallyReady(..) | ImageNotReady => {
+                // Need to reflow when the image, or more of it, is available
                 // FIXME: Instead we should be just passing a Future
                 // to the caller, then to the display list. Finally,
                 // the compositor should be resonsible for waiting
@@ -137,12 +196,18 @@ impl<NodeAddress: Send> LocalImageCache<NodeAddress> {
                     on_image_available(response_port.recv(), node_address);
                 });
             }
-            _ => ()
+            ImageReady(..) => {
+                // The image cache mustn't evict the image while it is displayed
+                self.image_cache_task.send(AddImageUser((*url).clone()));
+                self.get_state(url).in_use = true;
+            }
+            ImageFailed => ()
         }
 
         // Put a copy of the response in the cache
         let response_copy = match response {
             ImageReady(ref image) => ImageReady(image.clone()),
+            ImagePartiallyReady(ref image) => ImagePartiallyReady(image.clone()),
             ImageNotReady => ImageNotReady,
             ImageFailed => ImageFailed
         };
@@ -160,6 +225,7 @@ impl<NodeAddress: Send> LocalImageCache<NodeAddress> {
                 entry.set(ImageState {
                     prefetched: false,
                     decoded: false,
+                    in_use: false,
                     last_request_round: 0,
                     last_response: ImageNotReady,
                 })
@@ -6,25 +6,35 @@
 
 use about_loader;
 use data_loader;
+use fetch::cors_cache::{CORSCacheSender, CORSCacheTask, ExitMsg};
+use fetch::request::{CacheMode, Context, DefaultCache, Request};
+use fetch::response::{Response, ResponseMsg, Chunk, Finished, Errored};
 use file_loader;
+use http_cache::{HttpCache, SharedHttpCache};
 use http_loader;
+use proxy::ProxyConfig;
 use sniffer_task;
 use sniffer_task::SnifferTask;
 
+use devtools_traits::DevtoolsControlChan;
+
 use std::comm::{channel, Receiver, Sender};
 use hyper::mime::{Mime, Charset};
 use hyper::header::Headers;
-use hyper::header::common::UserAgent;
+use hyper::header::common::{ContentType, UserAgent};
 use hyper::method::{Method, Get};
 use url::Url;
 
 use hyper::http::RawStatus;
 
+use servo_util::opts::Opts;
 use servo_util::task::spawn_named;
 
 pub enum ControlMsg {
     /// Request the data associated with a particular URL
     Load(LoadData),
+    /// Run the [Fetch algorithm](http://fetch.spec.whatwg.org/#concept-fetch) for a request
+    Fetch(Request, Sender<Response>),
     Exit
 }
 
@@ -35,6 +45,10 @@ pub struct LoadData {
     pub headers: Headers,
     pub data: Option<Vec<u8>>,
     pub cors: Option<ResourceCORSData>,
+    /// How the HTTP cache is used for the load.
+    pub cache_mode: CacheMode,
+    /// Whether HTTP redirects are followed, rather than handed to the consumer.
+    pub follow_redirects: bool,
     pub consumer: Sender<LoadResponse>,
 }
 
@@ -46,6 +60,8 @@ impl LoadData {
             headers: Headers::new(),
             data: None,
             cors: None,
+            cache_mode: DefaultCache,
+            follow_redirects: true,
             consumer: consumer,
         }
     }
@@ -103,6 +119,20 @@ impl Metadata {
             }
         }
     }
+
+    /// The metadata of a fetched response for `url`.
+    pub fn from_response(response: &Response, url: Url) -> Metadata {
+        let mut metadata = Metadata::default(response.url.clone().unwrap_or(url));
+        metadata.set_content_type(response.headers.get::<ContentType>().map(|content_type| {
+            let &ContentType(ref mime) = content_type;
+            mime
+        }));
+        metadata.status = response.status.clone().map(|status| {
+            RawStatus(status as u16, response.status_message.clone())
+        });
+        metadata.headers = Some(response.headers.clone());
+        metadata
+    }
 }
 
 /// Message sent in response to `Load`.  Contains metadata, and a port
@@ -158,48 +188,129 @@ pub fn start_sending_opt(senders: ResponseSenders, metadata: Metadata) -> Result
     }
 }
 
-/// Convenience function for synchronously loading a whole resource.
-pub fn load_whole_resource(resource_task: &ResourceTask, url: Url)
+/// Convenience function for synchronously fetching a whole resource.
+pub fn load_whole_resource(resource_task: &ResourceTask, url: Url, context: Context)
         -> Result<(Metadata, Vec<u8>), String> {
-    let (start_chan, start_port) = channel();
-    resource_task.send(Load(LoadData::new(url, start_chan)));
-    let response = start_port.recv();
+    let (response, body_port) = fetch_streaming(resource_task,
+                                                Request::new(url.clone(), context));
+    if response.is_network_error() {
+        return Err("network error".to_string());
+    }
+    let metadata = Metadata::from_response(&response, url);
 
     let mut buf = vec!();
     loop {
-        match response.progress_port.recv() {
-            Payload(data) => buf.push_all(data.as_slice()),
-            Done(Ok(()))  => return Ok((response.metadata, buf)),
-            Done(Err(e))  => return Err(e)
+        match body_port.recv_opt() {
+            Ok(Chunk(data)) => buf.push_all(data.as_slice()),
+            Ok(Finished) => return Ok((metadata, buf)),
+            Ok(Errored) | Err(()) => return Err("error receiving the body".to_string())
         }
     }
 }
 
+/// Convenience function for running the Fetch algorithm and waiting for its response.
+pub fn fetch(resource_task: &ResourceTask, request: Request) -> Response {
+    let (response_chan, response_port) = channel();
+    resource_task.send(Fetch(request, response_chan));
+    response_port.recv_opt().unwrap_or_else(|()| Response::network_error())
+}
+
+/// Runs the Fetch algorithm and waits for its response, whose body is then sent to the returned
+/// port as it arrives.
+pub fn fetch_streaming(resource_task: &ResourceTask, request: Request)
+                       -> (Response, Receiver<ResponseMsg>) {
+    let mut request = request;
+    let (body_chan, body_port) = channel();
+    request.body_chan = Some(body_chan);
+    (fetch(resource_task, request), body_port)
+}
+
 /// Handle to a resource task
 pub type ResourceTask = Sender<ControlMsg>;
 
-/// Create a ResourceTask
-pub fn new_resource_task(user_agent: Option<String>) -> ResourceTask {
+/// The network settings of a resource task.
+#[deriving(Clone)]
+pub struct NetworkConfig {
+    /// The `User-Agent` header sent with requests, instead of the default one.
+    pub user_agent: Option<String>,
+    /// How many redirects a load follows before it fails.
+    pub max_redirects: uint,
+    /// How long connections may wait to connect or for data, in milliseconds, or `None` to wait
+    /// for as long as the system allows.
+    pub timeout_ms: Option<u64>,
+    /// The proxies that HTTP loads go through.
+    pub proxy: ProxyConfig,
+    /// A directory that the HTTP cache writes responses to, as well as keeping them in memory.
+    pub http_cache_dir: Option<Path>,
+}
+
+impl NetworkConfig {
+    /// The default settings, which connect directly to every host.
+    pub fn new() -> NetworkConfig {
+        NetworkConfig {
+            user_agent: None,
+            max_redirects: 50,
+            timeout_ms: None,
+            proxy: ProxyConfig::direct(),
+            http_cache_dir: None,
+        }
+    }
+
+    /// The settings given on the command line, falling back to the proxy settings of the
+    /// environment.
+    pub fn from_opts(opts: &Opts) -> NetworkConfig {
+        NetworkConfig {
+            user_agent: opts.user_agent.clone(),
+            timeout_ms: opts.network_timeout.map(|seconds| seconds * 1000),
+            proxy: ProxyConfig::new(opts.proxy.clone(), opts.no_proxy.clone()),
+            http_cache_dir: opts.http_cache_dir.clone(),
+            .. NetworkConfig::new()
+        }
+    }
+}
+
+/// Create a ResourceTask. HTTP loads are reported to the devtools server, if there is one.
+pub fn new_resource_task(config: NetworkConfig,
+                         devtools_chan: Option<DevtoolsControlChan>) -> ResourceTask {
     let (setup_chan, setup_port) = channel();
     let sniffer_task = sniffer_task::new_sniffer_task();
+    let resource_task = setup_chan.clone();
     spawn_named("ResourceManager", proc() {
-        ResourceManager::new(setup_port, user_agent, sniffer_task).start();
+        ResourceManager::new(setup_port, resource_task, config, sniffer_task, devtools_chan)
+            .start();
     });
     setup_chan
 }
 
 struct ResourceManager {
     from_client: Receiver<ControlMsg>,
-    user_agent: Option<String>,
+    /// A handle to this resource task, which fetches use to load their requests.
+    resource_task: ResourceTask,
+    config: NetworkConfig,
     sniffer_task: SnifferTask,
+    devtools_chan: Option<DevtoolsControlChan>,
+    http_cache: SharedHttpCache,
+    cors_cache: CORSCacheSender,
 }
 
 impl ResourceManager {
-    fn new(from_client: Receiver<ControlMsg>, user_agent: Option<String>, sniffer_task: SnifferTask) -> ResourceManager {
+    fn new(from_client: Receiver<ControlMsg>, resource_task: ResourceTask, config: NetworkConfig,
+           sniffer_task: SnifferTask, devtools_chan: Option<DevtoolsControlChan>)
+           -> ResourceManager {
+        let http_cache = HttpCache::new_shared(config.http_cache_dir.clone());
+        let mut cors_cache_task = CORSCacheTask::new();
+        let cors_cache = cors_cache_task.get_sender();
+        spawn_named("CORSCache", proc() {
+            cors_cache_task.run();
+        });
         ResourceManager {
             from_client: from_client,
-            user_agent: user_agent,
+            resource_task: resource_task,
+            config: config,
             sniffer_task: sniffer_task,
+            devtools_chan: devtools_chan,
+            http_cache: http_cache,
+            cors_cache: cors_cache,
         }
     }
 }
@@ -212,16 +323,36 @@ impl ResourceManager {
                 Load(load_data) => {
                     self.load(load_data);
                 }
+                Fetch(request, response_chan) => {
+                    self.fetch(request, response_chan);
+                }
                 Exit => {
+                    self.cors_cache.send(ExitMsg);
                     break;
                 }
             }
         }
     }
 
+    fn fetch(&self, request: Request, response_chan: Sender<Response>) {
+        let mut request = request;
+        request.cache = Some(box self.cors_cache.clone());
+        let resource_task = self.resource_task.clone();
+        // The fetch sends its loads back to this task, so it can't run here.
+        spawn_named("Fetch", proc() {
+            let response = request.fetch(&resource_task);
+            if request.body_chan.is_some() {
+                let _ = response_chan.send_opt(response.clone());
+                request.send_body(&response);
+            } else {
+                let _ = response_chan.send_opt(response);
+            }
+        });
+    }
+
     fn load(&self, load_data: LoadData) {
         let mut load_data = load_data;
-        self.user_agent.as_ref().map(|ua| load_data.headers.set(UserAgent(ua.clone())));
+        self.config.user_agent.as_ref().map(|ua| load_data.headers.set(UserAgent(ua.clone())));
         let senders = ResponseSenders {
             immediate_consumer: self.sniffer_task.clone(),
             eventual_consumer: load_data.consumer.clone(),
@@ -229,7 +360,13 @@ impl ResourceManager {
 
         let loader = match load_data.url.scheme.as_slice() {
             "file" => file_loader::factory,
-            "http" | "https" => http_loader::factory,
+            "http" | "https" => {
+                debug!("resource_task: loading url: {:s}", load_data.url.serialize());
+                http_loader::factory(load_data, self.sniffer_task.clone(),
+                                     self.devtools_chan.clone(), self.http_cache.clone(),
+                                     self.config.clone());
+                return
+            }
             "data" => data_loader::factory,
             "about" => about_loader::factory,
             _ => {
@@ -245,28 +382,27 @@ impl ResourceManager {
     }
 }
 
-/// Load a URL asynchronously and iterate over chunks of bytes from the response.
-pub fn load_bytes_iter(resource_task: &ResourceTask, url: Url) -> (Metadata, ProgressMsgPortIterator) {
-    let (input_chan, input_port) = channel();
-    resource_task.send(Load(LoadData::new(url, input_chan)));
-
-    let response = input_port.recv();
-    let iter = ProgressMsgPortIterator { progress_port: response.progress_port };
-    (response.metadata, iter)
+/// Fetch a URL asynchronously and iterate over chunks of bytes from the response.
+pub fn load_bytes_iter(resource_task: &ResourceTask, url: Url, context: Context)
+                       -> (Metadata, BodyChunkIterator) {
+    let (response, body_port) = fetch_streaming(resource_task,
+                                                Request::new(url.clone(), context));
+    let metadata = Metadata::from_response(&response, url);
+    (metadata, BodyChunkIterator { body_port: body_port })
 }
 
-/// Iterator that reads chunks of bytes from a ProgressMsg port
-pub struct ProgressMsgPortIterator {
-    progress_port: Receiver<ProgressMsg>
+/// Iterator that reads the chunks of bytes of a fetched body
+pub struct BodyChunkIterator {
+    body_port: Receiver<ResponseMsg>
 }
 
-impl Iterator<Vec<u8>> for ProgressMsgPortIterator {
+impl Iterator<Vec<u8>> for BodyChunkIterator {
     fn next(&mut self) -> Option<Vec<u8>> {
-        match self.progress_port.recv() {
-            Payload(data) => Some(data),
-            Done(Ok(()))  => None,
-            Done(Err(e))  => {
-                error!("error receiving bytes: {}", e);
+        match self.body_port.recv_opt() {
+            Ok(Chunk(data)) => Some(data),
+            Ok(Finished) => None,
+            Ok(Errored) | Err(()) => {
+                error!("error receiving bytes");
                 None
             }
         }
@@ -275,13 +411,13 @@ impl Iterator<Vec<u8>> for ProgressMsgPortIterator {
 
 #[test]
 fn test_exit() {
-    let resource_task = new_resource_task(None);
+    let resource_task = new_resource_task(NetworkConfig::new(), None);
     resource_task.send(Exit);
 }
 
 #[test]
 fn test_bad_scheme() {
-    let resource_task = new_resource_task(None);
+    let resource_task = new_resource_task(NetworkConfig::new(), None);
     let (start_chan, start) = channel();
     let url = Url::parse("bogus://whatever").unwrap();
     resource_task.send(Load(LoadData::new(url, start_chan)));
@@ -292,3 +428,59 @@ fn test_bad_scheme() {
     }
     resource_task.send(Exit);
 }
+
+#[test]
+fn test_fetch_data_url() {
+    use fetch::request::Fetch as FetchContext;
+    use fetch::response::Done as BodyDone;
+
+    let resource_task = new_resource_task(NetworkConfig::new(), None);
+    let url = Url::parse("data:text/plain,hello").unwrap();
+    let response = fetch(&resource_task, Request::new(url, FetchContext));
+    assert!(!response.is_network_error());
+    match response.body {
+        BodyDone(ref body) => assert_eq!(body.as_slice(), b"hello"),
+        _ => panic!("the body wasn't loaded")
+    }
+    resource_task.send(Exit);
+}
+
+#[test]
+fn test_fetch_streams_body() {
+    use fetch::request::Fetch as FetchContext;
+    use fetch::response::{Chunk, Finished, Receiving};
+
+    let resource_task = new_resource_task(NetworkConfig::new(), None);
+    let url = Url::parse("data:text/plain,hello").unwrap();
+    let (body_chan, body_port) = channel();
+    let mut request = Request::new(url, FetchContext);
+    request.body_chan = Some(body_chan);
+    let response = fetch(&resource_task, request);
+    match response.body {
+        Receiving(ref body) => assert!(body.is_empty()),
+        _ => panic!("the body wasn't left to stream")
+    }
+    let mut body = vec!();
+    loop {
+        match body_port.recv() {
+            Chunk(data) => body.push_all(data.as_slice()),
+            Finished => break,
+            _ => panic!("the body failed to load")
+        }
+    }
+    assert_eq!(body.as_slice(), b"hello");
+    resource_task.send(Exit);
+}
+
+#[test]
+fn test_load_whole_resource_keeps_content_type() {
+    use fetch::request::Fetch as FetchContext;
+
+    let resource_task = new_resource_task(NetworkConfig::new(), None);
+    let url = Url::parse("data:text/plain;charset=utf-8,hello").unwrap();
+    let (metadata, body) = load_whole_resource(&resource_task, url, FetchContext).unwrap();
+    assert_eq!(metadata.content_type, Some(("text".to_string(), "plain".to_string())));
+    assert_eq!(metadata.charset, Some("utf-8".to_string()));
+    assert_eq!(body.as_slice(), b"hello");
+    resource_task.send(Exit);
+}
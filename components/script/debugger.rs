@@ -0,0 +1,278 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Script debugging for the devtools thread actor, built on SpiderMonkey's `Debugger` API.
+//!
+//! The `Debugger` object has to live in a different compartment from the code it debugs, so
+//! each script task creates a separate global for it and runs `resources/debugger.js` there.
+//! Devtools requests call the functions of that script, which answer in JSON.
+
+use dom::bindings::conversions;
+use dom::bindings::conversions::{FromJSValConvertible, ToJSValConvertible};
+use dom::bindings::utils::get_dictionary_property;
+use script_task::ScriptTask;
+
+use devtools_traits::{FrameInfo, PauseInfo, SourceInfo, SourceLocation};
+use devtools_traits::{StepIn, StepOut, StepOver};
+use servo_util::resource_files::read_resource_file;
+use servo_util::str::DOMString;
+
+use js::jsapi::{JSBool, JSClass, JSContext, JSObject, JS_CallFunctionValue, JS_DefineProperty};
+use js::jsapi::{JS_AddObjectRoot, JS_EvaluateUCScript, JS_GetFunctionObject, JS_NewFunction};
+use js::jsapi::{JS_InitStandardClasses, JS_NewGlobalObject, JS_WrapObject};
+use js::jsapi::{JS_PropertyStub, JS_StrictPropertyStub, JS_EnumerateStub, JS_ResolveStub};
+use js::jsapi::JS_ConvertStub;
+use js::jsval::{JSVal, ObjectValue, UInt32Value, UndefinedValue};
+use js::rust::with_compartment;
+use js::{JS_ARGV, JSCLASS_IS_GLOBAL, JSCLASS_GLOBAL_SLOT_COUNT};
+use js::{JSCLASS_RESERVED_SLOTS_MASK, JSCLASS_RESERVED_SLOTS_SHIFT};
+use libc;
+use serialize::{json, Decodable};
+use serialize::json::Decoder;
+use std::cell::{Cell, RefCell};
+use std::ptr;
+use url::Url;
+
+extern {
+    /// Defines the `Debugger` constructor on a global; it is not part of the standard classes.
+    fn JS_DefineDebuggerObject(cx: *mut JSContext, obj: *mut JSObject) -> JSBool;
+}
+
+/// The script task whose devtools port a pause waits on.
+local_data_key!(pub PausingScriptTask: *const ScriptTask)
+
+/// The main scripts of the workers started by this task, which the debugger cannot see but
+/// which are listed as sources.
+local_data_key!(WorkerScripts: RefCell<Vec<String>>)
+
+const DEBUGGER_GLOBAL_NAME: [u8, ..15] = [
+    b'D', b'e', b'b', b'u', b'g', b'g', b'e', b'r', b'G', b'l', b'o', b'b', b'a', b'l', 0,
+];
+
+static DEBUGGER_GLOBAL_CLASS: JSClass = JSClass {
+    name: &DEBUGGER_GLOBAL_NAME as *const u8 as *const libc::c_char,
+    flags: JSCLASS_IS_GLOBAL |
+           ((JSCLASS_GLOBAL_SLOT_COUNT & JSCLASS_RESERVED_SLOTS_MASK) <<
+            JSCLASS_RESERVED_SLOTS_SHIFT as uint),
+    addProperty: Some(JS_PropertyStub),
+    delProperty: Some(JS_PropertyStub),
+    getProperty: Some(JS_PropertyStub),
+    setProperty: Some(JS_StrictPropertyStub),
+    enumerate: Some(JS_EnumerateStub),
+    resolve: Some(JS_ResolveStub),
+    convert: Some(JS_ConvertStub),
+    finalize: None,
+    checkAccess: None,
+    call: None,
+    hasInstance: None,
+    construct: None,
+    trace: None,
+    reserved: [0 as *mut libc::c_void, ..40]
+};
+
+/// Records the main script of a new worker, so that it can be listed as a source.
+pub fn note_worker_script(url: &Url) {
+    match WorkerScripts.get() {
+        Some(scripts) => {
+            scripts.borrow_mut().push(url.serialize());
+            return;
+        }
+        None => (),
+    }
+    WorkerScripts.replace(Some(RefCell::new(vec!(url.serialize()))));
+}
+
+/// Called by `debugger.js` to pause: reports where to the devtools client, then serves its
+/// requests until it resumes.
+unsafe extern fn pause(cx: *mut JSContext, argc: libc::c_uint, vp: *mut JSVal) -> JSBool {
+    let info = if argc > 0 {
+        let info: DOMString = FromJSValConvertible::from_jsval(cx, *JS_ARGV(cx, vp),
+                                                               conversions::Default).unwrap();
+        decode::<PauseInfo>(info.as_slice())
+    } else {
+        None
+    };
+    let limit = match (info, PausingScriptTask.get()) {
+        (Some(info), Some(task)) => (**task).pause(info),
+        _ => None,
+    };
+    let limit = match limit {
+        Some(StepOver) => "stepOver",
+        Some(StepIn) => "stepIn",
+        Some(StepOut) => "stepOut",
+        None => "",
+    };
+    *vp = limit.to_string().to_jsval(cx);
+    1
+}
+
+fn decode<T: Decodable<Decoder, json::DecoderError>>(text: &str) -> Option<T> {
+    match json::decode(text) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            debug!("malformed reply from the debugger script: {}", error);
+            None
+        }
+    }
+}
+
+pub struct ScriptDebugger {
+    /// The global that `debugger.js` runs in, created when a thread is first attached.
+    global: Cell<*mut JSObject>,
+    /// Where pauses are reported while a devtools client is attached.
+    pause_chan: RefCell<Option<Sender<PauseInfo>>>,
+}
+
+impl ScriptDebugger {
+    pub fn new() -> ScriptDebugger {
+        ScriptDebugger {
+            global: Cell::new(ptr::null_mut()),
+            pause_chan: RefCell::new(None),
+        }
+    }
+
+    fn global(&self, cx: *mut JSContext) -> *mut JSObject {
+        if self.global.get().is_null() {
+            self.global.set(ScriptDebugger::create_global(cx));
+            unsafe {
+                JS_AddObjectRoot(cx, self.global.as_unsafe_cell().get());
+            }
+        }
+        self.global.get()
+    }
+
+    fn create_global(cx: *mut JSContext) -> *mut JSObject {
+        let source = read_resource_file(&["debugger.js"]).unwrap();
+        let source = String::from_utf8(source).unwrap();
+        let source: Vec<u16> = source.as_slice().utf16_units().collect();
+        let filename = "resource://servo/debugger.js".to_c_str();
+        let name = "pause".to_c_str();
+
+        unsafe {
+            let global = JS_NewGlobalObject(cx, &DEBUGGER_GLOBAL_CLASS, ptr::null_mut());
+            assert!(global.is_not_null());
+            with_compartment(cx, global, || {
+                assert!(JS_InitStandardClasses(cx, global) != 0);
+                assert!(JS_DefineDebuggerObject(cx, global) != 0);
+
+                let fun = JS_NewFunction(cx, Some(pause), 1, 0, global, name.as_ptr());
+                assert!(fun.is_not_null());
+                assert!(JS_DefineProperty(cx, global, name.as_ptr(),
+                                          ObjectValue(&*JS_GetFunctionObject(fun)),
+                                          None, None, 0) != 0);
+
+                let mut rval = UndefinedValue();
+                assert!(JS_EvaluateUCScript(cx, global, source.as_ptr(),
+                                            source.len() as libc::c_uint,
+                                            filename.as_ptr(), 1, &mut rval) != 0);
+            });
+            global
+        }
+    }
+
+    /// Calls a function of `debugger.js` with the arguments that `args` creates in the
+    /// debugger's compartment, returning its result if it is a string.
+    fn call(&self, cx: *mut JSContext, function: &str, args: |*mut JSContext| -> Vec<JSVal>)
+            -> Option<DOMString> {
+        let global = self.global(cx);
+        with_compartment(cx, global, || {
+            let callee = match get_dictionary_property(cx, global, function) {
+                Ok(Some(callee)) => callee,
+                _ => return None,
+            };
+            let mut args = args(cx);
+            let mut rval = UndefinedValue();
+            unsafe {
+                if JS_CallFunctionValue(cx, global, callee, args.len() as libc::c_uint,
+                                        args.as_mut_ptr(), &mut rval) == 0 {
+                    debug!("error calling {} in the debugger script", function);
+                    return None;
+                }
+            }
+            if !rval.is_string() {
+                return None;
+            }
+            FromJSValConvertible::from_jsval(cx, rval, conversions::Default).ok()
+        })
+    }
+
+    /// Starts debugging the scripts of `debuggee`, reporting pauses through `pause_chan`.
+    pub fn attach(&self, cx: *mut JSContext, debuggee: *mut JSObject,
+                  pause_chan: Sender<PauseInfo>) {
+        *self.pause_chan.borrow_mut() = Some(pause_chan);
+        self.call(cx, "attach", |cx| {
+            let mut debuggee = debuggee;
+            unsafe {
+                assert!(JS_WrapObject(cx, &mut debuggee) != 0);
+                vec!(ObjectValue(&*debuggee))
+            }
+        });
+    }
+
+    pub fn detach(&self, cx: *mut JSContext, debuggee: *mut JSObject) {
+        *self.pause_chan.borrow_mut() = None;
+        self.call(cx, "detach", |cx| {
+            let mut debuggee = debuggee;
+            unsafe {
+                assert!(JS_WrapObject(cx, &mut debuggee) != 0);
+                vec!(ObjectValue(&*debuggee))
+            }
+        });
+    }
+
+    pub fn interrupt(&self, cx: *mut JSContext) {
+        self.call(cx, "interrupt", |_| vec!());
+    }
+
+    pub fn set_breakpoint(&self, cx: *mut JSContext, url: String, line: uint)
+                          -> Option<SourceLocation> {
+        let location = self.call(cx, "setBreakpoint", |cx| {
+            vec!(url.to_jsval(cx), UInt32Value(line as u32))
+        });
+        location.and_then(|location| decode::<Option<SourceLocation>>(location.as_slice()))
+                .and_then(|location| location)
+    }
+
+    pub fn remove_breakpoint(&self, cx: *mut JSContext, url: String, line: uint) {
+        self.call(cx, "removeBreakpoint", |cx| {
+            vec!(url.to_jsval(cx), UInt32Value(line as u32))
+        });
+    }
+
+    pub fn frames(&self, cx: *mut JSContext, start: uint, count: uint) -> Vec<FrameInfo> {
+        let frames = self.call(cx, "frames", |_| {
+            vec!(UInt32Value(start as u32), UInt32Value(count as u32))
+        });
+        frames.and_then(|frames| decode(frames.as_slice())).unwrap_or(vec!())
+    }
+
+    pub fn sources(&self, cx: *mut JSContext) -> Vec<SourceInfo> {
+        let sources = self.call(cx, "sources", |_| vec!());
+        let mut sources: Vec<SourceInfo> =
+            sources.and_then(|sources| decode(sources.as_slice())).unwrap_or(vec!());
+        match WorkerScripts.get() {
+            Some(scripts) => {
+                sources.extend(scripts.borrow().iter().map(|url| SourceInfo {
+                    url: url.clone(),
+                    worker: true,
+                }));
+            }
+            None => (),
+        }
+        sources
+    }
+
+    pub fn source_text(&self, cx: *mut JSContext, url: String) -> Option<String> {
+        self.call(cx, "sourceText", |cx| vec!(url.to_jsval(cx)))
+    }
+
+    /// Reports a pause to the attached devtools client. Returns false if no client is
+    /// attached, in which case there is nobody to resume the pause.
+    pub fn report_pause(&self, info: PauseInfo) -> bool {
+        match *self.pause_chan.borrow() {
+            Some(ref chan) => chan.send_opt(info).is_ok(),
+            None => false,
+        }
+    }
+}
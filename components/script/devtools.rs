@@ -3,22 +3,28 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use devtools_traits;
-use devtools_traits::{EvaluateJSReply, NodeInfo, Modification};
+use devtools_traits::{EvaluateJSReply, NodeInfo, Modification, PauseInfo};
+use devtools_traits::{AppliedRuleInfo, PropertyModification, StylesheetInfo};
+use debugger::ScriptDebugger;
 use dom::bindings::conversions;
 use dom::bindings::conversions::FromJSValConvertible;
 use dom::bindings::js::{JSRef, Temporary, OptionalRootable};
+use dom::bindings::utils::Reflectable;
 use dom::bindings::codegen::InheritTypes::{NodeCast, ElementCast};
 use dom::bindings::codegen::Bindings::DocumentBinding::DocumentMethods;
 use dom::bindings::codegen::Bindings::DOMRectBinding::{DOMRectMethods};
 use dom::bindings::codegen::Bindings::ElementBinding::{ElementMethods};
 use dom::node::{Node, NodeHelpers};
 use dom::window::{WindowHelpers};
-use dom::element::Element;
+use dom::element::{AttributeHandlers, Element};
 use dom::document::DocumentHelpers;
+use layout_interface::{LayoutChan, MatchedRulesResponse, NoQuery, ReplaceStylesheetMsg};
+use layout_interface::{StylesheetSource, StylesheetsResponse};
 use page::Page;
 use servo_msg::constellation_msg::PipelineId;
 use script_task::get_page;
 use std::rc::Rc;
+use style::{AuthorOrigin, Stylesheet, modify_declarations_text};
 
 
 pub fn handle_evaluate_js(page: &Rc<Page>, pipeline: PipelineId, eval: String, reply: Sender<EvaluateJSReply>){
@@ -104,3 +110,161 @@ pub fn handle_modify_attribute(page: &Rc<Page>, pipeline: PipelineId, node_id: S
         }
     }
 }
+
+pub fn handle_attach_thread(page: &Rc<Page>, debugger: &ScriptDebugger, pipeline: PipelineId,
+                            pause_chan: Sender<PauseInfo>) {
+    let page = get_page(&*page, pipeline);
+    let frame = page.frame();
+    let window = frame.as_ref().unwrap().window.root();
+    debugger.attach(window.get_cx(), window.reflector().get_jsobject(), pause_chan);
+}
+
+pub fn handle_detach_thread(page: &Rc<Page>, debugger: &ScriptDebugger, pipeline: PipelineId) {
+    let page = get_page(&*page, pipeline);
+    let frame = page.frame();
+    let window = frame.as_ref().unwrap().window.root();
+    debugger.detach(window.get_cx(), window.reflector().get_jsobject());
+}
+
+fn stylesheet_sources(page: &Page) -> Vec<StylesheetSource> {
+    let StylesheetsResponse(stylesheets) = page.layout().stylesheets();
+    stylesheets
+}
+
+fn find_stylesheet(page: &Page, index: uint) -> Option<StylesheetSource> {
+    stylesheet_sources(page).into_iter().find(|stylesheet| stylesheet.index == index)
+}
+
+pub fn handle_get_stylesheets(page: &Rc<Page>, pipeline: PipelineId,
+                              reply: Sender<Vec<StylesheetInfo>>) {
+    let page = get_page(&*page, pipeline);
+    let page_url = page.get_url();
+    let stylesheets = stylesheet_sources(&*page).into_iter().map(|stylesheet| {
+        // Inline stylesheets take the URL of their document.
+        let href = if stylesheet.url == page_url {
+            None
+        } else {
+            Some(stylesheet.url.serialize())
+        };
+        StylesheetInfo {
+            index: stylesheet.index,
+            href: href,
+            text: stylesheet.source.unwrap_or("".to_string()),
+            rule_count: stylesheet.rule_count,
+        }
+    }).collect();
+    reply.send(stylesheets);
+}
+
+pub fn handle_get_applied_rules(page: &Rc<Page>, pipeline: PipelineId, node_id: String,
+                                reply: Sender<Vec<AppliedRuleInfo>>) {
+    let node = find_node_by_unique_id(&*page, pipeline, node_id).root();
+    if !node.is_element() {
+        reply.send(vec!());
+        return;
+    }
+    let page = get_page(&*page, pipeline);
+    // Rules are only matched against stylesheets that layout has applied, so make sure that a
+    // reflow picks up any that changed since the last one.
+    page.damage();
+    page.flush_layout(NoQuery);
+    let MatchedRulesResponse(rules) = page.layout().matched_rules(node.to_trusted_node_address());
+    let rules = rules.into_iter().map(|rule| {
+        AppliedRuleInfo {
+            stylesheet: rule.stylesheet,
+            rule: rule.rule,
+            selector: rule.selector_text,
+            css_text: rule.declarations_text,
+            line: rule.line,
+            column: rule.column,
+            specificity: rule.specificity,
+        }
+    }).collect();
+    reply.send(rules);
+}
+
+fn replace_stylesheet(page: &Page, index: uint, text: &str) {
+    let stylesheet = match find_stylesheet(page, index) {
+        Some(stylesheet) => stylesheet,
+        None => return,
+    };
+    let sheet = Stylesheet::from_str(text, stylesheet.url, AuthorOrigin, true);
+    let LayoutChan(ref chan) = page.layout_chan;
+    chan.send(ReplaceStylesheetMsg(index, sheet));
+    page.damage();
+    page.flush_layout(NoQuery);
+}
+
+pub fn handle_set_stylesheet_text(page: &Rc<Page>, pipeline: PipelineId, index: uint,
+                                  text: String) {
+    let page = get_page(&*page, pipeline);
+    replace_stylesheet(&*page, index, text.as_slice());
+}
+
+pub fn handle_set_rule_text(page: &Rc<Page>, pipeline: PipelineId, index: uint, rule: uint,
+                            declarations: String) {
+    let page = get_page(&*page, pipeline);
+    let stylesheet = match find_stylesheet(&*page, index) {
+        Some(stylesheet) => stylesheet,
+        None => return,
+    };
+    let source = match stylesheet.source {
+        Some(source) => source,
+        None => return,
+    };
+    let sheet = Stylesheet::from_str(source.as_slice(), stylesheet.url, AuthorOrigin, true);
+    match sheet.with_rule_declarations(rule, declarations.as_slice()) {
+        Some(text) => replace_stylesheet(&*page, index, text.as_slice()),
+        None => (),
+    }
+}
+
+pub fn handle_modify_rule_properties(page: &Rc<Page>, pipeline: PipelineId, index: uint,
+                                     rule: uint, modifications: Vec<PropertyModification>,
+                                     reply: Sender<Option<String>>) {
+    let page = get_page(&*page, pipeline);
+    let stylesheet = match find_stylesheet(&*page, index) {
+        Some(stylesheet) => stylesheet,
+        None => return reply.send(None),
+    };
+    let source = match stylesheet.source {
+        Some(source) => source,
+        None => return reply.send(None),
+    };
+    let sheet = Stylesheet::from_str(source.as_slice(), stylesheet.url, AuthorOrigin, true);
+    let declarations = match sheet.rule_text(rule) {
+        Some((_, declarations)) => declarations,
+        None => return reply.send(None),
+    };
+    let declarations = modify_properties(declarations, modifications.as_slice());
+    match sheet.with_rule_declarations(rule, declarations.as_slice()) {
+        Some(text) => replace_stylesheet(&*page, index, text.as_slice()),
+        None => (),
+    }
+    reply.send(Some(declarations));
+}
+
+pub fn handle_modify_style_attribute_properties(page: &Rc<Page>, pipeline: PipelineId,
+                                                node_id: String,
+                                                modifications: Vec<PropertyModification>,
+                                                reply: Sender<Option<String>>) {
+    let node = find_node_by_unique_id(&*page, pipeline, node_id).root();
+    let elem: JSRef<Element> = match ElementCast::to_ref(*node) {
+        Some(elem) => elem,
+        None => return reply.send(None),
+    };
+    let declarations = modify_properties(elem.get_string_attribute(&atom!("style")),
+                                         modifications.as_slice());
+    let _ = elem.SetAttribute("style".to_string(), declarations.clone());
+    reply.send(Some(declarations));
+}
+
+/// Applies the changes that the inspector made to some declarations.
+fn modify_properties(declarations: String, modifications: &[PropertyModification]) -> String {
+    modifications.iter().fold(declarations, |declarations, modification| {
+        let value = modification.value.as_ref().map(|&(ref value, important)| {
+            (value.as_slice(), important)
+        });
+        modify_declarations_text(declarations.as_slice(), modification.name.as_slice(), value)
+    })
+}
@@ -579,7 +579,24 @@ def getJSToNativeConversionTemplate(type, descriptorProvider, failureCode=None,
         raise TypeError("Can't handle array arguments yet")
 
     if type.isSequence():
-        raise TypeError("Can't handle sequence arguments yet")
+        # The conversion throws a TypeError if the value isn't a sequence of the element type.
+        sequenceType = type.inner if type.nullable() else type
+        elementType = sequenceType.inner
+        if elementType.isObject():
+            elementDeclType = "*mut JSObject"
+        else:
+            raise TypeError("Can't handle sequences of %s yet" % elementType)
+
+        declType = CGGeneric("Vec<%s>" % elementDeclType)
+        if type.nullable():
+            declType = CGWrapper(declType, pre="Option<", post=" >")
+
+        templateBody = ("match FromJSValConvertible::from_jsval(cx, ${val}, ()) {\n"
+                        "    Ok(value) => value,\n"
+                        "    Err(()) => { %s },\n"
+                        "}" % exceptionCode)
+
+        return handleOptional(templateBody, declType, handleDefaultNull("None"))
 
     if type.isUnion():
         declType = CGGeneric(union_native_type(type))
@@ -6,6 +6,7 @@
 
 //! Conversions of Rust values to and from `JSVal`.
 
+use dom::bindings::error::throw_type_error;
 use dom::bindings::js::{JS, JSRef, Root};
 use dom::bindings::str::ByteString;
 use dom::bindings::utils::{Reflectable, Reflector};
@@ -20,7 +21,8 @@ use js::jsapi::{JS_ValueToECMAUint32, JS_ValueToECMAInt32};
 use js::jsapi::{JS_ValueToUint16, JS_ValueToNumber, JS_ValueToBoolean};
 use js::jsapi::{JS_ValueToString, JS_GetStringCharsAndLength};
 use js::jsapi::{JS_NewUCStringCopyN, JS_NewStringCopyN};
-use js::jsapi::{JS_WrapValue};
+use js::jsapi::{JS_WrapValue, JS_IsArrayObject, JS_GetArrayLength, JS_GetElement};
+use js::jsapi::JS_IsExceptionPending;
 use js::jsval::JSVal;
 use js::jsval::{UndefinedValue, NullValue, BooleanValue, Int32Value, UInt32Value};
 use js::jsval::{StringValue, ObjectValue, ObjectOrNullValue};
@@ -405,3 +407,47 @@ impl ToJSValConvertible for *mut JSObject {
         wrapped
     }
 }
+
+impl FromJSValConvertible<()> for *mut JSObject {
+    fn from_jsval(_cx: *mut JSContext, value: JSVal, _option: ()) -> Result<*mut JSObject, ()> {
+        if !value.is_object() {
+            return Err(());
+        }
+        Ok(value.to_object())
+    }
+}
+
+/// Converts an array to a WebIDL sequence, element by element. Throws a `TypeError` if the value
+/// isn't an array, or if one of its elements can't be converted.
+impl<T: FromJSValConvertible<()>> FromJSValConvertible<()> for Vec<T> {
+    fn from_jsval(cx: *mut JSContext, value: JSVal, _option: ()) -> Result<Vec<T>, ()> {
+        if !value.is_object() || unsafe { JS_IsArrayObject(cx, value.to_object()) } == 0 {
+            throw_type_error(cx, "value is not a sequence");
+            return Err(());
+        }
+        let object = value.to_object();
+
+        let mut length = 0;
+        if unsafe { JS_GetArrayLength(cx, object, &mut length) } == 0 {
+            return Err(());
+        }
+
+        let mut sequence = vec!();
+        for index in range(0, length) {
+            let mut element = UndefinedValue();
+            if unsafe { JS_GetElement(cx, object, index, &mut element) } == 0 {
+                return Err(());
+            }
+            match FromJSValConvertible::from_jsval(cx, element, ()) {
+                Ok(element) => sequence.push(element),
+                Err(()) => {
+                    if unsafe { JS_IsExceptionPending(cx) } == 0 {
+                        throw_type_error(cx, "sequence element has the wrong type");
+                    }
+                    return Err(());
+                }
+            }
+        }
+        Ok(sequence)
+    }
+}
@@ -28,6 +28,7 @@ pub enum Error {
     InvalidCharacter,
     NotSupported,
     InvalidState,
+    NoModificationAllowed,
     Syntax,
     NamespaceError,
     InvalidAccess,
@@ -35,7 +36,9 @@ pub enum Error {
     Network,
     Abort,
     Timeout,
-    DataClone
+    DataClone,
+    /// A `TypeError` with the given message, rather than a `DOMException`.
+    Type(&'static str)
 }
 
 /// The return type for IDL operations that can throw DOM exceptions.
@@ -49,6 +52,10 @@ pub type ErrorResult = Fallible<()>;
 pub fn throw_dom_exception(cx: *mut JSContext, global: &GlobalRef,
                            result: Error) {
     assert!(unsafe { JS_IsExceptionPending(cx) } == 0);
+    match result {
+        Type(message) => return throw_type_error(cx, message),
+        _ => ()
+    }
     let exception = DOMException::new_from_error(*global, result).root();
     let thrown = exception.to_jsval(cx);
     unsafe {
@@ -14,6 +14,9 @@ use dom::workerglobalscope::WorkerGlobalScope;
 use dom::window;
 use script_task::ScriptChan;
 
+use devtools_traits::DevtoolsControlChan;
+
+use servo_msg::constellation_msg::PipelineId;
 use servo_net::resource_task::ResourceTask;
 
 use js::{JSCLASS_IS_GLOBAL, JSCLASS_IS_DOMJSCLASS};
@@ -79,6 +82,23 @@ impl<'a> GlobalRef<'a> {
         }
     }
 
+    /// The channel to the devtools server, if it is running.
+    pub fn devtools_chan(&self) -> Option<DevtoolsControlChan> {
+        match *self {
+            Window(ref window) => window.page().devtools_chan.clone(),
+            Worker(ref worker) => worker.devtools_chan().clone(),
+        }
+    }
+
+    /// The pipeline that this global belongs to. Workers belong to the pipeline of the
+    /// window that started them.
+    pub fn pipeline(&self) -> PipelineId {
+        match *self {
+            Window(ref window) => window.page().id,
+            Worker(ref worker) => worker.pipeline(),
+        }
+    }
+
     /// `ScriptChan` used to send messages to the event loop of this global's
     /// thread.
     pub fn script_chan<'b>(&'b self) -> &'b ScriptChan {
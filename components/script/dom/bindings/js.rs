@@ -48,6 +48,9 @@
 use dom::bindings::utils::{Reflector, Reflectable};
 use dom::node::Node;
 use dom::xmlhttprequest::{XMLHttpRequest, TrustedXHRAddress};
+use dom::messageport::{MessagePort, TrustedMessagePortAddress};
+use dom::promise::{Promise, TrustedPromiseAddress};
+use dom::sharedworker::{SharedWorker, TrustedSharedWorkerAddress};
 use dom::worker::{Worker, TrustedWorkerAddress};
 use js::jsapi::JSObject;
 use layout_interface::TrustedNodeAddress;
@@ -158,6 +161,34 @@ impl JS<Worker> {
     }
 }
 
+impl JS<MessagePort> {
+    pub unsafe fn from_trusted_port_address(inner: TrustedMessagePortAddress) -> JS<MessagePort> {
+        let TrustedMessagePortAddress(addr) = inner;
+        JS {
+            ptr: addr as *const MessagePort
+        }
+    }
+}
+
+impl JS<SharedWorker> {
+    pub unsafe fn from_trusted_shared_worker_address(inner: TrustedSharedWorkerAddress)
+                                                     -> JS<SharedWorker> {
+        let TrustedSharedWorkerAddress(addr) = inner;
+        JS {
+            ptr: addr as *const SharedWorker
+        }
+    }
+}
+
+impl JS<Promise> {
+    pub unsafe fn from_trusted_promise_address(inner: TrustedPromiseAddress) -> JS<Promise> {
+        let TrustedPromiseAddress(addr) = inner;
+        JS {
+            ptr: addr as *const Promise
+        }
+    }
+}
+
 impl<T: Reflectable> JS<T> {
     /// Create a new JS-owned value wrapped from a raw Rust pointer.
     pub unsafe fn from_raw(raw: *const T) -> JS<T> {
@@ -0,0 +1,328 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Structured cloning of the messages that are posted to windows, workers and message ports.
+//!
+//! SpiderMonkey serializes the JavaScript values itself, and calls back into Servo for the DOM
+//! objects: blobs, files and images are copied, and the message ports in the transfer list are
+//! written as references to the ports that are transferred along with the message.
+
+use dom::bindings::codegen::Bindings::MessagePortBinding::MessagePortMethods;
+use dom::bindings::conversions::FromJSValConvertible;
+use dom::bindings::error::{Fallible, DataClone, Type};
+use dom::bindings::global::GlobalRef;
+use dom::bindings::js::{JS, JSRef, Temporary};
+use dom::bindings::utils::Reflectable;
+use dom::blob::Blob;
+use dom::file::File;
+use dom::imagedata::{ImageData, ImageDataHelpers};
+use dom::messageport::{MessagePort, MessagePortHelpers, PortEndpoint};
+
+use servo_util::str::DOMString;
+
+use js::glue::JS_STRUCTURED_CLONE_VERSION;
+use js::jsapi::{JSContext, JSObject, JSBool, JS_ReadStructuredClone, JS_WriteStructuredClone};
+use js::jsapi::{JS_ClearPendingException, JS_AddObjectRoot, JS_RemoveObjectRoot};
+use js::jsapi::{JSStructuredCloneCallbacks, JSStructuredCloneReader, JSStructuredCloneWriter};
+use js::jsapi::{JS_ReadUint32Pair, JS_WriteUint32Pair, JS_ReadBytes, JS_WriteBytes};
+use js::jsapi::{JS_IsArrayBufferObject, JS_StealArrayBufferContents, JS_free};
+use js::jsval::{JSVal, UndefinedValue, ObjectValue};
+
+use libc::{c_void, size_t};
+use std::ptr;
+
+/// `JS_SCTAG_USER_MIN`: the tags below this one are SpiderMonkey's own.
+static SCTAG_USER_MIN: u32 = 0xFFFF8000;
+static SCTAG_BLOB: u32 = SCTAG_USER_MIN + 1;
+static SCTAG_FILE: u32 = SCTAG_USER_MIN + 2;
+static SCTAG_IMAGEDATA: u32 = SCTAG_USER_MIN + 3;
+static SCTAG_MESSAGEPORT: u32 = SCTAG_USER_MIN + 4;
+
+static CALLBACKS: JSStructuredCloneCallbacks = JSStructuredCloneCallbacks {
+    read: Some(read_dom_object),
+    write: Some(write_dom_object),
+    reportError: None,
+};
+
+/// What the callbacks need to know while a message is written.
+struct WriteState {
+    /// The ports in the transfer list, which the message can refer to by their index.
+    ports: Vec<JS<MessagePort>>,
+}
+
+/// What the callbacks need to know while a message is read.
+struct ReadState<'a> {
+    global: GlobalRef<'a>,
+    /// The ports that were transferred with the message, in the order of the transfer list.
+    ports: Vec<JS<MessagePort>>,
+}
+
+/// A message serialized by SpiderMonkey, along with the ports that were transferred with it.
+pub struct StructuredCloneData {
+    data: *mut u64,
+    nbytes: size_t,
+    ports: Vec<PortEndpoint>,
+}
+
+impl StructuredCloneData {
+    /// Serializes `message`, and detaches the objects in `transfer`, which must be
+    /// `MessagePort`s and `ArrayBuffer`s.
+    pub fn write(cx: *mut JSContext, message: JSVal, transfer: Option<Vec<*mut JSObject>>)
+                 -> Fallible<StructuredCloneData> {
+        let transferred = try!(Transferred::from_list(cx, transfer));
+        StructuredCloneData::write_transferring(cx, message, transferred)
+    }
+
+    /// Like `write`, for a message posted through `source`, which can't transfer itself.
+    pub fn write_for_port(cx: *mut JSContext, message: JSVal,
+                          transfer: Option<Vec<*mut JSObject>>, source: JSRef<MessagePort>)
+                          -> Fallible<StructuredCloneData> {
+        let transferred = try!(Transferred::from_list(cx, transfer));
+        if transferred.ports.contains(&JS::from_rooted(source)) {
+            return Err(DataClone);
+        }
+        StructuredCloneData::write_transferring(cx, message, transferred)
+    }
+
+    fn write_transferring(cx: *mut JSContext, message: JSVal, transferred: Transferred)
+                          -> Fallible<StructuredCloneData> {
+        let Transferred { ports, buffers } = transferred;
+        let mut state = WriteState { ports: ports };
+
+        let mut data = ptr::null_mut();
+        let mut nbytes = 0;
+        let result = unsafe {
+            JS_WriteStructuredClone(cx, message, &mut data, &mut nbytes,
+                                    &CALLBACKS, &mut state as *mut WriteState as *mut c_void)
+        };
+        if result == 0 {
+            unsafe { JS_ClearPendingException(cx); }
+            return Err(DataClone);
+        }
+
+        // The receiver gets a copy of the contents of the transferred buffers, which can't be
+        // used here anymore.
+        for buffer in buffers.into_iter() {
+            unsafe {
+                let mut contents = ptr::null_mut();
+                let mut bytes = ptr::null_mut();
+                if JS_StealArrayBufferContents(cx, buffer, &mut contents, &mut bytes) != 0 {
+                    JS_free(cx, contents);
+                } else {
+                    JS_ClearPendingException(cx);
+                }
+            }
+        }
+
+        Ok(StructuredCloneData {
+            data: data,
+            nbytes: nbytes,
+            ports: state.ports.iter().map(|port| port.root().detach()).collect(),
+        })
+    }
+
+    /// Deserializes the message in `global`, and creates the transferred ports there. Returns
+    /// `Err` if the message can't be deserialized, in which case the ports are closed.
+    pub fn read(self, global: GlobalRef) -> Result<(JSVal, Vec<Temporary<MessagePort>>), ()> {
+        let cx = global.get_cx();
+
+        // Nothing else refers to the new ports until the message is read, and creating them or
+        // reading the message may collect garbage, so they are rooted until then. The roots are
+        // the slots of `reflectors`, which isn't resized while they exist.
+        let mut reflectors: Vec<*mut JSObject> =
+            Vec::from_elem(self.ports.len(), ptr::null_mut());
+        let mut ports: Vec<JS<MessagePort>> = vec!();
+        for (endpoint, reflector) in self.ports.into_iter().zip(reflectors.iter_mut()) {
+            let port = MessagePort::new(&global, endpoint).root();
+            *reflector = port.reflector().get_jsobject();
+            unsafe { JS_AddObjectRoot(cx, reflector); }
+            ports.push(JS::from_rooted(*port));
+        }
+
+        let mut state = ReadState { global: global, ports: ports };
+        let mut message = UndefinedValue();
+        let result = unsafe {
+            JS_ReadStructuredClone(cx, self.data as *const u64, self.nbytes,
+                                   JS_STRUCTURED_CLONE_VERSION, &mut message,
+                                   &CALLBACKS, &mut state as *mut ReadState as *mut c_void)
+        };
+        if result == 0 {
+            unsafe { JS_ClearPendingException(cx); }
+            for port in state.ports.iter() {
+                port.root().Close();
+            }
+        }
+
+        for reflector in reflectors.iter_mut() {
+            unsafe { JS_RemoveObjectRoot(cx, reflector); }
+        }
+        if result == 0 {
+            return Err(());
+        }
+        Ok((message, state.ports.iter().map(|port| Temporary::new(*port)).collect()))
+    }
+
+    /// Drops a message that won't be delivered, and closes the ports that were transferred with
+    /// it.
+    pub fn discard(self) {
+        for endpoint in self.ports.iter() {
+            endpoint.close();
+        }
+    }
+}
+
+/// The objects in a transfer list. Each object can only be transferred once.
+struct Transferred {
+    ports: Vec<JS<MessagePort>>,
+    buffers: Vec<*mut JSObject>,
+}
+
+impl Transferred {
+    fn from_list(cx: *mut JSContext, transfer: Option<Vec<*mut JSObject>>)
+                 -> Fallible<Transferred> {
+        let mut transferred = Transferred { ports: vec!(), buffers: vec!() };
+
+        for &object in transfer.unwrap_or(vec!()).iter() {
+            if unsafe { JS_IsArrayBufferObject(object) } != 0 {
+                if transferred.buffers.contains(&object) {
+                    return Err(DataClone);
+                }
+                transferred.buffers.push(object);
+                continue;
+            }
+
+            let element = ObjectValue(unsafe { &*object });
+            let port: JS<MessagePort> = match FromJSValConvertible::from_jsval(cx, element, ()) {
+                Ok(port) => port,
+                Err(()) => return Err(Type("the transfer list contains an object that isn't \
+                                            transferable")),
+            };
+            if transferred.ports.contains(&port) || port.root().is_detached() {
+                return Err(DataClone);
+            }
+            transferred.ports.push(port);
+        }
+        Ok(transferred)
+    }
+}
+
+fn write_bytes(writer: *mut JSStructuredCloneWriter, bytes: &[u8]) -> bool {
+    unsafe {
+        JS_WriteBytes(writer, bytes.as_ptr() as *const c_void, bytes.len() as size_t) != 0
+    }
+}
+
+fn read_bytes(reader: *mut JSStructuredCloneReader, len: uint) -> Option<Vec<u8>> {
+    let mut bytes = Vec::from_elem(len, 0u8);
+    unsafe {
+        if JS_ReadBytes(reader, bytes.as_mut_ptr() as *mut c_void, len as size_t) == 0 {
+            return None;
+        }
+    }
+    Some(bytes)
+}
+
+/// Writes the DOM objects that SpiderMonkey can't serialize itself.
+extern fn write_dom_object(cx: *mut JSContext, writer: *mut JSStructuredCloneWriter,
+                           obj: *mut JSObject, closure: *mut c_void) -> JSBool {
+    let state = unsafe { &*(closure as *const WriteState) };
+    let value = ObjectValue(unsafe { &*obj });
+    let write_pair = |tag: u32, data: u32| unsafe {
+        JS_WriteUint32Pair(writer, tag, data) != 0
+    };
+
+    let port: Result<JS<MessagePort>, ()> = FromJSValConvertible::from_jsval(cx, value, ());
+    match port {
+        // Ports are only cloned by being transferred.
+        Ok(port) => return match state.ports.iter().position(|p| *p == port) {
+            Some(index) => write_pair(SCTAG_MESSAGEPORT, index as u32) as JSBool,
+            None => 0,
+        },
+        Err(()) => (),
+    }
+
+    let file: Result<JS<File>, ()> = FromJSValConvertible::from_jsval(cx, value, ());
+    match file {
+        Ok(file) => {
+            let file = file.root();
+            let name = file.name().as_bytes();
+            return (write_pair(SCTAG_FILE, name.len() as u32) && write_bytes(writer, name))
+                   as JSBool;
+        }
+        Err(()) => (),
+    }
+
+    let blob: Result<JS<Blob>, ()> = FromJSValConvertible::from_jsval(cx, value, ());
+    match blob {
+        // TODO: copy the contents of the blob, once it has any.
+        Ok(_) => return write_pair(SCTAG_BLOB, 0) as JSBool,
+        Err(()) => (),
+    }
+
+    let image: Result<JS<ImageData>, ()> = FromJSValConvertible::from_jsval(cx, value, ());
+    match image {
+        Ok(image) => {
+            let image = image.root();
+            let (width, height) = image.get_size();
+            return (write_pair(SCTAG_IMAGEDATA, 0) && write_pair(width, height) &&
+                    write_bytes(writer, image.get_pixels(cx).as_slice())) as JSBool;
+        }
+        Err(()) => (),
+    }
+
+    0
+}
+
+/// Creates the DOM objects written by `write_dom_object` in the global that reads the message.
+extern fn read_dom_object(_cx: *mut JSContext, reader: *mut JSStructuredCloneReader, tag: u32,
+                          data: u32, closure: *mut c_void) -> *mut JSObject {
+    let state = unsafe { &*(closure as *const ReadState) };
+    let global = state.global;
+
+    if tag == SCTAG_MESSAGEPORT {
+        return match state.ports.as_slice().get(data as uint) {
+            Some(port) => port.root().reflector().get_jsobject(),
+            None => ptr::null_mut(),
+        };
+    }
+
+    if tag == SCTAG_BLOB {
+        return Blob::new(global).root().reflector().get_jsobject();
+    }
+
+    if tag == SCTAG_FILE {
+        let name = match read_bytes(reader, data as uint) {
+            Some(name) => name,
+            None => return ptr::null_mut(),
+        };
+        let name: DOMString = match String::from_utf8(name) {
+            Ok(name) => name,
+            Err(_) => return ptr::null_mut(),
+        };
+        let blob = Blob::new(global).root();
+        return File::new(global, *blob, name).root().reflector().get_jsobject();
+    }
+
+    if tag == SCTAG_IMAGEDATA {
+        let (mut width, mut height) = (0, 0);
+        if unsafe { JS_ReadUint32Pair(reader, &mut width, &mut height) } == 0 {
+            return ptr::null_mut();
+        }
+        let length = match (width as uint).checked_mul(&(height as uint))
+                                          .and_then(|pixels| pixels.checked_mul(&4)) {
+            Some(length) => length,
+            None => return ptr::null_mut(),
+        };
+        let pixels = match read_bytes(reader, length) {
+            Some(pixels) => pixels,
+            None => return ptr::null_mut(),
+        };
+        return match ImageData::new(global, width, height, Some(pixels.as_slice())) {
+            Some(image) => image.root().reflector().get_jsobject(),
+            None => ptr::null_mut(),
+        };
+    }
+
+    ptr::null_mut()
+}
@@ -2,59 +2,307 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use dom::bindings::cell::DOMRefCell;
 use dom::bindings::codegen::Bindings::ConsoleBinding;
 use dom::bindings::codegen::Bindings::ConsoleBinding::ConsoleMethods;
-use dom::bindings::global::GlobalRef;
+use dom::bindings::conversions;
+use dom::bindings::conversions::{FromJSValConvertible, jsid_to_str};
+use dom::bindings::global::{GlobalRef, GlobalField, Worker};
 use dom::bindings::js::{JSRef, Temporary};
 use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
 use servo_util::str::DOMString;
 
+use devtools_traits::{CallSite, ConsoleAPIMsg, ConsoleArgument, ConsoleCounter, ConsoleMessage};
+use devtools_traits::{DebuggerValue, SourceLocation, TimerEnded, TimerStarted};
+use devtools_traits::is_console_api_monitored;
+
+use js::glue::{RUST_JSID_IS_INT, RUST_JSID_TO_INT};
+use js::jsapi::{JSContext, JSObject, JS_Enumerate, JS_IdArrayLength, JS_IdArrayGet};
+use js::jsapi::{JS_DestroyIdArray, JS_EvaluateUCScript, JS_GetArrayLength, JS_GetClass};
+use js::jsapi::{JS_GetElement, JS_GetPropertyById, JS_IsArrayObject, JS_ObjectIsCallable};
+use js::jsval::{JSVal, UndefinedValue};
+use libc;
+use std::c_str::CString;
+use std::collections::HashMap;
+use time;
+
+/// How many own properties of an object are previewed.
+const PREVIEW_PROPERTIES: uint = 10;
+
+/// How many rows of a `table` call are shown.
+const TABLE_ROWS: uint = 100;
+
+/// The file name that `capture_stack` evaluates its script as, so that its frame can be
+/// left out of the stack.
+const STACK_SCRIPT_NAME: &'static str = "resource://servo/console-stack";
+
 #[dom_struct]
 pub struct Console {
-    reflector_: Reflector
+    reflector_: Reflector,
+    global: GlobalField,
+    /// When each running timer was started, in milliseconds.
+    timers: DOMRefCell<HashMap<DOMString, f64>>,
+    /// How many times `count` has been called with each label.
+    counters: DOMRefCell<HashMap<DOMString, uint>>,
+    /// The labels of the open groups, innermost last.
+    groups: DOMRefCell<Vec<DOMString>>,
 }
 
 impl Console {
-    fn new_inherited() -> Console {
+    fn new_inherited(global: &GlobalRef) -> Console {
         Console {
-            reflector_: Reflector::new()
+            reflector_: Reflector::new(),
+            global: GlobalField::from_rooted(global),
+            timers: DOMRefCell::new(HashMap::new()),
+            counters: DOMRefCell::new(HashMap::new()),
+            groups: DOMRefCell::new(vec!()),
         }
     }
 
     pub fn new(global: GlobalRef) -> Temporary<Console> {
-        reflect_dom_object(box Console::new_inherited(), global, ConsoleBinding::Wrap)
+        reflect_dom_object(box Console::new_inherited(&global), global, ConsoleBinding::Wrap)
+    }
+}
+
+trait PrivateConsoleHelpers {
+    fn log_with_level(self, cx: *mut JSContext, level: &str, data: Vec<JSVal>);
+    fn message(self, level: &str, arguments: Vec<ConsoleArgument>, styles: Vec<String>)
+               -> ConsoleMessage;
+    fn print(self, text: &str);
+    fn send(self, message: ConsoleMessage);
+    fn open_group(self, cx: *mut JSContext, level: &str, data: Vec<JSVal>);
+}
+
+impl<'a> PrivateConsoleHelpers for JSRef<'a, Console> {
+    fn log_with_level(self, cx: *mut JSContext, level: &str, data: Vec<JSVal>) {
+        let arguments = data.into_iter().map(|value| preview(cx, value, 1)).collect();
+        let (arguments, styles) = apply_format(arguments);
+        self.print(display_arguments(arguments.as_slice()).as_slice());
+        let message = self.message(level, arguments, styles);
+        self.send(message);
+    }
+
+    /// Describes a call to the console, made from the script that is running.
+    fn message(self, level: &str, arguments: Vec<ConsoleArgument>, styles: Vec<String>)
+               -> ConsoleMessage {
+        let global = self.global.root();
+        let global = global.root_ref();
+        // Capturing the stack runs a script, so it is only done while a console is listening.
+        let stack = if is_console_api_monitored() {
+            capture_stack(global.get_cx(), global.reflector().get_jsobject())
+        } else {
+            vec!()
+        };
+        let location = match stack.as_slice().head() {
+            Some(call_site) => call_site.clone(),
+            None => CallSite {
+                function: None,
+                location: SourceLocation {
+                    url: global.get_url().serialize(),
+                    line: 0,
+                    column: 0,
+                },
+            },
+        };
+        let now = time::get_time();
+        ConsoleMessage {
+            level: level.to_string(),
+            arguments: arguments,
+            styles: styles,
+            location: location,
+            stacktrace: if level == "trace" { stack } else { vec!() },
+            group_name: None,
+            timer: None,
+            counter: None,
+            worker: match global {
+                Worker(_) => true,
+                _ => false,
+            },
+            time_stamp: now.sec as u64 * 1000 + now.nsec as u64 / 1000000,
+        }
+    }
+
+    /// Prints a line of console output, indented by the groups that are open.
+    fn print(self, text: &str) {
+        let indent = String::from_char(self.groups.borrow().len() * 2, ' ');
+        for line in text.lines() {
+            println!("{}{}", indent, line);
+        }
+    }
+
+    fn send(self, message: ConsoleMessage) {
+        let global = self.global.root();
+        let global = global.root_ref();
+        match global.devtools_chan() {
+            Some(chan) => chan.send(ConsoleAPIMsg(global.pipeline(), message)),
+            None => (),
+        }
+    }
+
+    fn open_group(self, cx: *mut JSContext, level: &str, data: Vec<JSVal>) {
+        let arguments = data.into_iter().map(|value| preview(cx, value, 1)).collect();
+        let (arguments, styles) = apply_format(arguments);
+        let label = if arguments.is_empty() {
+            "console.group".to_string()
+        } else {
+            display_arguments(arguments.as_slice())
+        };
+        self.print(label.as_slice());
+        let mut message = self.message(level, arguments, styles);
+        message.group_name = Some(label.clone());
+        self.groups.borrow_mut().push(label);
+        self.send(message);
     }
 }
 
 impl<'a> ConsoleMethods for JSRef<'a, Console> {
-    fn Log(self, message: DOMString) {
-        println!("{:s}", message);
+    fn Log(self, cx: *mut JSContext, data: Vec<JSVal>) {
+        self.log_with_level(cx, "log", data);
     }
 
-    fn Debug(self, message: DOMString) {
-        println!("{:s}", message);
+    fn Debug(self, cx: *mut JSContext, data: Vec<JSVal>) {
+        self.log_with_level(cx, "debug", data);
     }
 
-    fn Info(self, message: DOMString) {
-        println!("{:s}", message);
+    fn Info(self, cx: *mut JSContext, data: Vec<JSVal>) {
+        self.log_with_level(cx, "info", data);
     }
 
-    fn Warn(self, message: DOMString) {
-        println!("{:s}", message);
+    fn Warn(self, cx: *mut JSContext, data: Vec<JSVal>) {
+        self.log_with_level(cx, "warn", data);
     }
 
-    fn Error(self, message: DOMString) {
-        println!("{:s}", message);
+    fn Error(self, cx: *mut JSContext, data: Vec<JSVal>) {
+        self.log_with_level(cx, "error", data);
     }
 
-    fn Assert(self, condition: bool, message: Option<DOMString>) {
-        if !condition {
-            let message = match message {
-                Some(ref message) => message.as_slice(),
-                None => "no message",
-            };
-            println!("Assertion failed: {:s}", message);
+    fn Assert(self, cx: *mut JSContext, condition: bool, data: Vec<JSVal>) {
+        if condition {
+            return;
+        }
+        let arguments = data.into_iter().map(|value| preview(cx, value, 1)).collect();
+        let (mut arguments, styles) = apply_format(arguments);
+        if arguments.is_empty() {
+            arguments.push(string_argument("no message".to_string()));
+        }
+        self.print(format!("Assertion failed: {}",
+                           display_arguments(arguments.as_slice())).as_slice());
+        let message = self.message("assert", arguments, styles);
+        self.send(message);
+    }
+
+    fn Table(self, cx: *mut JSContext, data: JSVal, properties: JSVal) {
+        let columns = if properties.is_object() {
+            let mut columns = vec!();
+            for value in array_elements(cx, properties.to_object()).into_iter() {
+                let column: Result<DOMString, ()> =
+                    FromJSValConvertible::from_jsval(cx, value, conversions::Default);
+                match column {
+                    Ok(column) => columns.push(column),
+                    Err(()) => (),
+                }
+            }
+            Some(columns)
+        } else {
+            None
+        };
+
+        let mut data = preview(cx, data, 2);
+        if data.value.kind.as_slice() != "object" {
+            self.print(display_argument(&data, false).as_slice());
+            let message = self.message("log", vec!(data), vec!());
+            self.send(message);
+            return;
+        }
+        data.properties.truncate(TABLE_ROWS);
+        match columns {
+            Some(ref columns) => {
+                for &(_, ref mut row) in data.properties.iter_mut() {
+                    row.properties.retain(|&(ref name, _)| columns.contains(name));
+                }
+            }
+            None => (),
+        }
+        self.print(display_table(&data, columns).as_slice());
+        let message = self.message("table", vec!(data), vec!());
+        self.send(message);
+    }
+
+    fn Trace(self, cx: *mut JSContext, data: Vec<JSVal>) {
+        let arguments = data.into_iter().map(|value| preview(cx, value, 1)).collect();
+        let (arguments, styles) = apply_format(arguments);
+        let message = self.message("trace", arguments, styles);
+        let mut text = "console.trace()".to_string();
+        if !message.arguments.is_empty() {
+            text.push_str(": ");
+            text.push_str(display_arguments(message.arguments.as_slice()).as_slice());
+        }
+        for call_site in message.stacktrace.iter() {
+            text.push_str(format!("\n    {} @ {}:{}",
+                                  call_site.function.as_ref().map(|name| name.as_slice())
+                                                    .unwrap_or("<anonymous>"),
+                                  call_site.location.url,
+                                  call_site.location.line).as_slice());
         }
+        self.print(text.as_slice());
+        self.send(message);
+    }
+
+    fn Count(self, label: DOMString) {
+        let count = {
+            let mut counters = self.counters.borrow_mut();
+            let count = match counters.get(&label) {
+                Some(count) => *count + 1,
+                None => 1,
+            };
+            counters.insert(label.clone(), count);
+            count
+        };
+        self.print(format!("{}: {}", label, count).as_slice());
+        let mut message = self.message("count", vec!(), vec!());
+        message.counter = Some(ConsoleCounter {
+            label: label,
+            count: count,
+        });
+        self.send(message);
+    }
+
+    fn Group(self, cx: *mut JSContext, data: Vec<JSVal>) {
+        self.open_group(cx, "group", data);
+    }
+
+    fn GroupCollapsed(self, cx: *mut JSContext, data: Vec<JSVal>) {
+        self.open_group(cx, "groupCollapsed", data);
+    }
+
+    fn GroupEnd(self) {
+        let label = match self.groups.borrow_mut().pop() {
+            Some(label) => label,
+            None => return,
+        };
+        let mut message = self.message("groupEnd", vec!(), vec!());
+        message.group_name = Some(label);
+        self.send(message);
+    }
+
+    fn Time(self, label: DOMString) {
+        self.timers.borrow_mut().insert(label.clone(), now_ms());
+        self.print(format!("{}: timer started", label).as_slice());
+        let mut message = self.message("time", vec!(), vec!());
+        message.timer = Some(TimerStarted(label));
+        self.send(message);
+    }
+
+    fn TimeEnd(self, label: DOMString) {
+        let start = match self.timers.borrow_mut().remove(&label) {
+            Some(start) => start,
+            None => return,
+        };
+        let duration = now_ms() - start;
+        self.print(format!("{}: {:.3}ms", label, duration).as_slice());
+        let mut message = self.message("timeEnd", vec!(), vec!());
+        message.timer = Some(TimerEnded(label, duration));
+        self.send(message);
     }
 }
 
@@ -63,3 +311,336 @@ impl Reflectable for Console {
         &self.reflector_
     }
 }
+
+fn now_ms() -> f64 {
+    time::precise_time_ns() as f64 / 1000000.
+}
+
+fn string_argument(text: String) -> ConsoleArgument {
+    ConsoleArgument {
+        value: DebuggerValue {
+            kind: "string".to_string(),
+            text: text,
+        },
+        properties: vec!(),
+        property_count: 0,
+    }
+}
+
+/// Summarizes a JS value, previewing the own properties of objects `depth` levels deep.
+fn preview(cx: *mut JSContext, value: JSVal, depth: uint) -> ConsoleArgument {
+    let (kind, text) = if value.is_undefined() {
+        ("undefined", "undefined".to_string())
+    } else if value.is_null() {
+        ("null", "null".to_string())
+    } else if value.is_object() {
+        ("object", class_name(cx, value.to_object()))
+    } else {
+        let text: Result<DOMString, ()> =
+            FromJSValConvertible::from_jsval(cx, value, conversions::Default);
+        let kind = if value.is_boolean() {
+            "boolean"
+        } else if value.is_string() {
+            "string"
+        } else {
+            "number"
+        };
+        (kind, text.unwrap_or("".to_string()))
+    };
+
+    let mut argument = ConsoleArgument {
+        value: DebuggerValue {
+            kind: kind.to_string(),
+            text: text,
+        },
+        properties: vec!(),
+        property_count: 0,
+    };
+    if !value.is_object() || depth == 0 {
+        return argument;
+    }
+
+    let object = value.to_object();
+    let limit = if depth > 1 { TABLE_ROWS } else { PREVIEW_PROPERTIES };
+    unsafe {
+        let ids = JS_Enumerate(cx, object);
+        if ids.is_null() {
+            return argument;
+        }
+        let count = JS_IdArrayLength(cx, ids) as uint;
+        argument.property_count = count;
+        for index in range(0, count) {
+            if argument.properties.len() == limit {
+                break;
+            }
+            let id = JS_IdArrayGet(cx, ids, index as libc::c_uint);
+            let name = if RUST_JSID_IS_INT(id) != 0 {
+                RUST_JSID_TO_INT(id).to_string()
+            } else {
+                jsid_to_str(cx, id)
+            };
+            let mut property = UndefinedValue();
+            if JS_GetPropertyById(cx, object, id, &mut property) == 0 {
+                continue;
+            }
+            argument.properties.push((name, preview(cx, property, depth - 1)));
+        }
+        JS_DestroyIdArray(cx, ids);
+    }
+    argument
+}
+
+fn class_name(cx: *mut JSContext, object: *mut JSObject) -> String {
+    unsafe {
+        if JS_ObjectIsCallable(cx, object) != 0 {
+            return "Function".to_string();
+        }
+        if JS_IsArrayObject(cx, object) != 0 {
+            return "Array".to_string();
+        }
+        let class = JS_GetClass(object);
+        CString::new((*class).name, false).as_str().unwrap_or("Object").to_string()
+    }
+}
+
+fn array_elements(cx: *mut JSContext, array: *mut JSObject) -> Vec<JSVal> {
+    let mut elements = vec!();
+    unsafe {
+        let mut length = 0;
+        if JS_GetArrayLength(cx, array, &mut length) == 0 {
+            return elements;
+        }
+        for index in range(0, length) {
+            let mut element = UndefinedValue();
+            if JS_GetElement(cx, array, index, &mut element) != 0 {
+                elements.push(element);
+            }
+        }
+    }
+    elements
+}
+
+/// Captures the stack of the running script, newest frame first.
+fn capture_stack(cx: *mut JSContext, global: *mut JSObject) -> Vec<CallSite> {
+    let code: Vec<u16> = "new Error().stack".utf16_units().collect();
+    let filename = STACK_SCRIPT_NAME.to_c_str();
+    let mut rval = UndefinedValue();
+    unsafe {
+        if JS_EvaluateUCScript(cx, global, code.as_ptr(), code.len() as libc::c_uint,
+                               filename.as_ptr(), 1, &mut rval) == 0 {
+            return vec!();
+        }
+    }
+    let stack: Result<DOMString, ()> =
+        FromJSValConvertible::from_jsval(cx, rval, conversions::Default);
+    match stack {
+        Ok(stack) => parse_stack(stack.as_slice()),
+        Err(()) => vec!(),
+    }
+}
+
+/// Parses the `function@url:line:column` lines of an `Error`'s stack. The column is missing in
+/// some versions of SpiderMonkey.
+fn parse_stack(stack: &str) -> Vec<CallSite> {
+    let mut call_sites = vec!();
+    for frame in stack.lines() {
+        let (function, location) = match frame.find('@') {
+            Some(at) => (frame.slice_to(at), frame.slice_from(at + 1)),
+            None => continue,
+        };
+        let mut parts: Vec<&str> = location.rsplitn(2, ':').collect();
+        parts.reverse();
+        let numbers: Vec<Option<uint>> = parts.iter().map(|part| from_str(*part)).collect();
+        let (url, line, column) = match (parts.as_slice(), numbers.as_slice()) {
+            ([url, _, _], [_, Some(line), Some(column)]) => (url.to_string(), line, column),
+            ([scheme, rest, _], [_, _, Some(line)]) => (format!("{}:{}", scheme, rest), line, 0),
+            _ => continue,
+        };
+        if url.as_slice() == STACK_SCRIPT_NAME {
+            continue;
+        }
+        call_sites.push(CallSite {
+            function: if function.is_empty() { None } else { Some(function.to_string()) },
+            location: SourceLocation {
+                url: url,
+                line: line,
+                column: column,
+            },
+        });
+    }
+    call_sites
+}
+
+/// Applies the format string in the first argument, if it is a string: `%s`, `%d`, `%i`, `%f`
+/// and `%o` are replaced by the arguments that follow it, and `%c` styles the text after it
+/// with the CSS in its argument. Returns the resulting arguments, along with their styles if
+/// there was a `%c`.
+fn apply_format(arguments: Vec<ConsoleArgument>) -> (Vec<ConsoleArgument>, Vec<String>) {
+    let mut arguments = arguments.into_iter();
+    let format = match arguments.next() {
+        Some(first) => {
+            if first.value.kind.as_slice() != "string" {
+                let mut all = vec!(first);
+                all.extend(arguments);
+                return (all, vec!());
+            }
+            first.value.text
+        }
+        None => return (vec!(), vec!()),
+    };
+
+    let mut result = vec!();
+    let mut styles = vec!();
+    let mut style = "".to_string();
+    let mut styled = false;
+    let mut text = String::new();
+    let mut chars = format.as_slice().chars().peekable();
+    loop {
+        let c = match chars.next() {
+            Some(c) => c,
+            None => break,
+        };
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+        let directive = match chars.peek() {
+            Some(&directive) => directive,
+            None => {
+                text.push(c);
+                break;
+            }
+        };
+        match directive {
+            '%' => {
+                chars.next();
+                text.push('%');
+            }
+            's' | 'd' | 'i' | 'f' | 'o' | 'O' | 'c' => {
+                chars.next();
+                let argument = match arguments.next() {
+                    Some(argument) => argument,
+                    None => {
+                        text.push(c);
+                        text.push(directive);
+                        continue;
+                    }
+                };
+                match directive {
+                    's' => text.push_str(display_argument(&argument, false).as_slice()),
+                    'd' | 'i' => {
+                        let number: Option<f64> = from_str(argument.value.text.as_slice());
+                        match number {
+                            Some(number) if number.is_finite() => {
+                                text.push_str((number.trunc() as i64).to_string().as_slice())
+                            }
+                            _ => text.push_str("NaN"),
+                        }
+                    }
+                    'f' => {
+                        let number: Option<f64> = from_str(argument.value.text.as_slice());
+                        match number {
+                            Some(_) => text.push_str(argument.value.text.as_slice()),
+                            None => text.push_str("NaN"),
+                        }
+                    }
+                    'c' => {
+                        if !text.is_empty() {
+                            result.push(string_argument(text));
+                            styles.push(style);
+                            text = String::new();
+                        }
+                        style = argument.value.text;
+                        styled = true;
+                    }
+                    _ => {
+                        if !text.is_empty() {
+                            result.push(string_argument(text));
+                            styles.push(style.clone());
+                            text = String::new();
+                        }
+                        result.push(argument);
+                        styles.push("".to_string());
+                    }
+                }
+            }
+            _ => text.push(c),
+        }
+    }
+    if !text.is_empty() || result.is_empty() {
+        result.push(string_argument(text));
+        styles.push(style);
+    }
+    for argument in arguments {
+        result.push(argument);
+        styles.push("".to_string());
+    }
+    (result, if styled { styles } else { vec!() })
+}
+
+/// Displays an argument as console output. Strings are quoted when they are nested in an
+/// object preview.
+fn display_argument(argument: &ConsoleArgument, nested: bool) -> String {
+    let value = &argument.value;
+    match value.kind.as_slice() {
+        "string" if nested => format!("\"{}\"", value.text),
+        "object" if !nested && argument.property_count > 0 => {
+            let mut properties: Vec<String> = argument.properties.iter().map(|property| {
+                let (ref name, ref property) = *property;
+                format!("{}: {}", name, display_argument(property, true))
+            }).collect();
+            if argument.property_count > argument.properties.len() {
+                properties.push("…".to_string());
+            }
+            format!("{} {{ {} }}", value.text, properties.connect(", "))
+        }
+        _ => value.text.clone(),
+    }
+}
+
+fn display_arguments(arguments: &[ConsoleArgument]) -> String {
+    let arguments: Vec<String> =
+        arguments.iter().map(|argument| display_argument(argument, false)).collect();
+    arguments.connect(" ")
+}
+
+/// Displays the rows of a `table` call, one line each, under a header of column names. Rows
+/// that are not objects are shown in a `Values` column.
+fn display_table(data: &ConsoleArgument, columns: Option<Vec<String>>) -> String {
+    let columns = match columns {
+        Some(columns) => columns,
+        None => {
+            let mut columns: Vec<String> = vec!();
+            for &(_, ref row) in data.properties.iter() {
+                for &(ref name, _) in row.properties.iter() {
+                    if !columns.contains(name) {
+                        columns.push(name.clone());
+                    }
+                }
+            }
+            columns
+        }
+    };
+
+    let mut header = vec!("(index)".to_string());
+    header.push_all(columns.as_slice());
+    header.push("Values".to_string());
+    let mut lines = vec!(header.connect(" | "));
+    for &(ref index, ref row) in data.properties.iter() {
+        let mut cells = vec!(index.clone());
+        for column in columns.iter() {
+            let cell = row.properties.iter().find(|&&(ref name, _)| name == column);
+            cells.push(match cell {
+                Some(&(_, ref value)) => display_argument(value, true),
+                None => "".to_string(),
+            });
+        }
+        cells.push(if row.value.kind.as_slice() == "object" {
+            "".to_string()
+        } else {
+            display_argument(row, true)
+        });
+        lines.push(cells.connect(" | "));
+    }
+    lines.connect("\n")
+}
@@ -7,36 +7,56 @@ use dom::bindings::codegen::Bindings::DedicatedWorkerGlobalScopeBinding::Dedicat
 use dom::bindings::codegen::Bindings::EventHandlerBinding::EventHandlerNonNull;
 use dom::bindings::codegen::InheritTypes::DedicatedWorkerGlobalScopeDerived;
 use dom::bindings::codegen::InheritTypes::{EventTargetCast, WorkerGlobalScopeCast};
-use dom::bindings::error::{ErrorResult, DataClone};
+use dom::bindings::error::ErrorResult;
 use dom::bindings::global;
 use dom::bindings::js::{JSRef, Temporary, RootCollection};
+use dom::bindings::structuredclone::StructuredCloneData;
 use dom::bindings::utils::{Reflectable, Reflector};
+use dom::errorevent::{ErrorEvent, ErrorInfo};
 use dom::eventtarget::{EventTarget, EventTargetHelpers};
 use dom::eventtarget::WorkerGlobalScopeTypeId;
 use dom::messageevent::MessageEvent;
-use dom::worker::{Worker, TrustedWorkerAddress};
+use dom::messageport::MessagePort;
+use dom::sharedworker::SharedWorker;
+use dom::worker::{Worker, TrustedWorkerAddress, WorkerTerminator};
 use dom::workerglobalscope::DedicatedGlobalScope;
 use dom::workerglobalscope::{WorkerGlobalScope, WorkerGlobalScopeHelpers};
+use dom::workerglobalscope::{set_error_reporter, take_pending_error};
 use dom::xmlhttprequest::XMLHttpRequest;
 use script_task::{ScriptTask, ScriptChan};
 use script_task::{ScriptMsg, FromWorker,  DOMMessage, FireTimerMsg, XHRProgressMsg, XHRReleaseMsg, WorkerRelease};
-use script_task::WorkerPostMessage;
+use script_task::{WorkerPostMessage, WorkerErrorMsg, WorkerLoadErrorMsg, WorkerTerminateMsg};
+use script_task::{MessagePortMsg, SharedWorkerConnectedMsg};
 use script_task::StackRootTLS;
 
+use devtools_traits::DevtoolsControlChan;
+
+use servo_msg::constellation_msg::PipelineId;
+use servo_net::fetch::request::Worker as WorkerContext;
 use servo_net::resource_task::{ResourceTask, load_whole_resource};
 use servo_util::task::spawn_named_native;
 use servo_util::task_state;
 use servo_util::task_state::{SCRIPT, IN_WORKER};
 
-use js::glue::JS_STRUCTURED_CLONE_VERSION;
-use js::jsapi::{JSContext, JS_ReadStructuredClone, JS_WriteStructuredClone, JS_ClearPendingException};
-use js::jsval::{JSVal, UndefinedValue};
+use js::jsapi::{JSContext, JSObject, JSBool, JS_SetOperationCallback};
+use js::jsval::JSVal;
 use js::rust::Cx;
 
 use std::rc::Rc;
-use std::ptr;
 use url::Url;
 
+/// The terminator of the worker running on this task.
+local_data_key!(pub RunningWorker: WorkerTerminator)
+
+/// Called by SpiderMonkey every now and then while a script runs; aborts the script of a
+/// terminated worker.
+pub extern fn interrupt_worker(_cx: *mut JSContext) -> JSBool {
+    match RunningWorker.get() {
+        Some(terminator) => !terminator.is_terminated() as JSBool,
+        None => 1,
+    }
+}
+
 #[dom_struct]
 pub struct DedicatedWorkerGlobalScope {
     workerglobalscope: WorkerGlobalScope,
@@ -51,6 +71,8 @@ impl DedicatedWorkerGlobalScope {
                          worker: TrustedWorkerAddress,
                          cx: Rc<Cx>,
                          resource_task: ResourceTask,
+                         devtools_chan: Option<DevtoolsControlChan>,
+                         pipeline: PipelineId,
                          parent_sender: ScriptChan,
                          own_sender: ScriptChan,
                          receiver: Receiver<ScriptMsg>)
@@ -58,7 +80,7 @@ impl DedicatedWorkerGlobalScope {
         DedicatedWorkerGlobalScope {
             workerglobalscope: WorkerGlobalScope::new_inherited(
                 DedicatedGlobalScope, worker_url, cx, resource_task,
-                own_sender),
+                devtools_chan, pipeline, own_sender),
             receiver: receiver,
             parent_sender: parent_sender,
             worker: worker,
@@ -69,13 +91,15 @@ impl DedicatedWorkerGlobalScope {
                worker: TrustedWorkerAddress,
                cx: Rc<Cx>,
                resource_task: ResourceTask,
+               devtools_chan: Option<DevtoolsControlChan>,
+               pipeline: PipelineId,
                parent_sender: ScriptChan,
                own_sender: ScriptChan,
                receiver: Receiver<ScriptMsg>)
                -> Temporary<DedicatedWorkerGlobalScope> {
         let scope = box DedicatedWorkerGlobalScope::new_inherited(
-            worker_url, worker, cx.clone(), resource_task, parent_sender,
-            own_sender, receiver);
+            worker_url, worker, cx.clone(), resource_task, devtools_chan, pipeline,
+            parent_sender, own_sender, receiver);
         DedicatedWorkerGlobalScopeBinding::Wrap(cx.ptr, scope)
     }
 }
@@ -84,9 +108,12 @@ impl DedicatedWorkerGlobalScope {
     pub fn run_worker_scope(worker_url: Url,
                             worker: TrustedWorkerAddress,
                             resource_task: ResourceTask,
+                            devtools_chan: Option<DevtoolsControlChan>,
+                            pipeline: PipelineId,
                             parent_sender: ScriptChan,
                             own_sender: ScriptChan,
-                            receiver: Receiver<ScriptMsg>) {
+                            receiver: Receiver<ScriptMsg>,
+                            terminator: WorkerTerminator) {
         spawn_named_native(format!("WebWorker for {}", worker_url.serialize()), proc() {
 
             task_state::initialize(SCRIPT | IN_WORKER);
@@ -94,43 +121,56 @@ impl DedicatedWorkerGlobalScope {
             let roots = RootCollection::new();
             let _stack_roots_tls = StackRootTLS::new(&roots);
 
-            let (url, source) = match load_whole_resource(&resource_task, worker_url.clone()) {
+            let (url, source) = match load_whole_resource(&resource_task, worker_url.clone(),
+                                                          WorkerContext) {
                 Err(_) => {
-                    println!("error loading script {}", worker_url.serialize());
+                    let ScriptChan(ref sender) = parent_sender;
+                    sender.send(WorkerLoadErrorMsg(worker));
+                    sender.send(WorkerRelease(worker));
                     return;
                 }
                 Ok((metadata, bytes)) => {
-                    (metadata.final_url, String::from_utf8(bytes).unwrap())
+                    (metadata.final_url, String::from_utf8_lossy(bytes.as_slice()).into_string())
                 }
             };
 
-            let (_js_runtime, js_context) = ScriptTask::new_rt_and_cx();
-            let global = DedicatedWorkerGlobalScope::new(
-                worker_url, worker, js_context.clone(), resource_task,
-                parent_sender, own_sender, receiver).root();
-            match js_context.evaluate_script(
-                global.reflector().get_jsobject(), source, url.serialize(), 1) {
-                Ok(_) => (),
-                Err(_) => println!("evaluate_script failed")
+            let (js_runtime, js_context) = ScriptTask::new_rt_and_cx();
+            RunningWorker.replace(Some(terminator.clone()));
+            terminator.set_runtime(Some(js_runtime.ptr));
+            unsafe {
+                JS_SetOperationCallback(js_context.ptr, Some(interrupt_worker));
             }
-            global.delayed_release_worker();
+            set_error_reporter(js_context.ptr);
 
+            let global = DedicatedWorkerGlobalScope::new(
+                worker_url, worker, js_context.clone(), resource_task, devtools_chan,
+                pipeline, parent_sender, own_sender, receiver).root();
             let scope: JSRef<WorkerGlobalScope> =
                 WorkerGlobalScopeCast::from_ref(*global);
             let target: JSRef<EventTarget> =
                 EventTargetCast::from_ref(*global);
-            loop {
+
+            // The worker may have been terminated before its runtime existed.
+            if !terminator.is_terminated() {
+                // An uncaught exception is reported as an error event below.
+                let _ = js_context.evaluate_script(
+                    global.reflector().get_jsobject(), source, url.serialize(), 1);
+                global.report_pending_error();
+            }
+
+            while !scope.is_closing() && !terminator.is_terminated() {
                 match global.receiver.recv_opt() {
-                    Ok(DOMMessage(data, nbytes)) => {
-                        let mut message = UndefinedValue();
-                        unsafe {
-                            assert!(JS_ReadStructuredClone(
-                                js_context.ptr, data as *const u64, nbytes,
-                                JS_STRUCTURED_CLONE_VERSION, &mut message,
-                                ptr::null(), ptr::null_mut()) != 0);
+                    Ok(DOMMessage(data)) => {
+                        match data.read(global::Worker(scope)) {
+                            Ok((message, ports)) => {
+                                MessageEvent::dispatch_jsval(target, global::Worker(scope),
+                                                             message, ports)
+                            }
+                            Err(()) => {
+                                MessageEvent::dispatch_error(target, global::Worker(scope))
+                            }
                         }
-
-                        MessageEvent::dispatch_jsval(target, global::Worker(scope), message);
+                        global.report_pending_error();
                         global.delayed_release_worker();
                     },
                     Ok(XHRProgressMsg(addr, progress)) => {
@@ -139,46 +179,90 @@ impl DedicatedWorkerGlobalScope {
                     Ok(XHRReleaseMsg(addr)) => {
                         XMLHttpRequest::handle_release(addr)
                     },
-                    Ok(WorkerPostMessage(addr, data, nbytes)) => {
-                        Worker::handle_message(addr, data, nbytes);
+                    Ok(WorkerPostMessage(addr, data)) => {
+                        Worker::handle_message(addr, data);
                     },
                     Ok(WorkerRelease(addr)) => {
                         Worker::handle_release(addr)
                     },
+                    Ok(WorkerErrorMsg(addr, error)) => {
+                        Worker::handle_error(addr, error)
+                    },
+                    Ok(WorkerLoadErrorMsg(addr)) => {
+                        Worker::handle_load_error(addr)
+                    },
+                    Ok(MessagePortMsg(addr)) => {
+                        MessagePort::handle_message(addr)
+                    },
+                    Ok(SharedWorkerConnectedMsg(addr, load_failed)) => {
+                        SharedWorker::handle_connected(addr, load_failed)
+                    },
                     Ok(FireTimerMsg(FromWorker, timer_id)) => {
                         scope.handle_fire_timer(timer_id);
                     }
+                    Ok(WorkerTerminateMsg) => (),
                     Ok(_) => panic!("Unexpected message"),
                     Err(_) => break,
                 }
+                global.report_pending_error();
+            }
+
+            // Release the references that the messages which won't be handled hold on the
+            // Worker object. The Worker can't send any more of them once the terminator is
+            // closed.
+            terminator.close();
+            loop {
+                match global.receiver.try_recv() {
+                    Ok(DOMMessage(..)) => global.delayed_release_worker(),
+                    Ok(_) => (),
+                    Err(_) => break,
+                }
             }
+            // The reference taken when the Worker was created.
+            global.delayed_release_worker();
+
+            scope.terminate_child_workers();
+            terminator.set_runtime(None);
         });
     }
 }
 
 impl<'a> DedicatedWorkerGlobalScopeMethods for JSRef<'a, DedicatedWorkerGlobalScope> {
-    fn PostMessage(self, cx: *mut JSContext, message: JSVal) -> ErrorResult {
-        let mut data = ptr::null_mut();
-        let mut nbytes = 0;
-        let result = unsafe {
-            JS_WriteStructuredClone(cx, message, &mut data, &mut nbytes,
-                                    ptr::null(), ptr::null_mut())
-        };
-        if result == 0 {
-            unsafe { JS_ClearPendingException(cx); }
-            return Err(DataClone);
-        }
-
+    fn PostMessage(self, cx: *mut JSContext, message: JSVal,
+                   transfer: Option<Vec<*mut JSObject>>) -> ErrorResult {
+        let data = try!(StructuredCloneData::write(cx, message, transfer));
         let ScriptChan(ref sender) = self.parent_sender;
-        sender.send(WorkerPostMessage(self.worker, data, nbytes));
+        sender.send(WorkerPostMessage(self.worker, data));
         Ok(())
     }
 
     event_handler!(message, GetOnmessage, SetOnmessage)
+    event_handler!(messageerror, GetOnmessageerror, SetOnmessageerror)
+}
+
+pub trait DedicatedWorkerGlobalScopeHelpers {
+    fn report_error(self, error: ErrorInfo);
+}
+
+impl<'a> DedicatedWorkerGlobalScopeHelpers for JSRef<'a, DedicatedWorkerGlobalScope> {
+    // http://www.whatwg.org/html/#runtime-script-errors-2
+    fn report_error(self, error: ErrorInfo) {
+        let scope: JSRef<WorkerGlobalScope> = WorkerGlobalScopeCast::from_ref(self);
+        let target: JSRef<EventTarget> = EventTargetCast::from_ref(self);
+        let not_handled = ErrorEvent::dispatch(target, &global::Worker(scope), &error);
+        // Errors thrown by the error handlers themselves aren't reported.
+        take_pending_error();
+
+        if not_handled {
+            let ScriptChan(ref sender) = self.parent_sender;
+            sender.send(WorkerErrorMsg(self.worker, error));
+        }
+    }
 }
 
 trait PrivateDedicatedWorkerGlobalScopeHelpers {
     fn delayed_release_worker(self);
+    fn report_pending_error(self);
 }
 
 impl<'a> PrivateDedicatedWorkerGlobalScopeHelpers for JSRef<'a, DedicatedWorkerGlobalScope> {
@@ -186,6 +270,13 @@ impl<'a> PrivateDedicatedWorkerGlobalScopeHelpers for JSRef<'a, DedicatedWorkerG
         let ScriptChan(ref sender) = self.parent_sender;
         sender.send(WorkerRelease(self.worker));
     }
+
+    fn report_pending_error(self) {
+        match take_pending_error() {
+            Some(error) => self.report_error(error),
+            None => (),
+        }
+    }
 }
 
 impl Reflectable for DedicatedWorkerGlobalScope {
@@ -21,7 +21,7 @@ use dom::bindings::codegen::InheritTypes::{HTMLAreaElementDerived, HTMLEmbedElem
 use dom::bindings::codegen::InheritTypes::{HTMLFormElementDerived, HTMLImageElementDerived};
 use dom::bindings::codegen::InheritTypes::{HTMLScriptElementDerived};
 use dom::bindings::error::{ErrorResult, Fallible, NotSupported, InvalidCharacter};
-use dom::bindings::error::{HierarchyRequest, NamespaceError};
+use dom::bindings::error::{HierarchyRequest, NamespaceError, InvalidState};
 use dom::bindings::global::GlobalRef;
 use dom::bindings::global;
 use dom::bindings::js::{MutNullableJS, JS, JSRef, Temporary, OptionalSettable, TemporaryPushable};
@@ -43,6 +43,7 @@ use dom::htmlcollection::{HTMLCollection, CollectionFilter};
 use dom::htmlelement::HTMLElement;
 use dom::htmlheadelement::HTMLHeadElement;
 use dom::htmlhtmlelement::HTMLHtmlElement;
+use dom::htmlscriptelement::{HTMLScriptElement, HTMLScriptElementHelpers};
 use dom::htmltitleelement::HTMLTitleElement;
 use dom::location::Location;
 use dom::mouseevent::MouseEvent;
@@ -53,6 +54,7 @@ use dom::nodelist::NodeList;
 use dom::text::Text;
 use dom::processinginstruction::ProcessingInstruction;
 use dom::range::Range;
+use dom::servohtmlparser::ServoHTMLParser;
 use dom::treewalker::TreeWalker;
 use dom::uievent::UIEvent;
 use dom::window::{Window, WindowHelpers};
@@ -101,6 +103,16 @@ pub struct Document {
     possibly_focused: MutNullableJS<Element>,
     /// The element that currently has the document focus context.
     focused: MutNullableJS<Element>,
+    /// https://html.spec.whatwg.org/multipage/scripting.html#list-of-scripts-that-will-execute-when-the-document-has-finished-parsing
+    deferred_scripts: DOMRefCell<Vec<JS<HTMLScriptElement>>>,
+    /// https://html.spec.whatwg.org/multipage/scripting.html#list-of-scripts-that-will-execute-in-order-as-soon-as-possible
+    asap_in_order_scripts: DOMRefCell<Vec<JS<HTMLScriptElement>>>,
+    /// https://html.spec.whatwg.org/multipage/scripting.html#set-of-scripts-that-will-execute-as-soon-as-possible
+    asap_scripts: DOMRefCell<Vec<JS<HTMLScriptElement>>>,
+    /// https://html.spec.whatwg.org/multipage/syntax.html#active-parser
+    current_parser: MutNullableJS<ServoHTMLParser>,
+    /// https://html.spec.whatwg.org/multipage/webappapis.html#ignore-destructive-writes-counter
+    ignore_destructive_writes_counter: Cell<uint>,
 }
 
 impl DocumentDerived for EventTarget {
@@ -187,6 +199,14 @@ pub trait DocumentHelpers<'a> {
     fn begin_focus_transaction(self);
     fn request_focus(self, elem: JSRef<Element>);
     fn commit_focus_transaction(self);
+    fn add_deferred_script(self, script: JSRef<HTMLScriptElement>);
+    fn add_asap_script(self, script: JSRef<HTMLScriptElement>, in_order: bool);
+    fn execute_ready_asap_scripts(self);
+    fn execute_deferred_scripts(self);
+    fn wait_for_asap_scripts(self);
+    fn set_current_parser(self, parser: Option<JSRef<ServoHTMLParser>>);
+    fn increment_ignore_destructive_writes_counter(self);
+    fn decrement_ignore_destructive_writes_counter(self);
 }
 
 impl<'a> DocumentHelpers<'a> for JSRef<'a, Document> {
@@ -360,6 +380,89 @@ impl<'a> DocumentHelpers<'a> for JSRef<'a, Document> {
         //TODO: dispatch blur, focus, focusout, and focusin events
         self.focused.assign(self.possibly_focused.get());
     }
+
+    /// Add a script to run in order once the document has finished parsing.
+    fn add_deferred_script(self, script: JSRef<HTMLScriptElement>) {
+        self.deferred_scripts.borrow_mut().push(JS::from_rooted(script));
+    }
+
+    /// Add a script to run as soon as it is ready, either in the order the scripts were added or
+    /// in any order.
+    fn add_asap_script(self, script: JSRef<HTMLScriptElement>, in_order: bool) {
+        let scripts = if in_order { &self.asap_in_order_scripts } else { &self.asap_scripts };
+        scripts.borrow_mut().push(JS::from_rooted(script));
+        self.execute_ready_asap_scripts();
+    }
+
+    /// Execute every script that should run as soon as possible and is ready. Scripts are
+    /// removed from their list before they run, since running them can add new scripts.
+    fn execute_ready_asap_scripts(self) {
+        loop {
+            let script = {
+                let mut in_order = self.asap_in_order_scripts.borrow_mut();
+                let ready = in_order.iter().next().map_or(false, |script| script.root().is_ready());
+                if ready {
+                    in_order.remove(0)
+                } else {
+                    let mut unordered = self.asap_scripts.borrow_mut();
+                    let index = unordered.iter().position(|script| script.root().is_ready());
+                    index.and_then(|index| unordered.remove(index))
+                }
+            };
+            match script {
+                Some(script) => script.root().execute(),
+                None => break,
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/syntax.html#the-end step 3
+    fn execute_deferred_scripts(self) {
+        loop {
+            let script = {
+                let mut deferred = self.deferred_scripts.borrow_mut();
+                if deferred.is_empty() { None } else { deferred.remove(0) }
+            };
+            let script = match script {
+                Some(script) => script.root(),
+                None => break,
+            };
+            script.wait_until_ready();
+            self.execute_ready_asap_scripts();
+            script.execute();
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/syntax.html#the-end step 5
+    fn wait_for_asap_scripts(self) {
+        loop {
+            self.execute_ready_asap_scripts();
+            let pending = {
+                let in_order = self.asap_in_order_scripts.borrow();
+                let unordered = self.asap_scripts.borrow();
+                in_order.iter().chain(unordered.iter()).next()
+                        .map(|script| Temporary::new(script.clone()))
+            };
+            match pending {
+                Some(script) => script.root().wait_until_ready(),
+                None => break,
+            }
+        }
+    }
+
+    fn set_current_parser(self, parser: Option<JSRef<ServoHTMLParser>>) {
+        self.current_parser.assign(parser);
+    }
+
+    fn increment_ignore_destructive_writes_counter(self) {
+        let counter = self.ignore_destructive_writes_counter.get();
+        self.ignore_destructive_writes_counter.set(counter + 1);
+    }
+
+    fn decrement_ignore_destructive_writes_counter(self) {
+        let counter = self.ignore_destructive_writes_counter.get();
+        self.ignore_destructive_writes_counter.set(counter - 1);
+    }
 }
 
 #[deriving(PartialEq)]
@@ -425,6 +528,11 @@ impl Document {
             ready_state: Cell::new(ready_state),
             possibly_focused: Default::default(),
             focused: Default::default(),
+            deferred_scripts: DOMRefCell::new(vec!()),
+            asap_in_order_scripts: DOMRefCell::new(vec!()),
+            asap_scripts: DOMRefCell::new(vec!()),
+            current_parser: Default::default(),
+            ignore_destructive_writes_counter: Cell::new(0),
         }
     }
 
@@ -966,6 +1074,108 @@ impl<'a> DocumentMethods for JSRef<'a, Document> {
         self.ready_state.get()
     }
 
+    // https://html.spec.whatwg.org/multipage/webappapis.html#dom-document-open
+    fn Open(self, _type: DOMString, _replace: DOMString) -> Fallible<Temporary<Document>> {
+        // Step 1.
+        if !self.is_html_document {
+            return Err(InvalidState);
+        }
+
+        // Steps 2-5 and 10: every document has a browsing context, nothing ignores opens during
+        // unload, and a document isn't replaced by a new one.
+
+        // Step 6.
+        match self.current_parser.get().root() {
+            Some(ref parser) if parser.can_write() && !parser.is_script_created() => {
+                return Ok(Temporary::from_rooted(self));
+            },
+            _ => {},
+        }
+
+        // Steps 7-9: loads aren't aborted, and unload events aren't fired.
+
+        // Steps 11-13: remove the document's children. Event listeners and handlers are kept.
+        let node: JSRef<Node> = NodeCast::from_ref(self);
+        Node::replace_all(None, node);
+
+        // Steps 14-16.
+        let parser = ServoHTMLParser::new_script_created(Some(self.url.clone()), self).root();
+        self.set_current_parser(Some(*parser));
+        self.set_quirks_mode(NoQuirks);
+
+        // Steps 17-20.
+        self.set_ready_state(DocumentReadyStateValues::Loading);
+
+        // Step 21.
+        Ok(Temporary::from_rooted(self))
+    }
+
+    // https://html.spec.whatwg.org/multipage/webappapis.html#dom-document-close
+    fn Close(self) -> ErrorResult {
+        // Step 1.
+        if !self.is_html_document {
+            return Err(InvalidState);
+        }
+
+        // Step 2.
+        let parser = match self.current_parser.get().root() {
+            Some(ref parser) if parser.is_script_created() => Temporary::from_rooted(**parser),
+            _ => return Ok(()),
+        }.root();
+
+        // Steps 3-4: there are no parsing-blocking scripts to wait for, so the parser reaches the
+        // end of its input straight away.
+        parser.finish();
+
+        // https://html.spec.whatwg.org/multipage/syntax.html#the-end
+        let window = self.window.root();
+        self.set_ready_state(DocumentReadyStateValues::Interactive);
+        self.execute_deferred_scripts();
+        let doctarget: JSRef<EventTarget> = EventTargetCast::from_ref(self);
+        let event = Event::new(global::Window(*window), "DOMContentLoaded".to_string(),
+                               DoesNotBubble, NotCancelable).root();
+        let _ = doctarget.dispatch_event_with_target(None, *event);
+        self.wait_for_asap_scripts();
+        self.set_ready_state(DocumentReadyStateValues::Complete);
+        let event = Event::new(global::Window(*window), "load".to_string(),
+                               DoesNotBubble, NotCancelable).root();
+        let wintarget: JSRef<EventTarget> = EventTargetCast::from_ref(*window);
+        let _ = wintarget.dispatch_event_with_target(Some(doctarget), *event);
+        self.reflow();
+        Ok(())
+    }
+
+    // https://html.spec.whatwg.org/multipage/webappapis.html#dom-document-write
+    fn Write(self, text: Vec<DOMString>) -> ErrorResult {
+        // Step 1.
+        if !self.is_html_document {
+            return Err(InvalidState);
+        }
+
+        // Steps 2-3.
+        let parser = match self.current_parser.get().root() {
+            Some(ref parser) if parser.can_write() => Temporary::from_rooted(**parser),
+            _ => {
+                if self.ignore_destructive_writes_counter.get() > 0 {
+                    return Ok(());
+                }
+                try!(self.Open("text/html".to_string(), "".to_string()));
+                self.current_parser.get().expect("document.open didn't create a parser")
+            },
+        }.root();
+
+        // Steps 4-6.
+        parser.write(text.concat());
+        Ok(())
+    }
+
+    // https://html.spec.whatwg.org/multipage/webappapis.html#dom-document-writeln
+    fn Writeln(self, text: Vec<DOMString>) -> ErrorResult {
+        let mut text = text;
+        text.push("\n".to_string());
+        self.Write(text)
+    }
+
     event_handler!(click, GetOnclick, SetOnclick)
     event_handler!(load, GetOnload, SetOnload)
     event_handler!(readystatechange, GetOnreadystatechange, SetOnreadystatechange)
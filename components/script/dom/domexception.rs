@@ -48,6 +48,7 @@ impl DOMErrorName {
             error::InvalidCharacter => InvalidCharacterError,
             error::NotSupported => NotSupportedError,
             error::InvalidState => InvalidStateError,
+            error::NoModificationAllowed => NoModificationAllowedError,
             error::Syntax => SyntaxError,
             error::NamespaceError => NamespaceError,
             error::InvalidAccess => InvalidAccessError,
@@ -56,7 +57,7 @@ impl DOMErrorName {
             error::Abort => AbortError,
             error::Timeout => TimeoutError,
             error::DataClone => DataCloneError,
-            error::FailureUnknown => panic!(),
+            error::FailureUnknown | error::Type(_) => panic!(),
         }
     }
 }
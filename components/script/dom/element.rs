@@ -12,28 +12,34 @@ use dom::bindings::codegen::Bindings::AttrBinding::AttrMethods;
 use dom::bindings::codegen::Bindings::ElementBinding;
 use dom::bindings::codegen::Bindings::ElementBinding::ElementMethods;
 use dom::bindings::codegen::Bindings::NamedNodeMapBinding::NamedNodeMapMethods;
+use dom::bindings::codegen::Bindings::NodeBinding::NodeMethods;
 use dom::bindings::codegen::InheritTypes::{ElementDerived, HTMLInputElementDerived};
-use dom::bindings::codegen::InheritTypes::{HTMLTableCellElementDerived, NodeCast};
+use dom::bindings::codegen::InheritTypes::{HTMLTableCellElementDerived, ElementCast, NodeCast};
 use dom::bindings::js::{MutNullableJS, JS, JSRef, Temporary, TemporaryPushable};
-use dom::bindings::js::{OptionalSettable, OptionalRootable, Root};
+use dom::bindings::js::{OptionalSettable, OptionalRootable, Root, RootedReference};
 use dom::bindings::utils::{Reflectable, Reflector};
 use dom::bindings::error::{ErrorResult, Fallible, NamespaceError, InvalidCharacter, Syntax};
+use dom::bindings::error::NoModificationAllowed;
 use dom::bindings::utils::{QName, Name, InvalidXMLName, xml_name_type};
 use dom::create::create_element;
 use dom::domrect::DOMRect;
 use dom::domrectlist::DOMRectList;
 use dom::document::{Document, DocumentHelpers, LayoutDocumentHelpers};
+use dom::documentfragment::DocumentFragment;
 use dom::domtokenlist::DOMTokenList;
 use dom::eventtarget::{EventTarget, NodeTargetTypeId};
+use dom::htmlbodyelement::HTMLBodyElement;
 use dom::htmlcollection::HTMLCollection;
 use dom::htmlinputelement::{HTMLInputElement, RawLayoutHTMLInputElementHelpers};
 use dom::htmlserializer::serialize;
 use dom::htmltablecellelement::{HTMLTableCellElement, HTMLTableCellElementHelpers};
 use dom::node::{ElementNodeTypeId, Node, NodeHelpers, NodeIterator, document_from_node};
+use dom::node::{DocumentNodeTypeId, DocumentFragmentNodeTypeId};
 use dom::node::{window_from_node, LayoutNodeHelpers};
 use dom::nodelist::NodeList;
 use dom::virtualmethods::{VirtualMethods, vtable_for};
 use devtools_traits::AttrInfo;
+use parse::html::parse_html_fragment;
 use style::{IntegerAttribute, LengthAttribute, SizeIntegerAttribute, WidthLengthAttribute};
 use style::{matches, parse_selector_list_from_str};
 use style;
@@ -122,6 +128,7 @@ pub enum ElementTypeId {
     HTMLOutputElementTypeId,
     HTMLParagraphElementTypeId,
     HTMLParamElementTypeId,
+    HTMLPictureElementTypeId,
     HTMLPreElementTypeId,
     HTMLProgressElementTypeId,
     HTMLQuoteElementTypeId,
@@ -356,6 +363,7 @@ pub trait ElementHelpers<'a> {
     fn style_attribute(self) -> &'a DOMRefCell<Option<style::PropertyDeclarationBlock>>;
     fn summarize(self) -> Vec<AttrInfo>;
     fn is_void(self) -> bool;
+    fn parse_fragment(self, markup: DOMString) -> Temporary<DocumentFragment>;
 }
 
 impl<'a> ElementHelpers<'a> for JSRef<'a, Element> {
@@ -413,6 +421,46 @@ impl<'a> ElementHelpers<'a> for JSRef<'a, Element> {
             _ => false
         }
     }
+
+    // http://domparsing.spec.whatwg.org/#concept-parse-fragment
+    fn parse_fragment(self, markup: DOMString) -> Temporary<DocumentFragment> {
+        //XXX TODO: XML case
+        // Steps 1-2.
+        let context: JSRef<Node> = NodeCast::from_ref(self);
+        let new_children = parse_html_fragment(context, markup);
+
+        // Step 3.
+        let document = document_from_node(self).root();
+        let fragment = DocumentFragment::new(*document).root();
+        let fragment_node: JSRef<Node> = NodeCast::from_ref(*fragment);
+
+        // Step 4.
+        for child in new_children.into_iter() {
+            let child = child.root();
+            assert!(fragment_node.AppendChild(*child).is_ok());
+        }
+
+        // Step 5.
+        Temporary::from_rooted(*fragment)
+    }
+}
+
+/// The context that markup inserted next to or inside `node` is parsed in, which is a new `body`
+/// element if `node` isn't an element or is the root `html` element of an HTML document.
+/// (http://domparsing.spec.whatwg.org/#dom-element-insertadjacenthtml, step 2)
+pub fn fragment_parsing_context(node: JSRef<Node>) -> Temporary<Element> {
+    let element: Option<JSRef<Element>> = ElementCast::to_ref(node);
+    match element {
+        Some(element) if !(element.html_element_in_html_document() &&
+                           element.local_name().as_slice() == "html") => {
+            Temporary::from_rooted(element)
+        },
+        _ => {
+            let document = document_from_node(node).root();
+            let body = HTMLBodyElement::new("body".to_string(), None, *document);
+            ElementCast::from_temporary(body)
+        }
+    }
 }
 
 pub trait AttributeHandlers {
@@ -930,10 +978,93 @@ impl<'a> ElementMethods for JSRef<'a, Element> {
         Ok(serialize(&mut NodeIterator::new(NodeCast::from_ref(self), false, false)))
     }
 
+    // http://domparsing.spec.whatwg.org/#dom-element-innerhtml
+    fn SetInnerHTML(self, value: DOMString) -> ErrorResult {
+        // Step 1.
+        let fragment = self.parse_fragment(value).root();
+        // Step 2: template contents aren't supported yet.
+        // Step 3.
+        let context: JSRef<Node> = NodeCast::from_ref(self);
+        Node::replace_all(Some(NodeCast::from_ref(*fragment)), context);
+        Ok(())
+    }
+
     fn GetOuterHTML(self) -> Fallible<DOMString> {
         Ok(serialize(&mut NodeIterator::new(NodeCast::from_ref(self), true, false)))
     }
 
+    // http://domparsing.spec.whatwg.org/#dom-element-outerhtml
+    fn SetOuterHTML(self, value: DOMString) -> ErrorResult {
+        let context: JSRef<Node> = NodeCast::from_ref(self);
+
+        // Steps 1-2.
+        let parent = match context.parent_node() {
+            Some(parent) => parent.root(),
+            None => return Ok(()),
+        };
+
+        // Steps 3-4.
+        let fragment_context = match parent.type_id() {
+            DocumentNodeTypeId => return Err(NoModificationAllowed),
+            DocumentFragmentNodeTypeId => fragment_parsing_context(*parent),
+            _ => Temporary::from_rooted(ElementCast::to_ref(*parent).unwrap()),
+        }.root();
+
+        // Step 5.
+        let fragment = fragment_context.parse_fragment(value).root();
+
+        // Step 6.
+        parent.ReplaceChild(NodeCast::from_ref(*fragment), context).map(|_| ())
+    }
+
+    // http://domparsing.spec.whatwg.org/#dom-element-insertadjacenthtml
+    fn InsertAdjacentHTML(self, position: DOMString, text: DOMString) -> ErrorResult {
+        let node: JSRef<Node> = NodeCast::from_ref(self);
+        let position = position.as_slice().to_ascii_lower();
+
+        // Step 1.
+        let context = match position.as_slice() {
+            "beforebegin" | "afterend" => {
+                let parent = match node.parent_node() {
+                    Some(parent) => parent.root(),
+                    None => return Err(NoModificationAllowed),
+                };
+                if parent.is_document() {
+                    return Err(NoModificationAllowed);
+                }
+                Temporary::from_rooted(*parent)
+            },
+            "afterbegin" | "beforeend" => Temporary::from_rooted(node),
+            _ => return Err(Syntax),
+        }.root();
+
+        // Step 2.
+        let context = fragment_parsing_context(*context).root();
+
+        // Step 3.
+        let fragment_root = context.parse_fragment(text).root();
+        let fragment: JSRef<Node> = NodeCast::from_ref(*fragment_root);
+
+        // Step 4.
+        match position.as_slice() {
+            "beforebegin" => {
+                let parent = node.parent_node().unwrap().root();
+                parent.InsertBefore(fragment, Some(node)).map(|_| ())
+            },
+            "afterbegin" => {
+                let first_child = node.first_child().root();
+                node.InsertBefore(fragment, first_child.root_ref()).map(|_| ())
+            },
+            "beforeend" => node.AppendChild(fragment).map(|_| ()),
+            "afterend" => {
+                let parent = node.parent_node().unwrap().root();
+                let next_sibling = node.next_sibling().root();
+                parent.InsertBefore(fragment, next_sibling.root_ref()).map(|_| ())
+            },
+            _ => unreachable!(),
+        }
+    }
+
     // http://dom.spec.whatwg.org/#dom-parentnode-children
     fn Children(self) -> Temporary<HTMLCollection> {
         let window = window_from_node(self).root();
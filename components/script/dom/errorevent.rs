@@ -14,12 +14,22 @@ use dom::bindings::trace::JSTraceable;
 
 use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
 use dom::event::{Event, EventTypeId, ErrorEventTypeId};
+use dom::eventtarget::{EventTarget, EventTargetHelpers};
 use servo_util::str::DOMString;
 
 use dom::bindings::cell::DOMRefCell;
 use std::cell::{Cell};
 use js::jsval::{JSVal, NullValue};
 
+/// The details of an uncaught script error, which can be sent to other tasks.
+#[deriving(Clone)]
+pub struct ErrorInfo {
+    pub message: DOMString,
+    pub filename: DOMString,
+    pub lineno: u32,
+    pub column: u32,
+}
+
 #[dom_struct]
 pub struct ErrorEvent {
     event: Event,
@@ -98,6 +108,15 @@ impl ErrorEvent {
         Ok(event)
     }
 
+    /// Fires a cancelable `error` event describing `error` at `target`. Returns false if the
+    /// event was canceled, i.e. the error was handled.
+    pub fn dispatch(target: JSRef<EventTarget>, global: &GlobalRef, error: &ErrorInfo) -> bool {
+        let errorevent = ErrorEvent::new(global, "error".to_string(), false, true,
+                                         error.message.clone(), error.filename.clone(),
+                                         error.lineno, error.column, NullValue()).root();
+        let event: JSRef<Event> = EventCast::from_ref(*errorevent);
+        target.dispatch_event_with_target(None, event).unwrap()
+    }
 }
 
 impl<'a> ErrorEventMethods for JSRef<'a, ErrorEvent> {
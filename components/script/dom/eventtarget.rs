@@ -37,7 +37,9 @@ pub enum ListenerPhase {
 #[deriving(PartialEq)]
 #[jstraceable]
 pub enum EventTargetTypeId {
+    MessagePortTypeId,
     NodeTargetTypeId(NodeTypeId),
+    SharedWorkerTypeId,
     WebSocketTypeId,
     WindowTypeId,
     WorkerTypeId,
@@ -0,0 +1,239 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::cell::DOMRefCell;
+use dom::bindings::codegen::Bindings::HeadersBinding;
+use dom::bindings::codegen::Bindings::HeadersBinding::HeadersMethods;
+use dom::bindings::error::{ErrorResult, Fallible, Type};
+use dom::bindings::global::GlobalRef;
+use dom::bindings::js::{JSRef, Temporary};
+use dom::bindings::str::ByteString;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+
+use hyper::header::Headers as HeaderMap;
+
+use std::cell::Cell;
+
+/// The [guard](http://fetch.spec.whatwg.org/#concept-headers-guard) of a `Headers` object.
+#[deriving(PartialEq)]
+#[jstraceable]
+pub enum Guard {
+    ImmutableGuard,
+    RequestGuard,
+    RequestNoCORSGuard,
+    ResponseGuard,
+    NoGuard,
+}
+
+#[dom_struct]
+pub struct Headers {
+    reflector_: Reflector,
+    guard: Cell<Guard>,
+    header_list: DOMRefCell<Vec<(ByteString, ByteString)>>,
+}
+
+impl Headers {
+    fn new_inherited(guard: Guard) -> Headers {
+        Headers {
+            reflector_: Reflector::new(),
+            guard: Cell::new(guard),
+            header_list: DOMRefCell::new(vec!()),
+        }
+    }
+
+    pub fn new(global: GlobalRef, guard: Guard) -> Temporary<Headers> {
+        reflect_dom_object(box Headers::new_inherited(guard), global, HeadersBinding::Wrap)
+    }
+
+    /// Creates a `Headers` object with the headers of a response, which can't be modified.
+    pub fn from_response_headers(global: GlobalRef, headers: &HeaderMap) -> Temporary<Headers> {
+        let result = Headers::new(global, ImmutableGuard).root();
+        {
+            let mut header_list = result.header_list.borrow_mut();
+            for header in headers.iter() {
+                header_list.push((ByteString::new(header.name().as_bytes().to_vec()),
+                                  ByteString::new(header.value_string().into_bytes())));
+            }
+        }
+        Temporary::from_rooted(*result)
+    }
+
+    pub fn Constructor(global: &GlobalRef, init: Option<JSRef<Headers>>)
+                       -> Fallible<Temporary<Headers>> {
+        let headers = Headers::new(*global, NoGuard).root();
+        match init {
+            Some(init) => try!(headers.fill(init)),
+            None => ()
+        }
+        Ok(Temporary::from_rooted(*headers))
+    }
+}
+
+/// Whether `name` is a [forbidden header name]
+/// (http://fetch.spec.whatwg.org/#forbidden-header-name), which scripts can't set on requests.
+pub fn is_forbidden_header_name(name: &ByteString) -> bool {
+    let name = name.to_lower();
+    match name.as_str() {
+        Some("accept-charset") | Some("accept-encoding") |
+        Some("access-control-request-headers") | Some("access-control-request-method") |
+        Some("connection") | Some("content-length") | Some("cookie") | Some("cookie2") |
+        Some("date") | Some("dnt") | Some("expect") | Some("host") | Some("keep-alive") |
+        Some("origin") | Some("referer") | Some("te") | Some("trailer") |
+        Some("transfer-encoding") | Some("upgrade") | Some("user-agent") | Some("via") => true,
+        Some(name) => name.starts_with("proxy-") || name.starts_with("sec-"),
+        None => false
+    }
+}
+
+/// Whether `name` is a [forbidden response header name]
+/// (http://fetch.spec.whatwg.org/#forbidden-response-header-name).
+fn is_forbidden_response_header_name(name: &ByteString) -> bool {
+    match name.to_lower().as_str() {
+        Some("set-cookie") | Some("set-cookie2") => true,
+        _ => false
+    }
+}
+
+/// Whether the header is a [simple header](http://fetch.spec.whatwg.org/#simple-header), which
+/// requests that don't use CORS can have.
+fn is_simple_header(name: &ByteString, value: &ByteString) -> bool {
+    match name.to_lower().as_str() {
+        Some("accept") | Some("accept-language") | Some("content-language") => true,
+        Some("content-type") => {
+            let value = value.to_lower();
+            match value.as_str().map(|value| value.split(';').next().unwrap().trim()) {
+                Some("application/x-www-form-urlencoded") | Some("multipart/form-data") |
+                Some("text/plain") => true,
+                _ => false
+            }
+        }
+        _ => false
+    }
+}
+
+pub trait HeadersHelpers {
+    /// Appends the headers of `other`, as the [fill]
+    /// (http://fetch.spec.whatwg.org/#concept-headers-fill) algorithm does.
+    fn fill(self, other: JSRef<Headers>) -> ErrorResult;
+    fn set_guard(self, guard: Guard);
+    /// The headers, combining the values of headers with the same name.
+    fn to_header_map(self) -> HeaderMap;
+}
+
+impl<'a> HeadersHelpers for JSRef<'a, Headers> {
+    fn fill(self, other: JSRef<Headers>) -> ErrorResult {
+        let header_list = other.header_list.borrow().clone();
+        for &(ref name, ref value) in header_list.iter() {
+            try!(self.Append(name.clone(), value.clone()));
+        }
+        Ok(())
+    }
+
+    fn set_guard(self, guard: Guard) {
+        self.guard.set(guard);
+    }
+
+    fn to_header_map(self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for &(ref name, ref value) in self.header_list.borrow().iter() {
+            let name = name.as_str().unwrap().to_string();
+            let combined = match headers.get_raw(name.as_slice()) {
+                Some(values) => {
+                    let mut combined = values[0].clone();
+                    combined.push_all(b", ");
+                    combined.push_all(value.as_slice());
+                    combined
+                }
+                None => value.as_slice().to_vec()
+            };
+            headers.set_raw(name, vec![combined]);
+        }
+        headers
+    }
+}
+
+trait PrivateHeadersHelpers {
+    /// Checks that `name` and `value` are a valid header, and that this object can be modified.
+    /// Returns whether the guard ignores modifications of the header.
+    fn check_modification(self, name: &ByteString, value: &ByteString) -> Fallible<bool>;
+}
+
+impl<'a> PrivateHeadersHelpers for JSRef<'a, Headers> {
+    fn check_modification(self, name: &ByteString, value: &ByteString) -> Fallible<bool> {
+        if !name.is_token() || !value.is_field_value() {
+            return Err(Type("Invalid header name or value"));
+        }
+        Ok(match self.guard.get() {
+            ImmutableGuard => return Err(Type("The headers can't be modified")),
+            RequestGuard => is_forbidden_header_name(name),
+            RequestNoCORSGuard => !is_simple_header(name, value),
+            ResponseGuard => is_forbidden_response_header_name(name),
+            NoGuard => false,
+        })
+    }
+}
+
+impl<'a> HeadersMethods for JSRef<'a, Headers> {
+    // http://fetch.spec.whatwg.org/#dom-headers-append
+    fn Append(self, name: ByteString, value: ByteString) -> ErrorResult {
+        if !try!(self.check_modification(&name, &value)) {
+            self.header_list.borrow_mut().push((name, value));
+        }
+        Ok(())
+    }
+
+    // http://fetch.spec.whatwg.org/#dom-headers-delete
+    fn Delete(self, name: ByteString) -> ErrorResult {
+        let value = ByteString::new(b"invalid".to_vec());
+        if !try!(self.check_modification(&name, &value)) {
+            self.header_list.borrow_mut().retain(|&(ref other, _)| !other.eq_ignore_case(&name));
+        }
+        Ok(())
+    }
+
+    // http://fetch.spec.whatwg.org/#dom-headers-get
+    fn Get(self, name: ByteString) -> Fallible<Option<ByteString>> {
+        if !name.is_token() {
+            return Err(Type("Invalid header name"));
+        }
+        Ok(self.header_list.borrow().iter().find(|&&(ref other, _)| {
+            other.eq_ignore_case(&name)
+        }).map(|&(_, ref value)| value.clone()))
+    }
+
+    // http://fetch.spec.whatwg.org/#dom-headers-has
+    fn Has(self, name: ByteString) -> Fallible<bool> {
+        if !name.is_token() {
+            return Err(Type("Invalid header name"));
+        }
+        Ok(self.header_list.borrow().iter().any(|&(ref other, _)| other.eq_ignore_case(&name)))
+    }
+
+    // http://fetch.spec.whatwg.org/#dom-headers-set
+    fn Set(self, name: ByteString, value: ByteString) -> ErrorResult {
+        if try!(self.check_modification(&name, &value)) {
+            return Ok(());
+        }
+        let mut header_list = self.header_list.borrow_mut();
+        match header_list.iter().position(|&(ref other, _)| other.eq_ignore_case(&name)) {
+            Some(index) => {
+                // Replace the first header with that name, and remove the others.
+                header_list.as_mut_slice()[index] = (name.clone(), value);
+                let mut position = 0u;
+                header_list.retain(|&(ref other, _)| {
+                    position += 1;
+                    position - 1 <= index || !other.eq_ignore_case(&name)
+                });
+            }
+            None => header_list.push((name, value))
+        }
+        Ok(())
+    }
+}
+
+impl Reflectable for Headers {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+}
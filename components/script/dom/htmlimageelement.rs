@@ -8,26 +8,34 @@ use dom::bindings::cell::DOMRefCell;
 use dom::bindings::codegen::Bindings::HTMLImageElementBinding;
 use dom::bindings::codegen::Bindings::HTMLImageElementBinding::HTMLImageElementMethods;
 use dom::bindings::codegen::InheritTypes::{NodeCast, ElementCast, HTMLElementCast, HTMLImageElementDerived};
-use dom::bindings::js::{JS, JSRef, Temporary};
+use dom::bindings::codegen::InheritTypes::HTMLSourceElementCast;
+use dom::bindings::js::{JS, JSRef, Temporary, OptionalRootable};
 use dom::bindings::utils::{Reflectable, Reflector};
 use dom::document::{Document, DocumentHelpers};
-use dom::element::{Element, HTMLImageElementTypeId};
+use dom::element::{Element, HTMLImageElementTypeId, HTMLPictureElementTypeId};
 use dom::element::AttributeHandlers;
 use dom::eventtarget::{EventTarget, NodeTargetTypeId};
 use dom::htmlelement::HTMLElement;
 use dom::node::{Node, ElementNodeTypeId, NodeHelpers, window_from_node};
 use dom::virtualmethods::VirtualMethods;
 use servo_net::image_cache_task;
-use servo_util::geometry::to_px;
-use servo_util::str::DOMString;
+use servo_util::geometry::{to_frac_px, to_px};
+use servo_util::str::{DOMString, HTML_SPACE_CHARACTERS, split_html_space_chars};
+use std::ascii::AsciiExt;
+use std::cell::Cell;
+use std::str::CharRange;
 use string_cache::Atom;
+use style::{Device, Screen, parse_media_query_list_from_str, parse_source_size_list};
 
 use url::{Url, UrlParser};
 
 #[dom_struct]
 pub struct HTMLImageElement {
     htmlelement: HTMLElement,
+    /// The URL of the selected image source.
     image: DOMRefCell<Option<Url>>,
+    /// The pixel density of the selected image source, which scales its intrinsic size.
+    image_density: Cell<f64>,
 }
 
 impl HTMLImageElementDerived for EventTarget {
@@ -36,33 +44,294 @@ impl HTMLImageElementDerived for EventTarget {
     }
 }
 
+/// An image source from a `srcset` or `src` attribute, along with its descriptor.
+struct ImageCandidate {
+    url: DOMString,
+    descriptor: ImageCandidateDescriptor,
+}
+
+enum ImageCandidateDescriptor {
+    /// The pixel density of the image, e.g. `2x`.
+    DensityDescriptor(f64),
+    /// The width of the image in pixels, e.g. `800w`.
+    WidthDescriptor(u32),
+}
+
+/// Parses the descriptors of an image candidate. Returns `None` if they are invalid.
+fn parse_image_candidate_descriptors(descriptors: &str) -> Option<ImageCandidateDescriptor> {
+    let mut density = None;
+    let mut width = None;
+    let mut has_height = false;
+    for descriptor in split_html_space_chars(descriptors) {
+        // The descriptor is never empty, but its last character may be more than a byte long.
+        let CharRange { ch: suffix, next: value_end } =
+            descriptor.char_range_at_reverse(descriptor.len());
+        let value = descriptor.slice_to(value_end);
+        match suffix {
+            'x' if density.is_none() && width.is_none() => {
+                match from_str::<f64>(value) {
+                    Some(value) if value > 0. => density = Some(value),
+                    _ => return None,
+                }
+            }
+            'w' if density.is_none() && width.is_none() => {
+                match from_str::<u32>(value) {
+                    Some(value) if value > 0 => width = Some(value),
+                    _ => return None,
+                }
+            }
+            // Heights are only allowed along with widths, and are ignored.
+            'h' if !has_height && density.is_none() => {
+                match from_str::<u32>(value) {
+                    Some(value) if value > 0 => has_height = true,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        }
+    }
+    match (density, width) {
+        (_, Some(width)) => Some(WidthDescriptor(width)),
+        (Some(density), None) if !has_height => Some(DensityDescriptor(density)),
+        (None, None) if !has_height => Some(DensityDescriptor(1.)),
+        _ => None,
+    }
+}
+
+fn is_html_space(c: char) -> bool {
+    HTML_SPACE_CHARACTERS.contains(&c)
+}
+
+/// http://www.whatwg.org/html/#parse-a-srcset-attribute
+fn parse_srcset(input: &str) -> Vec<ImageCandidate> {
+    let mut candidates = vec!();
+    let mut remaining = input;
+    loop {
+        remaining = remaining.trim_left_chars(|c: char| is_html_space(c) || c == ',');
+        if remaining.is_empty() {
+            return candidates
+        }
+
+        let url_end = remaining.find(is_html_space).unwrap_or(remaining.len());
+        let url = remaining.slice_to(url_end);
+        remaining = remaining.slice_from(url_end);
+
+        // A URL that ends in a comma has no descriptors. Otherwise, they run up to the next
+        // comma that isn't inside parentheses.
+        let descriptors = if url.ends_with(",") {
+            ""
+        } else {
+            let mut in_parentheses = false;
+            let descriptors_end = remaining.find(|c: char| {
+                match c {
+                    '(' => in_parentheses = true,
+                    ')' => in_parentheses = false,
+                    _ => {}
+                }
+                c == ',' && !in_parentheses
+            }).unwrap_or(remaining.len());
+            let descriptors = remaining.slice_to(descriptors_end);
+            remaining = remaining.slice_from(descriptors_end);
+            descriptors
+        };
+
+        match parse_image_candidate_descriptors(descriptors) {
+            Some(descriptor) => {
+                candidates.push(ImageCandidate {
+                    url: url.trim_right_chars(',').to_string(),
+                    descriptor: descriptor,
+                })
+            }
+            None => debug!("ignoring invalid image candidate {:s}", url),
+        }
+    }
+}
+
+/// Picks the candidate whose density best fits the device: the least dense one that is at least
+/// as dense as the device, or the densest one if there is none. Returns its URL and density.
+fn select_image_candidate(candidates: Vec<ImageCandidate>, sizes: &str, device: &Device,
+                          device_pixel_ratio: f64)
+                          -> Option<(DOMString, f64)> {
+    let source_size = to_frac_px(parse_source_size_list(sizes).evaluate(device));
+    let candidates = candidates.into_iter().map(|candidate| {
+        let density = match candidate.descriptor {
+            DensityDescriptor(density) => density,
+            WidthDescriptor(width) if source_size > 0. => width as f64 / source_size,
+            WidthDescriptor(..) => 1.,
+        };
+        (candidate.url, density)
+    }).collect::<Vec<(DOMString, f64)>>();
+
+    let mut best: Option<&(DOMString, f64)> = None;
+    for candidate in candidates.iter() {
+        let &(_, density) = candidate;
+        best = match best {
+            None => Some(candidate),
+            Some(&(_, best_density)) => {
+                let is_better = if best_density >= device_pixel_ratio {
+                    density >= device_pixel_ratio && density < best_density
+                } else {
+                    density > best_density
+                };
+                if is_better { Some(candidate) } else { best }
+            }
+        };
+    }
+    best.map(|&(ref url, density)| (url.clone(), density))
+}
+
+/// Whether a `type` attribute of a `source` element names an image format that can be decoded.
+fn is_supported_image_type(mime_type: &str) -> bool {
+    match mime_type.trim_chars(HTML_SPACE_CHARACTERS).to_ascii_lower().as_slice() {
+        "image/png" | "image/apng" | "image/jpeg" | "image/gif" | "image/webp" | "image/bmp" => {
+            true
+        }
+        _ => false,
+    }
+}
+
+/// An attribute that is about to be removed from an element, and so is treated as absent.
+pub type RemovedAttribute<'a, 'b> = Option<(JSRef<'a, Element>, &'b Atom)>;
+
+/// Returns the value of an attribute, or `None` if it is absent or about to be removed.
+fn attribute_value(element: JSRef<Element>, name: &Atom, removed_attribute: RemovedAttribute)
+                   -> Option<DOMString> {
+    match removed_attribute {
+        Some((removed_from, removed_name)) if removed_from == element && removed_name == name => {
+            return None
+        }
+        _ => {}
+    }
+    if element.has_attribute(name) {
+        Some(element.get_string_attribute(name))
+    } else {
+        None
+    }
+}
+
+pub trait HTMLImageElementHelpers {
+    fn update_the_image_data(self, removed_attribute: RemovedAttribute);
+}
+
 trait PrivateHTMLImageElementHelpers {
-    fn update_image(self, value: Option<(DOMString, &Url)>);
+    fn select_image_source(self, device: &Device, device_pixel_ratio: f64,
+                           removed_attribute: RemovedAttribute)
+                           -> Option<(DOMString, f64)>;
 }
 
 impl<'a> PrivateHTMLImageElementHelpers for JSRef<'a, HTMLImageElement> {
-    /// Makes the local `image` member match the status of the `src` attribute and starts
-    /// prefetching the image. This method must be called after `src` is changed.
-    fn update_image(self, value: Option<(DOMString, &Url)>) {
+    /// http://www.whatwg.org/html/#select-an-image-source
+    fn select_image_source(self, device: &Device, device_pixel_ratio: f64,
+                           removed_attribute: RemovedAttribute)
+                           -> Option<(DOMString, f64)> {
+        let attribute = |element: JSRef<Element>, name: &Atom| {
+            attribute_value(element, name, removed_attribute).unwrap_or(String::new())
+        };
+        let node: JSRef<Node> = NodeCast::from_ref(self);
+
+        // The `source` elements before the image in a `picture` element come first.
+        let parent = node.parent_node().root();
+        match parent {
+            Some(ref parent) if *parent.type_id() ==
+                    ElementNodeTypeId(HTMLPictureElementTypeId) => {
+                for child in parent.children() {
+                    if child == node {
+                        break
+                    }
+                    if HTMLSourceElementCast::to_ref(child).is_none() {
+                        continue
+                    }
+                    let source: JSRef<Element> = ElementCast::to_ref(child).unwrap();
+                    match attribute_value(source, &atom!("media"), removed_attribute) {
+                        Some(ref media) if !parse_media_query_list_from_str(
+                                media.as_slice()).evaluate(device) => continue,
+                        _ => {}
+                    }
+                    match attribute_value(source, &atom!("type"), removed_attribute) {
+                        Some(ref mime_type) if !is_supported_image_type(
+                                mime_type.as_slice()) => continue,
+                        _ => {}
+                    }
+                    let srcset = attribute(source, &Atom::from_slice("srcset"));
+                    let candidates = parse_srcset(srcset.as_slice());
+                    if candidates.is_empty() {
+                        continue
+                    }
+                    let sizes = attribute(source, &Atom::from_slice("sizes"));
+                    return select_image_candidate(candidates, sizes.as_slice(), device,
+                                                  device_pixel_ratio)
+                }
+            }
+            _ => {}
+        }
+
+        // Then the image's own `srcset`, with `src` as a 1x candidate unless there already is
+        // one.
+        let element: JSRef<Element> = ElementCast::from_ref(self);
+        let srcset = attribute(element, &Atom::from_slice("srcset"));
+        let mut candidates = parse_srcset(srcset.as_slice());
+        let src = attribute(element, &atom!("src"));
+        let has_1x_candidate = candidates.iter().any(|candidate| {
+            match candidate.descriptor {
+                DensityDescriptor(density) => density == 1.,
+                WidthDescriptor(..) => false,
+            }
+        });
+        if !src.is_empty() && !has_1x_candidate {
+            candidates.push(ImageCandidate {
+                url: src,
+                descriptor: DensityDescriptor(1.),
+            });
+        }
+        let sizes = attribute(element, &Atom::from_slice("sizes"));
+        select_image_candidate(candidates, sizes.as_slice(), device, device_pixel_ratio)
+    }
+}
+
+impl<'a> HTMLImageElementHelpers for JSRef<'a, HTMLImageElement> {
+    /// Selects the image source that suits the viewport and the device pixel ratio best, and
+    /// starts prefetching it if it has changed. This method must be called whenever the
+    /// attributes of the image or of the `source` elements before it in a `picture` element
+    /// change, and whenever the viewport or the zoom changes.
+    fn update_the_image_data(self, removed_attribute: RemovedAttribute) {
         let node: JSRef<Node> = NodeCast::from_ref(self);
-        let document = node.owner_doc().root();
-        let window = document.window().root();
-        let image_cache = window.image_cache_task();
-        match value {
-            None => {
-                *self.image.borrow_mut() = None;
+        let window = window_from_node(self).root();
+        let window_size = window.page().window_size.get();
+        let device = Device::new(Screen, window_size.initial_viewport);
+        let device_pixel_ratio = window_size.device_pixel_ratio.get() as f64;
+
+        let base_url = window.get_url();
+        let selected = self.select_image_source(&device, device_pixel_ratio, removed_attribute);
+        let (image, density) = match selected {
+            Some((src, density)) => {
+                let image = UrlParser::new().base_url(&base_url).parse(src.as_slice()).ok();
+                if image.is_none() {
+                    debug!("failed to parse image URL {:s}", src);
+                }
+                (image, density)
             }
-            Some((src, base_url)) => {
-                let img_url = UrlParser::new().base_url(base_url).parse(src.as_slice());
-                // FIXME: handle URL parse errors more gracefully.
-                let img_url = img_url.unwrap();
-                *self.image.borrow_mut() = Some(img_url.clone());
-
-                // inform the image cache to load this, but don't store a
-                // handle.
-                image_cache.send(image_cache_task::Prefetch(img_url));
+            None => (None, 1.),
+        };
+
+        if *self.image.borrow() == image && self.image_density.get() == density {
+            return
+        }
+        self.image_density.set(density);
+        if *self.image.borrow() != image {
+            *self.image.borrow_mut() = image.clone();
+            match image {
+                Some(image) => {
+                    // inform the image cache to load this, but don't store a
+                    // handle.
+                    window.image_cache_task().send(image_cache_task::Prefetch(image));
+                }
+                None => {}
             }
         }
+        if node.is_in_doc() {
+            let document = node.owner_doc().root();
+            document.content_changed(node);
+        }
     }
 }
 
@@ -71,6 +340,7 @@ impl HTMLImageElement {
         HTMLImageElement {
             htmlelement: HTMLElement::new_inherited(HTMLImageElementTypeId, localName, prefix, document),
             image: DOMRefCell::new(None),
+            image_density: Cell::new(1.),
         }
     }
 
@@ -83,12 +353,17 @@ impl HTMLImageElement {
 
 pub trait LayoutHTMLImageElementHelpers {
     unsafe fn image(&self) -> Option<Url>;
+    unsafe fn image_density(&self) -> f64;
 }
 
 impl LayoutHTMLImageElementHelpers for JS<HTMLImageElement> {
     unsafe fn image(&self) -> Option<Url> {
         (*self.unsafe_get()).image.borrow_for_layout().clone()
     }
+
+    unsafe fn image_density(&self) -> f64 {
+        (*self.unsafe_get()).image_density.get()
+    }
 }
 
 impl<'a> HTMLImageElementMethods for JSRef<'a, HTMLImageElement> {
@@ -100,6 +375,18 @@ impl<'a> HTMLImageElementMethods for JSRef<'a, HTMLImageElement> {
 
     make_setter!(SetSrc, "src")
 
+    make_getter!(Srcset)
+
+    make_setter!(SetSrcset, "srcset")
+
+    make_getter!(Sizes)
+
+    make_setter!(SetSizes, "sizes")
+
+    fn CurrentSrc(self) -> DOMString {
+        self.image.borrow().as_ref().map_or(String::new(), |url| url.serialize())
+    }
+
     make_getter!(UseMap)
 
     make_setter!(SetUseMap, "usemap")
@@ -170,12 +457,8 @@ impl<'a> VirtualMethods for JSRef<'a, HTMLImageElement> {
             _ => ()
         }
 
-        match attr.local_name() {
-            &atom!("src") => {
-                let window = window_from_node(*self).root();
-                let url = window.get_url();
-                self.update_image(Some((attr.value().as_slice().to_string(), &url)));
-            },
+        match attr.local_name().as_slice() {
+            "src" | "srcset" | "sizes" => self.update_the_image_data(None),
             _ => ()
         }
     }
@@ -186,8 +469,11 @@ impl<'a> VirtualMethods for JSRef<'a, HTMLImageElement> {
             _ => ()
         }
 
-        match attr.local_name() {
-            &atom!("src") => self.update_image(None),
+        match attr.local_name().as_slice() {
+            "src" | "srcset" | "sizes" => {
+                let element: JSRef<Element> = ElementCast::from_ref(*self);
+                self.update_the_image_data(Some((element, attr.local_name())))
+            }
             _ => ()
         }
     }
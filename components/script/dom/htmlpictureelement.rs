@@ -0,0 +1,100 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::Bindings::HTMLPictureElementBinding;
+use dom::bindings::codegen::InheritTypes::{HTMLElementCast, HTMLImageElementCast, NodeCast};
+use dom::bindings::codegen::InheritTypes::{HTMLPictureElementDerived, HTMLSourceElementCast};
+use dom::bindings::js::{JSRef, Temporary};
+use dom::bindings::utils::{Reflectable, Reflector};
+use dom::document::Document;
+use dom::element::HTMLPictureElementTypeId;
+use dom::eventtarget::{EventTarget, NodeTargetTypeId};
+use dom::htmlelement::HTMLElement;
+use dom::htmlimageelement::{HTMLImageElementHelpers, RemovedAttribute};
+use dom::node::{Node, NodeHelpers, ElementNodeTypeId};
+use dom::virtualmethods::VirtualMethods;
+use servo_util::str::DOMString;
+
+#[dom_struct]
+pub struct HTMLPictureElement {
+    htmlelement: HTMLElement
+}
+
+impl HTMLPictureElementDerived for EventTarget {
+    fn is_htmlpictureelement(&self) -> bool {
+        *self.type_id() == NodeTargetTypeId(ElementNodeTypeId(HTMLPictureElementTypeId))
+    }
+}
+
+impl HTMLPictureElement {
+    fn new_inherited(localName: DOMString, prefix: Option<DOMString>, document: JSRef<Document>) -> HTMLPictureElement {
+        HTMLPictureElement {
+            htmlelement: HTMLElement::new_inherited(HTMLPictureElementTypeId, localName, prefix, document)
+        }
+    }
+
+    #[allow(unrooted_must_root)]
+    pub fn new(localName: DOMString, prefix: Option<DOMString>, document: JSRef<Document>) -> Temporary<HTMLPictureElement> {
+        let element = HTMLPictureElement::new_inherited(localName, prefix, document);
+        Node::reflect_node(box element, document, HTMLPictureElementBinding::Wrap)
+    }
+}
+
+pub trait HTMLPictureElementHelpers {
+    fn update_images(self, removed_attribute: RemovedAttribute);
+}
+
+impl<'a> HTMLPictureElementHelpers for JSRef<'a, HTMLPictureElement> {
+    /// Selects the image sources of the images in this picture again. This method must be called
+    /// whenever the `source` elements in this picture change.
+    fn update_images(self, removed_attribute: RemovedAttribute) {
+        let node: JSRef<Node> = NodeCast::from_ref(self);
+        for child in node.children() {
+            match HTMLImageElementCast::to_ref(child) {
+                Some(image) => image.update_the_image_data(removed_attribute),
+                None => {}
+            }
+        }
+    }
+}
+
+impl<'a> VirtualMethods for JSRef<'a, HTMLPictureElement> {
+    fn super_type<'a>(&'a self) -> Option<&'a VirtualMethods> {
+        let htmlelement: &JSRef<HTMLElement> = HTMLElementCast::from_borrowed_ref(self);
+        Some(htmlelement as &VirtualMethods)
+    }
+
+    fn child_inserted(&self, child: JSRef<Node>) {
+        match self.super_type() {
+            Some(ref s) => s.child_inserted(child),
+            _ => (),
+        }
+
+        match HTMLImageElementCast::to_ref(child) {
+            Some(image) => image.update_the_image_data(None),
+            None if HTMLSourceElementCast::to_ref(child).is_some() => self.update_images(None),
+            None => {}
+        }
+    }
+
+    fn child_removed(&self, child: JSRef<Node>) {
+        match self.super_type() {
+            Some(ref s) => s.child_removed(child),
+            _ => (),
+        }
+
+        // An image that leaves the picture no longer uses its sources.
+        match HTMLImageElementCast::to_ref(child) {
+            Some(image) => image.update_the_image_data(None),
+            None if HTMLSourceElementCast::to_ref(child).is_some() => self.update_images(None),
+            None => {}
+        }
+    }
+}
+
+impl Reflectable for HTMLPictureElement {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        self.htmlelement.reflector()
+    }
+}
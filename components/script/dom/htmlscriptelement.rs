@@ -6,30 +6,53 @@ use std::ascii::AsciiExt;
 
 use dom::attr::Attr;
 use dom::attr::AttrHelpers;
+use dom::bindings::cell::DOMRefCell;
 use dom::bindings::codegen::Bindings::AttrBinding::AttrMethods;
+use dom::bindings::codegen::Bindings::EventTargetBinding::EventTargetMethods;
 use dom::bindings::codegen::Bindings::HTMLScriptElementBinding;
 use dom::bindings::codegen::Bindings::HTMLScriptElementBinding::HTMLScriptElementMethods;
 use dom::bindings::codegen::Bindings::NodeBinding::NodeMethods;
 use dom::bindings::codegen::InheritTypes::{HTMLScriptElementDerived, HTMLScriptElementCast};
-use dom::bindings::codegen::InheritTypes::{ElementCast, HTMLElementCast, NodeCast};
+use dom::bindings::codegen::InheritTypes::{ElementCast, EventTargetCast, HTMLElementCast};
+use dom::bindings::codegen::InheritTypes::NodeCast;
+use dom::bindings::global;
 use dom::bindings::js::{JSRef, Temporary, OptionalRootable};
 use dom::bindings::utils::{Reflectable, Reflector};
-use dom::document::Document;
+use dom::document::{Document, DocumentHelpers};
 use dom::element::{HTMLScriptElementTypeId, Element, AttributeHandlers};
 use dom::element::{ElementCreator, ParserCreated};
+use dom::event::{Event, DoesNotBubble, NotCancelable};
 use dom::eventtarget::{EventTarget, NodeTargetTypeId};
 use dom::htmlelement::HTMLElement;
-use dom::node::{Node, NodeHelpers, ElementNodeTypeId, window_from_node, CloneChildrenFlag};
+use dom::node::{Node, NodeHelpers, ElementNodeTypeId, window_from_node, document_from_node};
+use dom::node::CloneChildrenFlag;
 use dom::virtualmethods::VirtualMethods;
 use dom::window::WindowHelpers;
+use script_task::{ScriptChan, ScriptLoadedMsg};
 
 use encoding::all::UTF_8;
 use encoding::types::{Encoding, DecodeReplace};
+use servo_net::fetch::request::Script as ScriptContext;
 use servo_net::resource_task::load_whole_resource;
 use servo_util::str::{DOMString, HTML_SPACE_CHARACTERS, StaticStringVec};
+use servo_util::task::spawn_named;
 use std::cell::Cell;
+use std::comm::{channel, Empty, Disconnected};
 use string_cache::Atom;
-use url::UrlParser;
+use url::{Url, UrlParser};
+
+/// The result of fetching an external script: its source and its final URL.
+pub type ScriptResult = Result<(DOMString, Url), String>;
+
+/// The source of an external script, which is fetched in parallel with parsing.
+pub enum ScriptLoad {
+    /// The fetch is still in progress.
+    Fetching(Receiver<ScriptResult>),
+    /// The script is ready (https://whatwg.org/html/#the-script-is-ready).
+    Loaded(ScriptResult),
+}
+
+no_jsmanaged_fields!(ScriptLoad)
 
 #[dom_struct]
 pub struct HTMLScriptElement {
@@ -42,14 +65,13 @@ pub struct HTMLScriptElement {
     parser_inserted: Cell<bool>,
 
     /// https://html.spec.whatwg.org/multipage/scripting.html#non-blocking
-    ///
-    /// (currently unused)
     non_blocking: Cell<bool>,
 
     /// https://html.spec.whatwg.org/multipage/scripting.html#ready-to-be-parser-executed
-    ///
-    /// (currently unused)
     ready_to_be_parser_executed: Cell<bool>,
+
+    /// The fetch of the script's source, if it is an external script that hasn't been executed.
+    load: DOMRefCell<Option<ScriptLoad>>,
 }
 
 impl HTMLScriptElementDerived for EventTarget {
@@ -67,6 +89,7 @@ impl HTMLScriptElement {
             parser_inserted: Cell::new(creator == ParserCreated),
             non_blocking: Cell::new(creator != ParserCreated),
             ready_to_be_parser_executed: Cell::new(false),
+            load: DOMRefCell::new(None),
         }
     }
 
@@ -87,6 +110,28 @@ pub trait HTMLScriptElementHelpers {
 
     /// Set the "already started" flag (<https://whatwg.org/html/#already-started>)
     fn mark_already_started(self);
+
+    /// Start fetching the script's source in parallel, for `execute` to run once it's ready.
+    fn fetch(self, url: Url);
+
+    /// Whether an external script's source has been fetched, without waiting for it.
+    fn is_ready(self) -> bool;
+
+    /// Block until an external script's source has been fetched.
+    fn wait_until_ready(self);
+
+    /// Execute a fetched external script (<https://whatwg.org/html/#execute-the-script-block>)
+    fn execute(self);
+
+    /// Run a script's source, which comes from `url`, and fire `load` at external scripts.
+    fn run(self, source: DOMString, url: Url, external: bool);
+
+    /// Fire a simple event at the element.
+    fn dispatch_simple_event(self, name: &str);
+
+    /// Unset the "already started" and "parser-inserted" flags, so that the script runs when it
+    /// is inserted into a document.
+    fn unmark_already_started_and_parser_inserted(self);
 }
 
 /// Supported script types as defined by
@@ -165,14 +210,12 @@ impl<'a> HTMLScriptElementHelpers for JSRef<'a, HTMLScriptElement> {
         // character encoding for this script element be the result of getting an encoding from the
         // value of the `charset` attribute.
 
-        // Step 14 and 15.
-        // TODO: Add support for the `defer` and `async` attributes.  (For now, we fetch all
-        // scripts synchronously and execute them immediately.)
+        // Step 14.
         let window = window_from_node(self).root();
         let page = window.page();
         let base_url = page.get_url();
 
-        let (source, url) = match element.get_attribute(ns!(""), &atom!("src")).root() {
+        let external = match element.get_attribute(ns!(""), &atom!("src")).root() {
             Some(src) => {
                 if src.deref().Value().is_empty() {
                     // TODO: queue a task to fire a simple event named `error` at the element
@@ -184,17 +227,8 @@ impl<'a> HTMLScriptElementHelpers for JSRef<'a, HTMLScriptElement> {
                         // state of the element's `crossorigin` content attribute, the origin being
                         // the origin of the script element's node document, and the default origin
                         // behaviour set to taint.
-                        match load_whole_resource(&page.resource_task, url) {
-                            Ok((metadata, bytes)) => {
-                                // TODO: use the charset from step 13.
-                                let source = UTF_8.decode(bytes.as_slice(), DecodeReplace).unwrap();
-                                (source, metadata.final_url)
-                            }
-                            Err(_) => {
-                                error!("error loading script {}", src.deref().Value());
-                                return;
-                            }
-                        }
+                        self.fetch(url);
+                        true
                     }
                     Err(_) => {
                         // TODO: queue a task to fire a simple event named `error` at the element
@@ -203,10 +237,117 @@ impl<'a> HTMLScriptElementHelpers for JSRef<'a, HTMLScriptElement> {
                     }
                 }
             }
-            None => (text, base_url)
+            None => false
+        };
+
+        // Step 15.
+        let document = document_from_node(self).root();
+        let is_async = element.has_attribute(&atom!("async"));
+        let is_defer = element.has_attribute(&atom!("defer"));
+        if external && is_defer && was_parser_inserted && !is_async {
+            // First case: run once the document has finished parsing.
+            document.add_deferred_script(self);
+        } else if external && was_parser_inserted && !is_async {
+            // Second case: the pending parsing-blocking script. The parser can't be paused, so it
+            // waits here until the script is ready.
+            self.ready_to_be_parser_executed.set(true);
+            self.wait_until_ready();
+            document.execute_ready_asap_scripts();
+            self.execute();
+        } else if external && !is_async && !self.non_blocking.get() {
+            // Fourth case: run in insertion order as soon as possible.
+            document.add_asap_script(self, true);
+        } else if external {
+            // Fifth case: run as soon as possible.
+            document.add_asap_script(self, false);
+        } else {
+            // Last case: run inline scripts immediately.
+            self.run(text, base_url, false);
+        }
+    }
+
+    fn fetch(self, url: Url) {
+        let window = window_from_node(self).root();
+        let page = window.page();
+        let resource_task = page.resource_task.clone();
+        let ScriptChan(script_chan) = window.script_chan().clone();
+        let pipeline_id = page.id;
+
+        let (result_chan, result_port) = channel();
+        *self.load.borrow_mut() = Some(Fetching(result_port));
+        spawn_named("ScriptFetch", proc() {
+            let result = load_whole_resource(&resource_task, url, ScriptContext);
+            let result = result.map(|(metadata, bytes)| {
+                // TODO: use the charset from step 13.
+                let source = UTF_8.decode(bytes.as_slice(), DecodeReplace).unwrap();
+                (source, metadata.final_url)
+            });
+            if result_chan.send_opt(result).is_ok() {
+                // Let the script task run scripts that were waiting for this one.
+                let _ = script_chan.send_opt(ScriptLoadedMsg(pipeline_id));
+            }
+        });
+    }
+
+    fn is_ready(self) -> bool {
+        let mut load = self.load.borrow_mut();
+        let result = match *load {
+            Some(Fetching(ref port)) => match port.try_recv() {
+                Ok(result) => result,
+                Err(Empty) => return false,
+                Err(Disconnected) => Err("script fetch failed".to_string()),
+            },
+            _ => return true,
+        };
+        *load = Some(Loaded(result));
+        true
+    }
+
+    fn wait_until_ready(self) {
+        let mut load = self.load.borrow_mut();
+        let result = match *load {
+            Some(Fetching(ref port)) => {
+                port.recv_opt().unwrap_or_else(|()| Err("script fetch failed".to_string()))
+            },
+            _ => return,
         };
+        *load = Some(Loaded(result));
+    }
 
+    fn execute(self) {
+        self.ready_to_be_parser_executed.set(false);
+        let load = self.load.borrow_mut().take();
+        match load {
+            Some(Loaded(Ok((source, url)))) => self.run(source, url, true),
+            Some(Loaded(Err(e))) => {
+                // Step 1.
+                error!("error loading script: {}", e);
+                self.dispatch_simple_event("error");
+            },
+            _ => panic!("executed a script that wasn't ready"),
+        }
+    }
+
+    fn run(self, source: DOMString, url: Url, external: bool) {
+        let window = window_from_node(self).root();
+        let document = document_from_node(self).root();
+        // https://html.spec.whatwg.org/multipage/#execute-the-script-block step 4.1
+        if external {
+            document.increment_ignore_destructive_writes_counter();
+        }
         window.evaluate_script_with_result(source.as_slice(), url.serialize().as_slice());
+        if external {
+            document.decrement_ignore_destructive_writes_counter();
+            self.dispatch_simple_event("load");
+        }
+    }
+
+    fn dispatch_simple_event(self, name: &str) {
+        let window = window_from_node(self).root();
+        let event = Event::new(global::Window(*window), name.to_string(),
+                               DoesNotBubble, NotCancelable).root();
+        let target: JSRef<EventTarget> = EventTargetCast::from_ref(self);
+        let _ = target.DispatchEvent(*event);
     }
 
     fn is_javascript(self) -> bool {
@@ -246,6 +387,11 @@ impl<'a> HTMLScriptElementHelpers for JSRef<'a, HTMLScriptElement> {
     fn mark_already_started(self) {
         self.already_started.set(true);
     }
+
+    fn unmark_already_started_and_parser_inserted(self) {
+        self.already_started.set(false);
+        self.parser_inserted.set(false);
+    }
 }
 
 impl<'a> VirtualMethods for JSRef<'a, HTMLScriptElement> {
@@ -307,6 +453,23 @@ impl<'a> HTMLScriptElementMethods for JSRef<'a, HTMLScriptElement> {
 
     make_setter!(SetSrc, "src")
 
+    // https://html.spec.whatwg.org/multipage/scripting.html#dom-script-async
+    fn Async(self) -> bool {
+        let element: JSRef<Element> = ElementCast::from_ref(self);
+        self.non_blocking.get() || element.has_attribute(&atom!("async"))
+    }
+
+    // https://html.spec.whatwg.org/multipage/scripting.html#dom-script-async
+    fn SetAsync(self, value: bool) {
+        self.non_blocking.set(false);
+        let element: JSRef<Element> = ElementCast::from_ref(self);
+        element.set_bool_attribute(&atom!("async"), value)
+    }
+
+    make_bool_getter!(Defer)
+
+    make_bool_setter!(SetDefer, "defer")
+
     // http://www.whatwg.org/html/#dom-script-text
     fn Text(self) -> DOMString {
         let node: JSRef<Node> = NodeCast::from_ref(self);
@@ -2,16 +2,23 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use dom::attr::{Attr, AttrHelpers};
 use dom::bindings::codegen::Bindings::HTMLSourceElementBinding;
-use dom::bindings::codegen::InheritTypes::HTMLSourceElementDerived;
-use dom::bindings::js::{JSRef, Temporary};
+use dom::bindings::codegen::Bindings::HTMLSourceElementBinding::HTMLSourceElementMethods;
+use dom::bindings::codegen::InheritTypes::{ElementCast, HTMLElementCast, HTMLPictureElementCast};
+use dom::bindings::codegen::InheritTypes::{HTMLSourceElementDerived, NodeCast};
+use dom::bindings::js::{JSRef, Temporary, OptionalRootable};
 use dom::bindings::utils::{Reflectable, Reflector};
 use dom::document::Document;
-use dom::element::HTMLSourceElementTypeId;
+use dom::element::{Element, HTMLSourceElementTypeId};
 use dom::eventtarget::{EventTarget, NodeTargetTypeId};
 use dom::htmlelement::HTMLElement;
-use dom::node::{Node, ElementNodeTypeId};
+use dom::htmlimageelement::RemovedAttribute;
+use dom::htmlpictureelement::HTMLPictureElementHelpers;
+use dom::node::{Node, NodeHelpers, ElementNodeTypeId};
+use dom::virtualmethods::VirtualMethods;
 use servo_util::str::DOMString;
+use string_cache::Atom;
 
 #[dom_struct]
 pub struct HTMLSourceElement {
@@ -38,6 +45,74 @@ impl HTMLSourceElement {
     }
 }
 
+trait PrivateHTMLSourceElementHelpers {
+    fn update_picture_images(self, removed_attribute: RemovedAttribute);
+}
+
+impl<'a> PrivateHTMLSourceElementHelpers for JSRef<'a, HTMLSourceElement> {
+    /// Selects the image sources of the images in the parent `picture` element again, if any.
+    fn update_picture_images(self, removed_attribute: RemovedAttribute) {
+        let node: JSRef<Node> = NodeCast::from_ref(self);
+        let parent = node.parent_node().root();
+        match parent.as_ref().and_then(|parent| HTMLPictureElementCast::to_ref(**parent)) {
+            Some(picture) => picture.update_images(removed_attribute),
+            None => {}
+        }
+    }
+}
+
+impl<'a> HTMLSourceElementMethods for JSRef<'a, HTMLSourceElement> {
+    make_getter!(Type)
+
+    make_setter!(SetType, "type")
+
+    make_getter!(Srcset)
+
+    make_setter!(SetSrcset, "srcset")
+
+    make_getter!(Sizes)
+
+    make_setter!(SetSizes, "sizes")
+
+    make_getter!(Media)
+
+    make_setter!(SetMedia, "media")
+}
+
+impl<'a> VirtualMethods for JSRef<'a, HTMLSourceElement> {
+    fn super_type<'a>(&'a self) -> Option<&'a VirtualMethods> {
+        let htmlelement: &JSRef<HTMLElement> = HTMLElementCast::from_borrowed_ref(self);
+        Some(htmlelement as &VirtualMethods)
+    }
+
+    fn after_set_attr(&self, attr: JSRef<Attr>) {
+        match self.super_type() {
+            Some(ref s) => s.after_set_attr(attr),
+            _ => ()
+        }
+
+        match attr.local_name().as_slice() {
+            "srcset" | "sizes" | "media" | "type" => self.update_picture_images(None),
+            _ => ()
+        }
+    }
+
+    fn before_remove_attr(&self, attr: JSRef<Attr>) {
+        match self.super_type() {
+            Some(ref s) => s.before_remove_attr(attr),
+            _ => ()
+        }
+
+        match attr.local_name().as_slice() {
+            "srcset" | "sizes" | "media" | "type" => {
+                let element: JSRef<Element> = ElementCast::from_ref(*self);
+                self.update_picture_images(Some((element, attr.local_name())))
+            }
+            _ => ()
+        }
+    }
+}
+
 impl Reflectable for HTMLSourceElement {
     fn reflector<'a>(&'a self) -> &'a Reflector {
         self.htmlelement.reflector()
@@ -14,6 +14,7 @@ use dom::htmlelement::HTMLElement;
 use dom::node::{Node, NodeHelpers, ElementNodeTypeId, window_from_node};
 use dom::virtualmethods::VirtualMethods;
 use layout_interface::{AddStylesheetMsg, LayoutChan};
+use servo_util::opts;
 use servo_util::str::DOMString;
 use style::{AuthorOrigin, Stylesheet};
 
@@ -55,7 +56,8 @@ impl<'a> StyleElementHelpers for JSRef<'a, HTMLStyleElement> {
         let url = win.page().get_url();
 
         let data = node.GetTextContent().expect("Element.textContent must be a string");
-        let sheet = Stylesheet::from_str(data.as_slice(), url, AuthorOrigin);
+        let keep_source = opts::get().devtools_port.is_some();
+        let sheet = Stylesheet::from_str(data.as_slice(), url, AuthorOrigin, keep_source);
         let LayoutChan(ref layout_chan) = win.page().layout_chan;
         layout_chan.send(AddStylesheetMsg(sheet));
     }
@@ -0,0 +1,115 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::Bindings::ImageDataBinding;
+use dom::bindings::codegen::Bindings::ImageDataBinding::ImageDataMethods;
+use dom::bindings::error::{Fallible, IndexSize};
+use dom::bindings::global::GlobalRef;
+use dom::bindings::js::{JSRef, Temporary};
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+
+use js::jsapi::{JSContext, JSObject, JS_NewUint8ClampedArray, JS_GetUint8ClampedArrayData};
+
+use std::ptr;
+use std::slice;
+
+#[dom_struct]
+pub struct ImageData {
+    reflector_: Reflector,
+    width: u32,
+    height: u32,
+    /// The pixels, as a `Uint8ClampedArray` of RGBA values.
+    data: *mut JSObject,
+}
+
+impl ImageData {
+    fn new_inherited(width: u32, height: u32, data: *mut JSObject) -> ImageData {
+        ImageData {
+            reflector_: Reflector::new(),
+            width: width,
+            height: height,
+            data: data,
+        }
+    }
+
+    /// Creates an image of the given size, with a copy of `pixels` if there are any, or with
+    /// transparent black pixels otherwise. Returns `None` if the image is too big.
+    pub fn new(global: GlobalRef, width: u32, height: u32, pixels: Option<&[u8]>)
+               -> Option<Temporary<ImageData>> {
+        let len = match width.checked_mul(&height).and_then(|area| area.checked_mul(&4)) {
+            Some(len) => len,
+            None => return None,
+        };
+
+        let cx = global.get_cx();
+        let data = unsafe { JS_NewUint8ClampedArray(cx, len) };
+        if data.is_null() {
+            return None;
+        }
+        match pixels {
+            Some(pixels) => unsafe {
+                assert!(pixels.len() == len as uint);
+                let dest = JS_GetUint8ClampedArrayData(data, cx);
+                ptr::copy_nonoverlapping_memory(dest, pixels.as_ptr(), pixels.len());
+            },
+            None => (),
+        }
+
+        Some(reflect_dom_object(box ImageData::new_inherited(width, height, data),
+                                global,
+                                ImageDataBinding::Wrap))
+    }
+
+    // http://www.whatwg.org/html/#dom-imagedata
+    pub fn Constructor(global: &GlobalRef, width: u32, height: u32)
+                       -> Fallible<Temporary<ImageData>> {
+        if width == 0 || height == 0 {
+            return Err(IndexSize);
+        }
+        match ImageData::new(*global, width, height, None) {
+            Some(image) => Ok(image),
+            None => Err(IndexSize),
+        }
+    }
+}
+
+pub trait ImageDataHelpers {
+    fn get_size(self) -> (u32, u32);
+    fn get_pixels(self, cx: *mut JSContext) -> Vec<u8>;
+}
+
+impl<'a> ImageDataHelpers for JSRef<'a, ImageData> {
+    fn get_size(self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// A copy of the RGBA values of the pixels.
+    fn get_pixels(self, cx: *mut JSContext) -> Vec<u8> {
+        let len = (self.width * self.height * 4) as uint;
+        unsafe {
+            let data = JS_GetUint8ClampedArrayData(self.data, cx);
+            slice::raw::buf_as_slice(data as *const u8, len, |pixels| pixels.to_vec())
+        }
+    }
+}
+
+impl<'a> ImageDataMethods for JSRef<'a, ImageData> {
+    fn Width(self) -> u32 {
+        self.width
+    }
+
+    fn Height(self) -> u32 {
+        self.height
+    }
+
+    fn Data(self, _cx: *mut JSContext) -> *mut JSObject {
+        self.data
+    }
+}
+
+impl Reflectable for ImageData {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+}
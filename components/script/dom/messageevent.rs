@@ -6,17 +6,22 @@ use dom::bindings::codegen::Bindings::EventBinding::EventMethods;
 use dom::bindings::codegen::Bindings::MessageEventBinding;
 use dom::bindings::codegen::Bindings::MessageEventBinding::MessageEventMethods;
 use dom::bindings::codegen::InheritTypes::{EventCast, MessageEventDerived};
+use dom::bindings::conversions::ToJSValConvertible;
 use dom::bindings::error::Fallible;
 use dom::bindings::global::GlobalRef;
-use dom::bindings::js::{JSRef, Temporary};
+use dom::bindings::js::{JS, JSRef, Temporary};
 use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
 use dom::event::{Event, MessageEventTypeId};
 use dom::eventtarget::{EventTarget, EventTargetHelpers};
+use dom::messageport::MessagePort;
+use dom::window::Window;
 
 use servo_util::str::DOMString;
 
-use js::jsapi::JSContext;
-use js::jsval::JSVal;
+use js::jsapi::{JSContext, JS_NewArrayObject};
+use js::jsval::{JSVal, UndefinedValue, ObjectValue};
+
+use libc::c_int;
 
 #[dom_struct]
 pub struct MessageEvent {
@@ -24,6 +29,10 @@ pub struct MessageEvent {
     data: JSVal,
     origin: DOMString,
     lastEventId: DOMString,
+    /// The window that posted the message, if it is in this task.
+    source: Option<JS<Window>>,
+    /// The ports that were transferred with the message.
+    ports: Vec<JS<MessagePort>>,
 }
 
 impl MessageEventDerived for Event {
@@ -33,21 +42,27 @@ impl MessageEventDerived for Event {
 }
 
 impl MessageEvent {
-    fn new_inherited(data: JSVal, origin: DOMString, lastEventId: DOMString)
-                         -> MessageEvent {
+    fn new_inherited(data: JSVal, origin: DOMString, lastEventId: DOMString,
+                     source: Option<JSRef<Window>>, ports: Vec<JS<MessagePort>>)
+                     -> MessageEvent {
         MessageEvent {
             event: Event::new_inherited(MessageEventTypeId),
             data: data,
             origin: origin,
             lastEventId: lastEventId,
+            source: source.map(|source| JS::from_rooted(source)),
+            ports: ports,
         }
     }
 
     pub fn new(global: GlobalRef, type_: DOMString,
                bubbles: bool, cancelable: bool,
-               data: JSVal, origin: DOMString, lastEventId: DOMString)
+               data: JSVal, origin: DOMString, lastEventId: DOMString,
+               source: Option<JSRef<Window>>, ports: Vec<Temporary<MessagePort>>)
                -> Temporary<MessageEvent> {
-        let ev = reflect_dom_object(box MessageEvent::new_inherited(data, origin, lastEventId),
+        let ports = ports.into_iter().map(|port| JS::from_rooted(port)).collect();
+        let ev = reflect_dom_object(box MessageEvent::new_inherited(data, origin, lastEventId,
+                                                                    source, ports),
                                     global,
                                     MessageEventBinding::Wrap).root();
         let event: JSRef<Event> = EventCast::from_ref(*ev);
@@ -60,7 +75,8 @@ impl MessageEvent {
                        init: &MessageEventBinding::MessageEventInit)
                        -> Fallible<Temporary<MessageEvent>> {
         let ev = MessageEvent::new(*global, type_, init.parent.bubbles, init.parent.cancelable,
-                                   init.data, init.origin.clone(), init.lastEventId.clone());
+                                   init.data, init.origin.clone(), init.lastEventId.clone(),
+                                   None, vec!());
         Ok(ev)
     }
 }
@@ -68,10 +84,20 @@ impl MessageEvent {
 impl MessageEvent {
     pub fn dispatch_jsval(target: JSRef<EventTarget>,
                           scope: GlobalRef,
-                          message: JSVal) {
+                          message: JSVal,
+                          ports: Vec<Temporary<MessagePort>>) {
         let messageevent = MessageEvent::new(
             scope, "message".to_string(), false, false, message,
-            "".to_string(), "".to_string()).root();
+            "".to_string(), "".to_string(), None, ports).root();
+        let event: JSRef<Event> = EventCast::from_ref(*messageevent);
+        target.dispatch_event_with_target(None, event).unwrap();
+    }
+
+    /// Fires a `messageerror` event at `target`, for a message that couldn't be deserialized.
+    pub fn dispatch_error(target: JSRef<EventTarget>, scope: GlobalRef) {
+        let messageevent = MessageEvent::new(
+            scope, "messageerror".to_string(), false, false, UndefinedValue(),
+            "".to_string(), "".to_string(), None, vec!()).root();
         let event: JSRef<Event> = EventCast::from_ref(*messageevent);
         target.dispatch_event_with_target(None, event).unwrap();
     }
@@ -89,6 +115,23 @@ impl<'a> MessageEventMethods for JSRef<'a, MessageEvent> {
     fn LastEventId(self) -> DOMString {
         self.lastEventId.clone()
     }
+
+    // http://www.whatwg.org/html/#dom-messageevent-source
+    fn GetSource(self) -> Option<Temporary<Window>> {
+        self.source.map(|source| Temporary::new(source))
+    }
+
+    // http://www.whatwg.org/html/#dom-messageevent-ports
+    fn Ports(self, cx: *mut JSContext) -> JSVal {
+        let mut ports: Vec<JSVal> = self.ports.iter().map(|port| {
+            port.root().to_jsval(cx)
+        }).collect();
+        let array = unsafe {
+            JS_NewArrayObject(cx, ports.len() as c_int, ports.as_mut_ptr())
+        };
+        assert!(array.is_not_null());
+        ObjectValue(unsafe { &*array })
+    }
 }
 
 impl Reflectable for MessageEvent {
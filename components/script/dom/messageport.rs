@@ -0,0 +1,339 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::cell::DOMRefCell;
+use dom::bindings::codegen::Bindings::EventHandlerBinding::EventHandlerNonNull;
+use dom::bindings::codegen::Bindings::MessagePortBinding;
+use dom::bindings::codegen::Bindings::MessagePortBinding::MessagePortMethods;
+use dom::bindings::codegen::InheritTypes::EventTargetCast;
+use dom::bindings::error::ErrorResult;
+use dom::bindings::global::{GlobalRef, GlobalField};
+use dom::bindings::js::{JS, JSRef, Temporary};
+use dom::bindings::structuredclone::StructuredCloneData;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::eventtarget::{EventTarget, EventTargetHelpers, MessagePortTypeId};
+use dom::messageevent::MessageEvent;
+use script_task::{ScriptChan, MessagePortMsg};
+
+use js::jsapi::{JSContext, JSObject, JS_AddObjectRoot, JS_RemoveObjectRoot};
+use js::jsval::JSVal;
+
+use libc::c_void;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+pub struct TrustedMessagePortAddress(pub *const c_void);
+no_jsmanaged_fields!(TrustedMessagePortAddress)
+
+/// The addresses of the ports of this task that haven't been collected. Ports are only rooted
+/// while they need to be, so the messages that wake them up may arrive after they are gone.
+local_data_key!(LivePorts: RefCell<HashSet<uint>>)
+
+fn with_live_ports<R>(f: |&mut HashSet<uint>| -> R) -> R {
+    if LivePorts.get().is_none() {
+        LivePorts.replace(Some(RefCell::new(HashSet::new())));
+    }
+    let ports = LivePorts.get().unwrap();
+    let mut ports = ports.borrow_mut();
+    f(&mut *ports)
+}
+
+/// One end of a message channel.
+struct ChannelEnd {
+    /// The task of the `MessagePort` that represents this end, and that port, unless the port
+    /// is being transferred to another task.
+    owner: Option<(ScriptChan, TrustedMessagePortAddress)>,
+    /// The messages sent to this end that haven't been dispatched yet.
+    queue: Vec<StructuredCloneData>,
+}
+
+/// The state of a message channel, which is shared by the tasks of both of its ports.
+struct Channel {
+    ends: [ChannelEnd, ..2],
+    /// Set once either port has been closed, after which messages are dropped.
+    closed: bool,
+}
+
+/// A handle to one end of a message channel, which can be sent to other tasks.
+pub struct PortEndpoint {
+    channel: Arc<Mutex<Channel>>,
+    index: uint,
+}
+
+no_jsmanaged_fields!(PortEndpoint)
+
+impl PortEndpoint {
+    /// Creates a message channel, and returns its two entangled ends.
+    pub fn new_pair() -> (PortEndpoint, PortEndpoint) {
+        let channel = Arc::new(Mutex::new(Channel {
+            ends: [
+                ChannelEnd { owner: None, queue: vec!() },
+                ChannelEnd { owner: None, queue: vec!() },
+            ],
+            closed: false,
+        }));
+        (PortEndpoint { channel: channel.clone(), index: 0 },
+         PortEndpoint { channel: channel, index: 1 })
+    }
+
+    /// Queues a message at the other end, and tells the task of its port.
+    pub fn post(&self, message: StructuredCloneData) {
+        let mut channel = self.channel.lock();
+        if channel.closed {
+            return;
+        }
+        let other = &mut channel.ends[1 - self.index];
+        other.queue.push(message);
+        match other.owner {
+            Some((ScriptChan(ref sender), address)) => {
+                let _ = sender.send_opt(MessagePortMsg(address));
+            }
+            None => (),
+        }
+    }
+
+    fn set_owner(&self, owner: Option<(ScriptChan, TrustedMessagePortAddress)>) {
+        self.channel.lock().ends[self.index].owner = owner;
+    }
+
+    fn take_message(&self) -> Option<StructuredCloneData> {
+        self.channel.lock().ends[self.index].queue.remove(0)
+    }
+
+    fn has_messages(&self) -> bool {
+        !self.channel.lock().ends[self.index].queue.is_empty()
+    }
+
+    fn is_entangled(&self) -> bool {
+        !self.channel.lock().closed
+    }
+
+    /// Disentangles both ends of the channel, and tells the tasks of their ports so that they
+    /// can be unrooted.
+    pub fn close(&self) {
+        let mut channel = self.channel.lock();
+        channel.closed = true;
+        for end in channel.ends.iter_mut() {
+            match end.owner.take() {
+                Some((ScriptChan(ref sender), address)) => {
+                    let _ = sender.send_opt(MessagePortMsg(address));
+                }
+                None => (),
+            }
+            end.queue.clear();
+        }
+    }
+}
+
+#[dom_struct]
+pub struct MessagePort {
+    eventtarget: EventTarget,
+    global: GlobalField,
+    /// The end of the channel that this port represents, until the port is transferred.
+    endpoint: DOMRefCell<Option<PortEndpoint>>,
+    /// Whether the messages sent to this port are dispatched.
+    started: Cell<bool>,
+    /// Whether this port is rooted, which it is while it is entangled and either has a message
+    /// handler or messages that haven't been dispatched, so that they can be.
+    rooted: Cell<bool>,
+}
+
+impl MessagePort {
+    fn new_inherited(global: &GlobalRef, endpoint: PortEndpoint) -> MessagePort {
+        MessagePort {
+            eventtarget: EventTarget::new_inherited(MessagePortTypeId),
+            global: GlobalField::from_rooted(global),
+            endpoint: DOMRefCell::new(Some(endpoint)),
+            started: Cell::new(false),
+            rooted: Cell::new(false),
+        }
+    }
+
+    /// Creates the port that represents `endpoint` in `global`.
+    pub fn new(global: &GlobalRef, endpoint: PortEndpoint) -> Temporary<MessagePort> {
+        let port = reflect_dom_object(box MessagePort::new_inherited(global, endpoint),
+                                      *global,
+                                      MessagePortBinding::Wrap).root();
+        let address = port.to_trusted();
+        let TrustedMessagePortAddress(ptr) = address;
+        with_live_ports(|ports| ports.insert(ptr as uint));
+        port.endpoint.borrow().as_ref().unwrap().set_owner(Some((global.script_chan().clone(),
+                                                                 address)));
+        // A transferred port may come with messages.
+        port.update_root();
+        Temporary::from_rooted(*port)
+    }
+
+    /// Dispatches the messages that have arrived for a port, or unroots it if it has been
+    /// disentangled.
+    pub fn handle_message(address: TrustedMessagePortAddress) {
+        let TrustedMessagePortAddress(ptr) = address;
+        if !with_live_ports(|ports| ports.contains(&(ptr as uint))) {
+            return;
+        }
+        let port = unsafe { JS::from_trusted_port_address(address).root() };
+        port.dispatch_messages();
+        port.update_root();
+    }
+}
+
+pub trait MessagePortHelpers {
+    fn is_detached(self) -> bool;
+    fn detach(self) -> PortEndpoint;
+}
+
+impl<'a> MessagePortHelpers for JSRef<'a, MessagePort> {
+    fn is_detached(self) -> bool {
+        self.endpoint.borrow().is_none()
+    }
+
+    /// Takes the end of the channel away from this port, to transfer it to another task. The
+    /// messages that haven't been dispatched yet are transferred along with it.
+    fn detach(self) -> PortEndpoint {
+        let endpoint = self.endpoint.borrow_mut().take().unwrap();
+        endpoint.set_owner(None);
+        self.update_root();
+        endpoint
+    }
+}
+
+trait PrivateMessagePortHelpers {
+    fn to_trusted(self) -> TrustedMessagePortAddress;
+    fn has_message_handler(self) -> bool;
+    fn update_root(self);
+    fn dispatch_messages(self);
+    fn wake(self);
+}
+
+impl<'a> PrivateMessagePortHelpers for JSRef<'a, MessagePort> {
+    fn to_trusted(self) -> TrustedMessagePortAddress {
+        TrustedMessagePortAddress(self.deref() as *const MessagePort as *const c_void)
+    }
+
+    fn has_message_handler(self) -> bool {
+        let target: JSRef<EventTarget> = EventTargetCast::from_ref(self);
+        self.started.get() &&
+            target.get_listeners("message").map_or(false, |listeners| !listeners.is_empty())
+    }
+
+    fn update_root(self) {
+        let needs_root = match *self.endpoint.borrow() {
+            Some(ref endpoint) => {
+                endpoint.is_entangled() && (endpoint.has_messages() || self.has_message_handler())
+            }
+            None => false,
+        };
+        if needs_root == self.rooted.get() {
+            return;
+        }
+
+        let cx = self.global.root().root_ref().get_cx();
+        unsafe {
+            if needs_root {
+                JS_AddObjectRoot(cx, self.reflector().rootable());
+            } else {
+                JS_RemoveObjectRoot(cx, self.reflector().rootable());
+            }
+        }
+        self.rooted.set(needs_root);
+    }
+
+    /// The event handlers may transfer or close the port, in which case the remaining messages
+    /// aren't dispatched here.
+    fn dispatch_messages(self) {
+        if !self.started.get() {
+            return;
+        }
+
+        let global = self.global.root();
+        let target: JSRef<EventTarget> = EventTargetCast::from_ref(self);
+        loop {
+            let message = match *self.endpoint.borrow() {
+                Some(ref endpoint) => endpoint.take_message(),
+                None => None,
+            };
+            let message = match message {
+                Some(message) => message,
+                None => return,
+            };
+            match message.read(global.root_ref()) {
+                Ok((data, ports)) => {
+                    MessageEvent::dispatch_jsval(target, global.root_ref(), data, ports)
+                }
+                Err(()) => MessageEvent::dispatch_error(target, global.root_ref()),
+            }
+        }
+    }
+
+    /// Makes the task dispatch the messages that arrived before this port was started.
+    fn wake(self) {
+        let ScriptChan(sender) = self.global.root().root_ref().script_chan().clone();
+        sender.send(MessagePortMsg(self.to_trusted()));
+    }
+}
+
+impl<'a> MessagePortMethods for JSRef<'a, MessagePort> {
+    // http://www.whatwg.org/html/#dom-messageport-postmessage
+    fn PostMessage(self, cx: *mut JSContext, message: JSVal,
+                   transfer: Option<Vec<*mut JSObject>>) -> ErrorResult {
+        let data = try!(StructuredCloneData::write_for_port(cx, message, transfer, self));
+        match *self.endpoint.borrow() {
+            Some(ref endpoint) => endpoint.post(data),
+            None => (),
+        }
+        Ok(())
+    }
+
+    // http://www.whatwg.org/html/#dom-messageport-start
+    fn Start(self) {
+        if self.started.get() || self.is_detached() {
+            return;
+        }
+        self.started.set(true);
+        self.update_root();
+        self.wake();
+    }
+
+    // http://www.whatwg.org/html/#dom-messageport-close
+    fn Close(self) {
+        match self.endpoint.borrow_mut().take() {
+            Some(endpoint) => endpoint.close(),
+            None => return,
+        }
+        self.update_root();
+    }
+
+    fn GetOnmessage(self) -> Option<EventHandlerNonNull> {
+        let eventtarget: JSRef<EventTarget> = EventTargetCast::from_ref(self);
+        eventtarget.get_event_handler_common("message")
+    }
+
+    // Setting onmessage starts the port.
+    fn SetOnmessage(self, listener: Option<EventHandlerNonNull>) {
+        let eventtarget: JSRef<EventTarget> = EventTargetCast::from_ref(self);
+        eventtarget.set_event_handler_common("message", listener);
+        self.Start();
+        self.update_root();
+    }
+
+    event_handler!(messageerror, GetOnmessageerror, SetOnmessageerror)
+}
+
+#[unsafe_destructor]
+impl Drop for MessagePort {
+    fn drop(&mut self) {
+        with_live_ports(|ports| ports.remove(&(self as *const MessagePort as uint)));
+        match *self.endpoint.borrow() {
+            Some(ref endpoint) => endpoint.set_owner(None),
+            None => (),
+        }
+    }
+}
+
+impl Reflectable for MessagePort {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        self.eventtarget.reflector()
+    }
+}
@@ -263,7 +263,7 @@ pub enum NodeTypeId {
 
 trait PrivateNodeHelpers {
     fn node_inserted(self);
-    fn node_removed(self, parent_in_doc: bool);
+    fn node_removed(self, parent: JSRef<Node>);
     fn add_child(self, new_child: JSRef<Node>, before: Option<JSRef<Node>>);
     fn remove_child(self, child: JSRef<Node>);
 }
@@ -286,14 +286,17 @@ impl<'a> PrivateNodeHelpers for JSRef<'a, Node> {
     }
 
     // http://dom.spec.whatwg.org/#node-is-removed
-    fn node_removed(self, parent_in_doc: bool) {
+    fn node_removed(self, parent: JSRef<Node>) {
         assert!(self.parent_node().is_none());
         let document = document_from_node(self).root();
+        let parent_in_doc = parent.is_in_doc();
 
         for node in self.traverse_preorder() {
             vtable_for(&node).unbind_from_tree(parent_in_doc);
         }
 
+        vtable_for(&parent).child_removed(self);
+
         document.content_changed(self);
     }
 
@@ -454,6 +457,7 @@ pub trait NodeHelpers<'a> {
     fn remove_self(self);
 
     fn get_unique_id(self) -> String;
+    fn has_unique_id(self, id: &str) -> bool;
     fn summarize(self) -> NodeInfo;
 }
 
@@ -788,17 +792,21 @@ impl<'a> NodeHelpers<'a> for JSRef<'a, Node> {
     }
 
     fn get_unique_id(self) -> String {
-        self.unique_id.borrow().clone()
-    }
-
-    fn summarize(self) -> NodeInfo {
         if self.unique_id.borrow().is_empty() {
             let mut unique_id = self.unique_id.borrow_mut();
             *unique_id = uuid::Uuid::new_v4().to_simple_string();
         }
+        self.unique_id.borrow().clone()
+    }
+
+    /// Whether the node has been given the unique id, without giving it one if it has none.
+    fn has_unique_id(self, id: &str) -> bool {
+        !id.is_empty() && self.unique_id.borrow().as_slice() == id
+    }
 
+    fn summarize(self) -> NodeInfo {
         NodeInfo {
-            uniqueId: self.unique_id.borrow().clone(),
+            uniqueId: self.get_unique_id(),
             baseURI: self.GetBaseURI().unwrap_or("".to_string()),
             parent: self.GetParentNode().root().map(|node| node.get_unique_id()).unwrap_or("".to_string()),
             nodeType: self.NodeType() as uint,
@@ -1383,7 +1391,7 @@ impl Node {
     }
 
     // http://dom.spec.whatwg.org/#concept-node-replace-all
-    fn replace_all(node: Option<JSRef<Node>>, parent: JSRef<Node>) {
+    pub fn replace_all(node: Option<JSRef<Node>>, parent: JSRef<Node>) {
 
         // Step 1.
         match node {
@@ -1420,9 +1428,8 @@ impl Node {
         // Step 6: mutation records.
 
         // Step 7.
-        let parent_in_doc = parent.is_in_doc();
         for removedNode in removedNodes.iter() {
-            removedNode.node_removed(parent_in_doc);
+            removedNode.node_removed(parent);
         }
         for addedNode in addedNodes.iter() {
             addedNode.node_inserted();
@@ -1458,7 +1465,7 @@ impl Node {
         // Step 9.
         match suppress_observers {
             Suppressed => (),
-            Unsuppressed => node.node_removed(parent.is_in_doc()),
+            Unsuppressed => node.node_removed(parent),
         }
     }
 
@@ -1921,7 +1928,7 @@ impl<'a> NodeMethods for JSRef<'a, Node> {
 
         // Step 12-14.
         // Step 13: mutation records.
-        child.node_removed(self.is_in_doc());
+        child.node_removed(self);
         if node.type_id() == DocumentFragmentNodeTypeId {
             for child_node in node.children() {
                 child_node.node_inserted();
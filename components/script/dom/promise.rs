@@ -0,0 +1,284 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The subset of promises that the DOM needs, until the JS engine implements them.
+
+use dom::bindings::callback::RethrowExceptions;
+use dom::bindings::cell::DOMRefCell;
+use dom::bindings::codegen::Bindings::FunctionBinding::Function;
+use dom::bindings::codegen::Bindings::PromiseBinding;
+use dom::bindings::codegen::Bindings::PromiseBinding::PromiseMethods;
+use dom::bindings::conversions::{FromJSValConvertible, ToJSValConvertible};
+use dom::bindings::error::{Error, Type};
+use dom::bindings::global::{GlobalField, GlobalRef};
+use dom::bindings::js::{JS, JSRef, Temporary};
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::domexception::DOMException;
+use script_task::{ScriptChan, PromiseJobMsg};
+
+use js::jsapi::{JSContext, JS_AddObjectRoot, JS_RemoveObjectRoot, JS_CallFunctionName};
+use js::jsapi::{JS_ClearPendingException, JS_GetPendingException, JS_IsExceptionPending};
+use js::jsval::{JSVal, UndefinedValue};
+
+use libc::c_void;
+use std::cell::Cell;
+
+pub struct TrustedPromiseAddress(pub *const c_void);
+
+#[deriving(PartialEq)]
+#[jstraceable]
+enum PromiseState {
+    Pending,
+    Fulfilled,
+    Rejected,
+}
+
+/// A reaction to the settlement of a promise. The `derived` promise is resolved with the
+/// result of the handler for the settlement, or settled the same way if there is no handler.
+#[jstraceable]
+#[must_root]
+struct PromiseReaction {
+    on_fulfilled: Option<Function>,
+    on_rejected: Option<Function>,
+    derived: JS<Promise>,
+}
+
+#[dom_struct]
+pub struct Promise {
+    reflector_: Reflector,
+    global: GlobalField,
+    state: Cell<PromiseState>,
+    result: Cell<JSVal>,
+    /// Whether the promise has been resolved or rejected, even if it is still pending because
+    /// it adopted the state of another promise.
+    resolved: Cell<bool>,
+    reactions: DOMRefCell<Vec<PromiseReaction>>,
+    /// Whether a job that runs the reactions is queued and hasn't run yet.
+    job_queued: Cell<bool>,
+    pinned_count: Cell<uint>,
+}
+
+impl Promise {
+    fn new_inherited(global: &GlobalRef) -> Promise {
+        Promise {
+            reflector_: Reflector::new(),
+            global: GlobalField::from_rooted(global),
+            state: Cell::new(Pending),
+            result: Cell::new(UndefinedValue()),
+            resolved: Cell::new(false),
+            reactions: DOMRefCell::new(vec!()),
+            job_queued: Cell::new(false),
+            pinned_count: Cell::new(0),
+        }
+    }
+
+    pub fn new(global: &GlobalRef) -> Temporary<Promise> {
+        reflect_dom_object(box Promise::new_inherited(global), *global, PromiseBinding::Wrap)
+    }
+
+    /// Runs the reactions of a settled promise, as queued by `queue_job`.
+    pub fn handle_job(address: TrustedPromiseAddress) {
+        let promise = unsafe { JS::from_trusted_promise_address(address).root() };
+        promise.run_reactions();
+        promise.release_once();
+    }
+}
+
+/// Returns the exception for `error`. Unlike `throw_dom_exception`, this creates `TypeError`s
+/// even if no script is running.
+pub fn exception_for_error(global: &GlobalRef, error: Error) -> JSVal {
+    let cx = global.get_cx();
+    match error {
+        Type(message) => {
+            let mut argument = message.to_string().to_jsval(cx);
+            let mut exception = UndefinedValue();
+            "TypeError".with_c_str(|name| unsafe {
+                if JS_CallFunctionName(cx, global.reflector().get_jsobject(), name, 1,
+                                       &mut argument, &mut exception) == 0 {
+                    JS_ClearPendingException(cx);
+                }
+            });
+            exception
+        }
+        _ => DOMException::new_from_error(*global, error).root().to_jsval(cx)
+    }
+}
+
+/// Takes the exception that a failed call left pending on `cx`.
+fn take_pending_exception(cx: *mut JSContext) -> JSVal {
+    let mut exception = UndefinedValue();
+    unsafe {
+        if JS_IsExceptionPending(cx) != 0 && JS_GetPendingException(cx, &mut exception) != 0 {
+            JS_ClearPendingException(cx);
+        }
+    }
+    exception
+}
+
+pub trait PromiseHelpers {
+    /// Resolves the promise with `value`, adopting its state if it is a promise.
+    fn resolve(self, value: JSVal);
+    fn reject(self, reason: JSVal);
+    /// Rejects the promise with the exception for `error`.
+    fn reject_error(self, error: Error);
+    /// Creates a trusted address to the promise, and roots it. Always pair this with a
+    /// `release_once`.
+    unsafe fn to_trusted(self) -> TrustedPromiseAddress;
+    fn release_once(self);
+}
+
+impl<'a> PromiseHelpers for JSRef<'a, Promise> {
+    fn resolve(self, value: JSVal) {
+        if self.resolved.get() {
+            return;
+        }
+        let global = self.global.root();
+        let cx = global.root_ref().get_cx();
+        // FIXME: Other thenables should be adopted too.
+        let adopted: Result<JS<Promise>, ()> = FromJSValConvertible::from_jsval(cx, value, ());
+        match adopted {
+            Ok(promise) => {
+                let promise = promise.root();
+                if promise.reflector().get_jsobject() == self.reflector().get_jsobject() {
+                    return self.reject_error(Type("A promise can't be resolved with itself"));
+                }
+                self.resolved.set(true);
+                promise.add_reaction(None, None, self);
+            }
+            Err(()) => {
+                self.resolved.set(true);
+                self.settle(Fulfilled, value);
+            }
+        }
+    }
+
+    fn reject(self, reason: JSVal) {
+        if self.resolved.get() {
+            return;
+        }
+        self.resolved.set(true);
+        self.settle(Rejected, reason);
+    }
+
+    fn reject_error(self, error: Error) {
+        let global = self.global.root();
+        self.reject(exception_for_error(&global.root_ref(), error));
+    }
+
+    unsafe fn to_trusted(self) -> TrustedPromiseAddress {
+        if self.pinned_count.get() == 0 {
+            JS_AddObjectRoot(self.global.root().root_ref().get_cx(), self.reflector().rootable());
+        }
+        let pinned_count = self.pinned_count.get();
+        self.pinned_count.set(pinned_count + 1);
+        TrustedPromiseAddress(self.deref() as *const Promise as *const c_void)
+    }
+
+    fn release_once(self) {
+        assert!(self.pinned_count.get() > 0)
+        let pinned_count = self.pinned_count.get();
+        self.pinned_count.set(pinned_count - 1);
+        if self.pinned_count.get() == 0 {
+            unsafe {
+                JS_RemoveObjectRoot(self.global.root().root_ref().get_cx(),
+                                    self.reflector().rootable());
+            }
+        }
+    }
+}
+
+trait PrivatePromiseHelpers {
+    fn settle(self, state: PromiseState, result: JSVal);
+    fn add_reaction(self, on_fulfilled: Option<Function>, on_rejected: Option<Function>,
+                    derived: JSRef<Promise>);
+    fn queue_job(self);
+    fn run_reactions(self);
+}
+
+impl<'a> PrivatePromiseHelpers for JSRef<'a, Promise> {
+    fn settle(self, state: PromiseState, result: JSVal) {
+        if self.state.get() != Pending {
+            return;
+        }
+        self.state.set(state);
+        self.result.set(result);
+        self.queue_job();
+    }
+
+    #[allow(unrooted_must_root)]
+    fn add_reaction(self, on_fulfilled: Option<Function>, on_rejected: Option<Function>,
+                    derived: JSRef<Promise>) {
+        self.reactions.borrow_mut().push(PromiseReaction {
+            on_fulfilled: on_fulfilled,
+            on_rejected: on_rejected,
+            derived: JS::from_rooted(derived),
+        });
+        self.queue_job();
+    }
+
+    /// Queues a job that runs the reactions, if the promise is settled. The promise stays
+    /// rooted until the job has run.
+    fn queue_job(self) {
+        if self.state.get() == Pending || self.job_queued.get() ||
+           self.reactions.borrow().is_empty() {
+            return;
+        }
+        self.job_queued.set(true);
+        let global = self.global.root();
+        let ScriptChan(chan) = global.root_ref().script_chan().clone();
+        let address = unsafe { self.to_trusted() };
+        chan.send(PromiseJobMsg(address));
+    }
+
+    #[allow(unrooted_must_root)]
+    fn run_reactions(self) {
+        self.job_queued.set(false);
+        let global = self.global.root();
+        let cx = global.root_ref().get_cx();
+        let state = self.state.get();
+        let result = self.result.get();
+        loop {
+            // The handlers can add reactions, so they are taken one at a time.
+            let reaction = match self.reactions.borrow_mut().remove(0) {
+                Some(reaction) => reaction,
+                None => break,
+            };
+            let derived = reaction.derived.root();
+            let handler = match state {
+                Fulfilled => reaction.on_fulfilled,
+                Rejected => reaction.on_rejected,
+                Pending => unreachable!(),
+            };
+            match handler {
+                Some(handler) => match handler.Call__(vec![result], RethrowExceptions) {
+                    Ok(value) => derived.resolve(value),
+                    Err(_) => derived.reject(take_pending_exception(cx)),
+                },
+                None => derived.settle(state, result),
+            }
+        }
+    }
+}
+
+impl<'a> PromiseMethods for JSRef<'a, Promise> {
+    fn Then(self, on_fulfilled: Option<Option<Function>>,
+            on_rejected: Option<Option<Function>>) -> Temporary<Promise> {
+        let global = self.global.root();
+        let derived = Promise::new(&global.root_ref()).root();
+        self.add_reaction(on_fulfilled.and_then(|handler| handler),
+                          on_rejected.and_then(|handler| handler),
+                          *derived);
+        Temporary::from_rooted(*derived)
+    }
+
+    fn Catch(self, on_rejected: Option<Option<Function>>) -> Temporary<Promise> {
+        self.Then(None, on_rejected)
+    }
+}
+
+impl Reflectable for Promise {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+}
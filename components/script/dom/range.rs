@@ -5,27 +5,38 @@
 use dom::bindings::codegen::Bindings::RangeBinding;
 use dom::bindings::codegen::Bindings::RangeBinding::RangeMethods;
 use dom::bindings::codegen::Bindings::WindowBinding::WindowMethods;
+use dom::bindings::codegen::InheritTypes::{ElementCast, HTMLScriptElementCast, NodeCast};
 use dom::bindings::error::Fallible;
 use dom::bindings::global::{GlobalRef, Window};
-use dom::bindings::js::{JSRef, Temporary};
+use dom::bindings::js::{JS, JSRef, OptionalRootable, Root, Temporary};
 use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
 use dom::document::{Document, DocumentHelpers};
+use dom::documentfragment::DocumentFragment;
+use dom::element::{Element, ElementHelpers, fragment_parsing_context};
+use dom::htmlscriptelement::{HTMLScriptElement, HTMLScriptElementHelpers};
+use dom::node::{Node, NodeHelpers, TextNodeTypeId, CommentNodeTypeId};
+use dom::node::ProcessingInstructionNodeTypeId;
+use servo_util::str::DOMString;
 
 #[dom_struct]
 pub struct Range {
-    reflector_: Reflector
+    reflector_: Reflector,
+    /// The node of the range's start boundary point. Ranges can't be moved yet, so this is
+    /// always the document that the range was created for.
+    start_container: JS<Node>,
 }
 
 impl Range {
-    fn new_inherited() -> Range {
+    fn new_inherited(document: JSRef<Document>) -> Range {
         Range {
-            reflector_: Reflector::new()
+            reflector_: Reflector::new(),
+            start_container: JS::from_rooted(NodeCast::from_ref(document)),
         }
     }
 
     pub fn new(document: JSRef<Document>) -> Temporary<Range> {
         let window = document.window().root();
-        reflect_dom_object(box Range::new_inherited(),
+        reflect_dom_object(box Range::new_inherited(document),
                            Window(*window),
                            RangeBinding::Wrap)
     }
@@ -41,6 +52,44 @@ impl<'a> RangeMethods for JSRef<'a, Range> {
     fn Detach(self) {
         // This method intentionally left blank.
     }
+
+    /// http://domparsing.spec.whatwg.org/#dom-range-createcontextualfragment
+    fn CreateContextualFragment(self, fragment: DOMString)
+                                -> Fallible<Temporary<DocumentFragment>> {
+        // Step 1.
+        let node = self.start_container.root();
+
+        // Step 2.
+        let element = match node.type_id() {
+            TextNodeTypeId | CommentNodeTypeId | ProcessingInstructionNodeTypeId => {
+                node.parent_node().root().and_then(|parent| {
+                    ElementCast::to_ref(*parent).map(|element| Temporary::from_rooted(element))
+                })
+            },
+            _ => ElementCast::to_ref(*node).map(|element| Temporary::from_rooted(element)),
+        }.root();
+
+        // Step 3.
+        let context: JSRef<Node> = match element {
+            Some(ref element) => NodeCast::from_ref(**element),
+            None => *node,
+        };
+        let element: Root<Element> = fragment_parsing_context(context).root();
+
+        // Step 4.
+        let fragment_node = element.parse_fragment(fragment).root();
+
+        // Step 5.
+        let node: JSRef<Node> = NodeCast::from_ref(*fragment_node);
+        for descendant in node.traverse_preorder() {
+            let script: Option<JSRef<HTMLScriptElement>> =
+                HTMLScriptElementCast::to_ref(descendant);
+            script.map(|script| script.unmark_already_started_and_parser_inserted());
+        }
+
+        // Step 6.
+        Ok(Temporary::from_rooted(*fragment_node))
+    }
 }
 
 impl Reflectable for Range {
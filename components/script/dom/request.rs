@@ -0,0 +1,218 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::Bindings::RequestBinding;
+use dom::bindings::codegen::Bindings::RequestBinding::{RequestInit, RequestMethods};
+use dom::bindings::codegen::Bindings::RequestBinding::{RequestCache, RequestCacheValues};
+use dom::bindings::codegen::Bindings::RequestBinding::RequestCredentials;
+use dom::bindings::codegen::Bindings::RequestBinding::RequestCredentialsValues;
+use dom::bindings::codegen::Bindings::RequestBinding::{RequestMode, RequestModeValues};
+use dom::bindings::codegen::UnionTypes::RequestOrString::{RequestOrString, eRequest, eString};
+use dom::bindings::error::{Fallible, Type};
+use dom::bindings::global::GlobalRef;
+use dom::bindings::js::{JS, JSRef, RootedReference, Temporary};
+use dom::bindings::str::ByteString;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::headers::{Headers, HeadersHelpers, RequestGuard, RequestNoCORSGuard};
+
+use hyper::method::Method;
+use net::fetch::request::Request as FetchRequest;
+use net::fetch::request::{CORSMode, NoCORS, SameOrigin};
+use net::fetch::request::{CredentialsSameOrigin, Include, Omit, RefererUrl};
+use net::fetch::request::{DefaultCache, ForceCache, NoCache, NoStore, OnlyIfCached, Reload};
+use net::fetch::request::Fetch as FetchContext;
+use servo_util::str::DOMString;
+
+use std::ascii::AsciiExt;
+use std::from_str::FromStr;
+use url::{Url, UrlParser};
+
+#[dom_struct]
+pub struct Request {
+    reflector_: Reflector,
+    method: ByteString,
+    url: Url,
+    headers: JS<Headers>,
+    body: Option<Vec<u8>>,
+    mode: RequestMode,
+    credentials: RequestCredentials,
+    cache: RequestCache,
+}
+
+impl Request {
+    fn new_inherited(method: ByteString, url: Url, headers: JSRef<Headers>,
+                     body: Option<Vec<u8>>, mode: RequestMode,
+                     credentials: RequestCredentials, cache: RequestCache) -> Request {
+        Request {
+            reflector_: Reflector::new(),
+            method: method,
+            url: url,
+            headers: JS::from_rooted(headers),
+            body: body,
+            mode: mode,
+            credentials: credentials,
+            cache: cache,
+        }
+    }
+
+    pub fn new(global: GlobalRef, method: ByteString, url: Url, headers: JSRef<Headers>,
+               body: Option<Vec<u8>>, mode: RequestMode, credentials: RequestCredentials,
+               cache: RequestCache) -> Temporary<Request> {
+        reflect_dom_object(box Request::new_inherited(method, url, headers, body, mode,
+                                                      credentials, cache),
+                           global,
+                           RequestBinding::Wrap)
+    }
+
+    // http://fetch.spec.whatwg.org/#dom-request
+    pub fn Constructor(global: &GlobalRef, input: RequestOrString, init: &RequestInit)
+                       -> Fallible<Temporary<Request>> {
+        // Steps 1-5
+        let (mut method, url, input_headers, mut body, mut mode, mut credentials, mut cache) =
+            match input {
+                eRequest(request) => {
+                    let request = request.root();
+                    (request.method.clone(), request.url.clone(), Some(request.headers.root()),
+                     request.body.clone(), request.mode, request.credentials, request.cache)
+                }
+                eString(url) => {
+                    let base = global.get_url();
+                    let url = match UrlParser::new().base_url(&base).parse(url.as_slice()) {
+                        Ok(url) => url,
+                        Err(_) => return Err(Type("Invalid URL"))
+                    };
+                    (ByteString::new(b"GET".to_vec()), url, None, None,
+                     RequestModeValues::cors, RequestCredentialsValues::omit,
+                     RequestCacheValues::default)
+                }
+            };
+
+        // Steps 6-9
+        mode = init.mode.unwrap_or(mode);
+        credentials = init.credentials.unwrap_or(credentials);
+        cache = init.cache.unwrap_or(cache);
+
+        // Step 10
+        match init.method {
+            Some(ref init_method) => {
+                if !init_method.is_token() {
+                    return Err(Type("Invalid method"));
+                }
+                let upper = init_method.as_str().unwrap().to_ascii_upper();
+                method = match upper.as_slice() {
+                    "CONNECT" | "TRACE" | "TRACK" => return Err(Type("Forbidden method")),
+                    "DELETE" | "GET" | "HEAD" | "OPTIONS" | "POST" | "PUT" => {
+                        ByteString::new(upper.into_bytes())
+                    }
+                    _ => init_method.clone()
+                };
+            }
+            None => ()
+        }
+
+        // Steps 11-14
+        let headers = Headers::new(*global, RequestGuard).root();
+        if mode == RequestModeValues::no_cors {
+            match method.as_str() {
+                Some("GET") | Some("HEAD") | Some("POST") => (),
+                _ => return Err(Type("Invalid method for a no-cors request"))
+            }
+            headers.set_guard(RequestNoCORSGuard);
+        }
+        match init.headers.root_ref().or(input_headers.root_ref()) {
+            Some(init_headers) => try!(headers.fill(init_headers)),
+            None => ()
+        }
+
+        // Step 15
+        match init.body {
+            Some(ref init_body) => {
+                match method.as_str() {
+                    Some("GET") | Some("HEAD") => {
+                        return Err(Type("GET and HEAD requests can't have a body"))
+                    }
+                    _ => ()
+                }
+                body = Some(init_body.as_bytes().to_vec());
+                let content_type = ByteString::new(b"Content-Type".to_vec());
+                if !try!(headers.Has(content_type.clone())) {
+                    try!(headers.Append(content_type,
+                                        ByteString::new(b"text/plain;charset=UTF-8".to_vec())));
+                }
+            }
+            None => ()
+        }
+
+        Ok(Request::new(*global, method, url, *headers, body, mode, credentials, cache))
+    }
+}
+
+pub trait RequestHelpers {
+    /// The request for the network, as made by `client`.
+    fn to_fetch_request(self, client: &Url) -> FetchRequest;
+}
+
+impl<'a> RequestHelpers for JSRef<'a, Request> {
+    fn to_fetch_request(self, client: &Url) -> FetchRequest {
+        let mut request = FetchRequest::new(self.url.clone(), FetchContext);
+        let method: Option<Method> = FromStr::from_str(self.method.as_str().unwrap());
+        request.method = method.unwrap();
+        request.headers = self.headers.root().to_header_map();
+        request.body = self.body.clone();
+        request.origin = Some(client.clone());
+        request.referer = RefererUrl(client.clone());
+        request.unsafe_request = true;
+        request.mode = match self.mode {
+            RequestModeValues::same_origin => SameOrigin,
+            RequestModeValues::no_cors => NoCORS,
+            RequestModeValues::cors => CORSMode,
+        };
+        request.credentials_mode = match self.credentials {
+            RequestCredentialsValues::omit => Omit,
+            RequestCredentialsValues::same_origin => CredentialsSameOrigin,
+            RequestCredentialsValues::include => Include,
+        };
+        request.cache_mode = match self.cache {
+            RequestCacheValues::default => DefaultCache,
+            RequestCacheValues::no_store => NoStore,
+            RequestCacheValues::reload => Reload,
+            RequestCacheValues::no_cache => NoCache,
+            RequestCacheValues::force_cache => ForceCache,
+            RequestCacheValues::only_if_cached => OnlyIfCached,
+        };
+        request
+    }
+}
+
+impl<'a> RequestMethods for JSRef<'a, Request> {
+    fn Method(self) -> ByteString {
+        self.method.clone()
+    }
+
+    fn Url(self) -> DOMString {
+        self.url.serialize()
+    }
+
+    fn Headers(self) -> Temporary<Headers> {
+        Temporary::new(self.headers)
+    }
+
+    fn Mode(self) -> RequestMode {
+        self.mode
+    }
+
+    fn Credentials(self) -> RequestCredentials {
+        self.credentials
+    }
+
+    fn Cache(self) -> RequestCache {
+        self.cache
+    }
+}
+
+impl Reflectable for Request {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+}
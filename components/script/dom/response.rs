@@ -0,0 +1,181 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::cell::DOMRefCell;
+use dom::bindings::codegen::Bindings::ResponseBinding;
+use dom::bindings::codegen::Bindings::ResponseBinding::ResponseMethods;
+use dom::bindings::codegen::Bindings::ResponseBinding::{ResponseType, ResponseTypeValues};
+use dom::bindings::conversions::ToJSValConvertible;
+use dom::bindings::error::Type;
+use dom::bindings::global::{GlobalField, GlobalRef, global_object_for_js_object};
+use dom::bindings::js::{JS, JSRef, Temporary};
+use dom::bindings::str::ByteString;
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::headers::Headers;
+use dom::promise::{Promise, PromiseHelpers, TrustedPromiseAddress};
+
+use encoding::all::UTF_8;
+use encoding::types::{DecodeReplace, Encoding};
+
+use js::jsapi::{JS_ClearPendingException, JS_GetPendingException, JS_ParseJSON};
+use js::jsval::UndefinedValue;
+
+use net::fetch::response::Response as FetchResponse;
+use net::fetch::response::{Basic, CORS, Opaque, OpaqueRedirect};
+use net::fetch::response::Default as DefaultType;
+use net::fetch::response::Error as ErrorType;
+use net::fetch::response::{Done as BodyDone, Empty, Receiving};
+use servo_util::str::DOMString;
+
+use std::cell::Cell;
+use std::mem;
+
+enum BodyType {
+    JSONBody,
+    TextBody,
+}
+
+#[dom_struct]
+pub struct Response {
+    reflector_: Reflector,
+    global: GlobalField,
+    response_type: ResponseType,
+    url: DOMString,
+    status: u16,
+    status_text: ByteString,
+    headers: JS<Headers>,
+    body: DOMRefCell<Vec<u8>>,
+    body_used: Cell<bool>,
+}
+
+impl Response {
+    fn new_inherited(global: &GlobalRef, response: FetchResponse) -> Response {
+        let response_type = match response.response_type {
+            Basic => ResponseTypeValues::basic,
+            CORS => ResponseTypeValues::cors,
+            DefaultType => ResponseTypeValues::default,
+            ErrorType => ResponseTypeValues::error,
+            Opaque | OpaqueRedirect => ResponseTypeValues::opaque,
+        };
+        let headers = Headers::from_response_headers(*global, &response.headers).root();
+        Response {
+            reflector_: Reflector::new(),
+            global: GlobalField::from_rooted(global),
+            response_type: response_type,
+            url: response.url.as_ref().map_or("".to_string(), |url| url.serialize()),
+            status: response.status.clone().map_or(0, |status| status as u16),
+            status_text: ByteString::new(response.status_message.into_bytes()),
+            headers: JS::from_rooted(*headers),
+            body: DOMRefCell::new(match response.body {
+                BodyDone(body) | Receiving(body) => body,
+                Empty => vec!()
+            }),
+            body_used: Cell::new(false),
+        }
+    }
+
+    pub fn new(global: &GlobalRef, response: FetchResponse) -> Temporary<Response> {
+        reflect_dom_object(box Response::new_inherited(global, response),
+                           *global,
+                           ResponseBinding::Wrap)
+    }
+
+    /// Settles the promise that `fetch()` returned with the response of the fetch.
+    pub fn handle_fetch_response(address: TrustedPromiseAddress, response: FetchResponse) {
+        let promise = unsafe { JS::from_trusted_promise_address(address).root() };
+        if response.is_network_error() {
+            promise.reject_error(Type("The fetch failed"));
+        } else {
+            let global = global_object_for_js_object(promise.reflector().get_jsobject()).root();
+            let response = Response::new(&global.root_ref(), response).root();
+            promise.resolve(response.to_jsval(global.root_ref().get_cx()));
+        }
+        promise.release_once();
+    }
+}
+
+trait PrivateResponseHelpers {
+    /// Reads the body, as the [consume body]
+    /// (http://fetch.spec.whatwg.org/#concept-body-consume-body) algorithm does.
+    fn consume_body(self, body_type: BodyType) -> Temporary<Promise>;
+}
+
+impl<'a> PrivateResponseHelpers for JSRef<'a, Response> {
+    fn consume_body(self, body_type: BodyType) -> Temporary<Promise> {
+        let global = self.global.root();
+        let promise = Promise::new(&global.root_ref()).root();
+        if self.body_used.get() {
+            promise.reject_error(Type("The body has already been read"));
+            return Temporary::from_rooted(*promise);
+        }
+        self.body_used.set(true);
+
+        let body = mem::replace(&mut *self.body.borrow_mut(), vec!());
+        let text = UTF_8.decode(body.as_slice(), DecodeReplace).unwrap();
+        let cx = global.root_ref().get_cx();
+        match body_type {
+            TextBody => promise.resolve(text.to_jsval(cx)),
+            JSONBody => {
+                let text: Vec<u16> = text.as_slice().utf16_units().collect();
+                let mut value = UndefinedValue();
+                unsafe {
+                    if JS_ParseJSON(cx, text.as_ptr(), text.len() as u32, &mut value) != 0 {
+                        promise.resolve(value);
+                    } else {
+                        let mut exception = UndefinedValue();
+                        JS_GetPendingException(cx, &mut exception);
+                        JS_ClearPendingException(cx);
+                        promise.reject(exception);
+                    }
+                }
+            }
+        }
+        Temporary::from_rooted(*promise)
+    }
+}
+
+impl<'a> ResponseMethods for JSRef<'a, Response> {
+    fn Type(self) -> ResponseType {
+        self.response_type
+    }
+
+    fn Url(self) -> DOMString {
+        self.url.clone()
+    }
+
+    fn Status(self) -> u16 {
+        self.status
+    }
+
+    // http://fetch.spec.whatwg.org/#dom-response-ok
+    fn Ok(self) -> bool {
+        self.status >= 200 && self.status <= 299
+    }
+
+    fn StatusText(self) -> ByteString {
+        self.status_text.clone()
+    }
+
+    fn Headers(self) -> Temporary<Headers> {
+        Temporary::new(self.headers)
+    }
+
+    fn BodyUsed(self) -> bool {
+        self.body_used.get()
+    }
+
+    fn Json(self) -> Temporary<Promise> {
+        self.consume_body(JSONBody)
+    }
+
+    fn Text(self) -> Temporary<Promise> {
+        self.consume_body(TextBody)
+    }
+}
+
+impl Reflectable for Response {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        &self.reflector_
+    }
+}
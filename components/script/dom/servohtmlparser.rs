@@ -7,20 +7,26 @@
 
 use dom::bindings::cell::DOMRefCell;
 use dom::bindings::codegen::Bindings::ServoHTMLParserBinding;
+use dom::bindings::codegen::InheritTypes::HTMLScriptElementCast;
 use dom::bindings::global;
 use dom::bindings::trace::JSTraceable;
-use dom::bindings::js::{JS, JSRef, Temporary};
+use dom::bindings::js::{JS, JSRef, Temporary, Root};
 use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
-use dom::node::TrustedNodeAddress;
+use dom::node::{Node, NodeHelpers, TrustedNodeAddress};
 use dom::document::{Document, DocumentHelpers};
+use dom::htmlscriptelement::{HTMLScriptElement, HTMLScriptElementHelpers};
 use parse::Parser;
 
 use servo_util::task_state;
+use servo_util::task_state::IN_HTML_PARSER;
 
+use std::ascii::AsciiExt;
+use std::cell::Cell;
 use std::default::Default;
 use url::Url;
 use js::jsapi::JSTracer;
 use html5ever::tokenizer;
+use html5ever::tokenizer::TokenizerOpts;
 use html5ever::tree_builder;
 use html5ever::tree_builder::{TreeBuilder, TreeBuilderOpts};
 
@@ -29,10 +35,17 @@ use html5ever::tree_builder::{TreeBuilder, TreeBuilderOpts};
 pub struct Sink {
     pub base_url: Option<Url>,
     pub document: JS<Document>,
+    /// The scripts that the tree builder has finished, which the parser prepares once the
+    /// tokenizer returns.
+    pub pending_scripts: Vec<TrustedNodeAddress>,
 }
 
 pub type Tokenizer = tokenizer::Tokenizer<TreeBuilder<TrustedNodeAddress, Sink>>;
 
+/// How deeply scripts run by the parser may nest through `document.write` before further writes
+/// are ignored, so that a script that writes itself can't recurse forever.
+static MAX_SCRIPT_NESTING_LEVEL: uint = 32;
+
 // NB: JSTraceable is *not* auto-derived.
 // You must edit the impl below if you add fields!
 #[must_root]
@@ -40,26 +53,77 @@ pub type Tokenizer = tokenizer::Tokenizer<TreeBuilder<TrustedNodeAddress, Sink>>
 pub struct ServoHTMLParser {
     reflector_: Reflector,
     tokenizer: DOMRefCell<Tokenizer>,
+    /// https://html.spec.whatwg.org/multipage/syntax.html#script-nesting-level
+    script_nesting_level: Cell<uint>,
+    /// Whether the parser was created by `document.open`
+    /// (https://html.spec.whatwg.org/multipage/syntax.html#script-created-parser).
+    script_created: bool,
+    /// The document that the parser builds.
+    document: JS<Document>,
 }
 
 impl Parser for ServoHTMLParser{
     fn parse_chunk(&self, input: String) {
-        self.tokenizer().borrow_mut().feed(input);
+        self.tokenize(input);
     }
     fn finish(&self){
         self.tokenizer().borrow_mut().end();
+        self.run_pending_scripts();
+        let document = self.document.root();
+        document.set_current_parser(None);
     }
 }
 
 impl ServoHTMLParser {
-    #[allow(unrooted_must_root)]
     pub fn new(base_url: Option<Url>, document: JSRef<Document>) -> Temporary<ServoHTMLParser> {
-        let window = document.window().root();
-        let sink = Sink {
+        ServoHTMLParser::new_with_sink(ServoHTMLParser::sink(base_url, document), document, false)
+    }
+
+    /// A [script-created parser]
+    /// (https://html.spec.whatwg.org/multipage/syntax.html#script-created-parser), which
+    /// `document.open` creates to parse what scripts write.
+    pub fn new_script_created(base_url: Option<Url>, document: JSRef<Document>)
+                              -> Temporary<ServoHTMLParser> {
+        ServoHTMLParser::new_with_sink(ServoHTMLParser::sink(base_url, document), document, true)
+    }
+
+    /// A parser for the [HTML fragment parsing algorithm]
+    /// (https://html.spec.whatwg.org/multipage/syntax.html#parsing-html-fragments), which
+    /// builds the fragment in `document` as though it were parsed inside `context`.
+    #[allow(unrooted_must_root)]
+    pub fn new_for_fragment(base_url: Option<Url>, document: JSRef<Document>,
+                            context: JSRef<Node>, form: Option<JSRef<Node>>)
+                            -> Temporary<ServoHTMLParser> {
+        let tb = TreeBuilder::new_for_fragment(ServoHTMLParser::sink(base_url, document),
+                                               context.to_trusted_node_address(),
+                                               form.map(|form| form.to_trusted_node_address()),
+                                               TreeBuilderOpts {
+                                                   ignore_missing_rules: true,
+                                                   .. Default::default()
+                                               });
+
+        // The tokenizer starts in the state that the context element's content is parsed in.
+        let initial_state = tb.tokenizer_state_for_context_elem();
+        let tok = tokenizer::Tokenizer::new(tb, TokenizerOpts {
+            initial_state: Some(initial_state),
+            .. Default::default()
+        });
+
+        ServoHTMLParser::new_with_tokenizer(tok, document, false)
+    }
+
+    #[allow(unrooted_must_root)]
+    fn sink(base_url: Option<Url>, document: JSRef<Document>) -> Sink {
+        Sink {
             base_url: base_url,
             document: JS::from_rooted(document),
-        };
+            pending_scripts: vec!(),
+        }
+    }
 
+    #[allow(unrooted_must_root)]
+    fn new_with_sink(sink: Sink, document: JSRef<Document>, script_created: bool)
+                     -> Temporary<ServoHTMLParser> {
         let tb = TreeBuilder::new(sink, TreeBuilderOpts {
             ignore_missing_rules: true,
             .. Default::default()
@@ -67,20 +131,123 @@ impl ServoHTMLParser {
 
         let tok = tokenizer::Tokenizer::new(tb, Default::default());
 
+        ServoHTMLParser::new_with_tokenizer(tok, document, script_created)
+    }
+
+    #[allow(unrooted_must_root)]
+    fn new_with_tokenizer(tok: Tokenizer, document: JSRef<Document>, script_created: bool)
+                          -> Temporary<ServoHTMLParser> {
+        let window = document.window().root();
         let parser = ServoHTMLParser {
             reflector_: Reflector::new(),
             tokenizer: DOMRefCell::new(tok),
+            script_nesting_level: Cell::new(0),
+            script_created: script_created,
+            document: JS::from_rooted(document),
         };
 
         reflect_dom_object(box parser, global::Window(*window), ServoHTMLParserBinding::Wrap)
     }
 
+    /// Whether `document.write` inserts into this parser's input, rather than opening the
+    /// document again.
+    pub fn can_write(&self) -> bool {
+        self.script_created || self.script_nesting_level.get() > 0
+    }
+
+    pub fn is_script_created(&self) -> bool {
+        self.script_created
+    }
+
+    /// Insert `input` at the insertion point, and parse it before anything after it
+    /// (https://html.spec.whatwg.org/multipage/webappapis.html#dom-document-write, step 6).
+    pub fn write(&self, input: String) {
+        if self.script_nesting_level.get() >= MAX_SCRIPT_NESTING_LEVEL {
+            warn!("ignoring document.write nested more than {} deep", MAX_SCRIPT_NESTING_LEVEL);
+            return;
+        }
+        // Scripts run while the tokenizer isn't borrowed, and it has consumed exactly the input
+        // up to the end of the script, so what is fed now is parsed at the insertion point.
+        self.tokenize(input);
+    }
+
+    /// Tokenize `input`, running each script that the tree builder finishes as the parser
+    /// reaches it. The tokenizer consumes all the input that it is fed before it returns, so
+    /// the input is fed in pieces that end after script end tags, and the scripts run between
+    /// the pieces, where they can write to the parser.
+    fn tokenize(&self, input: String) {
+        // Writes from scripts that run after parsing are tokenized here too.
+        let entered = !task_state::get().contains(IN_HTML_PARSER);
+        if entered {
+            task_state::enter(IN_HTML_PARSER);
+        }
+        for piece in split_after_script_end_tags(input).into_iter() {
+            self.tokenizer.borrow_mut().feed(piece);
+            self.run_pending_scripts();
+        }
+        if entered {
+            task_state::exit(IN_HTML_PARSER);
+        }
+    }
+
+    fn run_pending_scripts(&self) {
+        loop {
+            let script = {
+                let mut tokenizer = self.tokenizer.borrow_mut();
+                tokenizer.sink_mut().sink_mut().pending_scripts.remove(0)
+            };
+            let script = match script {
+                Some(script) => script,
+                None => break,
+            };
+            let script: Root<Node> = unsafe { JS::from_trusted_node_address(script).root() };
+            let script: JSRef<HTMLScriptElement> = HTMLScriptElementCast::to_ref(*script)
+                .expect("the tree builder finished something that isn't a script");
+            let level = self.script_nesting_level.get();
+            self.script_nesting_level.set(level + 1);
+            script.prepare();
+            self.script_nesting_level.set(level);
+        }
+    }
+
     #[inline]
     pub fn tokenizer<'a>(&'a self) -> &'a DOMRefCell<Tokenizer> {
         &self.tokenizer
     }
 }
 
+/// Splits `input` after each `</script ...>` end tag, and after the first `>`, which may end a
+/// script end tag that started in the previous chunk of input.
+fn split_after_script_end_tags(input: String) -> Vec<String> {
+    // ASCII lowercasing keeps the byte offsets the same.
+    let lower = input.as_slice().to_ascii_lower();
+    let mut pieces = vec!();
+    let mut start = 0u;
+    match lower.as_slice().find('>') {
+        Some(index) => {
+            pieces.push(input.as_slice().slice_to(index + 1).to_string());
+            start = index + 1;
+        }
+        None => (),
+    }
+    loop {
+        let rest = lower.as_slice().slice_from(start);
+        let end = match rest.find_str("</script") {
+            Some(tag) => match rest.slice_from(tag).find('>') {
+                Some(close) => start + tag + close + 1,
+                None => break,
+            },
+            None => break,
+        };
+        pieces.push(input.as_slice().slice(start, end).to_string());
+        start = end;
+    }
+    if start < input.len() {
+        pieces.push(input.as_slice().slice_from(start).to_string());
+    }
+    pieces
+}
+
 impl Reflectable for ServoHTMLParser {
     fn reflector<'a>(&'a self) -> &'a Reflector {
         &self.reflector_
@@ -100,6 +267,7 @@ impl tree_builder::Tracer<TrustedNodeAddress> for Tracer {
 impl JSTraceable for ServoHTMLParser {
     fn trace(&self, trc: *mut JSTracer) {
         self.reflector_.trace(trc);
+        self.document.trace(trc);
 
         let tracer = Tracer {
             trc: trc,
@@ -0,0 +1,274 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::Bindings::EventHandlerBinding::EventHandlerNonNull;
+use dom::bindings::codegen::Bindings::SharedWorkerBinding;
+use dom::bindings::codegen::Bindings::SharedWorkerBinding::SharedWorkerMethods;
+use dom::bindings::codegen::Bindings::EventTargetBinding::EventTargetMethods;
+use dom::bindings::codegen::InheritTypes::EventTargetCast;
+use dom::bindings::error::{Fallible, Syntax, Security};
+use dom::bindings::global::{GlobalRef, GlobalField};
+use dom::bindings::js::{JS, JSRef, Temporary};
+use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
+use dom::event::{Event, DoesNotBubble, NotCancelable};
+use dom::eventtarget::{EventTarget, EventTargetHelpers, SharedWorkerTypeId};
+use dom::messageport::{MessagePort, PortEndpoint};
+use dom::sharedworkerglobalscope::SharedWorkerGlobalScope;
+use dom::urlhelper::UrlHelper;
+use dom::worker::WorkerTerminator;
+use debugger;
+use script_task::{ScriptChan, SharedWorkerConnectMsg, SharedWorkerConnectedMsg};
+
+use servo_msg::constellation_msg::PipelineId;
+use servo_util::str::DOMString;
+
+use js::jsapi::{JS_AddObjectRoot, JS_RemoveObjectRoot};
+
+use libc::c_void;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use url::UrlParser;
+
+/// Identifies a shared worker: the origin of the documents that use it, the URL of its script,
+/// and its name.
+pub type SharedWorkerKey = (String, String, DOMString);
+
+pub struct TrustedSharedWorkerAddress(pub *const c_void);
+no_jsmanaged_fields!(TrustedSharedWorkerAddress)
+
+/// A SharedWorker object's request to connect to a shared worker.
+pub struct SharedWorkerConnection {
+    /// The end of the channel that the worker's port represents.
+    endpoint: PortEndpoint,
+    /// The task of the SharedWorker object, and that object, which is rooted until the worker
+    /// is done with the connection.
+    owner: (ScriptChan, TrustedSharedWorkerAddress),
+}
+
+impl SharedWorkerConnection {
+    /// Called by the worker when it handles the connection.
+    pub fn accept(self) -> PortEndpoint {
+        self.notify_owner(false);
+        self.endpoint
+    }
+
+    /// Called by a worker that stops before handling the connection, which disentangles the
+    /// SharedWorker object's port. If the worker stops because its script couldn't be loaded,
+    /// an error event is fired at the SharedWorker object.
+    pub fn reject(self, load_failed: bool) {
+        self.endpoint.close();
+        self.notify_owner(load_failed);
+    }
+
+    fn notify_owner(&self, load_failed: bool) {
+        let (ScriptChan(ref sender), address) = self.owner;
+        let _ = sender.send_opt(SharedWorkerConnectedMsg(address, load_failed));
+    }
+}
+
+/// A shared worker that is running, on a task of its own.
+struct RunningSharedWorker {
+    /// Tells apart the workers that have been started for the same key.
+    id: uint,
+    /// Sender to the Receiver of the worker task.
+    sender: ScriptChan,
+    /// Stops the worker, even while its script is busy.
+    terminator: WorkerTerminator,
+    /// The pipelines of the documents that use the worker, once per SharedWorker object they
+    /// created. The worker stops when there are none left.
+    owners: Vec<PipelineId>,
+}
+
+/// The shared workers that the documents of a script task have started.
+pub struct SharedWorkerRegistry {
+    workers: HashMap<SharedWorkerKey, RunningSharedWorker>,
+    next_id: uint,
+}
+
+/// The registry of a script task, which its shared workers also hold on to, so that they can
+/// unregister themselves.
+pub type SharedWorkers = Arc<Mutex<SharedWorkerRegistry>>;
+
+local_data_key!(TaskSharedWorkers: SharedWorkers)
+
+fn shared_workers() -> SharedWorkers {
+    if TaskSharedWorkers.get().is_none() {
+        TaskSharedWorkers.replace(Some(Arc::new(Mutex::new(SharedWorkerRegistry {
+            workers: HashMap::new(),
+            next_id: 0,
+        }))));
+    }
+    TaskSharedWorkers.get().unwrap().clone()
+}
+
+/// Called by a shared worker that stops, so that the next SharedWorker objects with its key
+/// start a new worker. Returns once no more connections can be sent to the worker.
+pub fn unregister_shared_worker(workers: &SharedWorkers, key: &SharedWorkerKey, id: uint) {
+    let mut registry = workers.lock();
+    let is_current = match registry.workers.get(key) {
+        Some(worker) => worker.id == id,
+        None => false,
+    };
+    if is_current {
+        registry.workers.remove(key);
+    }
+}
+
+/// Called when the document of a pipeline goes away. The shared workers that no other document
+/// uses stop.
+pub fn remove_shared_worker_owner(pipeline: PipelineId) {
+    let workers = shared_workers();
+    let mut registry = workers.lock();
+    let mut unused = vec!();
+    for (key, worker) in registry.workers.iter_mut() {
+        worker.owners.retain(|owner| *owner != pipeline);
+        if worker.owners.is_empty() {
+            unused.push(key.clone());
+        }
+    }
+    for key in unused.iter() {
+        registry.workers.remove(key).unwrap().terminator.terminate();
+    }
+}
+
+#[dom_struct]
+pub struct SharedWorker {
+    eventtarget: EventTarget,
+    global: GlobalField,
+    /// The port through which the page talks to the worker.
+    port: JS<MessagePort>,
+}
+
+impl SharedWorker {
+    fn new_inherited(global: &GlobalRef, port: JSRef<MessagePort>) -> SharedWorker {
+        SharedWorker {
+            eventtarget: EventTarget::new_inherited(SharedWorkerTypeId),
+            global: GlobalField::from_rooted(global),
+            port: JS::from_rooted(port),
+        }
+    }
+
+    pub fn new(global: &GlobalRef, port: JSRef<MessagePort>) -> Temporary<SharedWorker> {
+        reflect_dom_object(box SharedWorker::new_inherited(global, port),
+                           *global,
+                           SharedWorkerBinding::Wrap)
+    }
+
+    // http://www.whatwg.org/html/#dom-sharedworker
+    pub fn Constructor(global: &GlobalRef, scriptURL: DOMString, name: DOMString)
+                       -> Fallible<Temporary<SharedWorker>> {
+        // Step 2-4.
+        let worker_url = match UrlParser::new().base_url(&global.get_url())
+                .parse(scriptURL.as_slice()) {
+            Ok(url) => url,
+            Err(_) => return Err(Syntax),
+        };
+
+        // Step 5.
+        let origin = UrlHelper::Origin(&global.get_url());
+        if UrlHelper::Origin(&worker_url) != origin {
+            return Err(Security);
+        }
+
+        // Step 6-7.
+        let (outside, inside) = PortEndpoint::new_pair();
+        let port = MessagePort::new(global, outside).root();
+        let worker = SharedWorker::new(global, *port).root();
+        let connection = SharedWorkerConnection {
+            endpoint: inside,
+            owner: (global.script_chan().clone(), worker.root_until_connected()),
+        };
+
+        // Step 8.
+        let key = (origin, worker_url.serialize(), name.clone());
+        let workers = shared_workers();
+        let mut registry = workers.lock();
+        let connection = match registry.workers.get_mut(&key) {
+            Some(running) => {
+                let ScriptChan(ref sender) = running.sender;
+                match sender.send_opt(SharedWorkerConnectMsg(connection)) {
+                    Ok(()) => {
+                        running.owners.push(global.pipeline());
+                        return Ok(Temporary::from_rooted(*worker));
+                    }
+                    // The worker has stopped without unregistering itself.
+                    Err(SharedWorkerConnectMsg(connection)) => connection,
+                    Err(_) => unreachable!(),
+                }
+            }
+            None => connection,
+        };
+
+        debugger::note_worker_script(&worker_url);
+
+        // The first connection is handled once the worker's script has run.
+        let (receiver, sender) = ScriptChan::new();
+        {
+            let ScriptChan(ref chan) = sender;
+            chan.send(SharedWorkerConnectMsg(connection));
+        }
+
+        let terminator = WorkerTerminator::new(sender.clone());
+        let id = registry.next_id;
+        registry.next_id += 1;
+        registry.workers.insert(key.clone(), RunningSharedWorker {
+            id: id,
+            sender: sender.clone(),
+            terminator: terminator.clone(),
+            owners: vec!(global.pipeline()),
+        });
+
+        SharedWorkerGlobalScope::run_worker_scope(
+            worker_url, key, id, global.resource_task(), global.devtools_chan(),
+            global.pipeline(), sender, receiver, terminator, workers.clone());
+
+        Ok(Temporary::from_rooted(*worker))
+    }
+
+    /// Unroots a SharedWorker object once the worker is done with its connection.
+    // http://www.whatwg.org/html/#dom-sharedworker step 8
+    pub fn handle_connected(address: TrustedSharedWorkerAddress, load_failed: bool) {
+        let worker = unsafe { JS::from_trusted_shared_worker_address(address).root() };
+        let global = worker.global.root();
+        unsafe {
+            JS_RemoveObjectRoot(global.root_ref().get_cx(), worker.reflector().rootable());
+        }
+
+        if load_failed {
+            let event = Event::new(global.root_ref(), "error".to_string(),
+                                   DoesNotBubble, NotCancelable).root();
+            let target: JSRef<EventTarget> = EventTargetCast::from_ref(*worker);
+            let _ = target.DispatchEvent(*event);
+        }
+    }
+}
+
+trait PrivateSharedWorkerHelpers {
+    fn root_until_connected(self) -> TrustedSharedWorkerAddress;
+}
+
+impl<'a> PrivateSharedWorkerHelpers for JSRef<'a, SharedWorker> {
+    /// Roots the object, so that it can be told whether the worker's script loaded.
+    fn root_until_connected(self) -> TrustedSharedWorkerAddress {
+        let global = self.global.root();
+        unsafe {
+            JS_AddObjectRoot(global.root_ref().get_cx(), self.reflector().rootable());
+        }
+        TrustedSharedWorkerAddress(self.deref() as *const SharedWorker as *const c_void)
+    }
+}
+
+impl<'a> SharedWorkerMethods for JSRef<'a, SharedWorker> {
+    fn Port(self) -> Temporary<MessagePort> {
+        Temporary::new(self.port)
+    }
+
+    event_handler!(error, GetOnerror, SetOnerror)
+}
+
+impl Reflectable for SharedWorker {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        self.eventtarget.reflector()
+    }
+}
@@ -0,0 +1,264 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::codegen::Bindings::EventHandlerBinding::EventHandlerNonNull;
+use dom::bindings::codegen::Bindings::SharedWorkerGlobalScopeBinding;
+use dom::bindings::codegen::Bindings::SharedWorkerGlobalScopeBinding::SharedWorkerGlobalScopeMethods;
+use dom::bindings::codegen::InheritTypes::SharedWorkerGlobalScopeDerived;
+use dom::bindings::codegen::InheritTypes::{EventCast, EventTargetCast, WorkerGlobalScopeCast};
+use dom::bindings::conversions::ToJSValConvertible;
+use dom::bindings::global;
+use dom::bindings::js::{JSRef, Temporary, RootCollection};
+use dom::bindings::utils::{Reflectable, Reflector};
+use dom::dedicatedworkerglobalscope::{RunningWorker, interrupt_worker};
+use dom::errorevent::ErrorEvent;
+use dom::event::Event;
+use dom::eventtarget::{EventTarget, EventTargetHelpers};
+use dom::eventtarget::WorkerGlobalScopeTypeId;
+use dom::messageevent::MessageEvent;
+use dom::messageport::{MessagePort, PortEndpoint};
+use dom::sharedworker::{SharedWorker, SharedWorkerKey, SharedWorkers, unregister_shared_worker};
+use dom::worker::{Worker, WorkerTerminator};
+use dom::workerglobalscope::SharedGlobalScope;
+use dom::workerglobalscope::{WorkerGlobalScope, WorkerGlobalScopeHelpers};
+use dom::workerglobalscope::{set_error_reporter, take_pending_error};
+use dom::xmlhttprequest::XMLHttpRequest;
+use script_task::{ScriptTask, ScriptChan, ScriptMsg, StackRootTLS};
+use script_task::{FromWorker, FireTimerMsg, XHRProgressMsg, XHRReleaseMsg};
+use script_task::{WorkerPostMessage, WorkerRelease, WorkerErrorMsg, WorkerLoadErrorMsg};
+use script_task::{MessagePortMsg, SharedWorkerConnectMsg, SharedWorkerConnectedMsg};
+use script_task::WorkerTerminateMsg;
+
+use devtools_traits::DevtoolsControlChan;
+
+use servo_msg::constellation_msg::PipelineId;
+use servo_net::fetch::request::SharedWorker as SharedWorkerContext;
+use servo_net::resource_task::{ResourceTask, load_whole_resource};
+use servo_util::str::DOMString;
+use servo_util::task::spawn_named_native;
+use servo_util::task_state;
+use servo_util::task_state::{SCRIPT, IN_WORKER};
+
+use js::jsapi::JS_SetOperationCallback;
+use js::rust::Cx;
+
+use std::rc::Rc;
+use url::Url;
+
+#[dom_struct]
+pub struct SharedWorkerGlobalScope {
+    workerglobalscope: WorkerGlobalScope,
+    receiver: Receiver<ScriptMsg>,
+    name: DOMString,
+}
+
+impl SharedWorkerGlobalScope {
+    fn new_inherited(worker_url: Url,
+                     name: DOMString,
+                     cx: Rc<Cx>,
+                     resource_task: ResourceTask,
+                     devtools_chan: Option<DevtoolsControlChan>,
+                     pipeline: PipelineId,
+                     own_sender: ScriptChan,
+                     receiver: Receiver<ScriptMsg>)
+                     -> SharedWorkerGlobalScope {
+        SharedWorkerGlobalScope {
+            workerglobalscope: WorkerGlobalScope::new_inherited(
+                SharedGlobalScope, worker_url, cx, resource_task,
+                devtools_chan, pipeline, own_sender),
+            receiver: receiver,
+            name: name,
+        }
+    }
+
+    pub fn new(worker_url: Url,
+               name: DOMString,
+               cx: Rc<Cx>,
+               resource_task: ResourceTask,
+               devtools_chan: Option<DevtoolsControlChan>,
+               pipeline: PipelineId,
+               own_sender: ScriptChan,
+               receiver: Receiver<ScriptMsg>)
+               -> Temporary<SharedWorkerGlobalScope> {
+        let scope = box SharedWorkerGlobalScope::new_inherited(
+            worker_url, name, cx.clone(), resource_task, devtools_chan, pipeline,
+            own_sender, receiver);
+        SharedWorkerGlobalScopeBinding::Wrap(cx.ptr, scope)
+    }
+}
+
+impl SharedWorkerGlobalScope {
+    /// Runs a shared worker on a task of its own. The worker doesn't belong to the pipeline that
+    /// started it: it keeps running, and accepting connections from the SharedWorker objects
+    /// with its key, until it calls `close()` or none of the documents that use it remain.
+    pub fn run_worker_scope(worker_url: Url,
+                            key: SharedWorkerKey,
+                            id: uint,
+                            resource_task: ResourceTask,
+                            devtools_chan: Option<DevtoolsControlChan>,
+                            pipeline: PipelineId,
+                            own_sender: ScriptChan,
+                            receiver: Receiver<ScriptMsg>,
+                            terminator: WorkerTerminator,
+                            workers: SharedWorkers) {
+        spawn_named_native(format!("SharedWorker for {}", worker_url.serialize()), proc() {
+
+            task_state::initialize(SCRIPT | IN_WORKER);
+
+            let roots = RootCollection::new();
+            let _stack_roots_tls = StackRootTLS::new(&roots);
+
+            let (url, source) = match load_whole_resource(&resource_task, worker_url.clone(),
+                                                          SharedWorkerContext) {
+                Err(_) => {
+                    // The SharedWorker objects that are connecting get an error event.
+                    unregister_shared_worker(&workers, &key, id);
+                    reject_pending_connections(&receiver, true);
+                    return;
+                }
+                Ok((metadata, bytes)) => {
+                    (metadata.final_url, String::from_utf8_lossy(bytes.as_slice()).into_string())
+                }
+            };
+
+            let (js_runtime, js_context) = ScriptTask::new_rt_and_cx();
+            RunningWorker.replace(Some(terminator.clone()));
+            terminator.set_runtime(Some(js_runtime.ptr));
+            unsafe {
+                JS_SetOperationCallback(js_context.ptr, Some(interrupt_worker));
+            }
+            set_error_reporter(js_context.ptr);
+
+            let (_, _, ref name) = key;
+            let global = SharedWorkerGlobalScope::new(
+                worker_url, name.clone(), js_context.clone(), resource_task, devtools_chan,
+                pipeline, own_sender, receiver).root();
+            let scope: JSRef<WorkerGlobalScope> =
+                WorkerGlobalScopeCast::from_ref(*global);
+
+            // The worker may have been stopped before its runtime existed.
+            if !terminator.is_terminated() {
+                // An uncaught exception is reported as an error event below.
+                let _ = js_context.evaluate_script(
+                    global.reflector().get_jsobject(), source, url.serialize(), 1);
+                global.report_pending_error();
+            }
+
+            while !scope.is_closing() && !terminator.is_terminated() {
+                match global.receiver.recv_opt() {
+                    Ok(SharedWorkerConnectMsg(connection)) => {
+                        global.connect(connection.accept());
+                    },
+                    Ok(XHRProgressMsg(addr, progress)) => {
+                        XMLHttpRequest::handle_progress(addr, progress)
+                    },
+                    Ok(XHRReleaseMsg(addr)) => {
+                        XMLHttpRequest::handle_release(addr)
+                    },
+                    Ok(WorkerPostMessage(addr, data)) => {
+                        Worker::handle_message(addr, data);
+                    },
+                    Ok(WorkerRelease(addr)) => {
+                        Worker::handle_release(addr)
+                    },
+                    Ok(WorkerErrorMsg(addr, error)) => {
+                        Worker::handle_error(addr, error)
+                    },
+                    Ok(WorkerLoadErrorMsg(addr)) => {
+                        Worker::handle_load_error(addr)
+                    },
+                    Ok(MessagePortMsg(addr)) => {
+                        MessagePort::handle_message(addr)
+                    },
+                    Ok(SharedWorkerConnectedMsg(addr, load_failed)) => {
+                        SharedWorker::handle_connected(addr, load_failed)
+                    },
+                    Ok(FireTimerMsg(FromWorker, timer_id)) => {
+                        scope.handle_fire_timer(timer_id);
+                    }
+                    Ok(WorkerTerminateMsg) => break,
+                    Ok(_) => panic!("Unexpected message"),
+                    Err(_) => break,
+                }
+                global.report_pending_error();
+            }
+
+            // No connection can be sent to the worker once it is unregistered.
+            unregister_shared_worker(&workers, &key, id);
+            reject_pending_connections(&global.receiver, false);
+
+            scope.terminate_child_workers();
+            terminator.set_runtime(None);
+        });
+    }
+}
+
+/// Rejects the connections of the SharedWorker objects that connected to a worker which stopped
+/// before handling them.
+fn reject_pending_connections(receiver: &Receiver<ScriptMsg>, load_failed: bool) {
+    loop {
+        match receiver.try_recv() {
+            Ok(SharedWorkerConnectMsg(connection)) => connection.reject(load_failed),
+            Ok(_) => (),
+            Err(_) => break,
+        }
+    }
+}
+
+impl<'a> SharedWorkerGlobalScopeMethods for JSRef<'a, SharedWorkerGlobalScope> {
+    fn Name(self) -> DOMString {
+        self.name.clone()
+    }
+
+    event_handler!(connect, GetOnconnect, SetOnconnect)
+}
+
+trait PrivateSharedWorkerGlobalScopeHelpers {
+    fn connect(self, endpoint: PortEndpoint);
+    fn report_pending_error(self);
+}
+
+impl<'a> PrivateSharedWorkerGlobalScopeHelpers for JSRef<'a, SharedWorkerGlobalScope> {
+    // http://www.whatwg.org/html/#dom-sharedworker step 8
+    fn connect(self, endpoint: PortEndpoint) {
+        let scope: JSRef<WorkerGlobalScope> = WorkerGlobalScopeCast::from_ref(self);
+        let target: JSRef<EventTarget> = EventTargetCast::from_ref(self);
+        let port = MessagePort::new(&global::Worker(scope), endpoint);
+        let data = "".to_string().to_jsval(scope.get_cx());
+        let event = MessageEvent::new(global::Worker(scope), "connect".to_string(),
+                                      false, false, data, "".to_string(), "".to_string(),
+                                      None, vec!(port)).root();
+        let event: JSRef<Event> = EventCast::from_ref(*event);
+        target.dispatch_event_with_target(None, event).unwrap();
+    }
+
+    // Shared workers have no parent to report their errors to, so they are only reported to the
+    // worker's own `onerror` handler.
+    fn report_pending_error(self) {
+        match take_pending_error() {
+            Some(error) => {
+                let scope: JSRef<WorkerGlobalScope> = WorkerGlobalScopeCast::from_ref(self);
+                let target: JSRef<EventTarget> = EventTargetCast::from_ref(self);
+                ErrorEvent::dispatch(target, &global::Worker(scope), &error);
+                take_pending_error();
+            }
+            None => (),
+        }
+    }
+}
+
+impl Reflectable for SharedWorkerGlobalScope {
+    fn reflector<'a>(&'a self) -> &'a Reflector {
+        self.workerglobalscope.reflector()
+    }
+}
+
+impl SharedWorkerGlobalScopeDerived for EventTarget {
+    fn is_sharedworkerglobalscope(&self) -> bool {
+        match *self.type_id() {
+            WorkerGlobalScopeTypeId(SharedGlobalScope) => true,
+            _ => false
+        }
+    }
+}
@@ -28,6 +28,15 @@ impl UrlHelper {
         }
     }
 
+    /// The [ASCII serialization](http://tools.ietf.org/html/rfc6454#section-6.2) of the origin.
+    pub fn Origin(url: &Url) -> DOMString {
+        let host = url.serialize_host().unwrap_or("".to_string());
+        match url.port() {
+            Some(port) => format!("{}://{}:{}", url.scheme, host, port),
+            None => format!("{}://{}", url.scheme, host)
+        }
+    }
+
     /// https://html.spec.whatwg.org/multipage/browsers.html#same-origin
     pub fn SameOrigin(urlA: &Url, urlB: &Url) -> bool {
         if urlA.host() != urlB.host() {
@@ -5,37 +5,50 @@
 use dom::bindings::cell::DOMRefCell;
 use dom::bindings::codegen::Bindings::EventHandlerBinding::{OnErrorEventHandlerNonNull, EventHandlerNonNull};
 use dom::bindings::codegen::Bindings::FunctionBinding::Function;
+use dom::bindings::codegen::Bindings::RequestBinding::RequestInit;
 use dom::bindings::codegen::Bindings::WindowBinding;
 use dom::bindings::codegen::Bindings::WindowBinding::WindowMethods;
-use dom::bindings::codegen::InheritTypes::EventTargetCast;
-use dom::bindings::error::{Fallible, InvalidCharacter};
+use dom::bindings::codegen::InheritTypes::{EventCast, EventTargetCast};
+use dom::bindings::codegen::UnionTypes::RequestOrString::RequestOrString;
+use dom::bindings::error::{ErrorResult, Fallible, InvalidCharacter, Syntax};
 use dom::bindings::global;
+use dom::bindings::global::global_object_for_js_object;
 use dom::bindings::js::{MutNullableJS, JSRef, Temporary};
+use dom::bindings::structuredclone::StructuredCloneData;
 use dom::bindings::utils::{Reflectable, Reflector};
 use dom::browsercontext::BrowserContext;
 use dom::console::Console;
 use dom::document::Document;
+use dom::event::Event;
 use dom::eventtarget::{EventTarget, WindowTypeId, EventTargetHelpers};
 use dom::location::Location;
+use dom::messageevent::MessageEvent;
 use dom::navigator::Navigator;
 use dom::performance::Performance;
+use dom::promise::{Promise, PromiseHelpers};
+use dom::request::{Request, RequestHelpers};
 use dom::screen::Screen;
 use dom::storage::Storage;
+use dom::urlhelper::UrlHelper;
+use dom::worker::WorkerTerminator;
 use layout_interface::NoQuery;
 use page::Page;
-use script_task::{ExitWindowMsg, ScriptChan, TriggerLoadMsg, TriggerFragmentMsg};
+use script_task::{ExitWindowMsg, FetchResponseMsg, ScriptChan, TriggerLoadMsg};
+use script_task::TriggerFragmentMsg;
 use script_task::FromWindow;
 use script_traits::ScriptControlChan;
 use timers::{Interval, NonInterval, TimerId, TimerManager};
 
 use servo_msg::compositor_msg::ScriptListener;
-use servo_msg::constellation_msg::LoadData;
+use servo_msg::constellation_msg::{ConstellationChan, LoadData, PipelineId, PostMessageMsg};
 use servo_net::image_cache_task::ImageCacheTask;
+use servo_net::resource_task::fetch;
 use servo_net::storage_task::StorageTask;
 use servo_util::str::{DOMString,HTML_SPACE_CHARACTERS};
+use servo_util::task::spawn_named;
 
-use js::jsapi::JS_EvaluateUCScript;
-use js::jsapi::JSContext;
+use js::jsapi::{JS_EvaluateUCScript, JS_GetGlobalForScopeChain};
+use js::jsapi::{JSContext, JSObject};
 use js::jsapi::{JS_GC, JS_GetRuntime};
 use js::jsval::{JSVal, UndefinedValue};
 use js::rust::with_compartment;
@@ -43,6 +56,7 @@ use url::{Url, UrlParser};
 
 use libc;
 use serialize::base64::{FromBase64, ToBase64, STANDARD};
+use std::any::Any;
 use std::cell::{Ref, RefMut};
 use std::default::Default;
 use std::rc::Rc;
@@ -65,7 +79,9 @@ pub struct Window {
     navigation_start_precise: f64,
     screen: MutNullableJS<Screen>,
     session_storage: MutNullableJS<Storage>,
-    timers: TimerManager
+    timers: TimerManager,
+    /// The dedicated workers started by this window, which stop when its pipeline exits.
+    child_workers: DOMRefCell<Vec<WorkerTerminator>>
 }
 
 impl Window {
@@ -98,6 +114,16 @@ impl Window {
         &*self.page
     }
 
+    pub fn add_child_worker(&self, terminator: WorkerTerminator) {
+        self.child_workers.borrow_mut().push(terminator);
+    }
+
+    pub fn terminate_child_workers(&self) {
+        for terminator in self.child_workers.borrow().iter() {
+            terminator.terminate();
+        }
+    }
+
     pub fn navigation_start(&self) -> u64 {
         self.navigation_start
     }
@@ -273,6 +299,8 @@ impl<'a> WindowMethods for JSRef<'a, Window> {
     event_handler!(click, GetOnclick, SetOnclick)
     event_handler!(load, GetOnload, SetOnload)
     event_handler!(unload, GetOnunload, SetOnunload)
+    event_handler!(message, GetOnmessage, SetOnmessage)
+    event_handler!(messageerror, GetOnmessageerror, SetOnmessageerror)
     error_event_handler!(error, GetOnerror, SetOnerror)
 
     fn Screen(self) -> Temporary<Screen> {
@@ -296,6 +324,81 @@ impl<'a> WindowMethods for JSRef<'a, Window> {
     fn Atob(self, atob: DOMString) -> Fallible<DOMString> {
         base64_atob(atob)
     }
+
+    // http://www.whatwg.org/html/#dom-window-postmessage
+    fn PostMessage(self, cx: *mut JSContext, message: JSVal, targetOrigin: DOMString,
+                   transfer: Option<Vec<*mut JSObject>>) -> ErrorResult {
+        // Step 1-3.
+        let source = global_object_for_js_object(unsafe { JS_GetGlobalForScopeChain(cx) });
+        let source = source.root();
+        let origin = UrlHelper::Origin(&source.root_ref().get_url());
+        let target_origin = match targetOrigin.as_slice() {
+            "*" => None,
+            "/" => Some(origin.clone()),
+            _ => match Url::parse(targetOrigin.as_slice()) {
+                Ok(url) => Some(UrlHelper::Origin(&url)),
+                Err(_) => return Err(Syntax),
+            },
+        };
+
+        // Step 4.
+        let data = try!(StructuredCloneData::write(cx, message, transfer));
+
+        // Step 5. The message goes through the constellation, which knows the task of the
+        // target window.
+        let message = box PostedMessage {
+            data: data,
+            origin: origin,
+            source: source.root_ref().pipeline(),
+            target_origin: target_origin,
+        };
+        let ConstellationChan(ref chan) = self.page().constellation_chan;
+        chan.send(PostMessageMsg(self.page().id, message as Box<Any+Send>));
+        Ok(())
+    }
+
+    // http://fetch.spec.whatwg.org/#dom-global-fetch
+    fn Fetch(self, input: RequestOrString, init: &RequestInit) -> Temporary<Promise> {
+        // Step 1
+        let global = global::Window(self);
+        let promise = Promise::new(&global).root();
+        // Step 2
+        let request = match Request::Constructor(&global, input, init) {
+            Ok(request) => request.root(),
+            Err(error) => {
+                promise.reject_error(error);
+                return Temporary::from_rooted(*promise);
+            }
+        };
+        // Step 3. The promise stays rooted until the FetchResponseMsg settles it.
+        let request = request.to_fetch_request(&self.get_url());
+        let resource_task = self.page().resource_task.clone();
+        let ScriptChan(chan) = self.script_chan().clone();
+        let address = unsafe { promise.to_trusted() };
+        spawn_named("Window:Fetch", proc() {
+            let response = fetch(&resource_task, request);
+            chan.send(FetchResponseMsg(address, response));
+        });
+        Temporary::from_rooted(*promise)
+    }
+}
+
+/// A message posted to a window, on its way to the task of that window.
+pub struct PostedMessage {
+    data: StructuredCloneData,
+    /// The origin of the window that posted the message.
+    origin: DOMString,
+    /// The pipeline of the window that posted the message.
+    pub source: PipelineId,
+    /// The origin that the window must have for the message to be delivered, if any.
+    target_origin: Option<DOMString>,
+}
+
+impl PostedMessage {
+    /// Drops a message that won't be delivered.
+    pub fn discard(self) {
+        self.data.discard();
+    }
 }
 
 impl Reflectable for Window {
@@ -313,6 +416,7 @@ pub trait WindowHelpers {
     fn handle_fire_timer(self, timer_id: TimerId);
     fn evaluate_js_with_result(self, code: &str) -> JSVal;
     fn evaluate_script_with_result(self, code: &str, filename: &str) -> JSVal;
+    fn dispatch_posted_message(self, message: PostedMessage, source: Option<JSRef<Window>>);
 }
 
 
@@ -377,6 +481,29 @@ impl<'a> WindowHelpers for JSRef<'a, Window> {
         self.timers.fire_timer(timer_id, self.clone());
         self.flush_layout();
     }
+
+    // http://www.whatwg.org/html/#dom-window-postmessage step 6
+    fn dispatch_posted_message(self, message: PostedMessage, source: Option<JSRef<Window>>) {
+        let PostedMessage { data, origin, target_origin, .. } = message;
+        match target_origin {
+            Some(ref target_origin) if *target_origin != UrlHelper::Origin(&self.get_url()) => {
+                data.discard();
+                return;
+            }
+            _ => (),
+        }
+
+        let target: JSRef<EventTarget> = EventTargetCast::from_ref(self);
+        let (type_, data, ports) = match data.read(global::Window(self)) {
+            Ok((data, ports)) => ("message", data, ports),
+            Err(()) => ("messageerror", UndefinedValue(), vec!()),
+        };
+        let messageevent = MessageEvent::new(global::Window(self), type_.to_string(),
+                                             false, false, data, origin, "".to_string(),
+                                             source, ports).root();
+        let event: JSRef<Event> = EventCast::from_ref(*messageevent);
+        target.dispatch_event_with_target(None, event).unwrap();
+    }
 }
 
 impl Window {
@@ -403,7 +530,8 @@ impl Window {
             navigation_start_precise: time::precise_time_s(),
             screen: Default::default(),
             session_storage: Default::default(),
-            timers: TimerManager::new()
+            timers: TimerManager::new(),
+            child_workers: DOMRefCell::new(vec!())
         };
 
         WindowBinding::Wrap(cx, win)
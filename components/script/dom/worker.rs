@@ -5,54 +5,125 @@
 use dom::bindings::codegen::Bindings::WorkerBinding;
 use dom::bindings::codegen::Bindings::WorkerBinding::WorkerMethods;
 use dom::bindings::codegen::Bindings::EventHandlerBinding::EventHandlerNonNull;
-use dom::bindings::codegen::InheritTypes::EventTargetCast;
-use dom::bindings::error::{Fallible, Syntax, ErrorResult, DataClone};
+use dom::bindings::codegen::Bindings::EventTargetBinding::EventTargetMethods;
+use dom::bindings::codegen::InheritTypes::{DedicatedWorkerGlobalScopeCast, EventTargetCast};
+use dom::bindings::error::{Fallible, Syntax, ErrorResult};
+use dom::bindings::global;
 use dom::bindings::global::{GlobalRef, GlobalField};
 use dom::bindings::js::{JS, JSRef, Temporary};
+use dom::bindings::structuredclone::StructuredCloneData;
 use dom::bindings::trace::JSTraceable;
 use dom::bindings::utils::{Reflectable, Reflector, reflect_dom_object};
 use dom::dedicatedworkerglobalscope::DedicatedWorkerGlobalScope;
+use dom::dedicatedworkerglobalscope::DedicatedWorkerGlobalScopeHelpers;
+use dom::errorevent::{ErrorEvent, ErrorInfo};
+use dom::event::{Event, DoesNotBubble, NotCancelable};
 use dom::eventtarget::{EventTarget, EventTargetHelpers, WorkerTypeId};
 use dom::messageevent::MessageEvent;
-use script_task::{ScriptChan, DOMMessage};
+use debugger;
+use script_task::{ScriptChan, ScriptMsg, DOMMessage, WorkerTerminateMsg};
 
 use servo_util::str::DOMString;
 
-use js::glue::JS_STRUCTURED_CLONE_VERSION;
-use js::jsapi::{JSContext, JS_AddObjectRoot, JS_RemoveObjectRoot, JSTracer};
-use js::jsapi::{JS_ReadStructuredClone, JS_WriteStructuredClone, JS_ClearPendingException};
-use js::jsval::{JSVal, UndefinedValue};
+use js::jsapi::{JSContext, JSObject, JSRuntime, JS_AddObjectRoot, JS_RemoveObjectRoot};
+use js::jsapi::JSTracer;
+use js::jsapi::JS_TriggerOperationCallback;
+use js::jsval::JSVal;
 use url::UrlParser;
 
-use libc::{c_void, size_t};
+use libc::c_void;
 use std::cell::Cell;
-use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, SeqCst};
 
 pub struct TrustedWorkerAddress(pub *const c_void);
 no_jsmanaged_fields!(TrustedWorkerAddress)
 
+/// Lets the scope that started a worker stop the task running the worker's script, even while
+/// the script is running.
+#[deriving(Clone)]
+pub struct WorkerTerminator {
+    terminated: Arc<AtomicBool>,
+    /// The runtime of the worker task, while it exists.
+    runtime: Arc<Mutex<Option<*mut JSRuntime>>>,
+    /// Set by the worker task before it drains its Receiver for the last time.
+    closed: Arc<Mutex<bool>>,
+    /// Sender to the Receiver of the worker task.
+    sender: ScriptChan,
+}
+
+no_jsmanaged_fields!(WorkerTerminator)
+
+impl WorkerTerminator {
+    pub fn new(sender: ScriptChan) -> WorkerTerminator {
+        WorkerTerminator {
+            terminated: Arc::new(AtomicBool::new(false)),
+            runtime: Arc::new(Mutex::new(None)),
+            closed: Arc::new(Mutex::new(false)),
+            sender: sender,
+        }
+    }
+
+    // http://www.whatwg.org/html/#terminate-a-worker
+    pub fn terminate(&self) {
+        self.terminated.store(true, SeqCst);
+        // The operation callback of the worker task aborts the running script.
+        match *self.runtime.lock() {
+            Some(runtime) => unsafe { JS_TriggerOperationCallback(runtime) },
+            None => (),
+        }
+        let ScriptChan(ref sender) = self.sender;
+        let _ = sender.send_opt(WorkerTerminateMsg);
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.terminated.load(SeqCst)
+    }
+
+    /// Sends a message to the worker task, unless it has stopped. Returns whether the worker
+    /// task will see the message.
+    pub fn send(&self, msg: ScriptMsg) -> bool {
+        let closed = self.closed.lock();
+        if *closed || self.is_terminated() {
+            return false;
+        }
+        let ScriptChan(ref sender) = self.sender;
+        sender.send_opt(msg).is_ok()
+    }
+
+    /// Called by the worker task before it drains its Receiver for the last time, so that no
+    /// message arrives after that.
+    pub fn close(&self) {
+        *self.closed.lock() = true;
+    }
+
+    /// Called by the worker task when it has created its runtime, and with `None` before it
+    /// destroys it.
+    pub fn set_runtime(&self, runtime: Option<*mut JSRuntime>) {
+        *self.runtime.lock() = runtime;
+    }
+}
+
 #[dom_struct]
 pub struct Worker {
     eventtarget: EventTarget,
     refcount: Cell<uint>,
     global: GlobalField,
-    /// Sender to the Receiver associated with the DedicatedWorkerGlobalScope
-    /// this Worker created.
-    sender: ScriptChan,
+    terminator: WorkerTerminator,
 }
 
 impl Worker {
-    fn new_inherited(global: &GlobalRef, sender: ScriptChan) -> Worker {
+    fn new_inherited(global: &GlobalRef, terminator: WorkerTerminator) -> Worker {
         Worker {
             eventtarget: EventTarget::new_inherited(WorkerTypeId),
             refcount: Cell::new(0),
             global: GlobalField::from_rooted(global),
-            sender: sender,
+            terminator: terminator,
         }
     }
 
-    pub fn new(global: &GlobalRef, sender: ScriptChan) -> Temporary<Worker> {
-        reflect_dom_object(box Worker::new_inherited(global, sender),
+    pub fn new(global: &GlobalRef, terminator: WorkerTerminator) -> Temporary<Worker> {
+        reflect_dom_object(box Worker::new_inherited(global, terminator),
                            *global,
                            WorkerBinding::Wrap)
     }
@@ -66,35 +137,91 @@ impl Worker {
             Err(_) => return Err(Syntax),
         };
 
+        debugger::note_worker_script(&worker_url);
+
         let resource_task = global.resource_task();
         let (receiver, sender) = ScriptChan::new();
+        let terminator = WorkerTerminator::new(sender.clone());
 
-        let worker = Worker::new(global, sender.clone()).root();
+        let worker = Worker::new(global, terminator.clone()).root();
+        // The worker task releases this reference after everything else it sends, so that the
+        // Worker outlives the messages and errors that are queued for it.
         let worker_ref = worker.addref();
 
+        // Workers stop when the worker that started them does, or when the pipeline of the
+        // window that started them exits.
+        match *global {
+            global::Worker(scope) => scope.add_child_worker(terminator.clone()),
+            global::Window(window) => window.add_child_worker(terminator.clone()),
+        }
+
         DedicatedWorkerGlobalScope::run_worker_scope(
-            worker_url, worker_ref, resource_task, global.script_chan().clone(),
-            sender, receiver);
+            worker_url, worker_ref, resource_task, global.devtools_chan(), global.pipeline(),
+            global.script_chan().clone(), sender, receiver, terminator);
 
         Ok(Temporary::from_rooted(*worker))
     }
 
-    pub fn handle_message(address: TrustedWorkerAddress,
-                          data: *mut u64, nbytes: size_t) {
+    pub fn handle_message(address: TrustedWorkerAddress, data: StructuredCloneData) {
         let worker = unsafe { JS::from_trusted_worker_address(address).root() };
+        if worker.terminator.is_terminated() {
+            return;
+        }
 
         let global = worker.global.root();
+        let target: JSRef<EventTarget> = EventTargetCast::from_ref(*worker);
+        match data.read(global.root_ref()) {
+            Ok((message, ports)) => {
+                MessageEvent::dispatch_jsval(target, global.root_ref(), message, ports)
+            }
+            Err(()) => MessageEvent::dispatch_error(target, global.root_ref()),
+        }
+    }
+
+    // http://www.whatwg.org/html/#run-a-worker step 4
+    pub fn handle_load_error(address: TrustedWorkerAddress) {
+        let worker = unsafe { JS::from_trusted_worker_address(address).root() };
+        if worker.terminator.is_terminated() {
+            return;
+        }
+
+        let global = worker.global.root();
+        let event = Event::new(global.root_ref(), "error".to_string(),
+                               DoesNotBubble, NotCancelable).root();
+        let target: JSRef<EventTarget> = EventTargetCast::from_ref(*worker);
+        let _ = target.DispatchEvent(*event);
+    }
 
-        let mut message = UndefinedValue();
-        unsafe {
-            assert!(JS_ReadStructuredClone(
-                global.root_ref().get_cx(), data as *const u64, nbytes,
-                JS_STRUCTURED_CLONE_VERSION, &mut message,
-                ptr::null(), ptr::null_mut()) != 0);
+    // http://www.whatwg.org/html/#runtime-script-errors-2
+    pub fn handle_error(address: TrustedWorkerAddress, error: ErrorInfo) {
+        let worker = unsafe { JS::from_trusted_worker_address(address).root() };
+        if worker.terminator.is_terminated() {
+            return;
         }
 
+        let global = worker.global.root();
         let target: JSRef<EventTarget> = EventTargetCast::from_ref(*worker);
-        MessageEvent::dispatch_jsval(target, global.root_ref(), message);
+        if !ErrorEvent::dispatch(target, &global.root_ref(), &error) {
+            return;
+        }
+
+        // Nothing handled the error, so it is reported in the scope that started the worker.
+        match global.root_ref() {
+            global::Window(window) => {
+                let target: JSRef<EventTarget> = EventTargetCast::from_ref(window);
+                ErrorEvent::dispatch(target, &global.root_ref(), &error);
+            }
+            global::Worker(scope) => {
+                match DedicatedWorkerGlobalScopeCast::to_ref(scope) {
+                    Some(scope) => scope.report_error(error),
+                    // Shared workers have nowhere to report their errors to.
+                    None => {
+                        let target: JSRef<EventTarget> = EventTargetCast::from_ref(scope);
+                        ErrorEvent::dispatch(target, &global.root_ref(), &error);
+                    }
+                }
+            }
+        }
     }
 }
 
@@ -131,25 +258,25 @@ impl Worker {
 }
 
 impl<'a> WorkerMethods for JSRef<'a, Worker> {
-    fn PostMessage(self, cx: *mut JSContext, message: JSVal) -> ErrorResult {
-        let mut data = ptr::null_mut();
-        let mut nbytes = 0;
-        let result = unsafe {
-            JS_WriteStructuredClone(cx, message, &mut data, &mut nbytes,
-                                    ptr::null(), ptr::null_mut())
-        };
-        if result == 0 {
-            unsafe { JS_ClearPendingException(cx); }
-            return Err(DataClone);
-        }
+    fn PostMessage(self, cx: *mut JSContext, message: JSVal,
+                   transfer: Option<Vec<*mut JSObject>>) -> ErrorResult {
+        let data = try!(StructuredCloneData::write(cx, message, transfer));
 
-        self.addref();
-        let ScriptChan(ref sender) = self.sender;
-        sender.send(DOMMessage(data, nbytes));
+        // Messages to a worker that has stopped are dropped.
+        if self.terminator.send(DOMMessage(data)) {
+            self.addref();
+        }
         Ok(())
     }
 
+    // http://www.whatwg.org/html/#dom-worker-terminate
+    fn Terminate(self) {
+        self.terminator.terminate();
+    }
+
     event_handler!(message, GetOnmessage, SetOnmessage)
+    event_handler!(messageerror, GetOnmessageerror, SetOnmessageerror)
+    event_handler!(error, GetOnerror, SetOnerror)
 }
 
 impl Reflectable for Worker {
@@ -2,35 +2,96 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use dom::bindings::cell::DOMRefCell;
+use dom::bindings::codegen::Bindings::EventHandlerBinding::OnErrorEventHandlerNonNull;
 use dom::bindings::codegen::Bindings::WorkerGlobalScopeBinding::WorkerGlobalScopeMethods;
 use dom::bindings::codegen::Bindings::FunctionBinding::Function;
+use dom::bindings::codegen::InheritTypes::EventTargetCast;
 use dom::bindings::error::{ErrorResult, Fallible, Syntax, Network, FailureUnknown};
 use dom::bindings::global;
 use dom::bindings::js::{MutNullableJS, JSRef, Temporary, OptionalSettable};
 use dom::bindings::utils::{Reflectable, Reflector};
 use dom::console::Console;
-use dom::eventtarget::{EventTarget, WorkerGlobalScopeTypeId};
+use dom::errorevent::ErrorInfo;
+use dom::eventtarget::{EventTarget, EventTargetHelpers, WorkerGlobalScopeTypeId};
+use dom::worker::WorkerTerminator;
 use dom::workerlocation::WorkerLocation;
 use dom::workernavigator::WorkerNavigator;
 use dom::window::{base64_atob, base64_btoa};
 use script_task::{ScriptChan, FromWorker};
 use timers::{Interval, NonInterval, TimerId, TimerManager};
 
+use devtools_traits::DevtoolsControlChan;
+
+use servo_msg::constellation_msg::PipelineId;
+use servo_net::fetch::request::Script as ScriptContext;
 use servo_net::resource_task::{ResourceTask, load_whole_resource};
 use servo_util::str::DOMString;
 
-use js::jsapi::JSContext;
+use js::jsapi::{JSContext, JSErrorReport, JS_SetErrorReporter};
 use js::jsval::JSVal;
-use js::rust::Cx;
+use js::rust::{Cx, reportError};
 
+use libc::{c_char, c_uint};
+use std::cell::Cell;
 use std::default::Default;
 use std::rc::Rc;
+use std::string;
 use url::{Url, UrlParser};
 
+/// The flag of `JSErrorReport` that marks warnings.
+static JSREPORT_WARNING: c_uint = 0x1;
+
+/// The last uncaught error in the script of the worker running on this task, which hasn't been
+/// reported to `onerror` handlers yet.
+local_data_key!(PendingError: ErrorInfo)
+
+/// Logs uncaught errors like in the other script tasks, and remembers them so that they can be
+/// reported to `onerror` handlers once the script has returned.
+extern fn report_worker_error(cx: *mut JSContext, message: *const c_char,
+                              report: *mut JSErrorReport) {
+    reportError(cx, message, report);
+    unsafe {
+        if report.is_null() || (*report).flags & JSREPORT_WARNING != 0 {
+            return;
+        }
+
+        PendingError.replace(Some(ErrorInfo {
+            message: c_str_to_string(message),
+            filename: c_str_to_string((*report).filename),
+            lineno: (*report).lineno as u32,
+            column: (*report).column as u32,
+        }));
+    }
+}
+
+unsafe fn c_str_to_string(s: *const c_char) -> DOMString {
+    if s.is_null() {
+        "".to_string()
+    } else {
+        string::raw::from_buf(s as *const u8)
+    }
+}
+
+/// Makes the uncaught errors of the worker running on this task available through
+/// `take_pending_error`.
+pub fn set_error_reporter(cx: *mut JSContext) {
+    unsafe {
+        JS_SetErrorReporter(cx, Some(report_worker_error));
+    }
+}
+
+/// Returns the last uncaught error of the worker running on this task, if it hasn't been taken
+/// yet.
+pub fn take_pending_error() -> Option<ErrorInfo> {
+    PendingError.replace(None)
+}
+
 #[deriving(PartialEq)]
 #[jstraceable]
 pub enum WorkerGlobalScopeId {
     DedicatedGlobalScope,
+    SharedGlobalScope,
 }
 
 #[dom_struct]
@@ -39,11 +100,18 @@ pub struct WorkerGlobalScope {
     worker_url: Url,
     js_context: Rc<Cx>,
     resource_task: ResourceTask,
+    /// Where console messages are reported, along with the pipeline that started the worker.
+    devtools_chan: Option<DevtoolsControlChan>,
+    pipeline: PipelineId,
     script_chan: ScriptChan,
     location: MutNullableJS<WorkerLocation>,
     navigator: MutNullableJS<WorkerNavigator>,
     console: MutNullableJS<Console>,
     timers: TimerManager,
+    /// Set by `close()`; the worker stops once the script that called it returns.
+    closing: Cell<bool>,
+    /// The workers started by this worker, which are terminated when it stops.
+    child_workers: DOMRefCell<Vec<WorkerTerminator>>,
 }
 
 impl WorkerGlobalScope {
@@ -51,17 +119,23 @@ impl WorkerGlobalScope {
                          worker_url: Url,
                          cx: Rc<Cx>,
                          resource_task: ResourceTask,
+                         devtools_chan: Option<DevtoolsControlChan>,
+                         pipeline: PipelineId,
                          script_chan: ScriptChan) -> WorkerGlobalScope {
         WorkerGlobalScope {
             eventtarget: EventTarget::new_inherited(WorkerGlobalScopeTypeId(type_id)),
             worker_url: worker_url,
             js_context: cx,
             resource_task: resource_task,
+            devtools_chan: devtools_chan,
+            pipeline: pipeline,
             script_chan: script_chan,
             location: Default::default(),
             navigator: Default::default(),
             console: Default::default(),
-            timers: TimerManager::new()
+            timers: TimerManager::new(),
+            closing: Cell::new(false),
+            child_workers: DOMRefCell::new(vec!()),
         }
     }
 
@@ -85,6 +159,28 @@ impl WorkerGlobalScope {
     pub fn script_chan<'a>(&'a self) -> &'a ScriptChan {
         &self.script_chan
     }
+
+    pub fn devtools_chan<'a>(&'a self) -> &'a Option<DevtoolsControlChan> {
+        &self.devtools_chan
+    }
+
+    pub fn pipeline(&self) -> PipelineId {
+        self.pipeline
+    }
+
+    pub fn is_closing(&self) -> bool {
+        self.closing.get()
+    }
+
+    pub fn add_child_worker(&self, terminator: WorkerTerminator) {
+        self.child_workers.borrow_mut().push(terminator);
+    }
+
+    pub fn terminate_child_workers(&self) {
+        for terminator in self.child_workers.borrow().iter() {
+            terminator.terminate();
+        }
+    }
 }
 
 impl<'a> WorkerGlobalScopeMethods for JSRef<'a, WorkerGlobalScope> {
@@ -92,6 +188,13 @@ impl<'a> WorkerGlobalScopeMethods for JSRef<'a, WorkerGlobalScope> {
         Temporary::from_rooted(self)
     }
 
+    // http://www.whatwg.org/html/#dom-workerglobalscope-close
+    fn Close(self) {
+        self.closing.set(true);
+    }
+
+    error_event_handler!(error, GetOnerror, SetOnerror)
+
     fn Location(self) -> Temporary<WorkerLocation> {
         if self.location.get().is_none() {
             let location = WorkerLocation::new(self, self.worker_url.clone());
@@ -112,7 +215,7 @@ impl<'a> WorkerGlobalScopeMethods for JSRef<'a, WorkerGlobalScope> {
         }
 
         for url in urls.into_iter() {
-            let (url, source) = match load_whole_resource(&self.resource_task, url) {
+            let (url, source) = match load_whole_resource(&self.resource_task, url, ScriptContext) {
                 Err(_) => return Err(Network),
                 Ok((metadata, bytes)) => {
                     (metadata.final_url, String::from_utf8(bytes).unwrap())
@@ -42,8 +42,12 @@ use js::jsval::{JSVal, NullValue, UndefinedValue};
 use libc;
 use libc::c_void;
 
-use net::resource_task::{ResourceTask, ResourceCORSData, Load, LoadData, LoadResponse, Payload, Done};
-use cors::{allow_cross_origin_request, CORSRequest, CORSMode, ForcedPreflightMode};
+use net::fetch::request::{Request, CORSMode, ForcedPreflightMode, RefererUrl};
+use net::fetch::request::{CredentialsSameOrigin, Include};
+use net::fetch::request::XMLHttpRequest as XMLHttpRequestContext;
+use net::fetch::response::Response as FetchResponse;
+use net::fetch::response::{Chunk, Finished, Errored};
+use net::resource_task::{ResourceTask, Fetch};
 use script_task::{ScriptChan, XHRProgressMsg, XHRReleaseMsg};
 use servo_util::str::DOMString;
 use servo_util::task::spawn_named;
@@ -203,10 +207,8 @@ impl XMLHttpRequest {
         addr.release_once();
     }
 
-    fn fetch(fetch_type: &SyncOrAsync, resource_task: ResourceTask,
-             mut load_data: LoadData, terminate_receiver: Receiver<TerminateReason>,
-             cors_request: Result<Option<CORSRequest>,()>, gen_id: GenerationId,
-             start_port: Receiver<LoadResponse>) -> ErrorResult {
+    fn fetch(fetch_type: &SyncOrAsync, resource_task: ResourceTask, mut request: Request,
+             terminate_receiver: Receiver<TerminateReason>, gen_id: GenerationId) -> ErrorResult {
 
         fn notify_partial_progress(fetch_type: &SyncOrAsync, msg: XHRProgress) {
             match *fetch_type {
@@ -241,97 +243,59 @@ impl XMLHttpRequest {
             );
         )
 
-
-        match cors_request {
-            Err(_) => {
-                // Happens in case of cross-origin non-http URIs
-                notify_error_and_return!(Network);
-            }
-
-            Ok(Some(ref req)) => {
-                let (chan, cors_port) = channel();
-                let req2 = req.clone();
-                // TODO: this exists only to make preflight check non-blocking
-                // perhaps shoud be handled by the resource_loader?
-                spawn_named("XHR:Cors", proc() {
-                    let response = req2.http_fetch();
-                    chan.send(response);
-                });
-
-                select! (
-                    response = cors_port.recv() => {
-                        if response.network_error {
-                            notify_error_and_return!(Network);
-                        } else {
-                            load_data.cors = Some(ResourceCORSData {
-                                preflight: req.preflight_flag,
-                                origin: req.origin.clone()
-                            });
-                        }
-                    },
-                    reason = terminate_receiver.recv() => terminate!(reason)
-                )
-            }
-            _ => {}
-        }
-
         // Step 10, 13
-        resource_task.send(Load(load_data));
+        // The fetch takes care of the CORS preflight and of the CORS check of the response.
+        let (response_chan, response_port) = channel();
+        let (body_chan, body_port) = channel();
+        request.body_chan = Some(body_chan);
+        resource_task.send(Fetch(request, response_chan));
 
-
-        let progress_port;
+        let response;
         select! (
-            response = start_port.recv() => {
-                match cors_request {
-                    Ok(Some(ref req)) => {
-                        match response.metadata.headers {
-                            Some(ref h) if allow_cross_origin_request(req, h) => {},
-                            _ => notify_error_and_return!(Network)
-                        }
-                    },
-
-                    _ => {}
-                };
-                // XXXManishearth Clear cache entries in case of a network error
-                notify_partial_progress(fetch_type, HeadersReceivedMsg(gen_id,
-                    response.metadata.headers.clone(), response.metadata.status.clone()));
-
-                progress_port = response.progress_port;
+            fetched = response_port.recv_opt() => {
+                response = fetched.unwrap_or_else(|()| FetchResponse::network_error());
             },
             reason = terminate_receiver.recv() => terminate!(reason)
         )
 
+        if response.is_network_error() {
+            notify_error_and_return!(Network);
+        }
+
+        let status = response.status.clone().map(|status| {
+            RawStatus(status as u16, response.status_message.clone())
+        });
+        notify_partial_progress(fetch_type, HeadersReceivedMsg(gen_id,
+            Some(response.headers.clone()), status));
+
         let mut buf = vec!();
         loop {
-            // Under most circumstances, progress_port will contain lots of Payload
-            // events. Since select! does not have any fairness or priority, it
-            // might always remove the progress_port event, even when there is
-            // a terminate event waiting in the terminate_receiver. If this happens,
-            // a timeout or abort will take too long to be processed. To avoid this,
-            // in each iteration, we check for a terminate event before we block.
+            // Under most circumstances, body_port will contain lots of chunks. Since select!
+            // does not have any fairness or priority, it might always remove the body_port
+            // event, even when there is a terminate event waiting in the terminate_receiver.
+            // If this happens, a timeout or abort will take too long to be processed. To avoid
+            // this, in each iteration, we check for a terminate event before we block.
             match terminate_receiver.try_recv() {
                 Ok(reason) => terminate!(reason),
                 Err(_) => ()
             };
 
             select! (
-                progress = progress_port.recv() => match progress {
-                    Payload(data) => {
+                msg = body_port.recv_opt() => match msg {
+                    Ok(Chunk(data)) => {
                         buf.push_all(data.as_slice());
                         notify_partial_progress(fetch_type,
                                                 LoadingMsg(gen_id, ByteString::new(buf.clone())));
                     },
-                    Done(Ok(()))  => {
-                        notify_partial_progress(fetch_type, DoneMsg(gen_id));
-                        return Ok(());
-                    },
-                    Done(Err(_))  => {
-                        notify_error_and_return!(Network);
-                    }
+                    Ok(Finished) => break,
+                    Ok(Errored) | Err(()) => notify_error_and_return!(Network)
                 },
                 reason = terminate_receiver.recv() => terminate!(reason)
             )
         }
+
+        notify_partial_progress(fetch_type, DoneMsg(gen_id));
+        Ok(())
     }
 }
 
@@ -537,9 +501,9 @@ impl<'a> XMLHttpRequestMethods for JSRef<'a, XMLHttpRequest> {
 
         let global = self.global.root();
         let resource_task = global.root_ref().resource_task();
-        let (start_chan, start_port) = channel();
-        let mut load_data = LoadData::new(self.request_url.borrow().clone().unwrap(), start_chan);
-        load_data.data = extracted;
+        let mut request = Request::new(self.request_url.borrow().clone().unwrap(),
+                                       XMLHttpRequestContext);
+        request.body = extracted;
 
         // Default headers
         {
@@ -574,44 +538,34 @@ impl<'a> XMLHttpRequestMethods for JSRef<'a, XMLHttpRequest> {
             }
         } // drops the borrow_mut
 
-        load_data.headers = (*self.request_headers.borrow()).clone();
-        load_data.method = (*self.request_method.borrow()).clone();
+        request.headers = (*self.request_headers.borrow()).clone();
+        request.method = (*self.request_method.borrow()).clone();
         let (terminate_sender, terminate_receiver) = channel();
         *self.terminate_sender.borrow_mut() = Some(terminate_sender);
 
-        // CORS stuff
+        // The fetch adds the Origin and Referer headers.
         let referer_url = self.global.root().root_ref().get_url();
-        let mode = if self.upload_events.get() {
+        request.origin = Some(referer_url.clone());
+        request.referer = RefererUrl(referer_url);
+        request.unsafe_request = true;
+        request.mode = if self.upload_events.get() {
             ForcedPreflightMode
         } else {
             CORSMode
         };
-        let cors_request = CORSRequest::maybe_new(referer_url.clone(), load_data.url.clone(), mode,
-                                                  load_data.method.clone(), load_data.headers.clone());
-        match cors_request {
-            Ok(None) => {
-                let mut buf = String::new();
-                buf.push_str(referer_url.scheme.as_slice());
-                buf.push_str("://".as_slice());
-                referer_url.serialize_host().map(|ref h| buf.push_str(h.as_slice()));
-                referer_url.port().as_ref().map(|&p| {
-                    buf.push_str(":".as_slice());
-                    buf.push_str(format!("{:u}", p).as_slice());
-                });
-                referer_url.serialize_path().map(|ref h| buf.push_str(h.as_slice()));
-                self.request_headers.borrow_mut().set_raw("Referer".to_string(), vec![buf.into_bytes()]);
-            },
-            Ok(Some(ref req)) => self.insert_trusted_header("origin".to_string(),
-                                                            format!("{}", req.origin)),
-            _ => {}
-        }
+        request.credentials_mode = if self.with_credentials.get() {
+            Include
+        } else {
+            CredentialsSameOrigin
+        };
+        request.sync = self.sync.get();
 
         debug!("request_headers = {}", *self.request_headers.borrow());
 
         let gen_id = self.generation_id.get();
         if self.sync.get() {
-            return XMLHttpRequest::fetch(&mut Sync(self), resource_task, load_data,
-                                         terminate_receiver, cors_request, gen_id, start_port);
+            return XMLHttpRequest::fetch(&mut Sync(self), resource_task, request,
+                                         terminate_receiver, gen_id);
         } else {
             self.fetch_time.set(time::now().to_timespec().sec);
             let script_chan = global.root_ref().script_chan().clone();
@@ -626,11 +580,9 @@ impl<'a> XMLHttpRequestMethods for JSRef<'a, XMLHttpRequest> {
             spawn_named("XHRTask", proc() {
                 let _ = XMLHttpRequest::fetch(&mut Async(addr, &script_chan),
                                               resource_task,
-                                              load_data,
+                                              request,
                                               terminate_receiver,
-                                              cors_request,
-                                              gen_id,
-                                              start_port);
+                                              gen_id);
                 let ScriptChan(ref chan) = script_chan;
                 chan.send(XHRReleaseMsg(addr));
             });
@@ -771,7 +723,6 @@ trait PrivateXMLHttpRequestHelpers {
     fn change_ready_state(self, XMLHttpRequestState);
     fn process_partial_response(self, progress: XHRProgress);
     fn terminate_ongoing_fetch(self);
-    fn insert_trusted_header(self, name: String, value: String);
     fn dispatch_progress_event(self, upload: bool, type_: DOMString, loaded: u64, total: Option<u64>);
     fn dispatch_upload_progress_event(self, type_: DOMString, partial_load: Option<u64>);
     fn dispatch_response_progress_event(self, type_: DOMString);
@@ -938,12 +889,6 @@ impl<'a> PrivateXMLHttpRequestHelpers for JSRef<'a, XMLHttpRequest> {
         self.terminate_sender.borrow().as_ref().map(|s| s.send_opt(AbortedOrReopened));
     }
 
-    fn insert_trusted_header(self, name: String, value: String) {
-        // Insert a header without checking spec-compliance
-        // Use for hardcoded headers
-        self.request_headers.borrow_mut().set_raw(name, vec![value.into_bytes()]);
-    }
-
     fn dispatch_progress_event(self, upload: bool, type_: DOMString, loaded: u64, total: Option<u64>) {
         let global = self.global.root();
         let upload_target = *self.upload.root();
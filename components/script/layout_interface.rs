@@ -16,7 +16,7 @@ use servo_util::geometry::Au;
 use std::any::{Any, AnyRefExt};
 use std::comm::{channel, Receiver, Sender};
 use std::boxed::BoxAny;
-use style::Stylesheet;
+use style::{MatchedRule, Stylesheet};
 use url::Url;
 
 pub use dom::node::TrustedNodeAddress;
@@ -29,6 +29,10 @@ pub enum Msg {
     /// Adds the given stylesheet to the document.
     LoadStylesheetMsg(Url),
 
+    /// Replaces the stylesheet at the given index, as listed by `LayoutRPC::stylesheets`, and
+    /// restyles the document at the next reflow.
+    ReplaceStylesheetMsg(uint, Stylesheet),
+
     /// Requests a reflow.
     ReflowMsg(Box<Reflow>),
 
@@ -66,12 +70,27 @@ pub trait LayoutRPC {
     /// Requests the node containing the point of interest
     fn hit_test(&self, node: TrustedNodeAddress, point: Point2D<f32>) -> Result<HitTestResponse, ()>;
     fn mouse_over(&self, node: TrustedNodeAddress, point: Point2D<f32>) -> Result<MouseOverResponse, ()>;
+    /// Requests the author stylesheets of the document, for the devtools style editor.
+    fn stylesheets(&self) -> StylesheetsResponse;
+    /// Requests the stylesheet rules that apply to an element, for the devtools inspector.
+    fn matched_rules(&self, node: TrustedNodeAddress) -> MatchedRulesResponse;
 }
 
 pub struct ContentBoxResponse(pub Rect<Au>);
 pub struct ContentBoxesResponse(pub Vec<Rect<Au>>);
 pub struct HitTestResponse(pub UntrustedNodeAddress);
 pub struct MouseOverResponse(pub Vec<UntrustedNodeAddress>);
+pub struct StylesheetsResponse(pub Vec<StylesheetSource>);
+pub struct MatchedRulesResponse(pub Vec<MatchedRule>);
+
+pub struct StylesheetSource {
+    /// The index of the stylesheet, in the order that stylesheets were added to layout.
+    pub index: uint,
+    pub url: Url,
+    /// The text of the stylesheet, if it was kept for the devtools.
+    pub source: Option<String>,
+    pub rule_count: uint,
+}
 
 /// Why we're doing reflow.
 #[deriving(PartialEq, Show)]
@@ -33,6 +33,7 @@ extern crate serialize;
 extern crate time;
 extern crate canvas;
 extern crate script_traits;
+extern crate webdriver_traits;
 #[phase(plugin)]
 extern crate "plugins" as servo_plugins;
 extern crate "net" as servo_net;
@@ -46,7 +47,6 @@ extern crate string_cache;
 #[phase(plugin)]
 extern crate string_cache_macros;
 
-pub mod cors;
 
 /// The implementation of the DOM.
 #[macro_escape]
@@ -65,6 +65,7 @@ pub mod dom {
         pub mod conversions;
         mod proxyhandler;
         pub mod str;
+        pub mod structuredclone;
         pub mod trace;
 
         /// Generated JS-Rust bindings.
@@ -109,6 +110,7 @@ pub mod dom {
     pub mod eventtarget;
     pub mod file;
     pub mod formdata;
+    pub mod headers;
     pub mod htmlanchorelement;
     pub mod htmlappletelement;
     pub mod htmlareaelement;
@@ -154,6 +156,7 @@ pub mod dom {
     pub mod htmloutputelement;
     pub mod htmlparagraphelement;
     pub mod htmlparamelement;
+    pub mod htmlpictureelement;
     pub mod htmlpreelement;
     pub mod htmlprogresselement;
     pub mod htmlquoteelement;
@@ -179,9 +182,12 @@ pub mod dom {
     pub mod htmlulistelement;
     pub mod htmlvideoelement;
     pub mod htmlunknownelement;
+    pub mod imagedata;
     pub mod keyboardevent;
     pub mod location;
+    pub mod messagechannel;
     pub mod messageevent;
+    pub mod messageport;
     pub mod mouseevent;
     pub mod namednodemap;
     pub mod navigator;
@@ -193,9 +199,14 @@ pub mod dom {
     pub mod performance;
     pub mod performancetiming;
     pub mod progressevent;
+    pub mod promise;
     pub mod range;
+    pub mod request;
+    pub mod response;
     pub mod screen;
     pub mod servohtmlparser;
+    pub mod sharedworker;
+    pub mod sharedworkerglobalscope;
     pub mod storage;
     pub mod text;
     pub mod treewalker;
@@ -224,4 +235,6 @@ pub mod page;
 pub mod script_task;
 mod timers;
 pub mod textinput;
+mod debugger;
 mod devtools;
+mod webdriver_handlers;
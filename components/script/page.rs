@@ -19,6 +19,7 @@ use layout_interface::{
 };
 use script_traits::{UntrustedNodeAddress, ScriptControlChan};
 
+use devtools_traits::DevtoolsControlChan;
 use geom::{Point2D, Rect, Size2D};
 use js::rust::Cx;
 use servo_msg::compositor_msg::PerformingLayout;
@@ -93,6 +94,9 @@ pub struct Page {
     /// A handle for communicating messages to the constellation task.
     pub constellation_chan: ConstellationChan,
 
+    /// A handle for reporting to the devtools server, if it is running.
+    pub devtools_chan: Option<DevtoolsControlChan>,
+
     // Child Pages.
     pub children: DOMRefCell<Vec<Rc<Page>>>,
 
@@ -143,6 +147,7 @@ impl Page {
            resource_task: ResourceTask,
            storage_task: StorageTask,
            constellation_chan: ConstellationChan,
+           devtools_chan: Option<DevtoolsControlChan>,
            js_context: Rc<Cx>) -> Page {
         let js_info = JSPageInfo {
             dom_static: GlobalStaticData(),
@@ -172,6 +177,7 @@ impl Page {
             resource_task: resource_task,
             storage_task: storage_task,
             constellation_chan: constellation_chan,
+            devtools_chan: devtools_chan,
             children: DOMRefCell::new(vec!()),
             damaged: Cell::new(false),
             pending_reflows: Cell::new(0),
@@ -3,16 +3,18 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use dom::attr::AttrHelpers;
+use dom::bindings::codegen::Bindings::DocumentBinding::DocumentMethods;
 use dom::bindings::codegen::Bindings::NodeBinding::NodeMethods;
 use dom::bindings::codegen::InheritTypes::{NodeCast, ElementCast, HTMLScriptElementCast};
+use dom::bindings::codegen::InheritTypes::HTMLFormElementCast;
 use dom::bindings::js::{JS, JSRef, Temporary, OptionalRootable, Root};
 use dom::comment::Comment;
-use dom::document::{Document, DocumentHelpers};
+use dom::document::{Document, DocumentHelpers, HTMLDocument, FromParser};
 use dom::documenttype::DocumentType;
 use dom::element::{Element, AttributeHandlers, ElementHelpers, ParserCreated};
 use dom::htmlscriptelement::HTMLScriptElement;
 use dom::htmlscriptelement::HTMLScriptElementHelpers;
-use dom::node::{Node, NodeHelpers, TrustedNodeAddress};
+use dom::node::{Node, NodeHelpers, TrustedNodeAddress, document_from_node, window_from_node};
 use dom::servohtmlparser;
 use dom::servohtmlparser::ServoHTMLParser;
 use dom::text::Text;
@@ -24,6 +26,7 @@ use encoding::types::{Encoding, DecodeReplace};
 
 use servo_net::resource_task::{Load, LoadData, Payload, Done, ResourceTask};
 use servo_msg::constellation_msg::LoadData as MsgLoadData;
+use servo_util::str::DOMString;
 use servo_util::task_state;
 use servo_util::task_state::IN_HTML_PARSER;
 use std::ascii::AsciiExt;
@@ -201,9 +204,9 @@ impl<'a> TreeSink<TrustedNodeAddress> for servohtmlparser::Sink {
     }
 
     fn complete_script(&mut self, node: TrustedNodeAddress) {
-        let node: Root<Node> = unsafe { JS::from_trusted_node_address(node).root() };
-        let script: Option<JSRef<HTMLScriptElement>> = HTMLScriptElementCast::to_ref(*node);
-        script.map(|script| script.prepare());
+        // `ServoHTMLParser` prepares the script once the tokenizer returns, so that the script
+        // can write to the parser.
+        self.pending_scripts.push(node);
     }
 }
 
@@ -254,6 +257,7 @@ pub fn parse_html(page: &Page,
 
     let parser = ServoHTMLParser::new(base_url.clone(), document).root();
     let parser: JSRef<ServoHTMLParser> = *parser;
+    document.set_current_parser(Some(parser));
 
     task_state::enter(IN_HTML_PARSER);
 
@@ -275,6 +279,8 @@ pub fn parse_html(page: &Page,
                                 // FIXME: use Vec<u8> (html5ever #34)
                                 let data = UTF_8.decode(data.as_slice(), DecodeReplace).unwrap();
                                 parser.parse_chunk(data);
+                                // Async scripts that have arrived run between chunks.
+                                document.execute_ready_asap_scripts();
                             }
                             Done(Err(err)) => {
                                 panic!("Failed to load page URL {:s}, error: {:s}", url.serialize(), err);
@@ -293,3 +299,50 @@ pub fn parse_html(page: &Page,
 
     debug!("finished parsing");
 }
+
+/// The [HTML fragment parsing algorithm]
+/// (https://html.spec.whatwg.org/multipage/syntax.html#parsing-html-fragments), which returns
+/// the nodes that `input` parses to inside `context`. They belong to a new document, so they
+/// have to be adopted before they are inserted anywhere.
+pub fn parse_html_fragment(context: JSRef<Node>, input: DOMString) -> Vec<Temporary<Node>> {
+    let window = window_from_node(context).root();
+    let context_document = document_from_node(context).root();
+    let url = context_document.url().clone();
+
+    // Steps 1-2.
+    let document = Document::new(*window, Some(url.clone()), HTMLDocument, None,
+                                 FromParser).root();
+    document.set_quirks_mode(context_document.quirks_mode());
+
+    // Steps 3-11: the tree builder creates the root element and finds the insertion mode, and
+    // the form element pointer is the nearest form to the context element.
+    let form = Some(context).into_iter().chain(context.ancestors())
+                            .find(|&node| HTMLFormElementCast::to_ref(node).is_some());
+    let parser = ServoHTMLParser::new_for_fragment(Some(url), *document, context, form).root();
+    let parser: JSRef<ServoHTMLParser> = *parser;
+
+    // Step 12. Scripts that the main parser runs may parse fragments.
+    let entered = !task_state::get().contains(IN_HTML_PARSER);
+    if entered {
+        task_state::enter(IN_HTML_PARSER);
+    }
+    parser.parse_chunk(input);
+    parser.finish();
+    if entered {
+        task_state::exit(IN_HTML_PARSER);
+    }
+
+    // Step 13.
+    let root = document.GetDocumentElement().expect("fragment parsing didn't create a root")
+                                            .root();
+    let root: JSRef<Node> = NodeCast::from_ref(*root);
+    root.children().map(|node| {
+        // Scripts inserted through markup never run, so make sure that they can't.
+        for descendant in node.traverse_preorder() {
+            let script: Option<JSRef<HTMLScriptElement>> =
+                HTMLScriptElementCast::to_ref(descendant);
+            script.map(|script| script.mark_already_started());
+        }
+        Temporary::from_rooted(node)
+    }).collect()
+}
@@ -11,21 +11,30 @@ use dom::bindings::codegen::Bindings::EventBinding::EventMethods;
 use dom::bindings::codegen::Bindings::EventTargetBinding::EventTargetMethods;
 use dom::bindings::codegen::Bindings::WindowBinding::WindowMethods;
 use dom::bindings::codegen::InheritTypes::{EventTargetCast, NodeCast, EventCast};
+use dom::bindings::codegen::InheritTypes::HTMLImageElementCast;
 use dom::bindings::conversions::{FromJSValConvertible, Empty};
 use dom::bindings::global;
 use dom::bindings::js::{JS, JSRef, RootCollection, Temporary, OptionalRootable};
+use dom::bindings::structuredclone::StructuredCloneData;
 use dom::bindings::trace::JSTraceable;
 use dom::bindings::utils::{wrap_for_same_compartment, pre_wrap};
 use dom::document::{Document, HTMLDocument, DocumentHelpers, FromParser};
 use dom::element::{Element, HTMLButtonElementTypeId, HTMLInputElementTypeId};
 use dom::element::{HTMLSelectElementTypeId, HTMLTextAreaElementTypeId, HTMLOptionElementTypeId};
 use dom::event::{Event, Bubbles, DoesNotBubble, Cancelable, NotCancelable};
+use dom::htmlimageelement::HTMLImageElementHelpers;
 use dom::uievent::UIEvent;
 use dom::eventtarget::{EventTarget, EventTargetHelpers};
 use dom::keyboardevent::KeyboardEvent;
 use dom::node;
 use dom::node::{ElementNodeTypeId, Node, NodeHelpers};
-use dom::window::{Window, WindowHelpers};
+use dom::window::{Window, WindowHelpers, PostedMessage};
+use dom::errorevent::ErrorInfo;
+use dom::messageport::{MessagePort, TrustedMessagePortAddress};
+use dom::promise::{Promise, TrustedPromiseAddress};
+use dom::response::Response;
+use dom::sharedworker::{SharedWorker, SharedWorkerConnection, TrustedSharedWorkerAddress};
+use dom::sharedworker::remove_shared_worker_owner;
 use dom::worker::{Worker, TrustedWorkerAddress};
 use dom::xmlhttprequest::{TrustedXHRAddress, XMLHttpRequest, XHRProgress};
 use parse::html::{InputString, InputUrl, parse_html};
@@ -33,16 +42,27 @@ use layout_interface::{ScriptLayoutChan, LayoutChan, NoQuery, ReflowForDisplay};
 use layout_interface;
 use page::{Page, IterablePage, Frame};
 use timers::TimerId;
+use debugger::{PausingScriptTask, ScriptDebugger};
 use devtools;
+use webdriver_handlers;
 
 use devtools_traits::{DevtoolsControlChan, DevtoolsControlPort, NewGlobal, GetRootNode};
+use devtools_traits::{NavigatedMsg, PipelineExitedMsg};
 use devtools_traits::{DevtoolScriptControlMsg, EvaluateJS, GetDocumentElement};
-use devtools_traits::{GetChildren, GetLayout, ModifyAttribute};
+use devtools_traits::{GetChildren, GetLayout, ModifyAttribute, AttachThread, DetachThread};
+use devtools_traits::{Interrupt, Resume, SetBreakpoint, RemoveBreakpoint, GetFrames, GetSources};
+use devtools_traits::{GetSourceText, PauseInfo, ResumeLimit, GetStylesheets, GetAppliedRules};
+use devtools_traits::{SetStylesheetText, SetRuleText, ModifyRuleProperties};
+use devtools_traits::ModifyStyleAttributeProperties;
+use webdriver_traits::{WebDriverScriptCommand, ExecuteScript, FindElementCSS, FindElementsCSS};
+use webdriver_traits::{GetElementText, GetElementAttribute, GetElementTagName, ClickElement};
+use webdriver_traits::{FocusElement, GetUrl, GetTitle};
 use script_traits::{CompositorEvent, ResizeEvent, ReflowEvent, ClickEvent, MouseDownEvent};
 use script_traits::{MouseMoveEvent, MouseUpEvent, ConstellationControlMsg, ScriptTaskFactory};
 use script_traits::{ResizeMsg, AttachLayoutMsg, LoadMsg, ViewportMsg, SendEventMsg};
 use script_traits::{ResizeInactiveMsg, ExitPipelineMsg, NewLayoutInfo, OpaqueScriptLayoutChannel};
 use script_traits::{ScriptControlChan, ReflowCompleteMsg, UntrustedNodeAddress, KeyEvent};
+use script_traits::{WebDriverCommandMsg, PostMessageMsg};
 use servo_msg::compositor_msg::{FinishedLoading, LayerId, Loading};
 use servo_msg::compositor_msg::{ScriptListener};
 use servo_msg::constellation_msg::{ConstellationChan, LoadCompleteMsg, LoadUrlMsg, NavigationDirection};
@@ -50,6 +70,7 @@ use servo_msg::constellation_msg::{LoadData, PipelineId, Failure, FailureMsg, Wi
 use servo_msg::constellation_msg::{KeyModifiers, SUPER, SHIFT, CONTROL, ALT, Repeated, Pressed};
 use servo_msg::constellation_msg::{Released};
 use servo_msg::constellation_msg;
+use servo_net::fetch::response::Response as FetchResponse;
 use servo_net::image_cache_task::ImageCacheTask;
 use servo_net::resource_task::ResourceTask;
 use servo_net::storage_task::StorageTask;
@@ -67,8 +88,8 @@ use js::rust::{Cx, RtUtils};
 use js;
 use url::Url;
 
-use libc::size_t;
 use std::any::{Any, AnyRefExt};
+use std::boxed::BoxAny;
 use std::collections::HashSet;
 use std::comm::{channel, Sender, Receiver, Select};
 use std::mem::replace;
@@ -107,11 +128,36 @@ pub enum ScriptMsg {
     XHRReleaseMsg(TrustedXHRAddress),
     /// Message sent through Worker.postMessage (only dispatched to
     /// DedicatedWorkerGlobalScope).
-    DOMMessage(*mut u64, size_t),
+    DOMMessage(StructuredCloneData),
     /// Posts a message to the Worker object (dispatched to all tasks).
-    WorkerPostMessage(TrustedWorkerAddress, *mut u64, size_t),
+    WorkerPostMessage(TrustedWorkerAddress, StructuredCloneData),
     /// Releases one reference to the Worker object (dispatched to all tasks).
     WorkerRelease(TrustedWorkerAddress),
+    /// Reports an error that the worker's global scope didn't handle to the Worker object
+    /// (dispatched to all tasks).
+    WorkerErrorMsg(TrustedWorkerAddress, ErrorInfo),
+    /// Tells the Worker object that its script couldn't be loaded (dispatched to all tasks).
+    WorkerLoadErrorMsg(TrustedWorkerAddress),
+    /// Wakes up a worker that has been terminated, so that it stops (only dispatched to
+    /// DedicatedWorkerGlobalScope and SharedWorkerGlobalScope).
+    WorkerTerminateMsg,
+    /// Notifies a MessagePort that messages have arrived for it (dispatched to all tasks).
+    MessagePortMsg(TrustedMessagePortAddress),
+    /// Connects a new SharedWorker object to the worker (only dispatched to
+    /// SharedWorkerGlobalScope).
+    SharedWorkerConnectMsg(SharedWorkerConnection),
+    /// Tells a SharedWorker object that the worker is done with its connection, and whether the
+    /// worker's script failed to load (dispatched to all tasks).
+    SharedWorkerConnectedMsg(TrustedSharedWorkerAddress, bool),
+    /// Notifies the script task that an external script of the specified pipeline has been
+    /// fetched, so that scripts waiting to run as soon as possible can run (only dispatched to
+    /// ScriptTask).
+    ScriptLoadedMsg(PipelineId),
+    /// Runs the reactions of a settled promise (only dispatched to ScriptTask).
+    PromiseJobMsg(TrustedPromiseAddress),
+    /// Settles the promise returned by `fetch()` with the response of the fetch (only
+    /// dispatched to ScriptTask).
+    FetchResponseMsg(TrustedPromiseAddress, FetchResponse),
 }
 
 /// Encapsulates internal communication within the script task.
@@ -178,6 +224,11 @@ pub struct ScriptTask {
     /// For receiving commands from an optional devtools server. Will be ignored if
     /// no such server exists.
     devtools_port: DevtoolsControlPort,
+    /// Devtools messages that arrived while paused in the debugger, but could not be handled
+    /// until the code that paused returns.
+    deferred_devtools_msgs: DOMRefCell<Vec<DevtoolScriptControlMsg>>,
+    /// The devtools thread actor's view of the scripts run by this task.
+    debugger: ScriptDebugger,
 
     /// The JavaScript runtime.
     js_runtime: js::rust::rt,
@@ -335,6 +386,7 @@ impl ScriptTask {
                              resource_task.clone(),
                              storage_task,
                              constellation_chan.clone(),
+                             devtools_chan.clone(),
                              js_context.clone());
 
         // Notify devtools that a new script global exists.
@@ -358,6 +410,8 @@ impl ScriptTask {
             compositor: DOMRefCell::new(compositor),
             devtools_chan: devtools_chan,
             devtools_port: devtools_receiver,
+            deferred_devtools_msgs: DOMRefCell::new(vec!()),
+            debugger: ScriptDebugger::new(),
 
             js_runtime: js_runtime,
             js_context: DOMRefCell::new(Some(js_context)),
@@ -409,6 +463,7 @@ impl ScriptTask {
     /// Starts the script task. After calling this method, the script task will loop receiving
     /// messages on its port.
     pub fn start(&self) {
+        PausingScriptTask.replace(Some(self as *const ScriptTask));
         while self.handle_msgs() {
             // Go on...
         }
@@ -527,6 +582,10 @@ impl ScriptTask {
             }
         }
 
+        for msg in replace(&mut *self.deferred_devtools_msgs.borrow_mut(), vec!()).into_iter() {
+            sequential.push(FromDevtools(msg));
+        }
+
         // Process the gathered events.
         for msg in sequential.into_iter() {
             match msg {
@@ -545,17 +604,25 @@ impl ScriptTask {
                 FromConstellation(ViewportMsg(..)) => panic!("should have handled ViewportMsg already"),
                 FromScript(ExitWindowMsg(id)) => self.handle_exit_window_msg(id),
                 FromConstellation(ResizeMsg(..)) => panic!("should have handled ResizeMsg already"),
+                FromConstellation(WebDriverCommandMsg(id, command)) => self.handle_webdriver_msg(id, command),
+                FromConstellation(PostMessageMsg(id, message)) => self.handle_post_message_msg(id, message),
                 FromScript(XHRProgressMsg(addr, progress)) => XMLHttpRequest::handle_progress(addr, progress),
                 FromScript(XHRReleaseMsg(addr)) => XMLHttpRequest::handle_release(addr),
                 FromScript(DOMMessage(..)) => panic!("unexpected message"),
-                FromScript(WorkerPostMessage(addr, data, nbytes)) => Worker::handle_message(addr, data, nbytes),
+                FromScript(WorkerPostMessage(addr, data)) => Worker::handle_message(addr, data),
                 FromScript(WorkerRelease(addr)) => Worker::handle_release(addr),
-                FromDevtools(EvaluateJS(id, s, reply)) => devtools::handle_evaluate_js(&*self.page.borrow(), id, s, reply),
-                FromDevtools(GetRootNode(id, reply)) => devtools::handle_get_root_node(&*self.page.borrow(), id, reply),
-                FromDevtools(GetDocumentElement(id, reply)) => devtools::handle_get_document_element(&*self.page.borrow(), id, reply),
-                FromDevtools(GetChildren(id, node_id, reply)) => devtools::handle_get_children(&*self.page.borrow(), id, node_id, reply),
-                FromDevtools(GetLayout(id, node_id, reply)) => devtools::handle_get_layout(&*self.page.borrow(), id, node_id, reply),
-                FromDevtools(ModifyAttribute(id, node_id, modifications)) => devtools::handle_modify_attribute(&*self.page.borrow(), id, node_id, modifications),
+                FromScript(WorkerErrorMsg(addr, error)) => Worker::handle_error(addr, error),
+                FromScript(WorkerLoadErrorMsg(addr)) => Worker::handle_load_error(addr),
+                FromScript(WorkerTerminateMsg) => panic!("unexpected message"),
+                FromScript(MessagePortMsg(addr)) => MessagePort::handle_message(addr),
+                FromScript(SharedWorkerConnectMsg(..)) => panic!("unexpected message"),
+                FromScript(SharedWorkerConnectedMsg(addr, load_failed)) =>
+                    SharedWorker::handle_connected(addr, load_failed),
+                FromScript(ScriptLoadedMsg(id)) => self.handle_script_loaded_msg(id),
+                FromScript(PromiseJobMsg(addr)) => Promise::handle_job(addr),
+                FromScript(FetchResponseMsg(addr, response)) =>
+                    Response::handle_fetch_response(addr, response),
+                FromDevtools(msg) => self.handle_devtools_msg(msg),
             }
         }
 
@@ -587,6 +654,7 @@ impl ScriptTask {
                       parent_page.resource_task.clone(),
                       parent_page.storage_task.clone(),
                       self.constellation_chan.clone(),
+                      self.devtools_chan.clone(),
                       self.js_context.borrow().as_ref().unwrap().clone())
         };
         parent_page.children.borrow_mut().push(Rc::new(new_page));
@@ -602,6 +670,157 @@ impl ScriptTask {
         window.handle_fire_timer(timer_id);
     }
 
+    /// Runs the scripts of a page that were waiting for an external script to be fetched.
+    fn handle_script_loaded_msg(&self, id: PipelineId) {
+        let page = self.page.borrow_mut();
+        // The page may have been navigated away from since the fetch started.
+        let page = match page.find(id) {
+            Some(page) => page,
+            None => return,
+        };
+        let document = match *page.frame() {
+            Some(ref frame) => Temporary::new(frame.document.clone()),
+            None => return,
+        }.root();
+        document.execute_ready_asap_scripts();
+    }
+
+    /// Dispatches a message that was posted to the window of a pipeline.
+    fn handle_post_message_msg(&self, id: PipelineId, message: Box<Any+Send>) {
+        let message = *message.downcast::<PostedMessage>().unwrap();
+        let page = self.page.borrow_mut();
+        // The pages may have been navigated away from since the message was posted. The source
+        // window is only exposed if it is in this task.
+        let find_window = |id: PipelineId| match page.find(id) {
+            Some(page) => match *page.frame() {
+                Some(ref frame) => Some(frame.window.root()),
+                None => None,
+            },
+            None => None,
+        };
+        let source = find_window(message.source);
+        match find_window(id) {
+            Some(window) => {
+                let source = source.as_ref().map(|source| source.root_ref());
+                window.dispatch_posted_message(message, source)
+            }
+            None => message.discard(),
+        }
+    }
+
+    /// Handles a request from the devtools server.
+    fn handle_devtools_msg(&self, msg: DevtoolScriptControlMsg) {
+        match msg {
+            EvaluateJS(id, s, reply) =>
+                devtools::handle_evaluate_js(&*self.page.borrow(), id, s, reply),
+            GetRootNode(id, reply) =>
+                devtools::handle_get_root_node(&*self.page.borrow(), id, reply),
+            GetDocumentElement(id, reply) =>
+                devtools::handle_get_document_element(&*self.page.borrow(), id, reply),
+            GetChildren(id, node_id, reply) =>
+                devtools::handle_get_children(&*self.page.borrow(), id, node_id, reply),
+            GetLayout(id, node_id, reply) =>
+                devtools::handle_get_layout(&*self.page.borrow(), id, node_id, reply),
+            ModifyAttribute(id, node_id, modifications) =>
+                devtools::handle_modify_attribute(&*self.page.borrow(), id, node_id, modifications),
+            AttachThread(id, pause_chan) =>
+                devtools::handle_attach_thread(&*self.page.borrow(), &self.debugger, id,
+                                               pause_chan),
+            DetachThread(id) =>
+                devtools::handle_detach_thread(&*self.page.borrow(), &self.debugger, id),
+            Interrupt(_) => self.debugger.interrupt(self.get_cx()),
+            // Nothing is paused, since pauses are served by `pause`.
+            Resume(..) => (),
+            SetBreakpoint(_, url, line, reply) =>
+                reply.send(self.debugger.set_breakpoint(self.get_cx(), url, line)),
+            RemoveBreakpoint(_, url, line) =>
+                self.debugger.remove_breakpoint(self.get_cx(), url, line),
+            GetFrames(_, start, count, reply) =>
+                reply.send(self.debugger.frames(self.get_cx(), start, count)),
+            GetSources(_, reply) => reply.send(self.debugger.sources(self.get_cx())),
+            GetSourceText(_, url, reply) =>
+                reply.send(self.debugger.source_text(self.get_cx(), url)),
+            GetStylesheets(id, reply) =>
+                devtools::handle_get_stylesheets(&*self.page.borrow(), id, reply),
+            GetAppliedRules(id, node_id, reply) =>
+                devtools::handle_get_applied_rules(&*self.page.borrow(), id, node_id, reply),
+            SetStylesheetText(id, index, text) =>
+                devtools::handle_set_stylesheet_text(&*self.page.borrow(), id, index, text),
+            SetRuleText(id, index, rule, text) =>
+                devtools::handle_set_rule_text(&*self.page.borrow(), id, index, rule, text),
+            ModifyRuleProperties(id, index, rule, modifications, reply) =>
+                devtools::handle_modify_rule_properties(&*self.page.borrow(), id, index, rule,
+                                                        modifications, reply),
+            ModifyStyleAttributeProperties(id, node_id, modifications, reply) =>
+                devtools::handle_modify_style_attribute_properties(&*self.page.borrow(), id,
+                                                                   node_id, modifications, reply),
+        }
+    }
+
+    /// Reports a pause in the debugger to the devtools client, then handles its requests until
+    /// it resumes. Returns how far to run before pausing again.
+    pub fn pause(&self, info: PauseInfo) -> Option<ResumeLimit> {
+        if !self.debugger.report_pause(info) {
+            return None;
+        }
+        loop {
+            let msg = match self.devtools_port.recv_opt() {
+                Ok(msg) => msg,
+                Err(()) => return None,
+            };
+            let detaching = match msg {
+                Resume(_, limit) => return limit,
+                DetachThread(..) => true,
+                _ => false,
+            };
+            let needs_page = match msg {
+                Interrupt(..) | SetBreakpoint(..) | RemoveBreakpoint(..) | GetFrames(..) |
+                GetSources(..) | GetSourceText(..) => false,
+                _ => true,
+            };
+            // The code that paused may be in the middle of changing the page, in which case
+            // requests that look at it wait until that code returns.
+            if needs_page && self.page.is_mutably_borrowed() {
+                self.deferred_devtools_msgs.borrow_mut().push(msg);
+            } else {
+                self.handle_devtools_msg(msg);
+            }
+            if detaching {
+                return None;
+            }
+        }
+    }
+
+    /// Runs a command from the WebDriver server against the document of a pipeline.
+    fn handle_webdriver_msg(&self, pipeline_id: PipelineId, msg: WebDriverScriptCommand) {
+        let page = self.page.borrow();
+        match msg {
+            ExecuteScript(script, reply) =>
+                webdriver_handlers::handle_execute_script(&*page, pipeline_id, script, reply),
+            FindElementCSS(root, selector, reply) =>
+                webdriver_handlers::handle_find_element_css(&*page, pipeline_id, root, selector,
+                                                            reply),
+            FindElementsCSS(root, selector, reply) =>
+                webdriver_handlers::handle_find_elements_css(&*page, pipeline_id, root, selector,
+                                                             reply),
+            GetElementText(node_id, reply) =>
+                webdriver_handlers::handle_get_element_text(&*page, pipeline_id, node_id, reply),
+            GetElementAttribute(node_id, name, reply) =>
+                webdriver_handlers::handle_get_element_attribute(&*page, pipeline_id, node_id,
+                                                                 name, reply),
+            GetElementTagName(node_id, reply) =>
+                webdriver_handlers::handle_get_element_tag_name(&*page, pipeline_id, node_id,
+                                                                reply),
+            ClickElement(node_id, reply) =>
+                webdriver_handlers::handle_click_element(&*page, pipeline_id, node_id,
+                                                         &self.control_chan, reply),
+            FocusElement(node_id, reply) =>
+                webdriver_handlers::handle_focus_element(&*page, pipeline_id, node_id, reply),
+            GetUrl(reply) => webdriver_handlers::handle_get_url(&*page, pipeline_id, reply),
+            GetTitle(reply) => webdriver_handlers::handle_get_title(&*page, pipeline_id, reply),
+        }
+    }
+
     /// Handles a notification that reflow completed.
     fn handle_reflow_complete_msg(&self, pipeline_id: PipelineId, reflow_id: uint) {
         debug!("Script: Reflow {} complete for {}", reflow_id, pipeline_id);
@@ -663,6 +882,8 @@ impl ScriptTask {
         let page = self.page.borrow_mut();
         if page.id == id {
             debug!("shutting down layout for root page {}", id);
+            self.devtools_chan.as_ref().map(|chan| chan.send(PipelineExitedMsg(id)));
+            stop_workers(&*page);
             *self.js_context.borrow_mut() = None;
             shut_down_layout(&*page, (*self.js_runtime).ptr);
             return true
@@ -671,6 +892,7 @@ impl ScriptTask {
         // otherwise find just the matching page and exit all sub-pages
         match page.remove(id) {
             Some(ref mut page) => {
+                stop_workers(&*page);
                 shut_down_layout(&*page, (*self.js_runtime).ptr);
                 false
             }
@@ -705,6 +927,8 @@ impl ScriptTask {
             _ => (),
         }
 
+        self.devtools_chan.as_ref().map(|chan| chan.send(NavigatedMsg(pipeline_id)));
+
         let is_javascript = url.scheme.as_slice() == "javascript";
         let last_url = last_loaded_url.map(|(ref loaded, _)| loaded.clone());
 
@@ -754,7 +978,9 @@ impl ScriptTask {
         parse_html(&*page, *document, parser_input, self.resource_task.clone(), Some(load_data));
         url = page.get_url().clone();
 
+        // https://html.spec.whatwg.org/multipage/#the-end steps 1-3
         document.set_ready_state(DocumentReadyStateValues::Interactive);
+        document.execute_deferred_scripts();
 
         // Kick off the initial reflow of the page.
         debug!("kicking off initial reflow of {}", url);
@@ -777,6 +1003,9 @@ impl ScriptTask {
         let doctarget: JSRef<EventTarget> = EventTargetCast::from_ref(*document);
         let _ = doctarget.DispatchEvent(*event);
 
+        // https://html.spec.whatwg.org/multipage/#the-end step 5
+        document.wait_for_asap_scripts();
+
         // We have no concept of a document loader right now, so just dispatch the
         // "load" event as soon as we've finished executing all scripts parsed during
         // the initial load.
@@ -935,6 +1164,23 @@ impl ScriptTask {
             let page = get_page(&*self.page.borrow(), pipeline_id);
             page.window_size.set(new_size);
 
+            // The image sources that suit the page best depend on the viewport and the zoom.
+            let document = page.frame().as_ref().map(|frame| {
+                Temporary::new(frame.document.clone())
+            }).root();
+            match document {
+                Some(ref document) => {
+                    let root: JSRef<Node> = NodeCast::from_ref(**document);
+                    for node in root.traverse_preorder() {
+                        match HTMLImageElementCast::to_ref(node) {
+                            Some(image) => image.update_the_image_data(None),
+                            None => {}
+                        }
+                    }
+                }
+                None => {}
+            }
+
             let frame = page.frame();
             if frame.is_some() {
                 self.force_reflow(&*page);
@@ -1101,6 +1347,18 @@ impl ScriptTask {
     }
 }
 
+/// Terminates the workers that the windows of the given page tree started, and stops the shared
+/// workers that only its documents use.
+fn stop_workers(page_tree: &Rc<Page>) {
+    for page in page_tree.iter() {
+        match *page.frame() {
+            Some(ref frame) => frame.window.root().terminate_child_workers(),
+            None => {}
+        }
+        remove_shared_worker_owner(page.id);
+    }
+}
+
 /// Shuts down layout for the given page tree.
 fn shut_down_layout(page_tree: &Rc<Page>, rt: *mut JSRuntime) {
     for page in page_tree.iter() {
@@ -0,0 +1,211 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use dom::bindings::conversions;
+use dom::bindings::conversions::FromJSValConvertible;
+use dom::bindings::codegen::Bindings::DocumentBinding::DocumentMethods;
+use dom::bindings::codegen::Bindings::ElementBinding::ElementMethods;
+use dom::bindings::codegen::Bindings::NodeBinding::NodeMethods;
+use dom::bindings::codegen::Bindings::NodeListBinding::NodeListMethods;
+use dom::bindings::codegen::InheritTypes::{NodeCast, ElementCast};
+use dom::bindings::js::{JSRef, Temporary, OptionalRootable};
+use dom::document::DocumentHelpers;
+use dom::element::Element;
+use dom::node::{Node, NodeHelpers};
+use dom::window::WindowHelpers;
+use page::Page;
+use script_traits::{ClickEvent, ScriptControlChan, SendEventMsg};
+use servo_msg::constellation_msg::PipelineId;
+use servo_util::geometry::{Au, to_frac_px};
+use script_task::get_page;
+use webdriver_traits::{WebDriverElementId, WebDriverError, WebDriverJSError, WebDriverJSValue};
+use webdriver_traits::{ElementNotVisible, InvalidSelector, StaleElementReference};
+use webdriver_traits::{JSBoolean, JSNull, JSNumber, JSString, JSUndefined, JSUnknownType};
+
+use geom::point::Point2D;
+use std::rc::Rc;
+
+fn find_node_by_unique_id(page: &Rc<Page>, pipeline: PipelineId, node_id: &str)
+                          -> Option<Temporary<Node>> {
+    let page = get_page(&*page, pipeline);
+    let frame = page.frame();
+    let document = frame.as_ref().unwrap().document.root();
+    let node: JSRef<Node> = NodeCast::from_ref(*document);
+
+    for candidate in node.traverse_preorder() {
+        if candidate.has_unique_id(node_id) {
+            return Some(Temporary::from_rooted(candidate));
+        }
+    }
+
+    None
+}
+
+/// Looks up the element that a WebDriver client refers to, running `f` on it if it still exists.
+fn with_element<T>(page: &Rc<Page>,
+                   pipeline: PipelineId,
+                   element_id: &str,
+                   f: |JSRef<Element>| -> Result<T, WebDriverError>)
+                   -> Result<T, WebDriverError> {
+    match find_node_by_unique_id(page, pipeline, element_id).root() {
+        Some(node) => match ElementCast::to_ref(*node) {
+            Some(element) => f(element),
+            None => Err(StaleElementReference),
+        },
+        None => Err(StaleElementReference),
+    }
+}
+
+pub fn handle_execute_script(page: &Rc<Page>,
+                             pipeline: PipelineId,
+                             eval: String,
+                             reply: Sender<Result<WebDriverJSValue, WebDriverJSError>>) {
+    let page = get_page(&*page, pipeline);
+    let frame = page.frame();
+    let window = frame.as_ref().unwrap().window.root();
+    let cx = window.get_cx();
+    let rval = window.evaluate_js_with_result(eval.as_slice());
+
+    reply.send(if rval.is_undefined() {
+        Ok(JSUndefined)
+    } else if rval.is_null() {
+        Ok(JSNull)
+    } else if rval.is_boolean() {
+        Ok(JSBoolean(rval.to_boolean()))
+    } else if rval.is_string() {
+        //FIXME: use jsstring_to_str when jsval grows to_jsstring
+        Ok(JSString(FromJSValConvertible::from_jsval(cx, rval, conversions::Default).unwrap()))
+    } else if rval.is_object_or_null() {
+        Err(JSUnknownType)
+    } else {
+        Ok(JSNumber(FromJSValConvertible::from_jsval(cx, rval, ()).unwrap()))
+    });
+}
+
+pub fn handle_find_element_css(page: &Rc<Page>,
+                               pipeline: PipelineId,
+                               root: Option<WebDriverElementId>,
+                               selector: String,
+                               reply: Sender<Result<Option<WebDriverElementId>, WebDriverError>>) {
+    reply.send(find_elements_css(page, pipeline, root, selector).map(|elements| {
+        elements.into_iter().next()
+    }));
+}
+
+pub fn handle_find_elements_css(page: &Rc<Page>,
+                                pipeline: PipelineId,
+                                root: Option<WebDriverElementId>,
+                                selector: String,
+                                reply: Sender<Result<Vec<WebDriverElementId>, WebDriverError>>) {
+    reply.send(find_elements_css(page, pipeline, root, selector));
+}
+
+/// Returns the elements matching `selector` in tree order, searching the descendants of `root`
+/// or, without a root element, the whole document.
+fn find_elements_css(page: &Rc<Page>,
+                     pipeline: PipelineId,
+                     root: Option<WebDriverElementId>,
+                     selector: String)
+                     -> Result<Vec<WebDriverElementId>, WebDriverError> {
+    let query = |root: JSRef<Node>| {
+        let nodes = match root.query_selector_all(selector.clone()) {
+            Ok(nodes) => nodes.root(),
+            Err(_) => return Err(InvalidSelector),
+        };
+        Ok(range(0, nodes.Length()).filter_map(|i| {
+            nodes.Item(i).root().map(|node| node.get_unique_id())
+        }).collect())
+    };
+
+    match root {
+        None => {
+            let page = get_page(&*page, pipeline);
+            let frame = page.frame();
+            let document = frame.as_ref().unwrap().document.root();
+            query(NodeCast::from_ref(*document))
+        }
+        Some(root) => with_element(page, pipeline, root.as_slice(), |element| {
+            query(NodeCast::from_ref(element))
+        }),
+    }
+}
+
+pub fn handle_get_element_text(page: &Rc<Page>,
+                               pipeline: PipelineId,
+                               element_id: WebDriverElementId,
+                               reply: Sender<Result<String, WebDriverError>>) {
+    // FIXME: This should be the rendered text of the element, not its text content.
+    reply.send(with_element(page, pipeline, element_id.as_slice(), |element| {
+        let node: JSRef<Node> = NodeCast::from_ref(element);
+        Ok(node.GetTextContent().unwrap_or(String::new()))
+    }));
+}
+
+pub fn handle_get_element_attribute(page: &Rc<Page>,
+                                    pipeline: PipelineId,
+                                    element_id: WebDriverElementId,
+                                    name: String,
+                                    reply: Sender<Result<Option<String>, WebDriverError>>) {
+    reply.send(with_element(page, pipeline, element_id.as_slice(), |element| {
+        Ok(element.GetAttribute(name.clone()))
+    }));
+}
+
+pub fn handle_get_element_tag_name(page: &Rc<Page>,
+                                   pipeline: PipelineId,
+                                   element_id: WebDriverElementId,
+                                   reply: Sender<Result<String, WebDriverError>>) {
+    reply.send(with_element(page, pipeline, element_id.as_slice(), |element| {
+        Ok(element.LocalName())
+    }));
+}
+
+/// Clicks the center of an element's box by sending the script task the same event that the
+/// compositor sends for a mouse click.
+pub fn handle_click_element(page: &Rc<Page>,
+                            pipeline: PipelineId,
+                            element_id: WebDriverElementId,
+                            control_chan: &ScriptControlChan,
+                            reply: Sender<Result<(), WebDriverError>>) {
+    reply.send(with_element(page, pipeline, element_id.as_slice(), |element| {
+        let node: JSRef<Node> = NodeCast::from_ref(element);
+        let rect = node.get_bounding_content_box();
+        if rect.size.width == Au(0) || rect.size.height == Au(0) {
+            return Err(ElementNotVisible)
+        }
+        let point = Point2D(to_frac_px(rect.origin.x + rect.size.width / 2) as f32,
+                            to_frac_px(rect.origin.y + rect.size.height / 2) as f32);
+        let ScriptControlChan(ref chan) = *control_chan;
+        chan.send(SendEventMsg(pipeline, ClickEvent(0, point)));
+        Ok(())
+    }));
+}
+
+pub fn handle_focus_element(page: &Rc<Page>,
+                            pipeline: PipelineId,
+                            element_id: WebDriverElementId,
+                            reply: Sender<Result<(), WebDriverError>>) {
+    reply.send(with_element(page, pipeline, element_id.as_slice(), |element| {
+        let node: JSRef<Node> = NodeCast::from_ref(element);
+        let document = node.owner_doc().root();
+        document.begin_focus_transaction();
+        document.request_focus(element);
+        document.commit_focus_transaction();
+        Ok(())
+    }));
+}
+
+pub fn handle_get_url(page: &Rc<Page>, pipeline: PipelineId, reply: Sender<String>) {
+    let page = get_page(&*page, pipeline);
+    let frame = page.frame();
+    let document = frame.as_ref().unwrap().document.root();
+    reply.send(document.URL());
+}
+
+pub fn handle_get_title(page: &Rc<Page>, pipeline: PipelineId, reply: Sender<String>) {
+    let page = get_page(&*page, pipeline);
+    let frame = page.frame();
+    let document = frame.as_ref().unwrap().document.root();
+    reply.send(document.Title());
+}
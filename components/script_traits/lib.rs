@@ -16,6 +16,7 @@ extern crate "net" as servo_net;
 extern crate "util" as servo_util;
 extern crate url;
 extern crate serialize;
+extern crate webdriver_traits;
 
 // This module contains traits in script used generically
 //   in the rest of Servo.
@@ -32,6 +33,7 @@ use servo_net::resource_task::ResourceTask;
 use servo_net::storage_task::StorageTask;
 use servo_util::smallvec::SmallVec1;
 use std::any::Any;
+use webdriver_traits::WebDriverScriptCommand;
 
 use geom::point::Point2D;
 use geom::rect::Rect;
@@ -66,6 +68,10 @@ pub enum ConstellationControlMsg {
     /// Notifies script that reflow is finished.
     ReflowCompleteMsg(PipelineId, uint),
     ViewportMsg(PipelineId, Rect<f32>),
+    /// Runs a command on behalf of a WebDriver client.
+    WebDriverCommandMsg(PipelineId, WebDriverScriptCommand),
+    /// Posts a message to the window of the specified pipeline.
+    PostMessageMsg(PipelineId, Box<Any+Send>),
 }
 
 /// Events from the compositor that the script task needs to know about
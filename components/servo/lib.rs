@@ -26,6 +26,7 @@ extern crate libc;
 extern crate native;
 extern crate rustrt;
 extern crate url;
+extern crate webdriver_server;
 
 use compositing::CompositorEventListener;
 use compositing::windowing::{WindowEvent, WindowMethods};
@@ -40,7 +41,7 @@ use script::dom::bindings::codegen::RegisterBindings;
 #[cfg(not(test))]
 use servo_net::image_cache_task::ImageCacheTask;
 #[cfg(not(test))]
-use servo_net::resource_task::new_resource_task;
+use servo_net::resource_task::{new_resource_task, NetworkConfig};
 #[cfg(not(test))]
 use servo_net::storage_task::StorageTaskFactory;
 #[cfg(not(test))]
@@ -48,7 +49,7 @@ use gfx::font_cache_task::FontCacheTask;
 #[cfg(not(test))]
 use servo_util::time::TimeProfiler;
 #[cfg(not(test))]
-use servo_util::memory::MemoryProfiler;
+use servo_util::memory::{MemoryProfiler, RegisterReporterMsg};
 #[cfg(not(test))]
 use servo_util::opts;
 #[cfg(not(test))]
@@ -84,6 +85,7 @@ impl<Window> Browser<Window> where Window: WindowMethods + 'static {
 
         let opts_clone = opts.clone();
         let time_profiler_chan_clone = time_profiler_chan.clone();
+        let memory_profiler_chan_clone = memory_profiler_chan.clone();
 
         let (result_chan, result_port) = channel();
         let compositor_proxy_for_constellation = compositor_proxy.clone_compositor_proxy();
@@ -91,15 +93,20 @@ impl<Window> Browser<Window> where Window: WindowMethods + 'static {
             .spawn(proc() {
             let opts = &opts_clone;
             // Create a Servo instance.
-            let resource_task = new_resource_task(opts.user_agent.clone());
+            let resource_task = new_resource_task(NetworkConfig::from_opts(opts),
+                                                  devtools_chan.clone());
             // If we are emitting an output file, then we need to block on
             // image load or we risk emitting an output file missing the
             // image.
             let image_cache_task = if opts.output_file.is_some() {
-                ImageCacheTask::new_sync(resource_task.clone(), shared_task_pool)
+                ImageCacheTask::new_sync(resource_task.clone(), shared_task_pool,
+                                         opts.image_cache_size)
             } else {
-                ImageCacheTask::new(resource_task.clone(), shared_task_pool)
+                ImageCacheTask::new(resource_task.clone(), shared_task_pool,
+                                    opts.image_cache_size)
             };
+            memory_profiler_chan_clone.send(
+                RegisterReporterMsg("image-cache".to_string(), box image_cache_task.clone()));
             let font_cache_task = FontCacheTask::new(resource_task.clone());
             let storage_task = StorageTaskFactory::new();
             let constellation_chan = Constellation::<layout::layout_task::LayoutTask,
@@ -126,6 +133,10 @@ impl<Window> Browser<Window> where Window: WindowMethods + 'static {
                 chan.send(InitLoadUrlMsg(url));
             }
 
+            opts.webdriver_port.map(|port| {
+                webdriver_server::start_server(port, constellation_chan.clone())
+            });
+
             // Send the constallation Chan as the result
             result_chan.send(constellation_chan);
         });
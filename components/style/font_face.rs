@@ -6,27 +6,25 @@ use cssparser::ast::*;
 use cssparser::parse_declaration_list;
 use errors::{ErrorLoggerIterator, log_css_error};
 use std::ascii::AsciiExt;
-use parsing_utils::{BufferedIter, ParserIter, parse_slice_comma_separated};
+use parsing_utils::{BufferedIter, ParserIter, one_component_value, parse_slice_comma_separated};
+use properties::longhands;
 use properties::longhands::font_family::parse_one_family;
 use properties::computed_values::font_family::FamilyName;
+use properties::computed_values::{font_stretch, font_style, font_weight};
 use stylesheets::{CSSRule, CSSFontFaceRule, CSSStyleRule, CSSMediaRule};
 use media_queries::Device;
 use url::{Url, UrlParser};
 
 
 pub fn iter_font_face_rules_inner(rules: &[CSSRule], device: &Device,
-                                    callback: |family: &str, source: &Source|) {
+                                    callback: |rule: &FontFaceRule|) {
     for rule in rules.iter() {
         match *rule {
             CSSStyleRule(_) => {},
             CSSMediaRule(ref rule) => if rule.media_queries.evaluate(device) {
-                iter_font_face_rules_inner(rule.rules.as_slice(), device, |f, s| callback(f, s))
-            },
-            CSSFontFaceRule(ref rule) => {
-                for source in rule.sources.iter() {
-                    callback(rule.family.as_slice(), source)
-                }
+                iter_font_face_rules_inner(rule.rules.as_slice(), device, |r| callback(r))
             },
+            CSSFontFaceRule(ref rule) => callback(rule),
         }
     }
 }
@@ -43,9 +41,30 @@ pub struct UrlSource {
     pub format_hints: Vec<String>,
 }
 
+/// A range of code points from a `unicode-range` descriptor, inclusive at both ends.
+#[deriving(Clone, PartialEq, Show)]
+pub struct CodepointRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl CodepointRange {
+    #[inline]
+    pub fn contains(&self, codepoint: char) -> bool {
+        self.start <= codepoint as u32 && codepoint as u32 <= self.end
+    }
+}
+
+#[deriving(Clone)]
 pub struct FontFaceRule {
     pub family: String,
     pub sources: Vec<Source>,
+    pub weight: font_weight::T,
+    pub style: font_style::T,
+    pub stretch: font_stretch::T,
+    /// The characters that the font face may be used for. Empty if the face may be used for all
+    /// characters.
+    pub unicode_range: Vec<CodepointRange>,
 }
 
 pub fn parse_font_face_rule(rule: AtRule, parent_rules: &mut Vec<CSSRule>, base_url: &Url) {
@@ -64,6 +83,10 @@ pub fn parse_font_face_rule(rule: AtRule, parent_rules: &mut Vec<CSSRule>, base_
 
     let mut maybe_family = None;
     let mut maybe_sources = None;
+    let mut weight = font_weight::Weight400;
+    let mut style = font_style::normal;
+    let mut stretch = font_stretch::normal;
+    let mut unicode_range = vec!();
 
     for item in ErrorLoggerIterator(parse_declaration_list(block.into_iter())) {
         match item {
@@ -93,6 +116,35 @@ pub fn parse_font_face_rule(rule: AtRule, parent_rules: &mut Vec<CSSRule>, base_
                             Err(()) => log_css_error(location, "Invalid src in @font-face"),
                         };
                     },
+                    "font-weight" => {
+                        match parse_font_weight(value.as_slice(), base_url) {
+                            Ok(value) => weight = value,
+                            Err(()) => log_css_error(location, "Invalid font-weight in @font-face"),
+                        }
+                    },
+                    "font-style" => {
+                        match one_component_value(value.as_slice()).and_then(|value| {
+                            longhands::font_style::from_component_value(value, base_url)
+                        }) {
+                            Ok(value) => style = value,
+                            Err(()) => log_css_error(location, "Invalid font-style in @font-face"),
+                        }
+                    },
+                    "font-stretch" => {
+                        match one_component_value(value.as_slice()).and_then(|value| {
+                            longhands::font_stretch::from_component_value(value, base_url)
+                        }) {
+                            Ok(value) => stretch = value,
+                            Err(()) => log_css_error(location, "Invalid font-stretch in @font-face"),
+                        }
+                    },
+                    "unicode-range" => {
+                        match parse_slice_comma_separated(value.as_slice(),
+                                                          parse_one_codepoint_range) {
+                            Ok(ranges) => unicode_range = ranges,
+                            Err(()) => log_css_error(location, "Invalid unicode-range in @font-face"),
+                        }
+                    },
                     _ => {
                         log_css_error(location, format!("Unsupported declaration {:s}", name).as_slice());
                     }
@@ -105,6 +157,10 @@ pub fn parse_font_face_rule(rule: AtRule, parent_rules: &mut Vec<CSSRule>, base_
         (Some(family), Some(sources)) => parent_rules.push(CSSFontFaceRule(FontFaceRule {
             family: family,
             sources: sources,
+            weight: weight,
+            style: style,
+            stretch: stretch,
+            unicode_range: unicode_range,
         })),
         (None, _) => log_css_error(rule.location, "@font-face without a font-family descriptor"),
         _ => log_css_error(rule.location, "@font-face without an src descriptor"),
@@ -167,3 +223,42 @@ fn parse_one_format(iter: ParserIter) -> Result<String, ()> {
         _ => Err(())
     }
 }
+
+
+/// normal | bold | 100 | 200 | 300 | 400 | 500 | 600 | 700 | 800 | 900
+///
+/// Unlike the `font-weight` property, the descriptor does not accept relative weights.
+fn parse_font_weight(value: &[ComponentValue], base_url: &Url) -> Result<font_weight::T, ()> {
+    let component_value = try!(one_component_value(value));
+    match try!(longhands::font_weight::from_component_value(component_value, base_url)) {
+        longhands::font_weight::SpecifiedWeight100 => Ok(font_weight::Weight100),
+        longhands::font_weight::SpecifiedWeight200 => Ok(font_weight::Weight200),
+        longhands::font_weight::SpecifiedWeight300 => Ok(font_weight::Weight300),
+        longhands::font_weight::SpecifiedWeight400 => Ok(font_weight::Weight400),
+        longhands::font_weight::SpecifiedWeight500 => Ok(font_weight::Weight500),
+        longhands::font_weight::SpecifiedWeight600 => Ok(font_weight::Weight600),
+        longhands::font_weight::SpecifiedWeight700 => Ok(font_weight::Weight700),
+        longhands::font_weight::SpecifiedWeight800 => Ok(font_weight::Weight800),
+        longhands::font_weight::SpecifiedWeight900 => Ok(font_weight::Weight900),
+        longhands::font_weight::Bolder | longhands::font_weight::Lighter => Err(()),
+    }
+}
+
+
+fn parse_one_codepoint_range(iter: ParserIter) -> Result<CodepointRange, ()> {
+    match iter.next() {
+        Some(&UnicodeRange(start, end)) if iter.next().is_none() => {
+            // Ranges are clipped to the valid code point space; a range that ends up empty is
+            // invalid.
+            let end = if end > 0x10FFFF { 0x10FFFF } else { end };
+            if start > end {
+                return Err(())
+            }
+            Ok(CodepointRange {
+                start: start,
+                end: end,
+            })
+        }
+        _ => Err(())
+    }
+}
@@ -34,10 +34,11 @@ extern crate "util" as servo_util;
 
 
 // Public API
-pub use media_queries::{Device, Screen};
-pub use stylesheets::{Stylesheet, iter_font_face_rules};
+pub use media_queries::{Device, Screen, MediaQueryList, SourceSizeList};
+pub use media_queries::{parse_media_query_list_from_str, parse_source_size_list};
+pub use stylesheets::{Stylesheet, iter_font_face_rules, style_rules, modify_declarations_text};
 pub use selector_matching::{Stylist, StylesheetOrigin, UserAgentOrigin, AuthorOrigin, UserOrigin};
-pub use selector_matching::{DeclarationBlock, CommonStyleAffectingAttributes};
+pub use selector_matching::{DeclarationBlock, CommonStyleAffectingAttributes, MatchedRule};
 pub use selector_matching::{CommonStyleAffectingAttributeInfo, CommonStyleAffectingAttributeMode};
 pub use selector_matching::{AttrIsPresentMode, AttrIsEqualMode};
 pub use selector_matching::{matches, matches_simple_selector, common_style_affecting_attributes};
@@ -54,7 +55,7 @@ pub use selectors::{AttrSelector, NamespaceConstraint, SpecificNamespace, AnyNam
 pub use selectors::{SimpleSelector,LocalNameSelector};
 pub use cssparser::{Color, RGBA};
 pub use legacy::{IntegerAttribute, LengthAttribute, SizeIntegerAttribute, WidthLengthAttribute};
-pub use font_face::{Source, LocalSource, UrlSource_};
+pub use font_face::{CodepointRange, FontFaceRule, Source, LocalSource, UrlSource_, UrlSource};
 
 mod stylesheets;
 mod errors;
@@ -3,7 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use std::ascii::AsciiExt;
-use cssparser::parse_rule_list;
+use cssparser::{parse_rule_list, tokenize};
 use cssparser::ast::*;
 
 use errors::{ErrorLoggerIterator, log_css_error};
@@ -81,6 +81,23 @@ pub enum MediaType {
     Unknown,
 }
 
+/// The length in a source size: either an absolute length or a fraction of the viewport width.
+enum SourceSizeLength {
+    AbsoluteSourceSize(Au),
+    ViewportRelativeSourceSize(CSSFloat),
+}
+
+/// A source size from the `sizes` attribute of an image: the width at which the image is
+/// displayed when the media condition matches.
+struct SourceSize {
+    condition: Option<MediaQuery>,
+    length: SourceSizeLength,
+}
+
+pub struct SourceSizeList {
+    source_sizes: Vec<SourceSize>,
+}
+
 pub struct Device {
     pub media_type: MediaType,
     pub viewport_size: TypedSize2D<ViewportPx, f32>,
@@ -278,31 +295,103 @@ pub fn parse_media_query_list(input: &[ComponentValue]) -> MediaQueryList {
     MediaQueryList { media_queries: media_queries }
 }
 
+pub fn parse_media_query_list_from_str(input: &str) -> MediaQueryList {
+    let component_values: Vec<ComponentValue> = tokenize(input).map(|(value, _)| value).collect();
+    parse_media_query_list(component_values.as_slice())
+}
+
+/// Parses the `sizes` attribute of an image. Invalid source sizes are dropped.
+pub fn parse_source_size_list(input: &str) -> SourceSizeList {
+    let component_values: Vec<ComponentValue> = tokenize(input).map(|(value, _)| value).collect();
+    let source_sizes = component_values.as_slice().split(|value| {
+        match *value {
+            Comma => true,
+            _ => false,
+        }
+    }).filter_map(|source_size| parse_source_size(source_size).ok()).collect();
+    SourceSizeList { source_sizes: source_sizes }
+}
+
+fn parse_source_size(input: &[ComponentValue]) -> Result<SourceSize, ()> {
+    // The length comes last, after the optional media condition.
+    let length_position = try!(input.iter().rposition(|value| {
+        match *value {
+            WhiteSpace => false,
+            _ => true,
+        }
+    }).ok_or(()));
+    let length = match input[length_position] {
+        Dimension(ref value, ref unit) if value.value >= 0. &&
+                unit.as_slice().eq_ignore_ascii_case("vw") => {
+            ViewportRelativeSourceSize(value.value / 100.)
+        }
+        ref value => AbsoluteSourceSize(try!(parse_value_as_length(value))),
+    };
+
+    let iter = &mut BufferedIter::new(input.slice_to(length_position).skip_whitespace());
+    let condition = if iter.is_eof() {
+        None
+    } else {
+        let condition = try!(parse_media_query(iter));
+        if !iter.is_eof() {
+            return Err(())
+        }
+        Some(condition)
+    };
+
+    Ok(SourceSize {
+        condition: condition,
+        length: length,
+    })
+}
+
+impl MediaQuery {
+    fn evaluate(&self, device: &Device) -> bool {
+        // Check if media matches. Unknown media never matches.
+        let media_match = match self.media_type {
+            MediaType_(Unknown) => false,
+            MediaType_(media_type) => media_type == device.media_type,
+            All => true,
+        };
+
+        // Check if all conditions match (AND condition)
+        let query_match = media_match && self.expressions.iter().all(|expression| {
+            match expression {
+                &Width(value) => value.evaluate(
+                    Au::from_frac_px(device.viewport_size.to_untyped().width as f64)),
+            }
+        });
+
+        // Apply the logical NOT qualifier to the result
+        match self.qualifier {
+            Some(Not) => !query_match,
+            _ => query_match,
+        }
+    }
+}
+
 impl MediaQueryList {
     pub fn evaluate(&self, device: &Device) -> bool {
         // Check if any queries match (OR condition)
-        self.media_queries.iter().any(|mq| {
-            // Check if media matches. Unknown media never matches.
-            let media_match = match mq.media_type {
-                MediaType_(Unknown) => false,
-                MediaType_(media_type) => media_type == device.media_type,
-                All => true,
-            };
-
-            // Check if all conditions match (AND condition)
-            let query_match = media_match && mq.expressions.iter().all(|expression| {
-                match expression {
-                    &Width(value) => value.evaluate(
-                        Au::from_frac_px(device.viewport_size.to_untyped().width as f64)),
-                }
-            });
+        self.media_queries.iter().any(|mq| mq.evaluate(device))
+    }
+}
 
-            // Apply the logical NOT qualifier to the result
-            match mq.qualifier {
-                Some(Not) => !query_match,
-                _ => query_match,
+impl SourceSizeList {
+    /// Returns the width at which the image is displayed on `device`: the length of the first
+    /// source size whose condition matches, or the viewport width if none does.
+    pub fn evaluate(&self, device: &Device) -> Au {
+        let viewport_width = Au::from_frac_px(device.viewport_size.to_untyped().width as f64);
+        let source_size = self.source_sizes.iter().find(|source_size| {
+            source_size.condition.as_ref().map_or(true, |condition| condition.evaluate(device))
+        });
+        match source_size {
+            Some(&SourceSize { length: AbsoluteSourceSize(length), .. }) => length,
+            Some(&SourceSize { length: ViewportRelativeSourceSize(fraction), .. }) => {
+                viewport_width.scale_by(fraction)
             }
-        })
+            None => viewport_width,
+        }
     }
 }
 
@@ -317,7 +406,7 @@ mod tests {
 
     fn test_media_rule(css: &str, callback: |&MediaQueryList, &str|) {
         let url = Url::parse("http://localhost").unwrap();
-        let stylesheet = Stylesheet::from_str(css, url, AuthorOrigin);
+        let stylesheet = Stylesheet::from_str(css, url, AuthorOrigin, false);
         let mut rule_count: int = 0;
         iter_stylesheet_media_rules(&stylesheet, |rule| {
             rule_count += 1;
@@ -328,7 +417,7 @@ mod tests {
 
     fn media_query_test(device: &Device, css: &str, expected_rule_count: int) {
         let url = Url::parse("http://localhost").unwrap();
-        let ss = Stylesheet::from_str(css, url, AuthorOrigin);
+        let ss = Stylesheet::from_str(css, url, AuthorOrigin, false);
         let mut rule_count: int = 0;
         iter_stylesheet_style_rules(&ss, device, |_| rule_count += 1);
         assert!(rule_count == expected_rule_count, css.to_string());
@@ -713,4 +802,24 @@ mod tests {
         media_query_test(&device, "@media screen and (height: 100px) { a { color: red; } }", 0);
         media_query_test(&device, "@media not print and (width: 100) { a { color: red; } }", 0);
     }
+
+    #[test]
+    fn test_source_size_list() {
+        let device = Device {
+            media_type: Screen,
+            viewport_size: TypedSize2D(200.0, 100.0),
+        };
+
+        let sizes =
+            parse_source_size_list("(max-width: 100px) 50px, (min-width: 150px) 50vw, 10px");
+        assert!(sizes.evaluate(&device) == Au::from_px(100));
+
+        let sizes = parse_source_size_list("(max-width: 100px) 50px, 10em");
+        assert!(sizes.evaluate(&device) == Au::from_px(160));
+
+        // Invalid source sizes are skipped, and the viewport width is the default.
+        let sizes = parse_source_size_list("(max-width: 100px) 50px, (min-width: 150px) red");
+        assert!(sizes.evaluate(&device) == Au::from_px(200));
+        assert!(parse_source_size_list("").evaluate(&device) == Au::from_px(200));
+    }
 }
@@ -918,6 +918,216 @@ pub mod longhands {
 
     ${single_keyword("font-style", "normal italic oblique")}
     ${single_keyword("font-variant", "normal small-caps")}
+    ${single_keyword("font-stretch", "normal ultra-condensed extra-condensed condensed semi-condensed semi-expanded expanded extra-expanded ultra-expanded")}
+
+    // CSS Fonts Module Level 3, Section 6 - Font feature properties
+
+    ${single_keyword("font-kerning", "auto normal none")}
+    ${single_keyword("font-variant-caps", "normal small-caps all-small-caps petite-caps all-petite-caps unicase titling-caps")}
+
+    <%self:longhand name="font-variant-ligatures">
+        pub use super::computed_as_specified as to_computed_value;
+        /// Whether each group of ligatures was explicitly enabled or disabled. `None` leaves the
+        /// font's default behavior alone.
+        #[deriving(PartialEq, Clone, Show)]
+        pub struct SpecifiedValue {
+            pub common_ligatures: Option<bool>,
+            pub discretionary_ligatures: Option<bool>,
+            pub historical_ligatures: Option<bool>,
+            pub contextual: Option<bool>,
+        }
+        pub mod computed_value {
+            pub type T = super::SpecifiedValue;
+            #[allow(non_upper_case_globals)]
+            pub const normal: T = super::SpecifiedValue {
+                common_ligatures: None,
+                discretionary_ligatures: None,
+                historical_ligatures: None,
+                contextual: None,
+            };
+            #[allow(non_upper_case_globals)]
+            pub const none: T = super::SpecifiedValue {
+                common_ligatures: Some(false),
+                discretionary_ligatures: Some(false),
+                historical_ligatures: Some(false),
+                contextual: Some(false),
+            };
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T {
+            normal
+        }
+        /// normal | none | [ <common-lig-values> || <discretionary-lig-values> ||
+        ///                   <historical-lig-values> || <contextual-alt-values> ]
+        pub fn parse(input: &[ComponentValue], _base_url: &Url) -> Result<SpecifiedValue, ()> {
+            match one_component_value(input).and_then(get_ident_lower) {
+                Ok(ref keyword) if keyword.as_slice() == "normal" => return Ok(normal),
+                Ok(ref keyword) if keyword.as_slice() == "none" => return Ok(none),
+                _ => {}
+            }
+            let mut result = normal;
+            let mut empty = true;
+            for component_value in input.skip_whitespace() {
+                let (slot, enabled) = match try!(get_ident_lower(component_value)).as_slice() {
+                    "common-ligatures" => (&mut result.common_ligatures, true),
+                    "no-common-ligatures" => (&mut result.common_ligatures, false),
+                    "discretionary-ligatures" => (&mut result.discretionary_ligatures, true),
+                    "no-discretionary-ligatures" => (&mut result.discretionary_ligatures, false),
+                    "historical-ligatures" => (&mut result.historical_ligatures, true),
+                    "no-historical-ligatures" => (&mut result.historical_ligatures, false),
+                    "contextual" => (&mut result.contextual, true),
+                    "no-contextual" => (&mut result.contextual, false),
+                    _ => return Err(()),
+                };
+                if slot.is_some() {
+                    return Err(())
+                }
+                *slot = Some(enabled);
+                empty = false;
+            }
+            if !empty { Ok(result) } else { Err(()) }
+        }
+    </%self:longhand>
+
+    <%self:longhand name="font-variant-numeric">
+        pub use super::computed_as_specified as to_computed_value;
+        #[deriving(PartialEq, Clone, Show)]
+        pub struct SpecifiedValue {
+            pub lining_nums: bool,
+            pub oldstyle_nums: bool,
+            pub proportional_nums: bool,
+            pub tabular_nums: bool,
+            pub diagonal_fractions: bool,
+            pub stacked_fractions: bool,
+            pub ordinal: bool,
+            pub slashed_zero: bool,
+        }
+        pub mod computed_value {
+            pub type T = super::SpecifiedValue;
+            #[allow(non_upper_case_globals)]
+            pub const normal: T = super::SpecifiedValue {
+                lining_nums: false,
+                oldstyle_nums: false,
+                proportional_nums: false,
+                tabular_nums: false,
+                diagonal_fractions: false,
+                stacked_fractions: false,
+                ordinal: false,
+                slashed_zero: false,
+            };
+        }
+        #[inline] pub fn get_initial_value() -> computed_value::T {
+            normal
+        }
+        /// normal | [ <numeric-figure-values> || <numeric-spacing-values> ||
+        ///            <numeric-fraction-values> || ordinal || slashed-zero ]
+        pub fn parse(input: &[ComponentValue], _base_url: &Url) -> Result<SpecifiedValue, ()> {
+            match one_component_value(input).and_then(get_ident_lower) {
+                Ok(ref keyword) if keyword.as_slice() == "normal" => return Ok(normal),
+                _ => {}
+            }
+            let mut result = normal;
+            let mut empty = true;
+            for component_value in input.skip_whitespace() {
+                let (slot, exclusive_with) = match try!(get_ident_lower(component_value)).as_slice() {
+                    "lining-nums" => (&mut result.lining_nums, result.oldstyle_nums),
+                    "oldstyle-nums" => (&mut result.oldstyle_nums, result.lining_nums),
+                    "proportional-nums" => (&mut result.proportional_nums, result.tabular_nums),
+                    "tabular-nums" => (&mut result.tabular_nums, result.proportional_nums),
+                    "diagonal-fractions" => {
+                        (&mut result.diagonal_fractions, result.stacked_fractions)
+                    }
+                    "stacked-fractions" => {
+                        (&mut result.stacked_fractions, result.diagonal_fractions)
+                    }
+                    "ordinal" => (&mut result.ordinal, false),
+                    "slashed-zero" => (&mut result.slashed_zero, false),
+                    _ => return Err(()),
+                };
+                if *slot || exclusive_with {
+                    return Err(())
+                }
+                *slot = true;
+                empty = false;
+            }
+            if !empty { Ok(result) } else { Err(()) }
+        }
+    </%self:longhand>
+
+    <%self:longhand name="font-feature-settings">
+        pub use super::computed_as_specified as to_computed_value;
+        pub mod computed_value {
+            /// A single OpenType feature setting, e.g. `"liga" 0`.
+            #[deriving(PartialEq, Clone, Show)]
+            pub struct FeatureTagValue {
+                /// The four-character OpenType feature tag.
+                pub tag: String,
+                pub value: u32,
+            }
+            /// The feature settings, in order of appearance. Empty for `normal`.
+            pub type T = Vec<FeatureTagValue>;
+        }
+        pub type SpecifiedValue = computed_value::T;
+        #[inline] pub fn get_initial_value() -> computed_value::T {
+            vec![]
+        }
+        /// normal | <feature-tag-value>#
+        pub fn parse(input: &[ComponentValue], _base_url: &Url) -> Result<SpecifiedValue, ()> {
+            match one_component_value(input).and_then(get_ident_lower) {
+                Ok(ref keyword) if keyword.as_slice() == "normal" => return Ok(vec![]),
+                _ => {}
+            }
+            parse_slice_comma_separated(input, parse_one_feature)
+        }
+        /// <feature-tag-value> = <string> [ <integer> | on | off ]?
+        pub fn parse_one_feature<'a>(iter: ParserIter) -> Result<FeatureTagValue, ()> {
+            let tag = match iter.next() {
+                Some(&QuotedString(ref tag)) if tag.len() == 4 &&
+                        tag.as_slice().chars().all(|c| c >= ' ' && c <= '~') => tag.clone(),
+                _ => return Err(()),
+            };
+            let value = match iter.next() {
+                Some(&Number(ref value)) => match value.int_value {
+                    Some(value) if value >= 0 => value as u32,
+                    _ => return Err(()),
+                },
+                Some(&Ident(ref value)) => match value.as_slice().to_ascii_lower().as_slice() {
+                    "on" => 1,
+                    "off" => 0,
+                    _ => return Err(()),
+                },
+                Some(component_value) => {
+                    iter.push_back(component_value);
+                    1
+                }
+                None => 1,
+            };
+            Ok(FeatureTagValue {
+                tag: tag,
+                value: value,
+            })
+        }
+    </%self:longhand>
+
+    <%self:single_component_value name="font-language-override">
+        pub use super::computed_as_specified as to_computed_value;
+        pub mod computed_value {
+            /// The OpenType language system tag to shape with, or `None` for `normal`.
+            pub type T = Option<String>;
+        }
+        pub type SpecifiedValue = computed_value::T;
+        #[inline] pub fn get_initial_value() -> computed_value::T {
+            None
+        }
+        /// normal | <string>
+        pub fn from_component_value(input: &ComponentValue, _base_url: &Url)
+                                    -> Result<SpecifiedValue, ()> {
+            match input {
+                &QuotedString(ref value) => Ok(Some(value.clone())),
+                &Ident(ref value) if value.as_slice().eq_ignore_ascii_case("normal") => Ok(None),
+                _ => Err(()),
+            }
+        }
+    </%self:single_component_value>
 
     <%self:single_component_value name="font-weight">
         #[deriving(Clone)]
@@ -970,6 +1180,15 @@ pub mod longhands {
                         _ => false
                     }
                 }
+
+                /// The numeric value of the weight, from 100 to 900.
+                pub fn value(self) -> u16 {
+                    match self {
+                        % for weight in range(100, 901, 100):
+                            Weight${weight} => ${weight},
+                        % endfor
+                    }
+                }
             }
         }
         #[inline]
@@ -1185,6 +1404,13 @@ pub mod longhands {
 
     ${single_keyword("white-space", "normal pre nowrap")}
 
+    // CSS Text Module Level 3
+    // http://dev.w3.org/csswg/css-text/
+
+    ${single_keyword("overflow-wrap", "normal break-word")}
+
+    ${single_keyword("word-break", "normal break-all keep-all")}
+
     // CSS 2.1, Section 17 - Tables
     ${new_style_struct("Table", is_inherited=False)}
 
@@ -1541,6 +1767,16 @@ pub mod shorthands {
         })
     </%self:shorthand>
 
+    // `word-wrap` is a legacy name for `overflow-wrap`.
+    // http://dev.w3.org/csswg/css-text/#overflow-wrap-property
+    <%self:shorthand name="word-wrap" sub_properties="overflow-wrap">
+        overflow_wrap::parse(input, base_url).map(|value| {
+            Longhands {
+                overflow_wrap: Some(value),
+            }
+        })
+    </%self:shorthand>
+
     <%self:shorthand name="font" sub_properties="font-style font-variant font-weight
                                                  font-size line-height font-family">
         let mut iter = input.skip_whitespace();
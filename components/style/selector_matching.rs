@@ -3,6 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use std::ascii::AsciiExt;
+use std::cmp::max;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::num::div_rem;
@@ -25,6 +26,7 @@ use properties::{PropertyDeclaration, PropertyDeclarationBlock, SpecifiedValue,
 use properties::{specified};
 use selectors::*;
 use stylesheets::{Stylesheet, iter_stylesheet_media_rules, iter_stylesheet_style_rules};
+use stylesheets::{StyleRule, style_rules};
 
 pub enum StylesheetOrigin {
     UserAgentOrigin,
@@ -305,7 +307,8 @@ impl Stylist {
                 Url::parse(format!("chrome:///{}", filename).as_slice()).unwrap(),
                 None,
                 None,
-                UserAgentOrigin);
+                UserAgentOrigin,
+                false);
             stylist.add_stylesheet(ua_stylesheet);
         }
         stylist
@@ -396,6 +399,97 @@ impl Stylist {
         self.is_dirty = true;
     }
 
+    /// Replaces the stylesheet at the given index, in the order that stylesheets were added.
+    pub fn replace_stylesheet(&mut self, index: uint, stylesheet: Stylesheet) {
+        self.stylesheets[index] = stylesheet;
+        self.is_dirty = true;
+    }
+
+    pub fn stylesheets(&self) -> &[Stylesheet] {
+        self.stylesheets.as_slice()
+    }
+
+    /// Returns the stylesheet rules that apply to the given element, in the order that they come
+    /// in the stylesheets, followed by the element's style attribute if it has one. Returns
+    /// nothing if stylesheets have changed since the last `update`.
+    pub fn matched_rules<'a,E,N>(&self, element: &N) -> Vec<MatchedRule>
+                                 where E: TElement<'a> + TElementAttributes,
+                                       N: TNode<'a,E> {
+        if self.is_dirty {
+            return vec!()
+        }
+
+        let map = &self.element_map;
+        let mut blocks = vec!();
+        let mut shareable = true;
+        for selector_map in [
+            &map.user_agent.normal,
+            &map.user.normal,
+            &map.author.normal,
+            &map.author.important,
+            &map.user.important,
+            &map.user_agent.important,
+        ].iter() {
+            selector_map.get_all_matching_rules(element, &None, &mut blocks, &mut shareable);
+        }
+
+        // A rule is matched once for each of its selectors that matches, and once more if it has
+        // `!important` declarations. Keep the most specific of its selectors that match.
+        let mut specificities: Vec<Option<u32>> = Vec::from_elem(self.rules_source_order, None);
+        for block in blocks.iter() {
+            let specificity = &mut specificities.as_mut_slice()[block.source_order];
+            *specificity = Some(max(specificity.unwrap_or(0), block.specificity));
+        }
+
+        // Source orders count the rules that apply to the device across all stylesheets, as
+        // `update` numbers them, so walk the rules in the same order to find where each one is.
+        let mut matched_rules = vec!();
+        let mut source_order = 0u;
+        for (stylesheet_index, stylesheet) in self.stylesheets.iter().enumerate() {
+            let all_rules = style_rules(stylesheet);
+            let mut rule_index = 0u;
+            iter_stylesheet_style_rules(stylesheet, &self.device, |style_rule| {
+                while all_rules[rule_index] as *const StyleRule != style_rule as *const StyleRule {
+                    rule_index += 1;
+                }
+                match specificities[source_order] {
+                    Some(specificity) => {
+                        let (selector_text, declarations_text) =
+                            stylesheet.rule_text(rule_index)
+                                      .unwrap_or(("".to_string(), "".to_string()));
+                        matched_rules.push(MatchedRule {
+                            stylesheet: Some(stylesheet_index),
+                            rule: rule_index,
+                            selector_text: selector_text,
+                            declarations_text: declarations_text,
+                            line: style_rule.line,
+                            column: style_rule.column,
+                            specificity: specificity,
+                        })
+                    }
+                    None => (),
+                }
+                source_order += 1;
+            });
+        }
+
+        match element.as_element().get_attr(&ns!(""), &atom!("style")) {
+            Some(style) => {
+                matched_rules.push(MatchedRule {
+                    stylesheet: None,
+                    rule: 0,
+                    selector_text: "".to_string(),
+                    declarations_text: style.trim().to_string(),
+                    line: 0,
+                    column: 0,
+                    specificity: 0,
+                })
+            }
+            None => (),
+        }
+        matched_rules
+    }
+
     /// Returns the applicable CSS declarations for the given element. This corresponds to
     /// `ElementRuleCollector` in WebKit.
     ///
@@ -576,6 +670,23 @@ struct Rule {
     declarations: DeclarationBlock,
 }
 
+/// A style rule that applies to an element, as returned by `Stylist::matched_rules`.
+pub struct MatchedRule {
+    /// The index of the rule's stylesheet, in the order that stylesheets were added, or `None`
+    /// for the element's style attribute.
+    pub stylesheet: Option<uint>,
+    /// The index of the rule in its stylesheet, as numbered by `style_rules`.
+    pub rule: uint,
+    pub selector_text: String,
+    pub declarations_text: String,
+    pub line: uint,
+    pub column: uint,
+    /// The specificity of the most specific of the rule's selectors that match the element.
+    /// This is not where the rule comes in the cascade, which also depends on its origin and
+    /// whether its declarations are `!important`.
+    pub specificity: u32,
+}
+
 /// A property declaration together with its precedence among rules of equal specificity so that
 /// we can sort them.
 #[deriving(Clone)]
@@ -9,6 +9,7 @@ use url::Url;
 use encoding::EncodingRef;
 
 use cssparser::{decode_stylesheet_bytes, tokenize, parse_stylesheet_rules, ToCss};
+use cssparser::parse_declaration_list;
 use cssparser::ast::*;
 use selectors;
 use properties;
@@ -16,7 +17,7 @@ use errors::{ErrorLoggerIterator, log_css_error};
 use namespaces::{NamespaceMap, parse_namespace_rule};
 use media_queries::{Device, MediaRule, parse_media_rule};
 use media_queries;
-use font_face::{FontFaceRule, Source, parse_font_face_rule, iter_font_face_rules_inner};
+use font_face::{FontFaceRule, parse_font_face_rule, iter_font_face_rules_inner};
 use selector_matching::StylesheetOrigin;
 
 
@@ -25,6 +26,10 @@ pub struct Stylesheet {
     /// cascading order)
     rules: Vec<CSSRule>,
     pub origin: StylesheetOrigin,
+    pub base_url: Url,
+    /// The text that the stylesheet was parsed from, for the devtools style editor. Only kept
+    /// when asked for.
+    pub source: Option<String>,
 }
 
 
@@ -38,31 +43,40 @@ pub enum CSSRule {
 pub struct StyleRule {
     pub selectors: Vec<selectors::Selector>,
     pub declarations: properties::PropertyDeclarationBlock,
+    /// Where the rule starts in its stylesheet's source.
+    pub line: uint,
+    pub column: uint,
 }
 
 
 impl Stylesheet {
     pub fn from_bytes_iter<I: Iterator<Vec<u8>>>(
             mut input: I, base_url: Url, protocol_encoding_label: Option<&str>,
-            environment_encoding: Option<EncodingRef>, origin: StylesheetOrigin) -> Stylesheet {
+            environment_encoding: Option<EncodingRef>, origin: StylesheetOrigin,
+            keep_source: bool) -> Stylesheet {
         let mut bytes = vec!();
         // TODO: incremental decoding and tokinization/parsing
         for chunk in input {
             bytes.push_all(chunk.as_slice())
         }
-        Stylesheet::from_bytes(bytes.as_slice(), base_url, protocol_encoding_label, environment_encoding, origin)
+        Stylesheet::from_bytes(bytes.as_slice(), base_url, protocol_encoding_label,
+                               environment_encoding, origin, keep_source)
     }
 
     pub fn from_bytes(
             bytes: &[u8], base_url: Url, protocol_encoding_label: Option<&str>,
-            environment_encoding: Option<EncodingRef>, origin: StylesheetOrigin) -> Stylesheet {
+            environment_encoding: Option<EncodingRef>, origin: StylesheetOrigin,
+            keep_source: bool) -> Stylesheet {
         // TODO: bytes.as_slice could be bytes.container_as_bytes()
         let (string, _) = decode_stylesheet_bytes(
             bytes.as_slice(), protocol_encoding_label, environment_encoding);
-        Stylesheet::from_str(string.as_slice(), base_url, origin)
+        Stylesheet::from_str(string.as_slice(), base_url, origin, keep_source)
     }
 
-    pub fn from_str(css: &str, base_url: Url, origin: StylesheetOrigin) -> Stylesheet {
+    /// Parses a stylesheet. `keep_source` keeps its text around for the devtools, which need it
+    /// to show and edit the stylesheet and its rules.
+    pub fn from_str(css: &str, base_url: Url, origin: StylesheetOrigin, keep_source: bool)
+                    -> Stylesheet {
         static STATE_CHARSET: uint = 1;
         static STATE_IMPORTS: uint = 2;
         static STATE_NAMESPACES: uint = 3;
@@ -124,6 +138,108 @@ impl Stylesheet {
         Stylesheet {
             rules: rules,
             origin: origin,
+            base_url: base_url,
+            source: if keep_source { Some(css.to_string()) } else { None },
+        }
+    }
+
+    /// Returns the selectors and the declarations of a style rule as written, without the
+    /// braces. Rules are numbered as `style_rules` lists them. Returns `None` if there is no such
+    /// rule, or if the source of the stylesheet was not kept.
+    pub fn rule_text(&self, rule: uint) -> Option<(String, String)> {
+        let source = match self.source {
+            Some(ref source) => source.as_slice(),
+            None => return None,
+        };
+        self.rule_bounds(rule).map(|(rule_start, block_start, block_end)| {
+            (source.slice(rule_start, block_start - 1).trim().to_string(),
+             source.slice(block_start, block_end).trim().to_string())
+        })
+    }
+
+    /// Returns the source of this stylesheet with the declarations of a style rule replaced.
+    /// Rules are numbered as `style_rules` lists them. Returns `None` if there is no such rule.
+    pub fn with_rule_declarations(&self, rule: uint, declarations: &str) -> Option<String> {
+        let source = match self.source {
+            Some(ref source) => source.as_slice(),
+            None => return None,
+        };
+        self.rule_bounds(rule).map(|(_, block_start, block_end)| {
+            format!("{}{}{}", source.slice_to(block_start), declarations,
+                    source.slice_from(block_end))
+        })
+    }
+
+    /// Returns the byte offsets in the source where a style rule starts, where its declarations
+    /// start just after the opening brace, and where they end at the closing brace.
+    fn rule_bounds(&self, rule: uint) -> Option<(uint, uint, uint)> {
+        let (line, column) = match style_rules(self).get(rule) {
+            Some(rule) => (rule.line, rule.column),
+            None => return None,
+        };
+        let source = match self.source {
+            Some(ref source) => source.as_slice(),
+            None => return None,
+        };
+
+        // Find where the rule starts, then the braces around its declarations, skipping any
+        // that are in strings or comments.
+        let mut chars = source.char_indices().peekable();
+        let (mut current_line, mut current_column) = (1u, 1u);
+        while (current_line, current_column) < (line, column) {
+            match chars.next() {
+                Some((_, '\n')) => {
+                    current_line += 1;
+                    current_column = 1;
+                }
+                Some(_) => current_column += 1,
+                None => return None,
+            }
+        }
+        let rule_start = match chars.peek() {
+            Some(&(index, _)) => index,
+            None => return None,
+        };
+
+        let mut block_start = None;
+        let mut depth = 0u;
+        let mut quote = None;
+        loop {
+            let (index, c) = match chars.next() {
+                Some(next) => next,
+                None => return None,
+            };
+            match (quote, c) {
+                (Some(_), '\\') => {
+                    chars.next();
+                }
+                (Some(q), c) if q == c => quote = None,
+                (Some(_), _) => (),
+                (None, '"') | (None, '\'') => quote = Some(c),
+                (None, '/') if chars.peek().map(|&(_, c)| c) == Some('*') => {
+                    chars.next();
+                    let mut previous = ' ';
+                    for (_, c) in chars.by_ref() {
+                        if previous == '*' && c == '/' {
+                            break
+                        }
+                        previous = c;
+                    }
+                }
+                (None, '{') => {
+                    if block_start.is_none() {
+                        block_start = Some(index + 1);
+                    }
+                    depth += 1;
+                }
+                (None, '}') if depth > 0 => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some((rule_start, block_start.unwrap(), index));
+                    }
+                }
+                (None, _) => (),
+            }
         }
     }
 }
@@ -137,7 +253,9 @@ pub fn parse_style_rule(rule: QualifiedRule, parent_rules: &mut Vec<CSSRule>,
     match selectors::parse_selector_list(prelude.into_iter(), namespaces) {
         Ok(selectors) => parent_rules.push(CSSStyleRule(StyleRule{
             selectors: selectors,
-            declarations: properties::parse_property_declaration_list(block.into_iter(), base_url)
+            declarations: properties::parse_property_declaration_list(block.into_iter(), base_url),
+            line: location.line,
+            column: location.column,
         })),
         Err(()) => log_css_error(location, format!(
             "Invalid/unsupported selector: {}", serialized).as_slice()),
@@ -170,6 +288,59 @@ pub fn iter_style_rules<'a>(rules: &[CSSRule], device: &media_queries::Device,
     }
 }
 
+/// Returns all the style rules of a stylesheet in source order, including those in `@media`
+/// rules that do not apply to the current device.
+pub fn style_rules<'a>(stylesheet: &'a Stylesheet) -> Vec<&'a StyleRule> {
+    fn push_style_rules<'a>(rules: &'a [CSSRule], result: &mut Vec<&'a StyleRule>) {
+        for rule in rules.iter() {
+            match *rule {
+                CSSStyleRule(ref rule) => result.push(rule),
+                CSSMediaRule(ref rule) => push_style_rules(rule.rules.as_slice(), result),
+                CSSFontFaceRule(_) => {},
+            }
+        }
+    }
+
+    let mut result = vec!();
+    push_style_rules(stylesheet.rules.as_slice(), &mut result);
+    result
+}
+
+/// Returns the text of a declaration block with the declarations of a property removed and, if
+/// a value is given, a declaration of it with that value and importance added at the end.
+pub fn modify_declarations_text(text: &str, name: &str, value: Option<(&str, bool)>) -> String {
+    let name = name.trim().to_ascii_lower();
+    let mut declarations = vec!();
+    for item in ErrorLoggerIterator(parse_declaration_list(tokenize(text))) {
+        match item {
+            Declaration_(declaration) => {
+                if declaration.name.as_slice().to_ascii_lower() == name {
+                    continue
+                }
+                declarations.push(format!("{}: {}{}",
+                                          declaration.name,
+                                          declaration.value.iter().to_css().as_slice().trim(),
+                                          if declaration.important { " !important" } else { "" }));
+            }
+            DeclAtRule(rule) => log_css_error(rule.location, format!(
+                "Unsupported at-rule in declaration list: @{:s}", rule.name).as_slice()),
+        }
+    }
+    match value {
+        Some((value, important)) => {
+            declarations.push(format!("{}: {}{}", name, value.trim(),
+                                      if important { " !important" } else { "" }));
+        }
+        None => {}
+    }
+
+    let mut text = declarations.connect("; ");
+    if !text.is_empty() {
+        text.push(';');
+    }
+    text
+}
+
 pub fn iter_stylesheet_media_rules(stylesheet: &Stylesheet, callback: |&MediaRule|) {
     for rule in stylesheet.rules.iter() {
         match *rule {
@@ -188,6 +359,61 @@ pub fn iter_stylesheet_style_rules(stylesheet: &Stylesheet, device: &media_queri
 
 #[inline]
 pub fn iter_font_face_rules(stylesheet: &Stylesheet, device: &Device,
-                            callback: |family: &str, source: &Source|) {
+                            callback: |rule: &FontFaceRule|) {
     iter_font_face_rules_inner(stylesheet.rules.as_slice(), device, callback)
 }
+
+#[cfg(test)]
+mod tests {
+    use selector_matching::AuthorOrigin;
+    use super::{Stylesheet, modify_declarations_text, style_rules};
+    use url::Url;
+
+    fn stylesheet(css: &str) -> Stylesheet {
+        Stylesheet::from_str(css, Url::parse("http://localhost").unwrap(), AuthorOrigin, true)
+    }
+
+    #[test]
+    fn test_style_rules_include_media_rules() {
+        let sheet = stylesheet("a { color: red }\n@media print { p { color: blue } }\nb {}");
+        let rules = style_rules(&sheet);
+        assert_eq!(rules.len(), 3);
+        assert_eq!((rules[2].line, rules[2].column), (3, 1));
+        assert_eq!(sheet.rule_text(0), Some(("a".to_string(), "color: red".to_string())));
+        assert_eq!(sheet.rule_text(1), Some(("p".to_string(), "color: blue".to_string())));
+        assert_eq!(sheet.rule_text(3), None);
+    }
+
+    #[test]
+    fn test_source_is_only_kept_when_asked_for() {
+        let css = "a { color: red }";
+        let sheet = Stylesheet::from_str(css, Url::parse("http://localhost").unwrap(),
+                                         AuthorOrigin, false);
+        assert!(sheet.source.is_none());
+        assert_eq!(sheet.rule_text(0), None);
+        assert_eq!(sheet.with_rule_declarations(0, ""), None);
+    }
+
+    #[test]
+    fn test_with_rule_declarations() {
+        let sheet = stylesheet("a { color: red }\n/* { */ b[title=\"{\"] { color: blue }\n");
+        assert_eq!(sheet.with_rule_declarations(1, " color: green "),
+                   Some("a { color: red }\n/* { */ b[title=\"{\"] { color: green }\n".to_string()));
+        assert_eq!(sheet.with_rule_declarations(2, ""), None);
+    }
+
+    #[test]
+    fn test_modify_declarations_text() {
+        let text = "background: url(data:image/png;base64,AAAA); content: \"a;b\"; COLOR: red";
+        let modified = modify_declarations_text(text, "color", Some(("blue", true)));
+        assert!(modified.as_slice().contains("base64,AAAA"));
+        assert!(modified.as_slice().contains("a;b"));
+        assert!(!modified.as_slice().contains("red"));
+        assert!(modified.as_slice().ends_with("; color: blue !important;"));
+
+        let modified = modify_declarations_text(text, "content", None);
+        assert!(modified.as_slice().contains("base64,AAAA"));
+        assert!(!modified.as_slice().contains("a;b"));
+        assert!(modified.as_slice().ends_with("; COLOR: red;"));
+    }
+}
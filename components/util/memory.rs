@@ -5,6 +5,7 @@
 //! Memory profiling functions.
 
 use libc::{c_char,c_int,c_void,size_t};
+use std::collections::HashMap;
 use std::io::timer::sleep;
 #[cfg(target_os="linux")]
 use std::io::File;
@@ -17,6 +18,7 @@ use task::spawn_named;
 #[cfg(target_os="macos")]
 use task_info::task_basic_info::{virtual_size,resident_size};
 
+#[deriving(Clone)]
 pub struct MemoryProfilerChan(pub Sender<MemoryProfilerMsg>);
 
 impl MemoryProfilerChan {
@@ -26,7 +28,27 @@ impl MemoryProfilerChan {
     }
 }
 
+/// A measurement made by a memory reporter.
+pub struct MemoryReport {
+    /// The name of what was measured.
+    pub path: String,
+    /// The size in bytes.
+    pub size: u64,
+}
+
+/// Something that measures the memory it uses itself, such as a cache that lives in another task.
+pub trait MemoryReporter {
+    /// Sends this reporter's measurements to `reports_chan`. Returns false if the reporter can
+    /// no longer measure anything, e.g. because its task has exited.
+    fn collect_reports(&self, reports_chan: Sender<Vec<MemoryReport>>) -> bool;
+}
+
 pub enum MemoryProfilerMsg {
+    /// Adds a reporter, whose measurements are printed along with the others. Reporters are
+    /// identified by name.
+    RegisterReporterMsg(String, Box<MemoryReporter + Send>),
+    /// Removes the reporter with the given name.
+    UnregisterReporterMsg(String),
     /// Message used to force print the memory profiling metrics.
     PrintMsg,
     /// Tells the memory profiler to shut down.
@@ -35,6 +57,8 @@ pub enum MemoryProfilerMsg {
 
 pub struct MemoryProfiler {
     pub port: Receiver<MemoryProfilerMsg>,
+    /// The reporters, by name.
+    reporters: HashMap<String, Box<MemoryReporter + Send>>,
 }
 
 impl MemoryProfiler {
@@ -54,7 +78,7 @@ impl MemoryProfiler {
                 });
                 // Spawn the memory profiler.
                 spawn_named("Memory profiler", proc() {
-                    let memory_profiler = MemoryProfiler::new(port);
+                    let mut memory_profiler = MemoryProfiler::new(port);
                     memory_profiler.start();
                 });
             }
@@ -77,11 +101,12 @@ impl MemoryProfiler {
 
     pub fn new(port: Receiver<MemoryProfilerMsg>) -> MemoryProfiler {
         MemoryProfiler {
-            port: port
+            port: port,
+            reporters: HashMap::new(),
         }
     }
 
-    pub fn start(&self) {
+    pub fn start(&mut self) {
         loop {
             match self.port.recv_opt() {
                Ok(msg) => {
@@ -94,8 +119,16 @@ impl MemoryProfiler {
         }
     }
 
-    fn handle_msg(&self, msg: MemoryProfilerMsg) -> bool {
+    fn handle_msg(&mut self, msg: MemoryProfilerMsg) -> bool {
         match msg {
+            RegisterReporterMsg(name, reporter) => {
+                self.reporters.insert(name, reporter);
+                true
+            },
+            UnregisterReporterMsg(name) => {
+                self.reporters.remove(&name);
+                true
+            },
             PrintMsg => {
                 self.handle_print_msg();
                 true
@@ -116,7 +149,7 @@ impl MemoryProfiler {
         }
     }
 
-    fn handle_print_msg(&self) {
+    fn handle_print_msg(&mut self) {
         println!("{:16s}: {:12s}", "_category_", "_size (MiB)_");
 
         // Virtual and physical memory usage, as reported by the OS.
@@ -139,6 +172,30 @@ impl MemoryProfiler {
         // |stats.active|. This does not include inactive chunks.
         MemoryProfiler::print_measurement("heap-mapped",    get_jemalloc_stat("stats.mapped"));
 
+        // Measurements from the reporters. Reporters that can't measure anything any more are
+        // dropped.
+        let mut dead_reporters = vec!();
+        for (name, reporter) in self.reporters.iter() {
+            let (reports_chan, reports_port) = channel();
+            let reports = if reporter.collect_reports(reports_chan) {
+                reports_port.recv_opt().ok()
+            } else {
+                None
+            };
+            match reports {
+                Some(reports) => {
+                    for report in reports.iter() {
+                        MemoryProfiler::print_measurement(report.path.as_slice(),
+                                                          Some(report.size));
+                    }
+                }
+                None => dead_reporters.push(name.clone()),
+            }
+        }
+        for name in dead_reporters.iter() {
+            self.reporters.remove(name);
+        }
+
         println!("");
     }
 }
@@ -100,12 +100,36 @@ pub struct Opts {
     /// remote Firefox devtools connections.
     pub devtools_port: Option<u16>,
 
+    /// `None` to disable WebDriver or `Some` with a port number to start a server to listen to
+    /// remote WebDriver commands.
+    pub webdriver_port: Option<u16>,
+
     /// The initial requested size of the window.
     pub initial_window_size: TypedSize2D<ScreenPx, uint>,
 
     /// An optional string allowing the user agent to be set for testing.
     pub user_agent: Option<String>,
 
+    /// A directory that the HTTP cache writes responses to, so that they are kept across runs.
+    /// Responses are only cached in memory if this is `None`.
+    pub http_cache_dir: Option<Path>,
+
+    /// The proxy to load HTTP and HTTPS URLs through, instead of the one in the `http_proxy` and
+    /// `https_proxy` environment variables.
+    pub proxy: Option<String>,
+
+    /// The comma-separated hosts to connect to without the proxy, instead of the ones in the
+    /// `no_proxy` environment variable.
+    pub no_proxy: Option<String>,
+
+    /// How long network connections may wait to connect or for data, in seconds, or `None` to
+    /// wait for as long as the system allows.
+    pub network_timeout: Option<u64>,
+
+    /// How many bytes of decoded images to keep in memory while they aren't being displayed, or
+    /// `None` to keep all of them.
+    pub image_cache_size: Option<uint>,
+
     /// Dumps the flow tree after a layout.
     pub dump_flow_tree: bool,
 
@@ -174,8 +198,14 @@ pub fn default_opts() -> Opts {
         enable_text_antialiasing: false,
         trace_layout: false,
         devtools_port: None,
+        webdriver_port: None,
         initial_window_size: TypedSize2D(800, 600),
         user_agent: None,
+        http_cache_dir: None,
+        proxy: None,
+        no_proxy: None,
+        network_timeout: None,
+        image_cache_size: None,
         dump_flow_tree: false,
         validate_display_list_geometry: false,
         profile_tasks: false,
@@ -203,8 +233,14 @@ pub fn from_cmdline_args(args: &[String]) -> bool {
         getopts::optflag("z", "headless", "Headless mode"),
         getopts::optflag("f", "hard-fail", "Exit on task failure instead of displaying about:failure"),
         getopts::optflagopt("", "devtools", "Start remote devtools server on port", "6000"),
+        getopts::optflagopt("", "webdriver", "Start remote WebDriver server on port", "7000"),
         getopts::optopt("", "resolution", "Set window resolution.", "800x600"),
         getopts::optopt("u", "user-agent", "Set custom user agent string", "NCSA Mosaic/1.0 (X11;SunOS 4.1.4 sun4m)"),
+        getopts::optopt("", "http-cache-dir", "Keep cached HTTP responses in a directory", "DIR"),
+        getopts::optopt("", "proxy", "Load HTTP and HTTPS URLs through a proxy", "URL"),
+        getopts::optopt("", "no-proxy", "Hosts to connect to without the proxy", "HOSTS"),
+        getopts::optopt("", "network-timeout", "Seconds to wait for network connections", "30"),
+        getopts::optopt("", "image-cache-size", "Megabytes of decoded images to keep", "256"),
         getopts::optopt("Z", "debug", "A comma-separated string of debug options. Pass help to show available options.", ""),
         getopts::optflag("h", "help", "Print this message"),
         getopts::optopt("r", "render-api", "Set the rendering API to use", "gl|mesa"),
@@ -287,6 +323,19 @@ pub fn from_cmdline_args(args: &[String]) -> bool {
         from_str(port.as_slice()).unwrap()
     });
 
+    let webdriver_port = opt_match.opt_default("webdriver", "7000").map(|port| {
+        from_str(port.as_slice()).unwrap()
+    });
+
+    let network_timeout = opt_match.opt_str("network-timeout").map(|seconds| {
+        from_str(seconds.as_slice()).unwrap()
+    });
+
+    let image_cache_size = opt_match.opt_str("image-cache-size").map(|megabytes| {
+        let megabytes: uint = from_str(megabytes.as_slice()).unwrap();
+        megabytes * 1024 * 1024
+    });
+
     let initial_window_size = match opt_match.opt_str("resolution") {
         Some(res_string) => {
             let res: Vec<uint> = res_string.as_slice().split('x').map(|r| from_str(r).unwrap()).collect();
@@ -324,8 +373,14 @@ pub fn from_cmdline_args(args: &[String]) -> bool {
         profile_tasks: debug_options.contains(&"profile-tasks"),
         trace_layout: trace_layout,
         devtools_port: devtools_port,
+        webdriver_port: webdriver_port,
         initial_window_size: initial_window_size,
         user_agent: opt_match.opt_str("u"),
+        http_cache_dir: opt_match.opt_str("http-cache-dir").map(|dir| Path::new(dir)),
+        proxy: opt_match.opt_str("proxy"),
+        no_proxy: opt_match.opt_str("no-proxy"),
+        network_timeout: network_timeout,
+        image_cache_size: image_cache_size,
         show_debug_borders: debug_options.contains(&"show-compositor-borders"),
         show_debug_fragment_borders: debug_options.contains(&"show-fragment-borders"),
         enable_text_antialiasing: !debug_options.contains(&"disable-text-aa"),
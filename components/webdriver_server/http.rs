@@ -0,0 +1,146 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/// Just enough HTTP/1.1 to serve WebDriver clients: one request per connection, with an
+/// optional `Content-Length` body and a JSON response.
+
+use std::ascii::AsciiExt;
+use std::io::{Buffer, IoError, IoResult, InvalidInput, Writer};
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+fn invalid_request(desc: &'static str) -> IoError {
+    IoError {
+        kind: InvalidInput,
+        desc: desc,
+        detail: None,
+    }
+}
+
+/// Reads the request line, the headers and the body of a single request.
+pub fn read_request<R: Buffer>(reader: &mut R) -> IoResult<Request> {
+    let request_line = try!(reader.read_line());
+    let mut parts = request_line.as_slice().trim_right().split(' ');
+    let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/") => {
+            (method.to_string(), target)
+        }
+        _ => return Err(invalid_request("malformed request line")),
+    };
+
+    // Clients may send a query string, but no WebDriver command uses one.
+    let path = match target.find('?') {
+        Some(index) => target.slice_to(index),
+        None => target,
+    };
+
+    let mut content_length = 0u;
+    loop {
+        let line = try!(reader.read_line());
+        let line = line.as_slice().trim_right();
+        if line.is_empty() {
+            break
+        }
+        let (name, value) = match line.find(':') {
+            Some(index) => (line.slice_to(index), line.slice_from(index + 1).trim()),
+            None => return Err(invalid_request("malformed header")),
+        };
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = match from_str(value) {
+                Some(length) => length,
+                None => return Err(invalid_request("malformed Content-Length")),
+            };
+        }
+    }
+
+    let body = try!(reader.read_exact(content_length));
+    let body = match String::from_utf8(body) {
+        Ok(body) => body,
+        Err(_) => return Err(invalid_request("request body is not UTF-8")),
+    };
+
+    Ok(Request {
+        method: method,
+        path: path.to_string(),
+        body: body,
+    })
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        _ => "Unknown",
+    }
+}
+
+pub fn write_response<W: Writer>(writer: &mut W, response: &Response) -> IoResult<()> {
+    try!(write!(writer, "HTTP/1.1 {} {}\r\n", response.status, reason_phrase(response.status)));
+    try!(writer.write_str("Content-Type: application/json; charset=utf-8\r\n"));
+    try!(write!(writer, "Content-Length: {}\r\n", response.body.len()));
+    try!(writer.write_str("Cache-Control: no-cache\r\n"));
+    try!(writer.write_str("Connection: close\r\n\r\n"));
+    try!(writer.write_str(response.body.as_slice()));
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Response, read_request, write_response};
+    use std::io::{MemReader, MemWriter};
+
+    fn parse(request: &str) -> Option<(String, String, String)> {
+        let mut reader = MemReader::new(request.as_bytes().to_vec());
+        read_request(&mut reader).ok().map(|request| {
+            (request.method, request.path, request.body)
+        })
+    }
+
+    #[test]
+    fn test_read_request_without_body() {
+        let request = parse("GET /session/1/url HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert_eq!(request, Some(("GET".to_string(), "/session/1/url".to_string(), String::new())));
+    }
+
+    #[test]
+    fn test_read_request_with_body() {
+        let request = parse("POST /session HTTP/1.1\r\ncontent-length: 2\r\n\r\n{}trailing");
+        assert_eq!(request, Some(("POST".to_string(), "/session".to_string(), "{}".to_string())));
+    }
+
+    #[test]
+    fn test_read_request_strips_query() {
+        let request = parse("GET /status?verbose=1 HTTP/1.1\r\n\r\n");
+        assert_eq!(request.map(|(_, path, _)| path), Some("/status".to_string()));
+    }
+
+    #[test]
+    fn test_read_request_rejects_garbage() {
+        assert_eq!(parse("hello\r\n\r\n"), None);
+        assert_eq!(parse("GET / HTTP/1.1\r\nno colon\r\n\r\n"), None);
+        assert_eq!(parse("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\n{}"), None);
+    }
+
+    #[test]
+    fn test_write_response() {
+        let mut writer = MemWriter::new();
+        write_response(&mut writer, &Response { status: 404, body: "{}".to_string() }).unwrap();
+        let response = String::from_utf8(writer.unwrap()).unwrap();
+        assert!(response.as_slice().starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(response.as_slice().contains("Content-Length: 2\r\n"));
+        assert!(response.as_slice().ends_with("\r\n\r\n{}"));
+    }
+}
@@ -0,0 +1,109 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/// Maps the characters sent by "Element Send Keys" to the key events that a US keyboard would
+/// produce, including the WebDriver codepoints for non-printable keys.
+
+use servo_msg::constellation_msg;
+use servo_msg::constellation_msg::{Key, KeyModifiers, SHIFT};
+
+static LETTERS: [Key, ..26] = [
+    constellation_msg::KeyA, constellation_msg::KeyB, constellation_msg::KeyC,
+    constellation_msg::KeyD, constellation_msg::KeyE, constellation_msg::KeyF,
+    constellation_msg::KeyG, constellation_msg::KeyH, constellation_msg::KeyI,
+    constellation_msg::KeyJ, constellation_msg::KeyK, constellation_msg::KeyL,
+    constellation_msg::KeyM, constellation_msg::KeyN, constellation_msg::KeyO,
+    constellation_msg::KeyP, constellation_msg::KeyQ, constellation_msg::KeyR,
+    constellation_msg::KeyS, constellation_msg::KeyT, constellation_msg::KeyU,
+    constellation_msg::KeyV, constellation_msg::KeyW, constellation_msg::KeyX,
+    constellation_msg::KeyY, constellation_msg::KeyZ,
+];
+
+static DIGITS: [Key, ..10] = [
+    constellation_msg::Key0, constellation_msg::Key1, constellation_msg::Key2,
+    constellation_msg::Key3, constellation_msg::Key4, constellation_msg::Key5,
+    constellation_msg::Key6, constellation_msg::Key7, constellation_msg::Key8,
+    constellation_msg::Key9,
+];
+
+/// Returns the key and modifiers that type `ch`, or `None` if no key does.
+pub fn key_for_char(ch: char) -> Option<(Key, KeyModifiers)> {
+    let unshifted = KeyModifiers::empty();
+    let key = match ch {
+        'a'...'z' => (LETTERS[ch as uint - 'a' as uint], unshifted),
+        'A'...'Z' => (LETTERS[ch as uint - 'A' as uint], SHIFT),
+        '0'...'9' => (DIGITS[ch as uint - '0' as uint], unshifted),
+        ' ' => (constellation_msg::KeySpace, unshifted),
+        '\'' => (constellation_msg::KeyApostrophe, unshifted),
+        '"' => (constellation_msg::KeyApostrophe, SHIFT),
+        ',' => (constellation_msg::KeyComma, unshifted),
+        '<' => (constellation_msg::KeyComma, SHIFT),
+        '-' => (constellation_msg::KeyMinus, unshifted),
+        '_' => (constellation_msg::KeyMinus, SHIFT),
+        '.' => (constellation_msg::KeyPeriod, unshifted),
+        '>' => (constellation_msg::KeyPeriod, SHIFT),
+        '/' => (constellation_msg::KeySlash, unshifted),
+        '?' => (constellation_msg::KeySlash, SHIFT),
+        ';' => (constellation_msg::KeySemicolon, unshifted),
+        ':' => (constellation_msg::KeySemicolon, SHIFT),
+        '=' => (constellation_msg::KeyEqual, unshifted),
+        '+' => (constellation_msg::KeyEqual, SHIFT),
+        '[' => (constellation_msg::KeyLeftBracket, unshifted),
+        '{' => (constellation_msg::KeyLeftBracket, SHIFT),
+        '\\' => (constellation_msg::KeyBackslash, unshifted),
+        '|' => (constellation_msg::KeyBackslash, SHIFT),
+        ']' => (constellation_msg::KeyRightBracket, unshifted),
+        '}' => (constellation_msg::KeyRightBracket, SHIFT),
+        '`' => (constellation_msg::KeyGraveAccent, unshifted),
+        '~' => (constellation_msg::KeyGraveAccent, SHIFT),
+        '!' => (constellation_msg::Key1, SHIFT),
+        '@' => (constellation_msg::Key2, SHIFT),
+        '#' => (constellation_msg::Key3, SHIFT),
+        '$' => (constellation_msg::Key4, SHIFT),
+        '%' => (constellation_msg::Key5, SHIFT),
+        '^' => (constellation_msg::Key6, SHIFT),
+        '&' => (constellation_msg::Key7, SHIFT),
+        '*' => (constellation_msg::Key8, SHIFT),
+        '(' => (constellation_msg::Key9, SHIFT),
+        ')' => (constellation_msg::Key0, SHIFT),
+        '\n' => (constellation_msg::KeyEnter, unshifted),
+        '\t' => (constellation_msg::KeyTab, unshifted),
+        // https://w3c.github.io/webdriver/#keyboard-actions
+        '\ue003' => (constellation_msg::KeyBackspace, unshifted),
+        '\ue004' => (constellation_msg::KeyTab, unshifted),
+        '\ue006' | '\ue007' => (constellation_msg::KeyEnter, unshifted),
+        '\ue00c' => (constellation_msg::KeyEscape, unshifted),
+        '\ue00d' => (constellation_msg::KeySpace, unshifted),
+        '\ue00e' => (constellation_msg::KeyPageUp, unshifted),
+        '\ue00f' => (constellation_msg::KeyPageDown, unshifted),
+        '\ue010' => (constellation_msg::KeyEnd, unshifted),
+        '\ue011' => (constellation_msg::KeyHome, unshifted),
+        '\ue012' => (constellation_msg::KeyLeft, unshifted),
+        '\ue013' => (constellation_msg::KeyUp, unshifted),
+        '\ue014' => (constellation_msg::KeyRight, unshifted),
+        '\ue015' => (constellation_msg::KeyDown, unshifted),
+        '\ue016' => (constellation_msg::KeyInsert, unshifted),
+        '\ue017' => (constellation_msg::KeyDelete, unshifted),
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::key_for_char;
+    use servo_msg::constellation_msg;
+    use servo_msg::constellation_msg::{KeyModifiers, SHIFT};
+
+    #[test]
+    fn test_key_for_char() {
+        let plain = KeyModifiers::empty();
+        assert_eq!(key_for_char('a'), Some((constellation_msg::KeyA, plain)));
+        assert_eq!(key_for_char('Z'), Some((constellation_msg::KeyZ, SHIFT)));
+        assert_eq!(key_for_char('7'), Some((constellation_msg::Key7, plain)));
+        assert_eq!(key_for_char('?'), Some((constellation_msg::KeySlash, SHIFT)));
+        assert_eq!(key_for_char('\ue007'), Some((constellation_msg::KeyEnter, plain)));
+        assert_eq!(key_for_char('é'), None);
+    }
+}
@@ -0,0 +1,565 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#![crate_name = "webdriver_server"]
+#![crate_type = "rlib"]
+
+#![comment = "The Servo Parallel Browser Project"]
+#![license = "MPL"]
+
+#![feature(phase)]
+
+#[phase(plugin, link)]
+extern crate log;
+
+/// A server for the [WebDriver](https://w3c.github.io/webdriver/) remote control protocol, so
+/// that test harnesses can drive Servo over HTTP. Commands that inspect or manipulate the page
+/// are forwarded by the constellation to the script task of the root frame.
+
+extern crate flate2;
+extern crate serialize;
+extern crate url;
+extern crate uuid;
+extern crate "msg" as servo_msg;
+extern crate "util" as servo_util;
+extern crate webdriver_traits;
+
+use http::{Request, Response};
+use keys::key_for_char;
+use screenshot::encode_png;
+
+use servo_msg::constellation_msg::{ConstellationChan, GetRootPipelineMsg, KeyEvent, LoadData};
+use servo_msg::constellation_msg::{LoadUrlMsg, Msg, NavigateMsg, PipelineId, Pressed, Released};
+use servo_msg::constellation_msg::{ScreenshotMsg, WebDriverCommandMsg};
+use servo_msg::constellation_msg;
+use servo_util::task::spawn_named;
+use webdriver_traits::{WebDriverElementId, WebDriverError, WebDriverScriptCommand};
+use webdriver_traits::{ElementNotVisible, InvalidSelector, StaleElementReference};
+use webdriver_traits::{JSBoolean, JSNull, JSNumber, JSString, JSUndefined};
+
+use serialize::base64::{ToBase64, STANDARD};
+use serialize::json;
+use serialize::json::{Json, JsonObject, ToJson};
+use std::collections::TreeMap;
+use std::comm;
+use std::io::{Acceptor, BufferedStream, Listener, TcpListener};
+use std::io::timer::sleep;
+use std::time::duration::Duration;
+use url::Url;
+
+mod http;
+mod keys;
+mod screenshot;
+
+/// The key that identifies an element reference in JSON.
+static ELEMENT_KEY: &'static str = "element-6066-11e4-a52e-4f735466cecf";
+
+/// How long to wait between checks for the end of a navigation, and how many checks to make.
+static LOAD_POLL_INTERVAL_MS: i64 = 20;
+static LOAD_POLL_ATTEMPTS: uint = 1500;
+
+/// Spin up a WebDriver server that listens for connections on the specified port.
+pub fn start_server(port: u16, constellation_chan: ConstellationChan) {
+    spawn_named("WebDriverServer", proc() {
+        run_server(port, constellation_chan)
+    });
+}
+
+fn run_server(port: u16, constellation_chan: ConstellationChan) {
+    let listener = TcpListener::bind(format!("{}:{}", "127.0.0.1", port).as_slice());
+    let mut acceptor = match listener.listen() {
+        Ok(acceptor) => acceptor,
+        Err(e) => {
+            debug!("WebDriver server could not listen on port {}: {}", port, e);
+            return
+        }
+    };
+
+    let mut handler = Handler::new(constellation_chan);
+    for stream in acceptor.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => BufferedStream::new(stream),
+            Err(e) => {
+                debug!("WebDriver server failed to accept a connection: {}", e);
+                continue
+            }
+        };
+        let response = match http::read_request(&mut stream) {
+            Ok(request) => handler.handle_request(&request),
+            Err(e) => CommandError::new(400, "invalid argument", e.desc.to_string()).to_response(),
+        };
+        if http::write_response(&mut stream, &response).is_err() {
+            debug!("WebDriver server failed to send a response");
+        }
+    }
+}
+
+/// An error in the format of the WebDriver specification, sent to the client with its status.
+#[deriving(PartialEq, Show)]
+struct CommandError {
+    status: u16,
+    error: &'static str,
+    message: String,
+}
+
+impl CommandError {
+    fn new(status: u16, error: &'static str, message: String) -> CommandError {
+        CommandError {
+            status: status,
+            error: error,
+            message: message,
+        }
+    }
+
+    fn invalid_argument(message: &str) -> CommandError {
+        CommandError::new(400, "invalid argument", message.to_string())
+    }
+
+    fn no_such_window() -> CommandError {
+        CommandError::new(404, "no such window", "No page has been loaded".to_string())
+    }
+
+    fn from_script_error(error: WebDriverError) -> CommandError {
+        match error {
+            StaleElementReference => CommandError::new(404, "stale element reference",
+                "The element is no longer in the document".to_string()),
+            InvalidSelector => CommandError::new(400, "invalid selector",
+                "The selector could not be parsed".to_string()),
+            ElementNotVisible => CommandError::new(400, "element not interactable",
+                "The element has no box".to_string()),
+        }
+    }
+
+    fn to_response(&self) -> Response {
+        let mut error = TreeMap::new();
+        error.insert("error".to_string(), self.error.to_string().to_json());
+        error.insert("message".to_string(), self.message.to_json());
+        error.insert("stacktrace".to_string(), String::new().to_json());
+        Response {
+            status: self.status,
+            body: value_body(json::Object(error)),
+        }
+    }
+}
+
+type CommandResult = Result<Json, CommandError>;
+
+fn value_body(value: Json) -> String {
+    let mut body = TreeMap::new();
+    body.insert("value".to_string(), value);
+    json::Object(body).to_string()
+}
+
+/// The commands that the server understands, parsed from the request method and path.
+#[deriving(PartialEq, Show)]
+enum Route {
+    Status,
+    NewSession,
+    DeleteSession,
+    Get,
+    GetCurrentUrl,
+    Back,
+    Forward,
+    Refresh,
+    GetTitle,
+    FindElement(Option<WebDriverElementId>),
+    FindElements(Option<WebDriverElementId>),
+    GetElementText(WebDriverElementId),
+    GetElementAttribute(WebDriverElementId, String),
+    GetElementTagName(WebDriverElementId),
+    ElementClick(WebDriverElementId),
+    ElementSendKeys(WebDriverElementId),
+    ExecuteScript,
+    TakeScreenshot,
+}
+
+/// Returns the session ID in the path, if there is one, and the command to run.
+fn parse_route(method: &str, path: &str) -> Result<(Option<String>, Route), CommandError> {
+    let segments: Vec<&str> = path.trim_chars('/').split('/').collect();
+    let (session, command) = match segments.as_slice() {
+        ["status"] => return route_method(method, "GET", (None, Status)),
+        ["session"] => return route_method(method, "POST", (None, NewSession)),
+        ["session", session, command..] => (Some(session.to_string()), command),
+        _ => return Err(unknown_command(path)),
+    };
+
+    let (expected, route) = match command {
+        [] => ("DELETE", DeleteSession),
+        ["url"] if method == "GET" => ("GET", GetCurrentUrl),
+        ["url"] => ("POST", Get),
+        ["back"] => ("POST", Back),
+        ["forward"] => ("POST", Forward),
+        ["refresh"] => ("POST", Refresh),
+        ["title"] => ("GET", GetTitle),
+        ["element"] => ("POST", FindElement(None)),
+        ["elements"] => ("POST", FindElements(None)),
+        ["element", id, "element"] => ("POST", FindElement(Some(id.to_string()))),
+        ["element", id, "elements"] => ("POST", FindElements(Some(id.to_string()))),
+        ["element", id, "text"] => ("GET", GetElementText(id.to_string())),
+        ["element", id, "attribute", name] => {
+            ("GET", GetElementAttribute(id.to_string(), name.to_string()))
+        }
+        ["element", id, "name"] => ("GET", GetElementTagName(id.to_string())),
+        ["element", id, "click"] => ("POST", ElementClick(id.to_string())),
+        ["element", id, "value"] => ("POST", ElementSendKeys(id.to_string())),
+        ["execute", "sync"] | ["execute"] => ("POST", ExecuteScript),
+        ["screenshot"] => ("GET", TakeScreenshot),
+        _ => return Err(unknown_command(path)),
+    };
+    route_method(method, expected, (session, route))
+}
+
+fn route_method<T>(method: &str, expected: &str, route: T) -> Result<T, CommandError> {
+    if method == expected {
+        Ok(route)
+    } else {
+        Err(CommandError::new(405, "unknown method", format!("Expected {}", expected)))
+    }
+}
+
+fn unknown_command(path: &str) -> CommandError {
+    CommandError::new(404, "unknown command", format!("No command at {}", path))
+}
+
+fn element_reference(id: WebDriverElementId) -> Json {
+    let mut reference = TreeMap::new();
+    reference.insert(ELEMENT_KEY.to_string(), id.to_json());
+    json::Object(reference)
+}
+
+fn string_parameter(parameters: &JsonObject, name: &str) -> Result<String, CommandError> {
+    match parameters.get(&name.to_string()).and_then(|value| value.as_string()) {
+        Some(value) => Ok(value.to_string()),
+        None => Err(CommandError::invalid_argument(format!("Missing {}", name).as_slice())),
+    }
+}
+
+struct Handler {
+    constellation_chan: ConstellationChan,
+    session_id: Option<String>,
+}
+
+impl Handler {
+    fn new(constellation_chan: ConstellationChan) -> Handler {
+        Handler {
+            constellation_chan: constellation_chan,
+            session_id: None,
+        }
+    }
+
+    fn handle_request(&mut self, request: &Request) -> Response {
+        let result = parse_route(request.method.as_slice(), request.path.as_slice())
+            .and_then(|(session_id, route)| {
+                if route != Status && route != NewSession && session_id != self.session_id {
+                    return Err(CommandError::new(404, "invalid session id",
+                                                 "No session with that ID".to_string()))
+                }
+                let parameters = if request.body.as_slice().trim().is_empty() {
+                    TreeMap::new()
+                } else {
+                    match json::from_str(request.body.as_slice()) {
+                        Ok(json::Object(parameters)) => parameters,
+                        _ => return Err(CommandError::invalid_argument("Body is not an object")),
+                    }
+                };
+                self.handle_command(route, &parameters)
+            });
+
+        match result {
+            Ok(value) => Response {
+                status: 200,
+                body: value_body(value),
+            },
+            Err(error) => error.to_response(),
+        }
+    }
+
+    fn handle_command(&mut self, route: Route, parameters: &JsonObject) -> CommandResult {
+        match route {
+            Status => {
+                let mut status = TreeMap::new();
+                status.insert("ready".to_string(), self.session_id.is_none().to_json());
+                status.insert("message".to_string(), "Servo".to_string().to_json());
+                Ok(json::Object(status))
+            }
+            NewSession => self.handle_new_session(),
+            DeleteSession => {
+                self.session_id = None;
+                Ok(json::Null)
+            }
+            Get => {
+                let url = try!(string_parameter(parameters, "url"));
+                self.handle_get(url.as_slice())
+            }
+            GetCurrentUrl => {
+                self.run_script_command(|reply| webdriver_traits::GetUrl(reply))
+                    .map(|url| url.to_json())
+            }
+            Back => self.handle_navigate(constellation_msg::Back),
+            Forward => self.handle_navigate(constellation_msg::Forward),
+            Refresh => {
+                let url = try!(self.run_script_command(|reply| webdriver_traits::GetUrl(reply)));
+                self.handle_get(url.as_slice())
+            }
+            GetTitle => {
+                self.run_script_command(|reply| webdriver_traits::GetTitle(reply))
+                    .map(|title| title.to_json())
+            }
+            FindElement(root) => {
+                let selector = try!(selector_parameter(parameters));
+                let element = try!(try!(self.run_script_command(|reply| {
+                    webdriver_traits::FindElementCSS(root.clone(), selector.clone(), reply)
+                })).map_err(CommandError::from_script_error));
+                match element {
+                    Some(element) => Ok(element_reference(element)),
+                    None => Err(CommandError::new(404, "no such element",
+                                                  "No element matches the selector".to_string())),
+                }
+            }
+            FindElements(root) => {
+                let selector = try!(selector_parameter(parameters));
+                let elements = try!(try!(self.run_script_command(|reply| {
+                    webdriver_traits::FindElementsCSS(root.clone(), selector.clone(), reply)
+                })).map_err(CommandError::from_script_error));
+                Ok(elements.into_iter().map(element_reference).collect::<Vec<Json>>().to_json())
+            }
+            GetElementText(element) => {
+                self.run_element_command(|reply| {
+                    webdriver_traits::GetElementText(element.clone(), reply)
+                }).map(|text| text.to_json())
+            }
+            GetElementAttribute(element, name) => {
+                self.run_element_command(|reply| {
+                    webdriver_traits::GetElementAttribute(element.clone(), name.clone(), reply)
+                }).map(|value| value.map_or(json::Null, |value| value.to_json()))
+            }
+            GetElementTagName(element) => {
+                self.run_element_command(|reply| {
+                    webdriver_traits::GetElementTagName(element.clone(), reply)
+                }).map(|name| name.to_json())
+            }
+            ElementClick(element) => {
+                self.run_element_command(|reply| {
+                    webdriver_traits::ClickElement(element.clone(), reply)
+                }).map(|_| json::Null)
+            }
+            ElementSendKeys(element) => {
+                let text = try!(string_parameter(parameters, "text"));
+                try!(self.run_element_command(|reply| {
+                    webdriver_traits::FocusElement(element.clone(), reply)
+                }));
+                self.send_keys(text.as_slice())
+            }
+            ExecuteScript => self.handle_execute_script(parameters),
+            TakeScreenshot => self.handle_take_screenshot(),
+        }
+    }
+
+    fn handle_new_session(&mut self) -> CommandResult {
+        if self.session_id.is_some() {
+            return Err(CommandError::new(500, "session not created",
+                                         "Servo only supports one session".to_string()))
+        }
+        let session_id = uuid::Uuid::new_v4().to_simple_string();
+        self.session_id = Some(session_id.clone());
+
+        let mut capabilities = TreeMap::new();
+        capabilities.insert("browserName".to_string(), "servo".to_string().to_json());
+        capabilities.insert("browserVersion".to_string(), "0.0.1".to_string().to_json());
+        let mut session = TreeMap::new();
+        session.insert("sessionId".to_string(), session_id.to_json());
+        session.insert("capabilities".to_string(), json::Object(capabilities));
+        Ok(json::Object(session))
+    }
+
+    fn send_constellation_msg(&self, msg: Msg) {
+        let ConstellationChan(ref chan) = self.constellation_chan;
+        chan.send(msg);
+    }
+
+    fn root_pipeline(&self) -> Option<PipelineId> {
+        let (sender, receiver) = comm::channel();
+        self.send_constellation_msg(GetRootPipelineMsg(sender));
+        receiver.recv_opt().ok().and_then(|pipeline_id| pipeline_id)
+    }
+
+    /// Runs a command in the script task of the root frame and waits for its reply.
+    fn run_script_command<T: Send>(&self, command: |Sender<T>| -> WebDriverScriptCommand)
+                                   -> Result<T, CommandError> {
+        let pipeline_id = match self.root_pipeline() {
+            Some(pipeline_id) => pipeline_id,
+            None => return Err(CommandError::no_such_window()),
+        };
+        let (sender, receiver) = comm::channel();
+        self.send_constellation_msg(WebDriverCommandMsg(pipeline_id, command(sender)));
+        receiver.recv_opt().map_err(|_| CommandError::no_such_window())
+    }
+
+    fn run_element_command<T: Send>(&self,
+                                    command: |Sender<Result<T, WebDriverError>>|
+                                             -> WebDriverScriptCommand)
+                                    -> Result<T, CommandError> {
+        try!(self.run_script_command(command)).map_err(CommandError::from_script_error)
+    }
+
+    fn handle_get(&self, url: &str) -> CommandResult {
+        let url = match Url::parse(url) {
+            Ok(url) => url,
+            Err(_) => return Err(CommandError::invalid_argument("Invalid URL")),
+        };
+        let pipeline_id = match self.root_pipeline() {
+            Some(pipeline_id) => pipeline_id,
+            None => return Err(CommandError::no_such_window()),
+        };
+        self.send_constellation_msg(LoadUrlMsg(pipeline_id, LoadData::new(url)));
+        self.wait_for_load(pipeline_id)
+    }
+
+    fn handle_navigate(&self, direction: constellation_msg::NavigationDirection)
+                       -> CommandResult {
+        let pipeline_id = match self.root_pipeline() {
+            Some(pipeline_id) => pipeline_id,
+            None => return Err(CommandError::no_such_window()),
+        };
+        self.send_constellation_msg(NavigateMsg(direction));
+        self.wait_for_load(pipeline_id)
+    }
+
+    /// Waits for the root frame to be replaced by a new pipeline and for its document to finish
+    /// loading. Fails with a timeout if that doesn't happen in time, which is also the case when
+    /// navigating past either end of the session history leaves the frame in place.
+    fn wait_for_load(&self, old_pipeline_id: PipelineId) -> CommandResult {
+        for _ in range(0, LOAD_POLL_ATTEMPTS) {
+            if self.root_pipeline().map_or(false, |pipeline_id| pipeline_id != old_pipeline_id) {
+                let ready_state = self.run_script_command(|reply| {
+                    webdriver_traits::ExecuteScript("document.readyState".to_string(), reply)
+                });
+                if ready_state == Ok(Ok(JSString("complete".to_string()))) {
+                    return Ok(json::Null)
+                }
+            }
+            sleep(Duration::milliseconds(LOAD_POLL_INTERVAL_MS));
+        }
+        Err(CommandError::new(408, "timeout", "The page did not finish loading in time"
+                                              .to_string()))
+    }
+
+    fn send_keys(&self, text: &str) -> CommandResult {
+        for ch in text.chars() {
+            let (key, modifiers) = match key_for_char(ch) {
+                Some(key) => key,
+                None => {
+                    return Err(CommandError::new(400, "element not interactable",
+                                                 format!("Cannot type {}", ch)))
+                }
+            };
+            self.send_constellation_msg(KeyEvent(key, Pressed, modifiers));
+            self.send_constellation_msg(KeyEvent(key, Released, modifiers));
+        }
+        Ok(json::Null)
+    }
+
+    /// Runs the body of a function with the given arguments. Arguments are passed as plain JSON,
+    /// so element references are not turned back into elements.
+    fn handle_execute_script(&self, parameters: &JsonObject) -> CommandResult {
+        let script = try!(string_parameter(parameters, "script"));
+        let args = match parameters.get(&"args".to_string()) {
+            Some(args) => args.to_string(),
+            None => "[]".to_string(),
+        };
+        if !args.as_slice().starts_with("[") {
+            return Err(CommandError::invalid_argument("args is not an array"))
+        }
+        let script = format!("(function() {{ {} }}).apply(null, {})", script, args);
+
+        match try!(self.run_script_command(|reply| {
+            webdriver_traits::ExecuteScript(script.clone(), reply)
+        })) {
+            Ok(JSUndefined) | Ok(JSNull) => Ok(json::Null),
+            Ok(JSBoolean(value)) => Ok(value.to_json()),
+            Ok(JSNumber(value)) => Ok(value.to_json()),
+            Ok(JSString(value)) => Ok(value.to_json()),
+            Err(_) => Err(CommandError::new(500, "javascript error",
+                                            "The script returned an unsupported value"
+                                            .to_string())),
+        }
+    }
+
+    fn handle_take_screenshot(&self) -> CommandResult {
+        let (sender, receiver) = comm::channel();
+        self.send_constellation_msg(ScreenshotMsg(sender));
+        let screenshot = match receiver.recv_opt() {
+            Ok(Some(screenshot)) => screenshot,
+            _ => return Err(CommandError::new(500, "unable to capture screen",
+                                              "This compositor cannot take screenshots"
+                                              .to_string())),
+        };
+
+        match encode_png(screenshot.width, screenshot.height, screenshot.pixels.as_slice()) {
+            Ok(encoded) => Ok(encoded.as_slice().to_base64(STANDARD).to_json()),
+            Err(e) => Err(CommandError::new(500, "unable to capture screen",
+                                            format!("The screenshot could not be encoded: {}",
+                                                    e))),
+        }
+    }
+}
+
+/// Returns the CSS selector for a "Find Element" command. Tag names are valid selectors, so
+/// that strategy is supported too.
+fn selector_parameter(parameters: &JsonObject) -> Result<String, CommandError> {
+    let strategy = try!(string_parameter(parameters, "using"));
+    match strategy.as_slice() {
+        "css selector" | "tag name" => string_parameter(parameters, "value"),
+        _ => Err(CommandError::invalid_argument("Unsupported location strategy")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_route, selector_parameter, CommandError};
+    use super::{Status, NewSession, DeleteSession, Get, GetCurrentUrl, FindElement};
+    use super::{FindElements, GetElementAttribute, ElementSendKeys, ExecuteScript};
+    use serialize::json;
+
+    fn session(route: super::Route) -> Result<(Option<String>, super::Route), CommandError> {
+        Ok((Some("1".to_string()), route))
+    }
+
+    #[test]
+    fn test_parse_route() {
+        assert_eq!(parse_route("GET", "/status"), Ok((None, Status)));
+        assert_eq!(parse_route("POST", "/session"), Ok((None, NewSession)));
+        assert_eq!(parse_route("DELETE", "/session/1"), session(DeleteSession));
+        assert_eq!(parse_route("POST", "/session/1/url"), session(Get));
+        assert_eq!(parse_route("GET", "/session/1/url"), session(GetCurrentUrl));
+        assert_eq!(parse_route("POST", "/session/1/element"), session(FindElement(None)));
+        assert_eq!(parse_route("POST", "/session/1/element/a/elements"),
+                   session(FindElements(Some("a".to_string()))));
+        assert_eq!(parse_route("GET", "/session/1/element/a/attribute/href"),
+                   session(GetElementAttribute("a".to_string(), "href".to_string())));
+        assert_eq!(parse_route("POST", "/session/1/element/a/value"),
+                   session(ElementSendKeys("a".to_string())));
+        assert_eq!(parse_route("POST", "/session/1/execute/sync"), session(ExecuteScript));
+    }
+
+    #[test]
+    fn test_parse_route_errors() {
+        assert_eq!(parse_route("GET", "/session/1/nonsense").map_err(|e| e.status), Err(404));
+        assert_eq!(parse_route("GET", "/session/1/back").map_err(|e| e.status), Err(405));
+        assert_eq!(parse_route("PUT", "/session").map_err(|e| e.error), Err("unknown method"));
+    }
+
+    #[test]
+    fn test_selector_parameter() {
+        let parameters = |body: &str| {
+            json::from_str(body).unwrap().as_object().unwrap().clone()
+        };
+        let css = parameters(r#"{"using": "css selector", "value": "p > a"}"#);
+        assert_eq!(selector_parameter(&css), Ok("p > a".to_string()));
+        let xpath = parameters(r#"{"using": "xpath", "value": "//a"}"#);
+        assert_eq!(selector_parameter(&xpath).map_err(|e| e.status), Err(400));
+        let missing = parameters(r#"{"using": "css selector"}"#);
+        assert_eq!(selector_parameter(&missing).map_err(|e| e.error), Err("invalid argument"));
+    }
+}
@@ -0,0 +1,94 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Encodes screenshots as PNG images in memory, to send them to the client.
+
+use flate2;
+use flate2::writer::ZlibEncoder;
+
+static PNG_SIGNATURE: &'static [u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Encodes 8-bit RGB pixels, row after row with no padding, as a PNG image.
+pub fn encode_png(width: uint, height: uint, pixels: &[u8]) -> Result<Vec<u8>, String> {
+    if pixels.len() != width * height * 3 {
+        return Err("the screenshot has the wrong number of pixels".to_string())
+    }
+
+    let mut header = vec!();
+    push_u32(&mut header, width as u32);
+    push_u32(&mut header, height as u32);
+    // 8 bits per sample of RGB, with the default compression and filtering and no interlacing.
+    header.push_all(&[8, 2, 0, 0, 0]);
+
+    // Each row starts with the type of filter that was applied to it, here none.
+    let mut encoder = ZlibEncoder::new(vec!(), flate2::Default);
+    for row in pixels.chunks(width * 3) {
+        try!(encoder.write_u8(0).map_err(|e| e.desc.to_string()));
+        try!(encoder.write(row).map_err(|e| e.desc.to_string()));
+    }
+    let data = try!(encoder.finish().map_err(|e| e.desc.to_string()));
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    push_chunk(&mut png, b"IHDR", header.as_slice());
+    push_chunk(&mut png, b"IDAT", data.as_slice());
+    push_chunk(&mut png, b"IEND", &[]);
+    Ok(png)
+}
+
+fn push_u32(output: &mut Vec<u8>, value: u32) {
+    output.push_all(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]);
+}
+
+/// The CRC-32 that PNG uses to check chunks.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in bytes.iter() {
+        crc ^= byte as u32;
+        for _ in range(0u, 8) {
+            crc = if crc & 1 != 0 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn push_chunk(output: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    push_u32(output, data.len() as u32);
+    let start = output.len();
+    output.push_all(kind);
+    output.push_all(data);
+    let crc = crc32(output.slice_from(start));
+    push_u32(output, crc);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encode_png;
+
+    use flate2::reader::ZlibDecoder;
+    use std::io::BufReader;
+
+    #[test]
+    fn test_encode_png() {
+        let pixels = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+        let png = encode_png(2, 2, &pixels).unwrap();
+        assert!(png.as_slice().starts_with(b"\x89PNG\r\n\x1a\n"));
+        // The header chunk holds the size, then a check of its kind and data.
+        assert_eq!(png.slice(8, 24), b"\0\0\0\x0dIHDR\0\0\0\x02\0\0\0\x02");
+        // The last chunk is empty and always has the same check.
+        assert_eq!(png.slice_from(png.len() - 12), b"\0\0\0\0IEND\xae\x42\x60\x82");
+
+        let data_length = png.slice(33, 37).iter().fold(0u, |length, &byte| {
+            length << 8 | byte as uint
+        });
+        assert_eq!(png.slice(37, 41), b"IDAT");
+        let data = png.slice(41, 41 + data_length);
+        let rows = ZlibDecoder::new(BufReader::new(data)).read_to_end().unwrap();
+        assert_eq!(rows, vec!(0, 255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255));
+    }
+
+    #[test]
+    fn test_wrong_size() {
+        assert!(encode_png(2, 2, &[0, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#![crate_name = "webdriver_traits"]
+#![crate_type = "rlib"]
+
+#![comment = "The Servo Parallel Browser Project"]
+#![license = "MPL"]
+
+/// This module contains the messages that the WebDriver server sends to script tasks.
+/// The traits are here instead of in script so that the WebDriver server can be modified
+/// independently of the rest of Servo.
+
+/// Elements are identified by the unique ID of their node, as the devtools do.
+pub type WebDriverElementId = String;
+
+/// Commands that a script task runs against the document of a pipeline on behalf of a
+/// WebDriver client.
+pub enum WebDriverScriptCommand {
+    /// Evaluates a script and replies with its completion value.
+    ExecuteScript(String, Sender<Result<WebDriverJSValue, WebDriverJSError>>),
+    /// Finds the first element matching a CSS selector, searching the descendants of the given
+    /// element or the whole document.
+    FindElementCSS(Option<WebDriverElementId>, String,
+                   Sender<Result<Option<WebDriverElementId>, WebDriverError>>),
+    /// Finds all of the elements matching a CSS selector.
+    FindElementsCSS(Option<WebDriverElementId>, String,
+                    Sender<Result<Vec<WebDriverElementId>, WebDriverError>>),
+    /// Replies with the text content of an element.
+    GetElementText(WebDriverElementId, Sender<Result<String, WebDriverError>>),
+    /// Replies with the value of an attribute of an element.
+    GetElementAttribute(WebDriverElementId, String,
+                        Sender<Result<Option<String>, WebDriverError>>),
+    /// Replies with the local name of an element.
+    GetElementTagName(WebDriverElementId, Sender<Result<String, WebDriverError>>),
+    /// Dispatches a click at the center of an element, as the compositor would.
+    ClickElement(WebDriverElementId, Sender<Result<(), WebDriverError>>),
+    /// Focuses an element, so that key events go to it.
+    FocusElement(WebDriverElementId, Sender<Result<(), WebDriverError>>),
+    /// Replies with the URL of the document.
+    GetUrl(Sender<String>),
+    /// Replies with the title of the document.
+    GetTitle(Sender<String>),
+}
+
+/// The values that scripts run by WebDriver can complete with.
+#[deriving(Clone, PartialEq, Show)]
+pub enum WebDriverJSValue {
+    JSUndefined,
+    JSNull,
+    JSBoolean(bool),
+    JSNumber(f64),
+    JSString(String),
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum WebDriverJSError {
+    /// The script completed with a value that cannot be sent back, such as an object.
+    JSUnknownType,
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum WebDriverError {
+    /// No element in the document has the given ID.
+    StaleElementReference,
+    /// The selector could not be parsed.
+    InvalidSelector,
+    /// The element has no box to click on.
+    ElementNotVisible,
+}
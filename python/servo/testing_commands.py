@@ -119,7 +119,7 @@ class MachCommands(CommandBase):
              description='Run the reference tests',
              category='testing')
     @CommandArgument('--kind', '-k', default=None,
-                     help="'cpu' or 'gpu' (default both)")
+                     help="'cpu', 'gpu' or 'headless' (default cpu and gpu)")
     @CommandArgument('--name', default=None,
                      help="Only run tests that match this pattern. If the "
                           "path to the ref test directory is included, it "
@@ -0,0 +1,218 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Runs in the script task's debugger global, which Servo calls into for each devtools thread
+// request. `pause` is provided by Servo: it reports a pause to the devtools client and blocks
+// until the client resumes, returning "stepOver", "stepIn", "stepOut" or "".
+
+"use strict";
+
+var dbg = new Debugger();
+var breakpoints = [];
+var steppingFrames = [];
+
+function describe(value) {
+  if (value === undefined) {
+    return { kind: "undefined", text: "" };
+  }
+  if (value === null) {
+    return { kind: "null", text: "" };
+  }
+  if (value instanceof Debugger.Object) {
+    var text = value.class;
+    if (value.callable) {
+      text = value.displayName || value.name || "Function";
+    }
+    return { kind: "object", text: text };
+  }
+  if (typeof value == "object") {
+    // Bindings that were optimized away or are not initialized yet.
+    return { kind: "undefined", text: "" };
+  }
+  return { kind: typeof value, text: String(value) };
+}
+
+function lineOf(frame) {
+  return frame.script ? frame.script.getOffsetLine(frame.offset) : 0;
+}
+
+function describeFrame(frame, depth) {
+  var environment = [];
+  // The outermost environment is the global, which is too big to send with every frame.
+  for (var env = frame.environment; env && env.parent; env = env.parent) {
+    environment.push({
+      kind: env.type,
+      bindings: env.names().map(function (name) {
+        return { name: name, value: describe(env.getVariable(name)) };
+      })
+    });
+  }
+  var callee = frame.callee ? (frame.callee.displayName || frame.callee.name || null) : null;
+  return {
+    depth: depth,
+    callee: callee,
+    location: { url: frame.script ? frame.script.url : "", line: lineOf(frame), column: 0 },
+    this: describe(frame.this),
+    arguments: (frame.arguments || []).map(describe),
+    environment: environment
+  };
+}
+
+function clearStepping() {
+  dbg.onEnterFrame = undefined;
+  steppingFrames.forEach(function (frame) {
+    if (frame.live) {
+      frame.onStep = undefined;
+      frame.onPop = undefined;
+    }
+  });
+  steppingFrames = [];
+}
+
+function pauseAt(reason, frame) {
+  clearStepping();
+  var limit = pause(JSON.stringify({ reason: reason, frame: describeFrame(frame, 0) }));
+  if (limit) {
+    startStepping(limit, frame);
+  }
+  return undefined;
+}
+
+function pauseOnStep() {
+  return pauseAt("resumeLimit", this);
+}
+
+function startStepping(limit, frame) {
+  var startLine = lineOf(frame);
+  if (limit != "stepOut") {
+    frame.onStep = function () {
+      if (lineOf(this) == startLine) {
+        return undefined;
+      }
+      return pauseAt("resumeLimit", this);
+    };
+  }
+  // Once the frame returns, pause at the next line of its caller.
+  frame.onPop = function () {
+    var older = this.older;
+    if (older) {
+      older.onStep = pauseOnStep;
+      steppingFrames.push(older);
+    }
+    return undefined;
+  };
+  if (limit == "stepIn") {
+    dbg.onEnterFrame = function (newer) {
+      return pauseAt("resumeLimit", newer);
+    };
+  }
+  steppingFrames.push(frame);
+}
+
+function installBreakpoint(breakpoint, script) {
+  var offsets = script.getLineOffsets(breakpoint.line);
+  offsets.forEach(function (offset) {
+    script.setBreakpoint(offset, breakpoint.handler);
+  });
+  return offsets.length > 0;
+}
+
+dbg.onDebuggerStatement = function (frame) {
+  return pauseAt("debuggerStatement", frame);
+};
+
+dbg.onNewScript = function (script) {
+  breakpoints.forEach(function (breakpoint) {
+    if (breakpoint.url != script.url) {
+      return;
+    }
+    [script].concat(script.getChildScripts()).forEach(function (child) {
+      installBreakpoint(breakpoint, child);
+    });
+  });
+};
+
+function attach(global) {
+  dbg.addDebuggee(global);
+}
+
+function detach(global) {
+  dbg.removeDebuggee(global);
+  if (dbg.getDebuggees().length == 0) {
+    dbg.findScripts().forEach(function (script) {
+      script.clearAllBreakpoints();
+    });
+    breakpoints = [];
+    clearStepping();
+  }
+}
+
+function interrupt() {
+  dbg.onEnterFrame = function (frame) {
+    return pauseAt("interrupted", frame);
+  };
+}
+
+function setBreakpoint(url, line) {
+  var breakpoint = {
+    url: url,
+    line: line,
+    handler: {
+      hit: function (frame) {
+        return pauseAt("breakpoint", frame);
+      }
+    }
+  };
+  breakpoints.push(breakpoint);
+  var installed = false;
+  dbg.findScripts({ url: url, line: line }).forEach(function (script) {
+    if (installBreakpoint(breakpoint, script)) {
+      installed = true;
+    }
+  });
+  return JSON.stringify(installed ? { url: url, line: line, column: 0 } : null);
+}
+
+function removeBreakpoint(url, line) {
+  breakpoints = breakpoints.filter(function (breakpoint) {
+    if (breakpoint.url != url || breakpoint.line != line) {
+      return true;
+    }
+    dbg.findScripts({ url: url }).forEach(function (script) {
+      script.clearBreakpoint(breakpoint.handler);
+    });
+    return false;
+  });
+}
+
+function frames(start, count) {
+  var result = [];
+  var depth = 0;
+  for (var frame = dbg.getNewestFrame(); frame && result.length < count; frame = frame.older) {
+    if (depth >= start) {
+      result.push(describeFrame(frame, depth));
+    }
+    depth++;
+  }
+  return JSON.stringify(result);
+}
+
+function sources() {
+  var urls = [];
+  dbg.findScripts().forEach(function (script) {
+    if (script.url && urls.indexOf(script.url) == -1) {
+      urls.push(script.url);
+    }
+  });
+  return JSON.stringify(urls.map(function (url) {
+    return { url: url, worker: false };
+  }));
+}
+
+function sourceText(url) {
+  var scripts = dbg.findScripts({ url: url }).filter(function (script) {
+    return script.source && typeof script.source.text == "string";
+  });
+  return scripts.length > 0 ? scripts[0].source.text : null;
+}
@@ -0,0 +1,8 @@
+var connections = 0;
+onconnect = function(e) {
+  connections++;
+  var port = e.ports[0];
+  port.onmessage = function(e) {
+    port.postMessage(name + " " + connections + ": " + e.data);
+  };
+};
@@ -0,0 +1,11 @@
+onmessage = function(e) {
+  if (e.data == "throw") {
+    throw new Error("thrown from worker");
+  }
+  if (e.data == "close") {
+    close();
+    postMessage("closed");
+    return;
+  }
+  postMessage(e.data);
+};
@@ -0,0 +1,2 @@
+postMessage("looping");
+while (true) {}
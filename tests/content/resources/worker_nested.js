@@ -0,0 +1,6 @@
+var child = new Worker("worker_echo.js");
+child.onmessage = function(e) {
+  postMessage("child: " + e.data);
+  child.postMessage("throw");
+};
+child.postMessage("hello");
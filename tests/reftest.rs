@@ -34,7 +34,8 @@ bitflags!(
         const GPU_RENDERING  = 0x00000010,
         const LINUX_TARGET   = 0x00000100,
         const MACOS_TARGET   = 0x00001000,
-        const ANDROID_TARGET = 0x00010000
+        const ANDROID_TARGET = 0x00010000,
+        const HEADLESS       = 0x00100000
     }
 )
 
@@ -47,7 +48,7 @@ fn main() {
     let servo_args = parts.next().unwrap_or(&[]);
 
     let (render_mode_string, base_path, testname) = match harness_args {
-        [] | [_] => panic!("USAGE: cpu|gpu base_path [testname regex]"),
+        [] | [_] => panic!("USAGE: cpu|gpu|headless base_path [testname regex]"),
         [ref render_mode_string, ref base_path] => (render_mode_string, base_path, None),
         [ref render_mode_string, ref base_path, ref testname, ..] => (render_mode_string, base_path, Some(Regex::new(testname.as_slice()).unwrap())),
     };
@@ -55,7 +56,9 @@ fn main() {
     let mut render_mode = match render_mode_string.as_slice() {
         "cpu" => CPU_RENDERING,
         "gpu" => GPU_RENDERING,
-        _ => panic!("First argument must specify cpu or gpu as rendering mode")
+        // Headless runs paint on the CPU and composite in software, so they need no display.
+        "headless" => CPU_RENDERING | HEADLESS,
+        _ => panic!("First argument must specify cpu, gpu or headless as rendering mode")
     };
     if cfg!(target_os = "linux") {
         render_mode.insert(LINUX_TARGET);
@@ -208,6 +211,7 @@ fn parse_lists(file: &Path, servo_args: &[String], render_mode: RenderMode, id_o
                 "flaky_gpu" => flakiness.insert(GPU_RENDERING),
                 "flaky_linux" => flakiness.insert(LINUX_TARGET),
                 "flaky_macos" => flakiness.insert(MACOS_TARGET),
+                "flaky_headless" => flakiness.insert(HEADLESS),
                 "experimental" => experimental = true,
                 _ => (),
             }
@@ -269,6 +273,9 @@ fn capture(reftest: &Reftest, side: uint) -> (u32, u32, Vec<u8>) {
     if reftest.render_mode.contains(GPU_RENDERING) {
         command.arg("-g");
     }
+    if reftest.render_mode.contains(HEADLESS) {
+        command.arg("-z");
+    }
     if reftest.experimental {
         command.arg("--experimental");
     }